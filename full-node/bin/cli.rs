@@ -30,13 +30,13 @@
 // TODO: I believe this example isn't tested ^ which kills the point of having it
 
 use smoldot::{
-    identity::seed_phrase,
+    identity::{keystore::KeyNamespace, seed_phrase, ss58},
     libp2p::{
         multiaddr::{Multiaddr, Protocol},
         PeerId,
     },
 };
-use std::{io, net::SocketAddr, path::PathBuf};
+use std::{io, net::SocketAddr, num::NonZeroU64, path::PathBuf};
 
 // Note: the doc-comments applied to this struct and its field are visible when the binary is
 // started with `--help`.
@@ -60,6 +60,35 @@ pub enum CliOptionsCommand {
     /// Computes the 256 bits BLAKE2 hash of a file and prints the hexadecimal-encoded hash.
     #[command(name = "blake2-256bits-hash")]
     Blake2256BitsHash(CliOptionsBlake2256Hash),
+    /// Writes the blocks of the best chain found in the local database to a file.
+    #[command(name = "export-blocks")]
+    ExportBlocks(CliOptionsExportBlocks),
+    /// Verifies the blocks found in a file and inserts them in the local database.
+    #[command(name = "import-blocks")]
+    ImportBlocks(CliOptionsImportBlocks),
+    /// Builds a raw chain specification whose genesis storage is the storage of a block found in
+    /// the local database.
+    #[command(name = "build-chain-spec", alias = "export-state")]
+    BuildChainSpec(CliOptionsBuildChainSpec),
+    /// Generates, inspects, and stores cryptographic keys.
+    #[command(name = "key", subcommand)]
+    Key(CliOptionsKey),
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum CliOptionsKey {
+    /// Generates a random seed phrase and prints it alongside with the corresponding public key.
+    #[command(name = "generate")]
+    Generate(CliOptionsKeyGenerate),
+    /// Prints the public key and SS58 address corresponding to a seed phrase.
+    #[command(name = "inspect")]
+    Inspect(CliOptionsKeyInspect),
+    /// Inserts the key corresponding to a seed phrase in an on-disk keystore.
+    #[command(name = "insert")]
+    Insert(CliOptionsKeyInsert),
+    /// Generates a random networking key, and prints it alongside with the corresponding PeerId.
+    #[command(name = "generate-node-key")]
+    GenerateNodeKey(CliOptionsKeyGenerateNodeKey),
 }
 
 #[derive(Debug, clap::Parser)]
@@ -91,13 +120,45 @@ pub struct CliOptionsRun {
     /// Maximum number of JSON-RPC clients that can be connected simultaneously. Ignored if no server.
     #[arg(long, default_value = "64")]
     pub json_rpc_max_clients: u32,
+    /// Value of the `Origin` HTTP header that is allowed to connect to the JSON-RPC server. Can
+    /// be passed multiple times. If not passed, all origins are allowed.
+    #[arg(long)]
+    pub json_rpc_cors_origin: Vec<String>,
+    /// Maximum size of a JSON-RPC request, whether sent through HTTP or WebSocket.
+    #[arg(long, default_value = "16M", value_parser = parse_max_bytes)]
+    pub json_rpc_max_request_size: MaxBytes,
+    /// JSON-RPC methods to expose: auto, safe, unsafe. Unsafe methods, such as
+    /// `author_insertKey`, modify the keystore of the node. "auto" exposes them only if the
+    /// JSON-RPC server listens on a loopback address.
+    #[arg(long, default_value = "auto")]
+    pub json_rpc_methods: JsonRpcMethods,
     /// List of secret phrases to insert in the keystore of the node. Used to author blocks.
     #[arg(long, value_parser = decode_sr25519_private_key)]
     // TODO: also automatically add the same keys through ed25519?
     pub keystore_memory: Vec<Box<[u8; 64]>>,
+    /// Directory where the keys of the node are stored. Defaults to a `keys` directory within
+    /// the directory of the chain. Keys inserted or generated through the JSON-RPC server are
+    /// saved there.
+    #[arg(long)]
+    pub keystore_path: Option<PathBuf>,
     /// Address of a Jaeger agent to send traces to (hint: port is typically 6831).
     #[arg(long)]
     pub jaeger: Option<SocketAddr>,
+    /// Address to serve Prometheus metrics on (typically `127.0.0.1:9615`). Metrics aren't
+    /// served if not passed.
+    #[arg(long)]
+    pub prometheus_address: Option<SocketAddr>,
+    /// Name of the node, as reported to the telemetry servers.
+    #[arg(long, default_value = "smoldot")]
+    pub name: String,
+    /// URL of a telemetry server to report to, followed with a verbosity level between 0 and 9
+    /// (for example `"ws://localhost:8001/submit 0"`). Can be passed multiple times. If passed,
+    /// the telemetry servers of the chain specification are ignored.
+    #[arg(long, value_parser = parse_telemetry_url)]
+    pub telemetry_url: Vec<TelemetryUrl>,
+    /// Do not report to any telemetry server.
+    #[arg(long)]
+    pub no_telemetry: bool,
     /// Do not load or store anything on disk.
     #[arg(long)]
     pub tmp: bool,
@@ -108,6 +169,22 @@ pub struct CliOptionsRun {
     /// chain is not a parachain.
     #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
     pub relay_chain_database_cache_size: MaxBytes,
+    /// Number of finalized blocks whose storage is kept in the database ("archive" or a number).
+    /// Also applies to the relay chain.
+    #[arg(long, default_value = "256", value_parser = parse_pruning)]
+    pub state_pruning: Pruning,
+    /// Number of finalized blocks whose body and justification are kept in the database
+    /// ("archive" or a number). Also applies to the relay chain.
+    #[arg(long, default_value = "archive", value_parser = parse_pruning)]
+    pub blocks_pruning: Pruning,
+    /// Warp sync to the head of the chain if the database is empty, instead of verifying all the
+    /// blocks since the genesis.
+    #[arg(long)]
+    pub warp_sync: bool,
+    /// Refuse the storage proof and call proof requests sent by other nodes, for example by
+    /// light clients.
+    #[arg(long)]
+    pub no_storage_and_call_proofs: bool,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -122,6 +199,121 @@ pub struct CliOptionsBlake2256Hash {
     pub file: PathBuf,
 }
 
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsExportBlocks {
+    /// Path to a file containing the specification of the chain whose blocks to export.
+    #[arg(long)]
+    pub path_to_chain_spec: PathBuf,
+    /// Number of the first block to export.
+    #[arg(long, default_value = "1")]
+    pub from: u64,
+    /// Number of the last block to export. Defaults to the current best block.
+    #[arg(long)]
+    pub to: Option<u64>,
+    /// Format of the output: scale, json-lines.
+    #[arg(long, default_value = "scale")]
+    pub format: BlocksFileFormat,
+    /// Path of the file to write the blocks to. The blocks are written to stdout if not passed.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Maximum size of the cache used by the database.
+    #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
+    pub database_cache_size: MaxBytes,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsImportBlocks {
+    /// Path to a file containing the specification of the chain whose blocks to import.
+    #[arg(long)]
+    pub path_to_chain_spec: PathBuf,
+    /// Format of the input: scale, json-lines.
+    #[arg(long, default_value = "scale")]
+    pub format: BlocksFileFormat,
+    /// Path of the file to read the blocks from. The blocks are read from stdin if not passed.
+    #[arg(long)]
+    pub input: Option<PathBuf>,
+    /// Maximum size of the cache used by the database.
+    #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
+    pub database_cache_size: MaxBytes,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsBuildChainSpec {
+    /// Path to a file containing the specification of the chain whose database to use.
+    #[arg(long)]
+    pub path_to_chain_spec: PathBuf,
+    /// Hexadecimal-encoded hash of the block whose storage to use. Defaults to the current
+    /// finalized block.
+    #[arg(long, value_parser = parse_block_hash)]
+    pub block_hash: Option<BlockHash>,
+    /// Embed a checkpoint of the block in the `lightSyncState` field of the chain specification.
+    /// The block must be the current finalized block.
+    #[arg(long)]
+    pub light_sync_state: bool,
+    /// Path of the file to write the chain specification to. The chain specification is written
+    /// to stdout if not passed.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Maximum size of the cache used by the database.
+    #[arg(long, default_value = "256M", value_parser = parse_max_bytes)]
+    pub database_cache_size: MaxBytes,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsKeyGenerate {
+    /// Number of words of the seed phrase: 12, 15, 18, 21, or 24.
+    #[arg(long, default_value = "12", value_parser = parse_num_words)]
+    pub words: NumWords,
+    /// Signature scheme of the key: sr25519, ed25519.
+    #[arg(long, default_value = "sr25519")]
+    pub scheme: KeyScheme,
+    /// SS58 prefix of the network to use when printing the address.
+    #[arg(long, default_value = "42", value_parser = parse_ss58_prefix)]
+    pub network_prefix: Ss58Prefix,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsKeyInspect {
+    /// Seed phrase, optionally followed with a derivation path, such as `//Alice`.
+    pub phrase: String,
+    /// Signature scheme of the key: sr25519, ed25519.
+    #[arg(long, default_value = "sr25519")]
+    pub scheme: KeyScheme,
+    /// SS58 prefix of the network to use when printing the address.
+    #[arg(long, default_value = "42", value_parser = parse_ss58_prefix)]
+    pub network_prefix: Ss58Prefix,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsKeyInsert {
+    /// Seed phrase, optionally followed with a derivation path, such as `//Alice`.
+    #[arg(long)]
+    pub suri: String,
+    /// Four-characters identifier of the namespace of the key: aura, audi, babe, gran, imon.
+    #[arg(long, value_parser = parse_key_namespace)]
+    pub key_type: KeyNamespace,
+    /// Signature scheme of the key: sr25519, ed25519. Defaults to ed25519 for the `gran`
+    /// namespace and to sr25519 for the other namespaces.
+    #[arg(long)]
+    pub scheme: Option<KeyScheme>,
+    /// Path to the keystore directory. If not passed, the keystore of the chain passed with
+    /// `--path-to-chain-spec` is used.
+    #[arg(long, required_unless_present = "path_to_chain_spec")]
+    pub keystore_path: Option<PathBuf>,
+    /// Path to a file containing the specification of the chain whose keystore to use.
+    #[arg(long)]
+    pub path_to_chain_spec: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct CliOptionsKeyGenerateNodeKey {
+    /// Path of the file to write the key to, in the same format as the
+    /// `libp2p_ed25519_secret_key.secret` file of the `run` subcommand. The key is written to
+    /// stdout if not passed.
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone)]
 pub enum ColorChoice {
     Always,
@@ -197,9 +389,22 @@ pub enum Output {
     LogsJson,
 }
 
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum BlocksFileFormat {
+    Scale,
+    JsonLines,
+}
+
 #[derive(Debug, Clone)]
 pub struct JsonRpcAddress(pub Option<SocketAddr>);
 
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum JsonRpcMethods {
+    Auto,
+    Safe,
+    Unsafe,
+}
+
 fn parse_json_rpc_address(string: &str) -> Result<JsonRpcAddress, String> {
     if string == "none" {
         return Ok(JsonRpcAddress(None));
@@ -229,6 +434,94 @@ fn parse_bootnode(string: &str) -> Result<Bootnode, String> {
     Ok(Bootnode { address, peer_id })
 }
 
+#[derive(Debug, Clone)]
+pub struct TelemetryUrl {
+    pub address: String,
+    pub verbosity: u8,
+}
+
+fn parse_telemetry_url(string: &str) -> Result<TelemetryUrl, String> {
+    let (address, verbosity) = match string.rsplit_once(' ') {
+        Some((address, verbosity)) => (
+            address,
+            verbosity
+                .parse::<u8>()
+                .ok()
+                .filter(|v| *v <= 9)
+                .ok_or_else(|| "Telemetry verbosity must be between 0 and 9".to_owned())?,
+        ),
+        None => (string, 0),
+    };
+
+    smoldot::telemetry::parse_endpoint(address).map_err(|err| err.to_string())?;
+    Ok(TelemetryUrl {
+        address: address.to_owned(),
+        verbosity,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Pruning(pub Option<NonZeroU64>);
+
+fn parse_pruning(string: &str) -> Result<Pruning, String> {
+    if string == "archive" {
+        return Ok(Pruning(None));
+    }
+
+    if let Ok(num) = string.parse::<NonZeroU64>() {
+        return Ok(Pruning(Some(num)));
+    }
+
+    Err("Failed to parse pruning".into())
+}
+
+#[derive(Debug, Clone, clap::ValueEnum)]
+pub enum KeyScheme {
+    Sr25519,
+    Ed25519,
+}
+
+#[derive(Debug, Clone)]
+pub struct NumWords(pub usize);
+
+fn parse_num_words(string: &str) -> Result<NumWords, String> {
+    match string.parse::<usize>() {
+        Ok(n @ (12 | 15 | 18 | 21 | 24)) => Ok(NumWords(n)),
+        _ => Err("Number of words must be one of: 12, 15, 18, 21, 24".into()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ss58Prefix(pub ss58::ChainPrefix);
+
+fn parse_ss58_prefix(string: &str) -> Result<Ss58Prefix, String> {
+    let Ok(prefix) = string.parse::<u16>() else {
+        return Err("Failed to parse SS58 prefix".into());
+    };
+    ss58::ChainPrefix::try_from(prefix)
+        .map(Ss58Prefix)
+        .map_err(|_| "SS58 prefix is too large".into())
+}
+
+fn parse_key_namespace(string: &str) -> Result<KeyNamespace, String> {
+    KeyNamespace::from_string(string)
+        .ok_or_else(|| "Key type must be one of: aura, audi, babe, gran, imon".into())
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockHash(pub [u8; 32]);
+
+fn parse_block_hash(string: &str) -> Result<BlockHash, String> {
+    let string = string.strip_prefix("0x").unwrap_or(string);
+    let Ok(bytes) = hex::decode(string) else {
+        return Err("Failed to parse block hash".into());
+    };
+    let Ok(hash) = <[u8; 32]>::try_from(bytes) else {
+        return Err("Block hash must be 32 bytes".into());
+    };
+    Ok(BlockHash(hash))
+}
+
 #[derive(Debug, Clone)]
 pub struct MaxBytes(pub usize);
 
@@ -43,6 +43,264 @@ async fn async_main() {
             let hash = blake2_rfc::blake2b::blake2b(32, &[], &content);
             println!("0x{}", hex::encode(hash));
         }
+        cli::CliOptionsCommand::ExportBlocks(opt) => export_blocks(opt).await,
+        cli::CliOptionsCommand::ImportBlocks(opt) => import_blocks(opt).await,
+        cli::CliOptionsCommand::BuildChainSpec(opt) => build_chain_spec(opt).await,
+        cli::CliOptionsCommand::Key(cli::CliOptionsKey::Generate(opt)) => key_generate(opt),
+        cli::CliOptionsCommand::Key(cli::CliOptionsKey::Inspect(opt)) => key_inspect(opt),
+        cli::CliOptionsCommand::Key(cli::CliOptionsKey::Insert(opt)) => key_insert(opt).await,
+        cli::CliOptionsCommand::Key(cli::CliOptionsKey::GenerateNodeKey(opt)) => {
+            key_generate_node_key(opt)
+        }
+    }
+}
+
+async fn export_blocks(cli_options: cli::CliOptionsExportBlocks) {
+    let chain_spec =
+        fs::read(&cli_options.path_to_chain_spec).expect("Failed to read chain specification");
+    let sqlite_database_path = database_path(&chain_spec);
+
+    let mut output: Box<dyn io::Write + Send> = match &cli_options.output {
+        Some(path) => Box::new(io::BufWriter::new(
+            fs::File::create(path).expect("Failed to create output file"),
+        )),
+        None => Box::new(io::BufWriter::new(io::stdout())),
+    };
+
+    match smoldot_full_node::export_blocks(smoldot_full_node::ExportBlocksConfig {
+        chain_spec: chain_spec.into(),
+        sqlite_database_path: Some(sqlite_database_path),
+        sqlite_cache_size: cli_options.database_cache_size.0,
+        first_block_number: cli_options.from,
+        last_block_number: cli_options.to,
+        format: blocks_file_format(&cli_options.format),
+        output: &mut *output,
+    })
+    .await
+    {
+        Ok(num_exported) => eprintln!("Exported {num_exported} blocks"),
+        Err(err) => {
+            eprintln!("Failed to export blocks: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn import_blocks(cli_options: cli::CliOptionsImportBlocks) {
+    let chain_spec =
+        fs::read(&cli_options.path_to_chain_spec).expect("Failed to read chain specification");
+    let sqlite_database_path = database_path(&chain_spec);
+
+    let mut input: Box<dyn io::BufRead + Send> = match &cli_options.input {
+        Some(path) => Box::new(io::BufReader::new(
+            fs::File::open(path).expect("Failed to open input file"),
+        )),
+        None => Box::new(io::BufReader::new(io::stdin())),
+    };
+
+    let log_callback: Arc<dyn smoldot_full_node::LogCallback + Send + Sync> =
+        Arc::new(|level, message| match level {
+            smoldot_full_node::LogLevel::Error | smoldot_full_node::LogLevel::Warn => {
+                eprintln!("{message}")
+            }
+            _ => {}
+        });
+
+    match smoldot_full_node::import_blocks(smoldot_full_node::ImportBlocksConfig {
+        chain_spec: chain_spec.into(),
+        sqlite_database_path: Some(sqlite_database_path),
+        sqlite_cache_size: cli_options.database_cache_size.0,
+        tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+        log_callback,
+        format: blocks_file_format(&cli_options.format),
+        input: &mut *input,
+    })
+    .await
+    {
+        Ok(outcome) => eprintln!(
+            "Imported {} blocks ({} already in the database)",
+            outcome.num_imported, outcome.num_already_known
+        ),
+        Err(err) => {
+            eprintln!("Failed to import blocks: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn build_chain_spec(cli_options: cli::CliOptionsBuildChainSpec) {
+    let chain_spec =
+        fs::read(&cli_options.path_to_chain_spec).expect("Failed to read chain specification");
+    let sqlite_database_path = database_path(&chain_spec);
+
+    match smoldot_full_node::build_chain_spec(smoldot_full_node::BuildChainSpecConfig {
+        chain_spec: chain_spec.into(),
+        sqlite_database_path: Some(sqlite_database_path),
+        sqlite_cache_size: cli_options.database_cache_size.0,
+        block_hash: cli_options.block_hash.map(|h| h.0),
+        with_light_sync_state: cli_options.light_sync_state,
+    })
+    .await
+    {
+        Ok(chain_spec) => match &cli_options.output {
+            Some(path) => fs::write(path, chain_spec).expect("Failed to write output file"),
+            None => println!("{chain_spec}"),
+        },
+        Err(err) => {
+            eprintln!("Failed to build chain specification: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn key_generate(cli_options: cli::CliOptionsKeyGenerate) {
+    // Each word encodes 11 bits, of which one bit every 33 bits is a checksum.
+    let mut entropy = zeroize::Zeroizing::new(vec![0u8; cli_options.words.0 * 4 / 3]);
+    rand::Fill::try_fill(&mut entropy[..], &mut rand::thread_rng()).unwrap();
+    let phrase = zeroize::Zeroizing::new(
+        smoldot::identity::seed_phrase::entropy_to_bip39(&entropy).unwrap(),
+    );
+    print_key_information(&phrase, &cli_options.scheme, &cli_options.network_prefix);
+}
+
+fn key_inspect(cli_options: cli::CliOptionsKeyInspect) {
+    print_key_information(
+        &cli_options.phrase,
+        &cli_options.scheme,
+        &cli_options.network_prefix,
+    );
+}
+
+/// Prints to stdout the secret phrase, public key, and SS58 address corresponding to the given
+/// seed phrase. Exits the process if the seed phrase is invalid.
+fn print_key_information(phrase: &str, scheme: &cli::KeyScheme, network_prefix: &cli::Ss58Prefix) {
+    let public_key = match scheme {
+        cli::KeyScheme::Sr25519 => {
+            smoldot::identity::seed_phrase::decode_sr25519_public_key(phrase)
+        }
+        cli::KeyScheme::Ed25519 => {
+            smoldot::identity::seed_phrase::decode_ed25519_public_key(phrase)
+        }
+    };
+    let public_key = match public_key {
+        Ok(k) => k,
+        Err(err) => {
+            eprintln!("Invalid seed phrase: {err}");
+            std::process::exit(1);
+        }
+    };
+
+    let ss58_address = smoldot::identity::ss58::encode(smoldot::identity::ss58::Decoded {
+        chain_prefix: network_prefix.0,
+        public_key: &public_key[..],
+    });
+
+    println!("Secret phrase:  {phrase}");
+    println!("Public key:     0x{}", hex::encode(public_key));
+    println!("SS58 address:   {ss58_address}");
+    if let cli::KeyScheme::Ed25519 = scheme {
+        let peer_id = smoldot::libp2p::peer_id::PublicKey::Ed25519(public_key).into_peer_id();
+        println!("Peer ID:        {peer_id}");
+    }
+}
+
+async fn key_insert(cli_options: cli::CliOptionsKeyInsert) {
+    let keystore_path = if let Some(path) = cli_options.keystore_path {
+        path
+    } else {
+        // Use the same directory as the `run` subcommand.
+        let chain_spec = fs::read(cli_options.path_to_chain_spec.as_ref().unwrap())
+            .expect("Failed to read chain specification");
+        let parsed_chain_spec = smoldot::chain_spec::ChainSpec::from_json_bytes(&chain_spec)
+            .expect("Failed to decode chain specification");
+        directories::ProjectDirs::from("io", "smoldot", "smoldot")
+            .expect("Failed to fetch $HOME directory")
+            .data_dir()
+            .join(parsed_chain_spec.id())
+            .join("keys")
+    };
+
+    let keystore =
+        match smoldot::identity::keystore::Keystore::new(Some(keystore_path), rand::random()).await
+        {
+            Ok(k) => k,
+            Err(err) => {
+                eprintln!("Failed to open keystore: {err}");
+                std::process::exit(1);
+            }
+        };
+
+    let scheme = cli_options.scheme.unwrap_or(match cli_options.key_type {
+        smoldot::identity::keystore::KeyNamespace::Grandpa => cli::KeyScheme::Ed25519,
+        _ => cli::KeyScheme::Sr25519,
+    });
+
+    let result = match scheme {
+        cli::KeyScheme::Sr25519 => {
+            keystore
+                .insert_sr25519_phrase(cli_options.key_type, &cli_options.suri, true)
+                .await
+        }
+        cli::KeyScheme::Ed25519 => {
+            keystore
+                .insert_ed25519_phrase(cli_options.key_type, &cli_options.suri, true)
+                .await
+        }
+    };
+
+    match result {
+        Ok(public_key) => println!("0x{}", hex::encode(public_key)),
+        Err(err) => {
+            eprintln!("Failed to insert key: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn key_generate_node_key(cli_options: cli::CliOptionsKeyGenerateNodeKey) {
+    let mut key = zeroize::Zeroizing::new([0u8; 32]);
+    rand::Fill::try_fill(&mut *key, &mut rand::thread_rng()).unwrap();
+    let hex_encoded = zeroize::Zeroizing::new(hex::encode(*key));
+
+    match &cli_options.file {
+        Some(path) => {
+            if let Err(err) = fs::write(path, &*hex_encoded) {
+                eprintln!("Failed to write node key file: {err}");
+                std::process::exit(1);
+            }
+            // On Unix platforms, set the permission as 0o400 (only reading and by owner is
+            // permitted), similar to the `run` subcommand.
+            #[cfg(unix)]
+            let _ = fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(0o400));
+        }
+        None => println!("{}", &*hex_encoded),
+    }
+
+    // The PeerId is printed on stderr, so that stdout only contains the key.
+    let noise_key = smoldot::libp2p::connection::NoiseKey::new(&key, &rand::random());
+    let peer_id =
+        smoldot::libp2p::peer_id::PublicKey::Ed25519(*noise_key.libp2p_public_ed25519_key())
+            .into_peer_id();
+    eprintln!("{peer_id}");
+}
+
+/// Returns the path of the database of the given chain, the same as the one used by the `run`
+/// subcommand.
+fn database_path(chain_spec: &[u8]) -> std::path::PathBuf {
+    let parsed_chain_spec = smoldot::chain_spec::ChainSpec::from_json_bytes(chain_spec)
+        .expect("Failed to decode chain specification");
+    let base_storage_directory = directories::ProjectDirs::from("io", "smoldot", "smoldot")
+        .expect("Failed to fetch $HOME directory")
+        .data_dir()
+        .join(parsed_chain_spec.id());
+    fs::create_dir_all(&base_storage_directory).unwrap();
+    base_storage_directory.join("database")
+}
+
+fn blocks_file_format(format: &cli::BlocksFileFormat) -> smoldot_full_node::BlocksFileFormat {
+    match format {
+        cli::BlocksFileFormat::Scale => smoldot_full_node::BlocksFileFormat::Scale,
+        cli::BlocksFileFormat::JsonLines => smoldot_full_node::BlocksFileFormat::JsonLines,
     }
 }
 
@@ -220,55 +478,67 @@ async fn run(cli_options: cli::CliOptionsRun) {
         .as_ref()
         .map(|d| d.join(parsed_chain_spec.id()).join("database"));
     // Directory supposed to contain the keystore.
-    let keystore_path = base_storage_directory
-        .as_ref()
-        .map(|path| path.join(parsed_chain_spec.id()).join("keys"));
+    let keystore_path = cli_options.keystore_path.clone().or_else(|| {
+        base_storage_directory
+            .as_ref()
+            .map(|path| path.join(parsed_chain_spec.id()).join("keys"))
+    });
 
     // Build the relay chain information if relevant.
-    let (relay_chain, relay_chain_name) =
-        if let Some((relay_chain_name, _parachain_id)) = parsed_chain_spec.relay_chain() {
-            let spec_json = {
-                let relay_chain_path = cli_options
-                    .path_to_chain_spec
-                    .parent()
-                    .unwrap()
-                    .join(format!("{relay_chain_name}.json"));
-                fs::read(&relay_chain_path).expect("Failed to read relay chain specification")
-            };
-
-            let parsed_relay_spec = smoldot::chain_spec::ChainSpec::from_json_bytes(&spec_json)
-                .expect("Failed to decode relay chain chain specs");
+    let (relay_chain, relay_chain_name) = if let Some((relay_chain_name, _parachain_id)) =
+        parsed_chain_spec.relay_chain()
+    {
+        let spec_json = {
+            let relay_chain_path = cli_options
+                .path_to_chain_spec
+                .parent()
+                .unwrap()
+                .join(format!("{relay_chain_name}.json"));
+            fs::read(&relay_chain_path).expect("Failed to read relay chain specification")
+        };
 
-            // Make sure we're not accidentally opening the same chain twice, otherwise weird
-            // interactions will happen.
-            assert_ne!(parsed_relay_spec.id(), parsed_chain_spec.id());
+        let parsed_relay_spec = smoldot::chain_spec::ChainSpec::from_json_bytes(&spec_json)
+            .expect("Failed to decode relay chain chain specs");
 
-            // Create the directory if necessary.
-            if let Some(base_storage_directory) = base_storage_directory.as_ref() {
-                fs::create_dir_all(base_storage_directory.join(parsed_relay_spec.id())).unwrap();
-            }
+        // Make sure we're not accidentally opening the same chain twice, otherwise weird
+        // interactions will happen.
+        assert_ne!(parsed_relay_spec.id(), parsed_chain_spec.id());
 
-            let cfg = smoldot_full_node::ChainConfig {
-                chain_spec: spec_json.into(),
-                additional_bootnodes: Vec::new(),
-                keystore_memory: Vec::new(),
-                sqlite_database_path: base_storage_directory.as_ref().map(|d| {
-                    d.join(parsed_relay_spec.id())
-                        .join("database")
-                        .join("database.sqlite")
-                }),
-                sqlite_cache_size: cli_options.relay_chain_database_cache_size.0,
-                keystore_path: base_storage_directory
-                    .as_ref()
-                    .map(|path| path.join(parsed_relay_spec.id()).join("keys")),
-                json_rpc_listen: None,
-            };
+        // Create the directory if necessary.
+        if let Some(base_storage_directory) = base_storage_directory.as_ref() {
+            fs::create_dir_all(base_storage_directory.join(parsed_relay_spec.id())).unwrap();
+        }
 
-            (Some(cfg), Some(relay_chain_name.to_owned()))
-        } else {
-            (None, None)
+        let cfg = smoldot_full_node::ChainConfig {
+            chain_spec: spec_json.into(),
+            additional_bootnodes: Vec::new(),
+            keystore_memory: Vec::new(),
+            sqlite_database_path: base_storage_directory.as_ref().map(|d| {
+                d.join(parsed_relay_spec.id())
+                    .join("database")
+                    .join("database.sqlite")
+            }),
+            sqlite_cache_size: cli_options.relay_chain_database_cache_size.0,
+            sqlite_state_pruning: cli_options.state_pruning.0,
+            sqlite_blocks_pruning: cli_options.blocks_pruning.0,
+            keystore_path: base_storage_directory
+                .as_ref()
+                .map(|path| path.join(parsed_relay_spec.id()).join("keys")),
+            json_rpc_listen: None,
+            warp_sync: false,
+            telemetry_endpoints: if cli_options.no_telemetry {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            allow_inbound_storage_and_call_proof_requests: !cli_options.no_storage_and_call_proofs,
         };
 
+        (Some(cfg), Some(relay_chain_name.to_owned()))
+    } else {
+        (None, None)
+    };
+
     // Determine which networking key to use.
     //
     // This is either passed as a CLI option, loaded from disk, or generated randomly.
@@ -370,15 +640,43 @@ async fn run(cli_options: cli::CliOptionsRun) {
             keystore_memory: cli_options.keystore_memory,
             sqlite_database_path,
             sqlite_cache_size: cli_options.database_cache_size.0,
+            sqlite_state_pruning: cli_options.state_pruning.0,
+            sqlite_blocks_pruning: cli_options.blocks_pruning.0,
             keystore_path,
             json_rpc_listen: if let Some(address) = cli_options.json_rpc_address.0 {
                 Some(smoldot_full_node::JsonRpcListenConfig {
                     address,
                     max_json_rpc_clients: cli_options.json_rpc_max_clients,
+                    cors_allowed_origins: if cli_options.json_rpc_cors_origin.is_empty() {
+                        None
+                    } else {
+                        Some(cli_options.json_rpc_cors_origin.clone())
+                    },
+                    max_request_size: cli_options.json_rpc_max_request_size.0,
+                    allow_unsafe_methods: match cli_options.json_rpc_methods {
+                        cli::JsonRpcMethods::Auto => address.ip().is_loopback(),
+                        cli::JsonRpcMethods::Safe => false,
+                        cli::JsonRpcMethods::Unsafe => true,
+                    },
                 })
             } else {
                 None
             },
+            warp_sync: cli_options.warp_sync,
+            telemetry_endpoints: if cli_options.no_telemetry {
+                Some(Vec::new())
+            } else if !cli_options.telemetry_url.is_empty() {
+                Some(
+                    cli_options
+                        .telemetry_url
+                        .into_iter()
+                        .map(|url| (url.address, url.verbosity))
+                        .collect(),
+                )
+            } else {
+                None
+            },
+            allow_inbound_storage_and_call_proof_requests: !cli_options.no_storage_and_call_proofs,
         },
         relay_chain,
         libp2p_key,
@@ -389,6 +687,8 @@ async fn run(cli_options: cli::CliOptionsRun) {
         },
         log_callback: log_callback.clone(),
         jaeger_agent: cli_options.jaeger,
+        prometheus_address: cli_options.prometheus_address,
+        node_name: cli_options.name,
     })
     .await;
 
@@ -414,6 +714,13 @@ async fn run(cli_options: cli::CliOptionsRun) {
         );
     }
 
+    if let Some(addr) = client.prometheus_server_addr() {
+        log_callback.log(
+            smoldot_full_node::LogLevel::Info,
+            format!("Prometheus metrics server listening on {addr}."),
+        );
+    }
+
     // Starting from here, a SIGINT (or equivalent) handler is set up. If the user does Ctrl+C,
     // an event will be triggered on `ctrlc_detected`.
     // This should be performed after all the expensive initialization is done, as otherwise these
@@ -0,0 +1,288 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Encoding and decoding of the files written by [`crate::export_blocks`] and read by
+//! [`crate::import_blocks`].
+//!
+//! A file contains a list of blocks, each consisting of a SCALE-encoded header, a list of
+//! SCALE-encoded extrinsics, and a list of justifications. See [`crate::BlocksFileFormat`] for
+//! the two possible formats.
+
+use crate::BlocksFileFormat;
+
+use smoldot::json_rpc::methods::HexString;
+use std::io;
+
+/// Block found in a blocks file.
+pub struct Block {
+    /// SCALE-encoded header of the block.
+    pub scale_encoded_header: Vec<u8>,
+    /// List of SCALE-encoded extrinsics of the block.
+    pub scale_encoded_extrinsics: Vec<Vec<u8>>,
+    /// List of consensus engine identifiers and SCALE-encoded justifications of the block.
+    pub scale_encoded_justifications: Vec<([u8; 4], Vec<u8>)>,
+}
+
+/// Representation of a block in the JSON-lines format.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonBlock {
+    header: HexString,
+    body: Vec<HexString>,
+    justifications: Vec<JsonJustification>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonJustification {
+    engine: HexString,
+    justification: HexString,
+}
+
+/// Writes the given block to `out`.
+pub fn write_block(
+    out: &mut dyn io::Write,
+    format: BlocksFileFormat,
+    block: &Block,
+) -> Result<(), io::Error> {
+    match format {
+        BlocksFileFormat::Scale => {
+            write_bytes(out, &block.scale_encoded_header)?;
+            write_compact(out, block.scale_encoded_extrinsics.len())?;
+            for extrinsic in &block.scale_encoded_extrinsics {
+                write_bytes(out, extrinsic)?;
+            }
+            write_compact(out, block.scale_encoded_justifications.len())?;
+            for (consensus_engine_id, justification) in &block.scale_encoded_justifications {
+                out.write_all(consensus_engine_id)?;
+                write_bytes(out, justification)?;
+            }
+            Ok(())
+        }
+        BlocksFileFormat::JsonLines => {
+            let json_block = JsonBlock {
+                header: HexString(block.scale_encoded_header.clone()),
+                body: block
+                    .scale_encoded_extrinsics
+                    .iter()
+                    .map(|ext| HexString(ext.clone()))
+                    .collect(),
+                justifications: block
+                    .scale_encoded_justifications
+                    .iter()
+                    .map(|(consensus_engine_id, justification)| JsonJustification {
+                        engine: HexString(consensus_engine_id.to_vec()),
+                        justification: HexString(justification.clone()),
+                    })
+                    .collect(),
+            };
+
+            serde_json::to_writer(&mut *out, &json_block)?;
+            out.write_all(b"\n")
+        }
+    }
+}
+
+/// Reads the blocks of a blocks file one by one.
+pub struct Reader<'a> {
+    /// Source of the data.
+    inner: &'a mut (dyn io::BufRead + Send),
+    /// Format of the file.
+    format: BlocksFileFormat,
+    /// Buffer where the current line is read. Only used for [`BlocksFileFormat::JsonLines`].
+    line: String,
+}
+
+impl<'a> Reader<'a> {
+    /// Initializes a new [`Reader`].
+    pub fn new(inner: &'a mut (dyn io::BufRead + Send), format: BlocksFileFormat) -> Self {
+        Reader {
+            inner,
+            format,
+            line: String::new(),
+        }
+    }
+
+    /// Reads the next block of the file. Returns `None` if the end of the file has been
+    /// reached.
+    pub fn next_block(&mut self) -> Result<Option<Block>, ReadError> {
+        match self.format {
+            BlocksFileFormat::Scale => {
+                // The end of the file is only valid at the boundary between two blocks.
+                if self.inner.fill_buf().map_err(ReadError::Io)?.is_empty() {
+                    return Ok(None);
+                }
+
+                let scale_encoded_header = read_bytes(self.inner)?;
+                let num_extrinsics = read_compact(self.inner)?;
+                let mut scale_encoded_extrinsics = Vec::with_capacity(num_extrinsics.min(1024));
+                for _ in 0..num_extrinsics {
+                    scale_encoded_extrinsics.push(read_bytes(self.inner)?);
+                }
+                let num_justifications = read_compact(self.inner)?;
+                let mut scale_encoded_justifications =
+                    Vec::with_capacity(num_justifications.min(16));
+                for _ in 0..num_justifications {
+                    let mut consensus_engine_id = [0; 4];
+                    read_exact(self.inner, &mut consensus_engine_id)?;
+                    scale_encoded_justifications
+                        .push((consensus_engine_id, read_bytes(self.inner)?));
+                }
+
+                Ok(Some(Block {
+                    scale_encoded_header,
+                    scale_encoded_extrinsics,
+                    scale_encoded_justifications,
+                }))
+            }
+            BlocksFileFormat::JsonLines => loop {
+                self.line.clear();
+                if self
+                    .inner
+                    .read_line(&mut self.line)
+                    .map_err(ReadError::Io)?
+                    == 0
+                {
+                    return Ok(None);
+                }
+
+                // Empty lines are ignored.
+                if self.line.trim().is_empty() {
+                    continue;
+                }
+
+                let json_block = serde_json::from_str::<JsonBlock>(&self.line)
+                    .map_err(ReadError::InvalidJson)?;
+
+                let mut scale_encoded_justifications =
+                    Vec::with_capacity(json_block.justifications.len());
+                for justification in json_block.justifications {
+                    let consensus_engine_id = <[u8; 4]>::try_from(&justification.engine.0[..])
+                        .map_err(|_| ReadError::InvalidConsensusEngineId)?;
+                    scale_encoded_justifications
+                        .push((consensus_engine_id, justification.justification.0));
+                }
+
+                break Ok(Some(Block {
+                    scale_encoded_header: json_block.header.0,
+                    scale_encoded_extrinsics: json_block.body.into_iter().map(|e| e.0).collect(),
+                    scale_encoded_justifications,
+                }));
+            },
+        }
+    }
+}
+
+/// Error potentially returned by [`Reader::next_block`].
+#[derive(Debug, derive_more::Display)]
+pub enum ReadError {
+    /// Error while reading the file.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// The file ends in the middle of a block.
+    #[display(fmt = "Unexpected end of file")]
+    UnexpectedEof,
+    /// Invalid SCALE-compact-encoded number.
+    #[display(fmt = "Invalid SCALE-compact-encoded number")]
+    InvalidCompact,
+    /// Failed to parse a line of the file as a JSON block.
+    #[display(fmt = "Invalid JSON block: {_0}")]
+    InvalidJson(serde_json::Error),
+    /// Consensus engine identifier of a justification isn't 4 bytes.
+    #[display(fmt = "Invalid consensus engine identifier")]
+    InvalidConsensusEngineId,
+}
+
+/// Writes a SCALE-compact-encoded length followed with the given bytes.
+fn write_bytes(out: &mut dyn io::Write, bytes: &[u8]) -> Result<(), io::Error> {
+    write_compact(out, bytes.len())?;
+    out.write_all(bytes)
+}
+
+/// Writes a SCALE-compact-encoded number.
+fn write_compact(out: &mut dyn io::Write, value: usize) -> Result<(), io::Error> {
+    let value = u64::try_from(value).unwrap();
+    if value < 1 << 6 {
+        out.write_all(&[u8::try_from(value << 2).unwrap()])
+    } else if value < 1 << 14 {
+        out.write_all(&u16::try_from((value << 2) | 0b01).unwrap().to_le_bytes())
+    } else if value < 1 << 30 {
+        out.write_all(&u32::try_from((value << 2) | 0b10).unwrap().to_le_bytes())
+    } else {
+        let bytes = value.to_le_bytes();
+        let num_bytes = bytes.len() - bytes.iter().rev().take_while(|b| **b == 0).count();
+        out.write_all(&[(u8::try_from(num_bytes - 4).unwrap() << 2) | 0b11])?;
+        out.write_all(&bytes[..num_bytes])
+    }
+}
+
+/// Reads a SCALE-compact-encoded length followed with the bytes.
+fn read_bytes(inner: &mut (dyn io::BufRead + Send)) -> Result<Vec<u8>, ReadError> {
+    let len = read_compact(inner)?;
+
+    // The length isn't trusted and the buffer grows as data is read, in order to not allocate
+    // a huge buffer in case of a corrupted file.
+    let mut out = Vec::new();
+    io::Read::read_to_end(
+        &mut io::Read::take(&mut *inner, u64::try_from(len).unwrap()),
+        &mut out,
+    )
+    .map_err(ReadError::Io)?;
+    if out.len() != len {
+        return Err(ReadError::UnexpectedEof);
+    }
+    Ok(out)
+}
+
+/// Reads a SCALE-compact-encoded number.
+fn read_compact(inner: &mut (dyn io::BufRead + Send)) -> Result<usize, ReadError> {
+    let mut first_byte = [0];
+    read_exact(inner, &mut first_byte)?;
+
+    let value = match first_byte[0] & 0b11 {
+        0b00 => u64::from(first_byte[0] >> 2),
+        0b01 => {
+            let mut bytes = [first_byte[0], 0];
+            read_exact(inner, &mut bytes[1..])?;
+            u64::from(u16::from_le_bytes(bytes) >> 2)
+        }
+        0b10 => {
+            let mut bytes = [first_byte[0], 0, 0, 0];
+            read_exact(inner, &mut bytes[1..])?;
+            u64::from(u32::from_le_bytes(bytes) >> 2)
+        }
+        _ => {
+            let num_bytes = usize::from(first_byte[0] >> 2) + 4;
+            if num_bytes > 8 {
+                return Err(ReadError::InvalidCompact);
+            }
+            let mut bytes = [0; 8];
+            read_exact(inner, &mut bytes[..num_bytes])?;
+            u64::from_le_bytes(bytes)
+        }
+    };
+
+    usize::try_from(value).map_err(|_| ReadError::InvalidCompact)
+}
+
+/// Fills `out` with data from `inner`.
+fn read_exact(inner: &mut (dyn io::BufRead + Send), out: &mut [u8]) -> Result<(), ReadError> {
+    io::Read::read_exact(inner, out).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => ReadError::UnexpectedEof,
+        _ => ReadError::Io(err),
+    })
+}
@@ -24,7 +24,9 @@
 // TODO: doc
 // TODO: re-review this once finished
 
-use crate::{database_thread, jaeger_service, network_service, LogCallback, LogLevel};
+use crate::{
+    database_thread, jaeger_service, metrics_service, network_service, util, LogCallback, LogLevel,
+};
 
 use core::num::NonZeroU32;
 use futures_channel::{mpsc, oneshot};
@@ -34,9 +36,11 @@ use hashbrown::HashSet;
 use smol::lock::Mutex;
 use smoldot::{
     author,
-    chain::chain_information,
+    chain::{blocks_tree, chain_information},
     database::full_sqlite,
-    executor, header,
+    executor,
+    finality::grandpa,
+    header,
     identity::keystore,
     informant::HashDisplay,
     libp2p,
@@ -48,6 +52,8 @@ use smoldot::{
 use std::{
     array,
     borrow::Cow,
+    cmp,
+    collections::{BTreeMap, BTreeSet},
     iter, mem,
     num::{NonZeroU64, NonZeroUsize},
     sync::Arc,
@@ -74,6 +80,24 @@ pub struct Config {
     /// >           to compare against a known genesis hash and print a warning.
     pub genesis_block_hash: [u8; 32],
 
+    /// Runtime codes that must be used instead of the on-chain runtime code, indexed by the
+    /// number of the block starting from which they apply.
+    ///
+    /// See [`smoldot::chain_spec::ChainSpec::code_substitutes`].
+    pub code_substitutes: BTreeMap<u64, Vec<u8>>,
+
+    /// List of block numbers and hashes that the chain must contain. Blocks that don't match are
+    /// rejected, and peers announcing them aren't used to download blocks.
+    ///
+    /// See [`smoldot::chain_spec::ChainSpec::fork_blocks`].
+    pub fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// List of hashes of blocks that are known to be bad. These blocks and their descendants are
+    /// rejected, and peers announcing them are banned.
+    ///
+    /// See [`smoldot::chain_spec::ChainSpec::bad_blocks_hashes`].
+    pub bad_blocks: BTreeSet<[u8; 32]>,
+
     /// Stores of key to use for all block-production-related purposes.
     pub keystore: Arc<keystore::Keystore>,
 
@@ -91,6 +115,16 @@ pub struct Config {
     /// Service to use to report traces.
     pub jaeger_service: Arc<jaeger_service::JaegerService>,
 
+    /// Metrics to update.
+    pub metrics: metrics_service::ConsensusMetrics,
+
+    /// Channel used to obtain the transactions to include in the blocks that are authored
+    /// locally. See [`AuthoringTransactionsRequest`].
+    ///
+    /// If the receiving side is closed or doesn't answer in time, blocks are authored without
+    /// any transaction.
+    pub authoring_transactions_requests: mpsc::Sender<AuthoringTransactionsRequest>,
+
     /// A node has the authorization to author a block during a slot.
     ///
     /// In order for the network to perform well, a block should be authored and propagated
@@ -110,6 +144,36 @@ pub struct Config {
     pub slot_duration_author_ratio: u16,
 }
 
+/// Message sent by the consensus service on [`Config::authoring_transactions_requests`].
+#[derive(Debug)]
+pub enum AuthoringTransactionsRequest {
+    /// A block is about to be authored. The receiver must send back the list of SCALE-encoded
+    /// transactions to try include in this block, in the order in which they should be included.
+    ///
+    /// The list should be empty if the parent block isn't known by the receiver.
+    IncludableTransactions {
+        /// Hash of the parent of the block being authored.
+        parent_hash: [u8; 32],
+        /// Sender to use to send back the list of transactions. If it is dropped, the block is
+        /// authored without any transaction.
+        result_tx: oneshot::Sender<Vec<Vec<u8>>>,
+    },
+
+    /// A transaction returned through [`AuthoringTransactionsRequest::IncludableTransactions`]
+    /// has been rejected by the runtime when trying to include it in a block, and should be
+    /// removed from the pool.
+    ///
+    /// Transactions that have been rejected because the block is full are not reported.
+    TransactionInvalid {
+        /// Hash of the parent of the block that was being authored.
+        parent_hash: [u8; 32],
+        /// SCALE-encoded transaction.
+        transaction: Vec<u8>,
+        /// Error returned by the runtime.
+        error: author::runtime::TransactionValidityError,
+    },
+}
+
 /// Identifier for a blocks request to be performed.
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct BlocksRequestId(usize);
@@ -131,6 +195,10 @@ pub struct ConsensusService {
 
     /// See [`Config::block_number_bytes`].
     block_number_bytes: usize,
+
+    /// Locked while a block is being imported through [`ConsensusService::import_block`], as
+    /// the background task only imports one block at a time.
+    import_lock: Mutex<()>,
 }
 
 enum ToBackground {
@@ -155,6 +223,54 @@ enum ToBackground {
     IsMajorSyncingHint {
         result_tx: oneshot::Sender<bool>,
     },
+    SyncingPeers {
+        result_tx: oneshot::Sender<Vec<(libp2p::PeerId, network::codec::Role, u64, [u8; 32])>>,
+    },
+    ImportBlock {
+        scale_encoded_header: Vec<u8>,
+        scale_encoded_extrinsics: Vec<Vec<u8>>,
+        scale_encoded_justifications: Vec<([u8; 4], Vec<u8>)>,
+        result_tx: oneshot::Sender<Result<ImportBlockOutcome, ImportBlockError>>,
+    },
+}
+
+/// Outcome of a successful call to [`ConsensusService::import_block`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImportBlockOutcome {
+    /// Block has been verified and inserted in the database.
+    Imported,
+    /// Block was already present in the database. Its justifications, if any, have been
+    /// verified.
+    AlreadyKnown,
+}
+
+/// Error potentially returned by [`ConsensusService::import_block`].
+#[derive(Debug, derive_more::Display)]
+pub enum ImportBlockError {
+    /// Failed to decode the header of the block.
+    #[display(fmt = "Failed to decode block header: {_0}")]
+    InvalidHeader(header::Error),
+    /// Block isn't a child of the current best block.
+    #[display(fmt = "Parent of the block isn't the current best block")]
+    NotBestBlockChild,
+    /// Block is older than the finalized block but isn't part of the finalized chain.
+    #[display(fmt = "Block isn't part of the finalized chain")]
+    NotFinalizedChain,
+    /// Block doesn't match the fork blocks of the chain.
+    #[display(fmt = "Block doesn't match the fork blocks of the chain")]
+    ForkBlockMismatch,
+    /// Block, or its parent, is in the list of bad blocks of the chain.
+    #[display(fmt = "Block or its parent is known to be bad")]
+    BadBlock,
+    /// Failed to verify the header of the block.
+    #[display(fmt = "Failed to verify block header: {_0}")]
+    HeaderVerify(all::HeaderVerifyError),
+    /// Failed to verify the body of the block.
+    #[display(fmt = "Failed to verify block body: {_0}")]
+    BodyVerify(body_only::Error),
+    /// Failed to verify a justification of the block.
+    #[display(fmt = "Failed to verify justification: {_0}")]
+    JustificationVerify(blocks_tree::JustificationVerifyError),
 }
 
 /// Potential error when calling [`ConsensusService::new`].
@@ -170,6 +286,9 @@ pub enum InitError {
     FinalizedHeapPagesInvalid(executor::InvalidHeapPagesError),
     /// Error initializing the runtime of the finalized block.
     FinalizedRuntimeInit(executor::host::NewErr),
+    /// Error initializing the code substitute of the chain specification that applies to the
+    /// finalized block.
+    CodeSubstituteInit(executor::host::NewErr),
 }
 
 impl ConsensusService {
@@ -177,6 +296,7 @@ impl ConsensusService {
     pub async fn new(config: Config) -> Result<Arc<Self>, InitError> {
         // Perform the initial access to the database to load a bunch of information.
         let (
+            finalized_block_hash,
             finalized_block_number,
             finalized_heap_pages,
             finalized_code,
@@ -252,6 +372,7 @@ impl ConsensusService {
                         | Err(full_sqlite::StorageAccessError::UnknownBlock) => unreachable!(),
                     };
                     Ok((
+                        finalized_block_hash,
                         finalized_block_number,
                         finalized_heap_pages,
                         finalized_code,
@@ -284,6 +405,11 @@ impl ConsensusService {
             );
         }
 
+        let is_babe = matches!(
+            finalized_chain_information.as_ref().consensus,
+            chain_information::ChainInformationConsensusRef::Babe { .. }
+        );
+
         let mut sync = all::AllSync::new(all::Config {
             chain_information: finalized_chain_information,
             block_number_bytes: config.block_number_bytes,
@@ -305,24 +431,53 @@ impl ConsensusService {
             },
             full_mode: true,
             code_trie_node_hint: None,
+            fork_blocks: config.fork_blocks,
+            bad_blocks: config.bad_blocks,
         });
 
+        let mut code_substitutes = util::CodeSubstitutes::from(config.code_substitutes);
+
         let finalized_runtime = {
             // Builds the runtime of the finalized block.
             // Assumed to always be valid, otherwise the block wouldn't have been
             // saved in the database, hence the large number of unwraps here.
             let heap_pages = executor::storage_heap_pages_to_value(finalized_heap_pages.as_deref())
                 .map_err(InitError::FinalizedHeapPagesInvalid)?;
-            executor::host::HostVmPrototype::new(executor::host::Config {
+            let on_chain_runtime = executor::host::HostVmPrototype::new(executor::host::Config {
                 module: finalized_code,
                 heap_pages,
                 exec_hint: executor::vm::ExecHint::CompileAheadOfTime, // TODO: probably should be decided by the optimisticsync
                 allow_unresolved_imports: false,
             })
-            .map_err(InitError::FinalizedRuntimeInit)?
+            .map_err(InitError::FinalizedRuntimeInit)?;
+            code_substitutes
+                .substitute(finalized_block_number, &on_chain_runtime)
+                .map_err(InitError::CodeSubstituteInit)?
+                .unwrap_or(on_chain_runtime)
+        };
+
+        // The duration of a Babe slot isn't part of the chain information, and must be obtained
+        // by calling the runtime. It is not modifiable, so we only do it once.
+        let babe_slot_duration = if is_babe {
+            let slot_duration = babe_slot_duration(
+                &config.database,
+                finalized_block_hash,
+                finalized_runtime.clone(),
+            )
+            .await;
+            if slot_duration.is_none() {
+                config.log_callback.log(
+                    LogLevel::Warn,
+                    "block-author-babe-slot-duration-unknown".to_string(),
+                );
+            }
+            slot_duration
+        } else {
+            None
         };
 
         let block_author_sync_source = sync.add_source(None, best_block_number, best_block_hash);
+        let import_sync_source = sync.add_source(None, best_block_number, best_block_hash);
 
         let (block_requests_finished_tx, block_requests_finished_rx) = mpsc::channel(0);
         let (to_background_tx, to_background_rx) = mpsc::channel(4);
@@ -330,10 +485,17 @@ impl ConsensusService {
         let background_sync = SyncBackground {
             sync,
             block_author_sync_source,
+            import_sync_source,
             block_authoring: None,
+            grandpa_voter: None,
             authored_block: None,
+            latest_authored_block_hash: None,
+            imported_block: None,
             slot_duration_author_ratio: config.slot_duration_author_ratio,
+            babe_slot_duration,
             keystore: config.keystore,
+            code_substitutes,
+            authoring_transactions_requests: config.authoring_transactions_requests,
             finalized_runtime: Arc::new(Mutex::new(Some(finalized_runtime))),
             network_service: config.network_service.0,
             network_chain_id: config.network_service.1,
@@ -347,6 +509,7 @@ impl ConsensusService {
             block_requests_finished_tx,
             block_requests_finished_rx,
             jaeger_service: config.jaeger_service,
+            metrics: config.metrics,
         };
 
         background_sync.start();
@@ -354,6 +517,7 @@ impl ConsensusService {
         Ok(Arc::new(ConsensusService {
             block_number_bytes: config.block_number_bytes,
             to_background_tx: Mutex::new(to_background_tx),
+            import_lock: Mutex::new(()),
         }))
     }
 
@@ -454,6 +618,53 @@ impl ConsensusService {
             .await;
         result_rx.await.unwrap()
     }
+
+    /// Returns the list of peers that the syncing is currently connected to, with their role
+    /// and the number and hash of their best block.
+    pub async fn syncing_peers(
+        &self,
+    ) -> Vec<(libp2p::PeerId, network::codec::Role, u64, [u8; 32])> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SyncingPeers { result_tx })
+            .await;
+        result_rx.await.unwrap()
+    }
+
+    /// Verifies the given block and inserts it in the database, similar to what happens when a
+    /// block is downloaded from the network.
+    ///
+    /// The block must be a child of the current best block, unless it is already present in
+    /// the database. Its justifications, if any, are verified as well, and the block is
+    /// finalized if they are valid.
+    ///
+    /// This function waits until the block and its justifications have been verified. If it is
+    /// called multiple times simultaneously, the blocks are imported one after the other.
+    pub async fn import_block(
+        &self,
+        scale_encoded_header: Vec<u8>,
+        scale_encoded_extrinsics: Vec<Vec<u8>>,
+        scale_encoded_justifications: Vec<([u8; 4], Vec<u8>)>,
+    ) -> Result<ImportBlockOutcome, ImportBlockError> {
+        let _import_lock = self.import_lock.lock().await;
+
+        let (result_tx, result_rx) = oneshot::channel();
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ImportBlock {
+                scale_encoded_header,
+                scale_encoded_extrinsics,
+                scale_encoded_justifications,
+                result_tx,
+            })
+            .await;
+        result_rx.await.unwrap()
+    }
 }
 
 /// Return value of [`ConsensusService::subscribe_all`].
@@ -539,6 +750,9 @@ pub struct BlockNotification {
     /// True if this block is considered as the best block of the chain.
     pub is_new_best: bool,
 
+    /// True if this block has been authored by the local node.
+    pub is_locally_authored: bool,
+
     /// SCALE-encoded header of the block.
     pub scale_encoded_header: Vec<u8>,
 
@@ -573,8 +787,10 @@ struct SyncBackground {
     /// the network requests in progress.
     ///
     /// Each peer holds a struct containing either information about a networking peer, or `None`
-    /// if this is the "special source" representing the local block authoring. Only one source
-    /// must contain `None` and its id must be [`SyncBackground::block_author_sync_source`].
+    /// if this is one of the "special sources" representing the local block authoring and the
+    /// blocks imported through [`ConsensusService::import_block`]. Only two sources must contain
+    /// `None` and their ids must be [`SyncBackground::block_author_sync_source`] and
+    /// [`SyncBackground::import_sync_source`].
     ///
     /// Each block holds its runtime if it has been verified.
     ///
@@ -591,6 +807,10 @@ struct SyncBackground {
     /// Source within the [`SyncBackground::sync`] to use to import locally-authored blocks.
     block_author_sync_source: all::SourceId,
 
+    /// Source within the [`SyncBackground::sync`] to use to import blocks provided through
+    /// [`ConsensusService::import_block`].
+    import_sync_source: all::SourceId,
+
     /// State of the authoring. If `None`, the builder should be (re)created. If `Some`, also
     /// contains the list of public keys that were loaded from the keystore when creating the
     /// builder.
@@ -606,14 +826,35 @@ struct SyncBackground {
     /// See [`Config::slot_duration_author_ratio`].
     slot_duration_author_ratio: u16,
 
+    /// Duration, in milliseconds, of a Babe slot. `None` if the chain doesn't use Babe, or if
+    /// the duration couldn't be determined, in which case no block is authored.
+    babe_slot_duration: Option<NonZeroU64>,
+
+    /// GrandPa voter of the current authorities set, or `None` if the chain doesn't use GrandPa
+    /// or if the voter hasn't been created yet. Recreated whenever the authorities set changes.
+    grandpa_voter: Option<grandpa::voter::Voter<Instant>>,
+
     /// After a block has been authored, it is inserted here while waiting for the `sync` to
     /// import it. Contains the block height, the block hash, the SCALE-encoded block header, and
     /// the list of SCALE-encoded extrinsics of the block.
     authored_block: Option<(u64, [u8; 32], Vec<u8>, Vec<Vec<u8>>)>,
 
+    /// Hash of the latest block that has been authored locally. Used in order to indicate in
+    /// [`BlockNotification::is_locally_authored`] whether a block has been authored locally.
+    latest_authored_block_hash: Option<[u8; 32]>,
+
+    /// Block provided through [`ConsensusService::import_block`] and that is being imported.
+    imported_block: Option<ImportedBlock>,
+
     /// See [`Config::keystore`].
     keystore: Arc<keystore::Keystore>,
 
+    /// See [`Config::code_substitutes`]. Caches the substitute runtimes once compiled.
+    code_substitutes: util::CodeSubstitutes,
+
+    /// See [`Config::authoring_transactions_requests`].
+    authoring_transactions_requests: mpsc::Sender<AuthoringTransactionsRequest>,
+
     /// Runtime of the latest finalized block.
     ///
     /// The runtime is extracted when necessary then put back it place.
@@ -672,6 +913,26 @@ struct SyncBackground {
 
     /// How to report events about blocks.
     jaeger_service: Arc<jaeger_service::JaegerService>,
+
+    /// See [`Config::metrics`].
+    metrics: metrics_service::ConsensusMetrics,
+}
+
+/// See [`SyncBackground::imported_block`].
+struct ImportedBlock {
+    /// Height of the block.
+    height: u64,
+    /// Hash of the block.
+    hash: [u8; 32],
+    /// SCALE-encoded header, list of SCALE-encoded extrinsics, and list of justifications of
+    /// the block. `None` if they have already been provided to [`SyncBackground::sync`].
+    data: Option<(Vec<u8>, Vec<Vec<u8>>, Vec<([u8; 4], Vec<u8>)>)>,
+    /// Outcome to report once [`SyncBackground::sync`] has nothing more to process, which
+    /// guarantees that the justifications of the block have been verified. `None` if the
+    /// block hasn't been verified yet.
+    outcome: Option<ImportBlockOutcome>,
+    /// Sender to use to report the result of the import.
+    result_tx: oneshot::Sender<Result<ImportBlockOutcome, ImportBlockError>>,
 }
 
 #[derive(Clone)]
@@ -691,6 +952,8 @@ enum NonFinalizedBlock {
 struct NetworkSourceInfo {
     /// Identity of the peer according to the networking.
     peer_id: libp2p::PeerId,
+    /// Role of the peer, as reported when connecting.
+    role: network::codec::Role,
     /// If `true`, this peer is considered disconnected by the network, and no new request should
     /// be started against it.
     is_disconnected: bool,
@@ -716,10 +979,21 @@ impl SyncBackground {
         let mut process_sync = true;
 
         loop {
+            self.metrics
+                .best_block_height
+                .set(self.sync.best_block_number());
+            self.metrics
+                .finalized_block_height
+                .set(self.sync.finalized_block_header().number);
+
             self.start_network_requests().await;
+            if self.process_grandpa_voter().await {
+                process_sync = true;
+            }
 
             enum WakeUpReason {
                 ReadyToAuthor,
+                GrandpaVoterReady,
                 FrontendEvent(ToBackground),
                 FrontendClosed,
                 NetworkEvent(network_service::Event),
@@ -784,24 +1058,62 @@ impl SyncBackground {
                                                 .duration_since(SystemTime::UNIX_EPOCH)
                                                 .unwrap(),
                                             slot_duration,
+                                            parent_slot_number: self
+                                                .sync
+                                                .best_block_header()
+                                                .digest
+                                                .aura_pre_runtime()
+                                                .map(|digest| digest.slot_number),
                                         },
                                     }),
                                     local_authorities,
                                 )),
                             ),
                             (
-                                None,
-                                chain_information::ChainInformationConsensusRef::Babe { .. },
-                            ) => {
-                                None // TODO: the block authoring doesn't support Babe at the moment
-                            }
+                                block_authoring @ None,
+                                chain_information::ChainInformationConsensusRef::Babe {
+                                    slots_per_epoch,
+                                    finalized_block_epoch_information, // TODO: field name not appropriate; should probably change the chain_information module
+                                    finalized_next_epoch_transition,
+                                },
+                            ) => match self.babe_slot_duration {
+                                Some(slot_duration) => Some(
+                                    block_authoring.insert((
+                                        author::build::Builder::new(author::build::Config {
+                                            consensus: author::build::ConfigConsensus::Babe {
+                                                now_from_unix_epoch: SystemTime::now()
+                                                    .duration_since(SystemTime::UNIX_EPOCH)
+                                                    .unwrap(),
+                                                slot_duration,
+                                                slots_per_epoch,
+                                                parent_slot_number: self
+                                                    .sync
+                                                    .best_block_header()
+                                                    .digest
+                                                    .babe_pre_runtime()
+                                                    .map(|digest| digest.slot_number()),
+                                                parent_block_epoch:
+                                                    finalized_block_epoch_information,
+                                                parent_block_next_epoch:
+                                                    finalized_next_epoch_transition,
+                                                local_authorities: local_authorities.iter(),
+                                            },
+                                        }),
+                                        local_authorities,
+                                    )),
+                                ),
+                                None => None,
+                            },
                             (None, _) => todo!(),
                         };
 
                     match &block_authoring {
-                        Some((author::build::Builder::Ready(_), _)) => future::Either::Left(
-                            future::Either::Left(future::ready(Instant::now())),
-                        ),
+                        Some((
+                            author::build::Builder::Ready(_) | author::build::Builder::ClaimSlot(_),
+                            _,
+                        )) => future::Either::Left(future::Either::Left(future::ready(
+                            Instant::now(),
+                        ))),
                         Some((author::build::Builder::WaitSlot(when), _)) => {
                             let delay = (UNIX_EPOCH + when.when())
                                 .duration_since(SystemTime::now())
@@ -828,6 +1140,15 @@ impl SyncBackground {
                     authoring_ready_future.await;
                     WakeUpReason::ReadyToAuthor
                 }
+                .or(async {
+                    match self.grandpa_voter.as_ref().and_then(|v| v.next_wake_up()) {
+                        Some(when) => {
+                            smol::Timer::at(when).await;
+                        }
+                        None => future::pending().await,
+                    }
+                    WakeUpReason::GrandpaVoterReady
+                })
                 .or(async {
                     self.to_background_rx
                         .next()
@@ -861,11 +1182,48 @@ impl SyncBackground {
                             self.author_block().await;
                         }
                         Some((author::build::Builder::WaitSlot(when), local_authorities)) => {
-                            self.block_authoring = Some((
-                                author::build::Builder::Ready(when.start()),
-                                local_authorities,
-                            ));
-                            self.author_block().await;
+                            self.block_authoring = Some((when.start(), local_authorities));
+                            if let Some((author::build::Builder::Ready(_), _)) =
+                                self.block_authoring
+                            {
+                                self.author_block().await;
+                            }
+                        }
+                        Some((author::build::Builder::ClaimSlot(claim), local_authorities)) => {
+                            // Generate a VRF output in order to try claim the slot. Signing is
+                            // done through `self.keystore`.
+                            let sign_result = self
+                                .keystore
+                                .sign_sr25519_vrf(
+                                    keystore::KeyNamespace::Babe,
+                                    &local_authorities[claim.authority_index()],
+                                    author::babe::VRF_TRANSCRIPT_LABEL,
+                                    claim.vrf_transcript_items(),
+                                )
+                                .await;
+
+                            let builder = match sign_result {
+                                Ok(signature) => {
+                                    claim.inject_vrf_signature(signature.output, signature.proof)
+                                }
+                                Err(error) => {
+                                    // Because the keystore is subject to race conditions, it is
+                                    // possible for this situation to happen if the key has been
+                                    // removed from the keystore in parallel.
+                                    self.log_callback.log(
+                                        LogLevel::Warn,
+                                        format!("block-author-vrf-signing-error; error={}", error),
+                                    );
+                                    claim.skip()
+                                }
+                            };
+
+                            self.block_authoring = Some((builder, local_authorities));
+                            if let Some((author::build::Builder::Ready(_), _)) =
+                                self.block_authoring
+                            {
+                                self.author_block().await;
+                            }
                         }
                         Some((author::build::Builder::Idle, _)) => {
                             self.block_authoring = None;
@@ -928,6 +1286,7 @@ impl SyncBackground {
                                 is_new_best: header::hash_from_scale_encoded_header(
                                     &scale_encoding,
                                 ) == best_hash,
+                                is_locally_authored: false,
                                 block_hash: header::hash_from_scale_encoded_header(&scale_encoding),
                                 scale_encoded_header: scale_encoding,
                                 runtime_update,
@@ -974,10 +1333,143 @@ impl SyncBackground {
 
                     let _ = result_tx.send(result);
                 }
+                WakeUpReason::FrontendEvent(ToBackground::SyncingPeers { result_tx }) => {
+                    let peers = self
+                        .sync
+                        .sources()
+                        .filter_map(|source_id| {
+                            let info = self.sync[source_id].as_ref()?;
+                            if info.is_disconnected {
+                                return None;
+                            }
+                            let (best_number, best_hash) = self.sync.source_best_block(source_id);
+                            Some((info.peer_id.clone(), info.role, best_number, *best_hash))
+                        })
+                        .collect();
+
+                    let _ = result_tx.send(peers);
+                }
+                WakeUpReason::FrontendEvent(ToBackground::ImportBlock {
+                    scale_encoded_header,
+                    scale_encoded_extrinsics,
+                    scale_encoded_justifications,
+                    result_tx,
+                }) => {
+                    // `ConsensusService::import_block` guarantees that only one block is
+                    // imported at a time.
+                    debug_assert!(self.imported_block.is_none());
+
+                    let block_number_bytes = self.sync.block_number_bytes();
+                    let (height, parent_hash) =
+                        match header::decode(&scale_encoded_header, block_number_bytes) {
+                            Ok(header) => (header.number, *header.parent_hash),
+                            Err(err) => {
+                                let _ = result_tx.send(Err(ImportBlockError::InvalidHeader(err)));
+                                continue;
+                            }
+                        };
+                    let hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+
+                    // Blocks that are already finalized are only checked against the database.
+                    if height <= self.sync.finalized_block_header().number {
+                        let is_in_database = self
+                            .database
+                            .with_database(move |database| {
+                                database.block_scale_encoded_header(&hash)
+                            })
+                            .await
+                            .expect("database access error")
+                            .is_some();
+                        let _ = result_tx.send(if is_in_database {
+                            Ok(ImportBlockOutcome::AlreadyKnown)
+                        } else {
+                            Err(ImportBlockError::NotFinalizedChain)
+                        });
+                        continue;
+                    }
+
+                    // If the block has already been verified, only its justifications, if any,
+                    // need to be verified.
+                    if self
+                        .sync
+                        .non_finalized_blocks_unordered()
+                        .any(|h| h.hash(block_number_bytes) == hash)
+                    {
+                        let mut justification_injected = false;
+                        for (consensus_engine_id, justification) in scale_encoded_justifications {
+                            match self
+                                .sync
+                                .inject_justification(consensus_engine_id, justification)
+                            {
+                                all::InjectJustificationOutcome::Queued => {
+                                    justification_injected = true
+                                }
+                                all::InjectJustificationOutcome::Discarded => {}
+                            }
+                        }
+
+                        if justification_injected {
+                            self.imported_block = Some(ImportedBlock {
+                                height,
+                                hash,
+                                data: None,
+                                outcome: Some(ImportBlockOutcome::AlreadyKnown),
+                                result_tx,
+                            });
+                            process_sync = true;
+                        } else {
+                            let _ = result_tx.send(Ok(ImportBlockOutcome::AlreadyKnown));
+                        }
+                        continue;
+                    }
+
+                    if parent_hash != self.sync.best_block_hash() {
+                        let _ = result_tx.send(Err(ImportBlockError::NotBestBlockChild));
+                        continue;
+                    }
+
+                    // Similar to locally-authored blocks, the block is imported in `self.sync`
+                    // by pretending that it comes from a source of blocks.
+                    match self.sync.block_announce(
+                        self.import_sync_source,
+                        scale_encoded_header.clone(),
+                        true,
+                    ) {
+                        all::BlockAnnounceOutcome::HeaderVerify
+                        | all::BlockAnnounceOutcome::StoredForLater
+                        | all::BlockAnnounceOutcome::Discarded => {}
+                        all::BlockAnnounceOutcome::ForkBlockMismatch => {
+                            let _ = result_tx.send(Err(ImportBlockError::ForkBlockMismatch));
+                            continue;
+                        }
+                        all::BlockAnnounceOutcome::BadBlock => {
+                            let _ = result_tx.send(Err(ImportBlockError::BadBlock));
+                            continue;
+                        }
+                        all::BlockAnnounceOutcome::TooOld { .. }
+                        | all::BlockAnnounceOutcome::AlreadyInChain
+                        | all::BlockAnnounceOutcome::NotFinalizedChain
+                        | all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
+                    }
+
+                    self.imported_block = Some(ImportedBlock {
+                        height,
+                        hash,
+                        data: Some((
+                            scale_encoded_header,
+                            scale_encoded_extrinsics,
+                            scale_encoded_justifications,
+                        )),
+                        outcome: None,
+                        result_tx,
+                    });
+                    process_sync = true;
+                }
 
                 WakeUpReason::NetworkEvent(network_service::Event::Connected {
                     peer_id,
                     chain_id,
+                    role,
                     best_block_number,
                     best_block_hash,
                 }) if chain_id == self.network_chain_id => {
@@ -997,6 +1489,7 @@ impl SyncBackground {
                             let id = self.sync.add_source(
                                 Some(NetworkSourceInfo {
                                     peer_id: entry.key().clone(),
+                                    role,
                                     is_disconnected: false,
                                 }),
                                 best_block_number,
@@ -1030,9 +1523,8 @@ impl SyncBackground {
                     scale_encoded_header,
                     is_best,
                 }) if chain_id == self.network_chain_id => {
-                    let _jaeger_span = self.jaeger_service.block_announce_process_span(
-                        &header::hash_from_scale_encoded_header(&scale_encoded_header),
-                    );
+                    let hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+                    let _jaeger_span = self.jaeger_service.block_announce_process_span(&hash);
 
                     let id = *self.peers_source_id_map.get(&peer_id).unwrap();
                     // TODO: log the outcome
@@ -1043,9 +1535,167 @@ impl SyncBackground {
                         all::BlockAnnounceOutcome::NotFinalizedChain => {}
                         all::BlockAnnounceOutcome::Discarded => {}
                         all::BlockAnnounceOutcome::StoredForLater {} => {}
+                        all::BlockAnnounceOutcome::ForkBlockMismatch => {}
+                        all::BlockAnnounceOutcome::BadBlock => {
+                            // The peer is following a chain that will never be accepted, and is
+                            // thus useless to us.
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "block-announce-bad-block; peer_id={}; hash={}",
+                                    peer_id,
+                                    HashDisplay(&hash)
+                                ),
+                            );
+                            self.network_service
+                                .ban_and_disconnect(
+                                    peer_id,
+                                    self.network_chain_id,
+                                    Duration::from_secs(60),
+                                    "bad-block-announce",
+                                )
+                                .await;
+                        }
                         all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
                     }
                 }
+                WakeUpReason::GrandpaVoterReady => {
+                    // The voter is processed at the beginning of the next loop iteration.
+                }
+
+                WakeUpReason::NetworkEvent(network_service::Event::GrandpaNeighborPacket {
+                    chain_id,
+                    peer_id,
+                    state,
+                }) if chain_id == self.network_chain_id => {
+                    let Some(voter) = &self.grandpa_voter else {
+                        continue;
+                    };
+
+                    let Some(request) = voter.catch_up_request(&network::codec::NeighborPacket {
+                        round_number: state.round_number,
+                        set_id: state.set_id,
+                        commit_finalized_height: state.commit_finalized_height,
+                    }) else {
+                        continue;
+                    };
+
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "grandpa-catch-up-request-send; peer_id={}; round_number={}; set_id={}",
+                            peer_id, request.round_number, request.set_id
+                        ),
+                    );
+
+                    let message = network::codec::GrandpaNotificationRef::CatchUpRequest(request)
+                        .scale_encoding(self.sync.block_number_bytes())
+                        .fold(Vec::new(), |mut a, b| {
+                            a.extend_from_slice(b.as_ref());
+                            a
+                        });
+                    let _ = self
+                        .network_service
+                        .clone()
+                        .send_grandpa_message(peer_id, self.network_chain_id, message)
+                        .await;
+                }
+                WakeUpReason::NetworkEvent(network_service::Event::GrandpaVote {
+                    chain_id,
+                    peer_id,
+                    message,
+                }) if chain_id == self.network_chain_id => {
+                    let Some(voter) = &mut self.grandpa_voter else {
+                        continue;
+                    };
+
+                    match voter.inject_vote(&message.decode()) {
+                        Ok(grandpa::voter::InjectVoteOutcome::Accepted)
+                        | Ok(grandpa::voter::InjectVoteOutcome::Duplicate) => {}
+                        Ok(grandpa::voter::InjectVoteOutcome::Equivocation(equivocation)) => {
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "grandpa-equivocation; authority={}; round_number={}; kind={:?}; \
+                                    first_target={}; second_target={}",
+                                    HashDisplay(&equivocation.authority_public_key),
+                                    equivocation.round_number,
+                                    equivocation.kind,
+                                    HashDisplay(&equivocation.first.0),
+                                    HashDisplay(&equivocation.second.0),
+                                ),
+                            );
+                        }
+                        Err(error) => {
+                            self.log_callback.log(
+                                LogLevel::Debug,
+                                format!("grandpa-vote-error; peer_id={}; error={}", peer_id, error),
+                            );
+                        }
+                    }
+                }
+                WakeUpReason::NetworkEvent(network_service::Event::GrandpaCatchUpRequest {
+                    chain_id,
+                    peer_id,
+                    request,
+                }) if chain_id == self.network_chain_id => {
+                    let Some(response) = self
+                        .grandpa_voter
+                        .as_ref()
+                        .and_then(|voter| voter.catch_up_response(&request))
+                    else {
+                        continue;
+                    };
+
+                    let _ = self
+                        .network_service
+                        .clone()
+                        .send_grandpa_message(peer_id, self.network_chain_id, response)
+                        .await;
+                }
+                WakeUpReason::NetworkEvent(network_service::Event::GrandpaCatchUp {
+                    chain_id,
+                    peer_id,
+                    message,
+                }) if chain_id == self.network_chain_id => {
+                    let Some(voter) = &mut self.grandpa_voter else {
+                        continue;
+                    };
+
+                    match voter.inject_catch_up(Instant::now(), &message.decode()) {
+                        Ok(()) => {
+                            self.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "grandpa-catch-up-success; peer_id={}; round_number={}",
+                                    peer_id,
+                                    voter.round_number()
+                                ),
+                            );
+
+                            let state = network::service::GrandpaState {
+                                round_number: voter.round_number(),
+                                set_id: voter.authorities_set_id(),
+                                commit_finalized_height: voter.finalized_block().1,
+                            };
+                            self.save_grandpa_voter_round(state.set_id, state.round_number)
+                                .await;
+                            self.network_service
+                                .set_grandpa_state(self.network_chain_id, state)
+                                .await;
+                        }
+                        Err(error) => {
+                            self.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "grandpa-catch-up-error; peer_id={}; error={}",
+                                    peer_id, error
+                                ),
+                            );
+                        }
+                    }
+                }
+
                 WakeUpReason::NetworkEvent(_) => {
                     // Different chain index.
                 }
@@ -1106,6 +1756,300 @@ impl SyncBackground {
         }
     }
 
+    /// Creates [`SyncBackground::grandpa_voter`] if necessary, then processes the actions that it
+    /// requests, such as signing and gossiping votes.
+    ///
+    /// Returns `true` if a justification has been injected in [`SyncBackground::sync`], in which
+    /// case the sync state machine needs to be processed.
+    async fn process_grandpa_voter(&mut self) -> bool {
+        let block_number_bytes = self.sync.block_number_bytes();
+
+        // (Re)create the voter if the authorities set has changed.
+        let new_authorities = match (
+            &self.grandpa_voter,
+            self.sync.as_chain_information().as_ref().finality,
+        ) {
+            (_, chain_information::ChainInformationFinalityRef::Outsourced) => return false,
+            (
+                Some(voter),
+                chain_information::ChainInformationFinalityRef::Grandpa {
+                    after_finalized_block_authorities_set_id,
+                    ..
+                },
+            ) if voter.authorities_set_id() == after_finalized_block_authorities_set_id => None,
+            (
+                _,
+                chain_information::ChainInformationFinalityRef::Grandpa {
+                    after_finalized_block_authorities_set_id,
+                    finalized_triggered_authorities,
+                    ..
+                },
+            ) => Some((
+                after_finalized_block_authorities_set_id,
+                finalized_triggered_authorities.to_vec(),
+            )),
+        };
+
+        if let Some((set_id, authorities)) = new_authorities {
+            if authorities.is_empty() {
+                self.grandpa_voter = None;
+                return false;
+            }
+
+            // Calling `keys()` on the keystore is racy, but that's considered acceptable and
+            // part of the design of the node.
+            let local_authority = self
+                .keystore
+                .keys()
+                .await
+                .filter(|(namespace, _)| *namespace == keystore::KeyNamespace::Grandpa)
+                .map(|(_, key)| key)
+                .find(|key| authorities.iter().any(|a| a.public_key == *key));
+
+            let finalized_block_hash = self.sync.finalized_block_header().hash(block_number_bytes);
+            let finalized_block_number = self.sync.finalized_block_header().number;
+
+            // Resume from the state saved before the node has been restarted, if any, in order
+            // to never vote again in a round that has already been voted in.
+            let (local_authority, round_number, local_votes) = match self
+                .database
+                .with_database(move |database| database.grandpa_voter_state(set_id))
+                .await
+            {
+                Ok(Some((round_number, local_votes))) => {
+                    (local_authority, round_number, local_votes)
+                }
+                Ok(None) => (local_authority, 1, Vec::new()),
+                Err(error) => {
+                    // Without knowing which votes have already been cast, voting might lead to
+                    // an equivocation. Only observe the other authorities instead.
+                    self.log_callback.log(
+                        LogLevel::Error,
+                        format!("grandpa-voter-state-load-error; error={}", error),
+                    );
+                    (None, 1, Vec::new())
+                }
+            };
+
+            let mut voter = grandpa::voter::Voter::new(grandpa::voter::Config {
+                now: Instant::now(),
+                block_number_bytes,
+                authorities_set_id: set_id,
+                authorities,
+                local_authority,
+                finalized_block_hash,
+                finalized_block_number,
+                round_number,
+                local_votes,
+                // Matches the value used by Substrate nodes.
+                gossip_duration: Duration::from_secs(1),
+                randomness_seed: rand::random(),
+            });
+
+            for header in self.sync.non_finalized_blocks_ancestry_order() {
+                voter.block_imported(
+                    header.hash(block_number_bytes),
+                    header.number,
+                    *header.parent_hash,
+                );
+            }
+            voter.set_best_block(self.sync.best_block_hash());
+
+            self.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "grandpa-voter-start; set_id={}; round_number={}; local_authority={}",
+                    set_id,
+                    voter.round_number(),
+                    match local_authority {
+                        Some(key) => HashDisplay(&key).to_string(),
+                        None => "none".to_string(),
+                    }
+                ),
+            );
+
+            self.save_grandpa_voter_round(set_id, voter.round_number())
+                .await;
+
+            self.network_service
+                .set_grandpa_state(
+                    self.network_chain_id,
+                    network::service::GrandpaState {
+                        round_number: voter.round_number(),
+                        set_id,
+                        commit_finalized_height: finalized_block_number,
+                    },
+                )
+                .await;
+
+            self.grandpa_voter = Some(voter);
+        }
+
+        let mut justification_injected = false;
+
+        loop {
+            let Some(voter) = &mut self.grandpa_voter else {
+                break;
+            };
+
+            let Some(action) = voter.next_action(&Instant::now()) else {
+                break;
+            };
+
+            match action {
+                grandpa::voter::Action::Vote(vote) => {
+                    let sign_result = self
+                        .keystore
+                        .sign(
+                            keystore::KeyNamespace::Grandpa,
+                            &vote.authority_public_key,
+                            &vote.payload_to_sign(),
+                        )
+                        .await;
+
+                    let signature = match sign_result {
+                        Ok(signature) => signature,
+                        Err(error) => {
+                            // Because the keystore is subject to race conditions, it is
+                            // possible for this situation to happen if the key has been
+                            // removed from the keystore in parallel.
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!("grandpa-vote-signing-error; error={}", error),
+                            );
+                            continue;
+                        }
+                    };
+
+                    // The vote is saved in the database before being sent out, in order to
+                    // never send a different vote in the same round after a restart.
+                    let set_id = vote.set_id;
+                    let local_vote = grandpa::voter::LocalVote {
+                        round_number: vote.round_number,
+                        kind: vote.kind,
+                        target_hash: vote.target_hash,
+                        target_number: vote.target_number,
+                        authority_public_key: vote.authority_public_key,
+                        signature,
+                    };
+                    let save_result = self
+                        .database
+                        .with_database(move |database| {
+                            database.insert_grandpa_voter_local_vote(set_id, &local_vote)
+                        })
+                        .await;
+                    if let Err(error) = save_result {
+                        self.log_callback.log(
+                            LogLevel::Error,
+                            format!(
+                                "grandpa-vote-save-error; round_number={}; kind={:?}; error={}",
+                                vote.round_number, vote.kind, error
+                            ),
+                        );
+                        continue;
+                    }
+
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "grandpa-vote; round_number={}; kind={:?}; target_hash={}; target_number={}",
+                            vote.round_number,
+                            vote.kind,
+                            HashDisplay(&vote.target_hash),
+                            vote.target_number
+                        ),
+                    );
+
+                    let message = self
+                        .grandpa_voter
+                        .as_mut()
+                        .unwrap()
+                        .inject_local_vote(vote, signature);
+                    self.network_service
+                        .broadcast_grandpa_message(self.network_chain_id, message)
+                        .await;
+                }
+                grandpa::voter::Action::Finalized(commit) => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "grandpa-finalized; round_number={}; target_hash={}; target_number={}",
+                            commit.round_number,
+                            HashDisplay(&commit.target_hash),
+                            commit.target_number
+                        ),
+                    );
+
+                    // Justifications must contain the headers of the blocks that have been voted
+                    // on and that are descendants of the finalized block.
+                    let votes_ancestries = self
+                        .sync
+                        .non_finalized_blocks_unordered()
+                        .filter(|h| {
+                            commit
+                                .votes_ancestries
+                                .contains(&h.hash(block_number_bytes))
+                        })
+                        .map(|h| h.scale_encoding_vec(block_number_bytes))
+                        .collect::<Vec<_>>();
+                    let justification = commit.scale_encoded_justification(
+                        block_number_bytes,
+                        votes_ancestries.iter().map(|h| &h[..]),
+                    );
+
+                    match self.sync.inject_justification(*b"FRNK", justification) {
+                        all::InjectJustificationOutcome::Queued => justification_injected = true,
+                        all::InjectJustificationOutcome::Discarded => {}
+                    }
+
+                    self.network_service
+                        .broadcast_grandpa_message(
+                            self.network_chain_id,
+                            commit.scale_encoded_notification(block_number_bytes),
+                        )
+                        .await;
+                }
+                grandpa::voter::Action::RoundStarted { round_number } => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!("grandpa-round-start; round_number={}", round_number),
+                    );
+
+                    let state = network::service::GrandpaState {
+                        round_number,
+                        set_id: voter.authorities_set_id(),
+                        commit_finalized_height: voter.finalized_block().1,
+                    };
+                    self.save_grandpa_voter_round(state.set_id, round_number)
+                        .await;
+                    self.network_service
+                        .set_grandpa_state(self.network_chain_id, state)
+                        .await;
+                }
+            }
+        }
+
+        justification_injected
+    }
+
+    /// Stores in the database the round reached by [`SyncBackground::grandpa_voter`], so that
+    /// the voter resumes from this round after a restart.
+    async fn save_grandpa_voter_round(&mut self, set_id: u64, round_number: u64) {
+        let result = self
+            .database
+            .with_database(move |database| database.set_grandpa_voter_round(set_id, round_number))
+            .await;
+        if let Err(error) = result {
+            self.log_callback.log(
+                LogLevel::Error,
+                format!(
+                    "grandpa-voter-round-save-error; set_id={}; round_number={}; error={}",
+                    set_id, round_number, error
+                ),
+            );
+        }
+    }
+
     /// Authors a block, then imports it and gossips it out.
     ///
     /// # Panic
@@ -1120,7 +2064,13 @@ impl SyncBackground {
             _ => panic!(),
         };
 
-        // TODO: it is possible that the current best block is already the same authoring slot as the slot we want to claim ; unclear how to solve this
+        // Namespace of the key that must sign the block.
+        let key_namespace = match self.sync.best_block_consensus() {
+            chain_information::ChainInformationConsensusRef::Babe { .. } => {
+                keystore::KeyNamespace::Babe
+            }
+            _ => keystore::KeyNamespace::Aura,
+        };
 
         let parent_number = self.sync.best_block_number();
         self.log_callback.log(
@@ -1146,7 +2096,6 @@ impl SyncBackground {
         // Most parts of the block authorship can't be accelerated, in particular the
         // initialization and the signing at the end. This end of authoring threshold is only
         // checked when deciding whether to continue including more transactions in the block.
-        // TODO: use this
         // TODO: Substrate nodes increase the time available for authoring if it detects that slots have been skipped, in order to account for the possibility that the initialization of a block or the inclusion of an extrinsic takes too long
         let authoring_end = {
             let start = authoring_start.slot_start_from_unix_epoch();
@@ -1159,6 +2108,39 @@ impl SyncBackground {
                     / u32::from(u16::max_value())
         };
 
+        // Ask the transactions pool for the list of transactions to include in the block.
+        // The pool runs in a different task and might be busy, for example waiting for this very
+        // service to answer one of its requests. For this reason, we only wait for a short amount
+        // of time, after which a block without any transaction is authored.
+        let mut transactions = {
+            let (result_tx, result_rx) = oneshot::channel();
+            let request = AuthoringTransactionsRequest::IncludableTransactions {
+                parent_hash: self.sync.best_block_hash(),
+                result_tx,
+            };
+            if self
+                .authoring_transactions_requests
+                .try_send(request)
+                .is_ok()
+            {
+                let timeout = cmp::min(
+                    authoring_end
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_else(|_| Duration::new(0, 0)),
+                    Duration::from_millis(500),
+                );
+                async { result_rx.await.unwrap_or_default() }
+                    .or(async {
+                        smol::Timer::after(timeout).await;
+                        Vec::new()
+                    })
+                    .await
+            } else {
+                Vec::new()
+            }
+        }
+        .into_iter();
+
         // Actual block production now happening.
         let (new_block_header, new_block_body, authoring_logs) = {
             let parent_hash = self.sync.best_block_hash();
@@ -1186,12 +2168,16 @@ impl SyncBackground {
                         .duration_since(SystemTime::UNIX_EPOCH)
                         .unwrap(),
                     parent_runtime,
-                    block_body_capacity: 0, // TODO: could be set to the size of the tx pool
+                    block_body_capacity: transactions.len(),
                     max_log_level: 0,
                     calculate_trie_changes: true,
                 })
             };
 
+            // Transaction currently being applied, and whether the block has been reported as full.
+            let mut transaction_in_progress = None;
+            let mut block_full = false;
+
             // The block authoring process jumps through various states, interrupted when it needs
             // access to the storage of the best block.
             loop {
@@ -1201,10 +2187,9 @@ impl SyncBackground {
                         // successful, and the only thing remaining to do is sign the block
                         // header. Signing is done through `self.keystore`.
 
-                        // TODO: correct key namespace
                         let data_to_sign = seal.to_sign();
                         let sign_future = self.keystore.sign(
-                            keystore::KeyNamespace::Aura,
+                            key_namespace,
                             &local_authorities[seal.authority_index()],
                             &data_to_sign,
                         );
@@ -1256,23 +2241,68 @@ impl SyncBackground {
                     // Part of the block production consists in adding transactions to the block.
                     // These transactions are extracted from the transactions pool.
                     author::build::BuilderAuthoring::ApplyExtrinsic(apply) => {
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = apply.finish();
+                        // No more transaction is included once the authoring deadline has been
+                        // reached, in order to leave time for the block to be propagated.
+                        if block_full || SystemTime::now() >= authoring_end {
+                            block_authoring = apply.finish();
+                            continue;
+                        }
+
+                        match transactions.next() {
+                            Some(transaction) => {
+                                block_authoring = apply.add_extrinsic(transaction.clone());
+                                transaction_in_progress = Some(transaction);
+                            }
+                            None => block_authoring = apply.finish(),
+                        }
                     }
                     author::build::BuilderAuthoring::ApplyExtrinsicResult { result, resume } => {
-                        if let Err(error) = result {
-                            // TODO: include transaction bytes or something?
-                            self.log_callback.log(
-                                LogLevel::Warn,
-                                format!(
-                                    "block-author-transaction-inclusion-error; error={}",
-                                    error
-                                ),
-                            );
+                        let transaction = transaction_in_progress.take().unwrap();
+                        match result {
+                            Ok(Ok(())) => {}
+                            Ok(Err(error)) => {
+                                // The transaction is included in the block, but its execution
+                                // has failed. This isn't a problem from the point of view of
+                                // the block author.
+                                self.log_callback.log(
+                                    LogLevel::Debug,
+                                    format!(
+                                        "block-author-transaction-dispatch-error; hash={}; error={}",
+                                        HashDisplay(&blake2_hash(&transaction)),
+                                        error
+                                    ),
+                                );
+                            }
+                            Err(author::runtime::TransactionValidityError::Invalid(
+                                author::runtime::InvalidTransaction::ExhaustsResources,
+                            )) => {
+                                // The block is full. The transaction stays in the pool and will
+                                // be included in a later block.
+                                block_full = true;
+                            }
+                            Err(error) => {
+                                self.log_callback.log(
+                                    LogLevel::Debug,
+                                    format!(
+                                        "block-author-transaction-inclusion-error; hash={}; error={}",
+                                        HashDisplay(&blake2_hash(&transaction)),
+                                        error
+                                    ),
+                                );
+
+                                // Failing to report the transaction isn't a big deal, as it
+                                // will simply be tried again.
+                                let _ = self.authoring_transactions_requests.try_send(
+                                    AuthoringTransactionsRequest::TransactionInvalid {
+                                        parent_hash,
+                                        transaction,
+                                        error,
+                                    },
+                                );
+                            }
                         }
 
-                        // TODO: actually implement including transactions in the blocks
-                        block_authoring = resume.finish();
+                        block_authoring = author::build::BuilderAuthoring::ApplyExtrinsic(resume);
                     }
 
                     // Access to the best block storage.
@@ -1392,8 +2422,9 @@ impl SyncBackground {
         // In any case, there is not much that a node operator can do except try increase the
         // performance of their machine.
         match authoring_end.elapsed() {
+            Err(_) => {}
             Ok(now_minus_end) if now_minus_end < Duration::from_millis(500) => {}
-            _ => {
+            Ok(_) => {
                 self.log_callback.log(
                     LogLevel::Warn,
                     format!(
@@ -1419,6 +2450,19 @@ impl SyncBackground {
             all::BlockAnnounceOutcome::HeaderVerify
             | all::BlockAnnounceOutcome::StoredForLater
             | all::BlockAnnounceOutcome::Discarded => {}
+            all::BlockAnnounceOutcome::ForkBlockMismatch | all::BlockAnnounceOutcome::BadBlock => {
+                // The authored block is forbidden by the chain specification, either because
+                // it is at the height of a fork block of the chain but isn't the expected block,
+                // or because it is a bad block. It can't be imported and is thus discarded.
+                self.log_callback.log(
+                    LogLevel::Warn,
+                    format!(
+                        "block-generation-forbidden-block; hash={}",
+                        HashDisplay(&new_block_hash)
+                    ),
+                );
+                return;
+            }
             all::BlockAnnounceOutcome::TooOld { .. }
             | all::BlockAnnounceOutcome::AlreadyInChain
             | all::BlockAnnounceOutcome::NotFinalizedChain
@@ -1426,6 +2470,7 @@ impl SyncBackground {
         }
 
         debug_assert!(self.authored_block.is_none());
+        self.latest_authored_block_hash = Some(new_block_hash);
         self.authored_block = Some((
             parent_number + 1,
             new_block_hash,
@@ -1449,6 +2494,27 @@ impl SyncBackground {
                     {
                         // Source is a networking source that has already been disconnected.
                         false
+                    } else if *source_id == self.import_sync_source {
+                        // Imported blocks source.
+                        match (request_details, &self.imported_block) {
+                            (
+                                all::DesiredRequest::BlocksRequest {
+                                    first_block_hash,
+                                    first_block_height,
+                                    ..
+                                },
+                                Some(ImportedBlock {
+                                    height,
+                                    hash,
+                                    data: Some(_),
+                                    ..
+                                }),
+                            ) => {
+                                first_block_height == height
+                                    && first_block_hash.map_or(true, |h| h == *hash)
+                            }
+                            _ => false,
+                        }
                     } else if *source_id != self.block_author_sync_source {
                         // Remote source.
                         self.sync.source_num_ongoing_requests(*source_id) == 0
@@ -1517,6 +2583,35 @@ impl SyncBackground {
                     );
                 }
 
+                all::DesiredRequest::BlocksRequest { .. }
+                    if source_id == self.import_sync_source =>
+                {
+                    let (
+                        scale_encoded_header,
+                        scale_encoded_extrinsics,
+                        scale_encoded_justifications,
+                    ) = self.imported_block.as_mut().unwrap().data.take().unwrap();
+
+                    // Create a request that is immediately answered right below.
+                    let request_id = self.sync.add_request(source_id, request_info.into(), ());
+
+                    self.sync.blocks_request_response(
+                        request_id,
+                        Ok(iter::once(all::BlockRequestSuccessBlock {
+                            scale_encoded_header,
+                            scale_encoded_extrinsics,
+                            scale_encoded_justifications: scale_encoded_justifications
+                                .into_iter()
+                                .map(|(engine_id, justification)| all::Justification {
+                                    engine_id,
+                                    justification,
+                                })
+                                .collect(),
+                            user_data: NonFinalizedBlock::NotVerified,
+                        })),
+                    );
+                }
+
                 all::DesiredRequest::BlocksRequest {
                     first_block_hash,
                     first_block_height,
@@ -1600,6 +2695,20 @@ impl SyncBackground {
         match self.sync.process_one() {
             all::ProcessOne::AllSync(idle) => {
                 self.sync = idle;
+
+                // Now that everything has been processed, the block being imported and its
+                // justifications have been verified.
+                if self
+                    .imported_block
+                    .as_ref()
+                    .map_or(false, |b| b.outcome.is_some())
+                {
+                    let imported_block = self.imported_block.take().unwrap();
+                    let _ = imported_block
+                        .result_tx
+                        .send(Ok(imported_block.outcome.unwrap()));
+                }
+
                 (self, false)
             }
             all::ProcessOne::VerifyWarpSyncFragment(_)
@@ -1632,6 +2741,18 @@ impl SyncBackground {
                                     error
                                 ),
                             );
+                            if self
+                                .imported_block
+                                .as_ref()
+                                .map_or(false, |b| b.hash == hash_to_verify)
+                            {
+                                let _ = self
+                                    .imported_block
+                                    .take()
+                                    .unwrap()
+                                    .result_tx
+                                    .send(Err(ImportBlockError::HeaderVerify(error)));
+                            }
                             self.sync = sync;
                             return (self, true);
                         }
@@ -1674,7 +2795,8 @@ impl SyncBackground {
                     calculate_trie_changes: true,
                 });
 
-                // TODO: check this block against the chain spec's badBlocks
+                // Note that bad blocks don't need to be checked here, as they are rejected
+                // during the header verification.
                 loop {
                     match body_verification {
                         body_only::Verify::Finished(Err((error, parent_runtime))) => {
@@ -1692,6 +2814,18 @@ impl SyncBackground {
                                     error
                                 ),
                             );
+                            if self
+                                .imported_block
+                                .as_ref()
+                                .map_or(false, |b| b.hash == hash_to_verify)
+                            {
+                                let _ = self
+                                    .imported_block
+                                    .take()
+                                    .unwrap()
+                                    .result_tx
+                                    .send(Err(ImportBlockError::BodyVerify(error)));
+                            }
                             *parent_runtime_arc.try_lock().unwrap() = Some(parent_runtime);
                             self.sync = header_verification_success.reject_bad_block();
                             return (self, true);
@@ -1711,12 +2845,17 @@ impl SyncBackground {
                                 .with_database_detached({
                                     let storage_changes = storage_changes.clone();
                                     let scale_encoded_header = header_verification_success.scale_encoded_header().to_vec();
+                                    let body = header_verification_success
+                                        .scale_encoded_extrinsics()
+                                        .unwrap()
+                                        .map(|ext| ext.as_ref().to_vec())
+                                        .collect::<Vec<_>>();
                                     move |database| {
                                         // TODO: overhead for building the SCALE encoding of the header
                                         let result = database.insert(
                                             &scale_encoded_header,
                                             is_new_best,
-                                            iter::empty::<Vec<u8>>(), // TODO:,no /!\
+                                            body.into_iter(),
                                             storage_changes.trie_changes_iter_ordered().unwrap().filter_map(
                                                 |(_child_trie, key, change)| {
                                                     let body_only::TrieChange::InsertUpdate {
@@ -1777,6 +2916,55 @@ impl SyncBackground {
                             let scale_encoded_header =
                                 header_verification_success.scale_encoded_header().to_vec();
 
+                            // The chain specification might require the runtime found in the
+                            // storage of this block to be substituted. This needs to be checked
+                            // only if the runtime has changed or if a code substitute starts
+                            // at this block.
+                            let new_runtime = if new_runtime.is_some()
+                                || self.code_substitutes.starts_at(height)
+                            {
+                                let before_runtime_build = Instant::now();
+                                let on_chain_runtime =
+                                    new_runtime.as_ref().unwrap_or(&parent_runtime);
+                                let substitute =
+                                    self.code_substitutes.substitute(height, on_chain_runtime);
+                                runtime_build_duration += before_runtime_build.elapsed();
+                                match substitute {
+                                    Ok(Some(substitute)) => {
+                                        self.log_callback.log(
+                                            LogLevel::Info,
+                                            format!(
+                                                "code-substitute-applied; hash={}; height={}; spec_version={}",
+                                                HashDisplay(&hash_to_verify),
+                                                height,
+                                                substitute.runtime_version().decode().spec_version
+                                            ),
+                                        );
+                                        Some(substitute)
+                                    }
+                                    Ok(None) => new_runtime,
+                                    Err(error) => {
+                                        self.log_callback.log(
+                                            LogLevel::Warn,
+                                            format!(
+                                                "code-substitute-error; hash={}; height={}; error={}",
+                                                HashDisplay(&hash_to_verify),
+                                                height,
+                                                error
+                                            ),
+                                        );
+                                        new_runtime
+                                    }
+                                }
+                            } else {
+                                new_runtime
+                            };
+
+                            self.metrics.blocks_verified.inc();
+                            self.metrics
+                                .block_verification_duration
+                                .observe(when_verification_started.elapsed().as_secs_f64());
+
                             self.log_callback.log(
                                 LogLevel::Debug,
                                 format!(
@@ -1798,12 +2986,15 @@ impl SyncBackground {
                             let runtime_to_notify = new_runtime
                                 .as_ref()
                                 .map(|new_runtime| Arc::new(new_runtime.clone()));
+                            let is_locally_authored =
+                                self.latest_authored_block_hash == Some(hash_to_verify);
                             for index in (0..self.blocks_notifications.len()).rev() {
                                 let subscription = self.blocks_notifications.swap_remove(index);
                                 if subscription
                                     .try_send(Notification::Block {
                                         block: BlockNotification {
                                             is_new_best,
+                                            is_locally_authored,
                                             scale_encoded_header: scale_encoded_header.clone(),
                                             block_hash: header_verification_success.hash(),
                                             runtime_update: runtime_to_notify.clone(),
@@ -1826,6 +3017,19 @@ impl SyncBackground {
                             self.sync =
                                 header_verification_success.finish(NonFinalizedBlock::NotVerified);
 
+                            if let Some(imported_block) = &mut self.imported_block {
+                                if imported_block.hash == hash_to_verify {
+                                    imported_block.outcome = Some(ImportBlockOutcome::Imported);
+                                }
+                            }
+
+                            if let Some(voter) = &mut self.grandpa_voter {
+                                voter.block_imported(hash_to_verify, height, parent_hash);
+                                if is_new_best {
+                                    voter.set_best_block(hash_to_verify);
+                                }
+                            }
+
                             // Store the storage of the children.
                             self.sync[(height, &hash_to_verify)] = NonFinalizedBlock::Verified {
                                 runtime: if let Some(new_runtime) = new_runtime {
@@ -2063,14 +3267,44 @@ impl SyncBackground {
                             self.block_authoring = None;
                         }
 
+                        if let Some(voter) = &mut self.grandpa_voter {
+                            voter.block_finalized(
+                                new_finalized_hash,
+                                finalized_blocks_newest_to_oldest
+                                    .first()
+                                    .unwrap()
+                                    .header
+                                    .number,
+                            );
+                        }
+
                         self.finalized_runtime =
                             match &finalized_blocks_newest_to_oldest.first().unwrap().user_data {
                                 NonFinalizedBlock::Verified { runtime } => runtime.clone(),
                                 _ => unreachable!(),
                             };
+                        // GrandPa justifications are stored in the database in order to be
+                        // able to serve them to other nodes.
+                        let justifications = finalized_blocks_newest_to_oldest
+                            .iter()
+                            .flat_map(|block| {
+                                let hash = block.header.hash(self.sync.block_number_bytes());
+                                block
+                                    .justifications
+                                    .iter()
+                                    .filter(|(engine_id, _)| *engine_id == *b"FRNK")
+                                    .map(move |(_, justification)| (hash, justification.clone()))
+                            })
+                            .collect::<Vec<_>>();
+
                         // TODO: what if best block changed?
                         self.database
                             .with_database_detached(move |database| {
+                                for (block_hash, justification) in justifications {
+                                    database
+                                        .set_block_justification(&block_hash, &justification)
+                                        .unwrap();
+                                }
                                 database.set_finalized(&new_finalized_hash).unwrap();
                             })
                             .await;
@@ -2126,6 +3360,20 @@ impl SyncBackground {
                             LogLevel::Warn,
                             format!("finality-proof-verification-failure; error={}", error),
                         );
+                        // Justifications of the block being imported are verified after the
+                        // block itself.
+                        if self
+                            .imported_block
+                            .as_ref()
+                            .map_or(false, |b| b.outcome.is_some())
+                        {
+                            let _ = self
+                                .imported_block
+                                .take()
+                                .unwrap()
+                                .result_tx
+                                .send(Err(ImportBlockError::JustificationVerify(error)));
+                        }
                         self.sync = sync_out;
                         (self, true)
                     }
@@ -2134,3 +3382,134 @@ impl SyncBackground {
         }
     }
 }
+
+/// Utility. Calculates the BLAKE2 hash of the given bytes.
+fn blake2_hash(bytes: &[u8]) -> [u8; 32] {
+    <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], bytes).as_bytes()).unwrap()
+}
+
+/// Calls the `BabeApi_configuration` runtime function of the given block, and returns the
+/// duration of a Babe slot in milliseconds. Returns `None` if the call fails.
+async fn babe_slot_duration(
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    runtime: executor::host::HostVmPrototype,
+) -> Option<NonZeroU64> {
+    let mut call = executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine: runtime,
+        function_to_call: "BabeApi_configuration",
+        parameter: iter::empty::<&'static [u8]>(),
+        max_log_level: 0,
+        storage_main_trie_changes: Default::default(),
+        calculate_trie_changes: false,
+    })
+    .ok()?;
+
+    loop {
+        match call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                // The slot duration is the first field of the output.
+                let output = success.virtual_machine.value();
+                return output.as_ref().get(..8).and_then(|b| {
+                    NonZeroU64::new(u64::from_le_bytes(<[u8; 8]>::try_from(b).unwrap()))
+                });
+            }
+            executor::runtime_host::RuntimeHostVm::Finished(Err(_)) => return None,
+            executor::runtime_host::RuntimeHostVm::StorageGet(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key = trie::bytes_to_nibbles(req.key().as_ref().iter().copied())
+                    .map(u8::from)
+                    .collect::<Vec<_>>();
+                let value = database
+                    .with_database(move |db| {
+                        db.block_storage_get(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key.iter().copied(),
+                        )
+                    })
+                    .await
+                    .ok()?;
+
+                call = req.inject_value(value.as_ref().map(|(val, vers)| {
+                    (
+                        iter::once(&val[..]),
+                        executor::runtime_host::TrieEntryVersion::try_from(*vers)
+                            .expect("corrupted database"),
+                    )
+                }));
+            }
+            executor::runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+
+                let merkle_value = database
+                    .with_database(move |db| {
+                        db.block_storage_closest_descendant_merkle_value(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .ok()?;
+
+                call = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+            }
+            executor::runtime_host::RuntimeHostVm::NextKey(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key_nibbles = req
+                    .key()
+                    .map(u8::from)
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+
+                let branch_nodes = req.branch_nodes();
+                let next_key = database
+                    .with_database(move |db| {
+                        db.block_storage_next_key(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                            prefix_nibbles.iter().copied(),
+                            branch_nodes,
+                        )
+                    })
+                    .await
+                    .ok()?;
+
+                call = req.inject_key(
+                    next_key.map(|k| k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())),
+                );
+            }
+            executor::runtime_host::RuntimeHostVm::OffchainStorageSet(req) => {
+                call = req.resume();
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(req) => {
+                call = req.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(_)
+            | executor::runtime_host::RuntimeHostVm::GenerateKey(_) => return None,
+            executor::runtime_host::RuntimeHostVm::LogEmit(req) => {
+                // Logs are ignored.
+                call = req.resume();
+            }
+        }
+    }
+}
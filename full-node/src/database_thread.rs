@@ -18,6 +18,8 @@
 //! As explained in the documentation of smoldot, the database uses synchronous I/O operations.
 //! For this reason, it is undesirable to access it from an asynchronous context.
 
+use crate::metrics_service;
+
 use futures_channel::oneshot;
 use smol::{channel, lock::Mutex, stream::StreamExt as _};
 use smoldot::database::full_sqlite::SqliteFullDatabase;
@@ -28,8 +30,6 @@ pub use smoldot::database::full_sqlite::{CorruptedError, StorageAccessError};
 /// Handle to the thread were the database accesses are performed.
 ///
 /// Destroying this object stops the thread.
-///
-/// Use the `From` trait implementation to build a [`DatabaseThread`].
 pub struct DatabaseThread {
     sender: Mutex<channel::Sender<Exec>>,
 }
@@ -37,6 +37,37 @@ pub struct DatabaseThread {
 type Exec = Box<dyn FnOnce(&SqliteFullDatabase) + Send>;
 
 impl DatabaseThread {
+    /// Spawns the thread where the database accesses are performed. The given metrics are
+    /// updated after each access.
+    pub fn new(
+        db: SqliteFullDatabase,
+        metrics: metrics_service::DatabaseMetrics,
+    ) -> DatabaseThread {
+        let (sender, mut rx) = channel::bounded::<Box<dyn FnOnce(&SqliteFullDatabase) + Send>>(256);
+
+        thread::Builder::new()
+            .name("sqlite-database".into())
+            .spawn(move || {
+                // When the `DatabaseThread` is dropped, the sender will close, `rx.next()`
+                // will return `None`, and the closure here will finish, ending the thread.
+                while let Some(closure) = smol::block_on(rx.next()) {
+                    closure(&db);
+
+                    let (cache_hits, cache_misses) = db.cache_statistics();
+                    metrics.cache_hits.set(cache_hits);
+                    metrics.cache_misses.set(cache_misses);
+                    metrics
+                        .queue_length
+                        .set(u64::try_from(rx.len()).unwrap_or(u64::MAX));
+                }
+            })
+            .unwrap();
+
+        DatabaseThread {
+            sender: Mutex::new(sender),
+        }
+    }
+
     /// Sends a closure to the database thread, executes it, then returns the value that the
     /// closure returned.
     pub async fn with_database<T: Send + 'static>(
@@ -71,24 +102,3 @@ impl DatabaseThread {
             .unwrap();
     }
 }
-
-impl From<SqliteFullDatabase> for DatabaseThread {
-    fn from(db: SqliteFullDatabase) -> DatabaseThread {
-        let (sender, mut rx) = channel::bounded::<Box<dyn FnOnce(&SqliteFullDatabase) + Send>>(256);
-
-        thread::Builder::new()
-            .name("sqlite-database".into())
-            .spawn(move || {
-                // When the `DatabaseThread` is dropped, the sender will close, `rx.next()`
-                // will return `None`, and the closure here will finish, ending the thread.
-                while let Some(closure) = smol::block_on(rx.next()) {
-                    closure(&db)
-                }
-            })
-            .unwrap();
-
-        DatabaseThread {
-            sender: Mutex::new(sender),
-        }
-    }
-}
@@ -0,0 +1,301 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Minimal HTTP/1.1 server-side implementation.
+//!
+//! This module reads the head of incoming HTTP requests, then lets the caller either read the
+//! body of the request and send back a response, or take over the socket. The latter is used
+//! in order to upgrade the connection to the WebSocket protocol.
+//!
+//! Only what is necessary in order to serve the JSON-RPC and metrics endpoints is supported. In
+//! particular, the body of a request must always be indicated with a `Content-Length` header.
+
+use smol::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::TcpStream,
+};
+use std::{
+    cmp, fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Maximum size, in bytes, of the head of an HTTP request (i.e. the request line and the headers).
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Maximum number of headers in an HTTP request.
+const MAX_HEADERS: usize = 32;
+
+/// TCP connection with an HTTP client.
+pub struct Connection {
+    /// The actual socket.
+    socket: TcpStream,
+    /// Data that has been read from the socket but not processed yet.
+    read_buffer: Vec<u8>,
+}
+
+impl Connection {
+    /// Wraps around a TCP socket.
+    pub fn new(socket: TcpStream) -> Self {
+        Connection {
+            socket,
+            read_buffer: Vec::with_capacity(1024),
+        }
+    }
+
+    /// Waits for the head of the next HTTP request to have been received, and returns it.
+    ///
+    /// The head isn't consumed from the connection. Call [`Connection::read_body`] in order to
+    /// consume it alongside with the body of the request, or [`Connection::into_socket`] in
+    /// order to process it differently.
+    ///
+    /// Returns `Ok(None)` if the remote has closed the connection before sending anything.
+    pub async fn read_request_head(&mut self) -> Result<Option<RequestHead>, Error> {
+        loop {
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut request = httparse::Request::new(&mut headers);
+            match request.parse(&self.read_buffer) {
+                Ok(httparse::Status::Complete(head_len)) => {
+                    return Ok(Some(RequestHead {
+                        method: request.method.unwrap_or_default().to_owned(),
+                        path: request.path.unwrap_or_default().to_owned(),
+                        headers: request
+                            .headers
+                            .iter()
+                            .map(|h| (h.name.to_ascii_lowercase(), h.value.to_vec()))
+                            .collect(),
+                        head_len,
+                    }));
+                }
+                Ok(httparse::Status::Partial) => {}
+                Err(error) => return Err(Error::InvalidHead(error)),
+            }
+
+            if self.read_buffer.len() >= MAX_HEAD_SIZE {
+                return Err(Error::HeadTooLarge);
+            }
+
+            let mut chunk = [0; 1024];
+            let num_read = self.socket.read(&mut chunk).await.map_err(Error::Io)?;
+            if num_read == 0 {
+                if self.read_buffer.is_empty() {
+                    return Ok(None);
+                }
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.read_buffer.extend_from_slice(&chunk[..num_read]);
+        }
+    }
+
+    /// Consumes the head that was returned by [`Connection::read_request_head`], then reads the
+    /// body of the request, whose length must have been verified to be `body_len`.
+    pub async fn read_body(&mut self, head: &RequestHead, body_len: usize) -> io::Result<Vec<u8>> {
+        self.read_buffer.drain(..head.head_len);
+
+        while self.read_buffer.len() < body_len {
+            let mut chunk = [0; 4096];
+            let to_read = cmp::min(chunk.len(), body_len - self.read_buffer.len());
+            let num_read = self.socket.read(&mut chunk[..to_read]).await?;
+            if num_read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.read_buffer.extend_from_slice(&chunk[..num_read]);
+        }
+
+        let remains = self.read_buffer.split_off(body_len);
+        Ok(std::mem::replace(&mut self.read_buffer, remains))
+    }
+
+    /// Sends back an HTTP response.
+    ///
+    /// The `Content-Length` header is added automatically and must not be part of `headers`.
+    pub async fn send_response(
+        &mut self,
+        status: Status,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<()> {
+        let mut response = format!("HTTP/1.1 {status}\r\n");
+        for (name, value) in headers {
+            response.push_str(name);
+            response.push_str(": ");
+            response.push_str(value);
+            response.push_str("\r\n");
+        }
+        response.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        self.socket.write_all(&response).await?;
+        self.socket.flush().await
+    }
+
+    /// Turns the connection into a socket. The data that has been received but not consumed
+    /// yet, such as the head returned by [`Connection::read_request_head`], is read again from
+    /// the returned socket.
+    pub fn into_socket(self) -> BufferedSocket {
+        BufferedSocket {
+            socket: self.socket,
+            buffered: self.read_buffer,
+            buffered_offset: 0,
+        }
+    }
+}
+
+/// Head of an HTTP request, as returned by [`Connection::read_request_head`].
+pub struct RequestHead {
+    /// HTTP method of the request, such as `GET` or `POST`.
+    pub method: String,
+    /// Path targeted by the request, including the query string if any.
+    pub path: String,
+    /// List of headers, with their name in lowercase.
+    headers: Vec<(String, Vec<u8>)>,
+    /// Number of bytes of the head of the request.
+    head_len: usize,
+}
+
+impl RequestHead {
+    /// Returns the value of the header with the given name, if any. The name must be in
+    /// lowercase.
+    pub fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| &value[..])
+    }
+
+    /// Returns `true` if the given header contains the given token in its comma-separated list
+    /// of values, compared case-insensitively.
+    pub fn header_contains_token(&self, name: &str, token: &str) -> bool {
+        self.header(name).is_some_and(|value| {
+            value
+                .split(|c| *c == b',')
+                .any(|v| trim_ascii_whitespace(v).eq_ignore_ascii_case(token.as_bytes()))
+        })
+    }
+
+    /// Returns `true` if the client requests an upgrade to the WebSocket protocol.
+    pub fn is_websocket_upgrade(&self) -> bool {
+        self.header_contains_token("upgrade", "websocket")
+    }
+}
+
+/// Removes the ASCII whitespace characters at the start and end of the given slice.
+fn trim_ascii_whitespace(value: &[u8]) -> &[u8] {
+    let Some(start) = value.iter().position(|c| !c.is_ascii_whitespace()) else {
+        return &[];
+    };
+    let end = value
+        .iter()
+        .rposition(|c| !c.is_ascii_whitespace())
+        .unwrap();
+    &value[start..=end]
+}
+
+/// Status line of an HTTP response.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    NoContent,
+    BadRequest,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    LengthRequired,
+    PayloadTooLarge,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "200 OK",
+            Status::NoContent => "204 No Content",
+            Status::BadRequest => "400 Bad Request",
+            Status::Forbidden => "403 Forbidden",
+            Status::NotFound => "404 Not Found",
+            Status::MethodNotAllowed => "405 Method Not Allowed",
+            Status::LengthRequired => "411 Length Required",
+            Status::PayloadTooLarge => "413 Payload Too Large",
+        })
+    }
+}
+
+/// Error potentially returned by [`Connection::read_request_head`].
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Error while reading from the socket.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+    /// Failed to parse the head of the request.
+    #[display(fmt = "Invalid HTTP request: {_0}")]
+    InvalidHead(httparse::Error),
+    /// The head of the request exceeds [`MAX_HEAD_SIZE`].
+    #[display(fmt = "HTTP request head too large")]
+    HeadTooLarge,
+}
+
+/// Socket returned by [`Connection::into_socket`].
+pub struct BufferedSocket {
+    /// The actual socket.
+    socket: TcpStream,
+    /// Data read from the socket before [`Connection::into_socket`] was called.
+    buffered: Vec<u8>,
+    /// Number of bytes at the start of [`BufferedSocket::buffered`] that have already been read.
+    buffered_offset: usize,
+}
+
+impl AsyncRead for BufferedSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+
+        if this.buffered_offset < this.buffered.len() {
+            let remaining = &this.buffered[this.buffered_offset..];
+            let num_copied = cmp::min(remaining.len(), buf.len());
+            buf[..num_copied].copy_from_slice(&remaining[..num_copied]);
+            this.buffered_offset += num_copied;
+            if this.buffered_offset == this.buffered.len() {
+                this.buffered = Vec::new();
+                this.buffered_offset = 0;
+            }
+            return Poll::Ready(Ok(num_copied));
+        }
+
+        Pin::new(&mut this.socket).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BufferedSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.socket).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.socket).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.socket).poll_close(cx)
+    }
+}
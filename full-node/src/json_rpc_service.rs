@@ -15,20 +15,28 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::{consensus_service, database_thread, network_service, LogCallback, LogLevel};
+use crate::{
+    consensus_service, database_thread, http, metrics_service, network_service,
+    transactions_service, LogCallback, LogLevel,
+};
 use futures_channel::oneshot;
 use futures_util::FutureExt;
 use smol::{
     future,
     net::{TcpListener, TcpStream},
 };
-use smoldot::json_rpc::{methods, service};
+use smoldot::{
+    identity::keystore,
+    json_rpc::{methods, parse, service},
+};
 use std::{
+    collections::BTreeMap,
     future::Future,
     io, mem,
     net::SocketAddr,
     num::{NonZeroU32, NonZeroUsize},
     pin::Pin,
+    str,
     sync::{
         atomic::{AtomicU32, Ordering},
         Arc,
@@ -61,9 +69,28 @@ pub struct Config {
         network_service::ChainId,
     ),
 
-    /// Where to bind the WebSocket server. If `None`, no TCP server is started.
+    /// Metrics to update.
+    pub metrics: metrics_service::JsonRpcMetrics,
+
+    /// Where to bind the server. If `None`, no TCP server is started.
+    ///
+    /// The server accepts both WebSocket connections and plain HTTP POST requests.
     pub bind_address: Option<SocketAddr>,
 
+    /// Values of the `Origin` header of the JSON-RPC clients that are allowed to access the
+    /// server. If `None`, all origins are allowed. Clients that don't send an `Origin` header,
+    /// which are typically not browsers, are always allowed.
+    pub cors_allowed_origins: Option<Vec<String>>,
+
+    /// Maximum size, in bytes, of a JSON-RPC request, sent either as the body of an HTTP
+    /// request or as a WebSocket message.
+    pub max_request_size: usize,
+
+    /// If `false`, the JSON-RPC methods that modify the keystore, such as `author_insertKey`,
+    /// are refused to the clients connected to [`Config::bind_address`]. Requests sent through
+    /// [`JsonRpcService::send_request`] are always allowed to call them.
+    pub allow_unsafe_methods: bool,
+
     /// Maximum number of requests to process in parallel.
     pub max_parallel_requests: u32,
 
@@ -82,12 +109,24 @@ pub struct Config {
     /// Whether the chain is a live network. Found in the chain specification.
     pub chain_is_live: bool,
 
+    /// Runtime codes that must be used instead of the on-chain runtime code, indexed by the
+    /// number of the block starting from which they apply.
+    ///
+    /// See [`smoldot::chain_spec::ChainSpec::code_substitutes`].
+    pub code_substitutes: BTreeMap<u64, Vec<u8>>,
+
     /// Hash of the genesis block.
     // TODO: load from database maybe?
     pub genesis_block_hash: [u8; 32],
 
     /// Consensus service of the chain.
     pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Transactions service of the chain.
+    pub transactions_service: Arc<transactions_service::TransactionsService>,
+
+    /// Keystore of the chain, used by the JSON-RPC functions that insert or generate keys.
+    pub keystore: Arc<keystore::Keystore>,
 }
 
 /// Running JSON-RPC service.
@@ -149,27 +188,35 @@ impl JsonRpcService {
         let (virtual_client_main_task, virtual_client_io) =
             service::client_main_task(service::Config {
                 max_active_subscriptions: u32::max_value(),
+                subscriptions_supported: true,
                 max_pending_requests: NonZeroU32::new(u32::max_value()).unwrap(),
             });
 
-        spawn_client_main_task(
-            config.tasks_executor.clone(),
-            config.log_callback.clone(),
-            config.consensus_service.clone(),
-            config.database.clone(),
-            to_requests_handlers.clone(),
-            virtual_client_main_task,
-        );
-
         let runtime_caches_service = Arc::new(runtime_caches_service::RuntimeCachesService::new(
             runtime_caches_service::Config {
                 tasks_executor: config.tasks_executor.clone(),
                 log_callback: config.log_callback.clone(),
                 database: config.database.clone(),
+                block_number_bytes: config.consensus_service.block_number_bytes(),
+                code_substitutes: config.code_substitutes,
                 num_cache_entries: NonZeroUsize::new(16).unwrap(), // TODO: configurable?
             },
         ));
 
+        let metrics = Arc::new(config.metrics);
+
+        spawn_client_main_task(
+            config.tasks_executor.clone(),
+            config.log_callback.clone(),
+            config.consensus_service.clone(),
+            config.database.clone(),
+            runtime_caches_service.clone(),
+            to_requests_handlers.clone(),
+            metrics.clone(),
+            true,
+            virtual_client_main_task,
+        );
+
         for _ in 0..config.max_parallel_requests {
             requests_handler::spawn_requests_handler(requests_handler::Config {
                 tasks_executor: config.tasks_executor.clone(),
@@ -184,6 +231,8 @@ impl JsonRpcService {
                 genesis_block_hash: config.genesis_block_hash,
                 consensus_service: config.consensus_service.clone(),
                 runtime_caches_service: runtime_caches_service.clone(),
+                transactions_service: config.transactions_service.clone(),
+                keystore: config.keystore.clone(),
             });
         }
 
@@ -195,9 +244,14 @@ impl JsonRpcService {
                 log_callback: config.log_callback,
                 consensus_service: config.consensus_service.clone(),
                 database: config.database.clone(),
+                runtime_caches_service,
                 to_requests_handlers,
                 num_json_rpc_clients: Arc::new(AtomicU32::new(0)),
+                metrics,
                 max_json_rpc_clients: config.max_json_rpc_clients,
+                cors_allowed_origins: config.cors_allowed_origins.map(Arc::from),
+                max_request_size: config.max_request_size,
+                allow_unsafe_methods: config.allow_unsafe_methods,
             };
 
             (config.tasks_executor)(Box::pin(async move { background.run().await }));
@@ -276,14 +330,29 @@ struct JsonRpcBackground {
     /// Consensus service of the chain.
     consensus_service: Arc<consensus_service::ConsensusService>,
 
+    /// Runtime caches service of the JSON-RPC service.
+    runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+
     /// Channel used to send requests to the tasks that process said requests.
     to_requests_handlers: async_channel::Sender<requests_handler::Message>,
 
     /// Number of clients currently alive.
     num_json_rpc_clients: Arc<AtomicU32>,
 
+    /// See [`Config::metrics`].
+    metrics: Arc<metrics_service::JsonRpcMetrics>,
+
     /// See [`Config::max_json_rpc_clients`].
     max_json_rpc_clients: u32,
+
+    /// See [`Config::cors_allowed_origins`].
+    cors_allowed_origins: Option<Arc<[String]>>,
+
+    /// See [`Config::max_request_size`].
+    max_request_size: usize,
+
+    /// See [`Config::allow_unsafe_methods`].
+    allow_unsafe_methods: bool,
 }
 
 impl JsonRpcBackground {
@@ -319,9 +388,10 @@ impl JsonRpcBackground {
             // New incoming TCP connection.
 
             // Try to increase `num_json_rpc_clients`. Fails if the maximum is reached.
-            if self
-                .num_json_rpc_clients
-                .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |old_value| {
+            let Ok(previous_num_json_rpc_clients) = self.num_json_rpc_clients.fetch_update(
+                Ordering::SeqCst,
+                Ordering::Relaxed,
+                |old_value| {
                     if old_value < self.max_json_rpc_clients {
                         // Considering that `old_value < max`, and `max` fits in a `u32` by
                         // definition, then `old_value + 1` also always fits in a `u32`. QED.
@@ -330,9 +400,8 @@ impl JsonRpcBackground {
                     } else {
                         None
                     }
-                })
-                .is_err()
-            {
+                },
+            ) else {
                 // Reject the socket without sending back anything. Sending back a status
                 // code would require allocating resources for that socket, which we
                 // specifically don't want to do.
@@ -342,215 +411,514 @@ impl JsonRpcBackground {
                 );
                 smol::Timer::after(Duration::from_millis(50)).await;
                 continue;
-            }
+            };
+            self.metrics
+                .clients
+                .set(u64::from(previous_num_json_rpc_clients) + 1);
 
             // Spawn two tasks: one for the socket I/O, and one to process requests.
             self.log_callback.log(
                 LogLevel::Debug,
                 format!("json-rpc-incoming-connection; address={}", address),
             );
-            let (client_main_task, io) = service::client_main_task(service::Config {
-                max_active_subscriptions: 128,
-                max_pending_requests: NonZeroU32::new(64).unwrap(),
-            });
+            // The configuration of the client main task depends on the transport, which is only
+            // known after the I/O task has started reading from the socket.
+            let start_client_main_task = {
+                let tasks_executor = self.tasks_executor.clone();
+                let log_callback = self.log_callback.clone();
+                let consensus_service = self.consensus_service.clone();
+                let database = self.database.clone();
+                let runtime_caches_service = self.runtime_caches_service.clone();
+                let to_requests_handlers = self.to_requests_handlers.clone();
+                let metrics = self.metrics.clone();
+                let allow_unsafe_methods = self.allow_unsafe_methods;
+                move |config| {
+                    let (client_main_task, io) = service::client_main_task(config);
+                    spawn_client_main_task(
+                        tasks_executor,
+                        log_callback,
+                        consensus_service,
+                        database,
+                        runtime_caches_service,
+                        to_requests_handlers,
+                        metrics,
+                        allow_unsafe_methods,
+                        client_main_task,
+                    );
+                    io
+                }
+            };
             spawn_client_io_task(
                 &self.tasks_executor,
                 self.log_callback.clone(),
                 tcp_socket,
                 address,
-                io,
+                start_client_main_task,
+                self.cors_allowed_origins.clone(),
+                self.max_request_size,
                 self.num_json_rpc_clients.clone(),
-            );
-            spawn_client_main_task(
-                self.tasks_executor.clone(),
-                self.log_callback.clone(),
-                self.consensus_service.clone(),
-                self.database.clone(),
-                self.to_requests_handlers.clone(),
-                client_main_task,
+                self.metrics.clone(),
             );
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_client_io_task(
     tasks_executor: &Arc<dyn Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync>,
     log_callback: Arc<dyn LogCallback + Send + Sync>,
     tcp_socket: TcpStream,
     socket_address: SocketAddr,
-    io: service::SerializedRequestsIo,
+    start_client_main_task: impl FnOnce(service::Config) -> service::SerializedRequestsIo
+        + Send
+        + 'static,
+    cors_allowed_origins: Option<Arc<[String]>>,
+    max_request_size: usize,
     num_json_rpc_clients: Arc<AtomicU32>,
+    metrics: Arc<metrics_service::JsonRpcMetrics>,
 ) {
     let run_future = async move {
-        // Perform the WebSocket handshake.
-        let (mut ws_sender, mut ws_receiver) = {
-            let mut ws_server = soketto::handshake::Server::new(tcp_socket);
+        // Read the head of the first HTTP request, in order to determine whether the client
+        // wants to upgrade to the WebSocket protocol or sends plain HTTP requests.
+        let mut connection = http::Connection::new(tcp_socket);
+        let head = match connection.read_request_head().await {
+            Ok(Some(head)) => head,
+            Ok(None) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("json-rpc-connection-closed; address={socket_address}"),
+                );
+                return;
+            }
+            Err(error) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+                );
+                return;
+            }
+        };
+
+        let result = if head.is_websocket_upgrade() {
+            let io = start_client_main_task(service::Config {
+                max_active_subscriptions: 128,
+                subscriptions_supported: true,
+                max_pending_requests: NonZeroU32::new(64).unwrap(),
+            });
+            websocket_connection(
+                connection.into_socket(),
+                &io,
+                &log_callback,
+                socket_address,
+                cors_allowed_origins.as_deref(),
+                max_request_size,
+            )
+            .await
+        } else {
+            // Plain HTTP can't deliver notifications, and subscriptions are thus not supported.
+            // Requests are processed one at a time.
+            let io = start_client_main_task(service::Config {
+                max_active_subscriptions: 0,
+                subscriptions_supported: false,
+                max_pending_requests: NonZeroU32::new(1).unwrap(),
+            });
+            http_connection(
+                connection,
+                head,
+                &io,
+                &log_callback,
+                socket_address,
+                cors_allowed_origins.as_deref(),
+                max_request_size,
+            )
+            .await
+        };
 
-            // TODO: enabling the `deflate` extension leads to "flate stream corrupted" errors
-            //let deflate = soketto::extension::deflate::Deflate::new(soketto::Mode::Server);
-            //ws_server.add_extension(Box::new(deflate));
+        match result {
+            Ok(()) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("json-rpc-connection-closed; address={socket_address}"),
+                );
+            }
+            Err(error) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!("json-rpc-connection-error; address={socket_address}, error={error}"),
+                );
+            }
+        }
+    };
 
-            let key = match ws_server.receive_request().await {
-                Ok(req) => req.key(),
-                Err(error) => {
+    tasks_executor(Box::pin(async move {
+        run_future.await;
+        let previous_num_json_rpc_clients = num_json_rpc_clients.fetch_sub(1, Ordering::Release);
+        metrics
+            .clients
+            .set(u64::from(previous_num_json_rpc_clients) - 1);
+    }))
+}
+
+/// Returns `true` if a client whose requests contain the given `Origin` header is allowed to
+/// access the server.
+fn is_origin_allowed(cors_allowed_origins: Option<&[String]>, origin: Option<&[u8]>) -> bool {
+    match (cors_allowed_origins, origin) {
+        (None, _) | (_, None) => true,
+        (Some(allowed), Some(origin)) => allowed.iter().any(|o| o.as_bytes() == origin),
+    }
+}
+
+/// Returns `true` if the given JSON-RPC request or batch of requests expects a response, in
+/// other words if it isn't a notification or a batch only made of notifications.
+fn expects_response(request: &str) -> bool {
+    let is_notification = |item: &str| {
+        matches!(
+            parse::parse_request(item),
+            Ok(parse::Request { id_json: None, .. })
+        )
+    };
+
+    match parse::parse_batch(request) {
+        Ok(items) => items.is_empty() || !items.into_iter().all(is_notification),
+        Err(_) => !is_notification(request),
+    }
+}
+
+/// Runs a JSON-RPC client connected through the WebSocket protocol until the connection closes.
+async fn websocket_connection(
+    socket: http::BufferedSocket,
+    io: &service::SerializedRequestsIo,
+    log_callback: &Arc<dyn LogCallback + Send + Sync>,
+    socket_address: SocketAddr,
+    cors_allowed_origins: Option<&[String]>,
+    max_request_size: usize,
+) -> Result<(), String> {
+    // Perform the WebSocket handshake.
+    let (mut ws_sender, mut ws_receiver) = {
+        let mut ws_server = soketto::handshake::Server::new(socket);
+
+        // TODO: enabling the `deflate` extension leads to "flate stream corrupted" errors
+        //let deflate = soketto::extension::deflate::Deflate::new(soketto::Mode::Server);
+        //ws_server.add_extension(Box::new(deflate));
+
+        let (key, origin_allowed) = match ws_server.receive_request().await {
+            Ok(req) => (
+                req.key(),
+                is_origin_allowed(cors_allowed_origins, req.headers().origin),
+            ),
+            Err(error) => return Err(error.to_string()),
+        };
+
+        if !origin_allowed {
+            let reject = soketto::handshake::server::Response::Reject { status_code: 403 };
+            ws_server
+                .send_response(&reject)
+                .await
+                .map_err(|err| err.to_string())?;
+            return Err("Origin not allowed".to_string());
+        }
+
+        let accept = soketto::handshake::server::Response::Accept {
+            key,
+            protocol: None,
+        };
+
+        ws_server
+            .send_response(&accept)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let mut builder = ws_server.into_builder();
+        builder.set_max_message_size(max_request_size);
+        builder.finish()
+    };
+
+    // Create a future responsible for pulling responses and sending them back.
+    let sending_future = async {
+        let mut must_flush_asap = false;
+
+        loop {
+            // If `must_flush_asap`, we simply peek for the next response but without awaiting.
+            // If `!must_flush_asap`, we wait for as long as necessary.
+            let maybe_response = if must_flush_asap {
+                io.wait_next_response().now_or_never()
+            } else {
+                Some(io.wait_next_response().await)
+            };
+
+            match maybe_response {
+                None => {
+                    if let Err(err) = ws_sender.flush().await {
+                        break Err(err.to_string());
+                    }
+                    must_flush_asap = false;
+                }
+                Some(Ok(response)) => {
                     log_callback.log(
                         LogLevel::Debug,
                         format!(
-                            "json-rpc-connection-error; address={socket_address}, error={error}"
+                            "json-rpc-response; address={}; response={}",
+                            socket_address,
+                            crate::util::truncated_str(
+                                response.chars().filter(|c| !c.is_control()),
+                                128
+                            )
                         ),
                     );
-                    return;
+
+                    if let Err(err) = ws_sender.send_text_owned(response).await {
+                        break Err(err.to_string());
+                    }
+                    must_flush_asap = true;
+                }
+                Some(Err(service::WaitNextResponseError::ClientMainTaskDestroyed)) => {
+                    // The client main task never closes by itself but only as a consequence
+                    // to the I/O task closing.
+                    unreachable!()
                 }
             };
+        }
+    };
 
-            let accept = soketto::handshake::server::Response::Accept {
-                key,
-                protocol: None,
+    // Create a future responsible for pulling messages from the socket and sending them to
+    // the main task.
+    let receiving_future = async {
+        let mut message = Vec::new();
+        loop {
+            message.clear();
+
+            match ws_receiver.receive_data(&mut message).await {
+                Ok(soketto::Data::Binary(_)) => {
+                    break Err("Unexpected binary frame".to_string());
+                }
+                Ok(soketto::Data::Text(_)) => {} // Handled below.
+                Err(soketto::connection::Error::Closed) => break Ok(()),
+                Err(err) => {
+                    break Err(err.to_string());
+                }
+            }
+
+            let request = match String::from_utf8(mem::take(&mut message)) {
+                Ok(r) => r,
+                Err(error) => {
+                    break Err(format!("Non-UTF8 text frame: {error}"));
+                }
             };
 
-            match ws_server.send_response(&accept).await {
+            log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "json-rpc-request; address={}; request={}",
+                    socket_address,
+                    crate::util::truncated_str(request.chars().filter(|c| !c.is_control()), 128)
+                ),
+            );
+
+            match io.send_request(request).await {
                 Ok(()) => {}
-                Err(error) => {
-                    log_callback.log(
-                        LogLevel::Debug,
-                        format!(
-                            "json-rpc-connection-error; address={socket_address}, error={error}"
-                        ),
-                    );
-                    return;
+                Err(service::SendRequestError {
+                    cause: service::SendRequestErrorCause::ClientMainTaskDestroyed,
+                    ..
+                }) => {
+                    // The client main task never closes by itself but only as a
+                    // consequence to the I/O task closing.
+                    unreachable!()
                 }
             }
+        }
+    };
 
-            ws_server.into_builder().finish()
-        };
+    // Run these two futures until completion.
+    future::or(sending_future, receiving_future).await
+}
 
-        // Create a future responsible for pulling responses and sending them back.
-        let sending_future = async {
-            let mut must_flush_asap = false;
+/// Runs a JSON-RPC client sending plain HTTP requests until the connection closes.
+///
+/// `head` must be the head of the first request sent by the client.
+async fn http_connection(
+    mut connection: http::Connection,
+    mut head: http::RequestHead,
+    io: &service::SerializedRequestsIo,
+    log_callback: &Arc<dyn LogCallback + Send + Sync>,
+    socket_address: SocketAddr,
+    cors_allowed_origins: Option<&[String]>,
+    max_request_size: usize,
+) -> Result<(), String> {
+    loop {
+        let origin = head.header("origin").map(|o| o.to_vec());
+        if !is_origin_allowed(cors_allowed_origins, origin.as_deref()) {
+            connection
+                .send_response(http::Status::Forbidden, &[("Connection", "close")], b"")
+                .await
+                .map_err(|err| err.to_string())?;
+            return Err("Origin not allowed".to_string());
+        }
 
-            loop {
-                // If `must_flush_asap`, we simply peek for the next response but without awaiting.
-                // If `!must_flush_asap`, we wait for as long as necessary.
-                let maybe_response = if must_flush_asap {
-                    io.wait_next_response().now_or_never()
-                } else {
-                    Some(io.wait_next_response().await)
-                };
+        // Value of the `Access-Control-Allow-Origin` header of the response, if any.
+        let allow_origin = match (&origin, cors_allowed_origins) {
+            (None, _) => None,
+            (Some(_), None) => Some("*".to_owned()),
+            (Some(origin), Some(_)) => Some(String::from_utf8_lossy(origin).into_owned()),
+        };
+        let mut headers = Vec::with_capacity(6);
+        if let Some(allow_origin) = &allow_origin {
+            headers.push(("Access-Control-Allow-Origin", &allow_origin[..]));
+            headers.push(("Vary", "Origin"));
+        }
 
-                match maybe_response {
+        // If `true`, the connection is closed after the response has been sent.
+        let mut must_close = head.header_contains_token("connection", "close");
+
+        let (status, body) = match head.method.as_str() {
+            "OPTIONS" => {
+                // CORS preflight request.
+                connection
+                    .read_body(&head, 0)
+                    .await
+                    .map_err(|err| err.to_string())?;
+                headers.push(("Access-Control-Allow-Methods", "POST, OPTIONS"));
+                headers.push(("Access-Control-Allow-Headers", "Content-Type"));
+                (http::Status::NoContent, String::new())
+            }
+            "POST" => {
+                let body_len = head
+                    .header("content-length")
+                    .and_then(|len| str::from_utf8(len).ok())
+                    .and_then(|len| len.trim().parse::<usize>().ok());
+
+                match body_len {
+                    _ if head.header("transfer-encoding").is_some() => {
+                        must_close = true;
+                        (http::Status::LengthRequired, String::new())
+                    }
                     None => {
-                        if let Err(err) = ws_sender.flush().await {
-                            break Err(err.to_string());
-                        }
-                        must_flush_asap = false;
+                        must_close = true;
+                        (http::Status::LengthRequired, String::new())
                     }
-                    Some(Ok(response)) => {
-                        log_callback.log(
-                            LogLevel::Debug,
-                            format!(
-                                "json-rpc-response; address={}; response={}",
-                                socket_address,
-                                crate::util::truncated_str(
-                                    response.chars().filter(|c| !c.is_control()),
-                                    128
-                                )
-                            ),
-                        );
-
-                        if let Err(err) = ws_sender.send_text_owned(response).await {
-                            break Err(err.to_string());
-                        }
-                        must_flush_asap = true;
+                    Some(body_len) if body_len > max_request_size => {
+                        must_close = true;
+                        (http::Status::PayloadTooLarge, String::new())
                     }
-                    Some(Err(service::WaitNextResponseError::ClientMainTaskDestroyed)) => {
-                        // The client main task never closes by itself but only as a consequence
-                        // to the I/O task closing.
-                        unreachable!()
+                    Some(body_len) => {
+                        let body = connection
+                            .read_body(&head, body_len)
+                            .await
+                            .map_err(|err| err.to_string())?;
+
+                        match String::from_utf8(body) {
+                            Ok(request) => {
+                                log_callback.log(
+                                    LogLevel::Debug,
+                                    format!(
+                                        "json-rpc-request; address={}; request={}",
+                                        socket_address,
+                                        crate::util::truncated_str(
+                                            request.chars().filter(|c| !c.is_control()),
+                                            128
+                                        )
+                                    ),
+                                );
+
+                                if expects_response(&request) {
+                                    send_request_wait_response(
+                                        io,
+                                        request,
+                                        log_callback,
+                                        socket_address,
+                                    )
+                                    .await
+                                } else {
+                                    // Notifications are ignored by the server. Since no
+                                    // response would ever be generated, the request isn't even
+                                    // sent to the client main task.
+                                    (http::Status::NoContent, String::new())
+                                }
+                            }
+                            Err(_) => (http::Status::BadRequest, String::new()),
+                        }
                     }
-                };
+                }
+            }
+            _ => {
+                connection
+                    .read_body(&head, 0)
+                    .await
+                    .map_err(|err| err.to_string())?;
+                headers.push(("Allow", "POST, OPTIONS"));
+                (http::Status::MethodNotAllowed, String::new())
             }
         };
 
-        // Create a future responsible for pulling messages from the socket and sending them to
-        // the main task.
-        let receiving_future = async {
-            let mut message = Vec::new();
-            loop {
-                message.clear();
-
-                match ws_receiver.receive_data(&mut message).await {
-                    Ok(soketto::Data::Binary(_)) => {
-                        break Err("Unexpected binary frame".to_string());
-                    }
-                    Ok(soketto::Data::Text(_)) => {} // Handled below.
-                    Err(soketto::connection::Error::Closed) => break Ok(()),
-                    Err(err) => {
-                        break Err(err.to_string());
-                    }
-                }
+        if status == http::Status::Ok {
+            headers.push(("Content-Type", "application/json; charset=utf-8"));
+        }
+        if must_close {
+            headers.push(("Connection", "close"));
+        }
 
-                let request = match String::from_utf8(mem::take(&mut message)) {
-                    Ok(r) => r,
-                    Err(error) => {
-                        break Err(format!("Non-UTF8 text frame: {error}"));
-                    }
-                };
+        connection
+            .send_response(status, &headers, body.as_bytes())
+            .await
+            .map_err(|err| err.to_string())?;
 
-                log_callback.log(
-                    LogLevel::Debug,
-                    format!(
-                        "json-rpc-request; address={}; request={}",
-                        socket_address,
-                        crate::util::truncated_str(
-                            request.chars().filter(|c| !c.is_control()),
-                            128
-                        )
-                    ),
-                );
+        if must_close {
+            return Ok(());
+        }
 
-                match io.send_request(request).await {
-                    Ok(()) => {}
-                    Err(service::SendRequestError {
-                        cause: service::SendRequestErrorCause::ClientMainTaskDestroyed,
-                        ..
-                    }) => {
-                        // The client main task never closes by itself but only as a
-                        // consequence to the I/O task closing.
-                        unreachable!()
-                    }
-                }
-            }
+        head = match connection.read_request_head().await {
+            Ok(Some(head)) => head,
+            Ok(None) => return Ok(()),
+            Err(error) => return Err(error.to_string()),
         };
+    }
+}
 
-        // Run these two futures until completion.
-        match future::or(sending_future, receiving_future).await {
-            Ok(()) => {
-                log_callback.log(
-                    LogLevel::Debug,
-                    format!("json-rpc-connection-closed; address={socket_address}"),
-                );
-            }
-            Err(error) => {
-                log_callback.log(
-                    LogLevel::Debug,
-                    format!("json-rpc-connection-error; address={socket_address}, error={error}"),
-                );
-            }
+/// Sends a request to the client main task, then waits for its response.
+async fn send_request_wait_response(
+    io: &service::SerializedRequestsIo,
+    request: String,
+    log_callback: &Arc<dyn LogCallback + Send + Sync>,
+    socket_address: SocketAddr,
+) -> (http::Status, String) {
+    match io.send_request(request).await {
+        Ok(()) => {}
+        Err(service::SendRequestError {
+            cause: service::SendRequestErrorCause::ClientMainTaskDestroyed,
+            ..
+        }) => {
+            // The client main task never closes by itself but only as a consequence to the
+            // I/O task closing.
+            unreachable!()
         }
+    }
+
+    let response = match io.wait_next_response().await {
+        Ok(response) => response,
+        Err(service::WaitNextResponseError::ClientMainTaskDestroyed) => unreachable!(),
     };
 
-    tasks_executor(Box::pin(async move {
-        run_future.await;
-        num_json_rpc_clients.fetch_sub(1, Ordering::Release);
-    }))
+    log_callback.log(
+        LogLevel::Debug,
+        format!(
+            "json-rpc-response; address={}; response={}",
+            socket_address,
+            crate::util::truncated_str(response.chars().filter(|c| !c.is_control()), 128)
+        ),
+    );
+
+    (http::Status::Ok, response)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_client_main_task(
     tasks_executor: Arc<dyn Fn(Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync>,
     log_callback: Arc<dyn LogCallback + Send + Sync>,
     consensus_service: Arc<consensus_service::ConsensusService>,
     database: Arc<database_thread::DatabaseThread>,
+    runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
     to_requests_handlers: async_channel::Sender<requests_handler::Message>,
+    metrics: Arc<metrics_service::JsonRpcMetrics>,
+    allow_unsafe_methods: bool,
     mut client_main_task: service::ClientMainTask,
 ) {
     let tasks_executor2 = tasks_executor.clone();
@@ -568,8 +936,18 @@ fn spawn_client_main_task(
                     request_process,
                 } => {
                     client_main_task = task;
+                    metrics.request(request_process.request().name());
 
                     match request_process.request() {
+                        methods::MethodCall::author_insertKey { .. }
+                        | methods::MethodCall::author_rotateKeys { .. }
+                            if !allow_unsafe_methods =>
+                        {
+                            request_process.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                "This method modifies the keystore of the node and isn't allowed on this JSON-RPC server",
+                            ));
+                        }
                         methods::MethodCall::chainHead_unstable_header {
                             follow_subscription,
                             ..
@@ -588,6 +966,106 @@ fn spawn_client_main_task(
                                     .respond(methods::Response::chainHead_unstable_header(None));
                             }
                         }
+                        methods::MethodCall::chainHead_unstable_body {
+                            follow_subscription,
+                            ..
+                        } => {
+                            if let Some(follow_subscription) =
+                                chain_head_follow_subscriptions.get_mut(&*follow_subscription)
+                            {
+                                let _ = follow_subscription
+                                    .send(chain_head_subscriptions::Message::Body {
+                                        request: request_process,
+                                    })
+                                    .await;
+                                // TODO racy; doesn't handle situation where follow subscription stops
+                            } else {
+                                request_process.respond(
+                                    methods::Response::chainHead_unstable_body(
+                                        methods::ChainHeadBodyCallReturn::LimitReached {},
+                                    ),
+                                );
+                            }
+                        }
+                        methods::MethodCall::chainHead_unstable_call {
+                            follow_subscription,
+                            ..
+                        } => {
+                            if let Some(follow_subscription) =
+                                chain_head_follow_subscriptions.get_mut(&*follow_subscription)
+                            {
+                                let _ = follow_subscription
+                                    .send(chain_head_subscriptions::Message::Call {
+                                        request: request_process,
+                                    })
+                                    .await;
+                                // TODO racy; doesn't handle situation where follow subscription stops
+                            } else {
+                                request_process.respond(
+                                    methods::Response::chainHead_unstable_call(
+                                        methods::ChainHeadBodyCallReturn::LimitReached {},
+                                    ),
+                                );
+                            }
+                        }
+                        methods::MethodCall::chainHead_unstable_storage {
+                            follow_subscription,
+                            ..
+                        } => {
+                            if let Some(follow_subscription) =
+                                chain_head_follow_subscriptions.get_mut(&*follow_subscription)
+                            {
+                                let _ = follow_subscription
+                                    .send(chain_head_subscriptions::Message::Storage {
+                                        request: request_process,
+                                    })
+                                    .await;
+                                // TODO racy; doesn't handle situation where follow subscription stops
+                            } else {
+                                request_process.respond(
+                                    methods::Response::chainHead_unstable_storage(
+                                        methods::ChainHeadStorageReturn::LimitReached {},
+                                    ),
+                                );
+                            }
+                        }
+                        methods::MethodCall::chainHead_unstable_continue {
+                            follow_subscription,
+                            ..
+                        } => {
+                            if let Some(follow_subscription) =
+                                chain_head_follow_subscriptions.get_mut(&*follow_subscription)
+                            {
+                                let _ = follow_subscription
+                                    .send(chain_head_subscriptions::Message::Continue {
+                                        request: request_process,
+                                    })
+                                    .await;
+                                // TODO racy; doesn't handle situation where follow subscription stops
+                            } else {
+                                request_process
+                                    .respond(methods::Response::chainHead_unstable_continue(()));
+                            }
+                        }
+                        methods::MethodCall::chainHead_unstable_stopOperation {
+                            follow_subscription,
+                            ..
+                        } => {
+                            if let Some(follow_subscription) =
+                                chain_head_follow_subscriptions.get_mut(&*follow_subscription)
+                            {
+                                let _ = follow_subscription
+                                    .send(chain_head_subscriptions::Message::StopOperation {
+                                        request: request_process,
+                                    })
+                                    .await;
+                                // TODO racy; doesn't handle situation where follow subscription stops
+                            } else {
+                                request_process.respond(
+                                    methods::Response::chainHead_unstable_stopOperation(()),
+                                );
+                            }
+                        }
                         methods::MethodCall::chainHead_unstable_unpin {
                             follow_subscription,
                             hash_or_hashes,
@@ -642,6 +1120,7 @@ fn spawn_client_main_task(
                     subscription_start,
                 } => {
                     client_main_task = task;
+                    metrics.request(subscription_start.request().name());
 
                     match subscription_start.request() {
                         // TODO: enforce limit to number of subscriptions
@@ -657,6 +1136,7 @@ fn spawn_client_main_task(
                                         with_runtime,
                                         consensus_service: consensus_service.clone(),
                                         database: database.clone(),
+                                        runtime_caches_service: runtime_caches_service.clone(),
                                     },
                                 )
                                 .await;
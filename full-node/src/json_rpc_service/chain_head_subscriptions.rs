@@ -21,17 +21,30 @@ use futures_channel::oneshot;
 use futures_lite::FutureExt as _;
 use smol::stream::StreamExt as _;
 use smoldot::{
+    database::full_sqlite,
     executor,
     json_rpc::{methods, service},
+    trie,
 };
 use std::{
     future::Future,
+    mem,
     num::NonZeroUsize,
     pin::{self, Pin},
     sync::Arc,
 };
 
-use crate::{consensus_service, database_thread, LogCallback};
+use crate::{
+    consensus_service, database_thread, json_rpc_service::runtime_caches_service, LogCallback,
+};
+
+/// Maximum number of body, call, and storage operations that can be in progress at the same time
+/// for a single subscription. Storage operations occupy one slot per requested item.
+const MAX_OPERATION_SLOTS: u32 = 32;
+
+/// Maximum number of items that a storage operation reports before waiting for the JSON-RPC
+/// client to call `chainHead_unstable_continue`.
+const MAX_STORAGE_ITEMS_BEFORE_CONTINUE: usize = 64;
 
 pub struct Config {
     /// Function that can be used to spawn background tasks.
@@ -56,12 +69,30 @@ pub struct Config {
 
     /// Database to access blocks.
     pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Runtime caches service of the JSON-RPC service.
+    pub runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
 }
 
 pub enum Message {
     Header {
         request: service::RequestProcess,
     },
+    Body {
+        request: service::RequestProcess,
+    },
+    Call {
+        request: service::RequestProcess,
+    },
+    Storage {
+        request: service::RequestProcess,
+    },
+    Continue {
+        request: service::RequestProcess,
+    },
+    StopOperation {
+        request: service::RequestProcess,
+    },
     Unpin {
         block_hashes: Vec<[u8; 32]>,
         outcome: oneshot::Sender<Result<(), ()>>,
@@ -87,6 +118,18 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
 
         let mut foreground_receiver = pin::pin!(config.receiver);
 
+        // Body, call, and storage operations are performed by separate tasks, which report their
+        // progress through this channel.
+        let (to_main_task, from_operations) = async_channel::bounded(16);
+        let mut from_operations = pin::pin!(from_operations);
+        let mut operations_in_progress =
+            hashbrown::HashMap::<String, Operation, _>::with_capacity_and_hasher(
+                8,
+                fnv::FnvBuildHasher::default(),
+            );
+        let mut next_operation_id: u128 = 1;
+        let mut available_operation_slots = MAX_OPERATION_SLOTS;
+
         let mut pinned_blocks =
             hashbrown::HashSet::with_capacity_and_hasher(32, fnv::FnvBuildHasher::default());
         let mut current_best_block = consensus_service_subscription.finalized_block_hash;
@@ -150,6 +193,8 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
                 ConsensusSubscriptionStop,
                 Foreground(Message),
                 ForegroundClosed,
+                OperationEvent(OperationEvent),
+                SubscriptionStale,
             }
 
             let wake_up_reason = async {
@@ -167,10 +212,18 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
                     .await
                     .map_or(WakeUpReason::ForegroundClosed, WakeUpReason::Foreground)
             })
+            .or(async {
+                // The channel can't close, as a sender is kept alive in this task.
+                WakeUpReason::OperationEvent(from_operations.next().await.unwrap())
+            })
+            .or(async {
+                json_rpc_subscription.wait_until_stale().await;
+                WakeUpReason::SubscriptionStale
+            })
             .await;
 
             match wake_up_reason {
-                WakeUpReason::ForegroundClosed => return,
+                WakeUpReason::ForegroundClosed | WakeUpReason::SubscriptionStale => return,
                 WakeUpReason::Foreground(Message::Header { request }) => {
                     let methods::MethodCall::chainHead_unstable_header { hash, .. } =
                         request.request()
@@ -205,6 +258,239 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
                         }
                     }
                 }
+                WakeUpReason::Foreground(Message::Body { request }) => {
+                    let methods::MethodCall::chainHead_unstable_body { hash, .. } =
+                        request.request()
+                    else {
+                        unreachable!()
+                    };
+
+                    if !pinned_blocks.contains(&hash.0) {
+                        request.fail(service::ErrorResponse::InvalidParams);
+                        continue;
+                    }
+
+                    let Some(remaining_slots) = available_operation_slots.checked_sub(1) else {
+                        request.respond(methods::Response::chainHead_unstable_body(
+                            methods::ChainHeadBodyCallReturn::LimitReached {},
+                        ));
+                        continue;
+                    };
+                    available_operation_slots = remaining_slots;
+
+                    let operation_id = next_operation_id.to_string();
+                    next_operation_id += 1;
+                    operations_in_progress.insert(
+                        operation_id.clone(),
+                        Operation {
+                            occupied_slots: 1,
+                            continue_tx: None,
+                            waiting_for_continue: false,
+                        },
+                    );
+
+                    request.respond(methods::Response::chainHead_unstable_body(
+                        methods::ChainHeadBodyCallReturn::Started {
+                            operation_id: (&operation_id).into(),
+                        },
+                    ));
+
+                    (config.tasks_executor)(Box::pin(body_operation(
+                        config.database.clone(),
+                        hash.0,
+                        operation_id,
+                        to_main_task.clone(),
+                    )));
+                }
+                WakeUpReason::Foreground(Message::Call { request }) => {
+                    let methods::MethodCall::chainHead_unstable_call {
+                        hash,
+                        function,
+                        call_parameters,
+                        ..
+                    } = request.request()
+                    else {
+                        unreachable!()
+                    };
+
+                    // It is invalid to call this function for a "without runtime" subscription.
+                    if !config.with_runtime || !pinned_blocks.contains(&hash.0) {
+                        request.fail(service::ErrorResponse::InvalidParams);
+                        continue;
+                    }
+
+                    let Some(remaining_slots) = available_operation_slots.checked_sub(1) else {
+                        request.respond(methods::Response::chainHead_unstable_call(
+                            methods::ChainHeadBodyCallReturn::LimitReached {},
+                        ));
+                        continue;
+                    };
+                    available_operation_slots = remaining_slots;
+
+                    let operation_id = next_operation_id.to_string();
+                    next_operation_id += 1;
+                    operations_in_progress.insert(
+                        operation_id.clone(),
+                        Operation {
+                            occupied_slots: 1,
+                            continue_tx: None,
+                            waiting_for_continue: false,
+                        },
+                    );
+
+                    let function = function.into_owned();
+                    request.respond(methods::Response::chainHead_unstable_call(
+                        methods::ChainHeadBodyCallReturn::Started {
+                            operation_id: (&operation_id).into(),
+                        },
+                    ));
+
+                    (config.tasks_executor)(Box::pin(call_operation(
+                        config.database.clone(),
+                        config.runtime_caches_service.clone(),
+                        hash.0,
+                        function,
+                        call_parameters.0,
+                        operation_id,
+                        to_main_task.clone(),
+                    )));
+                }
+                WakeUpReason::Foreground(Message::Storage { request }) => {
+                    let methods::MethodCall::chainHead_unstable_storage {
+                        hash,
+                        mut items,
+                        child_trie,
+                        ..
+                    } = request.request()
+                    else {
+                        unreachable!()
+                    };
+
+                    if !pinned_blocks.contains(&hash.0) {
+                        request.fail(service::ErrorResponse::InvalidParams);
+                        continue;
+                    }
+
+                    if available_operation_slots == 0 {
+                        request.respond(methods::Response::chainHead_unstable_storage(
+                            methods::ChainHeadStorageReturn::LimitReached {},
+                        ));
+                        continue;
+                    }
+
+                    // Items that don't fit in the available operation slots are discarded.
+                    let num_kept_items = usize::try_from(available_operation_slots)
+                        .unwrap_or(usize::max_value())
+                        .min(items.len());
+                    let discarded_items = items.len() - num_kept_items;
+                    items.truncate(num_kept_items);
+                    // `num_kept_items` is inferior or equal to `available_operation_slots`.
+                    let occupied_slots = u32::try_from(num_kept_items).unwrap();
+                    available_operation_slots -= occupied_slots;
+
+                    let operation_id = next_operation_id.to_string();
+                    next_operation_id += 1;
+                    let (continue_tx, continue_rx) = async_channel::bounded(1);
+                    operations_in_progress.insert(
+                        operation_id.clone(),
+                        Operation {
+                            occupied_slots,
+                            continue_tx: Some(continue_tx),
+                            waiting_for_continue: false,
+                        },
+                    );
+
+                    request.respond(methods::Response::chainHead_unstable_storage(
+                        methods::ChainHeadStorageReturn::Started {
+                            operation_id: (&operation_id).into(),
+                            discarded_items,
+                        },
+                    ));
+
+                    (config.tasks_executor)(Box::pin(storage_operation(
+                        config.database.clone(),
+                        hash.0,
+                        items,
+                        child_trie.map(|child_trie| child_trie.0),
+                        operation_id,
+                        to_main_task.clone(),
+                        continue_rx,
+                    )));
+                }
+                WakeUpReason::Foreground(Message::Continue { request }) => {
+                    let methods::MethodCall::chainHead_unstable_continue { operation_id, .. } =
+                        request.request()
+                    else {
+                        unreachable!()
+                    };
+
+                    match operations_in_progress.get_mut(&*operation_id) {
+                        Some(Operation {
+                            continue_tx: Some(continue_tx),
+                            waiting_for_continue: waiting_for_continue @ true,
+                            ..
+                        }) => {
+                            *waiting_for_continue = false;
+                            let _ = continue_tx.try_send(());
+                            request.respond(methods::Response::chainHead_unstable_continue(()));
+                        }
+                        _ => {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                        }
+                    }
+                }
+                WakeUpReason::Foreground(Message::StopOperation { request }) => {
+                    let methods::MethodCall::chainHead_unstable_stopOperation {
+                        operation_id, ..
+                    } = request.request()
+                    else {
+                        unreachable!()
+                    };
+
+                    // Dropping the `Operation` closes the channel to the task performing the
+                    // operation, which interrupts it. Any event generated by the operation
+                    // afterwards is ignored.
+                    if let Some(operation) = operations_in_progress.remove(&*operation_id) {
+                        available_operation_slots += operation.occupied_slots;
+                    }
+
+                    request.respond(methods::Response::chainHead_unstable_stopOperation(()));
+                }
+                WakeUpReason::OperationEvent(OperationEvent {
+                    operation_id,
+                    notification,
+                    is_done,
+                }) => {
+                    let operation_is_valid = if is_done {
+                        if let Some(operation) = operations_in_progress.remove(&operation_id) {
+                            available_operation_slots += operation.occupied_slots;
+                            true
+                        } else {
+                            false
+                        }
+                    } else if let Some(operation) = operations_in_progress.get_mut(&operation_id) {
+                        if matches!(
+                            notification,
+                            methods::FollowEvent::OperationWaitingForContinue { .. }
+                        ) {
+                            operation.waiting_for_continue = true;
+                        }
+                        true
+                    } else {
+                        false
+                    };
+
+                    if operation_is_valid {
+                        json_rpc_subscription
+                            .send_notification(
+                                methods::ServerToClient::chainHead_unstable_followEvent {
+                                    subscription: (&json_rpc_subscription_id).into(),
+                                    result: notification,
+                                },
+                            )
+                            .await;
+                    }
+                }
                 WakeUpReason::Foreground(Message::Unpin {
                     block_hashes,
                     outcome,
@@ -320,6 +606,398 @@ pub async fn spawn_chain_head_subscription_task(config: Config) -> String {
     return_value
 }
 
+/// Body, call, or storage operation in progress.
+struct Operation {
+    /// Number of operation slots that this operation occupies.
+    occupied_slots: u32,
+
+    /// Sender used to resume the operation after `chainHead_unstable_continue` is called.
+    /// `None` for operations that never wait. Dropping this sender interrupts the operation.
+    continue_tx: Option<async_channel::Sender<()>>,
+
+    /// `true` if an `operationWaitingForContinue` event has been sent and
+    /// `chainHead_unstable_continue` hasn't been called yet.
+    waiting_for_continue: bool,
+}
+
+/// Event generated by an operation and destined to the JSON-RPC client.
+struct OperationEvent {
+    operation_id: String,
+    notification: methods::FollowEvent<'static>,
+    /// `true` if this is the last event of the operation.
+    is_done: bool,
+}
+
+/// Performs a `chainHead_unstable_body` operation.
+async fn body_operation(
+    database: Arc<database_thread::DatabaseThread>,
+    block_hash: [u8; 32],
+    operation_id: String,
+    to_main_task: async_channel::Sender<OperationEvent>,
+) {
+    let outcome = database
+        .with_database(move |database| {
+            database
+                .block_extrinsics(&block_hash)
+                .map(|body| body.collect::<Vec<_>>())
+        })
+        .await;
+
+    let notification = match outcome {
+        Ok(body) => methods::FollowEvent::OperationBodyDone {
+            operation_id: operation_id.clone().into(),
+            value: body.into_iter().map(methods::HexString).collect(),
+        },
+        Err(full_sqlite::BlockBodyAccessError::BodyPruned)
+        | Err(full_sqlite::BlockBodyAccessError::UnknownBlock) => {
+            methods::FollowEvent::OperationInaccessible {
+                operation_id: operation_id.clone().into(),
+            }
+        }
+        Err(full_sqlite::BlockBodyAccessError::Corrupted(err)) => {
+            methods::FollowEvent::OperationError {
+                operation_id: operation_id.clone().into(),
+                error: err.to_string().into(),
+            }
+        }
+    };
+
+    let _ = to_main_task
+        .send(OperationEvent {
+            operation_id,
+            notification,
+            is_done: true,
+        })
+        .await;
+}
+
+/// Performs a `chainHead_unstable_call` operation.
+async fn call_operation(
+    database: Arc<database_thread::DatabaseThread>,
+    runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+    block_hash: [u8; 32],
+    function_to_call: String,
+    call_parameters: Vec<u8>,
+    operation_id: String,
+    to_main_task: async_channel::Sender<OperationEvent>,
+) {
+    let outcome = match runtime_caches_service.get(block_hash).await {
+        Ok(runtime) => runtime_caches_service::runtime_call(
+            &database,
+            block_hash,
+            (*runtime).clone(),
+            &function_to_call,
+            &call_parameters,
+            None,
+        )
+        .await
+        .map_err(|err| match err {
+            runtime_caches_service::RuntimeCallError::StorageAccess(
+                database_thread::StorageAccessError::StoragePruned
+                | database_thread::StorageAccessError::UnknownBlock,
+            ) => None,
+            err => Some(err.to_string()),
+        }),
+        Err(runtime_caches_service::GetError::UnknownBlock)
+        | Err(runtime_caches_service::GetError::Pruned) => Err(None),
+        Err(err) => Err(Some(err.to_string())),
+    };
+
+    let notification = match outcome {
+        Ok(output) => methods::FollowEvent::OperationCallDone {
+            operation_id: operation_id.clone().into(),
+            output: methods::HexString(output),
+        },
+        Err(None) => methods::FollowEvent::OperationInaccessible {
+            operation_id: operation_id.clone().into(),
+        },
+        Err(Some(error)) => methods::FollowEvent::OperationError {
+            operation_id: operation_id.clone().into(),
+            error: error.into(),
+        },
+    };
+
+    let _ = to_main_task
+        .send(OperationEvent {
+            operation_id,
+            notification,
+            is_done: true,
+        })
+        .await;
+}
+
+/// Performs a `chainHead_unstable_storage` operation.
+///
+/// Items are reported in batches of at most [`MAX_STORAGE_ITEMS_BEFORE_CONTINUE`]. After each
+/// full batch, the operation waits for a message on `continue_rx`. The operation stops if
+/// `continue_rx` is closed.
+async fn storage_operation(
+    database: Arc<database_thread::DatabaseThread>,
+    block_hash: [u8; 32],
+    items: Vec<methods::ChainHeadStorageRequestItem>,
+    child_trie: Option<Vec<u8>>,
+    operation_id: String,
+    to_main_task: async_channel::Sender<OperationEvent>,
+    continue_rx: async_channel::Receiver<()>,
+) {
+    let parent_path =
+        child_trie.map(|child_trie| runtime_caches_service::child_trie_path(&child_trie));
+    let mut pending_items = Vec::with_capacity(items.len());
+
+    for item in items {
+        let key_nibbles = trie::bytes_to_nibbles(item.key.0.iter().copied())
+            .map(u8::from)
+            .collect::<Vec<_>>();
+
+        match item.ty {
+            methods::ChainHeadStorageType::Value | methods::ChainHeadStorageType::Hash => {
+                let parent_path = parent_path.clone();
+                let result = database
+                    .with_database(move |database| {
+                        database.block_storage_get(
+                            &block_hash,
+                            parent_path.iter().map(|p| p.iter().copied()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await;
+
+                match result {
+                    Ok(Some((value, _))) => {
+                        pending_items.push(storage_response_item(
+                            item.key.0,
+                            value,
+                            matches!(item.ty, methods::ChainHeadStorageType::Hash),
+                        ));
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        let _ = to_main_task
+                            .send(storage_access_error_event(operation_id, err))
+                            .await;
+                        return;
+                    }
+                }
+            }
+            methods::ChainHeadStorageType::ClosestDescendantMerkleValue => {
+                let parent_path = parent_path.clone();
+                let result = database
+                    .with_database(move |database| {
+                        database.block_storage_closest_descendant_merkle_value(
+                            &block_hash,
+                            parent_path.iter().map(|p| p.iter().copied()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await;
+
+                match result {
+                    Ok(Some(merkle_value)) => {
+                        pending_items.push(methods::ChainHeadStorageResponseItem {
+                            key: item.key,
+                            value: None,
+                            hash: None,
+                            closest_descendant_merkle_value: Some(methods::HexString(merkle_value)),
+                        });
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        let _ = to_main_task
+                            .send(storage_access_error_event(operation_id, err))
+                            .await;
+                        return;
+                    }
+                }
+            }
+            methods::ChainHeadStorageType::DescendantsValues
+            | methods::ChainHeadStorageType::DescendantsHashes => {
+                let hashes = matches!(item.ty, methods::ChainHeadStorageType::DescendantsHashes);
+
+                // The first key that is looked up is the requested key itself.
+                let mut next_key_nibbles = Some(key_nibbles.clone());
+
+                while let Some(start_key_nibbles) = next_key_nibbles.take() {
+                    if continue_rx.is_closed() {
+                        return;
+                    }
+
+                    let parent_path = parent_path.clone();
+                    let prefix_nibbles = key_nibbles.clone();
+                    let max_entries = MAX_STORAGE_ITEMS_BEFORE_CONTINUE
+                        .saturating_sub(pending_items.len())
+                        .max(1);
+                    let result = database
+                        .with_database(
+                            move |database| -> Result<_, database_thread::StorageAccessError> {
+                                let mut entries = Vec::with_capacity(max_entries);
+                                let mut key_iter = start_key_nibbles;
+
+                                // The query is performed by repeatedly asking for the next key.
+                                while entries.len() < max_entries {
+                                    let Some(key) = database.block_storage_next_key(
+                                        &block_hash,
+                                        parent_path.iter().map(|p| p.iter().copied()),
+                                        key_iter.iter().copied(),
+                                        prefix_nibbles.iter().copied(),
+                                        false,
+                                    )?
+                                    else {
+                                        return Ok((entries, None));
+                                    };
+
+                                    if let Some((value, _)) = database.block_storage_get(
+                                        &block_hash,
+                                        parent_path.iter().map(|p| p.iter().copied()),
+                                        key.iter().copied(),
+                                    )? {
+                                        entries.push((
+                                            trie::nibbles_to_bytes_truncate(
+                                                key.iter()
+                                                    .copied()
+                                                    .map(|n| trie::Nibble::try_from(n).unwrap()),
+                                            )
+                                            .collect::<Vec<_>>(),
+                                            value,
+                                        ));
+                                    }
+
+                                    // Push an extra nibble as otherwise `block_storage_next_key`
+                                    // will return the same key again.
+                                    key_iter = key;
+                                    key_iter.push(0);
+                                }
+
+                                Ok((entries, Some(key_iter)))
+                            },
+                        )
+                        .await;
+
+                    match result {
+                        Ok((entries, next)) => {
+                            pending_items.extend(
+                                entries
+                                    .into_iter()
+                                    .map(|(key, value)| storage_response_item(key, value, hashes)),
+                            );
+                            next_key_nibbles = next;
+                        }
+                        Err(err) => {
+                            let _ = to_main_task
+                                .send(storage_access_error_event(operation_id, err))
+                                .await;
+                            return;
+                        }
+                    }
+
+                    if pending_items.len() >= MAX_STORAGE_ITEMS_BEFORE_CONTINUE {
+                        let _ = to_main_task
+                            .send(OperationEvent {
+                                operation_id: operation_id.clone(),
+                                notification: methods::FollowEvent::OperationStorageItems {
+                                    operation_id: operation_id.clone().into(),
+                                    items: mem::take(&mut pending_items),
+                                },
+                                is_done: false,
+                            })
+                            .await;
+                        let _ = to_main_task
+                            .send(OperationEvent {
+                                operation_id: operation_id.clone(),
+                                notification: methods::FollowEvent::OperationWaitingForContinue {
+                                    operation_id: operation_id.clone().into(),
+                                },
+                                is_done: false,
+                            })
+                            .await;
+
+                        if continue_rx.recv().await.is_err() {
+                            // Operation has been stopped.
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if !pending_items.is_empty() {
+        let _ = to_main_task
+            .send(OperationEvent {
+                operation_id: operation_id.clone(),
+                notification: methods::FollowEvent::OperationStorageItems {
+                    operation_id: operation_id.clone().into(),
+                    items: pending_items,
+                },
+                is_done: false,
+            })
+            .await;
+    }
+
+    let _ = to_main_task
+        .send(OperationEvent {
+            operation_id: operation_id.clone(),
+            notification: methods::FollowEvent::OperationStorageDone {
+                operation_id: operation_id.into(),
+            },
+            is_done: true,
+        })
+        .await;
+}
+
+/// Builds a storage item containing either the value or the hash of the value.
+fn storage_response_item(
+    key: Vec<u8>,
+    value: Vec<u8>,
+    hash: bool,
+) -> methods::ChainHeadStorageResponseItem {
+    if hash {
+        methods::ChainHeadStorageResponseItem {
+            key: methods::HexString(key),
+            value: None,
+            hash: Some(methods::HexString(
+                blake2_rfc::blake2b::blake2b(32, &[], &value)
+                    .as_bytes()
+                    .to_vec(),
+            )),
+            closest_descendant_merkle_value: None,
+        }
+    } else {
+        methods::ChainHeadStorageResponseItem {
+            key: methods::HexString(key),
+            value: Some(methods::HexString(value)),
+            hash: None,
+            closest_descendant_merkle_value: None,
+        }
+    }
+}
+
+/// Builds the event that ends a storage operation after an error while accessing the storage.
+fn storage_access_error_event(
+    operation_id: String,
+    error: database_thread::StorageAccessError,
+) -> OperationEvent {
+    let notification = match error {
+        database_thread::StorageAccessError::StoragePruned
+        | database_thread::StorageAccessError::UnknownBlock => {
+            methods::FollowEvent::OperationInaccessible {
+                operation_id: operation_id.clone().into(),
+            }
+        }
+        database_thread::StorageAccessError::Corrupted(err) => {
+            methods::FollowEvent::OperationError {
+                operation_id: operation_id.clone().into(),
+                error: err.to_string().into(),
+            }
+        }
+    };
+
+    OperationEvent {
+        operation_id,
+        notification,
+        is_done: true,
+    }
+}
+
 fn convert_runtime_spec(runtime: &executor::CoreVersion) -> methods::MaybeRuntimeSpec {
     let runtime = runtime.decode();
     methods::MaybeRuntimeSpec::Valid {
@@ -18,21 +18,25 @@
 use futures_lite::future;
 use smol::stream::StreamExt as _;
 use smoldot::{
+    database::full_sqlite,
     executor,
-    json_rpc::{methods, parse, service},
+    identity::{keystore, seed_phrase},
+    json_rpc::{self, methods, parse, service},
+    network::codec,
     trie,
 };
 use std::{
     future::Future,
     iter,
     pin::{self, Pin},
+    str,
     sync::Arc,
 };
 
 use crate::{
     consensus_service, database_thread,
     json_rpc_service::{legacy_api_subscriptions, runtime_caches_service},
-    network_service, LogCallback, LogLevel,
+    network_service, transactions_service, LogCallback, LogLevel,
 };
 
 pub struct Config {
@@ -77,6 +81,13 @@ pub struct Config {
 
     /// Runtime caches service of the JSON-RPC service.
     pub runtime_caches_service: Arc<runtime_caches_service::RuntimeCachesService>,
+
+    /// Transactions service of the chain.
+    pub transactions_service: Arc<transactions_service::TransactionsService>,
+
+    /// Keystore of the chain. Keys inserted or generated through the JSON-RPC functions are
+    /// added to it.
+    pub keystore: Arc<keystore::Keystore>,
 }
 
 pub enum Message {
@@ -99,6 +110,206 @@ pub fn spawn_requests_handler(config: Config) {
                         }));
                     }
 
+                    methods::MethodCall::author_submitExtrinsic { transaction } => {
+                        // In Substrate, `author_submitExtrinsic` returns the hash of the
+                        // transaction. It is unclear whether it has to actually be the hash of
+                        // the transaction or if it could be any opaque value. When in doubt, we
+                        // return the hash as well.
+                        let transaction_hash = <[u8; 32]>::try_from(
+                            blake2_rfc::blake2b::blake2b(32, &[], &transaction.0).as_bytes(),
+                        )
+                        .unwrap();
+                        config
+                            .transactions_service
+                            .submit_transaction(transaction.0)
+                            .await;
+                        request.respond(methods::Response::author_submitExtrinsic(
+                            methods::HashHexString(transaction_hash),
+                        ));
+                    }
+
+                    methods::MethodCall::author_hasKey {
+                        public_key,
+                        key_type,
+                    } => {
+                        let has_key = match keystore::KeyNamespace::from_string(&key_type) {
+                            Some(namespace) => config
+                                .keystore
+                                .keys()
+                                .await
+                                .any(|(n, key)| n == namespace && key[..] == public_key.0[..]),
+                            None => false,
+                        };
+
+                        request.respond(methods::Response::author_hasKey(has_key));
+                    }
+
+                    methods::MethodCall::author_hasSessionKeys { session_keys } => {
+                        let hash = match config
+                            .database
+                            .with_database(|db| db.best_block_hash())
+                            .await
+                        {
+                            Ok(b) => b,
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                                continue;
+                            }
+                        };
+
+                        let parameter =
+                            json_rpc::session_keys::decode_session_keys_parameters(&session_keys.0)
+                                .fold(Vec::new(), |mut a, b| {
+                                    a.extend_from_slice(b.as_ref());
+                                    a
+                                });
+
+                        let output = match runtime_call(
+                            &config.runtime_caches_service,
+                            &config.database,
+                            hash,
+                            json_rpc::session_keys::DECODE_SESSION_KEYS_FUNCTION_NAME,
+                            &parameter,
+                            None,
+                        )
+                        .await
+                        {
+                            Ok((output, _)) => output,
+                            Err(error) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    -32000,
+                                    &error.to_string(),
+                                ));
+                                continue;
+                            }
+                        };
+
+                        let keys = match json_rpc::session_keys::decode_decode_session_keys_output(
+                            &output,
+                        ) {
+                            Ok(Some(keys)) => keys,
+                            Ok(None) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    -32000,
+                                    "Session keys are not encoded correctly",
+                                ));
+                                continue;
+                            }
+                            Err(error) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    -32000,
+                                    &format!("Failed to decode runtime output: {error}"),
+                                ));
+                                continue;
+                            }
+                        };
+
+                        let local_keys = config.keystore.keys().await.collect::<Vec<_>>();
+                        // Public keys are compared with their actual length, as it depends on
+                        // the key type. For example, BEEFY keys are 33 bytes ECDSA keys.
+                        let has_session_keys = keys.iter().all(|(public_key, key_type)| {
+                            local_keys.iter().any(|(namespace, local_key)| {
+                                namespace.as_string().as_bytes() == key_type
+                                    && local_key[..] == public_key[..]
+                            })
+                        });
+
+                        request.respond(methods::Response::author_hasSessionKeys(has_session_keys));
+                    }
+
+                    methods::MethodCall::author_insertKey {
+                        key_type,
+                        suri,
+                        public_key,
+                    } => {
+                        let Some(namespace) = keystore::KeyNamespace::from_string(&key_type) else {
+                            request.fail(service::ErrorResponse::InvalidParams);
+                            continue;
+                        };
+
+                        // The signature scheme of the key isn't passed by the JSON-RPC client
+                        // and is instead deduced from the public key.
+                        let result = if seed_phrase::decode_sr25519_public_key(&suri)
+                            .is_ok_and(|k| k[..] == public_key.0[..])
+                        {
+                            config
+                                .keystore
+                                .insert_sr25519_phrase(namespace, &suri, true)
+                                .await
+                        } else if seed_phrase::decode_ed25519_public_key(&suri)
+                            .is_ok_and(|k| k[..] == public_key.0[..])
+                        {
+                            config
+                                .keystore
+                                .insert_ed25519_phrase(namespace, &suri, true)
+                                .await
+                        } else {
+                            request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                "Public key doesn't match the secret URI",
+                            ));
+                            continue;
+                        };
+
+                        match result {
+                            Ok(_) => request.respond(methods::Response::author_insertKey(())),
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
+
+                    methods::MethodCall::author_rotateKeys {} => {
+                        let hash = match config
+                            .database
+                            .with_database(|db| db.best_block_hash())
+                            .await
+                        {
+                            Ok(b) => b,
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                                continue;
+                            }
+                        };
+
+                        let parameter = json_rpc::session_keys::generate_session_keys_parameters(
+                            None,
+                        )
+                        .fold(Vec::new(), |mut a, b| {
+                            a.extend_from_slice(b.as_ref());
+                            a
+                        });
+
+                        let result = runtime_call(
+                            &config.runtime_caches_service,
+                            &config.database,
+                            hash,
+                            json_rpc::session_keys::GENERATE_SESSION_KEYS_FUNCTION_NAME,
+                            &parameter,
+                            Some(&config.keystore),
+                        )
+                        .await;
+
+                        match result.as_ref().map(|(output, _)| {
+                            json_rpc::session_keys::decode_generate_session_keys_output(output)
+                        }) {
+                            Ok(Ok(session_keys)) => {
+                                request.respond(methods::Response::author_rotateKeys(
+                                    methods::HexString(session_keys.to_vec()),
+                                ))
+                            }
+                            Ok(Err(error)) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &format!("Failed to decode runtime output: {error}"),
+                            )),
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
+
                     methods::MethodCall::chainSpec_v1_chainName {} => {
                         request.respond(methods::Response::chainSpec_v1_chainName(
                             (&config.chain_name).into(),
@@ -115,6 +326,74 @@ pub fn spawn_requests_handler(config: Config) {
                         ));
                     }
 
+                    methods::MethodCall::chain_getBlock { hash } => {
+                        let hash = match hash {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
+                                Err(_) => {
+                                    request.fail(service::ErrorResponse::InternalError);
+                                    continue;
+                                }
+                            },
+                        };
+
+                        let result = config
+                            .database
+                            .with_database(
+                                move |db| -> Result<_, full_sqlite::BlockBodyAccessError> {
+                                    let Some(header) = db.block_scale_encoded_header(&hash)? else {
+                                        return Ok(None);
+                                    };
+                                    let body = match db.block_extrinsics(&hash) {
+                                        Ok(body) => body.collect::<Vec<_>>(),
+                                        Err(full_sqlite::BlockBodyAccessError::BodyPruned)
+                                        | Err(full_sqlite::BlockBodyAccessError::UnknownBlock) => {
+                                            return Ok(None)
+                                        }
+                                        Err(err) => return Err(err),
+                                    };
+                                    let justification = db.block_justification(&hash)?;
+                                    Ok(Some((header, body, justification)))
+                                },
+                            )
+                            .await;
+
+                        match result {
+                            Ok(Some((header, body, justification))) => {
+                                match methods::Header::from_scale_encoded_header(
+                                    &header,
+                                    config.consensus_service.block_number_bytes(),
+                                ) {
+                                    Ok(header) => request.respond(
+                                        methods::Response::chain_getBlock(methods::Block {
+                                            extrinsics: body
+                                                .into_iter()
+                                                .map(methods::HexString)
+                                                .collect(),
+                                            header,
+                                            justifications: justification
+                                                .map(|j| vec![(*b"FRNK", j)]),
+                                        }),
+                                    ),
+                                    Err(_) => {
+                                        request.fail(service::ErrorResponse::InternalError);
+                                    }
+                                }
+                            }
+                            Ok(None) => {
+                                // Note that the body of the block might have been pruned.
+                                request.respond_null();
+                            }
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
                     methods::MethodCall::chain_getBlockHash { height: Some(0) } => {
                         // In the case where the database was populated through a warp sync, it
                         // might not store block 0 in it. However, the hash of block 0 is
@@ -144,6 +423,18 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::chain_getFinalizedHead {} => {
+                        match config
+                            .database
+                            .with_database(|db| db.finalized_block_hash())
+                            .await
+                        {
+                            Ok(hash) => request.respond(methods::Response::chain_getFinalizedHead(
+                                methods::HashHexString(hash),
+                            )),
+                            Err(_) => request.fail(service::ErrorResponse::InternalError),
+                        }
+                    }
                     methods::MethodCall::chain_getHeader { hash } => {
                         let hash = match hash {
                             Some(h) => h.0,
@@ -187,6 +478,189 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::payment_queryInfo { extrinsic, hash } => {
+                        let hash = match hash {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
+                                Err(_) => {
+                                    request.fail(service::ErrorResponse::InternalError);
+                                    continue;
+                                }
+                            },
+                        };
+
+                        let parameter = json_rpc::payment_info::payment_info_parameters(
+                            &extrinsic.0,
+                        )
+                        .fold(Vec::new(), |mut a, b| {
+                            a.extend_from_slice(b.as_ref());
+                            a
+                        });
+
+                        let result = runtime_call(
+                            &config.runtime_caches_service,
+                            &config.database,
+                            hash,
+                            json_rpc::payment_info::PAYMENT_FEES_FUNCTION_NAME,
+                            &parameter,
+                            None,
+                        )
+                        .await;
+
+                        match result {
+                            Ok((output, runtime_version)) => {
+                                let api_version = runtime_version
+                                    .decode()
+                                    .apis
+                                    .find_version("TransactionPaymentApi");
+                                match api_version.map(|api_version| {
+                                    json_rpc::payment_info::decode_payment_info(
+                                        &output,
+                                        api_version,
+                                    )
+                                }) {
+                                    Some(Ok(info)) => {
+                                        request.respond(methods::Response::payment_queryInfo(info))
+                                    }
+                                    Some(Err(error)) => {
+                                        request.fail(service::ErrorResponse::ServerError(
+                                            -32000,
+                                            &format!("Failed to decode runtime output: {error}"),
+                                        ))
+                                    }
+                                    None => request.fail(service::ErrorResponse::ServerError(
+                                        -32000,
+                                        "Runtime doesn't support TransactionPaymentApi",
+                                    )),
+                                }
+                            }
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
+                    methods::MethodCall::state_call {
+                        name,
+                        parameters,
+                        hash,
+                    } => {
+                        let hash = match hash {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
+                                Err(_) => {
+                                    request.fail(service::ErrorResponse::InternalError);
+                                    continue;
+                                }
+                            },
+                        };
+
+                        match runtime_call(
+                            &config.runtime_caches_service,
+                            &config.database,
+                            hash,
+                            &name,
+                            &parameters.0,
+                            None,
+                        )
+                        .await
+                        {
+                            Ok((output, _)) => request
+                                .respond(methods::Response::state_call(methods::HexString(output))),
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
+                    methods::MethodCall::state_getKeys { prefix, hash } => {
+                        // Listing all the keys of a prefix is unbounded, and would for example
+                        // walk the entire state if the prefix is empty. The number of keys is
+                        // capped, and clients are expected to use `state_getKeysPaged` instead.
+                        // This is the same limit as the one of `state_getKeysPaged`.
+                        const MAX_KEYS: usize = 1000;
+
+                        let prefix_nibbles = trie::bytes_to_nibbles(prefix.0.iter().copied())
+                            .map(u8::from)
+                            .collect::<Vec<_>>();
+
+                        let result = config
+                            .database
+                            .with_database(
+                                move |db| -> Result<_, database_thread::StorageAccessError> {
+                                    let hash = match hash {
+                                        Some(h) => h.0,
+                                        None => db.best_block_hash()?,
+                                    };
+
+                                    let mut out = Vec::new();
+                                    let mut key_iter = prefix_nibbles.clone();
+
+                                    // The query is performed by repeatedly asking for the next
+                                    // key.
+                                    while let Some(next_key_nibbles) = db.block_storage_next_key(
+                                        &hash,
+                                        iter::empty::<iter::Empty<_>>(),
+                                        key_iter.iter().copied(),
+                                        prefix_nibbles.iter().copied(),
+                                        false,
+                                    )? {
+                                        if out.len() == MAX_KEYS {
+                                            return Ok(None);
+                                        }
+
+                                        out.push(methods::HexString(
+                                            trie::nibbles_to_bytes_truncate(
+                                                next_key_nibbles
+                                                    .iter()
+                                                    .copied()
+                                                    .map(|n| trie::Nibble::try_from(n).unwrap()),
+                                            )
+                                            .collect::<Vec<_>>(),
+                                        ));
+
+                                        // Push an extra nibble as otherwise `block_storage_next_key`
+                                        // will return the same key again.
+                                        key_iter = next_key_nibbles;
+                                        key_iter.push(0);
+                                    }
+
+                                    Ok(Some(out))
+                                },
+                            )
+                            .await;
+
+                        match result {
+                            Ok(Some(out)) => {
+                                request.respond(methods::Response::state_getKeys(out));
+                            }
+                            Ok(None) => {
+                                request.fail(service::ErrorResponse::ServerError(
+                                    -32000,
+                                    "Too many keys under this prefix. Use state_getKeysPaged instead.",
+                                ));
+                            }
+                            Err(database_thread::StorageAccessError::StoragePruned)
+                            | Err(database_thread::StorageAccessError::UnknownBlock) => {
+                                // Note that it is unclear how the function should behave in
+                                // that situation.
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(database_thread::StorageAccessError::Corrupted(_)) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
                     methods::MethodCall::state_getKeysPaged {
                         prefix,
                         count,
@@ -322,164 +796,78 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         };
 
-                        let mut call =
-                            match executor::runtime_host::run(executor::runtime_host::Config {
-                                virtual_machine: runtime,
-                                function_to_call: "Metadata_metadata",
-                                parameter: iter::empty::<&'static [u8]>(),
-                                max_log_level: 0,
-                                storage_main_trie_changes: Default::default(),
-                                calculate_trie_changes: false,
-                            }) {
-                                Ok(c) => c,
+                        let result = runtime_caches_service::runtime_call(
+                            &config.database,
+                            hash,
+                            runtime,
+                            "Metadata_metadata",
+                            &[],
+                            None,
+                        )
+                        .await;
+
+                        match result
+                            .as_ref()
+                            .map(|output| methods::remove_metadata_length_prefix(output))
+                        {
+                            Ok(Ok(m)) => request.respond(methods::Response::state_getMetadata(
+                                methods::HexString(m.to_vec()),
+                            )),
+                            Ok(Err(_)) | Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
+                    methods::MethodCall::state_getReadProof { keys, at } => {
+                        let hash = at;
+                        let hash = match hash {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
                                 Err(_) => {
                                     request.fail(service::ErrorResponse::InternalError);
                                     continue;
                                 }
-                            };
-
-                        loop {
-                            match call {
-                                executor::runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
-                                    match methods::remove_metadata_length_prefix(success.virtual_machine.value().as_ref()) {
-                                        Ok(m) => request.respond(methods::Response::state_getMetadata(methods::HexString(m.to_vec()))),
-                                        Err(_) => {
-                                            request.fail(service::ErrorResponse::InternalError);
-                                        }
-                                    }
-                                    break;
-                                }
-                                executor::runtime_host::RuntimeHostVm::Finished(Err(_)) => {
-                                    request.fail(service::ErrorResponse::InternalError);
-                                    break;
-                                }
-                                executor::runtime_host::RuntimeHostVm::StorageGet(req) => {
-                                    let parent_paths = req.child_trie().map(|child_trie| {
-                                        trie::bytes_to_nibbles(
-                                            b":child_storage:default:".iter().copied(),
-                                        )
-                                        .chain(trie::bytes_to_nibbles(
-                                            child_trie.as_ref().iter().copied(),
-                                        ))
-                                        .map(u8::from)
-                                        .collect::<Vec<_>>()
-                                    });
-                                    let key =
-                                        trie::bytes_to_nibbles(req.key().as_ref().iter().copied())
-                                            .map(u8::from)
-                                            .collect::<Vec<_>>();
-                                    let value = config
-                                        .database
-                                        .with_database(move |db| {
-                                            db.block_storage_get(
-                                                &hash,
-                                                parent_paths.into_iter().map(|p| p.into_iter()),
-                                                key.iter().copied(),
-                                            )
-                                        })
-                                        .await;
-                                    let Ok(value) = value else {
-                                        request.fail(service::ErrorResponse::InternalError);
-                                        break;
-                                    };
-                                    let value = value.as_ref().map(|(val, vers)| {
-                                        (
-                                            iter::once(&val[..]),
-                                            executor::runtime_host::TrieEntryVersion::try_from(*vers)
-                                                .expect("corrupted database"),
-                                        )
-                                    });
-
-                                    call = req.inject_value(value);
-                                }
-                                executor::runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(req) => {
-                                    let parent_paths = req.child_trie().map(|child_trie| {
-                                        trie::bytes_to_nibbles(
-                                            b":child_storage:default:".iter().copied(),
-                                        )
-                                        .chain(trie::bytes_to_nibbles(
-                                            child_trie.as_ref().iter().copied(),
-                                        ))
-                                        .map(u8::from)
-                                        .collect::<Vec<_>>()
-                                    });
-                                    let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
-
-                                    let merkle_value = config
-                                        .database
-                                        .with_database(move |db| {
-                                            db.block_storage_closest_descendant_merkle_value(
-                                                &hash,
-                                                parent_paths.into_iter().map(|p| p.into_iter()),
-                                                key_nibbles.iter().copied(),
-                                            )
-                                        })
-                                        .await;
-
-                                    let Ok(merkle_value) = merkle_value else {
-                                        request.fail(service::ErrorResponse::InternalError);
-                                        break;
-                                    };
+                            },
+                        };
 
-                                    call = req
-                                        .inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+                        let result = config
+                            .database
+                            .with_database(move |db| {
+                                let mut recorder = network_service::ProofRecorder::default();
+                                for key in keys {
+                                    recorder.record(
+                                        db,
+                                        &hash,
+                                        None,
+                                        trie::bytes_to_nibbles(key.0.iter().copied()).map(u8::from),
+                                    )?;
                                 }
-                                executor::runtime_host::RuntimeHostVm::NextKey(req) => {
-                                    let parent_paths = req.child_trie().map(|child_trie| {
-                                        trie::bytes_to_nibbles(
-                                            b":child_storage:default:".iter().copied(),
-                                        )
-                                        .chain(trie::bytes_to_nibbles(
-                                            child_trie.as_ref().iter().copied(),
-                                        ))
-                                        .map(u8::from)
-                                        .collect::<Vec<_>>()
-                                    });
-                                    let key_nibbles = req
-                                        .key()
-                                        .map(u8::from)
-                                        .chain(if req.or_equal() { None } else { Some(0u8) })
-                                        .collect::<Vec<_>>();
-                                    let prefix_nibbles =
-                                        req.prefix().map(u8::from).collect::<Vec<_>>();
-
-                                    let branch_nodes = req.branch_nodes();
-                                    let next_key = config
-                                        .database
-                                        .with_database(move |db| {
-                                            db.block_storage_next_key(
-                                                &hash,
-                                                parent_paths.into_iter().map(|p| p.into_iter()),
-                                                key_nibbles.iter().copied(),
-                                                prefix_nibbles.iter().copied(),
-                                                branch_nodes,
-                                            )
-                                        })
-                                        .await;
-
-                                    let Ok(next_key) = next_key else {
-                                        request.fail(service::ErrorResponse::InternalError);
-                                        break;
-                                    };
+                                Ok::<_, database_thread::StorageAccessError>(
+                                    recorder.build_entries(),
+                                )
+                            })
+                            .await;
 
-                                    call = req.inject_key(next_key.map(|k| {
-                                        k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())
-                                    }));
-                                }
-                                executor::runtime_host::RuntimeHostVm::OffchainStorageSet(req) => {
-                                    call = req.resume();
-                                }
-                                executor::runtime_host::RuntimeHostVm::SignatureVerification(req) => {
-                                    call = req.verify_and_resume();
-                                }
-                                executor::runtime_host::RuntimeHostVm::Offchain(_) => {
-                                    request.fail(service::ErrorResponse::InternalError);
-                                    break;
-                                }
-                                executor::runtime_host::RuntimeHostVm::LogEmit(req) => {
-                                    // Logs are ignored.
-                                    call = req.resume();
-                                }
+                        match result {
+                            Ok(proof) => {
+                                request.respond(methods::Response::state_getReadProof(
+                                    methods::ReadProof {
+                                        at: methods::HashHexString(hash),
+                                        proof: proof.into_iter().map(methods::HexString).collect(),
+                                    },
+                                ));
+                            }
+                            Err(database_thread::StorageAccessError::StoragePruned)
+                            | Err(database_thread::StorageAccessError::UnknownBlock) => {
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(database_thread::StorageAccessError::Corrupted(_)) => {
+                                request.fail(service::ErrorResponse::InternalError);
                             }
                         }
                     }
@@ -517,6 +905,49 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::state_getStorage { key, hash } => {
+                        let hash = match hash {
+                            Some(h) => h.0,
+                            None => match config
+                                .database
+                                .with_database(|db| db.best_block_hash())
+                                .await
+                            {
+                                Ok(b) => b,
+                                Err(_) => {
+                                    request.fail(service::ErrorResponse::InternalError);
+                                    continue;
+                                }
+                            },
+                        };
+
+                        let result = config
+                            .database
+                            .with_database(move |db| {
+                                db.block_storage_get(
+                                    &hash,
+                                    iter::empty::<iter::Empty<_>>(),
+                                    trie::bytes_to_nibbles(key.0.iter().copied()).map(u8::from),
+                                )
+                            })
+                            .await;
+
+                        match result {
+                            Ok(Some((value, _))) => request.respond(
+                                methods::Response::state_getStorage(methods::HexString(value)),
+                            ),
+                            Ok(None) => request.respond_null(),
+                            Err(database_thread::StorageAccessError::StoragePruned)
+                            | Err(database_thread::StorageAccessError::UnknownBlock) => {
+                                // Note that it is unclear how the function should behave in
+                                // that situation.
+                                request.fail(service::ErrorResponse::InvalidParams);
+                            }
+                            Err(database_thread::StorageAccessError::Corrupted(_)) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                            }
+                        }
+                    }
                     methods::MethodCall::state_queryStorageAt { keys, at } => {
                         // TODO: add a limit to the number of keys?
 
@@ -600,6 +1031,56 @@ pub fn spawn_requests_handler(config: Config) {
                             }
                         }
                     }
+                    methods::MethodCall::system_accountNextIndex { account } => {
+                        let hash = match config
+                            .database
+                            .with_database(|db| db.best_block_hash())
+                            .await
+                        {
+                            Ok(b) => b,
+                            Err(_) => {
+                                request.fail(service::ErrorResponse::InternalError);
+                                continue;
+                            }
+                        };
+
+                        let result = runtime_call(
+                            &config.runtime_caches_service,
+                            &config.database,
+                            hash,
+                            "AccountNonceApi_account_nonce",
+                            &account.0,
+                            None,
+                        )
+                        .await;
+
+                        // The type of the nonce depends on the runtime. We accept both `u32` and
+                        // `u64`.
+                        match result {
+                            Ok((output, _)) => match <[u8; 4]>::try_from(&output[..]) {
+                                Ok(nonce) => {
+                                    request.respond(methods::Response::system_accountNextIndex(
+                                        u64::from(u32::from_le_bytes(nonce)),
+                                    ))
+                                }
+                                Err(_) => match <[u8; 8]>::try_from(&output[..]) {
+                                    Ok(nonce) => {
+                                        request.respond(methods::Response::system_accountNextIndex(
+                                            u64::from_le_bytes(nonce),
+                                        ))
+                                    }
+                                    Err(_) => request.fail(service::ErrorResponse::ServerError(
+                                        -32000,
+                                        "Failed to decode runtime output",
+                                    )),
+                                },
+                            },
+                            Err(error) => request.fail(service::ErrorResponse::ServerError(
+                                -32000,
+                                &error.to_string(),
+                            )),
+                        }
+                    }
                     methods::MethodCall::system_chain {} => {
                         request
                             .respond(methods::Response::system_chain((&config.chain_name).into()));
@@ -631,6 +1112,28 @@ pub fn spawn_requests_handler(config: Config) {
                             env!("CARGO_PKG_NAME").into(),
                         ));
                     }
+                    methods::MethodCall::system_peers {} => {
+                        let peers = config.consensus_service.syncing_peers().await;
+                        request.respond(methods::Response::system_peers(
+                            peers
+                                .into_iter()
+                                .map(|(peer_id, role, best_number, best_hash)| {
+                                    methods::SystemPeer {
+                                        peer_id: peer_id.to_string(),
+                                        roles: match role {
+                                            codec::Role::Authority => {
+                                                methods::SystemPeerRole::Authority
+                                            }
+                                            codec::Role::Full => methods::SystemPeerRole::Full,
+                                            codec::Role::Light => methods::SystemPeerRole::Light,
+                                        },
+                                        best_hash: methods::HashHexString(best_hash),
+                                        best_number,
+                                    }
+                                })
+                                .collect(),
+                        ));
+                    }
                     methods::MethodCall::system_properties {} => {
                         request.respond(methods::Response::system_properties(
                             serde_json::from_str(&config.chain_properties_json).unwrap(),
@@ -648,6 +1151,240 @@ pub fn spawn_requests_handler(config: Config) {
                     )),
                 },
                 Some(Message::SubscriptionStart(request)) => match request.request() {
+                    methods::MethodCall::author_submitAndWatchExtrinsic { transaction }
+                    | methods::MethodCall::transaction_unstable_submitAndWatch { transaction } => {
+                        let is_legacy = matches!(
+                            request.request(),
+                            methods::MethodCall::author_submitAndWatchExtrinsic { .. }
+                        );
+
+                        let transaction_updates = config
+                            .transactions_service
+                            .submit_and_watch_transaction(transaction.0, 16)
+                            .await;
+
+                        (config.tasks_executor)(Box::pin(async move {
+                            let mut transaction_updates = pin::pin!(transaction_updates);
+                            let mut subscription = request.accept();
+                            let subscription_id = subscription.subscription_id().to_owned();
+
+                            let mut included_block = None;
+                            let mut num_broadcasted_peers = 0;
+
+                            loop {
+                                let status_update = match future::or(
+                                    async { Some(transaction_updates.next().await) },
+                                    async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    },
+                                )
+                                .await
+                                {
+                                    Some(Some(status)) => status,
+                                    Some(None) => {
+                                        // Channel from the transactions service has been closed.
+                                        // There is nothing more that can be done except wait for
+                                        // the client to unsubscribe.
+                                        subscription.wait_until_stale().await;
+                                        break;
+                                    }
+                                    None => break,
+                                };
+
+                                let notification = match (status_update, is_legacy) {
+                                    (transactions_service::TransactionStatus::Broadcast(peers), true) => {
+                                        methods::ServerToClient::author_extrinsicUpdate {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionStatus::Broadcast(
+                                                peers.into_iter().map(|peer| peer.to_base58()).collect(),
+                                            ),
+                                        }
+                                    }
+                                    (transactions_service::TransactionStatus::Broadcast(peers), false) => {
+                                        num_broadcasted_peers += peers.len();
+                                        methods::ServerToClient::transaction_unstable_watchEvent {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionWatchEvent::Broadcasted {
+                                                num_peers: u32::try_from(num_broadcasted_peers)
+                                                    .unwrap_or(u32::max_value()),
+                                            },
+                                        }
+                                    }
+
+                                    (transactions_service::TransactionStatus::Validated, true) => {
+                                        methods::ServerToClient::author_extrinsicUpdate {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionStatus::Ready,
+                                        }
+                                    }
+                                    (transactions_service::TransactionStatus::Validated, false) => {
+                                        methods::ServerToClient::transaction_unstable_watchEvent {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionWatchEvent::Validated {},
+                                        }
+                                    }
+
+                                    (
+                                        transactions_service::TransactionStatus::IncludedBlockUpdate {
+                                            block_hash: Some((block_hash, _)),
+                                        },
+                                        true,
+                                    ) => {
+                                        included_block = Some(block_hash);
+                                        methods::ServerToClient::author_extrinsicUpdate {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionStatus::InBlock(
+                                                methods::HashHexString(block_hash),
+                                            ),
+                                        }
+                                    }
+                                    (
+                                        transactions_service::TransactionStatus::IncludedBlockUpdate {
+                                            block_hash: None,
+                                        },
+                                        true,
+                                    ) => {
+                                        let Some(block_hash) = included_block.take() else {
+                                            continue;
+                                        };
+                                        methods::ServerToClient::author_extrinsicUpdate {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionStatus::Retracted(
+                                                methods::HashHexString(block_hash),
+                                            ),
+                                        }
+                                    }
+                                    (
+                                        transactions_service::TransactionStatus::IncludedBlockUpdate {
+                                            block_hash,
+                                        },
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::BestChainBlockIncluded {
+                                            block: block_hash.map(|(hash, index)| {
+                                                methods::TransactionWatchEventBlock {
+                                                    hash: methods::HashHexString(hash),
+                                                    index,
+                                                }
+                                            }),
+                                        },
+                                    },
+
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::Finalized {
+                                                block_hash,
+                                                ..
+                                            },
+                                        ),
+                                        true,
+                                    ) => methods::ServerToClient::author_extrinsicUpdate {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionStatus::Finalized(
+                                            methods::HashHexString(block_hash),
+                                        ),
+                                    },
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::Invalid(_)
+                                            | transactions_service::DropReason::InvalidInBlock(_),
+                                        ),
+                                        true,
+                                    ) => methods::ServerToClient::author_extrinsicUpdate {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionStatus::Invalid,
+                                    },
+                                    (transactions_service::TransactionStatus::Dropped(_), true) => {
+                                        methods::ServerToClient::author_extrinsicUpdate {
+                                            subscription: (&subscription_id).into(),
+                                            result: methods::TransactionStatus::Dropped,
+                                        }
+                                    }
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::Finalized {
+                                                block_hash,
+                                                index,
+                                            },
+                                        ),
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Finalized {
+                                            block: methods::TransactionWatchEventBlock {
+                                                hash: methods::HashHexString(block_hash),
+                                                index,
+                                            },
+                                        },
+                                    },
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::GapInChain,
+                                        ),
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Dropped {
+                                            error: "gap in chain of blocks".into(),
+                                            broadcasted: num_broadcasted_peers != 0,
+                                        },
+                                    },
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::MaxPendingTransactionsReached,
+                                        ),
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Dropped {
+                                            error: "transactions pool full".into(),
+                                            broadcasted: num_broadcasted_peers != 0,
+                                        },
+                                    },
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::Invalid(error),
+                                        ),
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Invalid {
+                                            error: error.to_string().into(),
+                                        },
+                                    },
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::InvalidInBlock(
+                                                error,
+                                            ),
+                                        ),
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Invalid {
+                                            error: error.to_string().into(),
+                                        },
+                                    },
+                                    (
+                                        transactions_service::TransactionStatus::Dropped(
+                                            transactions_service::DropReason::ValidateError(error),
+                                        ),
+                                        false,
+                                    ) => methods::ServerToClient::transaction_unstable_watchEvent {
+                                        subscription: (&subscription_id).into(),
+                                        result: methods::TransactionWatchEvent::Error {
+                                            error: error.to_string().into(),
+                                        },
+                                    },
+                                };
+
+                                subscription.send_notification(notification).await;
+                            }
+                        }));
+                    }
+
                     methods::MethodCall::chain_subscribeAllHeads {} => {
                         let block_number_bytes = config.consensus_service.block_number_bytes();
                         let mut blocks_to_report = legacy_api_subscriptions::SubscribeAllHeads::new(
@@ -659,8 +1396,20 @@ pub fn spawn_requests_handler(config: Config) {
                             let subscription_id = subscription.subscription_id().to_owned();
 
                             loop {
-                                let scale_encoded_header =
-                                    blocks_to_report.next_scale_encoded_header().await;
+                                let Some(scale_encoded_header) = future::or(
+                                    async {
+                                        Some(blocks_to_report.next_scale_encoded_header().await)
+                                    },
+                                    async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    },
+                                )
+                                .await
+                                else {
+                                    // JSON-RPC client has unsubscribed.
+                                    break;
+                                };
 
                                 let json_rpc_header =
                                     match methods::Header::from_scale_encoded_header(
@@ -696,8 +1445,20 @@ pub fn spawn_requests_handler(config: Config) {
                             let subscription_id = subscription.subscription_id().to_owned();
 
                             loop {
-                                let scale_encoded_header =
-                                    blocks_to_report.next_scale_encoded_header().await;
+                                let Some(scale_encoded_header) = future::or(
+                                    async {
+                                        Some(blocks_to_report.next_scale_encoded_header().await)
+                                    },
+                                    async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    },
+                                )
+                                .await
+                                else {
+                                    // JSON-RPC client has unsubscribed.
+                                    break;
+                                };
 
                                 let json_rpc_header =
                                     match methods::Header::from_scale_encoded_header(
@@ -734,8 +1495,20 @@ pub fn spawn_requests_handler(config: Config) {
                             let subscription_id = subscription.subscription_id().to_owned();
 
                             loop {
-                                let scale_encoded_header =
-                                    blocks_to_report.next_scale_encoded_header().await;
+                                let Some(scale_encoded_header) = future::or(
+                                    async {
+                                        Some(blocks_to_report.next_scale_encoded_header().await)
+                                    },
+                                    async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    },
+                                )
+                                .await
+                                else {
+                                    // JSON-RPC client has unsubscribed.
+                                    break;
+                                };
 
                                 let json_rpc_header =
                                     match methods::Header::from_scale_encoded_header(
@@ -770,8 +1543,22 @@ pub fn spawn_requests_handler(config: Config) {
                             let subscription_id = subscription.subscription_id().to_owned();
 
                             loop {
-                                let runtime_version =
-                                    runtime_versions_to_report.next_runtime_version().await;
+                                let Some(runtime_version) = future::or(
+                                    async {
+                                        Some(
+                                            runtime_versions_to_report.next_runtime_version().await,
+                                        )
+                                    },
+                                    async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    },
+                                )
+                                .await
+                                else {
+                                    // JSON-RPC client has unsubscribed.
+                                    break;
+                                };
 
                                 subscription
                                     .send_notification(
@@ -798,8 +1585,20 @@ pub fn spawn_requests_handler(config: Config) {
                             let subscription_id = subscription.subscription_id().to_owned();
 
                             loop {
-                                let (block_hash, storage_changes) =
-                                    notifications_to_report.next_storage_update().await;
+                                let Some((block_hash, storage_changes)) = future::or(
+                                    async {
+                                        Some(notifications_to_report.next_storage_update().await)
+                                    },
+                                    async {
+                                        subscription.wait_until_stale().await;
+                                        None
+                                    },
+                                )
+                                .await
+                                else {
+                                    // JSON-RPC client has unsubscribed.
+                                    break;
+                                };
 
                                 subscription
                                     .send_notification(methods::ServerToClient::state_storage {
@@ -832,6 +1631,50 @@ pub fn spawn_requests_handler(config: Config) {
     }));
 }
 
+/// Performs a runtime call against the storage of the given block.
+///
+/// See [`runtime_caches_service::runtime_call`] for the meaning of `keystore`.
+///
+/// Returns the output of the call and the version of the runtime that was used.
+async fn runtime_call(
+    runtime_caches_service: &runtime_caches_service::RuntimeCachesService,
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    function_to_call: &str,
+    parameter: &[u8],
+    keystore: Option<&keystore::Keystore>,
+) -> Result<(Vec<u8>, executor::CoreVersion), RuntimeCallError> {
+    let runtime = runtime_caches_service
+        .get(block_hash)
+        .await
+        .map_err(RuntimeCallError::Runtime)?;
+    let runtime_version = runtime.runtime_version().clone();
+
+    let output = runtime_caches_service::runtime_call(
+        database,
+        block_hash,
+        (*runtime).clone(),
+        function_to_call,
+        parameter,
+        keystore,
+    )
+    .await
+    .map_err(RuntimeCallError::Call)?;
+
+    Ok((output, runtime_version))
+}
+
+/// Error potentially returned by [`runtime_call`].
+#[derive(Debug, derive_more::Display)]
+enum RuntimeCallError {
+    /// Failed to obtain the runtime of the block.
+    #[display(fmt = "Failed to obtain the runtime of the block: {_0}")]
+    Runtime(runtime_caches_service::GetError),
+    /// Error during the runtime call.
+    #[display(fmt = "{_0}")]
+    Call(runtime_caches_service::RuntimeCallError),
+}
+
 fn convert_runtime_version(runtime_spec: &executor::CoreVersion) -> methods::RuntimeVersion {
     let runtime_spec = runtime_spec.decode();
     methods::RuntimeVersion {
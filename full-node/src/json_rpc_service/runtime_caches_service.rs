@@ -15,16 +15,18 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::{database_thread, LogCallback};
+use crate::{database_thread, util, LogCallback, LogLevel};
 
 use futures_channel::oneshot;
 use futures_lite::{Future, StreamExt as _};
 use smol::lock::Mutex;
-use smoldot::{executor, trie};
+use smoldot::{executor, header, identity::keystore, informant::HashDisplay, trie};
 use std::{
+    collections::BTreeMap,
     iter,
     num::NonZeroUsize,
     pin::{self, Pin},
+    str,
     sync::Arc,
 };
 
@@ -39,6 +41,15 @@ pub struct Config {
     /// Database to access blocks.
     pub database: Arc<database_thread::DatabaseThread>,
 
+    /// Number of bytes of the block number in the headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Runtime codes that must be used instead of the on-chain runtime code, indexed by the
+    /// number of the block starting from which they apply.
+    ///
+    /// See [`smoldot::chain_spec::ChainSpec::code_substitutes`].
+    pub code_substitutes: BTreeMap<u64, Vec<u8>>,
+
     /// Number of entries in the cache of runtimes.
     pub num_cache_entries: NonZeroUsize,
 }
@@ -65,6 +76,7 @@ impl RuntimeCachesService {
             let mut from_foreground = pin::pin!(from_foreground);
             let mut cache =
                 lru::LruCache::<[u8; 32], Result<_, GetError>>::new(config.num_cache_entries);
+            let mut code_substitutes = util::CodeSubstitutes::from(config.code_substitutes);
 
             loop {
                 match from_foreground.next().await {
@@ -78,7 +90,8 @@ impl RuntimeCachesService {
                             continue;
                         }
 
-                        let (code, heap_pages) = config
+                        let block_number_bytes = config.block_number_bytes;
+                        let (code, heap_pages, block_number) = config
                             .database
                             .with_database(move |database| {
                                 let code = database.block_storage_get(
@@ -92,7 +105,16 @@ impl RuntimeCachesService {
                                     trie::bytes_to_nibbles(b":heappages".iter().copied())
                                         .map(u8::from),
                                 );
-                                (code, heap_pages)
+                                let block_number = database
+                                    .block_scale_encoded_header(&block_hash)
+                                    .ok()
+                                    .flatten()
+                                    .and_then(|header| {
+                                        header::decode(&header, block_number_bytes)
+                                            .ok()
+                                            .map(|header| header.number)
+                                    });
+                                (code, heap_pages, block_number)
                             })
                             .await;
 
@@ -137,6 +159,30 @@ impl RuntimeCachesService {
                             }
                         };
 
+                        // The chain specification might require the runtime found in the storage
+                        // of this block to be substituted.
+                        let runtime = match (runtime, block_number) {
+                            (Ok(on_chain_runtime), Some(block_number)) => {
+                                match code_substitutes.substitute(block_number, &on_chain_runtime) {
+                                    Ok(Some(substitute)) => Ok(substitute),
+                                    Ok(None) => Ok(on_chain_runtime),
+                                    Err(error) => {
+                                        config.log_callback.log(
+                                            LogLevel::Warn,
+                                            format!(
+                                                "code-substitute-error; hash={}; height={}; error={}",
+                                                HashDisplay(&block_hash),
+                                                block_number,
+                                                error
+                                            ),
+                                        );
+                                        Ok(on_chain_runtime)
+                                    }
+                                }
+                            }
+                            (runtime, _) => runtime,
+                        };
+
                         let runtime = runtime.map(Arc::new);
                         cache.put(block_hash, runtime.clone());
                         let _ = result_tx.send(runtime);
@@ -189,3 +235,198 @@ pub enum GetError {
     /// Impossible to compile the runtime.
     InvalidRuntime(executor::host::NewErr),
 }
+
+/// Performs a runtime call against the storage of the given block, found in the database.
+///
+/// If `keystore` is `Some`, the keys that the runtime generates are stored in it. If `None`,
+/// the runtime isn't allowed to generate keys.
+///
+/// Returns the output of the runtime call.
+pub async fn runtime_call(
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    runtime: executor::host::HostVmPrototype,
+    function_to_call: &str,
+    parameter: &[u8],
+    keystore: Option<&keystore::Keystore>,
+) -> Result<Vec<u8>, RuntimeCallError> {
+    let mut call = executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine: runtime,
+        function_to_call,
+        parameter: iter::once(parameter),
+        max_log_level: 0,
+        storage_main_trie_changes: Default::default(),
+        calculate_trie_changes: false,
+    })
+    .map_err(|(err, _)| RuntimeCallError::Start(err))?;
+
+    loop {
+        match call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                return Ok(success.virtual_machine.value().as_ref().to_vec());
+            }
+            executor::runtime_host::RuntimeHostVm::Finished(Err(err)) => {
+                return Err(RuntimeCallError::Execution(err.detail));
+            }
+            executor::runtime_host::RuntimeHostVm::StorageGet(req) => {
+                let parent_paths = req
+                    .child_trie()
+                    .map(|child_trie| child_trie_path(child_trie.as_ref()));
+                let key = trie::bytes_to_nibbles(req.key().as_ref().iter().copied())
+                    .map(u8::from)
+                    .collect::<Vec<_>>();
+                let value = database
+                    .with_database(move |db| {
+                        db.block_storage_get(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(RuntimeCallError::StorageAccess)?;
+                let value = value.as_ref().map(|(val, vers)| {
+                    (
+                        iter::once(&val[..]),
+                        executor::runtime_host::TrieEntryVersion::try_from(*vers)
+                            .expect("corrupted database"),
+                    )
+                });
+
+                call = req.inject_value(value);
+            }
+            executor::runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(req) => {
+                let parent_paths = req
+                    .child_trie()
+                    .map(|child_trie| child_trie_path(child_trie.as_ref()));
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+
+                let merkle_value = database
+                    .with_database(move |db| {
+                        db.block_storage_closest_descendant_merkle_value(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(RuntimeCallError::StorageAccess)?;
+
+                call = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+            }
+            executor::runtime_host::RuntimeHostVm::NextKey(req) => {
+                let parent_paths = req
+                    .child_trie()
+                    .map(|child_trie| child_trie_path(child_trie.as_ref()));
+                let key_nibbles = req
+                    .key()
+                    .map(u8::from)
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+
+                let branch_nodes = req.branch_nodes();
+                let next_key = database
+                    .with_database(move |db| {
+                        db.block_storage_next_key(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                            prefix_nibbles.iter().copied(),
+                            branch_nodes,
+                        )
+                    })
+                    .await
+                    .map_err(RuntimeCallError::StorageAccess)?;
+
+                call = req.inject_key(
+                    next_key.map(|k| k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())),
+                );
+            }
+            executor::runtime_host::RuntimeHostVm::OffchainStorageSet(req) => {
+                call = req.resume();
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(req) => {
+                call = req.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(_) => {
+                return Err(RuntimeCallError::ForbiddenHostFunction);
+            }
+            executor::runtime_host::RuntimeHostVm::GenerateKey(req) => {
+                let Some(keystore) = keystore else {
+                    return Err(RuntimeCallError::ForbiddenHostFunction);
+                };
+
+                let namespace = str::from_utf8(req.key_type_id())
+                    .ok()
+                    .and_then(keystore::KeyNamespace::from_string)
+                    .ok_or_else(|| {
+                        RuntimeCallError::UnsupportedKeyType(
+                            String::from_utf8_lossy(req.key_type_id()).into_owned(),
+                        )
+                    })?;
+                let seed = req
+                    .seed()
+                    .map(|seed| str::from_utf8(seed).map_err(|_| RuntimeCallError::InvalidKeySeed))
+                    .transpose()?;
+
+                let public_key = match (req.algorithm(), seed) {
+                    (executor::runtime_host::GenerateKeyAlgorithm::Ed25519, None) => keystore
+                        .generate_ed25519(namespace, true)
+                        .await
+                        .map_err(keystore::InsertPhraseError::Io),
+                    (executor::runtime_host::GenerateKeyAlgorithm::Sr25519, None) => keystore
+                        .generate_sr25519(namespace, true)
+                        .await
+                        .map_err(keystore::InsertPhraseError::Io),
+                    (executor::runtime_host::GenerateKeyAlgorithm::Ed25519, Some(seed)) => {
+                        keystore.insert_ed25519_phrase(namespace, seed, true).await
+                    }
+                    (executor::runtime_host::GenerateKeyAlgorithm::Sr25519, Some(seed)) => {
+                        keystore.insert_sr25519_phrase(namespace, seed, true).await
+                    }
+                }
+                .map_err(RuntimeCallError::KeyGeneration)?;
+
+                call = req.inject_public_key(public_key);
+            }
+            executor::runtime_host::RuntimeHostVm::LogEmit(req) => {
+                // Logs are ignored.
+                call = req.resume();
+            }
+        }
+    }
+}
+
+/// Returns the path, in nibbles, of the child trie with the given key, as expected by the
+/// database.
+pub fn child_trie_path(child_trie: &[u8]) -> Vec<u8> {
+    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+        .chain(trie::bytes_to_nibbles(child_trie.iter().copied()))
+        .map(u8::from)
+        .collect::<Vec<_>>()
+}
+
+/// Error potentially returned by [`runtime_call`].
+#[derive(Debug, derive_more::Display)]
+pub enum RuntimeCallError {
+    /// Failed to start the runtime call.
+    #[display(fmt = "{_0}")]
+    Start(executor::host::StartErr),
+    /// Error during the execution of the runtime.
+    #[display(fmt = "{_0}")]
+    Execution(executor::runtime_host::ErrorDetail),
+    /// Failed to access the storage of the block.
+    #[display(fmt = "{_0}")]
+    StorageAccess(database_thread::StorageAccessError),
+    /// Runtime has called an offchain host function, which isn't supported in this context.
+    ForbiddenHostFunction,
+    /// Runtime has tried to generate a key of a type that isn't supported by the keystore.
+    #[display(fmt = "Unsupported key type: {_0:?}")]
+    UnsupportedKeyType(String),
+    /// Runtime has tried to generate a key from a seed that isn't valid UTF-8.
+    InvalidKeySeed,
+    /// Failed to generate a key requested by the runtime.
+    #[display(fmt = "Failed to generate key: {_0}")]
+    KeyGeneration(keystore::InsertPhraseError),
+}
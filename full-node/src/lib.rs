@@ -18,7 +18,8 @@
 #![deny(rustdoc::broken_intra_doc_links)]
 // TODO: #![deny(unused_crate_dependencies)] doesn't work because some deps are used only by the binary, figure if this can be fixed?
 
-use futures_util::{future, StreamExt as _};
+use futures_channel::mpsc;
+use futures_util::{future, stream, StreamExt as _};
 use rand::RngCore as _;
 use smol::lock::Mutex;
 use smoldot::{
@@ -33,14 +34,29 @@ use smoldot::{
     },
     trie,
 };
-use std::{array, borrow::Cow, io, iter, mem, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{
+    array,
+    borrow::Cow,
+    cmp, io, iter, mem,
+    net::SocketAddr,
+    num::{NonZeroU32, NonZeroU64},
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
+mod blocks_file;
 mod consensus_service;
 mod database_thread;
+mod http;
 mod jaeger_service;
 mod json_rpc_service;
+mod metrics_service;
 mod network_service;
+mod telemetry_service;
+mod transactions_service;
 mod util;
+mod warp_sync;
 
 pub struct Config<'a> {
     /// Chain to connect to.
@@ -60,6 +76,10 @@ pub struct Config<'a> {
     pub log_callback: Arc<dyn LogCallback + Send + Sync>,
     /// Address of a Jaeger agent to send traces to. If `None`, do not send Jaeger traces.
     pub jaeger_agent: Option<SocketAddr>,
+    /// Address to bind the Prometheus metrics server to. If `None`, metrics aren't served.
+    pub prometheus_address: Option<SocketAddr>,
+    /// Name of the node, as reported to the telemetry servers.
+    pub node_name: String,
 }
 
 /// See [`ChainConfig::json_rpc_listen`].
@@ -69,6 +89,17 @@ pub struct JsonRpcListenConfig {
     pub address: SocketAddr,
     /// Maximum number of JSON-RPC clients that can be connected at the same time.
     pub max_json_rpc_clients: u32,
+    /// List of values of the `Origin` HTTP header that are allowed to connect to the JSON-RPC
+    /// server. If `None`, all origins are allowed.
+    pub cors_allowed_origins: Option<Vec<String>>,
+    /// Maximum size, in bytes, of a JSON-RPC request, whether sent through HTTP or as a
+    /// WebSocket message.
+    pub max_request_size: usize,
+    /// If `false`, the JSON-RPC methods that modify the keystore of the node, such as
+    /// `author_insertKey`, are refused to the clients of the server.
+    ///
+    /// Should be `false` if the server is reachable by untrusted clients.
+    pub allow_unsafe_methods: bool,
 }
 
 /// Allow generating logs.
@@ -110,12 +141,30 @@ pub struct ChainConfig<'a> {
     pub sqlite_database_path: Option<PathBuf>,
     /// Maximum size, in bytes, of the cache SQLite uses.
     pub sqlite_cache_size: usize,
+    /// Number of finalized blocks, including the finalized block itself, whose storage is kept
+    /// in the database. If `None`, the storage of all the blocks is kept forever.
+    pub sqlite_state_pruning: Option<NonZeroU64>,
+    /// Number of finalized blocks, including the finalized block itself, whose body and
+    /// justification are kept in the database. If `None`, the bodies and justifications of all
+    /// the blocks are kept forever.
+    pub sqlite_blocks_pruning: Option<NonZeroU64>,
     /// Path to the directory where cryptographic keys are stored on disk.
     ///
     /// If `None`, no keys are stored in disk.
     pub keystore_path: Option<PathBuf>,
     /// Configuration of the JSON-RPC server. If `None`, no TCP server is started.
     pub json_rpc_listen: Option<JsonRpcListenConfig>,
+    /// If `true` and the finalized block of the database is the genesis block, the node warp
+    /// syncs to the latest finalized block of the chain and downloads its storage, instead of
+    /// verifying all the blocks since the genesis.
+    pub warp_sync: bool,
+    /// List of addresses of telemetry servers to report to, alongside with their verbosity
+    /// level. If `None`, the telemetry servers found in the chain specification are used
+    /// instead.
+    pub telemetry_endpoints: Option<Vec<(String, u8)>>,
+    /// If `true`, storage proof and call proof requests sent by other nodes of the network are
+    /// answered. If `false`, they are refused.
+    pub allow_inbound_storage_and_call_proof_requests: bool,
 }
 
 /// Running client. As long as this object is alive, the client reads/writes the database and has
@@ -127,6 +176,10 @@ pub struct Client {
     relay_chain_consensus_service: Option<Arc<consensus_service::ConsensusService>>,
     network_service: Arc<network_service::NetworkService>,
     network_known_best: Arc<Mutex<Option<u64>>>,
+    metrics_service: Arc<metrics_service::MetricsService>,
+    /// Telemetry services of the chain and of the relay chain. Only kept alive in order for the
+    /// telemetry to continue being sent.
+    _telemetry_services: Vec<Arc<telemetry_service::TelemetryService>>,
 }
 
 impl Client {
@@ -148,6 +201,13 @@ impl Client {
             .and_then(|j| j.listen_addr())
     }
 
+    /// Returns the address the Prometheus metrics server is listening on.
+    ///
+    /// Returns `None` if and only if [`Config::prometheus_address`] was `None`.
+    pub fn prometheus_server_addr(&self) -> Option<SocketAddr> {
+        self.metrics_service.listen_addr()
+    }
+
     /// Returns the best block according to the networking.
     pub async fn network_known_best(&self) -> Option<u64> {
         *self.network_known_best.lock().await
@@ -252,6 +312,8 @@ pub enum StartError {
     RelayChainKeystoreInit(io::Error),
     /// Error initializing the Jaeger service.
     JaegerInit(io::Error),
+    /// Error initializing the Prometheus metrics service.
+    MetricsInit(metrics_service::InitError),
 }
 
 /// Error potentially returned by [`Client::relay_chain_send_json_rpc_request`].
@@ -312,28 +374,6 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         }
     }
 
-    // The `telemetryEndpoints` field of chain specifications isn't supported.
-    if chain_spec.telemetry_endpoints().count() != 0 {
-        config.log_callback.log(
-            LogLevel::Warn,
-            format!(
-                "chain-spec-has-telemetry-endpoints; chain={}",
-                chain_spec.id()
-            ),
-        );
-    }
-    if let Some(relay_chain_spec) = &relay_chain_spec {
-        if relay_chain_spec.telemetry_endpoints().count() != 0 {
-            config.log_callback.log(
-                LogLevel::Warn,
-                format!(
-                    "chain-spec-has-telemetry-endpoints; chain={}",
-                    relay_chain_spec.id()
-                ),
-            );
-        }
-    }
-
     // Printing the SQLite version number can be useful for debugging purposes for example in case
     // a query fails.
     config.log_callback.log(
@@ -341,33 +381,61 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         format!("sqlite-version; version={}", full_sqlite::sqlite_version()),
     );
 
+    let metrics_service = metrics_service::MetricsService::new(metrics_service::Config {
+        tasks_executor: &mut |task| (config.tasks_executor)(task),
+        log_callback: config.log_callback.clone(),
+        listen_address: config.prometheus_address,
+    })
+    .await
+    .map_err(StartError::MetricsInit)?;
+
     let (database, database_existed) = {
         let (db, existed) = open_database(
             &chain_spec,
             genesis_chain_information.as_ref(),
             config.chain.sqlite_database_path,
             config.chain.sqlite_cache_size,
+            config.chain.sqlite_state_pruning,
+            config.chain.sqlite_blocks_pruning,
         )
         .await;
 
-        (Arc::new(database_thread::DatabaseThread::from(db)), existed)
+        (
+            Arc::new(database_thread::DatabaseThread::new(
+                db,
+                metrics_service.database_metrics(chain_spec.id()),
+            )),
+            existed,
+        )
     };
 
     let relay_chain_database = if let Some(relay_chain) = &config.relay_chain {
-        Some(Arc::new(database_thread::DatabaseThread::from(
+        Some(Arc::new(database_thread::DatabaseThread::new(
             open_database(
                 relay_chain_spec.as_ref().unwrap(),
                 relay_genesis_chain_information.as_ref().unwrap().as_ref(),
                 relay_chain.sqlite_database_path.clone(),
                 relay_chain.sqlite_cache_size,
+                relay_chain.sqlite_state_pruning,
+                relay_chain.sqlite_blocks_pruning,
             )
             .await
             .0,
+            metrics_service.database_metrics(relay_chain_spec.as_ref().unwrap().id()),
         )))
     } else {
         None
     };
 
+    // Spawn the tasks that remove from the databases the storage, bodies, and justifications
+    // that are no longer needed.
+    for database in iter::once(&database).chain(relay_chain_database.as_ref()) {
+        (config.tasks_executor)(Box::pin(pruning_task(
+            Arc::downgrade(database),
+            config.log_callback.clone(),
+        )));
+    }
+
     let database_finalized_block_hash = database
         .with_database(|db| db.finalized_block_hash().unwrap())
         .await;
@@ -409,6 +477,7 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
     let (network_service, network_service_chain_ids, network_events_receivers) =
         network_service::NetworkService::new(network_service::Config {
             listen_addresses: config.listen_addresses,
+            metrics: metrics_service.network_metrics(),
             num_events_receivers: 2 + if relay_chain_database.is_some() { 1 } else { 0 },
             chains: iter::once(network_service::ChainConfig {
                 log_name: chain_spec.id().to_owned(),
@@ -477,6 +546,9 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     list.extend(config.chain.additional_bootnodes);
                     list
                 },
+                allow_inbound_storage_and_call_proof_requests: config
+                    .chain
+                    .allow_inbound_storage_and_call_proof_requests,
             })
             .chain(
                 if let Some(relay_chains_specs) = &relay_chain_spec {
@@ -551,6 +623,11 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                             }
                             list
                         },
+                        allow_inbound_storage_and_call_proof_requests: config
+                            .relay_chain
+                            .as_ref()
+                            .unwrap()
+                            .allow_inbound_storage_and_call_proof_requests,
                     })
                 } else {
                     None
@@ -572,6 +649,110 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
 
     let mut network_events_receivers = network_events_receivers.into_iter();
 
+    // Spawn the task printing the informant.
+    // This is not just a dummy task that just prints on the output, but is actually the main
+    // task that holds everything else alive. Without it, all the services that we create
+    // below would be cleanly dropped and nothing would happen.
+    // For this reason, it must be spawned even if no informant is started, in which case we simply
+    // inhibit the printing.
+    let network_known_best = Arc::new(Mutex::new(None));
+    (config.tasks_executor)(Box::pin({
+        let mut main_network_events_receiver = network_events_receivers.next().unwrap();
+        let network_service_chain_id = network_service_chain_ids[0];
+        let network_known_best = network_known_best.clone();
+        let block_number_bytes = usize::from(chain_spec.block_number_bytes());
+
+        // TODO: shut down this task if the client stops?
+        async move {
+            loop {
+                let network_event = main_network_events_receiver.next().await.unwrap();
+                let mut network_known_best = network_known_best.lock().await;
+
+                match network_event {
+                    network_service::Event::BlockAnnounce {
+                        chain_id,
+                        scale_encoded_header,
+                        ..
+                    } if chain_id == network_service_chain_id => match (
+                        *network_known_best,
+                        header::decode(&scale_encoded_header, block_number_bytes),
+                    ) {
+                        (Some(n), Ok(header)) if n >= header.number => {}
+                        (_, Ok(header)) => *network_known_best = Some(header.number),
+                        (_, Err(_)) => {
+                            // Do nothing if the block is invalid. This is just for the
+                            // informant and not for consensus-related purposes.
+                        }
+                    },
+                    network_service::Event::Connected {
+                        chain_id,
+                        best_block_number,
+                        ..
+                    } if chain_id == network_service_chain_id => match *network_known_best {
+                        Some(n) if n >= best_block_number => {}
+                        _ => *network_known_best = Some(best_block_number),
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }));
+
+    // Warp sync the chain if the database is empty.
+    // Parachains can't be warp synced, as they don't use GrandPa.
+    let mut consensus_network_events_receiver = network_events_receivers.next().unwrap();
+    let (database_finalized_block_hash, database_finalized_block_number) = if config.chain.warp_sync
+        && database_finalized_block_number == 0
+        && relay_chain_database.is_none()
+    {
+        let outcome = warp_sync::warp_sync(warp_sync::Config {
+            log_callback: config.log_callback.clone(),
+            database: database.clone(),
+            network_service: (network_service.clone(), network_service_chain_ids[0]),
+            network_events_receiver: &mut consensus_network_events_receiver,
+            chain_information: genesis_chain_information.clone(),
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+        })
+        .await;
+
+        match outcome {
+            Ok(connected_events) => {
+                // The peers that have connected during the warp sync are reported to the
+                // consensus service.
+                consensus_network_events_receiver = stream::iter(connected_events)
+                    .chain(consensus_network_events_receiver)
+                    .boxed();
+            }
+            Err(err) => {
+                config
+                    .log_callback
+                    .log(LogLevel::Warn, format!("warp-sync-error; error={}", err));
+            }
+        }
+
+        let finalized_block_hash = database
+            .with_database(|db| db.finalized_block_hash().unwrap())
+            .await;
+        let finalized_block_number = header::decode(
+            &database
+                .with_database(move |db| {
+                    db.block_scale_encoded_header(&finalized_block_hash)
+                        .unwrap()
+                        .unwrap()
+                })
+                .await,
+            chain_spec.block_number_bytes().into(),
+        )
+        .unwrap()
+        .number;
+        (finalized_block_hash, finalized_block_number)
+    } else {
+        (
+            database_finalized_block_hash,
+            database_finalized_block_number,
+        )
+    };
+
     let keystore = Arc::new({
         let mut keystore = keystore::Keystore::new(config.chain.keystore_path, rand::random())
             .await
@@ -582,6 +763,11 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         }
         keystore
     });
+    let is_authority = keystore.keys().await.next().is_some();
+
+    // Channel through which the consensus service asks the transactions service for the
+    // transactions to include in the blocks it authors.
+    let (authoring_transactions_requests_tx, authoring_transactions_requests_rx) = mpsc::channel(4);
 
     let consensus_service = consensus_service::ConsensusService::new(consensus_service::Config {
         tasks_executor: {
@@ -590,17 +776,62 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         },
         log_callback: config.log_callback.clone(),
         genesis_block_hash,
-        network_events_receiver: network_events_receivers.next().unwrap(),
+        code_substitutes: chain_spec
+            .code_substitutes()
+            .map(|(block_number, code)| (block_number, code.to_vec()))
+            .collect(),
+        fork_blocks: chain_spec
+            .fork_blocks()
+            .map(|(block_number, hash)| (block_number, *hash))
+            .collect(),
+        bad_blocks: chain_spec.bad_blocks_hashes().copied().collect(),
+        network_events_receiver: consensus_network_events_receiver,
         network_service: (network_service.clone(), network_service_chain_ids[0]),
         database: database.clone(),
         block_number_bytes: usize::from(chain_spec.block_number_bytes()),
-        keystore,
+        keystore: keystore.clone(),
         jaeger_service: jaeger_service.clone(),
+        metrics: metrics_service.consensus_metrics(chain_spec.id()),
+        authoring_transactions_requests: authoring_transactions_requests_tx,
         slot_duration_author_ratio: 43691_u16,
     })
     .await
     .map_err(StartError::ConsensusServiceInit)?;
 
+    let transactions_service =
+        transactions_service::TransactionsService::new(transactions_service::Config {
+            tasks_executor: {
+                let executor = config.tasks_executor.clone();
+                Box::new(move |task| executor(task))
+            },
+            log_callback: config.log_callback.clone(),
+            database: database.clone(),
+            consensus_service: consensus_service.clone(),
+            network_service: (network_service.clone(), network_service_chain_ids[0]),
+            authoring_transactions_requests: authoring_transactions_requests_rx,
+            max_pending_transactions: NonZeroU32::new(4096).unwrap(),
+            max_concurrent_validations: NonZeroU32::new(8).unwrap(),
+        })
+        .await;
+
+    let relay_chain_keystore = if let Some(relay_chain) = config.relay_chain.as_mut() {
+        let mut keystore =
+            keystore::Keystore::new(relay_chain.keystore_path.clone(), rand::random())
+                .await
+                .map_err(StartError::RelayChainKeystoreInit)?;
+        for mut private_key in mem::take(&mut relay_chain.keystore_memory) {
+            keystore.insert_sr25519_memory(keystore::KeyNamespace::all(), &private_key);
+            zeroize::Zeroize::zeroize(&mut *private_key);
+        }
+        Some(Arc::new(keystore))
+    } else {
+        None
+    };
+
+    let (
+        relay_chain_authoring_transactions_requests_tx,
+        relay_chain_authoring_transactions_requests_rx,
+    ) = mpsc::channel(4);
     let relay_chain_consensus_service = if let Some(relay_chain_database) = &relay_chain_database {
         Some(
             consensus_service::ConsensusService::new(consensus_service::Config {
@@ -617,28 +848,34 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                     .hash(usize::from(
                         relay_chain_spec.as_ref().unwrap().block_number_bytes(),
                     )),
+                code_substitutes: relay_chain_spec
+                    .as_ref()
+                    .unwrap()
+                    .code_substitutes()
+                    .map(|(block_number, code)| (block_number, code.to_vec()))
+                    .collect(),
+                fork_blocks: relay_chain_spec
+                    .as_ref()
+                    .unwrap()
+                    .fork_blocks()
+                    .map(|(block_number, hash)| (block_number, *hash))
+                    .collect(),
+                bad_blocks: relay_chain_spec
+                    .as_ref()
+                    .unwrap()
+                    .bad_blocks_hashes()
+                    .copied()
+                    .collect(),
                 network_events_receiver: network_events_receivers.next().unwrap(),
                 network_service: (network_service.clone(), network_service_chain_ids[1]),
                 database: relay_chain_database.clone(),
                 block_number_bytes: usize::from(
                     relay_chain_spec.as_ref().unwrap().block_number_bytes(),
                 ),
-                keystore: Arc::new({
-                    let mut keystore = keystore::Keystore::new(
-                        config.relay_chain.as_ref().unwrap().keystore_path.clone(),
-                        rand::random(),
-                    )
-                    .await
-                    .map_err(StartError::RelayChainKeystoreInit)?;
-                    for mut private_key in
-                        mem::take(&mut config.relay_chain.as_mut().unwrap().keystore_memory)
-                    {
-                        keystore.insert_sr25519_memory(keystore::KeyNamespace::all(), &private_key);
-                        zeroize::Zeroize::zeroize(&mut *private_key);
-                    }
-                    keystore
-                }),
+                keystore: relay_chain_keystore.clone().unwrap(),
                 jaeger_service, // TODO: consider passing a different jaeger service with a different service name
+                metrics: metrics_service.consensus_metrics(relay_chain_spec.as_ref().unwrap().id()),
+                authoring_transactions_requests: relay_chain_authoring_transactions_requests_tx,
                 slot_duration_author_ratio: 43691_u16,
             })
             .await
@@ -648,6 +885,63 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         None
     };
 
+    // Start reporting to the telemetry servers of the chain and of the relay chain.
+    let mut telemetry_services = Vec::with_capacity(2);
+    telemetry_services.push(telemetry_service::TelemetryService::new(
+        telemetry_service::Config {
+            tasks_executor: &mut |task| (config.tasks_executor)(task),
+            log_callback: config.log_callback.clone(),
+            log_name: chain_spec.id().to_owned(),
+            endpoints: config.chain.telemetry_endpoints.unwrap_or_else(|| {
+                chain_spec
+                    .telemetry_endpoints()
+                    .map(|(address, verbosity)| (address.to_owned(), verbosity))
+                    .collect()
+            }),
+            node_name: config.node_name.clone(),
+            chain_name: chain_spec.name().to_owned(),
+            genesis_block_hash,
+            network_id: local_peer_id.to_string(),
+            authority: is_authority,
+            consensus_service: consensus_service.clone(),
+            network_service: (network_service.clone(), network_service_chain_ids[0]),
+        },
+    ));
+    if let Some(relay_chain_consensus_service) = &relay_chain_consensus_service {
+        let relay_chain_spec = relay_chain_spec.as_ref().unwrap();
+        telemetry_services.push(telemetry_service::TelemetryService::new(
+            telemetry_service::Config {
+                tasks_executor: &mut |task| (config.tasks_executor)(task),
+                log_callback: config.log_callback.clone(),
+                log_name: relay_chain_spec.id().to_owned(),
+                endpoints: config
+                    .relay_chain
+                    .as_mut()
+                    .unwrap()
+                    .telemetry_endpoints
+                    .take()
+                    .unwrap_or_else(|| {
+                        relay_chain_spec
+                            .telemetry_endpoints()
+                            .map(|(address, verbosity)| (address.to_owned(), verbosity))
+                            .collect()
+                    }),
+                node_name: config.node_name.clone(),
+                chain_name: relay_chain_spec.name().to_owned(),
+                genesis_block_hash: relay_genesis_chain_information
+                    .as_ref()
+                    .unwrap()
+                    .as_ref()
+                    .finalized_block_header
+                    .hash(usize::from(relay_chain_spec.block_number_bytes())),
+                network_id: local_peer_id.to_string(),
+                authority: false,
+                consensus_service: relay_chain_consensus_service.clone(),
+                network_service: (network_service.clone(), network_service_chain_ids[1]),
+            },
+        ));
+    }
+
     // Start the JSON-RPC service.
     // It only needs to be kept alive in order to function.
     //
@@ -660,17 +954,40 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         log_callback: config.log_callback.clone(),
         database,
         consensus_service: consensus_service.clone(),
+        transactions_service,
+        keystore,
         network_service: (network_service.clone(), network_service_chain_ids[0]),
+        metrics: metrics_service.json_rpc_metrics(chain_spec.id()),
         bind_address: config.chain.json_rpc_listen.as_ref().map(|cfg| cfg.address),
         max_parallel_requests: 32,
         max_json_rpc_clients: config
             .chain
             .json_rpc_listen
+            .as_ref()
             .map_or(0, |cfg| cfg.max_json_rpc_clients),
+        cors_allowed_origins: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .and_then(|cfg| cfg.cors_allowed_origins.clone()),
+        max_request_size: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(0, |cfg| cfg.max_request_size),
+        allow_unsafe_methods: config
+            .chain
+            .json_rpc_listen
+            .as_ref()
+            .map_or(false, |cfg| cfg.allow_unsafe_methods),
         chain_name: chain_spec.name().to_owned(),
         chain_type: chain_spec.chain_type().to_owned(),
         chain_properties_json: chain_spec.properties().to_owned(),
         chain_is_live: chain_spec.has_live_network(),
+        code_substitutes: chain_spec
+            .code_substitutes()
+            .map(|(block_number, code)| (block_number, code.to_vec()))
+            .collect(),
         genesis_block_hash: genesis_chain_information
             .as_ref()
             .finalized_block_header
@@ -683,13 +1000,31 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
     // See remarks above.
     let relay_chain_json_rpc_service = if let Some(relay_chain_cfg) = config.relay_chain {
         let relay_chain_spec = relay_chain_spec.as_ref().unwrap();
+        let relay_chain_transactions_service =
+            transactions_service::TransactionsService::new(transactions_service::Config {
+                tasks_executor: {
+                    let executor = config.tasks_executor.clone();
+                    Box::new(move |task| executor(task))
+                },
+                log_callback: config.log_callback.clone(),
+                database: relay_chain_database.clone().unwrap(),
+                consensus_service: relay_chain_consensus_service.clone().unwrap(),
+                network_service: (network_service.clone(), network_service_chain_ids[1]),
+                authoring_transactions_requests: relay_chain_authoring_transactions_requests_rx,
+                max_pending_transactions: NonZeroU32::new(4096).unwrap(),
+                max_concurrent_validations: NonZeroU32::new(8).unwrap(),
+            })
+            .await;
         Some(
             json_rpc_service::JsonRpcService::new(json_rpc_service::Config {
                 tasks_executor: config.tasks_executor.clone(),
                 log_callback: config.log_callback.clone(),
                 database: relay_chain_database.clone().unwrap(),
                 consensus_service: relay_chain_consensus_service.clone().unwrap(),
+                transactions_service: relay_chain_transactions_service,
+                keystore: relay_chain_keystore.unwrap(),
                 network_service: (network_service.clone(), network_service_chain_ids[1]),
+                metrics: metrics_service.json_rpc_metrics(relay_chain_spec.id()),
                 bind_address: relay_chain_cfg
                     .json_rpc_listen
                     .as_ref()
@@ -697,11 +1032,28 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
                 max_parallel_requests: 32,
                 max_json_rpc_clients: relay_chain_cfg
                     .json_rpc_listen
+                    .as_ref()
                     .map_or(0, |cfg| cfg.max_json_rpc_clients),
+                cors_allowed_origins: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .and_then(|cfg| cfg.cors_allowed_origins.clone()),
+                max_request_size: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(0, |cfg| cfg.max_request_size),
+                allow_unsafe_methods: relay_chain_cfg
+                    .json_rpc_listen
+                    .as_ref()
+                    .map_or(false, |cfg| cfg.allow_unsafe_methods),
                 chain_name: relay_chain_spec.name().to_owned(),
                 chain_type: relay_chain_spec.chain_type().to_owned(),
                 chain_properties_json: relay_chain_spec.properties().to_owned(),
                 chain_is_live: relay_chain_spec.has_live_network(),
+                code_substitutes: relay_chain_spec
+                    .code_substitutes()
+                    .map(|(block_number, code)| (block_number, code.to_vec()))
+                    .collect(),
                 genesis_block_hash: relay_genesis_chain_information
                     .as_ref()
                     .unwrap()
@@ -716,57 +1068,6 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         None
     };
 
-    // Spawn the task printing the informant.
-    // This is not just a dummy task that just prints on the output, but is actually the main
-    // task that holds everything else alive. Without it, all the services that we have created
-    // above would be cleanly dropped and nothing would happen.
-    // For this reason, it must be spawned even if no informant is started, in which case we simply
-    // inhibit the printing.
-    let network_known_best = Arc::new(Mutex::new(None));
-    (config.tasks_executor)(Box::pin({
-        let mut main_network_events_receiver = network_events_receivers.next().unwrap();
-        let network_service_chain_id = network_service_chain_ids[0];
-        let network_known_best = network_known_best.clone();
-
-        // TODO: shut down this task if the client stops?
-        async move {
-            loop {
-                let network_event = main_network_events_receiver.next().await.unwrap();
-                let mut network_known_best = network_known_best.lock().await;
-
-                match network_event {
-                    network_service::Event::BlockAnnounce {
-                        chain_id,
-                        scale_encoded_header,
-                        ..
-                    } if chain_id == network_service_chain_id => match (
-                        *network_known_best,
-                        header::decode(
-                            &scale_encoded_header,
-                            usize::from(chain_spec.block_number_bytes()),
-                        ),
-                    ) {
-                        (Some(n), Ok(header)) if n >= header.number => {}
-                        (_, Ok(header)) => *network_known_best = Some(header.number),
-                        (_, Err(_)) => {
-                            // Do nothing if the block is invalid. This is just for the
-                            // informant and not for consensus-related purposes.
-                        }
-                    },
-                    network_service::Event::Connected {
-                        chain_id,
-                        best_block_number,
-                        ..
-                    } if chain_id == network_service_chain_id => match *network_known_best {
-                        Some(n) if n >= best_block_number => {}
-                        _ => *network_known_best = Some(best_block_number),
-                    },
-                    _ => {}
-                }
-            }
-        }
-    }));
-
     config.log_callback.log(
         LogLevel::Info,
         format!(
@@ -787,9 +1088,539 @@ pub async fn start(mut config: Config<'_>) -> Result<Client, StartError> {
         relay_chain_json_rpc_service,
         network_service,
         network_known_best,
+        metrics_service,
+        _telemetry_services: telemetry_services,
     })
 }
 
+/// Format of the files written by [`export_blocks`] and read by [`import_blocks`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlocksFileFormat {
+    /// Concatenation of SCALE-encoded blocks. Each block is encoded as the SCALE encoding of a
+    /// tuple containing the SCALE-encoded header, the list of SCALE-encoded extrinsics, and the
+    /// list of consensus engine identifiers and SCALE-encoded justifications of the block.
+    Scale,
+    /// One JSON object per line, in the format
+    /// `{"header":"0x...","body":["0x...", ...],"justifications":[{"engine":"0x...","justification":"0x..."}, ...]}`.
+    JsonLines,
+}
+
+/// Configuration for [`export_blocks`].
+pub struct ExportBlocksConfig<'a> {
+    /// Specification of the chain.
+    pub chain_spec: Cow<'a, [u8]>,
+    /// Path to the SQLite database. If `None`, the database is opened in memory.
+    pub sqlite_database_path: Option<PathBuf>,
+    /// Maximum size, in bytes, of the cache SQLite uses.
+    pub sqlite_cache_size: usize,
+    /// Number of the first block of the best chain to export.
+    pub first_block_number: u64,
+    /// Number of the last block of the best chain to export. If `None` or above the current
+    /// best block, the blocks are exported up to the current best block.
+    pub last_block_number: Option<u64>,
+    /// Format of the output.
+    pub format: BlocksFileFormat,
+    /// Where to write the blocks to.
+    pub output: &'a mut (dyn io::Write + Send),
+}
+
+/// Error potentially returned by [`export_blocks`].
+#[derive(Debug, derive_more::Display)]
+pub enum ExportBlocksError {
+    /// Failed to parse the chain specification.
+    ChainSpecParse(chain_spec::ParseError),
+    /// Error building the chain information of the genesis block.
+    InvalidGenesisInformation(chain_spec::FromGenesisStorageError),
+    /// Database is corrupted.
+    DatabaseCorruption(full_sqlite::CorruptedError),
+    /// A block to export isn't in the database, for example because the database has been
+    /// filled through a warp sync.
+    #[display(fmt = "Block #{_0} isn't in the database")]
+    MissingBlock(u64),
+    /// The body of a block to export has been pruned from the database.
+    #[display(fmt = "Body of block #{_0} has been pruned from the database")]
+    BodyPruned(u64),
+    /// Error while writing the output.
+    #[display(fmt = "Failed to write blocks: {_0}")]
+    Write(io::Error),
+}
+
+/// Writes the blocks of the best chain found in the database to [`ExportBlocksConfig::output`].
+///
+/// Returns the number of blocks that have been exported.
+///
+/// The blocks are written in increasing order, alongside with their justifications. The
+/// output can later be passed to [`import_blocks`].
+pub async fn export_blocks(config: ExportBlocksConfig<'_>) -> Result<u64, ExportBlocksError> {
+    let chain_spec = chain_spec::ChainSpec::from_json_bytes(&config.chain_spec)
+        .map_err(ExportBlocksError::ChainSpecParse)?;
+    let genesis_chain_information = chain_spec
+        .to_chain_information()
+        .map_err(ExportBlocksError::InvalidGenesisInformation)?
+        .0;
+    let block_number_bytes = usize::from(chain_spec.block_number_bytes());
+
+    // The database is accessed directly rather than through a `DatabaseThread`, as nothing
+    // else accesses it.
+    let (database, _) = open_database(
+        &chain_spec,
+        genesis_chain_information.as_ref(),
+        config.sqlite_database_path,
+        config.sqlite_cache_size,
+        None,
+        None,
+    )
+    .await;
+
+    let best_block_number = {
+        let best_block_hash = database
+            .best_block_hash()
+            .map_err(ExportBlocksError::DatabaseCorruption)?;
+        let best_block_header = database
+            .block_scale_encoded_header(&best_block_hash)
+            .map_err(ExportBlocksError::DatabaseCorruption)?
+            .unwrap(); // A panic here would indicate a bug in the database code.
+        header::decode(&best_block_header, block_number_bytes)
+            .unwrap()
+            .number
+    };
+    let last_block_number = config
+        .last_block_number
+        .map_or(best_block_number, |n| cmp::min(n, best_block_number));
+
+    let mut num_exported = 0;
+    for block_number in config.first_block_number..=last_block_number {
+        let block_hash = database
+            .best_block_hash_by_number(block_number)
+            .map_err(ExportBlocksError::DatabaseCorruption)?
+            .ok_or(ExportBlocksError::MissingBlock(block_number))?;
+        let scale_encoded_header = database
+            .block_scale_encoded_header(&block_hash)
+            .map_err(ExportBlocksError::DatabaseCorruption)?
+            .ok_or(ExportBlocksError::MissingBlock(block_number))?;
+        let scale_encoded_extrinsics = match database.block_extrinsics(&block_hash) {
+            Ok(list) => list.collect(),
+            Err(full_sqlite::BlockBodyAccessError::Corrupted(err)) => {
+                return Err(ExportBlocksError::DatabaseCorruption(err))
+            }
+            Err(full_sqlite::BlockBodyAccessError::BodyPruned) => {
+                return Err(ExportBlocksError::BodyPruned(block_number))
+            }
+            Err(full_sqlite::BlockBodyAccessError::UnknownBlock) => {
+                return Err(ExportBlocksError::MissingBlock(block_number))
+            }
+        };
+        // The database only stores GrandPa justifications.
+        let scale_encoded_justifications = database
+            .block_justification(&block_hash)
+            .map_err(ExportBlocksError::DatabaseCorruption)?
+            .map(|justification| (*b"FRNK", justification))
+            .into_iter()
+            .collect();
+
+        blocks_file::write_block(
+            config.output,
+            config.format,
+            &blocks_file::Block {
+                scale_encoded_header,
+                scale_encoded_extrinsics,
+                scale_encoded_justifications,
+            },
+        )
+        .map_err(ExportBlocksError::Write)?;
+        num_exported += 1;
+    }
+
+    io::Write::flush(config.output).map_err(ExportBlocksError::Write)?;
+    Ok(num_exported)
+}
+
+/// Configuration for [`import_blocks`].
+pub struct ImportBlocksConfig<'a> {
+    /// Specification of the chain.
+    pub chain_spec: Cow<'a, [u8]>,
+    /// Path to the SQLite database. If `None`, the database is opened in memory.
+    pub sqlite_database_path: Option<PathBuf>,
+    /// Maximum size, in bytes, of the cache SQLite uses.
+    pub sqlite_cache_size: usize,
+    /// Function that can be used to spawn background tasks.
+    ///
+    /// The tasks passed as parameter must be executed until they shut down.
+    pub tasks_executor: Arc<dyn Fn(future::BoxFuture<'static, ()>) + Send + Sync>,
+    /// Function called whenever a part of the node wants to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+    /// Format of the input.
+    pub format: BlocksFileFormat,
+    /// Where to read the blocks from.
+    pub input: &'a mut (dyn io::BufRead + Send),
+}
+
+/// Outcome of a successful call to [`import_blocks`].
+#[derive(Debug, Clone)]
+pub struct ImportBlocksOutcome {
+    /// Number of blocks that have been verified and inserted in the database.
+    pub num_imported: u64,
+    /// Number of blocks that were already in the database.
+    pub num_already_known: u64,
+}
+
+/// Error potentially returned by [`import_blocks`].
+#[derive(Debug, derive_more::Display)]
+pub enum ImportBlocksError {
+    /// Failed to parse the chain specification.
+    ChainSpecParse(chain_spec::ParseError),
+    /// Error building the chain information of the genesis block.
+    InvalidGenesisInformation(chain_spec::FromGenesisStorageError),
+    /// Error initializing the networking service.
+    NetworkInit(network_service::InitError),
+    /// Error initializing the consensus service.
+    ConsensusServiceInit(consensus_service::InitError),
+    /// Error initializing the keystore.
+    KeystoreInit(io::Error),
+    /// Error initializing the Jaeger service.
+    JaegerInit(io::Error),
+    /// Error initializing the Prometheus metrics service.
+    MetricsInit(metrics_service::InitError),
+    /// Error while reading the input.
+    #[display(fmt = "Failed to read blocks: {_0}")]
+    Read(blocks_file::ReadError),
+    /// Failed to import a block.
+    #[display(fmt = "Failed to import block at position {position}: {error}")]
+    Block {
+        /// Position of the block in the input, starting from 0.
+        position: u64,
+        /// Reason why the import failed.
+        error: consensus_service::ImportBlockError,
+    },
+}
+
+/// Verifies the blocks read from [`ImportBlocksConfig::input`] and inserts them in the database.
+///
+/// The blocks are verified the same way as blocks downloaded from the network, and must be
+/// ordered such that each block is a child of the block before it. Blocks that are already in
+/// the database are skipped, but their justifications are still verified. The import stops at
+/// the first invalid block.
+///
+/// No network connection is opened.
+pub async fn import_blocks(
+    config: ImportBlocksConfig<'_>,
+) -> Result<ImportBlocksOutcome, ImportBlocksError> {
+    let chain_spec = chain_spec::ChainSpec::from_json_bytes(&config.chain_spec)
+        .map_err(ImportBlocksError::ChainSpecParse)?;
+    let genesis_chain_information = chain_spec
+        .to_chain_information()
+        .map_err(ImportBlocksError::InvalidGenesisInformation)?
+        .0;
+    let genesis_block_hash = genesis_chain_information
+        .as_ref()
+        .finalized_block_header
+        .hash(chain_spec.block_number_bytes().into());
+
+    let metrics_service = metrics_service::MetricsService::new(metrics_service::Config {
+        tasks_executor: &mut |task| (config.tasks_executor)(task),
+        log_callback: config.log_callback.clone(),
+        listen_address: None,
+    })
+    .await
+    .map_err(ImportBlocksError::MetricsInit)?;
+
+    let database = Arc::new(database_thread::DatabaseThread::new(
+        open_database(
+            &chain_spec,
+            genesis_chain_information.as_ref(),
+            config.sqlite_database_path,
+            config.sqlite_cache_size,
+            None,
+            None,
+        )
+        .await
+        .0,
+        metrics_service.database_metrics(chain_spec.id()),
+    ));
+
+    let jaeger_service = jaeger_service::JaegerService::new(jaeger_service::Config {
+        tasks_executor: &mut |task| (config.tasks_executor)(task),
+        service_name: "import-blocks".to_owned(),
+        jaeger_agent: None,
+    })
+    .await
+    .map_err(ImportBlocksError::JaegerInit)?;
+
+    // The consensus service requires a networking service. A networking service without any
+    // bootnode and without any listening address is created, so that no connection is ever
+    // opened.
+    let (network_service, network_service_chain_ids, network_events_receivers) =
+        network_service::NetworkService::new(network_service::Config {
+            listen_addresses: Vec::new(),
+            metrics: metrics_service.network_metrics(),
+            num_events_receivers: 1,
+            chains: vec![network_service::ChainConfig {
+                log_name: chain_spec.id().to_owned(),
+                fork_id: chain_spec.fork_id().map(|n| n.to_owned()),
+                block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+                database: database.clone(),
+                grandpa_protocol_finalized_block_height: if matches!(
+                    genesis_chain_information.as_ref().finality,
+                    chain::chain_information::ChainInformationFinalityRef::Grandpa { .. }
+                ) {
+                    Some({
+                        let block_number_bytes = chain_spec.block_number_bytes();
+                        database
+                            .with_database(move |database| {
+                                let hash = database.finalized_block_hash().unwrap();
+                                let header =
+                                    database.block_scale_encoded_header(&hash).unwrap().unwrap();
+                                header::decode(&header, block_number_bytes.into())
+                                    .unwrap()
+                                    .number
+                            })
+                            .await
+                    })
+                } else {
+                    None
+                },
+                genesis_block_hash,
+                best_block: {
+                    let block_number_bytes = chain_spec.block_number_bytes();
+                    database
+                        .with_database(move |database| {
+                            let hash = database.finalized_block_hash().unwrap();
+                            let header =
+                                database.block_scale_encoded_header(&hash).unwrap().unwrap();
+                            let number = header::decode(&header, block_number_bytes.into())
+                                .unwrap()
+                                .number;
+                            (number, hash)
+                        })
+                        .await
+                },
+                bootstrap_nodes: Vec::new(),
+                allow_inbound_storage_and_call_proof_requests: false,
+            }],
+            identify_agent_version: concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"))
+                .to_owned(),
+            noise_key: {
+                let mut libp2p_key = zeroize::Zeroizing::new([0u8; 32]);
+                rand::thread_rng().fill_bytes(&mut *libp2p_key);
+                let mut noise_static_key = zeroize::Zeroizing::new([0u8; 32]);
+                rand::thread_rng().fill_bytes(&mut *noise_static_key);
+                connection::NoiseKey::new(&libp2p_key, &noise_static_key)
+            },
+            tasks_executor: {
+                let executor = config.tasks_executor.clone();
+                Box::new(move |task| executor(task))
+            },
+            log_callback: config.log_callback.clone(),
+            jaeger_service: jaeger_service.clone(),
+        })
+        .await
+        .map_err(ImportBlocksError::NetworkInit)?;
+
+    // No block is ever authored, and the requests for transactions to include in blocks are
+    // thus never sent.
+    let (authoring_transactions_requests_tx, _) = mpsc::channel(0);
+
+    let consensus_service = consensus_service::ConsensusService::new(consensus_service::Config {
+        tasks_executor: {
+            let executor = config.tasks_executor.clone();
+            Box::new(move |task| executor(task))
+        },
+        log_callback: config.log_callback.clone(),
+        genesis_block_hash,
+        code_substitutes: chain_spec
+            .code_substitutes()
+            .map(|(block_number, code)| (block_number, code.to_vec()))
+            .collect(),
+        fork_blocks: chain_spec
+            .fork_blocks()
+            .map(|(block_number, hash)| (block_number, *hash))
+            .collect(),
+        bad_blocks: chain_spec.bad_blocks_hashes().copied().collect(),
+        network_events_receiver: network_events_receivers.into_iter().next().unwrap(),
+        network_service: (network_service.clone(), network_service_chain_ids[0]),
+        database: database.clone(),
+        block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+        keystore: Arc::new(
+            keystore::Keystore::new(None, rand::random())
+                .await
+                .map_err(ImportBlocksError::KeystoreInit)?,
+        ),
+        jaeger_service,
+        metrics: metrics_service.consensus_metrics(chain_spec.id()),
+        authoring_transactions_requests: authoring_transactions_requests_tx,
+        slot_duration_author_ratio: 43691_u16,
+    })
+    .await
+    .map_err(ImportBlocksError::ConsensusServiceInit)?;
+
+    let mut reader = blocks_file::Reader::new(config.input, config.format);
+    let mut outcome = ImportBlocksOutcome {
+        num_imported: 0,
+        num_already_known: 0,
+    };
+
+    for position in 0.. {
+        let Some(block) = reader.next_block().map_err(ImportBlocksError::Read)? else {
+            break;
+        };
+
+        match consensus_service
+            .import_block(
+                block.scale_encoded_header,
+                block.scale_encoded_extrinsics,
+                block.scale_encoded_justifications,
+            )
+            .await
+        {
+            Ok(consensus_service::ImportBlockOutcome::Imported) => outcome.num_imported += 1,
+            Ok(consensus_service::ImportBlockOutcome::AlreadyKnown) => {
+                outcome.num_already_known += 1
+            }
+            Err(error) => return Err(ImportBlocksError::Block { position, error }),
+        }
+
+        config.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "import-blocks-progress; position={position}; imported={}; already_known={}",
+                outcome.num_imported, outcome.num_already_known
+            ),
+        );
+    }
+
+    // Database writes are performed in the background. Wait for all of them to be finished
+    // before returning.
+    database.with_database(|_| ()).await;
+
+    Ok(outcome)
+}
+
+/// Configuration for [`build_chain_spec`].
+pub struct BuildChainSpecConfig<'a> {
+    /// Specification of the chain.
+    pub chain_spec: Cow<'a, [u8]>,
+    /// Path to the SQLite database. If `None`, the database is opened in memory.
+    pub sqlite_database_path: Option<PathBuf>,
+    /// Maximum size, in bytes, of the cache SQLite uses.
+    pub sqlite_cache_size: usize,
+    /// Hash of the block whose state becomes the genesis storage of the new chain
+    /// specification. If `None`, the current finalized block is used.
+    pub block_hash: Option<[u8; 32]>,
+    /// If `true`, a checkpoint corresponding to the block is embedded in the `lightSyncState`
+    /// field of the new chain specification. The block must be the current finalized block.
+    pub with_light_sync_state: bool,
+}
+
+/// Error potentially returned by [`build_chain_spec`].
+#[derive(Debug, derive_more::Display)]
+pub enum BuildChainSpecError {
+    /// Failed to parse the chain specification.
+    ChainSpecParse(chain_spec::ParseError),
+    /// Error building the chain information of the genesis block.
+    InvalidGenesisInformation(chain_spec::FromGenesisStorageError),
+    /// Database is corrupted.
+    DatabaseCorruption(full_sqlite::CorruptedError),
+    /// Error while accessing the storage of the block.
+    #[display(fmt = "Failed to access the storage of the block: {_0}")]
+    StorageAccess(full_sqlite::StorageAccessError),
+    /// The storage of the block contains child tries, which chain specifications don't support.
+    ChildTriesUnsupported,
+    /// A light sync state has been requested but the block isn't the current finalized block.
+    LightSyncStateNotFinalized,
+    /// Failed to build the light sync state.
+    #[display(fmt = "Failed to build the light sync state: {_0}")]
+    LightSyncState(chain_spec::ChainInformationToCheckpointError),
+}
+
+/// Builds a raw chain specification identical to [`BuildChainSpecConfig::chain_spec`] except
+/// that the genesis storage is replaced with the storage of the given block found in the
+/// database.
+///
+/// Returns the JSON-encoded chain specification.
+pub async fn build_chain_spec(
+    config: BuildChainSpecConfig<'_>,
+) -> Result<String, BuildChainSpecError> {
+    let mut chain_spec = chain_spec::ChainSpec::from_json_bytes(&config.chain_spec)
+        .map_err(BuildChainSpecError::ChainSpecParse)?;
+    let genesis_chain_information = chain_spec
+        .to_chain_information()
+        .map_err(BuildChainSpecError::InvalidGenesisInformation)?
+        .0;
+
+    // The database is accessed directly rather than through a `DatabaseThread`, as nothing
+    // else accesses it.
+    let (database, _) = open_database(
+        &chain_spec,
+        genesis_chain_information.as_ref(),
+        config.sqlite_database_path,
+        config.sqlite_cache_size,
+        None,
+        None,
+    )
+    .await;
+
+    let finalized_block_hash = database
+        .finalized_block_hash()
+        .map_err(BuildChainSpecError::DatabaseCorruption)?;
+    let block_hash = config.block_hash.unwrap_or(finalized_block_hash);
+
+    // The storage is walked by repeatedly asking for the next key.
+    let mut storage = Vec::new();
+    let mut key_nibbles = Vec::new();
+    while let Some(key) = database
+        .block_storage_next_key(
+            &block_hash,
+            iter::empty::<iter::Empty<_>>(),
+            key_nibbles.iter().copied(),
+            iter::empty(),
+            false,
+        )
+        .map_err(BuildChainSpecError::StorageAccess)?
+    {
+        if let Some((value, _)) = database
+            .block_storage_get(
+                &block_hash,
+                iter::empty::<iter::Empty<_>>(),
+                key.iter().copied(),
+            )
+            .map_err(BuildChainSpecError::StorageAccess)?
+        {
+            let key_bytes = trie::nibbles_to_bytes_truncate(
+                key.iter()
+                    .copied()
+                    .map(|n| trie::Nibble::try_from(n).unwrap()),
+            )
+            .collect::<Vec<_>>();
+            if key_bytes.starts_with(b":child_storage:") {
+                return Err(BuildChainSpecError::ChildTriesUnsupported);
+            }
+            storage.push((key_bytes, value));
+        }
+
+        // Push an extra nibble as otherwise `block_storage_next_key` will return the same key
+        // again.
+        key_nibbles = key;
+        key_nibbles.push(0);
+    }
+
+    chain_spec.set_genesis_storage(storage.into_iter());
+
+    if config.with_light_sync_state {
+        if block_hash != finalized_block_hash {
+            return Err(BuildChainSpecError::LightSyncStateNotFinalized);
+        }
+
+        let chain_information = database
+            .to_chain_information(&finalized_block_hash)
+            .map_err(BuildChainSpecError::StorageAccess)?;
+        chain_spec
+            .set_light_sync_state(&chain_information)
+            .map_err(BuildChainSpecError::LightSyncState)?;
+    }
+
+    Ok(chain_spec.serialize())
+}
+
 /// Opens the database from the file system, or create a new database if none is found.
 ///
 /// If `db_path` is `None`, open the database in memory instead.
@@ -806,11 +1637,21 @@ async fn open_database(
     genesis_chain_information: chain::chain_information::ChainInformationRef<'_>,
     db_path: Option<PathBuf>,
     sqlite_cache_size: usize,
+    sqlite_state_pruning: Option<NonZeroU64>,
+    sqlite_blocks_pruning: Option<NonZeroU64>,
 ) -> (full_sqlite::SqliteFullDatabase, bool) {
     // The `unwrap()` here can panic for example in case of access denied.
     match full_sqlite::open(full_sqlite::Config {
         block_number_bytes: chain_spec.block_number_bytes().into(),
         cache_size: sqlite_cache_size,
+        state_pruning: match sqlite_state_pruning {
+            Some(num_blocks) => full_sqlite::StatePruning::KeepFinalized(num_blocks),
+            None => full_sqlite::StatePruning::Archive,
+        },
+        blocks_pruning: match sqlite_blocks_pruning {
+            Some(num_blocks) => full_sqlite::BlocksPruning::KeepFinalized(num_blocks),
+            None => full_sqlite::BlocksPruning::Archive,
+        },
         ty: if let Some(path) = &db_path {
             full_sqlite::ConfigTy::Disk {
                 path,
@@ -824,12 +1665,20 @@ async fn open_database(
     {
         // Database already exists and contains data.
         full_sqlite::DatabaseOpen::Open(database) => {
-            if database.block_hash_by_number(0).unwrap().next().unwrap()
-                != genesis_chain_information
-                    .finalized_block_header
-                    .hash(chain_spec.block_number_bytes().into())
-            {
-                panic!("Mismatch between database and chain specification. Shutting down node.");
+            // The genesis block is missing from databases that have been filled through a
+            // warp sync.
+            match database.block_hash_by_number(0).unwrap().next() {
+                Some(hash)
+                    if hash
+                        != genesis_chain_information
+                            .finalized_block_header
+                            .hash(chain_spec.block_number_bytes().into()) =>
+                {
+                    panic!(
+                        "Mismatch between database and chain specification. Shutting down node."
+                    );
+                }
+                _ => {}
             }
 
             (database, true)
@@ -861,131 +1710,10 @@ async fn open_database(
 
             // The chain specification only contains trie nodes that have a storage value attached
             // to them, while the database needs to know all trie nodes (including branch nodes).
-            // The good news is that we can determine the latter from the former, which we do
-            // here.
-            // TODO: consider moving this block to the chain spec module
-            // TODO: poorly optimized
-            let mut trie_structure = {
-                let mut trie_structure = trie::trie_structure::TrieStructure::new();
-                for (key, value) in genesis_storage.iter() {
-                    match trie_structure.node(trie::bytes_to_nibbles(key.iter().copied())) {
-                        trie::trie_structure::Entry::Vacant(e) => {
-                            e.insert_storage_value().insert(
-                                (Some(value), None::<trie::trie_node::MerkleValueOutput>),
-                                (None, None),
-                            );
-                        }
-                        trie::trie_structure::Entry::Occupied(
-                            trie::trie_structure::NodeAccess::Branch(mut e),
-                        ) => {
-                            *e.user_data() = (Some(value), None);
-                            e.insert_storage_value();
-                        }
-                        trie::trie_structure::Entry::Occupied(
-                            trie::trie_structure::NodeAccess::Storage(_),
-                        ) => {
-                            // Duplicate entry.
-                            panic!() // TODO: don't panic?
-                        }
-                    }
-                }
-
-                // Calculate the Merkle values of the nodes.
-                for node_index in trie_structure
-                    .iter_ordered()
-                    .collect::<Vec<_>>()
-                    .into_iter()
-                    .rev()
-                {
-                    let mut node_access = trie_structure.node_by_index(node_index).unwrap();
-
-                    let children = core::array::from_fn::<_, 16, _>(|n| {
-                        node_access
-                            .child(trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap())
-                            .map(|mut child| child.user_data().1.as_ref().unwrap().clone())
-                    });
-
-                    let is_root_node = node_access.is_root_node();
-                    let partial_key = node_access.partial_key().collect::<Vec<_>>().into_iter();
-
-                    // We have to hash the storage value ahead of time if necessary due to borrow
-                    // checking difficulties.
-                    let storage_value_hashed =
-                        match (node_access.user_data().0.as_ref(), state_version) {
-                            (Some(v), 1) => {
-                                if v.len() >= 33 {
-                                    Some(blake2_rfc::blake2b::blake2b(32, &[], v))
-                                } else {
-                                    None
-                                }
-                            }
-                            _ => None,
-                        };
-                    let storage_value = match (
-                        node_access.user_data().0.as_ref(),
-                        storage_value_hashed.as_ref(),
-                    ) {
-                        (_, Some(storage_value_hashed)) => trie::trie_node::StorageValue::Hashed(
-                            <&[u8; 32]>::try_from(storage_value_hashed.as_bytes()).unwrap(),
-                        ),
-                        (Some(v), None) => trie::trie_node::StorageValue::Unhashed(&v[..]),
-                        (None, _) => trie::trie_node::StorageValue::None,
-                    };
-
-                    let merkle_value = trie::trie_node::calculate_merkle_value(
-                        trie::trie_node::Decoded {
-                            children,
-                            partial_key,
-                            storage_value,
-                        },
-                        trie::HashFunction::Blake2,
-                        is_root_node,
-                    )
-                    .unwrap();
-
-                    node_access.into_user_data().1 = Some(merkle_value);
-                }
-
-                trie_structure
-            };
-
-            // Build the iterator of trie nodes.
-            let genesis_storage_full_trie = trie_structure
-                .iter_unordered()
-                .collect::<Vec<_>>()
-                .into_iter()
-                .map(|node_index| {
-                    let (storage_value, Some(merkle_value)) = &trie_structure[node_index] else {
-                        unreachable!()
-                    };
-                    // Cloning to solve borrow checker restriction. // TODO: optimize?
-                    let storage_value = if let Some(storage_value) = storage_value {
-                        // TODO: child tries support?
-                        full_sqlite::InsertTrieNodeStorageValue::Value {
-                            value: Cow::Owned(storage_value.to_vec()),
-                            references_merkle_value: false,
-                        }
-                    } else {
-                        full_sqlite::InsertTrieNodeStorageValue::NoValue
-                    };
-                    let merkle_value = merkle_value.as_ref().to_owned();
-                    let mut node_access = trie_structure.node_by_index(node_index).unwrap();
-
-                    full_sqlite::InsertTrieNode {
-                        storage_value,
-                        merkle_value: Cow::Owned(merkle_value),
-                        children_merkle_values: array::from_fn::<_, 16, _>(|n| {
-                            let child_index =
-                                trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap();
-                            node_access.child(child_index).map(|mut child| {
-                                Cow::Owned(child.user_data().1.as_ref().unwrap().as_ref().to_vec())
-                            })
-                        }),
-                        partial_key_nibbles: Cow::Owned(
-                            node_access.partial_key().map(u8::from).collect::<Vec<_>>(),
-                        ),
-                    }
-                });
+            // The good news is that we can determine the latter from the former.
+            // TODO: child tries support?
+            let (genesis_storage_full_trie, _) =
+                build_trie_nodes(genesis_storage.iter(), state_version, false);
 
             // The finalized block is the genesis block. As such, it has an empty body and
             // no justification.
@@ -994,7 +1722,7 @@ async fn open_database(
                     genesis_chain_information,
                     iter::empty(),
                     None,
-                    genesis_storage_full_trie,
+                    genesis_storage_full_trie.into_iter(),
                     state_version,
                 )
                 .unwrap();
@@ -1002,3 +1730,205 @@ async fn open_database(
         }
     }
 }
+
+/// Periodically removes from the database the storage, bodies, and justifications that are no
+/// longer needed.
+///
+/// The pruning is performed in small batches in order to not block the database for too long.
+/// Stops once the database has been destroyed.
+async fn pruning_task(
+    database: Weak<database_thread::DatabaseThread>,
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+) {
+    loop {
+        smol::Timer::after(Duration::from_secs(30)).await;
+
+        let mut num_pruned_blocks = 0;
+        let mut num_removed_trie_nodes = 0;
+        loop {
+            let Some(database) = database.upgrade() else {
+                return;
+            };
+
+            let outcome = database
+                .with_database(|db| {
+                    Ok::<_, full_sqlite::CorruptedError>((
+                        db.prune_blocks(256)?,
+                        db.prune_state(1024)?,
+                    ))
+                })
+                .await;
+
+            match outcome {
+                Ok((blocks_outcome, state_outcome)) => {
+                    num_pruned_blocks += blocks_outcome.num_pruned_blocks;
+                    num_removed_trie_nodes += state_outcome.num_removed_trie_nodes;
+                    if blocks_outcome.finished && state_outcome.finished {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    log_callback.log(LogLevel::Error, format!("pruning-error; error={}", err));
+                    return;
+                }
+            }
+        }
+
+        if num_pruned_blocks != 0 || num_removed_trie_nodes != 0 {
+            log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "database-pruned; num_pruned_blocks={}; num_removed_trie_nodes={}",
+                    num_pruned_blocks, num_removed_trie_nodes
+                ),
+            );
+        }
+    }
+}
+
+/// Builds the list of all the nodes of the trie containing the given storage entries, including
+/// branch nodes, in order to insert them in the database.
+///
+/// If `references_child_tries` is `true`, the values of the entries whose key starts with
+/// `:child_storage:` are considered as being the Merkle value of the root of a child trie.
+///
+/// Also returns the Merkle value of the root node of the trie, or `None` if the trie is empty.
+///
+/// # Panic
+///
+/// Panics if the same key is found multiple times.
+// TODO: consider moving this function to the chain spec module
+// TODO: poorly optimized
+fn build_trie_nodes<'a>(
+    entries: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+    state_version: u8,
+    references_child_tries: bool,
+) -> (
+    Vec<full_sqlite::InsertTrieNode<'static>>,
+    Option<trie::trie_node::MerkleValueOutput>,
+) {
+    // The storage entries only correspond to trie nodes that have a storage value attached to
+    // them. The good news is that we can determine the list of all the trie nodes from these
+    // entries, which we do here.
+    let mut trie_structure = trie::trie_structure::TrieStructure::new();
+    for (key, value) in entries {
+        match trie_structure.node(trie::bytes_to_nibbles(key.iter().copied())) {
+            trie::trie_structure::Entry::Vacant(e) => {
+                e.insert_storage_value().insert(
+                    (Some(value), None::<trie::trie_node::MerkleValueOutput>),
+                    (None, None),
+                );
+            }
+            trie::trie_structure::Entry::Occupied(trie::trie_structure::NodeAccess::Branch(
+                mut e,
+            )) => {
+                *e.user_data() = (Some(value), None);
+                e.insert_storage_value();
+            }
+            trie::trie_structure::Entry::Occupied(trie::trie_structure::NodeAccess::Storage(_)) => {
+                // Duplicate entry.
+                panic!() // TODO: don't panic?
+            }
+        }
+    }
+
+    // Calculate the Merkle values of the nodes.
+    for node_index in trie_structure
+        .iter_ordered()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        let mut node_access = trie_structure.node_by_index(node_index).unwrap();
+
+        let children = core::array::from_fn::<_, 16, _>(|n| {
+            node_access
+                .child(trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap())
+                .map(|mut child| child.user_data().1.as_ref().unwrap().clone())
+        });
+
+        let is_root_node = node_access.is_root_node();
+        let partial_key = node_access.partial_key().collect::<Vec<_>>().into_iter();
+
+        // We have to hash the storage value ahead of time if necessary due to borrow
+        // checking difficulties.
+        let storage_value_hashed = match (node_access.user_data().0.as_ref(), state_version) {
+            (Some(v), 1) => {
+                if v.len() >= 33 {
+                    Some(blake2_rfc::blake2b::blake2b(32, &[], v))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let storage_value = match (
+            node_access.user_data().0.as_ref(),
+            storage_value_hashed.as_ref(),
+        ) {
+            (_, Some(storage_value_hashed)) => trie::trie_node::StorageValue::Hashed(
+                <&[u8; 32]>::try_from(storage_value_hashed.as_bytes()).unwrap(),
+            ),
+            (Some(v), None) => trie::trie_node::StorageValue::Unhashed(&v[..]),
+            (None, _) => trie::trie_node::StorageValue::None,
+        };
+
+        let merkle_value = trie::trie_node::calculate_merkle_value(
+            trie::trie_node::Decoded {
+                children,
+                partial_key,
+                storage_value,
+            },
+            trie::HashFunction::Blake2,
+            is_root_node,
+        )
+        .unwrap();
+
+        node_access.into_user_data().1 = Some(merkle_value);
+    }
+
+    let root_merkle_value = trie_structure
+        .root_user_data()
+        .map(|(_, merkle_value)| merkle_value.as_ref().unwrap().clone());
+
+    // Build the list of trie nodes.
+    let trie_nodes = trie_structure
+        .iter_unordered()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|node_index| {
+            let mut node_access = trie_structure.node_by_index(node_index).unwrap();
+
+            let key = trie::nibbles_to_bytes_truncate(node_access.full_key()).collect::<Vec<_>>();
+            let (storage_value, Some(merkle_value)) = node_access.user_data() else {
+                unreachable!()
+            };
+            let storage_value = if let Some(storage_value) = storage_value {
+                full_sqlite::InsertTrieNodeStorageValue::Value {
+                    value: Cow::Owned(storage_value.to_vec()),
+                    references_merkle_value: references_child_tries
+                        && key.starts_with(b":child_storage:"),
+                }
+            } else {
+                full_sqlite::InsertTrieNodeStorageValue::NoValue
+            };
+            let merkle_value = merkle_value.as_ref().to_owned();
+
+            full_sqlite::InsertTrieNode {
+                storage_value,
+                merkle_value: Cow::Owned(merkle_value),
+                children_merkle_values: array::from_fn::<_, 16, _>(|n| {
+                    let child_index = trie::Nibble::try_from(u8::try_from(n).unwrap()).unwrap();
+                    node_access.child(child_index).map(|mut child| {
+                        Cow::Owned(child.user_data().1.as_ref().unwrap().as_ref().to_vec())
+                    })
+                }),
+                partial_key_nibbles: Cow::Owned(
+                    node_access.partial_key().map(u8::from).collect::<Vec<_>>(),
+                ),
+            }
+        })
+        .collect();
+
+    (trie_nodes, root_merkle_value)
+}
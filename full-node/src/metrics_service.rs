@@ -0,0 +1,634 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Prometheus integration.
+//!
+//! The [`MetricsService`] holds the values of the metrics of the node. The other services are
+//! given handles to the metrics that concern them (see for example [`ConsensusMetrics`]) and
+//! update them as they run.
+//!
+//! If [`Config::listen_address`] is `Some`, the metrics are served over HTTP at the `/metrics`
+//! path in the OpenMetrics text format, so that they can be scraped by Prometheus.
+//!
+//! See <https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md>
+//! for the format.
+
+use crate::{http, LogCallback, LogLevel};
+
+use futures_util::{stream, StreamExt as _};
+use smol::{
+    future,
+    net::{TcpListener, TcpStream},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Maximum number of HTTP connections that are served simultaneously.
+const MAX_CONNECTIONS: usize = 8;
+
+/// Configuration for a [`MetricsService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(Pin<Box<dyn Future<Output = ()> + Send>>),
+
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Address to bind the HTTP server to.
+    ///
+    /// If this is `None`, the service will still be created and the metrics still updated, but
+    /// they are not served.
+    pub listen_address: Option<SocketAddr>,
+}
+
+pub struct MetricsService {
+    /// Values of all the metrics. Shared with the HTTP server.
+    registry: Arc<Registry>,
+
+    /// Address the HTTP server is listening on, if any.
+    listen_addr: Option<SocketAddr>,
+
+    /// Notified when the service is destroyed.
+    shutdown_notify: event_listener::Event,
+}
+
+impl MetricsService {
+    pub async fn new(config: Config<'_>) -> Result<Arc<Self>, InitError> {
+        let registry = Arc::new(Registry {
+            families: Mutex::new(BTreeMap::new()),
+        });
+
+        let shutdown_notify = event_listener::Event::new();
+
+        let listen_addr = if let Some(listen_address) = config.listen_address {
+            let tcp_listener = TcpListener::bind(listen_address).await.map_err(|error| {
+                InitError::ListenError {
+                    bind_address: listen_address,
+                    error,
+                }
+            })?;
+            let listen_addr =
+                tcp_listener
+                    .local_addr()
+                    .map_err(|error| InitError::ListenError {
+                        bind_address: listen_address,
+                        error,
+                    })?;
+
+            let mut on_shutdown = shutdown_notify.listen();
+            let registry = registry.clone();
+            let log_callback = config.log_callback.clone();
+
+            // Spawn a background task that accepts incoming connections and serves them.
+            (config.tasks_executor)(Box::pin(async move {
+                let mut connections = stream::FuturesUnordered::new();
+
+                loop {
+                    enum WakeUpReason {
+                        Shutdown,
+                        ConnectionFinished,
+                        Accept(io::Result<(TcpStream, SocketAddr)>),
+                    }
+
+                    let wake_up_reason = future::or(
+                        future::or(
+                            async {
+                                (&mut on_shutdown).await;
+                                WakeUpReason::Shutdown
+                            },
+                            async {
+                                if connections.is_empty() {
+                                    future::pending::<()>().await;
+                                }
+                                connections.next().await;
+                                WakeUpReason::ConnectionFinished
+                            },
+                        ),
+                        async { WakeUpReason::Accept(tcp_listener.accept().await) },
+                    )
+                    .await;
+
+                    match wake_up_reason {
+                        WakeUpReason::Shutdown => break,
+                        WakeUpReason::ConnectionFinished => {}
+                        WakeUpReason::Accept(Ok((socket, _))) => {
+                            // Connections above the limit are simply dropped.
+                            if connections.len() < MAX_CONNECTIONS {
+                                connections.push(serve_connection(&registry, socket));
+                            }
+                        }
+                        WakeUpReason::Accept(Err(error)) => {
+                            // Failing to accept an incoming TCP connection generally happens
+                            // due to the limit of file descriptors being reached.
+                            // Sleep a little bit and try again.
+                            log_callback.log(
+                                LogLevel::Warn,
+                                format!("metrics-tcp-listener-error; error={error}"),
+                            );
+                            smol::Timer::after(Duration::from_millis(50)).await;
+                        }
+                    }
+                }
+            }));
+
+            Some(listen_addr)
+        } else {
+            None
+        };
+
+        Ok(Arc::new(MetricsService {
+            registry,
+            listen_addr,
+            shutdown_notify,
+        }))
+    }
+
+    /// Returns the address the HTTP server is listening on.
+    ///
+    /// Returns `None` if and only if [`Config::listen_address`] was `None`. However, if `Some`,
+    /// the address is not necessarily equal to the one in [`Config::listen_address`].
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.listen_addr
+    }
+
+    /// Returns the metrics of the consensus service of the given chain.
+    pub fn consensus_metrics(&self, chain: &str) -> ConsensusMetrics {
+        let labels = [("chain", chain)];
+        ConsensusMetrics {
+            best_block_height: self.registry.gauge(
+                "smoldot_best_block_height",
+                "Height of the current best block.",
+                &labels,
+            ),
+            finalized_block_height: self.registry.gauge(
+                "smoldot_finalized_block_height",
+                "Height of the current finalized block.",
+                &labels,
+            ),
+            blocks_verified: self.registry.counter(
+                "smoldot_blocks_verified",
+                "Number of blocks that have been successfully verified.",
+                &labels,
+            ),
+            block_verification_duration: self.registry.histogram(
+                "smoldot_block_verification_duration_seconds",
+                "Time it took to verify a block, including the database accesses.",
+                &labels,
+                &[
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+                ],
+            ),
+        }
+    }
+
+    /// Returns the metrics of the network service.
+    pub fn network_metrics(&self) -> NetworkMetrics {
+        NetworkMetrics {
+            registry: self.registry.clone(),
+            connections: self.registry.gauge(
+                "smoldot_network_connections",
+                "Number of connections, both handshaking or established, both incoming and \
+                 outgoing.",
+                &[],
+            ),
+            bytes_received: self.registry.counter(
+                "smoldot_network_received_bytes",
+                "Number of bytes received on all the connections.",
+                &[],
+            ),
+            bytes_sent: self.registry.counter(
+                "smoldot_network_sent_bytes",
+                "Number of bytes sent on all the connections.",
+                &[],
+            ),
+        }
+    }
+
+    /// Returns the metrics of the database of the given chain.
+    pub fn database_metrics(&self, chain: &str) -> DatabaseMetrics {
+        let labels = [("chain", chain)];
+        DatabaseMetrics {
+            queue_length: self.registry.gauge(
+                "smoldot_database_queue_length",
+                "Number of operations waiting to be executed by the database thread.",
+                &labels,
+            ),
+            cache_hits: self.registry.counter(
+                "smoldot_database_cache_hits",
+                "Number of times a page has been found in the SQLite cache.",
+                &labels,
+            ),
+            cache_misses: self.registry.counter(
+                "smoldot_database_cache_misses",
+                "Number of times a page hasn't been found in the SQLite cache.",
+                &labels,
+            ),
+        }
+    }
+
+    /// Returns the metrics of the JSON-RPC service of the given chain.
+    pub fn json_rpc_metrics(&self, chain: &str) -> JsonRpcMetrics {
+        JsonRpcMetrics {
+            registry: self.registry.clone(),
+            chain: chain.to_owned(),
+            clients: self.registry.gauge(
+                "smoldot_json_rpc_clients",
+                "Number of clients connected to the JSON-RPC server.",
+                &[("chain", chain)],
+            ),
+        }
+    }
+}
+
+impl Drop for MetricsService {
+    fn drop(&mut self) {
+        self.shutdown_notify.notify(usize::MAX);
+    }
+}
+
+/// Error potentially returned by [`MetricsService::new`].
+#[derive(Debug, derive_more::Display)]
+pub enum InitError {
+    /// Failed to listen on the server address.
+    #[display(fmt = "Failed to listen on TCP address {bind_address}: {error}")]
+    ListenError {
+        /// Address that was attempted.
+        bind_address: SocketAddr,
+        /// Error returned by the operating system.
+        error: io::Error,
+    },
+}
+
+/// See [`MetricsService::consensus_metrics`].
+pub struct ConsensusMetrics {
+    pub best_block_height: Arc<Gauge>,
+    pub finalized_block_height: Arc<Gauge>,
+    pub blocks_verified: Arc<Counter>,
+    pub block_verification_duration: Arc<Histogram>,
+}
+
+/// See [`MetricsService::network_metrics`].
+pub struct NetworkMetrics {
+    registry: Arc<Registry>,
+    pub connections: Arc<Gauge>,
+    pub bytes_received: Arc<Counter>,
+    pub bytes_sent: Arc<Counter>,
+}
+
+impl NetworkMetrics {
+    /// Returns the gauge containing the number of peers of the given chain.
+    pub fn peers(&self, chain: &str) -> Arc<Gauge> {
+        self.registry.gauge(
+            "smoldot_network_peers",
+            "Number of peers we have a gossip substream with.",
+            &[("chain", chain)],
+        )
+    }
+
+    /// Returns the counter of the requests of the given protocol that have finished with the
+    /// given outcome.
+    pub fn requests(&self, protocol: &str, success: bool) -> Arc<Counter> {
+        self.registry.counter(
+            "smoldot_network_requests",
+            "Number of outgoing requests that have finished.",
+            &[
+                ("protocol", protocol),
+                ("outcome", if success { "success" } else { "failure" }),
+            ],
+        )
+    }
+}
+
+/// See [`MetricsService::database_metrics`].
+pub struct DatabaseMetrics {
+    pub queue_length: Arc<Gauge>,
+    pub cache_hits: Arc<Counter>,
+    pub cache_misses: Arc<Counter>,
+}
+
+/// See [`MetricsService::json_rpc_metrics`].
+pub struct JsonRpcMetrics {
+    registry: Arc<Registry>,
+    chain: String,
+    pub clients: Arc<Gauge>,
+}
+
+impl JsonRpcMetrics {
+    /// Increments the number of requests of the given JSON-RPC method.
+    pub fn request(&self, method: &str) {
+        self.registry
+            .counter(
+                "smoldot_json_rpc_requests",
+                "Number of JSON-RPC requests received, including subscriptions.",
+                &[("chain", &self.chain), ("method", method)],
+            )
+            .inc();
+    }
+}
+
+/// Metric whose value only ever increases.
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    /// Increases the value by one.
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    /// Increases the value by the given amount.
+    pub fn inc_by(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Overwrites the value. Used when the counting is done by something else than the node,
+    /// in which case the value passed must never be inferior to the previous one.
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Metric whose value can go up and down.
+#[derive(Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    /// Overwrites the value.
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+}
+
+/// Metric that counts observed values in buckets.
+pub struct Histogram {
+    /// Upper bounds of the buckets, in increasing order. The `+Inf` bucket is implicit.
+    buckets: &'static [f64],
+    /// Number of observed values, total of the observed values, and number of observed values
+    /// for each bucket in [`Histogram::buckets`]. The bucket values aren't cumulative.
+    values: Mutex<(u64, f64, Vec<u64>)>,
+}
+
+impl Histogram {
+    /// Adds a value to the histogram.
+    pub fn observe(&self, value: f64) {
+        let mut values = self.values.lock().unwrap();
+        values.0 += 1;
+        values.1 += value;
+        if let Some(bucket) = self.buckets.iter().position(|b| value <= *b) {
+            values.2[bucket] += 1;
+        }
+    }
+}
+
+/// Collection of all the metrics.
+struct Registry {
+    /// List of metrics, indexed by name.
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+/// Group of metrics with the same name but different labels.
+struct Family {
+    /// Description of the metric.
+    help: &'static str,
+    /// Value of each metric, indexed by its labels.
+    metrics: BTreeMap<Vec<(&'static str, String)>, Metric>,
+}
+
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+impl Registry {
+    /// Returns the counter with the given name and labels, creating it if necessary.
+    fn counter(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+    ) -> Arc<Counter> {
+        match self.metric(name, help, labels, || Metric::Counter(Default::default())) {
+            Metric::Counter(counter) => counter,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the gauge with the given name and labels, creating it if necessary.
+    fn gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+    ) -> Arc<Gauge> {
+        match self.metric(name, help, labels, || Metric::Gauge(Default::default())) {
+            Metric::Gauge(gauge) => gauge,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the histogram with the given name and labels, creating it if necessary.
+    fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        buckets: &'static [f64],
+    ) -> Arc<Histogram> {
+        match self.metric(name, help, labels, || {
+            Metric::Histogram(Arc::new(Histogram {
+                buckets,
+                values: Mutex::new((0, 0.0, vec![0; buckets.len()])),
+            }))
+        }) {
+            Metric::Histogram(histogram) => histogram,
+            _ => unreachable!(),
+        }
+    }
+
+    fn metric(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        new: impl FnOnce() -> Metric,
+    ) -> Metric {
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(name).or_insert_with(|| Family {
+            help,
+            metrics: BTreeMap::new(),
+        });
+
+        let labels = labels
+            .iter()
+            .map(|(name, value)| (*name, String::from(*value)))
+            .collect::<Vec<_>>();
+        match family.metrics.entry(labels).or_insert_with(new) {
+            Metric::Counter(counter) => Metric::Counter(counter.clone()),
+            Metric::Gauge(gauge) => Metric::Gauge(gauge.clone()),
+            Metric::Histogram(histogram) => Metric::Histogram(histogram.clone()),
+        }
+    }
+
+    /// Builds the list of all metrics in the OpenMetrics text format.
+    fn encode(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::with_capacity(4096);
+
+        for (name, family) in families.iter() {
+            let Some(first) = family.metrics.values().next() else {
+                continue;
+            };
+            let kind = match first {
+                Metric::Counter(_) => "counter",
+                Metric::Gauge(_) => "gauge",
+                Metric::Histogram(_) => "histogram",
+            };
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "# HELP {name} {}", family.help);
+
+            for (labels, metric) in &family.metrics {
+                match metric {
+                    Metric::Counter(counter) => {
+                        let _ = writeln!(
+                            out,
+                            "{name}_total{} {}",
+                            encode_labels(labels, None),
+                            counter.0.load(Ordering::Relaxed)
+                        );
+                    }
+                    Metric::Gauge(gauge) => {
+                        let _ = writeln!(
+                            out,
+                            "{name}{} {}",
+                            encode_labels(labels, None),
+                            gauge.0.load(Ordering::Relaxed)
+                        );
+                    }
+                    Metric::Histogram(histogram) => {
+                        let (count, sum, buckets) = histogram.values.lock().unwrap().clone();
+                        let mut cumulated = 0;
+                        for (bound, bucket_count) in histogram.buckets.iter().zip(buckets) {
+                            cumulated += bucket_count;
+                            let _ = writeln!(
+                                out,
+                                "{name}_bucket{} {cumulated}",
+                                encode_labels(labels, Some(&format!("{bound:?}")))
+                            );
+                        }
+                        let _ = writeln!(
+                            out,
+                            "{name}_bucket{} {count}",
+                            encode_labels(labels, Some("+Inf"))
+                        );
+                        let _ =
+                            writeln!(out, "{name}_count{} {count}", encode_labels(labels, None));
+                        let _ = writeln!(out, "{name}_sum{} {sum:?}", encode_labels(labels, None));
+                    }
+                }
+            }
+        }
+
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+/// Builds the labels part of a metric line, optionally with an additional `le` label.
+fn encode_labels(labels: &[(&'static str, String)], le: Option<&str>) -> String {
+    if labels.is_empty() && le.is_none() {
+        return String::new();
+    }
+
+    let mut out = String::from("{");
+    for (name, value) in labels
+        .iter()
+        .map(|(n, v)| (*n, &v[..]))
+        .chain(le.map(|le| ("le", le)))
+    {
+        if out.len() > 1 {
+            out.push(',');
+        }
+        out.push_str(name);
+        out.push_str("=\"");
+        for c in value.chars() {
+            match c {
+                '\\' => out.push_str("\\\\"),
+                '"' => out.push_str("\\\""),
+                '\n' => out.push_str("\\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    out.push('}');
+    out
+}
+
+/// Answers the HTTP requests of the given connection until it is closed.
+async fn serve_connection(registry: &Registry, socket: TcpStream) -> Result<(), http::Error> {
+    let mut connection = http::Connection::new(socket);
+
+    while let Some(head) = connection.read_request_head().await? {
+        // Requests to this server never have a body.
+        connection
+            .read_body(&head, 0)
+            .await
+            .map_err(http::Error::Io)?;
+
+        if head.method != "GET" {
+            connection
+                .send_response(http::Status::MethodNotAllowed, &[("Allow", "GET")], &[])
+                .await
+                .map_err(http::Error::Io)?;
+            continue;
+        }
+
+        if head.path != "/metrics" {
+            connection
+                .send_response(http::Status::NotFound, &[], &[])
+                .await
+                .map_err(http::Error::Io)?;
+            continue;
+        }
+
+        connection
+            .send_response(
+                http::Status::Ok,
+                &[(
+                    "Content-Type",
+                    "application/openmetrics-text; version=1.0.0; charset=utf-8",
+                )],
+                registry.encode().as_bytes(),
+            )
+            .await
+            .map_err(http::Error::Io)?;
+    }
+
+    Ok(())
+}
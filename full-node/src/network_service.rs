@@ -27,9 +27,11 @@
 // TODO: doc
 // TODO: re-review this once finished
 
-use crate::{database_thread, jaeger_service, LogCallback, LogLevel};
+use crate::{database_thread, jaeger_service, metrics_service, LogCallback, LogLevel};
 
-use core::{cmp, future::Future, mem, pin::Pin, task::Poll, time::Duration};
+use core::{
+    cmp, fmt, future::Future, iter, mem, num::NonZeroUsize, pin::Pin, task::Poll, time::Duration,
+};
 use futures_channel::oneshot;
 use futures_lite::FutureExt as _;
 use hashbrown::HashMap;
@@ -41,7 +43,7 @@ use smol::{
 };
 use smoldot::{
     database::full_sqlite,
-    header,
+    executor, header,
     informant::HashDisplay,
     libp2p::{
         connection,
@@ -49,8 +51,10 @@ use smoldot::{
         peer_id::{self, PeerId},
     },
     network::{basic_peering_strategy, codec, service},
+    trie::{self, proof_encode},
 };
 use std::{
+    collections::VecDeque,
     io,
     net::{IpAddr, SocketAddr},
     sync::Arc,
@@ -61,6 +65,18 @@ pub use smoldot::network::service::ChainId;
 
 mod tasks;
 
+/// Maximum size, in bytes, of the fragments of a response to a GrandPa warp sync request. The
+/// response might exceed this size by one fragment. Same value as in Substrate.
+const MAX_GRANDPA_WARP_SYNC_RESPONSE_SIZE: usize = 8 * 1024 * 1024;
+
+/// Maximum size, in bytes, of the keys and values of a response to a state request. The
+/// response might exceed this size by one storage entry. Same value as in Substrate.
+const MAX_STATE_RESPONSE_SIZE: usize = 2 * 1024 * 1024;
+
+/// Maximum number of state, storage proof, and call proof requests from other nodes that are
+/// being answered at the same time. Requests beyond this limit are refused.
+const MAX_INBOUND_PROOF_REQUESTS: usize = 16;
+
 /// Configuration for a [`NetworkService`].
 pub struct Config {
     /// Closure that spawns background tasks.
@@ -88,6 +104,9 @@ pub struct Config {
 
     /// Service to use to report traces.
     pub jaeger_service: Arc<jaeger_service::JaegerService>,
+
+    /// Metrics to update.
+    pub metrics: metrics_service::NetworkMetrics,
 }
 
 /// Configuration for one chain.
@@ -119,6 +138,10 @@ pub struct ChainConfig {
     /// Must be `Some` if and only if the chain uses the GrandPa networking protocol. Contains the
     /// number of the finalized block at the time of the initialization.
     pub grandpa_protocol_finalized_block_height: Option<u64>,
+
+    /// If `true`, the storage proof and call proof requests of other nodes, typically light
+    /// clients, are answered. If `false`, they are refused.
+    pub allow_inbound_storage_and_call_proof_requests: bool,
 }
 
 /// Event generated by the events reporters returned by [`NetworkService::new`].
@@ -127,6 +150,7 @@ pub enum Event {
     Connected {
         chain_id: ChainId,
         peer_id: PeerId,
+        role: codec::Role,
         best_block_number: u64,
         best_block_hash: [u8; 32],
     },
@@ -140,6 +164,26 @@ pub enum Event {
         scale_encoded_header: Vec<u8>,
         is_best: bool,
     },
+    GrandpaNeighborPacket {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        state: service::GrandpaState,
+    },
+    GrandpaVote {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        message: service::EncodedGrandpaVoteMessage,
+    },
+    GrandpaCatchUpRequest {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        request: codec::CatchUpRequest,
+    },
+    GrandpaCatchUp {
+        chain_id: ChainId,
+        peer_id: PeerId,
+        message: service::EncodedGrandpaCatchUpMessage,
+    },
 }
 
 pub struct NetworkService {
@@ -187,12 +231,68 @@ enum ToBackground {
         best_hash: [u8; 32],
         best_number: u64,
     },
+    ForegroundBanAndDisconnect {
+        peer_id: PeerId,
+        chain_id: ChainId,
+        ban_duration: Duration,
+        reason: &'static str,
+    },
+    ForegroundAnnounceTransaction {
+        chain_id: ChainId,
+        transaction: Vec<u8>,
+        result_tx: oneshot::Sender<Vec<PeerId>>,
+    },
+    ForegroundSendGrandpaMessage {
+        target: PeerId,
+        chain_id: ChainId,
+        message: Vec<u8>,
+        result_tx: oneshot::Sender<Result<(), service::QueueNotificationError>>,
+    },
+    ForegroundBroadcastGrandpaMessage {
+        chain_id: ChainId,
+        message: Vec<u8>,
+    },
+    ForegroundSetGrandpaState {
+        chain_id: ChainId,
+        state: service::GrandpaState,
+    },
     ForegroundBlocksRequest {
         target: PeerId,
         chain_id: ChainId,
         config: codec::BlocksRequestConfig,
         result_tx: oneshot::Sender<Result<Vec<codec::BlockData>, BlocksRequestError>>,
     },
+    ForegroundGrandpaWarpSyncRequest {
+        target: PeerId,
+        chain_id: ChainId,
+        begin_hash: [u8; 32],
+        result_tx: oneshot::Sender<
+            Result<service::EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError>,
+        >,
+    },
+    ForegroundStorageProofRequest {
+        target: PeerId,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        keys: Vec<Vec<u8>>,
+        result_tx: oneshot::Sender<Result<service::EncodedMerkleProof, StorageProofRequestError>>,
+    },
+    ForegroundCallProofRequest {
+        target: PeerId,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        function_name: String,
+        parameter: Vec<u8>,
+        result_tx: oneshot::Sender<Result<service::EncodedMerkleProof, CallProofRequestError>>,
+    },
+    ForegroundStateRequest {
+        target: PeerId,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        child_trie: Option<Vec<u8>>,
+        start_key: Vec<u8>,
+        result_tx: oneshot::Sender<Result<service::EncodedStateResponse, StateRequestError>>,
+    },
     ForegroundGetNumConnections {
         result_tx: oneshot::Sender<usize>,
     },
@@ -204,6 +304,18 @@ enum ToBackground {
         result_tx: oneshot::Sender<usize>,
     },
     ForegroundShutdown,
+    InboundStorageProofResponse {
+        substream_id: service::SubstreamId,
+        response: Result<Option<Vec<u8>>, full_sqlite::CorruptedError>,
+    },
+    InboundCallProofResponse {
+        substream_id: service::SubstreamId,
+        response: Result<Option<Vec<u8>>, full_sqlite::CorruptedError>,
+    },
+    InboundStateResponse {
+        substream_id: service::SubstreamId,
+        response: Result<Option<StateRequestResponse>, full_sqlite::CorruptedError>,
+    },
 }
 struct Inner {
     /// Value provided through [`Config::identify_agent_version`].
@@ -227,6 +339,9 @@ struct Inner {
     /// Service to use to report traces.
     jaeger_service: Arc<jaeger_service::JaegerService>,
 
+    /// See [`Config::metrics`].
+    metrics: Arc<metrics_service::NetworkMetrics>,
+
     /// Data structure holding the entire state of the networking.
     network:
         service::ChainNetwork<Chain, channel::Sender<service::CoordinatorToConnection>, Instant>,
@@ -248,6 +363,10 @@ struct Inner {
 
     process_network_service_events: bool,
 
+    /// Events that haven't been generated by [`Inner::network`] and that must be dispatched
+    /// before processing any other event.
+    pending_events: VecDeque<Event>,
+
     /// Channel for the various tasks to send messages to the background task.
     to_background_rx: channel::Receiver<ToBackground>,
 
@@ -261,8 +380,43 @@ struct Inner {
         fnv::FnvBuildHasher,
     >,
 
+    /// List of all GrandPa warp sync requests that have been started but not finished yet.
+    grandpa_warp_sync_requests: HashMap<
+        service::SubstreamId,
+        oneshot::Sender<
+            Result<service::EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError>,
+        >,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all storage proof requests that have been started but not finished yet.
+    storage_proof_requests: HashMap<
+        service::SubstreamId,
+        oneshot::Sender<Result<service::EncodedMerkleProof, StorageProofRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all call proof requests that have been started but not finished yet.
+    call_proof_requests: HashMap<
+        service::SubstreamId,
+        oneshot::Sender<Result<service::EncodedMerkleProof, CallProofRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
+    /// List of all state requests that have been started but not finished yet.
+    state_requests: HashMap<
+        service::SubstreamId,
+        oneshot::Sender<Result<service::EncodedStateResponse, StateRequestError>>,
+        fnv::FnvBuildHasher,
+    >,
+
     /// List of Kademlia discovery operations that have been started but not finished yet.
     kademlia_find_nodes_requests: HashMap<service::SubstreamId, ChainId, fnv::FnvBuildHasher>,
+
+    /// List of state, storage proof, and call proof requests from other nodes whose response is
+    /// being built by a background task. Never contains more than [`MAX_INBOUND_PROOF_REQUESTS`]
+    /// elements.
+    inbound_proof_requests: hashbrown::HashSet<service::SubstreamId, fnv::FnvBuildHasher>,
 }
 
 /// Extra information of a chain.
@@ -272,6 +426,12 @@ struct Chain {
 
     /// How to access data to answer requests from the remotes.
     database: Arc<database_thread::DatabaseThread>,
+
+    /// Runtimes used to answer call proof requests, indexed by the hash of their code.
+    runtimes_cache: Arc<Mutex<lru::LruCache<[u8; 32], executor::host::HostVmPrototype>>>,
+
+    /// Metric containing the number of peers of this chain.
+    num_peers_metric: Arc<metrics_service::Gauge>,
 }
 
 impl NetworkService {
@@ -325,9 +485,19 @@ impl NetworkService {
                         },
                     ),
                     allow_inbound_block_requests: true,
+                    allow_inbound_grandpa_warp_sync_requests: chain
+                        .grandpa_protocol_finalized_block_height
+                        .is_some(),
+                    allow_inbound_storage_and_call_proof_requests: chain
+                        .allow_inbound_storage_and_call_proof_requests,
+                    allow_inbound_state_requests: true,
                     user_data: Chain {
+                        num_peers_metric: config.metrics.peers(&chain.log_name),
                         log_name: chain.log_name.clone(),
                         database: chain.database,
+                        runtimes_cache: Arc::new(Mutex::new(lru::LruCache::new(
+                            NonZeroUsize::new(2).unwrap(),
+                        ))),
                     },
                 })
                 .unwrap(); // TODO: don't unwrap?
@@ -358,6 +528,7 @@ impl NetworkService {
             to_background_rx,
             to_background_tx: to_background_tx.clone(),
             process_network_service_events: true,
+            pending_events: VecDeque::new(),
             tasks_executor: config.tasks_executor,
             log_callback: config.log_callback.clone(),
             network,
@@ -367,11 +538,29 @@ impl NetworkService {
                 50, // TODO: ?
                 Default::default(),
             ),
+            grandpa_warp_sync_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                4,
+                Default::default(),
+            ),
+            storage_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                4,
+                Default::default(),
+            ),
+            call_proof_requests: hashbrown::HashMap::with_capacity_and_hasher(
+                4,
+                Default::default(),
+            ),
+            state_requests: hashbrown::HashMap::with_capacity_and_hasher(4, Default::default()),
             kademlia_find_nodes_requests: hashbrown::HashMap::with_capacity_and_hasher(
                 4,
                 Default::default(),
             ),
+            inbound_proof_requests: hashbrown::HashSet::with_capacity_and_hasher(
+                MAX_INBOUND_PROOF_REQUESTS,
+                Default::default(),
+            ),
             jaeger_service: config.jaeger_service.clone(),
+            metrics: Arc::new(config.metrics),
         };
 
         // For each listening address in the configuration, create a background task dedicated to
@@ -627,6 +816,32 @@ impl NetworkService {
             .await;
     }
 
+    /// Bans the given peer from the given chain for the given duration, and closes the gossip
+    /// link with this peer, if any.
+    ///
+    /// If a gossip link with this peer was open, an [`Event::Disconnected`] is generated.
+    ///
+    /// The `reason` is used only for logging purposes.
+    pub async fn ban_and_disconnect(
+        &self,
+        peer_id: PeerId,
+        chain_id: ChainId,
+        ban_duration: Duration,
+        reason: &'static str,
+    ) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundBanAndDisconnect {
+                peer_id,
+                chain_id,
+                ban_duration,
+                reason,
+            })
+            .await;
+    }
+
     pub async fn send_block_announce(
         self: Arc<Self>,
         target: PeerId,
@@ -652,6 +867,79 @@ impl NetworkService {
         result_rx.await.unwrap()
     }
 
+    /// Sends a transaction to all the peers we are connected to on the given chain.
+    ///
+    /// Returns the list of peers the transaction has been sent to.
+    pub async fn announce_transaction(
+        self: Arc<Self>,
+        chain_id: ChainId,
+        transaction: Vec<u8>,
+    ) -> Vec<PeerId> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundAnnounceTransaction {
+                chain_id,
+                transaction,
+                result_tx,
+            })
+            .await;
+
+        result_rx.await.unwrap()
+    }
+
+    /// Sends a SCALE-encoded GrandPa notification to the given peer.
+    pub async fn send_grandpa_message(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_id: ChainId,
+        message: Vec<u8>,
+    ) -> Result<(), service::QueueNotificationError> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundSendGrandpaMessage {
+                target,
+                chain_id,
+                message,
+                result_tx,
+            })
+            .await;
+
+        result_rx.await.unwrap()
+    }
+
+    /// Sends a SCALE-encoded GrandPa notification to all the peers we are connected to on the
+    /// given chain.
+    pub async fn broadcast_grandpa_message(&self, chain_id: ChainId, message: Vec<u8>) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundBroadcastGrandpaMessage { chain_id, message })
+            .await;
+    }
+
+    /// Updates the GrandPa state of the local node and sends a neighbor packet to all the peers
+    /// we are connected to on the given chain.
+    ///
+    /// The chain must have been configured with
+    /// [`ChainConfig::grandpa_protocol_finalized_block_height`].
+    pub async fn set_grandpa_state(&self, chain_id: ChainId, state: service::GrandpaState) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundSetGrandpaState { chain_id, state })
+            .await;
+    }
+
     /// Sends a blocks request to the given peer.
     // TODO: more docs
     // TODO: proper error type
@@ -753,86 +1041,347 @@ impl NetworkService {
 
         result
     }
-}
 
-impl Drop for NetworkService {
-    fn drop(&mut self) {
-        self.foreground_shutdown.notify(usize::max_value());
-    }
-}
+    /// Sends a GrandPa warp sync request to the given peer, asking for the proof of finality of
+    /// the blocks that follow the given block.
+    pub async fn grandpa_warp_sync_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_id: ChainId,
+        begin_hash: [u8; 32],
+    ) -> Result<service::EncodedGrandpaWarpSyncResponse, GrandpaWarpSyncRequestError> {
+        self.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "warp-sync-request-start; peer_id={}; chain={}; begin_hash={}",
+                target,
+                self.chain_names[&chain_id],
+                HashDisplay(&begin_hash)
+            ),
+        );
 
-/// Error when initializing the network service.
-#[derive(Debug, derive_more::Display)]
-pub enum InitError {
-    /// I/O error when initializing a listener.
-    #[display(fmt = "I/O error when creating listener for {_0}: {_1}")]
-    ListenerIo(Multiaddr, io::Error),
-    /// A listening address passed through the configuration isn't valid.
-    #[display(fmt = "A listening address passed through the configuration isn't valid: {_0}")]
-    BadListenMultiaddr(Multiaddr),
-}
+        let (result_tx, result_rx) = oneshot::channel();
 
-/// Error returned by [`NetworkService::blocks_request`].
-#[derive(Debug, derive_more::Display)]
-pub enum BlocksRequestError {
-    /// No established connection with the target.
-    NoConnection,
-    /// Error during the request.
-    #[display(fmt = "{_0}")]
-    Request(service::BlocksRequestError),
-}
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundGrandpaWarpSyncRequest {
+                target: target.clone(),
+                chain_id,
+                begin_hash,
+                result_tx,
+            })
+            .await;
 
-fn run(mut inner: Inner) {
-    // This function is a small hack because I didn't find a better way to store the executor
-    // within `Inner` while at the same time spawning the `Inner` using said executor.
-    let mut actual_executor = mem::replace(&mut inner.tasks_executor, Box::new(|_| unreachable!()));
-    let (tx, rx) = oneshot::channel();
-    actual_executor(Box::pin(async move {
-        let actual_executor = rx.await.unwrap();
-        inner.tasks_executor = actual_executor;
-        background_task(inner).await;
-    }));
-    tx.send(actual_executor).unwrap_or_else(|_| panic!());
-}
+        let result = result_rx.await.unwrap();
+        self.log_request_ended("warp-sync", &target, chain_id, result.as_ref().err());
+        result
+    }
 
-async fn background_task(mut inner: Inner) {
-    loop {
-        // Pull messages that the coordinator has generated in destination to the various
-        // connections.
-        while let Some((connection_id, message)) = inner.network.pull_message_to_connection() {
-            // Note that it is critical for the sending to not take too long here, in order to not
-            // block the process of the network service.
-            // In particular, if sending the message to the connection is blocked due to sending
-            // a message on the connection-to-coordinator channel, this will result in a deadlock.
-            // For this reason, the connection task is always ready to immediately accept a message
-            // on the coordinator-to-connection channel.
-            inner.network[connection_id].send(message).await.unwrap();
-        }
+    /// Sends a request to the given peer for a Merkle proof of the given storage keys.
+    pub async fn storage_proof_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        keys: Vec<Vec<u8>>,
+    ) -> Result<service::EncodedMerkleProof, StorageProofRequestError> {
+        self.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "storage-proof-request-start; peer_id={}; chain={}; block_hash={}; num_keys={}",
+                target,
+                self.chain_names[&chain_id],
+                HashDisplay(&block_hash),
+                keys.len()
+            ),
+        );
 
-        if inner.process_network_service_events && matches!(inner.event_senders, either::Left(_)) {
-            let event = loop {
-                let inner_event = match inner.network.next_event() {
-                    Some(ev) => ev,
-                    None => break None,
-                };
+        let (result_tx, result_rx) = oneshot::channel();
 
-                match inner_event {
-                    service::Event::HandshakeFinished {
-                        id,
-                        expected_peer_id,
-                        peer_id,
-                        ..
-                    } => {
-                        inner.num_pending_out_attempts -= 1;
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundStorageProofRequest {
+                target: target.clone(),
+                chain_id,
+                block_hash,
+                keys,
+                result_tx,
+            })
+            .await;
 
-                        let remote_addr = Multiaddr::from_bytes(
-                            inner.network.connection_remote_addr(id).to_owned(),
-                        )
-                        .unwrap(); // TODO: review this unwrap
-                        if let Some(expected_peer_id) =
-                            expected_peer_id.as_ref().filter(|p| **p != peer_id)
-                        {
-                            inner
+        let result = result_rx.await.unwrap();
+        self.log_request_ended("storage-proof", &target, chain_id, result.as_ref().err());
+        result
+    }
+
+    /// Sends a request to the given peer for a Merkle proof of the storage entries accessed by
+    /// the given runtime call.
+    pub async fn call_proof_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        function_name: String,
+        parameter: Vec<u8>,
+    ) -> Result<service::EncodedMerkleProof, CallProofRequestError> {
+        self.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "call-proof-request-start; peer_id={}; chain={}; block_hash={}; function={}",
+                target,
+                self.chain_names[&chain_id],
+                HashDisplay(&block_hash),
+                function_name
+            ),
+        );
+
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundCallProofRequest {
+                target: target.clone(),
+                chain_id,
+                block_hash,
+                function_name,
+                parameter,
+                result_tx,
+            })
+            .await;
+
+        let result = result_rx.await.unwrap();
+        self.log_request_ended("call-proof", &target, chain_id, result.as_ref().err());
+        result
+    }
+
+    /// Sends a state request to the given peer, asking for a proof of the storage entries of
+    /// the given block that follow the given key.
+    ///
+    /// If `child_trie` is `Some`, then `start_key` is a key within the given child trie.
+    pub async fn state_request(
+        self: Arc<Self>,
+        target: PeerId,
+        chain_id: ChainId,
+        block_hash: [u8; 32],
+        child_trie: Option<Vec<u8>>,
+        start_key: Vec<u8>,
+    ) -> Result<service::EncodedStateResponse, StateRequestError> {
+        self.log_callback.log(
+            LogLevel::Debug,
+            format!(
+                "state-request-start; peer_id={}; chain={}; block_hash={}; child_trie={}; start_key={}",
+                target,
+                self.chain_names[&chain_id],
+                HashDisplay(&block_hash),
+                child_trie.as_ref().map_or("none".to_owned(), hex::encode),
+                hex::encode(&start_key)
+            ),
+        );
+
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::ForegroundStateRequest {
+                target: target.clone(),
+                chain_id,
+                block_hash,
+                child_trie,
+                start_key,
+                result_tx,
+            })
+            .await;
+
+        let result = result_rx.await.unwrap();
+        self.log_request_ended("state", &target, chain_id, result.as_ref().err());
+        result
+    }
+
+    /// Prints a log message indicating that a request has finished.
+    fn log_request_ended(
+        &self,
+        request_ty: &str,
+        target: &PeerId,
+        chain_id: ChainId,
+        error: Option<&impl fmt::Display>,
+    ) {
+        match error {
+            None => self.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "{}-request-ended; peer_id={}; chain={}; outcome=success",
+                    request_ty, target, self.chain_names[&chain_id]
+                ),
+            ),
+            Some(err) => self.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "{}-request-ended; peer_id={}; chain={}; outcome=failure; error={}",
+                    request_ty, target, self.chain_names[&chain_id], err
+                ),
+            ),
+        }
+    }
+}
+
+impl Drop for NetworkService {
+    fn drop(&mut self) {
+        self.foreground_shutdown.notify(usize::max_value());
+    }
+}
+
+/// Error when initializing the network service.
+#[derive(Debug, derive_more::Display)]
+pub enum InitError {
+    /// I/O error when initializing a listener.
+    #[display(fmt = "I/O error when creating listener for {_0}: {_1}")]
+    ListenerIo(Multiaddr, io::Error),
+    /// A listening address passed through the configuration isn't valid.
+    #[display(fmt = "A listening address passed through the configuration isn't valid: {_0}")]
+    BadListenMultiaddr(Multiaddr),
+}
+
+/// Error returned by [`NetworkService::blocks_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum BlocksRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::BlocksRequestError),
+}
+
+/// Error returned by [`NetworkService::grandpa_warp_sync_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum GrandpaWarpSyncRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::GrandpaWarpSyncRequestError),
+}
+
+/// Error returned by [`NetworkService::storage_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StorageProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Size of the request is over the maximum allowed by the protocol.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::StorageProofRequestError),
+}
+
+/// Error returned by [`NetworkService::call_proof_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum CallProofRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Size of the request is over the maximum allowed by the protocol.
+    RequestTooLarge,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::CallProofRequestError),
+}
+
+/// Error returned by [`NetworkService::state_request`].
+#[derive(Debug, derive_more::Display)]
+pub enum StateRequestError {
+    /// No established connection with the target.
+    NoConnection,
+    /// Error during the request.
+    #[display(fmt = "{_0}")]
+    Request(service::StateRequestError),
+}
+
+fn run(mut inner: Inner) {
+    // This function is a small hack because I didn't find a better way to store the executor
+    // within `Inner` while at the same time spawning the `Inner` using said executor.
+    let mut actual_executor = mem::replace(&mut inner.tasks_executor, Box::new(|_| unreachable!()));
+    let (tx, rx) = oneshot::channel();
+    actual_executor(Box::pin(async move {
+        let actual_executor = rx.await.unwrap();
+        inner.tasks_executor = actual_executor;
+        background_task(inner).await;
+    }));
+    tx.send(actual_executor).unwrap_or_else(|_| panic!());
+}
+
+async fn background_task(mut inner: Inner) {
+    loop {
+        inner
+            .metrics
+            .connections
+            .set(u64::try_from(inner.network.num_connections()).unwrap_or(u64::MAX));
+
+        // Pull messages that the coordinator has generated in destination to the various
+        // connections.
+        while let Some((connection_id, message)) = inner.network.pull_message_to_connection() {
+            // Note that it is critical for the sending to not take too long here, in order to not
+            // block the process of the network service.
+            // In particular, if sending the message to the connection is blocked due to sending
+            // a message on the connection-to-coordinator channel, this will result in a deadlock.
+            // For this reason, the connection task is always ready to immediately accept a message
+            // on the coordinator-to-connection channel.
+            inner.network[connection_id].send(message).await.unwrap();
+        }
+
+        if inner.process_network_service_events && matches!(inner.event_senders, either::Left(_)) {
+            let event = loop {
+                if let Some(event) = inner.pending_events.pop_front() {
+                    break Some(event);
+                }
+
+                let inner_event = match inner.network.next_event() {
+                    Some(ev) => ev,
+                    None => break None,
+                };
+
+                if let service::Event::RequestResult { response, .. } = &inner_event {
+                    let (protocol, success) = match response {
+                        service::RequestResult::Blocks(r) => ("blocks", r.is_ok()),
+                        service::RequestResult::GrandpaWarpSync(r) => {
+                            ("grandpa-warp-sync", r.is_ok())
+                        }
+                        service::RequestResult::State(r) => ("state", r.is_ok()),
+                        service::RequestResult::StorageProof(r) => ("storage-proof", r.is_ok()),
+                        service::RequestResult::CallProof(r) => ("call-proof", r.is_ok()),
+                        service::RequestResult::KademliaFindNode(r) => {
+                            ("kademlia-find-node", r.is_ok())
+                        }
+                    };
+                    inner.metrics.requests(protocol, success).inc();
+                }
+
+                match inner_event {
+                    service::Event::HandshakeFinished {
+                        id,
+                        expected_peer_id,
+                        peer_id,
+                        ..
+                    } => {
+                        // Only outgoing connections have an expected peer ID.
+                        if expected_peer_id.is_some() {
+                            inner.num_pending_out_attempts -= 1;
+                        }
+
+                        let remote_addr = Multiaddr::from_bytes(
+                            inner.network.connection_remote_addr(id).to_owned(),
+                        )
+                        .unwrap(); // TODO: review this unwrap
+                        if let Some(expected_peer_id) =
+                            expected_peer_id.as_ref().filter(|p| **p != peer_id)
+                        {
+                            inner
                             .log_callback
                             .log(LogLevel::Debug, format!("connected-peer-id-mismatch; expected_peer_id={}; actual_peer_id={}; address={}", expected_peer_id, peer_id, remote_addr));
 
@@ -866,8 +1415,8 @@ async fn background_task(mut inner: Inner) {
                         expected_peer_id,
                         ..
                     } => {
-                        inner.num_pending_out_attempts -= 1;
                         if let Some(expected_peer_id) = expected_peer_id {
+                            inner.num_pending_out_attempts -= 1;
                             inner
                                 .peering_strategy
                                 .disconnect_addr(&expected_peer_id, &address)
@@ -969,10 +1518,12 @@ async fn background_task(mut inner: Inner) {
                     service::Event::GossipConnected {
                         peer_id,
                         chain_id,
+                        role,
                         best_number,
                         best_hash,
                         ..
                     } => {
+                        update_num_peers_metric(&inner, chain_id);
                         inner.log_callback.log(
                             LogLevel::Debug,
                             format!(
@@ -986,6 +1537,7 @@ async fn background_task(mut inner: Inner) {
                         break Some(Event::Connected {
                             peer_id,
                             chain_id,
+                            role,
                             best_block_number: best_number,
                             best_block_hash: best_hash,
                         });
@@ -993,6 +1545,7 @@ async fn background_task(mut inner: Inner) {
                     service::Event::GossipDisconnected {
                         peer_id, chain_id, ..
                     } => {
+                        update_num_peers_metric(&inner, chain_id);
                         inner.log_callback.log(
                             LogLevel::Debug,
                             format!(
@@ -1209,13 +1762,52 @@ async fn background_task(mut inner: Inner) {
                             ),
                         );
                     }
-                    service::Event::RequestResult { .. } => {
-                        // We never start a request of any other kind.
-                        unreachable!()
+                    service::Event::RequestResult {
+                        substream_id,
+                        response: service::RequestResult::GrandpaWarpSync(response),
+                    } => {
+                        let _ = inner
+                            .grandpa_warp_sync_requests
+                            .remove(&substream_id)
+                            .unwrap()
+                            .send(response.map_err(GrandpaWarpSyncRequestError::Request));
                     }
-                    service::Event::RequestInCancel { .. } => {
-                        // Requests are answered immediately, and thus cancelling events can't happen.
-                        unreachable!()
+                    service::Event::RequestResult {
+                        substream_id,
+                        response: service::RequestResult::StorageProof(response),
+                    } => {
+                        let _ = inner
+                            .storage_proof_requests
+                            .remove(&substream_id)
+                            .unwrap()
+                            .send(response.map_err(StorageProofRequestError::Request));
+                    }
+                    service::Event::RequestResult {
+                        substream_id,
+                        response: service::RequestResult::CallProof(response),
+                    } => {
+                        let _ = inner
+                            .call_proof_requests
+                            .remove(&substream_id)
+                            .unwrap()
+                            .send(response.map_err(CallProofRequestError::Request));
+                    }
+                    service::Event::RequestResult {
+                        substream_id,
+                        response: service::RequestResult::State(response),
+                    } => {
+                        let _ = inner
+                            .state_requests
+                            .remove(&substream_id)
+                            .unwrap()
+                            .send(response.map_err(StateRequestError::Request));
+                    }
+                    service::Event::RequestInCancel { substream_id } => {
+                        // Only state, storage proof, and call proof requests are answered
+                        // asynchronously.
+                        // Other requests are answered immediately, and thus can't be cancelled.
+                        let _was_in = inner.inbound_proof_requests.remove(&substream_id);
+                        debug_assert!(_was_in);
                     }
                     service::Event::IdentifyRequestIn {
                         peer_id,
@@ -1276,37 +1868,310 @@ async fn background_task(mut inner: Inner) {
                             },
                         );
                     }
-                    service::Event::GrandpaNeighborPacket {
-                        chain_id,
+                    service::Event::GrandpaWarpSyncRequestIn {
                         peer_id,
-                        state,
-                    } => {
-                        inner.log_callback.log(LogLevel::Debug, format!(
-                            "grandpa-neighbor-packet; peer_id={}; chain={}; round_number={}; set_id={}; commit_finalized_height={}",
-                            peer_id,
-                            inner.network[chain_id].log_name,
-                            state.round_number,
-                            state.set_id,
-                            state.commit_finalized_height,
-                        ));
-                        // TODO: report to the sync state machine
-                    }
-                    service::Event::GrandpaCommitMessage {
                         chain_id,
-                        peer_id,
-                        message,
+                        begin_hash,
+                        substream_id,
                     } => {
                         inner.log_callback.log(
                             LogLevel::Debug,
                             format!(
-                                "grandpa-commit-message; peer_id={}; chain={}; target_hash={}",
+                                "incoming-grandpa-warp-sync-request; peer_id={}; chain={}; begin_hash={}",
                                 peer_id,
                                 inner.network[chain_id].log_name,
-                                HashDisplay(message.decode().message.target_hash),
+                                HashDisplay(&begin_hash)
                             ),
                         );
+
+                        // TODO: is it a good idea to await here while the lock is held and freezing the entire networking background task?
+                        let response = inner.network[chain_id]
+                            .database
+                            .with_database(move |database| {
+                                database.grandpa_warp_sync_fragments(
+                                    &begin_hash,
+                                    MAX_GRANDPA_WARP_SYNC_RESPONSE_SIZE,
+                                )
+                            })
+                            .await;
+                        inner.network.respond_grandpa_warp_sync(
+                            substream_id,
+                            match &response {
+                                Ok(Some(full_sqlite::GrandpaWarpSyncFragments {
+                                    fragments,
+                                    is_finished,
+                                })) => Some(codec::GrandpaWarpSyncResponse {
+                                    fragments: fragments
+                                        .iter()
+                                        .map(|(header, justification)| {
+                                            codec::GrandpaWarpSyncResponseFragment {
+                                                scale_encoded_header: header,
+                                                scale_encoded_justification: justification,
+                                            }
+                                        })
+                                        .collect(),
+                                    is_finished: *is_finished,
+                                }),
+                                Ok(None) => None,
+                                Err(error) => {
+                                    inner.log_callback.log(
+                                        LogLevel::Warn,
+                                        format!(
+                                            "incoming-grandpa-warp-sync-request-error; error={}",
+                                            error
+                                        ),
+                                    );
+                                    None
+                                }
+                            },
+                        );
                     }
-                    service::Event::ProtocolError { peer_id, error } => {
+                    service::Event::StateRequestIn {
+                        peer_id,
+                        chain_id,
+                        block_hash,
+                        child_trie,
+                        start_key,
+                        with_proof,
+                        substream_id,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "incoming-state-request; peer_id={}; chain={}; block_hash={}; with_proof={:?}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                HashDisplay(&block_hash),
+                                with_proof
+                            ),
+                        );
+
+                        if inner.inbound_proof_requests.len() >= MAX_INBOUND_PROOF_REQUESTS {
+                            inner.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "incoming-state-request-refused; peer_id={}; chain={}; reason=too-many-requests",
+                                    peer_id, inner.network[chain_id].log_name
+                                ),
+                            );
+                            inner.network.respond_state(substream_id, None);
+                            continue;
+                        }
+
+                        // The response is built in a separate task in order to not block the
+                        // networking while the database is being accessed.
+                        inner.inbound_proof_requests.insert(substream_id);
+                        let database = inner.network[chain_id].database.clone();
+                        let to_background_tx = inner.to_background_tx.clone();
+                        (inner.tasks_executor)(Box::pin(async move {
+                            let response = state_request_response(
+                                &database, block_hash, child_trie, start_key, with_proof,
+                            )
+                            .await;
+                            let _ = to_background_tx
+                                .send(ToBackground::InboundStateResponse {
+                                    substream_id,
+                                    response,
+                                })
+                                .await;
+                        }));
+                    }
+                    service::Event::StorageProofRequestIn {
+                        peer_id,
+                        chain_id,
+                        block_hash,
+                        keys,
+                        substream_id,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "incoming-storage-proof-request; peer_id={}; chain={}; block_hash={}; num_keys={}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                HashDisplay(&block_hash),
+                                keys.len()
+                            ),
+                        );
+
+                        if inner.inbound_proof_requests.len() >= MAX_INBOUND_PROOF_REQUESTS {
+                            inner.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "incoming-storage-proof-request-refused; peer_id={}; chain={}; reason=too-many-requests",
+                                    peer_id, inner.network[chain_id].log_name
+                                ),
+                            );
+                            inner.network.respond_storage_proof(substream_id, None);
+                            continue;
+                        }
+
+                        // The response is built in a separate task in order to not block the
+                        // networking while the database is being accessed.
+                        inner.inbound_proof_requests.insert(substream_id);
+                        let database = inner.network[chain_id].database.clone();
+                        let to_background_tx = inner.to_background_tx.clone();
+                        (inner.tasks_executor)(Box::pin(async move {
+                            let response =
+                                storage_proof_response(&database, block_hash, keys).await;
+                            let _ = to_background_tx
+                                .send(ToBackground::InboundStorageProofResponse {
+                                    substream_id,
+                                    response,
+                                })
+                                .await;
+                        }));
+                    }
+                    service::Event::CallProofRequestIn {
+                        peer_id,
+                        chain_id,
+                        block_hash,
+                        function_name,
+                        parameter,
+                        substream_id,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "incoming-call-proof-request; peer_id={}; chain={}; block_hash={}; function={}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                HashDisplay(&block_hash),
+                                function_name
+                            ),
+                        );
+
+                        if inner.inbound_proof_requests.len() >= MAX_INBOUND_PROOF_REQUESTS {
+                            inner.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "incoming-call-proof-request-refused; peer_id={}; chain={}; reason=too-many-requests",
+                                    peer_id, inner.network[chain_id].log_name
+                                ),
+                            );
+                            inner.network.respond_call_proof(substream_id, None);
+                            continue;
+                        }
+
+                        // The response is built in a separate task in order to not block the
+                        // networking while the runtime is being executed.
+                        inner.inbound_proof_requests.insert(substream_id);
+                        let database = inner.network[chain_id].database.clone();
+                        let runtimes_cache = inner.network[chain_id].runtimes_cache.clone();
+                        let to_background_tx = inner.to_background_tx.clone();
+                        (inner.tasks_executor)(Box::pin(async move {
+                            let response = call_proof_response(
+                                &database,
+                                &runtimes_cache,
+                                block_hash,
+                                function_name,
+                                parameter,
+                            )
+                            .await;
+                            let _ = to_background_tx
+                                .send(ToBackground::InboundCallProofResponse {
+                                    substream_id,
+                                    response,
+                                })
+                                .await;
+                        }));
+                    }
+                    service::Event::GrandpaNeighborPacket {
+                        chain_id,
+                        peer_id,
+                        state,
+                    } => {
+                        inner.log_callback.log(LogLevel::Debug, format!(
+                            "grandpa-neighbor-packet; peer_id={}; chain={}; round_number={}; set_id={}; commit_finalized_height={}",
+                            peer_id,
+                            inner.network[chain_id].log_name,
+                            state.round_number,
+                            state.set_id,
+                            state.commit_finalized_height,
+                        ));
+                        break Some(Event::GrandpaNeighborPacket {
+                            chain_id,
+                            peer_id,
+                            state,
+                        });
+                    }
+                    service::Event::GrandpaCommitMessage {
+                        chain_id,
+                        peer_id,
+                        message,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "grandpa-commit-message; peer_id={}; chain={}; target_hash={}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                HashDisplay(message.decode().message.target_hash),
+                            ),
+                        );
+                    }
+                    service::Event::GrandpaVoteMessage {
+                        chain_id,
+                        peer_id,
+                        message,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "grandpa-vote-message; peer_id={}; chain={}; round_number={}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                message.decode().round_number,
+                            ),
+                        );
+                        break Some(Event::GrandpaVote {
+                            chain_id,
+                            peer_id,
+                            message,
+                        });
+                    }
+                    service::Event::GrandpaCatchUpRequest {
+                        chain_id,
+                        peer_id,
+                        request,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "grandpa-catch-up-request; peer_id={}; chain={}; round_number={}; set_id={}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                request.round_number,
+                                request.set_id,
+                            ),
+                        );
+                        break Some(Event::GrandpaCatchUpRequest {
+                            chain_id,
+                            peer_id,
+                            request,
+                        });
+                    }
+                    service::Event::GrandpaCatchUpMessage {
+                        chain_id,
+                        peer_id,
+                        message,
+                    } => {
+                        inner.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "grandpa-catch-up; peer_id={}; chain={}; round_number={}",
+                                peer_id,
+                                inner.network[chain_id].log_name,
+                                message.decode().round_number,
+                            ),
+                        );
+                        break Some(Event::GrandpaCatchUp {
+                            chain_id,
+                            peer_id,
+                            message,
+                        });
+                    }
+                    service::Event::ProtocolError { peer_id, error } => {
                         inner.log_callback.log(
                             LogLevel::Warn,
                             format!("protocol-error; peer_id={}; error={}", peer_id, error),
@@ -1490,6 +2355,7 @@ async fn background_task(mut inner: Inner) {
                     connection_task,
                     rx,
                     inner.to_background_tx.clone(),
+                    inner.metrics.clone(),
                 )));
 
                 inner.process_network_service_events = true;
@@ -1584,6 +2450,7 @@ async fn background_task(mut inner: Inner) {
                     connection_task,
                     rx,
                     inner.to_background_tx.clone(),
+                    inner.metrics.clone(),
                 )));
 
                 inner.process_network_service_events = true;
@@ -1634,6 +2501,97 @@ async fn background_task(mut inner: Inner) {
                 return;
             }
 
+            ToBackground::InboundStorageProofResponse {
+                substream_id,
+                response,
+            } => {
+                // The request might have been cancelled while the response was being built.
+                if inner.inbound_proof_requests.remove(&substream_id) {
+                    inner.network.respond_storage_proof(
+                        substream_id,
+                        match &response {
+                            Ok(proof) => proof.as_deref(),
+                            Err(error) => {
+                                inner.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "incoming-storage-proof-request-error; error={}",
+                                        error
+                                    ),
+                                );
+                                None
+                            }
+                        },
+                    );
+                    inner.process_network_service_events = true;
+                }
+            }
+
+            ToBackground::InboundCallProofResponse {
+                substream_id,
+                response,
+            } => {
+                // The request might have been cancelled while the response was being built.
+                if inner.inbound_proof_requests.remove(&substream_id) {
+                    inner.network.respond_call_proof(
+                        substream_id,
+                        match &response {
+                            Ok(proof) => proof.as_deref(),
+                            Err(error) => {
+                                inner.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!("incoming-call-proof-request-error; error={}", error),
+                                );
+                                None
+                            }
+                        },
+                    );
+                    inner.process_network_service_events = true;
+                }
+            }
+
+            ToBackground::InboundStateResponse {
+                substream_id,
+                response,
+            } => {
+                // The request might have been cancelled while the response was being built.
+                if inner.inbound_proof_requests.remove(&substream_id) {
+                    inner.network.respond_state(
+                        substream_id,
+                        match &response {
+                            Ok(Some(StateRequestResponse::Proof(proof))) => {
+                                Some(codec::StateResponse::Proof(proof))
+                            }
+                            Ok(Some(StateRequestResponse::KeyValues(tries))) => {
+                                Some(codec::StateResponse::KeyValues(
+                                    tries
+                                        .iter()
+                                        .map(|trie| codec::StateResponseKeyValues {
+                                            state_root: &trie.state_root,
+                                            entries: trie
+                                                .entries
+                                                .iter()
+                                                .map(|(k, v)| (&k[..], &v[..]))
+                                                .collect(),
+                                            complete: trie.complete,
+                                        })
+                                        .collect(),
+                                ))
+                            }
+                            Ok(None) => None,
+                            Err(error) => {
+                                inner.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!("incoming-state-request-error; error={}", error),
+                                );
+                                None
+                            }
+                        },
+                    );
+                    inner.process_network_service_events = true;
+                }
+            }
+
             ToBackground::ForegroundAnnounceBlock {
                 target,
                 chain_id,
@@ -1657,6 +2615,103 @@ async fn background_task(mut inner: Inner) {
                     .network
                     .set_chain_local_best_block(chain_id, best_hash, best_number);
             }
+            ToBackground::ForegroundBanAndDisconnect {
+                peer_id,
+                chain_id,
+                ban_duration,
+                reason,
+            } => {
+                inner.peering_strategy.unassign_slot_and_ban(
+                    &chain_id,
+                    &peer_id,
+                    Instant::now() + ban_duration,
+                );
+                if inner.network.gossip_remove_desired(
+                    chain_id,
+                    &peer_id,
+                    service::GossipKind::ConsensusTransactions,
+                ) {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "slot-unassigned; peer_id={}; chain={}; reason={}",
+                            peer_id, inner.network[chain_id].log_name, reason
+                        ),
+                    );
+                }
+
+                // Closing the gossip link doesn't generate any event, and we thus need to
+                // generate the `Disconnected` event ourselves.
+                let was_connected = inner
+                    .network
+                    .gossip_connected_peers(chain_id, service::GossipKind::ConsensusTransactions)
+                    .any(|p| *p == peer_id);
+                let _ = inner.network.gossip_close(
+                    chain_id,
+                    &peer_id,
+                    service::GossipKind::ConsensusTransactions,
+                );
+                if was_connected {
+                    inner.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "gossip-closed; peer_id={}; chain={}; reason={}",
+                            peer_id, inner.network[chain_id].log_name, reason
+                        ),
+                    );
+                    inner
+                        .pending_events
+                        .push_back(Event::Disconnected { chain_id, peer_id });
+                    inner.process_network_service_events = true;
+                }
+            }
+            ToBackground::ForegroundAnnounceTransaction {
+                chain_id,
+                transaction,
+                result_tx,
+            } => {
+                // TODO: collecting in a Vec :-/
+                let peers_to_send = inner
+                    .network
+                    .gossip_connected_peers(chain_id, service::GossipKind::ConsensusTransactions)
+                    .cloned()
+                    .collect::<Vec<_>>();
+
+                let mut peers_sent = Vec::with_capacity(peers_to_send.len());
+                for peer in peers_to_send {
+                    if inner
+                        .network
+                        .gossip_send_transaction(&peer, chain_id, &transaction)
+                        .is_ok()
+                    {
+                        peers_sent.push(peer);
+                    }
+                }
+
+                let _ = result_tx.send(peers_sent);
+            }
+            ToBackground::ForegroundSendGrandpaMessage {
+                target,
+                chain_id,
+                message,
+                result_tx,
+            } => {
+                let _ = result_tx.send(
+                    inner
+                        .network
+                        .gossip_send_grandpa_message(&target, chain_id, &message),
+                );
+            }
+            ToBackground::ForegroundBroadcastGrandpaMessage { chain_id, message } => {
+                inner
+                    .network
+                    .gossip_broadcast_grandpa_message(chain_id, &message);
+            }
+            ToBackground::ForegroundSetGrandpaState { chain_id, state } => {
+                inner
+                    .network
+                    .gossip_broadcast_grandpa_state_and_update(chain_id, state);
+            }
             ToBackground::ForegroundBlocksRequest {
                 target,
                 chain_id,
@@ -1678,6 +2733,117 @@ async fn background_task(mut inner: Inner) {
                     }
                 }
             }
+            ToBackground::ForegroundGrandpaWarpSyncRequest {
+                target,
+                chain_id,
+                begin_hash,
+                result_tx,
+            } => {
+                // The timeout needs to be long enough to potentially download the maximum
+                // response size of 16 MiB.
+                match inner.network.start_grandpa_warp_sync_request(
+                    &target,
+                    chain_id,
+                    begin_hash,
+                    Duration::from_secs(24),
+                ) {
+                    Ok(request_id) => {
+                        inner
+                            .grandpa_warp_sync_requests
+                            .insert(request_id, result_tx);
+                    }
+                    Err(service::StartRequestError::NoConnection) => {
+                        let _ = result_tx.send(Err(GrandpaWarpSyncRequestError::NoConnection));
+                    }
+                }
+            }
+            ToBackground::ForegroundStorageProofRequest {
+                target,
+                chain_id,
+                block_hash,
+                keys,
+                result_tx,
+            } => {
+                match inner.network.start_storage_proof_request(
+                    &target,
+                    chain_id,
+                    codec::StorageProofRequestConfig {
+                        block_hash,
+                        keys: keys.into_iter(),
+                    },
+                    Duration::from_secs(16),
+                ) {
+                    Ok(request_id) => {
+                        inner.storage_proof_requests.insert(request_id, result_tx);
+                    }
+                    Err(service::StartRequestMaybeTooLargeError::NoConnection) => {
+                        let _ = result_tx.send(Err(StorageProofRequestError::NoConnection));
+                    }
+                    Err(service::StartRequestMaybeTooLargeError::RequestTooLarge) => {
+                        let _ = result_tx.send(Err(StorageProofRequestError::RequestTooLarge));
+                    }
+                }
+            }
+            ToBackground::ForegroundCallProofRequest {
+                target,
+                chain_id,
+                block_hash,
+                function_name,
+                parameter,
+                result_tx,
+            } => {
+                match inner.network.start_call_proof_request(
+                    &target,
+                    chain_id,
+                    codec::CallProofRequestConfig {
+                        block_hash,
+                        method: function_name.into(),
+                        parameter_vectored: iter::once(parameter),
+                    },
+                    Duration::from_secs(16),
+                ) {
+                    Ok(request_id) => {
+                        inner.call_proof_requests.insert(request_id, result_tx);
+                    }
+                    Err(service::StartRequestMaybeTooLargeError::NoConnection) => {
+                        let _ = result_tx.send(Err(CallProofRequestError::NoConnection));
+                    }
+                    Err(service::StartRequestMaybeTooLargeError::RequestTooLarge) => {
+                        let _ = result_tx.send(Err(CallProofRequestError::RequestTooLarge));
+                    }
+                }
+            }
+            ToBackground::ForegroundStateRequest {
+                target,
+                chain_id,
+                block_hash,
+                child_trie,
+                start_key,
+                result_tx,
+            } => {
+                let start_key = match &child_trie {
+                    Some(child_trie) => codec::StateRequestStart::ChildTrieDefault {
+                        child_trie,
+                        key: &start_key,
+                    },
+                    None => codec::StateRequestStart::MainTrie(&start_key),
+                };
+
+                match inner.network.start_state_request(
+                    &target,
+                    chain_id,
+                    &block_hash,
+                    start_key,
+                    Duration::from_secs(24),
+                ) {
+                    Ok(request_id) => {
+                        inner.state_requests.insert(request_id, result_tx);
+                    }
+                    Err(service::StartRequestError::NoConnection) => {
+                        let _ = result_tx.send(Err(StateRequestError::NoConnection));
+                    }
+                }
+            }
             ToBackground::ForegroundGetNumConnections { result_tx } => {
                 let _ = result_tx.send(inner.network.num_connections());
             }
@@ -1718,6 +2884,17 @@ async fn background_task(mut inner: Inner) {
 }
 
 /// Builds the response to a block request by reading from the given database.
+/// Updates [`Chain::num_peers_metric`] of the given chain.
+fn update_num_peers_metric(inner: &Inner, chain_id: ChainId) {
+    let num_peers = inner
+        .network
+        .gossip_connected_peers(chain_id, service::GossipKind::ConsensusTransactions)
+        .count();
+    inner.network[chain_id]
+        .num_peers_metric
+        .set(u64::try_from(num_peers).unwrap_or(u64::MAX));
+}
+
 async fn blocks_request_response(
     database: &database_thread::DatabaseThread,
     block_number_bytes: usize,
@@ -1775,16 +2952,33 @@ async fn blocks_request_response(
                         None
                     },
                     body: if config.fields.body {
-                        Some(match database.block_extrinsics(&hash)? {
-                            Some(body) => body.collect(),
-                            None => break,
+                        // Blocks whose body is unknown aren't included in the response, as
+                        // sending an empty body would be incorrect.
+                        Some(match database.block_extrinsics(&hash) {
+                            Ok(body) => body.collect(),
+                            Err(
+                                full_sqlite::BlockBodyAccessError::BodyPruned
+                                | full_sqlite::BlockBodyAccessError::UnknownBlock,
+                            ) => break,
+                            Err(full_sqlite::BlockBodyAccessError::Corrupted(err)) => {
+                                return Err(err)
+                            }
                         })
                     } else {
                         None
                     },
                     justifications: if config.fields.justifications {
-                        // TODO: justifications aren't saved in database at the moment
-                        Some(Vec::new())
+                        // Only GrandPa justifications are saved in the database.
+                        Some(
+                            database
+                                .block_justification(&hash)?
+                                .map(|justification| codec::Justification {
+                                    engine_id: *b"FRNK",
+                                    justification,
+                                })
+                                .into_iter()
+                                .collect(),
+                        )
                     } else {
                         None
                     },
@@ -1795,3 +2989,646 @@ async fn blocks_request_response(
         })
         .await
 }
+
+/// Response to a state request. See [`state_request_response`].
+enum StateRequestResponse {
+    /// Compact Merkle proof of the storage entries.
+    Proof(Vec<u8>),
+    /// Storage entries of the main trie, followed with the storage entries of child tries.
+    KeyValues(Vec<StateResponseTrie>),
+}
+
+/// Storage entries of a trie. See [`StateRequestResponse::KeyValues`].
+struct StateResponseTrie {
+    /// Merkle value of the root of the child trie, or empty for the main trie.
+    state_root: Vec<u8>,
+    /// List of keys and values, in increasing order of keys.
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// `true` if the trie doesn't contain any entry after the last one in `entries`.
+    complete: bool,
+}
+
+/// Builds the response to a state request by reading from the given database.
+///
+/// Returns `Ok(None)` if the storage of the requested block isn't available.
+async fn state_request_response(
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    child_trie: Option<Vec<u8>>,
+    start_key: Vec<u8>,
+    with_proof: bool,
+) -> Result<Option<StateRequestResponse>, full_sqlite::CorruptedError> {
+    database
+        .with_database(move |database| {
+            let mut iterator = StateRequestIterator {
+                database,
+                block_hash,
+                remaining_size: MAX_STATE_RESPONSE_SIZE,
+                recorder: if with_proof {
+                    Some(ProofRecorder::default())
+                } else {
+                    None
+                },
+                child_tries: Vec::new(),
+            };
+
+            let main_trie = match iterator.iterate(child_trie.as_deref(), &start_key) {
+                Ok(main_trie) => main_trie,
+                Err(err) => return storage_access_error_to_response(err),
+            };
+
+            if let Some(recorder) = iterator.recorder {
+                return Ok(Some(StateRequestResponse::Proof(recorder.build_compact())));
+            }
+
+            Ok(Some(StateRequestResponse::KeyValues(
+                iter::once(main_trie).chain(iterator.child_tries).collect(),
+            )))
+        })
+        .await
+}
+
+/// Iterates over the storage entries of a block in order to answer a state request.
+struct StateRequestIterator<'a> {
+    database: &'a full_sqlite::SqliteFullDatabase,
+    block_hash: [u8; 32],
+    /// Number of bytes of keys and values that can still be added to the response.
+    remaining_size: usize,
+    /// If `Some`, the response contains a proof, and all the trie nodes that are accessed are
+    /// recorded.
+    recorder: Option<ProofRecorder>,
+    /// Child tries that have been iterated.
+    child_tries: Vec<StateResponseTrie>,
+}
+
+impl StateRequestIterator<'_> {
+    /// Iterates over the entries of the main trie strictly after `start_key`.
+    ///
+    /// If `child_trie` is `Some`, the entries of this child trie strictly after `start_key` are
+    /// iterated first, and the main trie is then iterated starting after the entry of the child
+    /// trie.
+    fn iterate(
+        &mut self,
+        child_trie: Option<&[u8]>,
+        start_key: &[u8],
+    ) -> Result<StateResponseTrie, full_sqlite::StorageAccessError> {
+        let main_trie_start_key = match child_trie {
+            Some(child_trie) => {
+                let child_trie_key = b":child_storage:default:"
+                    .iter()
+                    .chain(child_trie.iter())
+                    .copied()
+                    .collect::<Vec<_>>();
+                if !self.iterate_child_trie(child_trie, Some(start_key))? {
+                    return Ok(StateResponseTrie {
+                        state_root: Vec::new(),
+                        entries: Vec::new(),
+                        complete: false,
+                    });
+                }
+                Some(child_trie_key)
+            }
+            // An empty start key is the default value of the request, and thus indicates that
+            // the iteration must include the empty key.
+            None if start_key.is_empty() => None,
+            None => Some(start_key.to_vec()),
+        };
+
+        let mut entries = Vec::new();
+        let mut after = main_trie_start_key;
+
+        loop {
+            if !self.push_next_entry(None, after.as_deref(), &mut entries)? {
+                return Ok(StateResponseTrie {
+                    state_root: Vec::new(),
+                    entries,
+                    complete: true,
+                });
+            }
+
+            let (key, _) = entries.last().unwrap();
+            let child_trie = key
+                .strip_prefix(b":child_storage:default:")
+                .map(|c| c.to_vec());
+            after = Some(key.clone());
+
+            if let Some(child_trie) = child_trie {
+                if !self.iterate_child_trie(&child_trie, None)? {
+                    return Ok(StateResponseTrie {
+                        state_root: Vec::new(),
+                        entries,
+                        complete: false,
+                    });
+                }
+            }
+
+            if self.remaining_size == 0 {
+                return Ok(StateResponseTrie {
+                    state_root: Vec::new(),
+                    entries,
+                    complete: false,
+                });
+            }
+        }
+    }
+
+    /// Iterates over the entries of the given child trie strictly after `after`, or from the
+    /// start if `after` is `None`, and adds them to [`StateRequestIterator::child_tries`].
+    ///
+    /// Returns `true` if the end of the child trie has been reached.
+    fn iterate_child_trie(
+        &mut self,
+        child_trie: &[u8],
+        after: Option<&[u8]>,
+    ) -> Result<bool, full_sqlite::StorageAccessError> {
+        let state_root = self
+            .database
+            .block_storage_get(
+                &self.block_hash,
+                iter::empty::<iter::Empty<u8>>(),
+                trie::bytes_to_nibbles(
+                    b":child_storage:default:"
+                        .iter()
+                        .chain(child_trie.iter())
+                        .copied(),
+                )
+                .map(u8::from),
+            )?
+            .map(|(root, _)| root)
+            .unwrap_or_default();
+
+        let mut entries = Vec::new();
+        let mut after = after.map(|a| a.to_vec());
+        let complete = loop {
+            if self.remaining_size == 0 {
+                break false;
+            }
+
+            if !self.push_next_entry(Some(child_trie), after.as_deref(), &mut entries)? {
+                break true;
+            }
+
+            after = entries.last().map(|(key, _)| key.clone());
+        };
+
+        self.child_tries.push(StateResponseTrie {
+            state_root,
+            entries,
+            complete,
+        });
+
+        Ok(complete)
+    }
+
+    /// Pushes to `entries` the storage entry that immediately follows `after` in the given trie,
+    /// or the first entry of the trie if `after` is `None`.
+    ///
+    /// Returns `false` if there isn't any such entry.
+    fn push_next_entry(
+        &mut self,
+        child_trie: Option<&[u8]>,
+        after: Option<&[u8]>,
+        entries: &mut Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<bool, full_sqlite::StorageAccessError> {
+        let key_nibbles = match after {
+            Some(after) => trie::bytes_to_nibbles(after.iter().copied())
+                .map(u8::from)
+                .chain(iter::once(0))
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(
+                self.database,
+                &self.block_hash,
+                child_trie,
+                key_nibbles.iter().copied(),
+            )?;
+        }
+
+        let Some(next_key) = self.database.block_storage_next_key(
+            &self.block_hash,
+            child_trie_parent_path(child_trie)
+                .into_iter()
+                .map(|p| p.into_iter()),
+            key_nibbles.iter().copied(),
+            iter::empty(),
+            false,
+        )?
+        else {
+            return Ok(false);
+        };
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(
+                self.database,
+                &self.block_hash,
+                child_trie,
+                next_key.iter().copied(),
+            )?;
+        }
+
+        let Some((value, _)) = self.database.block_storage_get(
+            &self.block_hash,
+            child_trie_parent_path(child_trie)
+                .into_iter()
+                .map(|p| p.into_iter()),
+            next_key.iter().copied(),
+        )?
+        else {
+            // Keys returned by `block_storage_next_key` always have a storage value.
+            return Err(full_sqlite::StorageAccessError::Corrupted(
+                full_sqlite::CorruptedError::BrokenTrie,
+            ));
+        };
+
+        let key = trie::nibbles_to_bytes_truncate(
+            next_key
+                .into_iter()
+                .map(|n| trie::Nibble::try_from(n).unwrap()),
+        )
+        .collect::<Vec<_>>();
+
+        self.remaining_size = self.remaining_size.saturating_sub(key.len() + value.len());
+        entries.push((key, value));
+        Ok(true)
+    }
+}
+
+/// Builds the response to a storage proof request by reading from the given database.
+///
+/// Returns `Ok(None)` if the storage of the requested block isn't available.
+async fn storage_proof_response(
+    database: &database_thread::DatabaseThread,
+    block_hash: [u8; 32],
+    keys: Vec<Vec<u8>>,
+) -> Result<Option<Vec<u8>>, full_sqlite::CorruptedError> {
+    database
+        .with_database(move |database| {
+            let mut recorder = ProofRecorder::default();
+
+            for key in keys {
+                let key_nibbles = trie::bytes_to_nibbles(key.iter().copied()).map(u8::from);
+                if let Err(err) = recorder.record(database, &block_hash, None, key_nibbles) {
+                    return storage_access_error_to_response(err);
+                }
+            }
+
+            Ok(Some(recorder.build()))
+        })
+        .await
+}
+
+/// Builds the response to a call proof request by executing the requested runtime function
+/// on top of the storage found in the given database.
+///
+/// The runtime is compiled only if it can't be found in `runtimes_cache`, in which case it is
+/// then inserted in it.
+///
+/// Returns `Ok(None)` if the storage of the requested block isn't available, or if the call
+/// has failed.
+async fn call_proof_response(
+    database: &database_thread::DatabaseThread,
+    runtimes_cache: &Mutex<lru::LruCache<[u8; 32], executor::host::HostVmPrototype>>,
+    block_hash: [u8; 32],
+    function_name: String,
+    parameter: Vec<u8>,
+) -> Result<Option<Vec<u8>>, full_sqlite::CorruptedError> {
+    // The runtime code and heap pages are read from the storage, and are thus part of the
+    // proof as well.
+    let (mut recorder, code, heap_pages) = match database
+        .with_database(move |database| {
+            let mut recorder = ProofRecorder::default();
+            let code = recorder.storage_get(database, &block_hash, None, b":code")?;
+            let heap_pages = recorder.storage_get(database, &block_hash, None, b":heappages")?;
+            Ok::<_, full_sqlite::StorageAccessError>((recorder, code, heap_pages))
+        })
+        .await
+    {
+        Ok((recorder, Some((code, _)), heap_pages)) => {
+            (recorder, code, heap_pages.map(|(hp, _)| hp))
+        }
+        Ok((_, None, _)) => return Ok(None),
+        Err(err) => return storage_access_error_to_response(err),
+    };
+
+    let runtime = {
+        let Ok(heap_pages) = executor::storage_heap_pages_to_value(heap_pages.as_deref()) else {
+            return Ok(None);
+        };
+
+        let code_hash =
+            <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], &code).as_bytes()).unwrap();
+        let cached = runtimes_cache
+            .lock()
+            .await
+            .get(&code_hash)
+            .filter(|runtime| runtime.heap_pages() == heap_pages)
+            .cloned();
+
+        match cached {
+            Some(runtime) => runtime,
+            None => {
+                let Ok(runtime) = executor::host::HostVmPrototype::new(executor::host::Config {
+                    module: code,
+                    heap_pages,
+                    exec_hint: executor::vm::ExecHint::CompileAheadOfTime,
+                    allow_unresolved_imports: false,
+                }) else {
+                    return Ok(None);
+                };
+                runtimes_cache.lock().await.put(code_hash, runtime.clone());
+                runtime
+            }
+        }
+    };
+
+    let mut call = match executor::runtime_host::run(executor::runtime_host::Config {
+        virtual_machine: runtime,
+        function_to_call: &function_name,
+        parameter: iter::once(&parameter),
+        max_log_level: 0,
+        storage_main_trie_changes: Default::default(),
+        calculate_trie_changes: false,
+    }) {
+        Ok(call) => call,
+        Err(_) => return Ok(None),
+    };
+
+    // Each storage access of the runtime is performed through a separate database access, in
+    // order to not block the database while the runtime is being executed.
+    loop {
+        match call {
+            executor::runtime_host::RuntimeHostVm::Finished(Ok(_)) => break,
+            executor::runtime_host::RuntimeHostVm::Finished(Err(_)) => return Ok(None),
+            executor::runtime_host::RuntimeHostVm::StorageGet(req) => {
+                let child_trie = req.child_trie().map(|c| c.as_ref().to_vec());
+                let key = req.key().as_ref().to_vec();
+                let value;
+                (recorder, value) = database
+                    .with_database(move |database| {
+                        let value = recorder.storage_get(
+                            database,
+                            &block_hash,
+                            child_trie.as_deref(),
+                            &key,
+                        );
+                        (recorder, value)
+                    })
+                    .await;
+                let value = match value {
+                    Ok(v) => v,
+                    Err(err) => return storage_access_error_to_response(err),
+                };
+                call = req.inject_value(value.as_ref().map(|(val, vers)| {
+                    (
+                        iter::once(&val[..]),
+                        executor::runtime_host::TrieEntryVersion::try_from(*vers)
+                            .expect("corrupted database"),
+                    )
+                }));
+            }
+            executor::runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(req) => {
+                let child_trie = req.child_trie().map(|c| c.as_ref().to_vec());
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+                let merkle_value;
+                (recorder, merkle_value) = database
+                    .with_database(move |database| {
+                        let merkle_value = recorder
+                            .record(
+                                database,
+                                &block_hash,
+                                child_trie.as_deref(),
+                                key_nibbles.iter().copied(),
+                            )
+                            .and_then(|()| {
+                                database.block_storage_closest_descendant_merkle_value(
+                                    &block_hash,
+                                    child_trie_parent_path(child_trie.as_deref())
+                                        .into_iter()
+                                        .map(|p| p.into_iter()),
+                                    key_nibbles.iter().copied(),
+                                )
+                            });
+                        (recorder, merkle_value)
+                    })
+                    .await;
+                let merkle_value = match merkle_value {
+                    Ok(v) => v,
+                    Err(err) => return storage_access_error_to_response(err),
+                };
+                call = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+            }
+            executor::runtime_host::RuntimeHostVm::NextKey(req) => {
+                let child_trie = req.child_trie().map(|c| c.as_ref().to_vec());
+                let key_nibbles = req
+                    .key()
+                    .map(u8::from)
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+                let branch_nodes = req.branch_nodes();
+
+                // Both the path to the requested key and the path to the key that has been found
+                // are necessary in order to prove the absence of any key in-between.
+                let next_key;
+                (recorder, next_key) = database
+                    .with_database(move |database| {
+                        let next_key = recorder
+                            .record(
+                                database,
+                                &block_hash,
+                                child_trie.as_deref(),
+                                key_nibbles.iter().copied(),
+                            )
+                            .and_then(|()| {
+                                database.block_storage_next_key(
+                                    &block_hash,
+                                    child_trie_parent_path(child_trie.as_deref())
+                                        .into_iter()
+                                        .map(|p| p.into_iter()),
+                                    key_nibbles.iter().copied(),
+                                    prefix_nibbles.iter().copied(),
+                                    branch_nodes,
+                                )
+                            })
+                            .and_then(|next_key| {
+                                if let Some(next_key) = &next_key {
+                                    recorder.record(
+                                        database,
+                                        &block_hash,
+                                        child_trie.as_deref(),
+                                        next_key.iter().copied(),
+                                    )?;
+                                }
+                                Ok(next_key)
+                            });
+                        (recorder, next_key)
+                    })
+                    .await;
+                let next_key = match next_key {
+                    Ok(v) => v,
+                    Err(err) => return storage_access_error_to_response(err),
+                };
+                call = req.inject_key(
+                    next_key.map(|k| k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())),
+                );
+            }
+            executor::runtime_host::RuntimeHostVm::OffchainStorageSet(req) => {
+                call = req.resume();
+            }
+            executor::runtime_host::RuntimeHostVm::SignatureVerification(req) => {
+                call = req.verify_and_resume();
+            }
+            executor::runtime_host::RuntimeHostVm::Offchain(_)
+            | executor::runtime_host::RuntimeHostVm::GenerateKey(_) => return Ok(None),
+            executor::runtime_host::RuntimeHostVm::LogEmit(req) => {
+                // Logs are ignored.
+                call = req.resume();
+            }
+        }
+    }
+
+    Ok(Some(recorder.build()))
+}
+
+/// Converts a [`full_sqlite::StorageAccessError`] into the value to return from
+/// [`storage_proof_response`], [`call_proof_response`], or [`state_request_response`].
+fn storage_access_error_to_response<T>(
+    error: full_sqlite::StorageAccessError,
+) -> Result<Option<T>, full_sqlite::CorruptedError> {
+    match error {
+        full_sqlite::StorageAccessError::Corrupted(err) => Err(err),
+        full_sqlite::StorageAccessError::StoragePruned
+        | full_sqlite::StorageAccessError::UnknownBlock => Ok(None),
+    }
+}
+
+/// Returns the list of nibbles that lead to the root of the given child trie, or `None` if
+/// `child_trie` is `None`.
+fn child_trie_parent_path(child_trie: Option<&[u8]>) -> Option<Vec<u8>> {
+    child_trie.map(|child_trie| {
+        trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+            .chain(trie::bytes_to_nibbles(child_trie.iter().copied()))
+            .map(u8::from)
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Accumulates the trie nodes accessed in the storage of a block, in order to build a Merkle
+/// proof out of them.
+#[derive(Default)]
+pub struct ProofRecorder {
+    /// One proof builder for the main trie, plus one proof builder for each child trie that
+    /// has been accessed. Indexed by the key of the child trie, or `None` for the main trie.
+    tries: HashMap<Option<Vec<u8>>, proof_encode::ProofBuilder>,
+}
+
+impl ProofRecorder {
+    /// Adds to the recorder the nodes that are traversed when searching for the given key in
+    /// the storage of the given block.
+    ///
+    /// If `child_trie` is `Some`, the nodes of the main trie that lead to the root of the child
+    /// trie are also added.
+    pub fn record(
+        &mut self,
+        database: &full_sqlite::SqliteFullDatabase,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        key_nibbles: impl Iterator<Item = u8>,
+    ) -> Result<(), full_sqlite::StorageAccessError> {
+        let parent_path = child_trie_parent_path(child_trie);
+
+        if let Some(parent_path) = &parent_path {
+            let nodes = database.block_storage_trie_nodes_path(
+                block_hash,
+                iter::empty::<iter::Empty<u8>>(),
+                parent_path.iter().copied(),
+            )?;
+            self.insert(None, nodes);
+        }
+
+        let nodes = database.block_storage_trie_nodes_path(
+            block_hash,
+            parent_path.as_ref().map(|p| p.iter().copied()).into_iter(),
+            key_nibbles,
+        )?;
+        self.insert(child_trie.map(|c| c.to_vec()), nodes);
+        Ok(())
+    }
+
+    /// Reads a storage value of the given block and adds to the recorder the trie nodes that are
+    /// traversed in order to find it.
+    pub fn storage_get(
+        &mut self,
+        database: &full_sqlite::SqliteFullDatabase,
+        block_hash: &[u8; 32],
+        child_trie: Option<&[u8]>,
+        key: &[u8],
+    ) -> Result<Option<(Vec<u8>, u8)>, full_sqlite::StorageAccessError> {
+        let key_nibbles = trie::bytes_to_nibbles(key.iter().copied())
+            .map(u8::from)
+            .collect::<Vec<_>>();
+        self.record(
+            database,
+            block_hash,
+            child_trie,
+            key_nibbles.iter().copied(),
+        )?;
+        database.block_storage_get(
+            block_hash,
+            child_trie_parent_path(child_trie)
+                .into_iter()
+                .map(|p| p.into_iter()),
+            key_nibbles.iter().copied(),
+        )
+    }
+
+    fn insert(&mut self, trie: Option<Vec<u8>>, nodes: Vec<full_sqlite::StorageTrieNode>) {
+        let builder = self.tries.entry(trie).or_default();
+        for node in nodes {
+            let key = node
+                .key_nibbles
+                .into_iter()
+                .map(|n| trie::Nibble::try_from(n).unwrap())
+                .collect::<Vec<_>>();
+            builder.set_node_value(
+                &key,
+                &node.node_value,
+                node.unhashed_storage_value.as_deref(),
+            );
+        }
+    }
+
+    /// Builds the SCALE-encoded Merkle proof containing all the recorded nodes.
+    pub fn build(self) -> Vec<u8> {
+        proof_encode::build_multiple(self.tries.into_values()).fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+    }
+
+    /// Builds the SCALE-encoded compact Merkle proof containing all the recorded nodes, in the
+    /// format expected in responses to state requests.
+    pub fn build_compact(self) -> Vec<u8> {
+        // The main trie must come first, followed with the child tries in increasing order of
+        // keys. `None` is ordered before `Some`.
+        let mut tries = self.tries.into_iter().collect::<Vec<_>>();
+        tries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        proof_encode::build_compact_multiple(tries.into_iter().map(|(_, builder)| builder)).fold(
+            Vec::new(),
+            |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            },
+        )
+    }
+
+    /// Builds the list of entries of the Merkle proof containing all the recorded nodes, in the
+    /// format of the `state_getReadProof` JSON-RPC function.
+    pub fn build_entries(self) -> Vec<Vec<u8>> {
+        proof_encode::build_multiple_entries(self.tries.into_values()).collect()
+    }
+}
@@ -15,7 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use crate::{LogCallback, LogLevel};
+use crate::{metrics_service, LogCallback, LogLevel};
 use core::future::Future;
 use futures_lite::future;
 use futures_util::StreamExt as _;
@@ -43,6 +43,7 @@ pub(super) trait AsyncReadWrite: AsyncRead + AsyncWrite {}
 impl<T> AsyncReadWrite for T where T: AsyncRead + AsyncWrite {}
 
 /// Asynchronous task managing a specific connection.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn connection_task(
     log_callback: Arc<dyn LogCallback + Send + Sync>,
     address: String,
@@ -51,6 +52,7 @@ pub(super) async fn connection_task(
     mut connection_task: service::SingleStreamConnectionTask<Instant>,
     mut coordinator_to_connection: channel::Receiver<service::CoordinatorToConnection>,
     connection_to_coordinator: channel::Sender<super::ToBackground>,
+    metrics: Arc<metrics_service::NetworkMetrics>,
 ) {
     // The socket future is wrapped around an object containing a read buffer and a write buffer
     // and allowing easier usage.
@@ -72,6 +74,15 @@ pub(super) async fn connection_task(
 
                 connection_task.read_write(&mut *socket_read_write);
 
+                metrics.bytes_received.inc_by(
+                    u64::try_from(socket_read_write.read_bytes - read_bytes_before)
+                        .unwrap_or(u64::MAX),
+                );
+                metrics.bytes_sent.inc_by(
+                    u64::try_from(socket_read_write.write_bytes_queued - written_bytes_before)
+                        .unwrap_or(u64::MAX),
+                );
+
                 if socket_read_write.read_bytes != read_bytes_before
                     || socket_read_write.write_bytes_queued != written_bytes_before
                     || (!write_closed && socket_read_write.write_bytes_queueable.is_none())
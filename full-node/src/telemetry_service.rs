@@ -0,0 +1,488 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background service that reports information about the node to telemetry servers.
+//!
+//! One background task is spawned for each telemetry server. These tasks receive messages that
+//! are already encoded from a background task that follows the blocks of the consensus service.
+//!
+//! The certificates of secure (`wss://`) telemetry servers are verified against the Mozilla set
+//! of root certificates.
+
+use crate::{consensus_service, network_service, LogCallback, LogLevel};
+
+use futures_rustls::rustls;
+use futures_util::{AsyncRead, AsyncWrite, StreamExt as _};
+use smol::{
+    future::{self, FutureExt as _},
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+};
+use smoldot::{header, libp2p::websocket, telemetry};
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    num::NonZeroUsize,
+    pin::{self, Pin},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Configuration for a [`TelemetryService`].
+pub struct Config<'a> {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: &'a mut dyn FnMut(Pin<Box<dyn Future<Output = ()> + Send>>),
+
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Identifier of the chain, used for logging purposes.
+    pub log_name: String,
+
+    /// List of addresses of telemetry servers to connect to, alongside with their verbosity
+    /// level. See [`telemetry::parse_endpoint`] for the format of the addresses.
+    pub endpoints: Vec<(String, u8)>,
+
+    /// Name of the node, as displayed by the telemetry servers.
+    pub node_name: String,
+
+    /// Name of the chain, as found in the chain specification.
+    pub chain_name: String,
+
+    /// Hash of the genesis block of the chain.
+    pub genesis_block_hash: [u8; 32],
+
+    /// String representation of the network identity of the node.
+    pub network_id: String,
+
+    /// `true` if the node might author blocks.
+    pub authority: bool,
+
+    /// Consensus service whose blocks are reported.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Network service used to report the number of peers.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+}
+
+/// Reports information about the node to telemetry servers.
+///
+/// Destroying this object stops the background tasks.
+pub struct TelemetryService {
+    /// Notified when the service is destroyed.
+    shutdown_notify: event_listener::Event,
+}
+
+/// Interval between two `system.interval` messages.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Delay before trying to reconnect to a telemetry server after a disconnection or a failed
+/// connection attempt.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+impl TelemetryService {
+    /// Starts the background tasks of the service.
+    pub fn new(config: Config) -> Arc<Self> {
+        let shutdown_notify = event_listener::Event::new();
+
+        let now_from_unix_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::new(0, 0));
+        let system_connected = telemetry::encode_message(
+            1,
+            now_from_unix_epoch,
+            &telemetry::Payload::SystemConnected {
+                chain: &config.chain_name,
+                genesis_hash: &config.genesis_block_hash,
+                name: &config.node_name,
+                implementation: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+                network_id: &config.network_id,
+                authority: config.authority,
+                startup_time: u64::try_from(now_from_unix_epoch.as_millis()).unwrap_or(u64::MAX),
+                target_os: std::env::consts::OS,
+                target_arch: std::env::consts::ARCH,
+                target_env: if cfg!(target_env = "gnu") {
+                    "gnu"
+                } else if cfg!(target_env = "musl") {
+                    "musl"
+                } else if cfg!(target_env = "msvc") {
+                    "msvc"
+                } else {
+                    ""
+                },
+            },
+        );
+
+        let mut endpoints_senders = Vec::with_capacity(config.endpoints.len());
+        for (address, verbosity) in &config.endpoints {
+            let endpoint = match telemetry::parse_endpoint(address) {
+                Ok(ep) => ep,
+                Err(error) => {
+                    config.log_callback.log(
+                        LogLevel::Warn,
+                        format!(
+                            "telemetry-endpoint-invalid; chain={}; address={}; error={}",
+                            config.log_name, address, error
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+            let (tx, rx) = async_channel::bounded(64);
+            endpoints_senders.push((tx, *verbosity));
+
+            let task = endpoint_task(
+                config.log_callback.clone(),
+                config.log_name.clone(),
+                endpoint,
+                system_connected.clone(),
+                rx,
+            );
+            let on_shutdown = shutdown_notify.listen();
+            (config.tasks_executor)(Box::pin(task.or(on_shutdown)));
+        }
+
+        if !endpoints_senders.is_empty() {
+            let task = blocks_task(
+                config.consensus_service,
+                config.network_service,
+                endpoints_senders,
+            );
+            let on_shutdown = shutdown_notify.listen();
+            (config.tasks_executor)(Box::pin(task.or(on_shutdown)));
+        }
+
+        Arc::new(TelemetryService { shutdown_notify })
+    }
+}
+
+impl Drop for TelemetryService {
+    fn drop(&mut self) {
+        self.shutdown_notify.notify(usize::MAX);
+    }
+}
+
+/// Background task that follows the blocks of the consensus service and generates the
+/// telemetry messages.
+async fn blocks_task(
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+    endpoints: Vec<(async_channel::Sender<String>, u8)>,
+) {
+    let block_number_bytes = consensus_service.block_number_bytes();
+
+    let send = |payload: telemetry::Payload| {
+        let now_from_unix_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::new(0, 0));
+        let message = telemetry::encode_message(1, now_from_unix_epoch, &payload);
+        for (sender, verbosity) in &endpoints {
+            if payload.verbosity() <= *verbosity {
+                // Telemetry is best effort. Messages are discarded if the server is too slow.
+                let _ = sender.try_send(message.clone());
+            }
+        }
+    };
+
+    let mut next_interval = Instant::now() + INTERVAL;
+
+    loop {
+        // The maximum number of pinned block is ignored, as this maximum is a way to avoid
+        // malicious behaviors. This code is by definition not considered malicious.
+        let subscribe_all = consensus_service
+            .subscribe_all(32, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+
+        // Numbers of all the non-finalized blocks.
+        let mut blocks_numbers = hashbrown::HashMap::<_, _, fnv::FnvBuildHasher>::default();
+        let mut finalized = (
+            header::decode(
+                &subscribe_all.finalized_block_scale_encoded_header,
+                block_number_bytes,
+            )
+            .unwrap()
+            .number,
+            subscribe_all.finalized_block_hash,
+        );
+        let mut best = finalized;
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let number = header::decode(&block.scale_encoded_header, block_number_bytes)
+                .unwrap()
+                .number;
+            blocks_numbers.insert(block.block_hash, number);
+            if block.is_new_best {
+                best = (number, block.block_hash);
+            }
+        }
+
+        let mut new_blocks = pin::pin!(subscribe_all.new_blocks);
+
+        loop {
+            enum WakeUpReason {
+                Notification(consensus_service::Notification),
+                SubscriptionDead,
+                Interval,
+            }
+
+            let wake_up_reason = async {
+                match new_blocks.next().await {
+                    Some(notification) => WakeUpReason::Notification(notification),
+                    None => WakeUpReason::SubscriptionDead,
+                }
+            }
+            .or(async {
+                smol::Timer::at(next_interval).await;
+                WakeUpReason::Interval
+            })
+            .await;
+
+            match wake_up_reason {
+                WakeUpReason::SubscriptionDead => break,
+                WakeUpReason::Interval => {
+                    next_interval = Instant::now() + INTERVAL;
+                    let peers = network_service.0.num_peers(network_service.1).await;
+                    send(telemetry::Payload::SystemInterval {
+                        peers: u64::try_from(peers).unwrap_or(u64::MAX),
+                        height: best.0,
+                        best: &best.1,
+                        finalized_height: finalized.0,
+                        finalized_hash: &finalized.1,
+                        transactions_count: None,
+                        bandwidth_download: None,
+                        bandwidth_upload: None,
+                    });
+                }
+                WakeUpReason::Notification(consensus_service::Notification::Block {
+                    block,
+                    ..
+                }) => {
+                    let number = header::decode(&block.scale_encoded_header, block_number_bytes)
+                        .unwrap()
+                        .number;
+                    blocks_numbers.insert(block.block_hash, number);
+                    if block.is_new_best {
+                        best = (number, block.block_hash);
+                        send(telemetry::Payload::BlockImport {
+                            height: number,
+                            best: &block.block_hash,
+                            origin: if block.is_locally_authored {
+                                "Own"
+                            } else {
+                                "NetworkBroadcast"
+                            },
+                        });
+                    }
+                }
+                WakeUpReason::Notification(consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    best_block_hash,
+                    pruned_blocks_hashes,
+                }) => {
+                    let new_finalized_hash = finalized_blocks_newest_to_oldest[0];
+                    finalized = (blocks_numbers[&new_finalized_hash], new_finalized_hash);
+                    if best_block_hash != best.1 {
+                        best = (
+                            blocks_numbers
+                                .get(&best_block_hash)
+                                .copied()
+                                .unwrap_or(finalized.0),
+                            best_block_hash,
+                        );
+                    }
+
+                    for hash in finalized_blocks_newest_to_oldest
+                        .iter()
+                        .chain(pruned_blocks_hashes.iter())
+                    {
+                        blocks_numbers.remove(hash);
+                    }
+
+                    send(telemetry::Payload::NotifyFinalized {
+                        height: finalized.0,
+                        best: &finalized.1,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Background task that maintains a connection with a telemetry server and sends the messages
+/// received on `messages` to it.
+async fn endpoint_task(
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+    log_name: String,
+    endpoint: telemetry::Endpoint,
+    system_connected: String,
+    messages: async_channel::Receiver<String>,
+) {
+    loop {
+        let socket = match &endpoint.host {
+            telemetry::EndpointHost::Dns(host) => {
+                smol::net::TcpStream::connect((&host[..], endpoint.port)).await
+            }
+            telemetry::EndpointHost::Ipv4(ip) => {
+                smol::net::TcpStream::connect(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::from(*ip)),
+                    endpoint.port,
+                ))
+                .await
+            }
+            telemetry::EndpointHost::Ipv6(ip) => {
+                smol::net::TcpStream::connect(SocketAddr::new(
+                    IpAddr::V6(Ipv6Addr::from(*ip)),
+                    endpoint.port,
+                ))
+                .await
+            }
+        };
+
+        match socket {
+            Ok(socket) => {
+                let _ = socket.set_nodelay(true);
+                let error = if endpoint.secure {
+                    match tls_handshake(&endpoint, socket).await {
+                        Ok(socket) => {
+                            run_connection(&endpoint, socket, &system_connected, &messages).await
+                        }
+                        Err(error) => error.to_string(),
+                    }
+                } else {
+                    run_connection(&endpoint, socket, &system_connected, &messages).await
+                };
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "telemetry-disconnected; chain={}; endpoint={}; error={}",
+                        log_name, endpoint, error
+                    ),
+                );
+            }
+            Err(error) => {
+                log_callback.log(
+                    LogLevel::Debug,
+                    format!(
+                        "telemetry-connection-error; chain={}; endpoint={}; error={}",
+                        log_name, endpoint, error
+                    ),
+                );
+            }
+        }
+
+        // Messages generated while disconnected are discarded.
+        smol::Timer::after(RECONNECT_DELAY).await;
+        while messages.try_recv().is_ok() {}
+    }
+}
+
+/// Negotiates TLS on top of the given TCP connection to a telemetry server.
+async fn tls_handshake(
+    endpoint: &telemetry::Endpoint,
+    socket: smol::net::TcpStream,
+) -> Result<futures_rustls::client::TlsStream<smol::net::TcpStream>, io::Error> {
+    let server_name = match &endpoint.host {
+        telemetry::EndpointHost::Dns(host) => rustls::pki_types::ServerName::try_from(host.clone())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+        telemetry::EndpointHost::Ipv4(ip) => IpAddr::V4(Ipv4Addr::from(*ip)).into(),
+        telemetry::EndpointHost::Ipv6(ip) => IpAddr::V6(Ipv6Addr::from(*ip)).into(),
+    };
+
+    let mut root_certificates = rustls::RootCertStore::empty();
+    root_certificates.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(root_certificates)
+        .with_no_client_auth();
+
+    futures_rustls::TlsConnector::from(Arc::new(config))
+        .connect(server_name, socket)
+        .await
+}
+
+/// Drives a connection to a telemetry server until it is closed. Returns the reason why it has
+/// been closed.
+async fn run_connection(
+    endpoint: &telemetry::Endpoint,
+    socket: impl AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    system_connected: &str,
+    messages: &async_channel::Receiver<String>,
+) -> String {
+    let connection = match websocket::websocket_client_handshake(websocket::Config {
+        tcp_socket: socket,
+        host: &format!("{}:{}", endpoint.host, endpoint.port),
+        url: &endpoint.path,
+    })
+    .await
+    {
+        Ok(connection) => connection,
+        Err(error) => return error.to_string(),
+    };
+
+    let (mut reader, mut writer) = smol::io::split(connection);
+
+    let mut message = system_connected.to_owned();
+
+    loop {
+        // Each write is sent out as a separate WebSocket message, as expected by the telemetry
+        // servers.
+        let result = async {
+            writer.write_all(message.as_bytes()).await?;
+            writer.flush().await
+        }
+        .await;
+        if let Err(error) = result {
+            return error.to_string();
+        }
+
+        enum WakeUpReason {
+            Message(String),
+            SocketError(String),
+        }
+
+        let wake_up_reason = async {
+            match messages.recv().await {
+                Ok(message) => WakeUpReason::Message(message),
+                // The sender is never dropped before this task is shut down.
+                Err(_) => future::pending().await,
+            }
+        }
+        .or(async {
+            // The content of the messages sent by the server, if any, is ignored.
+            let mut buffer = [0; 1024];
+            loop {
+                match reader.read(&mut buffer).await {
+                    Ok(0) => break WakeUpReason::SocketError("connection closed".to_owned()),
+                    Ok(_) => {}
+                    Err(error) => break WakeUpReason::SocketError(error.to_string()),
+                }
+            }
+        })
+        .await;
+
+        match wake_up_reason {
+            WakeUpReason::Message(m) => message = m,
+            WakeUpReason::SocketError(error) => return error,
+        }
+    }
+}
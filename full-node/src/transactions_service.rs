@@ -0,0 +1,1075 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background transactions service.
+//!
+//! The [`TransactionsService`] holds the pool of transactions that the local node wants to see
+//! included in the chain, validates them against the best block of the chain, sends them out to
+//! the peers the node is connected to, and reports their status.
+//!
+//! The service follows all the blocks that are verified by the
+//! [`consensus_service::ConsensusService`] (see
+//! [`consensus_service::ConsensusService::subscribe_all`]), and loads from the database the body
+//! of every block in order to find out whether transactions have been included.
+//!
+//! When the consensus service authors a block, it asks the transactions service for the
+//! transactions to include (see [`consensus_service::AuthoringTransactionsRequest`]). The
+//! validated transactions of the pool that aren't included in the best chain yet are returned,
+//! by decreasing priority.
+//!
+//! If the subscription to the consensus service is closed, which happens if the transactions
+//! service is too slow to process the new blocks, all the transactions in the pool are dropped
+//! with [`DropReason::GapInChain`].
+
+use crate::{consensus_service, database_thread, network_service, LogCallback, LogLevel};
+
+use futures_channel::mpsc;
+use futures_lite::FutureExt as _;
+use futures_util::{future, stream::FuturesUnordered, SinkExt as _, StreamExt as _};
+use smol::lock::Mutex;
+use smoldot::{
+    author, executor, header,
+    informant::HashDisplay,
+    libp2p::peer_id::PeerId,
+    transactions::{pool, validate},
+    trie,
+};
+use std::{
+    cmp, iter, mem,
+    num::{NonZeroU32, NonZeroUsize},
+    pin,
+    sync::Arc,
+};
+
+/// Configuration for a [`TransactionsService`].
+pub struct Config {
+    /// Closure that spawns background tasks.
+    pub tasks_executor: Box<dyn FnMut(future::BoxFuture<'static, ()>) + Send>,
+
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Database to use to read the bodies of the blocks and the storage used when validating
+    /// transactions.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Consensus service whose blocks are followed.
+    pub consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Access to the network, and identifier of the chain to use to gossip transactions from the
+    /// point of view of the network service.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+
+    /// Receiver of the requests emitted by the consensus service when authoring blocks. Must be
+    /// connected to [`consensus_service::Config::authoring_transactions_requests`].
+    pub authoring_transactions_requests:
+        mpsc::Receiver<consensus_service::AuthoringTransactionsRequest>,
+
+    /// Maximum number of pending transactions allowed in the service.
+    ///
+    /// Any extra transaction will lead to [`DropReason::MaxPendingTransactionsReached`].
+    pub max_pending_transactions: NonZeroU32,
+
+    /// Maximum number of transaction validations that can be performed in parallel.
+    pub max_concurrent_validations: NonZeroU32,
+}
+
+/// See [the module-level documentation](..).
+pub struct TransactionsService {
+    /// Used to communicate with the background task. Also used for the background task to detect
+    /// a shutdown.
+    to_background_tx: Mutex<mpsc::Sender<ToBackground>>,
+}
+
+impl TransactionsService {
+    /// Initializes the [`TransactionsService`] with the given configuration.
+    pub async fn new(mut config: Config) -> Arc<Self> {
+        let (to_background_tx, to_background_rx) = mpsc::channel(8);
+
+        (config.tasks_executor)(Box::pin(background_task(BackgroundTaskConfig {
+            log_callback: config.log_callback,
+            database: config.database,
+            consensus_service: config.consensus_service,
+            network_service: config.network_service.0,
+            network_chain_id: config.network_service.1,
+            to_background_rx,
+            authoring_transactions_requests: config.authoring_transactions_requests,
+            max_pending_transactions: usize::try_from(config.max_pending_transactions.get())
+                .unwrap_or(usize::MAX),
+            max_concurrent_validations: usize::try_from(config.max_concurrent_validations.get())
+                .unwrap_or(usize::MAX),
+        })));
+
+        Arc::new(TransactionsService {
+            to_background_tx: Mutex::new(to_background_tx),
+        })
+    }
+
+    /// Adds a transaction to the service. The service will try to validate it and send it out
+    /// as soon as possible.
+    ///
+    /// Must pass as parameter the SCALE-encoded transaction.
+    ///
+    /// The return value of this method is a channel which will receive updates on the state
+    /// of the transaction. The channel is closed when no new update is expected or if it becomes
+    /// full.
+    ///
+    /// > **Note**: Dropping the value returned does not cancel sending out the transaction.
+    ///
+    /// If this exact same transaction is already in the pool, the transaction isn't added a
+    /// second time. Instead, a second channel is created pointing to the already-existing
+    /// transaction.
+    pub async fn submit_and_watch_transaction(
+        &self,
+        transaction_bytes: Vec<u8>,
+        channel_size: usize,
+    ) -> async_channel::Receiver<TransactionStatus> {
+        let (updates_report, rx) = async_channel::bounded(channel_size);
+
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SubmitTransaction {
+                transaction_bytes,
+                updates_report: Some(updates_report),
+            })
+            .await;
+
+        rx
+    }
+
+    /// Similar to [`TransactionsService::submit_and_watch_transaction`], but doesn't return any
+    /// channel.
+    pub async fn submit_transaction(&self, transaction_bytes: Vec<u8>) {
+        let _ = self
+            .to_background_tx
+            .lock()
+            .await
+            .send(ToBackground::SubmitTransaction {
+                transaction_bytes,
+                updates_report: None,
+            })
+            .await;
+    }
+}
+
+/// Update on the state of a transaction in the service.
+#[derive(Debug, Clone)]
+pub enum TransactionStatus {
+    /// Transaction has been broadcasted to the given peers.
+    Broadcast(Vec<PeerId>),
+
+    /// Transaction is now known to be valid. If it ever becomes invalid in the future, a
+    /// [`TransactionStatus::Dropped`] will be generated.
+    Validated,
+
+    /// The block in which a block is included has changed.
+    IncludedBlockUpdate {
+        /// If `Some`, the transaction is included in the block of the best chain with the given
+        /// hash and at the given index. If `None`, the transaction isn't present in the best
+        /// chain.
+        block_hash: Option<([u8; 32], u32)>,
+    },
+
+    /// Transaction has been removed from the pool.
+    ///
+    /// This is always the last message sent back by the channel reporting the status.
+    Dropped(DropReason),
+}
+
+/// See [`TransactionStatus::Dropped`].
+#[derive(Debug, Clone)]
+pub enum DropReason {
+    /// Transaction has been included in a finalized block.
+    ///
+    /// This is a success path.
+    Finalized { block_hash: [u8; 32], index: u32 },
+
+    /// Transaction has been dropped because the subscription to the consensus service has been
+    /// interrupted. It is impossible to know whether it has been included or not.
+    GapInChain,
+
+    /// Transaction has been dropped because the maximum number of transactions in the pool has
+    /// been reached.
+    MaxPendingTransactionsReached,
+
+    /// Transaction has been dropped because it is invalid.
+    Invalid(validate::TransactionValidityError),
+
+    /// Transaction has been dropped because the runtime has rejected it when trying to include
+    /// it in a block authored by the local node.
+    InvalidInBlock(author::runtime::TransactionValidityError),
+
+    /// Transaction has been dropped because we have failed to validate it.
+    ValidateError(ValidateTransactionError),
+}
+
+/// Failed to check the validity of a transaction.
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum ValidateTransactionError {
+    /// The storage of the block to validate against is no longer available in the database.
+    #[display(fmt = "Storage of the block is no longer available")]
+    StorageUnavailable,
+    /// Error during the validation runtime call.
+    #[display(fmt = "{_0}")]
+    Validation(validate::Error),
+}
+
+/// Message sent from the foreground service to the background.
+enum ToBackground {
+    SubmitTransaction {
+        transaction_bytes: Vec<u8>,
+        updates_report: Option<async_channel::Sender<TransactionStatus>>,
+    },
+}
+
+/// Configuration for [`background_task`].
+struct BackgroundTaskConfig {
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+    database: Arc<database_thread::DatabaseThread>,
+    consensus_service: Arc<consensus_service::ConsensusService>,
+    network_service: Arc<network_service::NetworkService>,
+    network_chain_id: network_service::ChainId,
+    to_background_rx: mpsc::Receiver<ToBackground>,
+    authoring_transactions_requests:
+        mpsc::Receiver<consensus_service::AuthoringTransactionsRequest>,
+    max_pending_transactions: usize,
+    max_concurrent_validations: usize,
+}
+
+/// Background task running in parallel of the front service.
+async fn background_task(mut config: BackgroundTaskConfig) {
+    let block_number_bytes = config.consensus_service.block_number_bytes();
+
+    'resubscribe: loop {
+        // The buffer size should be large enough so that, if the CPU is busy, it doesn't become
+        // full before the execution of the transactions service resumes.
+        // The maximum number of pinned block is ignored, as this maximum is a way to avoid
+        // malicious behaviors. This code is by definition not considered malicious.
+        let subscribe_all = config
+            .consensus_service
+            .subscribe_all(64, NonZeroUsize::new(usize::MAX).unwrap())
+            .await;
+
+        let finalized_block_number = header::decode(
+            &subscribe_all.finalized_block_scale_encoded_header,
+            block_number_bytes,
+        )
+        .unwrap()
+        .number;
+
+        let mut worker = Worker {
+            log_callback: config.log_callback.clone(),
+            database: config.database.clone(),
+            consensus_service: config.consensus_service.clone(),
+            subscription_id: subscribe_all.id,
+            block_number_bytes,
+            pool: pool::Pool::new(pool::Config {
+                capacity: cmp::min(64, config.max_pending_transactions),
+                finalized_block_height: finalized_block_number,
+                randomness_seed: rand::random(),
+            }),
+            blocks: hashbrown::HashMap::with_capacity_and_hasher(
+                subscribe_all.non_finalized_blocks_ancestry_order.len() + 16,
+                Default::default(),
+            ),
+            finalized_block_hash: subscribe_all.finalized_block_hash,
+            pool_best_chain: Vec::new(),
+            best_block_hash: subscribe_all.finalized_block_hash,
+        };
+
+        worker.blocks.insert(
+            subscribe_all.finalized_block_hash,
+            Block {
+                parent_hash: [0; 32], // Unused for the finalized block.
+                number: finalized_block_number,
+                scale_encoded_header: subscribe_all.finalized_block_scale_encoded_header,
+                runtime: subscribe_all.finalized_block_runtime,
+                body: None, // Unused for the finalized block.
+            },
+        );
+
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            worker.insert_block(block).await;
+        }
+        let best_block_hash = worker.best_block_hash;
+        worker.set_best_block(&best_block_hash);
+
+        let mut new_blocks = pin::pin!(subscribe_all.new_blocks);
+
+        // Validations that are currently in progress. Each future yields the identifier of the
+        // transaction, its bytes, the hash and number of the block it was validated against, and
+        // the outcome of the validation.
+        let mut validations_in_progress = FuturesUnordered::new();
+
+        loop {
+            // Start new validations if possible.
+            while validations_in_progress.len() < config.max_concurrent_validations {
+                let Some((tx_id, block_number)) = worker
+                    .pool
+                    .unvalidated_transactions()
+                    .find(|(_, tx, _)| !tx.validation_in_progress)
+                    .map(|(tx_id, _, block_number)| (tx_id, block_number))
+                else {
+                    break;
+                };
+
+                let block_hash = worker.block_hash_by_number(block_number);
+                let block = &worker.blocks[&block_hash];
+                worker.pool[tx_id].validation_in_progress = true;
+
+                let transaction_bytes = worker.pool.scale_encoding(tx_id).unwrap().to_owned();
+                let runtime = (*block.runtime).clone();
+                let scale_encoded_header = block.scale_encoded_header.clone();
+                let database = config.database.clone();
+                validations_in_progress.push(async move {
+                    let result = validate_transaction(
+                        &database,
+                        runtime,
+                        &block_hash,
+                        &scale_encoded_header,
+                        block_number_bytes,
+                        &transaction_bytes,
+                    )
+                    .await;
+                    (tx_id, transaction_bytes, block_hash, block_number, result)
+                });
+            }
+
+            enum WakeUpReason {
+                Notification(Option<consensus_service::Notification>),
+                FrontendEvent(Option<ToBackground>),
+                AuthoringRequest(consensus_service::AuthoringTransactionsRequest),
+                ValidationFinished(
+                    pool::TransactionId,
+                    Vec<u8>,
+                    [u8; 32],
+                    u64,
+                    Result<
+                        Result<validate::ValidTransaction, validate::TransactionValidityError>,
+                        ValidateTransactionError,
+                    >,
+                ),
+            }
+
+            let wake_up_reason = async { WakeUpReason::Notification(new_blocks.next().await) }
+                .or(async { WakeUpReason::FrontendEvent(config.to_background_rx.next().await) })
+                .or(async {
+                    // If the consensus service has shut down, this never yields anything.
+                    WakeUpReason::AuthoringRequest(
+                        config
+                            .authoring_transactions_requests
+                            .select_next_some()
+                            .await,
+                    )
+                })
+                .or(async {
+                    if validations_in_progress.is_empty() {
+                        future::pending::<()>().await;
+                    }
+                    let (tx_id, bytes, block_hash, block_number, result) =
+                        validations_in_progress.select_next_some().await;
+                    WakeUpReason::ValidationFinished(tx_id, bytes, block_hash, block_number, result)
+                })
+                .await;
+
+            match wake_up_reason {
+                WakeUpReason::FrontendEvent(None) => {
+                    // Shutdown.
+                    return;
+                }
+
+                WakeUpReason::FrontendEvent(Some(ToBackground::SubmitTransaction {
+                    transaction_bytes,
+                    updates_report,
+                })) => {
+                    // If the transaction is already in the pool, we simply add the new channel
+                    // to the existing one.
+                    let existing_tx_id = worker
+                        .pool
+                        .transactions_by_scale_encoding(&transaction_bytes)
+                        .next();
+                    if let Some(existing_tx_id) = existing_tx_id {
+                        if let Some(updates_report) = updates_report {
+                            worker.pool[existing_tx_id].add_status_update(updates_report);
+                        }
+                        continue;
+                    }
+
+                    if worker.pool.len() >= config.max_pending_transactions {
+                        if let Some(updates_report) = updates_report {
+                            let _ = updates_report.try_send(TransactionStatus::Dropped(
+                                DropReason::MaxPendingTransactionsReached,
+                            ));
+                        }
+                        continue;
+                    }
+
+                    config.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "transaction-submitted; hash={}",
+                            HashDisplay(&blake2_hash(&transaction_bytes))
+                        ),
+                    );
+
+                    let mut tx = PendingTransaction {
+                        status_update: Vec::with_capacity(1),
+                        included_block: None,
+                        validation_in_progress: false,
+                        is_validated: false,
+                        announced: false,
+                    };
+                    if let Some(updates_report) = updates_report {
+                        tx.add_status_update(updates_report);
+                    }
+                    worker.pool.add_unvalidated(transaction_bytes, tx);
+                }
+
+                WakeUpReason::AuthoringRequest(
+                    consensus_service::AuthoringTransactionsRequest::IncludableTransactions {
+                        parent_hash,
+                        result_tx,
+                    },
+                ) => {
+                    // The transactions that are includable are only known relative to the best
+                    // block of the pool. If the block being authored isn't a child of this best
+                    // block, no transaction is returned.
+                    let pool_best_block_hash = worker
+                        .pool_best_chain
+                        .last()
+                        .copied()
+                        .unwrap_or(worker.finalized_block_hash);
+                    let transactions = if pool_best_block_hash == parent_hash {
+                        worker
+                            .pool
+                            .best_block_includable_transactions()
+                            .map(|(tx_id, _)| worker.pool.scale_encoding(tx_id).unwrap().to_vec())
+                            .collect::<Vec<_>>()
+                    } else {
+                        Vec::new()
+                    };
+
+                    config.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "transactions-service-authoring-request; parent_hash={}; \
+                            num_transactions={}",
+                            HashDisplay(&parent_hash),
+                            transactions.len()
+                        ),
+                    );
+
+                    let _ = result_tx.send(transactions);
+                }
+
+                WakeUpReason::AuthoringRequest(
+                    consensus_service::AuthoringTransactionsRequest::TransactionInvalid {
+                        parent_hash,
+                        transaction,
+                        error,
+                    },
+                ) => {
+                    // The transaction might have been removed in the meanwhile.
+                    let tx_id = worker
+                        .pool
+                        .transactions_by_scale_encoding(&transaction)
+                        .next();
+                    let Some(tx_id) = tx_id else { continue };
+
+                    config.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "transaction-invalid-in-block; hash={}; parent_hash={}; error={}",
+                            HashDisplay(&blake2_hash(&transaction)),
+                            HashDisplay(&parent_hash),
+                            error
+                        ),
+                    );
+
+                    let mut tx = worker.pool.remove(tx_id);
+                    tx.update_status(TransactionStatus::Dropped(DropReason::InvalidInBlock(
+                        error,
+                    )));
+                }
+
+                WakeUpReason::Notification(None) => {
+                    // The consensus service has closed the subscription because we were too
+                    // slow to process the notifications. Drop all the transactions and
+                    // start again.
+                    config.log_callback.log(
+                        LogLevel::Warn,
+                        "transactions-service-subscription-closed".to_string(),
+                    );
+                    let tx_ids = worker.pool.iter().map(|(id, _)| id).collect::<Vec<_>>();
+                    for tx_id in tx_ids {
+                        let mut tx = worker.pool.remove(tx_id);
+                        tx.update_status(TransactionStatus::Dropped(DropReason::GapInChain));
+                    }
+                    continue 'resubscribe;
+                }
+
+                WakeUpReason::Notification(Some(consensus_service::Notification::Block {
+                    block,
+                    ..
+                })) => {
+                    let is_new_best = block.is_new_best;
+                    let block_hash = block.block_hash;
+                    worker.insert_block(block).await;
+                    if is_new_best {
+                        worker.set_best_block(&block_hash);
+                    }
+                }
+
+                WakeUpReason::Notification(Some(consensus_service::Notification::Finalized {
+                    finalized_blocks_newest_to_oldest,
+                    best_block_hash,
+                    pruned_blocks_hashes,
+                })) => {
+                    worker.set_best_block(&best_block_hash);
+                    worker
+                        .set_finalized_block(
+                            &finalized_blocks_newest_to_oldest,
+                            &pruned_blocks_hashes,
+                        )
+                        .await;
+                }
+
+                WakeUpReason::ValidationFinished(
+                    tx_id,
+                    transaction_bytes,
+                    block_hash,
+                    block_number,
+                    result,
+                ) => {
+                    // The transaction might have been removed from the pool and its identifier
+                    // re-used in the meanwhile.
+                    if worker.pool.scale_encoding(tx_id) != Some(&transaction_bytes[..]) {
+                        continue;
+                    }
+
+                    worker.pool[tx_id].validation_in_progress = false;
+
+                    // If the block the transaction has been validated against is no longer
+                    // in the best chain, the validation result is discarded and the validation
+                    // will be performed again.
+                    if !matches!(worker.blocks.get(&block_hash), Some(b) if b.number == block_number)
+                        || worker.block_hash_by_number(block_number) != block_hash
+                    {
+                        continue;
+                    }
+
+                    match result {
+                        Ok(Ok(validity)) => {
+                            config.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "transaction-validated; hash={}; block={}; priority={}; \
+                                    longevity={}; propagate={:?}",
+                                    HashDisplay(&blake2_hash(&transaction_bytes)),
+                                    HashDisplay(&block_hash),
+                                    validity.priority,
+                                    validity.longevity,
+                                    validity.propagate
+                                ),
+                            );
+
+                            let propagate = validity.propagate;
+                            worker
+                                .pool
+                                .set_validation_result(tx_id, block_number, validity);
+
+                            let tx = &mut worker.pool[tx_id];
+                            if !tx.is_validated {
+                                tx.is_validated = true;
+                                tx.update_status(TransactionStatus::Validated);
+                            }
+
+                            if propagate && !tx.announced {
+                                tx.announced = true;
+                                let peers = config
+                                    .network_service
+                                    .clone()
+                                    .announce_transaction(
+                                        config.network_chain_id,
+                                        transaction_bytes.clone(),
+                                    )
+                                    .await;
+                                if !peers.is_empty() {
+                                    worker.pool[tx_id]
+                                        .update_status(TransactionStatus::Broadcast(peers));
+                                }
+                            }
+                        }
+                        Ok(Err(error)) => {
+                            config.log_callback.log(
+                                LogLevel::Debug,
+                                format!(
+                                    "transaction-invalid; hash={}; block={}; error={}",
+                                    HashDisplay(&blake2_hash(&transaction_bytes)),
+                                    HashDisplay(&block_hash),
+                                    error
+                                ),
+                            );
+                            let mut tx = worker.pool.remove(tx_id);
+                            tx.update_status(TransactionStatus::Dropped(DropReason::Invalid(
+                                error,
+                            )));
+                        }
+                        Err(error) => {
+                            config.log_callback.log(
+                                LogLevel::Warn,
+                                format!(
+                                    "transaction-validation-error; hash={}; block={}; error={}",
+                                    HashDisplay(&blake2_hash(&transaction_bytes)),
+                                    HashDisplay(&block_hash),
+                                    error
+                                ),
+                            );
+                            let mut tx = worker.pool.remove(tx_id);
+                            tx.update_status(TransactionStatus::Dropped(
+                                DropReason::ValidateError(error),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Worker {
+    /// See [`Config::log_callback`].
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::consensus_service`].
+    consensus_service: Arc<consensus_service::ConsensusService>,
+
+    /// Identifier of the subscription to the consensus service.
+    subscription_id: consensus_service::SubscriptionId,
+
+    /// Number of bytes used to encode the block number in headers.
+    block_number_bytes: usize,
+
+    /// Pool of all the transactions. Its best chain is [`Worker::pool_best_chain`].
+    pool: pool::Pool<PendingTransaction>,
+
+    /// All the blocks that are known to be either the current finalized block or one of its
+    /// non-finalized descendants.
+    blocks: hashbrown::HashMap<[u8; 32], Block, fnv::FnvBuildHasher>,
+
+    /// Hash of the current finalized block.
+    finalized_block_hash: [u8; 32],
+
+    /// Hashes of the blocks of the best chain tracked by [`Worker::pool`], starting from the
+    /// child of the finalized block. The first entry corresponds to the finalized block number
+    /// plus one, and the last entry to the best block of the pool.
+    pool_best_chain: Vec<[u8; 32]>,
+
+    /// Hash of the current best block according to the consensus service.
+    best_block_hash: [u8; 32],
+}
+
+impl Worker {
+    /// Adds a new block to [`Worker::blocks`]. Doesn't update the best chain.
+    async fn insert_block(&mut self, block: consensus_service::BlockNotification) {
+        let runtime = match block.runtime_update {
+            Some(runtime) => runtime,
+            None => self.blocks[&block.parent_hash].runtime.clone(),
+        };
+
+        // The body of the block is loaded now, as the block is guaranteed to be in the database.
+        let body = match self
+            .database
+            .with_database({
+                let block_hash = block.block_hash;
+                move |database| {
+                    database
+                        .block_extrinsics(&block_hash)
+                        .map(|body| body.collect::<Vec<_>>())
+                }
+            })
+            .await
+        {
+            Ok(body) => Some(body),
+            Err(error) => {
+                // This can only happen if the database is corrupted or if the body has been
+                // removed in parallel, which is never supposed to happen for non-finalized blocks.
+                // The transactions of the pool that are included in this block can't be detected,
+                // and will only be removed from the pool when they fail to validate against a
+                // descendant of the block.
+                self.log_callback.log(
+                    LogLevel::Error,
+                    format!(
+                        "transactions-service-block-body-error; hash={}; error={}",
+                        HashDisplay(&block.block_hash),
+                        error
+                    ),
+                );
+                None
+            }
+        };
+
+        let number = header::decode(&block.scale_encoded_header, self.block_number_bytes)
+            .unwrap()
+            .number;
+
+        if block.is_new_best {
+            self.best_block_hash = block.block_hash;
+        }
+
+        self.blocks.insert(
+            block.block_hash,
+            Block {
+                parent_hash: block.parent_hash,
+                number,
+                scale_encoded_header: block.scale_encoded_header,
+                runtime,
+                body,
+            },
+        );
+    }
+
+    /// Returns the hash of the block of the best chain of the pool with the given number.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the number is out of range.
+    ///
+    fn block_hash_by_number(&self, block_number: u64) -> [u8; 32] {
+        let finalized_block_number = self.blocks[&self.finalized_block_hash].number;
+        if block_number == finalized_block_number {
+            self.finalized_block_hash
+        } else {
+            let index = usize::try_from(block_number - finalized_block_number - 1).unwrap();
+            self.pool_best_chain[index]
+        }
+    }
+
+    /// Updates the best chain of [`Worker::pool`] so that the given block is the best block.
+    fn set_best_block(&mut self, new_best_block_hash: &[u8; 32]) {
+        self.best_block_hash = *new_best_block_hash;
+
+        // Find the list of blocks that must be added to the pool, from highest to lowest, and
+        // the common ancestor between the current best chain of the pool and the new best block.
+        let mut to_add = Vec::new();
+        let common_ancestor_index = {
+            let mut iter = *new_best_block_hash;
+            loop {
+                if iter == self.finalized_block_hash {
+                    break None;
+                }
+                if let Some(index) = self.pool_best_chain.iter().position(|h| *h == iter) {
+                    break Some(index);
+                }
+                to_add.push(iter);
+                iter = self.blocks[&iter].parent_hash;
+            }
+        };
+
+        // Retract the blocks of the pool that are no longer in the best chain.
+        let num_to_keep = common_ancestor_index.map_or(0, |idx| idx + 1);
+        let num_to_retract = self.pool_best_chain.len() - num_to_keep;
+        if num_to_retract != 0 {
+            self.pool_best_chain.truncate(num_to_keep);
+            let retracted = self
+                .pool
+                .retract_blocks(u64::try_from(num_to_retract).unwrap())
+                .collect::<Vec<_>>();
+            for (tx_id, _) in retracted {
+                let tx = &mut self.pool[tx_id];
+                tx.included_block = None;
+                tx.update_status(TransactionStatus::IncludedBlockUpdate { block_hash: None });
+            }
+        }
+
+        // Add the new blocks to the pool.
+        for block_hash in to_add.into_iter().rev() {
+            self.pool.append_empty_block();
+            self.pool_best_chain.push(block_hash);
+
+            let Some(body) = &self.blocks[&block_hash].body else {
+                // The body couldn't be loaded. An error has been logged when the block was
+                // inserted.
+                continue;
+            };
+
+            for (index, extrinsic) in body.iter().enumerate() {
+                let pool::AppendBlockTransaction::NonIncludedUpdated { user_data: tx, .. } = self
+                    .pool
+                    .best_block_add_transaction_by_scale_encoding(extrinsic)
+                else {
+                    // Transactions that we don't know about aren't tracked.
+                    continue;
+                };
+
+                let index = u32::try_from(index).unwrap();
+                tx.included_block = Some((block_hash, index));
+                tx.update_status(TransactionStatus::IncludedBlockUpdate {
+                    block_hash: Some((block_hash, index)),
+                });
+            }
+        }
+    }
+
+    /// Updates the finalized block. Must be called after the best block has been updated.
+    async fn set_finalized_block(
+        &mut self,
+        finalized_blocks_newest_to_oldest: &[[u8; 32]],
+        pruned_blocks_hashes: &[[u8; 32]],
+    ) {
+        let new_finalized_block_hash = finalized_blocks_newest_to_oldest[0];
+        let new_finalized_block_number = self.blocks[&new_finalized_block_hash].number;
+
+        // Because the best block is always a descendant of the finalized block, the newly
+        // finalized blocks are always at the start of the best chain of the pool.
+        let num_finalized = finalized_blocks_newest_to_oldest.len();
+        debug_assert_eq!(
+            self.pool_best_chain[num_finalized - 1],
+            new_finalized_block_hash
+        );
+
+        for (_, mut tx) in self.pool.remove_included(new_finalized_block_number) {
+            let (block_hash, index) = tx.included_block.take().unwrap();
+            tx.update_status(TransactionStatus::Dropped(DropReason::Finalized {
+                block_hash,
+                index,
+            }));
+        }
+
+        let blocks_to_unpin = iter::once(mem::replace(
+            &mut self.finalized_block_hash,
+            new_finalized_block_hash,
+        ))
+        .chain(self.pool_best_chain.drain(..num_finalized - 1))
+        .chain(pruned_blocks_hashes.iter().copied())
+        .collect::<Vec<_>>();
+        self.pool_best_chain.remove(0);
+
+        for block_hash in blocks_to_unpin {
+            self.blocks.remove(&block_hash);
+            self.consensus_service
+                .unpin_block(self.subscription_id, block_hash)
+                .await;
+        }
+
+        self.log_callback.log(
+            LogLevel::Trace,
+            format!(
+                "transactions-service-finalized; hash={}; pool_size={}",
+                HashDisplay(&new_finalized_block_hash),
+                self.pool.len()
+            ),
+        );
+    }
+}
+
+struct Block {
+    /// Hash of the parent of this block.
+    parent_hash: [u8; 32],
+    /// Height of this block.
+    number: u64,
+    /// SCALE-encoded header of this block.
+    scale_encoded_header: Vec<u8>,
+    /// Runtime of this block.
+    runtime: Arc<executor::host::HostVmPrototype>,
+    /// List of SCALE-encoded extrinsics of this block. `None` if the body couldn't be loaded
+    /// from the database.
+    body: Option<Vec<Vec<u8>>>,
+}
+
+struct PendingTransaction {
+    /// List of channels that should receive changes to the transaction status.
+    status_update: Vec<async_channel::Sender<TransactionStatus>>,
+
+    /// Hash of the block of the best chain the transaction is included in, and index within
+    /// that block, if any.
+    included_block: Option<([u8; 32], u32)>,
+
+    /// `true` if a validation of this transaction is currently in progress.
+    validation_in_progress: bool,
+
+    /// `true` if the transaction has been successfully validated at least once.
+    is_validated: bool,
+
+    /// `true` if the transaction has already been sent out to peers.
+    announced: bool,
+}
+
+impl PendingTransaction {
+    fn add_status_update(&mut self, channel: async_channel::Sender<TransactionStatus>) {
+        // Report the current status to the new channel, so that it's up-to-date.
+        if self.is_validated {
+            let _ = channel.try_send(TransactionStatus::Validated);
+        }
+        if let Some(included_block) = self.included_block {
+            let _ = channel.try_send(TransactionStatus::IncludedBlockUpdate {
+                block_hash: Some(included_block),
+            });
+        }
+
+        self.status_update.push(channel);
+    }
+
+    fn update_status(&mut self, status: TransactionStatus) {
+        self.status_update
+            .retain(|channel| channel.try_send(status.clone()).is_ok());
+    }
+}
+
+/// Validates the given transaction against the given block.
+async fn validate_transaction(
+    database: &database_thread::DatabaseThread,
+    runtime: executor::host::HostVmPrototype,
+    block_hash: &[u8; 32],
+    block_scale_encoded_header: &[u8],
+    block_number_bytes: usize,
+    transaction_bytes: &[u8],
+) -> Result<
+    Result<validate::ValidTransaction, validate::TransactionValidityError>,
+    ValidateTransactionError,
+> {
+    let block_hash = *block_hash;
+
+    let mut validation = validate::validate_transaction(validate::Config {
+        runtime,
+        scale_encoded_header: block_scale_encoded_header,
+        block_number_bytes,
+        scale_encoded_transaction: iter::once(transaction_bytes),
+        source: validate::TransactionSource::External,
+        max_log_level: 0,
+    });
+
+    loop {
+        match validation {
+            validate::Query::Finished { result, .. } => {
+                return result.map_err(ValidateTransactionError::Validation)
+            }
+            validate::Query::StorageGet(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key = trie::bytes_to_nibbles(req.key().as_ref().iter().copied())
+                    .map(u8::from)
+                    .collect::<Vec<_>>();
+                let value = database
+                    .with_database(move |db| {
+                        db.block_storage_get(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(storage_access_error)?;
+                let value = value.as_ref().map(|(val, vers)| {
+                    (
+                        iter::once(&val[..]),
+                        validate::TrieEntryVersion::try_from(*vers).expect("corrupted database"),
+                    )
+                });
+
+                validation = req.inject_value(value);
+            }
+            validate::Query::ClosestDescendantMerkleValue(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key_nibbles = req.key().map(u8::from).collect::<Vec<_>>();
+
+                let merkle_value = database
+                    .with_database(move |db| {
+                        db.block_storage_closest_descendant_merkle_value(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                        )
+                    })
+                    .await
+                    .map_err(storage_access_error)?;
+
+                validation = req.inject_merkle_value(merkle_value.as_ref().map(|v| &v[..]));
+            }
+            validate::Query::NextKey(req) => {
+                let parent_paths = req.child_trie().map(|child_trie| {
+                    trie::bytes_to_nibbles(b":child_storage:default:".iter().copied())
+                        .chain(trie::bytes_to_nibbles(child_trie.as_ref().iter().copied()))
+                        .map(u8::from)
+                        .collect::<Vec<_>>()
+                });
+                let key_nibbles = req
+                    .key()
+                    .map(u8::from)
+                    .chain(if req.or_equal() { None } else { Some(0u8) })
+                    .collect::<Vec<_>>();
+                let prefix_nibbles = req.prefix().map(u8::from).collect::<Vec<_>>();
+                let branch_nodes = req.branch_nodes();
+
+                let next_key = database
+                    .with_database(move |db| {
+                        db.block_storage_next_key(
+                            &block_hash,
+                            parent_paths.into_iter().map(|p| p.into_iter()),
+                            key_nibbles.iter().copied(),
+                            prefix_nibbles.iter().copied(),
+                            branch_nodes,
+                        )
+                    })
+                    .await
+                    .map_err(storage_access_error)?;
+
+                validation = req.inject_key(
+                    next_key.map(|k| k.into_iter().map(|b| trie::Nibble::try_from(b).unwrap())),
+                );
+            }
+        }
+    }
+}
+
+/// Converts a [`database_thread::StorageAccessError`] into a [`ValidateTransactionError`].
+///
+/// # Panic
+///
+/// Panics if the database is corrupted.
+///
+fn storage_access_error(error: database_thread::StorageAccessError) -> ValidateTransactionError {
+    match error {
+        database_thread::StorageAccessError::Corrupted(err) => panic!("corrupted database: {err}"),
+        database_thread::StorageAccessError::UnknownBlock
+        | database_thread::StorageAccessError::StoragePruned => {
+            ValidateTransactionError::StorageUnavailable
+        }
+    }
+}
+
+/// Utility. Calculates the BLAKE2 hash of the given bytes.
+fn blake2_hash(bytes: &[u8]) -> [u8; 32] {
+    <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], bytes).as_bytes()).unwrap()
+}
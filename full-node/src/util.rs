@@ -15,7 +15,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::fmt::{self, Write as _};
+use smoldot::executor;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+};
 
 /// Returns an opaque object implementing the `fmt::Display` trait. Truncates the given `char`
 /// yielding iterator to the given number of elements, and if the limit is reached adds a `…` at
@@ -45,3 +49,71 @@ pub fn truncated_str<'a>(
 
     Iter(input, limit)
 }
+
+/// List of code substitutes of a chain, caching the runtimes compiled from them.
+///
+/// See [`smoldot::chain_spec::ChainSpec::code_substitutes`].
+pub struct CodeSubstitutes {
+    /// Code substitutes indexed by the block number starting from which they apply. Each
+    /// substitute is accompanied with the runtime compiled from it, if it has been compiled
+    /// before.
+    substitutes: BTreeMap<u64, (Vec<u8>, Option<executor::host::HostVmPrototype>)>,
+}
+
+impl CodeSubstitutes {
+    /// Returns `true` if a code substitute starts at the given block number.
+    pub fn starts_at(&self, block_number: u64) -> bool {
+        self.substitutes.contains_key(&block_number)
+    }
+
+    /// Returns the runtime that must be used instead of the given on-chain runtime, if any.
+    ///
+    /// `on_chain_runtime` must be the runtime built from the `:code` and `:heappages` found in
+    /// the storage of the block whose number is `block_number`. The code substitute with the
+    /// highest block number inferior or equal to `block_number` is returned if its
+    /// `spec_version` is equal to the one of `on_chain_runtime`.
+    ///
+    /// The substitute is only compiled the first time it is needed, or if the number of heap
+    /// pages of the on-chain runtime has changed since then.
+    pub fn substitute(
+        &mut self,
+        block_number: u64,
+        on_chain_runtime: &executor::host::HostVmPrototype,
+    ) -> Result<Option<executor::host::HostVmPrototype>, executor::host::NewErr> {
+        let Some((_, (code, compiled))) = self.substitutes.range_mut(..=block_number).next_back()
+        else {
+            return Ok(None);
+        };
+
+        let substitute = match compiled {
+            Some(compiled) if compiled.heap_pages() == on_chain_runtime.heap_pages() => compiled,
+            _ => compiled.insert(executor::host::HostVmPrototype::new(
+                executor::host::Config {
+                    module: &code,
+                    heap_pages: on_chain_runtime.heap_pages(),
+                    exec_hint: executor::vm::ExecHint::CompileAheadOfTime,
+                    allow_unresolved_imports: false,
+                },
+            )?),
+        };
+
+        if substitute.runtime_version().decode().spec_version
+            != on_chain_runtime.runtime_version().decode().spec_version
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(substitute.clone()))
+    }
+}
+
+impl From<BTreeMap<u64, Vec<u8>>> for CodeSubstitutes {
+    fn from(substitutes: BTreeMap<u64, Vec<u8>>) -> Self {
+        CodeSubstitutes {
+            substitutes: substitutes
+                .into_iter()
+                .map(|(block_number, code)| (block_number, (code, None)))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,1124 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Warp syncing of a full node.
+//!
+//! Instead of downloading and executing every single block since the genesis, a full node whose
+//! database is empty can warp sync to a recent finalized block, download the entire storage of
+//! this block, and store it in the database. The full node then continues syncing normally
+//! starting from this block.
+//!
+//! The storage is downloaded through state requests, and each response is verified against the
+//! state root found in the header of the finalized block. The trie nodes found in the responses
+//! are written to the database as soon as all their descendants have been downloaded, and the
+//! finalized block is then inserted in the database once the entire storage is there.
+//!
+//! If the peers stop providing the body or the storage of the finalized block, for example
+//! because they have pruned it, the warp syncing is restarted towards a more recent block.
+
+use crate::{database_thread, network_service, LogCallback, LogLevel};
+
+use futures_lite::FutureExt as _;
+use futures_util::{future, stream, StreamExt as _};
+use hashbrown::{HashMap, HashSet};
+use smoldot::{
+    chain::chain_information,
+    database::full_sqlite,
+    executor, header,
+    informant::HashDisplay,
+    libp2p::PeerId,
+    network::{codec, service},
+    sync::warp_sync,
+    trie::{self, proof_decode},
+};
+use std::{borrow::Cow, collections::BTreeMap, iter, mem, sync::Arc};
+
+/// Number of times in a row that every peer can fail to provide the body or the storage of the
+/// block that has been warp synced to before this block is considered as unavailable, for
+/// example because all the peers have pruned its storage. When that happens, the warp syncing
+/// restarts towards a more recent block.
+const MAX_FAILED_ROUNDS: u32 = 3;
+
+/// Configuration for [`warp_sync`].
+pub struct Config<'a> {
+    /// Function called in order to notify of something.
+    pub log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// Database to fill with the finalized block and its storage once the warp sync is finished.
+    pub database: Arc<database_thread::DatabaseThread>,
+
+    /// Access to the network, and identifier of the chain to sync from the point of view of the
+    /// network service.
+    pub network_service: (
+        Arc<network_service::NetworkService>,
+        network_service::ChainId,
+    ),
+
+    /// Receiver for events coming from the network, as returned by
+    /// [`network_service::NetworkService::new`].
+    pub network_events_receiver: &'a mut stream::BoxStream<'static, network_service::Event>,
+
+    /// Chain information of the block to start warp syncing from. Typically the genesis block.
+    pub chain_information: chain_information::ValidChainInformation,
+
+    /// Number of bytes of the block number in the networking protocol.
+    pub block_number_bytes: usize,
+}
+
+/// Warp syncs to the latest finalized block of the chain, downloads its storage, then replaces
+/// the content of the database with this block.
+///
+/// On success, returns the list of [`network_service::Event::Connected`] events of the peers
+/// that are still connected at the end of the warp syncing. These events have been consumed
+/// from [`Config::network_events_receiver`] and should be reported to the consensus service.
+pub async fn warp_sync(config: Config<'_>) -> Result<Vec<network_service::Event>, Error> {
+    let mut task = Task {
+        log_callback: config.log_callback,
+        database: config.database,
+        network_service: config.network_service.0,
+        network_chain_id: config.network_service.1,
+        network_events_receiver: config.network_events_receiver,
+        block_number_bytes: config.block_number_bytes,
+        peers: HashMap::with_capacity_and_hasher(32, Default::default()),
+    };
+
+    let mut start_chain_information = config.chain_information;
+    let (warp_sync_outcome, finalized_block_hash, finalized_block_number, body) = loop {
+        let warp_sync_outcome = task.warp_sync(start_chain_information).await?;
+        let finalized_block_header = warp_sync_outcome
+            .chain_information
+            .as_ref()
+            .finalized_block_header;
+        let finalized_block_hash = finalized_block_header.hash(task.block_number_bytes);
+        let finalized_block_number = finalized_block_header.number;
+        let finalized_block_state_root = *finalized_block_header.state_root;
+        let finalized_block_extrinsics_root = *finalized_block_header.extrinsics_root;
+
+        task.log_callback.log(
+            LogLevel::Info,
+            format!(
+                "warp-sync-target-reached; hash={}; number={}",
+                HashDisplay(&finalized_block_hash),
+                finalized_block_number
+            ),
+        );
+
+        let body = task
+            .download_block_body(
+                finalized_block_hash,
+                finalized_block_number,
+                finalized_block_extrinsics_root,
+            )
+            .await;
+
+        // The storage is written to the database as it is downloaded.
+        // TODO: this assumes that all the storage entries use the state version of the runtime, which isn't necessarily the case if the state hasn't been migrated
+        if let Some(body) = body {
+            let state_downloaded = task
+                .download_state(
+                    finalized_block_hash,
+                    finalized_block_number,
+                    finalized_block_state_root,
+                    warp_sync_outcome.state_version,
+                )
+                .await?;
+            if state_downloaded {
+                break (
+                    warp_sync_outcome,
+                    finalized_block_hash,
+                    finalized_block_number,
+                    body,
+                );
+            }
+        }
+
+        // The peers can't provide the body or the storage of the block, most likely because
+        // they have pruned it. Warp syncing is restarted from this block, which has been
+        // verified, towards a more recent block. The trie nodes that have already been written
+        // to the database are removed after the database has been reset.
+        task.log_callback.log(
+            LogLevel::Warn,
+            format!(
+                "warp-sync-target-unavailable; hash={}; number={}",
+                HashDisplay(&finalized_block_hash),
+                finalized_block_number
+            ),
+        );
+        start_chain_information = warp_sync_outcome.chain_information;
+    };
+
+    let state_version = warp_sync_outcome.state_version;
+    let chain_information = warp_sync_outcome.chain_information;
+    let justification = warp_sync_outcome.justification;
+    task.database
+        .with_database(move |database| {
+            database.reset(
+                chain_information.as_ref(),
+                body.iter().map(|extrinsic| &extrinsic[..]),
+                justification,
+                iter::empty(),
+                state_version,
+            )
+        })
+        .await
+        .map_err(Error::Database)?;
+
+    task.log_callback.log(
+        LogLevel::Info,
+        format!(
+            "warp-sync-finished; hash={}; number={}",
+            HashDisplay(&finalized_block_hash),
+            finalized_block_number
+        ),
+    );
+
+    task.network_service
+        .set_local_best_block(
+            task.network_chain_id,
+            finalized_block_hash,
+            finalized_block_number,
+        )
+        .await;
+
+    Ok(task
+        .peers
+        .into_iter()
+        .map(|(peer_id, peer)| network_service::Event::Connected {
+            chain_id: task.network_chain_id,
+            peer_id,
+            role: peer.role,
+            best_block_number: peer.best_block_number,
+            best_block_hash: peer.best_block_hash,
+        })
+        .collect())
+}
+
+/// Error potentially returned by [`warp_sync`].
+#[derive(Debug, derive_more::Display)]
+pub enum Error {
+    /// Failed to initialize the warp syncing state machine. This typically happens when the
+    /// chain doesn't support warp syncing.
+    #[display(fmt = "{_0}")]
+    Init(warp_sync::WarpSyncInitError),
+    /// Error while writing to the database.
+    #[display(fmt = "{_0}")]
+    Database(full_sqlite::CorruptedError),
+}
+
+struct Task<'a> {
+    /// See [`Config::log_callback`].
+    log_callback: Arc<dyn LogCallback + Send + Sync>,
+
+    /// See [`Config::database`].
+    database: Arc<database_thread::DatabaseThread>,
+
+    /// See [`Config::network_service`].
+    network_service: Arc<network_service::NetworkService>,
+
+    /// See [`Config::network_service`].
+    network_chain_id: network_service::ChainId,
+
+    /// See [`Config::network_events_receiver`].
+    network_events_receiver: &'a mut stream::BoxStream<'static, network_service::Event>,
+
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// List of peers that are currently connected, as reported by the network service.
+    peers: HashMap<PeerId, Peer, fnv::FnvBuildHasher>,
+}
+
+/// See [`Task::peers`].
+struct Peer {
+    /// Role of the peer, as reported when connecting.
+    role: codec::Role,
+    /// Best block of the peer, as reported when connecting or through block announces.
+    best_block_number: u64,
+    /// Hash of the block indicated by [`Peer::best_block_number`].
+    best_block_hash: [u8; 32],
+    /// Height of the latest finalized block of the peer, as reported through GrandPa neighbor
+    /// packets.
+    finalized_block_height: u64,
+}
+
+/// Outcome of [`Task::warp_sync`].
+struct WarpSyncOutcome {
+    /// Chain information of the finalized block that has been warp synced to.
+    chain_information: chain_information::ValidChainInformation,
+    /// Justification that proves the finality of the finalized block, if known.
+    justification: Option<Vec<u8>>,
+    /// State version of the runtime of the finalized block.
+    state_version: u8,
+}
+
+/// Change in the list of peers caused by a network event. See [`Task::inject_network_event`].
+enum PeerChange {
+    Connected(PeerId),
+    Disconnected(PeerId),
+    Finalized(PeerId, u64),
+}
+
+/// Outcome of a request started during the warp syncing.
+enum RequestOutcome {
+    WarpSync(
+        Result<
+            service::EncodedGrandpaWarpSyncResponse,
+            network_service::GrandpaWarpSyncRequestError,
+        >,
+    ),
+    StorageProof(Result<service::EncodedMerkleProof, network_service::StorageProofRequestError>),
+    CallProof(Result<service::EncodedMerkleProof, network_service::CallProofRequestError>),
+}
+
+/// Progress of the download of the storage of the finalized block. See
+/// [`Task::download_state`].
+#[derive(Default)]
+struct StateDownload {
+    /// Number of storage entries that have been downloaded and verified so far, including the
+    /// entries of child tries.
+    num_entries: usize,
+    /// Number of default child tries whose download has started.
+    num_child_tries: usize,
+    /// Key of the last entry of the main trie that has been fully downloaded, including its
+    /// child trie if any. `None` if no entry has been downloaded yet.
+    main_trie_cursor: Option<Vec<u8>>,
+    /// If `Some`, the child trie whose entries are currently being downloaded.
+    child_trie_cursor: Option<ChildTrieCursor>,
+    /// Trie nodes that have been verified but can't be written to the database yet, because
+    /// some of their descendants haven't been downloaded yet. Indexed by the hash of the root of
+    /// their trie and their key.
+    pending_nodes: BTreeMap<([u8; 32], Vec<trie::Nibble>), full_sqlite::InsertTrieNode<'static>>,
+    /// Trie nodes whose descendants are all either in the database or in this list, and that
+    /// can thus be written to the database.
+    nodes_to_write: Vec<full_sqlite::InsertTrieNode<'static>>,
+}
+
+/// See [`StateDownload::child_trie_cursor`].
+struct ChildTrieCursor {
+    /// Key of the child trie, without the `:child_storage:default:` prefix.
+    child_trie: Vec<u8>,
+    /// Hash of the root of the child trie.
+    trie_root: [u8; 32],
+    /// Key of the last entry of the child trie that has been downloaded, if any.
+    after: Option<Vec<u8>>,
+}
+
+impl Task<'_> {
+    /// Updates [`Task::peers`] according to the given network event.
+    fn inject_network_event(&mut self, event: network_service::Event) -> Option<PeerChange> {
+        match event {
+            network_service::Event::Connected {
+                chain_id,
+                peer_id,
+                role,
+                best_block_number,
+                best_block_hash,
+            } if chain_id == self.network_chain_id => {
+                self.peers.insert(
+                    peer_id.clone(),
+                    Peer {
+                        role,
+                        best_block_number,
+                        best_block_hash,
+                        finalized_block_height: 0,
+                    },
+                );
+                Some(PeerChange::Connected(peer_id))
+            }
+            network_service::Event::Disconnected { chain_id, peer_id }
+                if chain_id == self.network_chain_id =>
+            {
+                self.peers.remove(&peer_id);
+                Some(PeerChange::Disconnected(peer_id))
+            }
+            network_service::Event::BlockAnnounce {
+                chain_id,
+                peer_id,
+                scale_encoded_header,
+                is_best: true,
+            } if chain_id == self.network_chain_id => {
+                if let (Some(peer), Ok(decoded)) = (
+                    self.peers.get_mut(&peer_id),
+                    header::decode(&scale_encoded_header, self.block_number_bytes),
+                ) {
+                    peer.best_block_number = decoded.number;
+                    peer.best_block_hash =
+                        header::hash_from_scale_encoded_header(&scale_encoded_header);
+                }
+                None
+            }
+            network_service::Event::GrandpaNeighborPacket {
+                chain_id,
+                peer_id,
+                state,
+            } if chain_id == self.network_chain_id => {
+                let peer = self.peers.get_mut(&peer_id)?;
+                peer.finalized_block_height = state.commit_finalized_height;
+                Some(PeerChange::Finalized(
+                    peer_id,
+                    state.commit_finalized_height,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Waits for the given future to finish, while processing the network events in the
+    /// background.
+    async fn run_with_network_events<T>(&mut self, future: impl future::Future<Output = T>) -> T {
+        let mut future = Box::pin(future);
+        loop {
+            let outcome = async { Ok(future.as_mut().await) }
+                .or(async { Err(self.network_events_receiver.next().await.unwrap()) })
+                .await;
+            match outcome {
+                Ok(output) => return output,
+                Err(event) => {
+                    let _ = self.inject_network_event(event);
+                }
+            }
+        }
+    }
+
+    /// Returns a peer whose finalized block is superior or equal to `block_number` and that
+    /// isn't in `excluded`.
+    ///
+    /// Returns `None` if all such peers are in `excluded`. If there isn't any such peer at all,
+    /// waits for the situation to change.
+    async fn pick_peer(&mut self, block_number: u64, excluded: &HashSet<PeerId>) -> Option<PeerId> {
+        loop {
+            let mut any_excluded = false;
+            for (peer_id, peer) in &self.peers {
+                if peer.finalized_block_height < block_number {
+                    continue;
+                }
+                if !excluded.contains(peer_id) {
+                    return Some(peer_id.clone());
+                }
+                any_excluded = true;
+            }
+
+            if any_excluded {
+                return None;
+            }
+
+            let event = self.network_events_receiver.next().await.unwrap();
+            let _ = self.inject_network_event(event);
+        }
+    }
+
+    /// Runs the warp syncing state machine until it has reached the latest finalized block of
+    /// the chain.
+    async fn warp_sync(
+        &mut self,
+        chain_information: chain_information::ValidChainInformation,
+    ) -> Result<WarpSyncOutcome, Error> {
+        let mut sync =
+            warp_sync::start_warp_sync::<PeerId, future::AbortHandle>(warp_sync::Config {
+                start_chain_information: chain_information,
+                block_number_bytes: self.block_number_bytes,
+                sources_capacity: 32,
+                requests_capacity: 32,
+                code_trie_node_hint: None,
+                num_download_ahead_fragments: 128,
+                warp_sync_minimum_gap: 32,
+            })
+            .map_err(|(_, err)| Error::Init(err))?;
+
+        let mut sources = HashMap::<_, _, fnv::FnvBuildHasher>::default();
+        for (peer_id, peer) in &self.peers {
+            let source_id = sync.add_source(peer_id.clone());
+            sync.set_source_finality_state(source_id, peer.finalized_block_height);
+            sources.insert(peer_id.clone(), source_id);
+        }
+
+        // Justifications of the last fragment of each warp sync response, indexed by block hash.
+        // Used in order to store the justification of the finalized block in the database.
+        let mut justifications = HashMap::<_, _, fnv::FnvBuildHasher>::default();
+
+        let mut pending_requests = stream::FuturesUnordered::new();
+
+        loop {
+            // Process the CPU-heavy operations that the state machine would like to perform.
+            loop {
+                match sync.process_one() {
+                    warp_sync::ProcessOne::Idle(s) => {
+                        sync = s;
+                        break;
+                    }
+                    warp_sync::ProcessOne::VerifyWarpSyncFragment(verify) => {
+                        let sender = verify
+                            .proof_sender()
+                            .map(|(_, peer_id)| peer_id.to_string())
+                            .unwrap_or_else(|| "<disconnected>".to_owned());
+                        let (s, result) = verify.verify(rand::random());
+                        sync = s;
+                        match result {
+                            Ok((hash, number)) => {
+                                self.log_callback.log(
+                                    LogLevel::Debug,
+                                    format!(
+                                        "warp-sync-fragment-verified; sender={}; hash={}; number={}",
+                                        sender,
+                                        HashDisplay(&hash),
+                                        number
+                                    ),
+                                );
+                            }
+                            Err(err) => {
+                                self.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "warp-sync-fragment-verify-error; sender={}; error={}",
+                                        sender, err
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                    warp_sync::ProcessOne::BuildRuntime(build) => {
+                        let (s, result) = build.build(executor::vm::ExecHint::Oneshot, true);
+                        sync = s;
+                        if let Err(err) = result {
+                            self.log_callback.log(
+                                LogLevel::Warn,
+                                format!("warp-sync-runtime-build-error; error={}", err),
+                            );
+                        }
+                    }
+                    warp_sync::ProcessOne::BuildChainInformation(build) => {
+                        let (s, result) = build.build();
+                        sync = s;
+                        match result {
+                            Ok(runtime_information) => {
+                                let chain_information =
+                                    chain_information::ValidChainInformation::from(
+                                        sync.as_chain_information(),
+                                    );
+                                let finalized_block_hash = chain_information
+                                    .as_ref()
+                                    .finalized_block_header
+                                    .hash(self.block_number_bytes);
+                                let state_version = runtime_information
+                                    .finalized_runtime
+                                    .runtime_version()
+                                    .decode()
+                                    .state_version
+                                    .map(u8::from)
+                                    .unwrap_or(0);
+
+                                // Requests that are still in progress are dropped alongside
+                                // with `pending_requests`.
+                                return Ok(WarpSyncOutcome {
+                                    chain_information,
+                                    justification: justifications.remove(&finalized_block_hash),
+                                    state_version,
+                                });
+                            }
+                            Err(err) => {
+                                self.log_callback.log(
+                                    LogLevel::Warn,
+                                    format!(
+                                        "warp-sync-chain-information-build-error; error={}",
+                                        err
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Start the requests that the state machine would like to start.
+            loop {
+                let Some((source_id, peer_id, detail)) =
+                    sync.desired_requests()
+                        .next()
+                        .map(|(source_id, peer_id, request)| {
+                            let detail = match request {
+                                warp_sync::DesiredRequest::WarpSyncRequest { block_hash } => {
+                                    warp_sync::RequestDetail::WarpSyncRequest { block_hash }
+                                }
+                                warp_sync::DesiredRequest::StorageGetMerkleProof {
+                                    block_hash,
+                                    keys,
+                                    ..
+                                } => warp_sync::RequestDetail::StorageGetMerkleProof {
+                                    block_hash,
+                                    keys,
+                                },
+                                warp_sync::DesiredRequest::RuntimeCallMerkleProof {
+                                    block_hash,
+                                    function_name,
+                                    parameter_vectored,
+                                } => warp_sync::RequestDetail::RuntimeCallMerkleProof {
+                                    block_hash,
+                                    function_name,
+                                    parameter_vectored,
+                                },
+                            };
+                            (source_id, peer_id.clone(), detail)
+                        })
+                else {
+                    break;
+                };
+
+                let network_service = self.network_service.clone();
+                let network_chain_id = self.network_chain_id;
+
+                let request = match &detail {
+                    warp_sync::RequestDetail::WarpSyncRequest { block_hash } => {
+                        let block_hash = *block_hash;
+                        async move {
+                            RequestOutcome::WarpSync(
+                                network_service
+                                    .grandpa_warp_sync_request(
+                                        peer_id,
+                                        network_chain_id,
+                                        block_hash,
+                                    )
+                                    .await,
+                            )
+                        }
+                        .boxed()
+                    }
+                    warp_sync::RequestDetail::StorageGetMerkleProof { block_hash, keys } => {
+                        let (block_hash, keys) = (*block_hash, keys.clone());
+                        async move {
+                            RequestOutcome::StorageProof(
+                                network_service
+                                    .storage_proof_request(
+                                        peer_id,
+                                        network_chain_id,
+                                        block_hash,
+                                        keys,
+                                    )
+                                    .await,
+                            )
+                        }
+                        .boxed()
+                    }
+                    warp_sync::RequestDetail::RuntimeCallMerkleProof {
+                        block_hash,
+                        function_name,
+                        parameter_vectored,
+                    } => {
+                        let block_hash = *block_hash;
+                        let function_name = function_name.clone().into_owned();
+                        let parameter = parameter_vectored.clone().into_owned();
+                        async move {
+                            RequestOutcome::CallProof(
+                                network_service
+                                    .call_proof_request(
+                                        peer_id,
+                                        network_chain_id,
+                                        block_hash,
+                                        function_name,
+                                        parameter,
+                                    )
+                                    .await,
+                            )
+                        }
+                        .boxed()
+                    }
+                };
+
+                let (request, abort) = future::abortable(request);
+                let request_id = sync.add_request(source_id, abort, detail);
+                pending_requests.push(async move { (request_id, request.await) });
+            }
+
+            // Wait for either a network event or a request to finish.
+            let outcome = async {
+                if pending_requests.is_empty() {
+                    future::pending::<()>().await;
+                }
+                Ok(pending_requests.next().await.unwrap())
+            }
+            .or(async { Err(self.network_events_receiver.next().await.unwrap()) })
+            .await;
+
+            match outcome {
+                Err(event) => match self.inject_network_event(event) {
+                    // A source might already exist in case of a duplicate event.
+                    Some(PeerChange::Connected(peer_id)) if !sources.contains_key(&peer_id) => {
+                        let source_id = sync.add_source(peer_id.clone());
+                        sources.insert(peer_id, source_id);
+                    }
+                    Some(PeerChange::Disconnected(peer_id)) => {
+                        if let Some(source_id) = sources.remove(&peer_id) {
+                            let (_, requests) = sync.remove_source(source_id);
+                            for (_, abort) in requests {
+                                abort.abort();
+                            }
+                        }
+                    }
+                    Some(PeerChange::Finalized(peer_id, height)) => {
+                        if let Some(source_id) = sources.get(&peer_id) {
+                            sync.set_source_finality_state(*source_id, height);
+                        }
+                    }
+                    Some(PeerChange::Connected(_)) | None => {}
+                },
+
+                // Requests whose source has been removed are aborted, and their identifier is
+                // no longer valid.
+                Ok((_, Err(future::Aborted))) => {}
+
+                Ok((request_id, Ok(RequestOutcome::WarpSync(Ok(response))))) => {
+                    let decoded = response.decode();
+                    if let Some(last) = decoded.fragments.last() {
+                        justifications.insert(
+                            header::hash_from_scale_encoded_header(last.scale_encoded_header),
+                            last.scale_encoded_justification.to_vec(),
+                        );
+                    }
+                    let fragments = decoded
+                        .fragments
+                        .into_iter()
+                        .map(|fragment| warp_sync::WarpSyncFragment {
+                            scale_encoded_header: fragment.scale_encoded_header.to_vec(),
+                            scale_encoded_justification: fragment
+                                .scale_encoded_justification
+                                .to_vec(),
+                        })
+                        .collect();
+                    sync.warp_sync_request_success(request_id, fragments, decoded.is_finished);
+                }
+                Ok((request_id, Ok(RequestOutcome::StorageProof(Ok(proof))))) => {
+                    sync.storage_get_success(request_id, proof.decode().to_vec());
+                }
+                Ok((request_id, Ok(RequestOutcome::CallProof(Ok(proof))))) => {
+                    sync.runtime_call_merkle_proof_success(request_id, proof.decode().to_vec());
+                }
+                Ok((
+                    request_id,
+                    Ok(
+                        RequestOutcome::WarpSync(Err(_))
+                        | RequestOutcome::StorageProof(Err(_))
+                        | RequestOutcome::CallProof(Err(_)),
+                    ),
+                )) => {
+                    // The failure has already been logged by the network service.
+                    sync.fail_request(request_id);
+                }
+            }
+        }
+    }
+
+    /// Downloads the body of the given block from the network.
+    ///
+    /// Returns `None` if no peer has been able to provide the body after
+    /// [`MAX_FAILED_ROUNDS`] attempts with each peer.
+    async fn download_block_body(
+        &mut self,
+        block_hash: [u8; 32],
+        block_number: u64,
+        extrinsics_root: [u8; 32],
+    ) -> Option<Vec<Vec<u8>>> {
+        let mut excluded = HashSet::with_capacity_and_hasher(32, Default::default());
+        let mut num_failed_rounds = 0;
+
+        loop {
+            let Some(peer_id) = self.pick_peer(block_number, &excluded).await else {
+                num_failed_rounds += 1;
+                if num_failed_rounds >= MAX_FAILED_ROUNDS {
+                    return None;
+                }
+                excluded.clear();
+                continue;
+            };
+            excluded.insert(peer_id.clone());
+
+            let request = self.network_service.clone().blocks_request(
+                peer_id.clone(),
+                self.network_chain_id,
+                codec::BlocksRequestConfig {
+                    start: codec::BlocksRequestConfigStart::Hash(block_hash),
+                    desired_count: 1.try_into().unwrap(),
+                    direction: codec::BlocksRequestDirection::Ascending,
+                    fields: codec::BlocksRequestFields {
+                        header: false,
+                        body: true,
+                        justifications: false,
+                    },
+                },
+            );
+
+            let Ok(blocks) = self.run_with_network_events(request).await else {
+                continue;
+            };
+
+            match blocks.into_iter().next() {
+                Some(codec::BlockData {
+                    hash,
+                    body: Some(body),
+                    ..
+                }) if hash == block_hash && header::extrinsics_root(&body) == extrinsics_root => {
+                    return Some(body)
+                }
+                _ => {
+                    self.log_callback.log(
+                        LogLevel::Debug,
+                        format!(
+                            "warp-sync-bad-block-body; peer_id={}; hash={}",
+                            peer_id,
+                            HashDisplay(&block_hash)
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Downloads the entire storage of the given block from the network and writes its trie
+    /// nodes to the database.
+    ///
+    /// Returns `Ok(false)` if the peers have stopped providing the storage, without any progress
+    /// during [`MAX_FAILED_ROUNDS`] attempts with each peer.
+    async fn download_state(
+        &mut self,
+        block_hash: [u8; 32],
+        block_number: u64,
+        state_root: [u8; 32],
+        state_version: u8,
+    ) -> Result<bool, Error> {
+        let mut download = StateDownload::default();
+        let mut excluded = HashSet::with_capacity_and_hasher(32, Default::default());
+        let mut num_failed_rounds = 0;
+
+        loop {
+            let Some(peer_id) = self.pick_peer(block_number, &excluded).await else {
+                num_failed_rounds += 1;
+                if num_failed_rounds >= MAX_FAILED_ROUNDS {
+                    return Ok(false);
+                }
+                excluded.clear();
+                continue;
+            };
+
+            let (child_trie, start_key) = match &download.child_trie_cursor {
+                Some(cursor) => (
+                    Some(cursor.child_trie.clone()),
+                    cursor.after.clone().unwrap_or_default(),
+                ),
+                None => (None, download.main_trie_cursor.clone().unwrap_or_default()),
+            };
+
+            let request = self.network_service.clone().state_request(
+                peer_id.clone(),
+                self.network_chain_id,
+                block_hash,
+                child_trie,
+                start_key,
+            );
+
+            let response = match self.run_with_network_events(request).await {
+                Ok(response) => response,
+                Err(_) => {
+                    excluded.insert(peer_id);
+                    continue;
+                }
+            };
+
+            // State responses contain compact proofs, which must first be converted to regular
+            // proofs.
+            let decoded =
+                match proof_decode::decode_compact_proof(response.decode()).and_then(|proof| {
+                    proof_decode::decode_and_verify_proof(proof_decode::Config { proof })
+                }) {
+                    Ok(decoded) => decoded,
+                    Err(err) => {
+                        self.log_callback.log(
+                            LogLevel::Debug,
+                            format!(
+                                "warp-sync-bad-state-response; peer_id={}; error={}",
+                                peer_id, err
+                            ),
+                        );
+                        excluded.insert(peer_id);
+                        continue;
+                    }
+                };
+
+            let num_entries_before = download.num_entries;
+            let finished = download.inject_proof(&decoded, &state_root);
+
+            let nodes_to_write = mem::take(&mut download.nodes_to_write);
+            if !nodes_to_write.is_empty() {
+                self.database
+                    .with_database(move |database| {
+                        database.insert_trie_nodes(nodes_to_write.into_iter(), state_version)
+                    })
+                    .await
+                    .map_err(Error::Database)?;
+            }
+
+            if finished {
+                self.log_callback.log(
+                    LogLevel::Info,
+                    format!(
+                        "warp-sync-state-downloaded; hash={}; num_entries={}; num_child_tries={}",
+                        HashDisplay(&block_hash),
+                        download.num_entries,
+                        download.num_child_tries
+                    ),
+                );
+                return Ok(true);
+            }
+
+            // Peers that don't let us make progress are ignored for the next requests.
+            if download.num_entries == num_entries_before {
+                excluded.insert(peer_id);
+            } else {
+                num_failed_rounds = 0;
+            }
+
+            self.log_callback.log(
+                LogLevel::Debug,
+                format!(
+                    "warp-sync-state-download-progress; num_entries={}",
+                    download.num_entries
+                ),
+            );
+        }
+    }
+}
+
+impl StateDownload {
+    /// Iterates over the entries found in the given proof, starting from the current cursor, and
+    /// moves to [`StateDownload::nodes_to_write`] the trie nodes that can be written to the
+    /// database.
+    ///
+    /// Returns `true` if the entire storage has been downloaded.
+    fn inject_proof(
+        &mut self,
+        proof: &proof_decode::DecodedTrieProof<Vec<u8>>,
+        state_root: &[u8; 32],
+    ) -> bool {
+        self.record_nodes(proof, state_root, true);
+        if let Some(cursor) = &self.child_trie_cursor {
+            let child_trie_root = cursor.trie_root;
+            self.record_nodes(proof, &child_trie_root, false);
+        }
+
+        let finished = 'download: loop {
+            // Continue downloading the child trie that is currently being iterated, if any.
+            if let Some(cursor) = &mut self.child_trie_cursor {
+                loop {
+                    match next_entry(proof, &cursor.trie_root, cursor.after.as_deref()) {
+                        NextEntry::Entry(key, _) => {
+                            cursor.after = Some(key);
+                            self.num_entries += 1;
+                        }
+                        NextEntry::End => break,
+                        NextEntry::IncompleteProof => break 'download false,
+                    }
+                }
+
+                // The child trie has been entirely downloaded.
+                let cursor = self.child_trie_cursor.take().unwrap();
+                self.flush_nodes(&cursor.trie_root, None);
+                self.main_trie_cursor = Some(child_trie_key(&cursor.child_trie));
+            }
+
+            match next_entry(proof, state_root, self.main_trie_cursor.as_deref()) {
+                NextEntry::Entry(key, value) => {
+                    self.num_entries += 1;
+                    match (
+                        key.strip_prefix(b":child_storage:default:"),
+                        <[u8; 32]>::try_from(&value[..]),
+                    ) {
+                        (Some(child_trie), Ok(child_trie_root)) => {
+                            self.num_child_tries += 1;
+                            self.child_trie_cursor = Some(ChildTrieCursor {
+                                child_trie: child_trie.to_vec(),
+                                trie_root: child_trie_root,
+                                after: None,
+                            });
+                            self.record_nodes(proof, &child_trie_root, false);
+                        }
+                        // A child trie root with an invalid length can't be downloaded, and is
+                        // stored as a regular storage value.
+                        _ => self.main_trie_cursor = Some(key),
+                    }
+                }
+                NextEntry::End => break true,
+                NextEntry::IncompleteProof => break false,
+            }
+        };
+
+        if finished {
+            self.flush_nodes(state_root, None);
+            debug_assert!(self.pending_nodes.is_empty());
+            return true;
+        }
+
+        if let Some(ChildTrieCursor {
+            trie_root,
+            after: Some(after),
+            ..
+        }) = &self.child_trie_cursor
+        {
+            let (trie_root, after) = (*trie_root, after.clone());
+            self.flush_nodes(&trie_root, Some(&after));
+        }
+        if let Some(main_trie_cursor) = self.main_trie_cursor.clone() {
+            self.flush_nodes(state_root, Some(&main_trie_cursor));
+        }
+        false
+    }
+
+    /// Adds to [`StateDownload::pending_nodes`] the nodes of the given trie found in the proof.
+    ///
+    /// The nodes whose storage value is missing from the proof are ignored. They are found
+    /// again in the proof that contains their storage value.
+    fn record_nodes(
+        &mut self,
+        proof: &proof_decode::DecodedTrieProof<Vec<u8>>,
+        trie_root: &[u8; 32],
+        is_main_trie: bool,
+    ) {
+        for (key, entry) in proof.iter_ordered() {
+            if key.trie_root_hash != trie_root {
+                continue;
+            }
+
+            let storage_value = match entry.trie_node_info.storage_value {
+                proof_decode::StorageValue::Known { value, .. } => {
+                    // Values that point to a child trie that is downloaded reference the root
+                    // node of this child trie.
+                    let references_merkle_value = is_main_trie
+                        && value.len() == 32
+                        && key.key.len() % 2 == 0
+                        && trie::nibbles_to_bytes_truncate(key.key.iter().copied())
+                            .take(b":child_storage:default:".len())
+                            .eq(b":child_storage:default:".iter().copied());
+                    full_sqlite::InsertTrieNodeStorageValue::Value {
+                        value: Cow::Owned(value.to_vec()),
+                        references_merkle_value,
+                    }
+                }
+                proof_decode::StorageValue::None => {
+                    full_sqlite::InsertTrieNodeStorageValue::NoValue
+                }
+                proof_decode::StorageValue::HashKnownValueMissing(_) => continue,
+            };
+
+            // Node values found in a proof have already been verified.
+            let decoded = trie::trie_node::decode(entry.node_value).unwrap();
+
+            // The root node is always hashed, while other nodes are inlined in their parent if
+            // their node value is shorter than 32 bytes.
+            let merkle_value = if key.key.is_empty() || entry.node_value.len() >= 32 {
+                blake2_rfc::blake2b::blake2b(32, &[], entry.node_value)
+                    .as_bytes()
+                    .to_vec()
+            } else {
+                entry.node_value.to_vec()
+            };
+
+            self.pending_nodes.insert(
+                (*trie_root, key.key.to_vec()),
+                full_sqlite::InsertTrieNode {
+                    merkle_value: Cow::Owned(merkle_value),
+                    partial_key_nibbles: Cow::Owned(decoded.partial_key.map(u8::from).collect()),
+                    children_merkle_values: decoded
+                        .children
+                        .map(|child| child.map(|child| Cow::Owned(child.to_vec()))),
+                    storage_value,
+                },
+            );
+        }
+    }
+
+    /// Moves from [`StateDownload::pending_nodes`] to [`StateDownload::nodes_to_write`] the nodes
+    /// of the given trie whose descendants have all been downloaded.
+    ///
+    /// `downloaded_until` is the key of the last entry of the trie that has been downloaded, or
+    /// `None` if the entire trie has been downloaded.
+    fn flush_nodes(&mut self, trie_root: &[u8; 32], downloaded_until: Option<&[u8]>) {
+        let downloaded_until = downloaded_until
+            .map(|key| trie::bytes_to_nibbles(key.iter().copied()).collect::<Vec<_>>());
+
+        // The descendants of a node have all been downloaded if the node is strictly before
+        // `downloaded_until` and isn't one of its ancestors.
+        let completed = self
+            .pending_nodes
+            .range((*trie_root, Vec::new())..)
+            .take_while(|((root, _), _)| root == trie_root)
+            .filter(|((_, key), _)| match &downloaded_until {
+                Some(downloaded_until) => {
+                    !downloaded_until.starts_with(key) && downloaded_until > key
+                }
+                None => true,
+            })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in completed {
+            let node = self.pending_nodes.remove(&key).unwrap();
+            self.nodes_to_write.push(node);
+        }
+    }
+}
+
+/// Returns the storage entry that immediately follows `after` in the given trie, or the first
+/// entry of the trie if `after` is `None`.
+///
+fn next_entry(
+    proof: &proof_decode::DecodedTrieProof<Vec<u8>>,
+    trie_root: &[u8; 32],
+    after: Option<&[u8]>,
+) -> NextEntry {
+    let key_before = after
+        .map(|after| trie::bytes_to_nibbles(after.iter().copied()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let key_nibbles = match proof.next_key(trie_root, &key_before, after.is_none(), &[], false) {
+        Ok(Some(key)) => key,
+        Ok(None) => return NextEntry::End,
+        Err(_) => return NextEntry::IncompleteProof,
+    };
+
+    let key = trie::nibbles_to_bytes_truncate(key_nibbles.iter().copied()).collect::<Vec<_>>();
+    match proof.storage_value(trie_root, &key) {
+        Ok(Some((value, _))) => NextEntry::Entry(key, value.to_vec()),
+        // Keys returned by `next_key` always have a storage value, but the value might be
+        // missing from the proof.
+        Ok(None) | Err(_) => NextEntry::IncompleteProof,
+    }
+}
+
+/// See [`next_entry`].
+enum NextEntry {
+    /// Key and value of the storage entry.
+    Entry(Vec<u8>, Vec<u8>),
+    /// There isn't any storage entry after the requested one.
+    End,
+    /// The proof doesn't contain enough information.
+    IncompleteProof,
+}
+
+/// Returns the key of the given default child trie within the main trie.
+fn child_trie_key(child_trie: &[u8]) -> Vec<u8> {
+    b":child_storage:default:"
+        .iter()
+        .chain(child_trie.iter())
+        .copied()
+        .collect()
+}
@@ -16,7 +16,10 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use smoldot::json_rpc;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+
+mod common;
+use common::{alice_remark, request};
 
 #[test]
 fn basic_block_generated() {
@@ -31,8 +34,13 @@ fn basic_block_generated() {
                 .unwrap()],
                 sqlite_database_path: None,
                 sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
                 keystore_path: None,
                 json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
             },
             relay_chain: None,
             libp2p_key: Box::new([0; 32]),
@@ -40,6 +48,8 @@ fn basic_block_generated() {
             tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
             log_callback: Arc::new(move |_, _| {}),
             jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
         })
         .await
         .unwrap();
@@ -73,3 +83,90 @@ fn basic_block_generated() {
         }
     });
 }
+
+#[test]
+fn pool_transaction_included() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![smoldot::identity::seed_phrase::decode_sr25519_private_key(
+                    "//Alice",
+                )
+                .unwrap()],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
+                keystore_path: None,
+                json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new([0; 32]),
+            listen_addresses: Vec::new(),
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
+        })
+        .await
+        .unwrap();
+
+        let transaction = format!(
+            "0x{}",
+            hex::encode(alice_remark(&client, 0, b"authored").await)
+        );
+        let _ = request(
+            &client,
+            format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"author_submitExtrinsic","params":["{transaction}"]}}"#
+            ),
+        )
+        .await;
+
+        // Go through the authored blocks one by one until the transaction is found.
+        for block_number in 1.. {
+            let block_hash = loop {
+                let block_hash = serde_json::from_str::<Option<String>>(
+                    &request(
+                        &client,
+                        format!(
+                            r#"{{"jsonrpc":"2.0","id":1,"method":"chain_getBlockHash","params":[{block_number}]}}"#
+                        ),
+                    )
+                    .await,
+                )
+                .unwrap();
+                match block_hash {
+                    Some(hash) => break hash,
+                    None => smol::Timer::after(Duration::from_millis(200)).await,
+                };
+            };
+
+            let block = serde_json::from_str::<serde_json::Value>(
+                &request(
+                    &client,
+                    format!(
+                        r#"{{"jsonrpc":"2.0","id":1,"method":"chain_getBlock","params":["{block_hash}"]}}"#
+                    ),
+                )
+                .await,
+            )
+            .unwrap();
+
+            if block["block"]["extrinsics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|extrinsic| extrinsic.as_str() == Some(&transaction))
+            {
+                return; // Test success
+            }
+        }
+    });
+}
@@ -0,0 +1,172 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::{io, sync::Arc};
+
+#[test]
+fn genesis_round_trip() {
+    smol::block_on(async move {
+        for format in [
+            smoldot_full_node::BlocksFileFormat::Scale,
+            smoldot_full_node::BlocksFileFormat::JsonLines,
+        ] {
+            let mut exported = Vec::new();
+            let num_exported =
+                smoldot_full_node::export_blocks(smoldot_full_node::ExportBlocksConfig {
+                    chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                    sqlite_database_path: None,
+                    sqlite_cache_size: 256 * 1024 * 1024,
+                    first_block_number: 0,
+                    last_block_number: None,
+                    format,
+                    output: &mut exported,
+                })
+                .await
+                .unwrap();
+            assert_eq!(num_exported, 1);
+
+            let outcome = smoldot_full_node::import_blocks(smoldot_full_node::ImportBlocksConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+                log_callback: Arc::new(move |_, _| {}),
+                format,
+                input: &mut io::Cursor::new(exported),
+            })
+            .await
+            .unwrap();
+            assert_eq!(outcome.num_imported, 0);
+            assert_eq!(outcome.num_already_known, 1);
+        }
+    });
+}
+
+#[test]
+fn authored_block_imported() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![smoldot::identity::seed_phrase::decode_sr25519_private_key(
+                    "//Alice",
+                )
+                .unwrap()],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
+                keystore_path: None,
+                json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new([0; 32]),
+            listen_addresses: Vec::new(),
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
+        })
+        .await
+        .unwrap();
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chainHead_unstable_follow","params":[false]}"#
+                .to_owned(),
+        );
+
+        // Wait for the first block authored by the node, and download its header and body.
+        let mut subscription = None;
+        let mut header = None;
+        let mut body = None;
+        while header.is_none() || body.is_none() {
+            let message =
+                serde_json::from_str::<serde_json::Value>(&client.next_json_rpc_response().await)
+                    .unwrap();
+
+            if message["id"] == 1 {
+                subscription = Some(message["result"].as_str().unwrap().to_owned());
+            } else if message["id"] == 2 {
+                header = Some(message["result"].clone());
+            } else if message["params"]["result"]["event"] == "newBlock" && header.is_none() {
+                let block_hash = &message["params"]["result"]["blockHash"];
+                let subscription = subscription.as_ref().unwrap();
+                client.send_json_rpc_request(format!(
+                    r#"{{"jsonrpc":"2.0","id":2,"method":"chainHead_unstable_header","params":["{subscription}",{block_hash}]}}"#
+                ));
+                client.send_json_rpc_request(format!(
+                    r#"{{"jsonrpc":"2.0","id":3,"method":"chainHead_unstable_body","params":["{subscription}",{block_hash}]}}"#
+                ));
+            } else if message["params"]["result"]["event"] == "operationBodyDone" {
+                body = Some(message["params"]["result"]["value"].clone());
+            }
+        }
+
+        let exported = serde_json::to_string(&serde_json::json!({
+            "header": header.unwrap(),
+            "body": body.unwrap(),
+            "justifications": [],
+        }))
+        .unwrap();
+
+        let outcome = smoldot_full_node::import_blocks(smoldot_full_node::ImportBlocksConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            format: smoldot_full_node::BlocksFileFormat::JsonLines,
+            input: &mut io::Cursor::new(exported),
+        })
+        .await
+        .unwrap();
+        assert_eq!(outcome.num_imported, 1);
+        assert_eq!(outcome.num_already_known, 0);
+    });
+}
+
+#[test]
+fn invalid_block_rejected() {
+    smol::block_on(async move {
+        let block = serde_json::json!({
+            "header": "0x00",
+            "body": [],
+            "justifications": [],
+        });
+
+        let result = smoldot_full_node::import_blocks(smoldot_full_node::ImportBlocksConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            format: smoldot_full_node::BlocksFileFormat::JsonLines,
+            input: &mut io::Cursor::new(block.to_string()),
+        })
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(smoldot_full_node::ImportBlocksError::Block { position: 0, .. })
+        ));
+    });
+}
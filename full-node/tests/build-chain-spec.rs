@@ -0,0 +1,86 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#[test]
+fn genesis_storage_preserved() {
+    smol::block_on(async move {
+        let original = smoldot::chain_spec::ChainSpec::from_json_bytes(include_bytes!(
+            "./substrate-node-template.json"
+        ))
+        .unwrap();
+
+        // The database is empty, so the storage of the genesis block is used.
+        let built = smoldot_full_node::build_chain_spec(smoldot_full_node::BuildChainSpecConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            block_hash: None,
+            with_light_sync_state: false,
+        })
+        .await
+        .unwrap();
+        let built = smoldot::chain_spec::ChainSpec::from_json_bytes(built).unwrap();
+
+        assert_eq!(built.id(), original.id());
+        let original_storage = original.genesis_storage().into_genesis_items().unwrap();
+        let built_storage = built.genesis_storage().into_genesis_items().unwrap();
+        assert_eq!(
+            built_storage.iter().collect::<Vec<_>>(),
+            original_storage.iter().collect::<Vec<_>>()
+        );
+    });
+}
+
+#[test]
+fn light_sync_state_requires_babe() {
+    smol::block_on(async move {
+        let result = smoldot_full_node::build_chain_spec(smoldot_full_node::BuildChainSpecConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            block_hash: None,
+            with_light_sync_state: true,
+        })
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(smoldot_full_node::BuildChainSpecError::LightSyncState(
+                smoldot::chain_spec::ChainInformationToCheckpointError::UnsupportedConsensus
+            ))
+        ));
+    });
+}
+
+#[test]
+fn unknown_block() {
+    smol::block_on(async move {
+        let result = smoldot_full_node::build_chain_spec(smoldot_full_node::BuildChainSpecConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            block_hash: Some([0xff; 32]),
+            with_light_sync_state: false,
+        })
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(smoldot_full_node::BuildChainSpecError::StorageAccess(_))
+        ));
+    });
+}
@@ -0,0 +1,165 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use smoldot::json_rpc;
+use std::sync::Arc;
+
+/// Returns the uncompressed runtime found in the genesis storage of the substrate-node-template
+/// chain specification.
+fn genesis_runtime() -> Vec<u8> {
+    let chain_spec = serde_json::from_slice::<serde_json::Value>(include_bytes!(
+        "./substrate-node-template.json"
+    ))
+    .unwrap();
+
+    let code = hex::decode(
+        chain_spec["genesis"]["raw"]["top"]["0x3a636f6465"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches("0x"),
+    )
+    .unwrap();
+
+    // The runtime is prefixed with a magic number indicating that it is zstd-compressed.
+    let mut compressed = &code[8..];
+    let mut decoder = ruzstd::frame_decoder::FrameDecoder::new();
+    decoder.init(&mut compressed).unwrap();
+    decoder
+        .decode_blocks(
+            &mut compressed,
+            ruzstd::frame_decoder::BlockDecodingStrategy::All,
+        )
+        .unwrap();
+    decoder.collect().unwrap()
+}
+
+/// Overwrites the `spec_version` and `impl_version` found in the `runtime_version` custom
+/// section of the given runtime.
+fn set_runtime_version(code: &mut [u8], spec_version: u32, impl_version: u32) {
+    let section_start = code
+        .windows(16)
+        .position(|w| w == b"\x0fruntime_version")
+        .unwrap()
+        + 16;
+
+    // The section starts with `spec_name` and `impl_name`, followed with `authoring_version`,
+    // `spec_version`, and `impl_version`.
+    let impl_name_start = section_start + 1 + usize::from(code[section_start] >> 2);
+    let spec_version_start = impl_name_start + 1 + usize::from(code[impl_name_start] >> 2) + 4;
+    code[spec_version_start..][..4].copy_from_slice(&spec_version.to_le_bytes());
+    code[spec_version_start + 4..][..4].copy_from_slice(&impl_version.to_le_bytes());
+}
+
+/// Returns the substrate-node-template chain specification with the given code substitute
+/// starting at the genesis block.
+fn chain_spec_with_code_substitute(code: &[u8]) -> Vec<u8> {
+    let mut chain_spec = serde_json::from_slice::<serde_json::Value>(include_bytes!(
+        "./substrate-node-template.json"
+    ))
+    .unwrap();
+
+    chain_spec["codeSubstitutes"] = serde_json::json!({ "0": format!("0x{}", hex::encode(code)) });
+
+    serde_json::to_vec(&chain_spec).unwrap()
+}
+
+/// Returns the `specVersion` and `implVersion` reported by `state_getRuntimeVersion`.
+async fn runtime_version(client: &smoldot_full_node::Client) -> (u64, u64) {
+    client.send_json_rpc_request(
+        r#"{"jsonrpc":"2.0","id":1,"method":"state_getRuntimeVersion","params":[]}"#.to_owned(),
+    );
+
+    let response_raw = client.next_json_rpc_response().await;
+    let (_, result) = json_rpc::parse::parse_response(&response_raw)
+        .unwrap()
+        .into_success()
+        .unwrap();
+    let result = serde_json::from_str::<serde_json::Value>(result).unwrap();
+    (
+        result["specVersion"].as_u64().unwrap(),
+        result["implVersion"].as_u64().unwrap(),
+    )
+}
+
+async fn start_client(
+    chain_spec: Vec<u8>,
+) -> Result<smoldot_full_node::Client, smoldot_full_node::StartError> {
+    smoldot_full_node::start(smoldot_full_node::Config {
+        chain: smoldot_full_node::ChainConfig {
+            chain_spec: chain_spec.into(),
+            additional_bootnodes: Vec::new(),
+            keystore_memory: vec![],
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            sqlite_state_pruning: None,
+            sqlite_blocks_pruning: None,
+            keystore_path: None,
+            json_rpc_listen: None,
+            warp_sync: false,
+            telemetry_endpoints: None,
+            allow_inbound_storage_and_call_proof_requests: true,
+        },
+        relay_chain: None,
+        libp2p_key: Box::new([0; 32]),
+        listen_addresses: Vec::new(),
+        tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+        log_callback: Arc::new(move |_, _| {}),
+        jaeger_agent: None,
+        prometheus_address: None,
+        node_name: "smoldot".into(),
+    })
+    .await
+}
+
+#[test]
+fn substitute_with_same_spec_version() {
+    smol::block_on(async move {
+        let mut substitute = genesis_runtime();
+        set_runtime_version(&mut substitute, 100, 2);
+
+        let client = start_client(chain_spec_with_code_substitute(&substitute))
+            .await
+            .unwrap_or_else(|_| panic!());
+
+        // The on-chain runtime has an `impl_version` of 1.
+        assert_eq!(runtime_version(&client).await, (100, 2));
+    });
+}
+
+#[test]
+fn substitute_with_different_spec_version_ignored() {
+    smol::block_on(async move {
+        let mut substitute = genesis_runtime();
+        set_runtime_version(&mut substitute, 101, 2);
+
+        let client = start_client(chain_spec_with_code_substitute(&substitute))
+            .await
+            .unwrap_or_else(|_| panic!());
+
+        assert_eq!(runtime_version(&client).await, (100, 1));
+    });
+}
+
+#[test]
+fn invalid_substitute_rejected() {
+    smol::block_on(async move {
+        assert!(matches!(
+            start_client(chain_spec_with_code_substitute(&[1, 2])).await,
+            Err(smoldot_full_node::StartError::ConsensusServiceInit(_))
+        ));
+    });
+}
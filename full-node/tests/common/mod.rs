@@ -0,0 +1,110 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers shared between the integration tests.
+
+use smoldot::{identity::keystore, json_rpc};
+
+/// Sends a JSON-RPC request and returns the JSON-formatted result.
+pub async fn request(client: &smoldot_full_node::Client, request: String) -> String {
+    client.send_json_rpc_request(request);
+    let response_raw = client.next_json_rpc_response().await;
+    let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+        .unwrap()
+        .into_success()
+        .unwrap();
+    result_json.to_owned()
+}
+
+/// Builds a `System::remark` transaction signed by Alice, using the given nonce.
+pub async fn alice_remark(client: &smoldot_full_node::Client, nonce: u8, remark: &[u8]) -> Vec<u8> {
+    fn compact(value: usize) -> Vec<u8> {
+        if value < 64 {
+            vec![u8::try_from(value << 2).unwrap()]
+        } else {
+            (u16::try_from(value << 2).unwrap() | 1)
+                .to_le_bytes()
+                .to_vec()
+        }
+    }
+
+    let genesis_hash = hex::decode(
+        serde_json::from_str::<String>(
+            &request(
+                client,
+                r#"{"jsonrpc":"2.0","id":1,"method":"chain_getBlockHash","params":[0]}"#.to_owned(),
+            )
+            .await,
+        )
+        .unwrap()
+        .trim_start_matches("0x"),
+    )
+    .unwrap();
+    let runtime_version = serde_json::from_str::<serde_json::Value>(
+        &request(
+            client,
+            r#"{"jsonrpc":"2.0","id":1,"method":"state_getRuntimeVersion","params":[]}"#.to_owned(),
+        )
+        .await,
+    )
+    .unwrap();
+    let spec_version = u32::try_from(runtime_version["specVersion"].as_u64().unwrap()).unwrap();
+    let transaction_version =
+        u32::try_from(runtime_version["transactionVersion"].as_u64().unwrap()).unwrap();
+
+    // `System::remark`.
+    let mut call = vec![0, 1];
+    call.extend_from_slice(&compact(remark.len()));
+    call.extend_from_slice(remark);
+
+    // Immortal era, nonce, and tip.
+    let extra = vec![0, nonce << 2, 0];
+
+    let mut payload = call.clone();
+    payload.extend_from_slice(&extra);
+    payload.extend_from_slice(&spec_version.to_le_bytes());
+    payload.extend_from_slice(&transaction_version.to_le_bytes());
+    payload.extend_from_slice(&genesis_hash);
+    payload.extend_from_slice(&genesis_hash);
+    if payload.len() > 256 {
+        payload = blake2_rfc::blake2b::blake2b(32, &[], &payload)
+            .as_bytes()
+            .to_vec();
+    }
+
+    let mut keystore = keystore::Keystore::new(None, [0; 32]).await.unwrap();
+    let public_key = keystore.insert_sr25519_memory(
+        [keystore::KeyNamespace::Aura].into_iter(),
+        &smoldot::identity::seed_phrase::decode_sr25519_private_key("//Alice").unwrap(),
+    );
+    let signature = keystore
+        .sign(keystore::KeyNamespace::Aura, &public_key, &payload)
+        .await
+        .unwrap();
+
+    // Signed transaction, version 4.
+    let mut body = vec![0x84, 0];
+    body.extend_from_slice(&public_key);
+    body.push(1);
+    body.extend_from_slice(&signature);
+    body.extend_from_slice(&extra);
+    body.extend_from_slice(&call);
+
+    let mut transaction = compact(body.len());
+    transaction.extend_from_slice(&body);
+    transaction
+}
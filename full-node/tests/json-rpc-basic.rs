@@ -28,8 +28,13 @@ fn send_request_errs_if_malformed() {
                 keystore_memory: vec![],
                 sqlite_database_path: None,
                 sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
                 keystore_path: None,
                 json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
             },
             relay_chain: None,
             libp2p_key: Box::new([0; 32]),
@@ -37,6 +42,8 @@ fn send_request_errs_if_malformed() {
             tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
             log_callback: Arc::new(move |_, _| {}),
             jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
         })
         .await
         .unwrap();
@@ -60,8 +67,13 @@ fn send_request_works_if_unknown_request() {
                 keystore_memory: vec![],
                 sqlite_database_path: None,
                 sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
                 keystore_path: None,
                 json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
             },
             relay_chain: None,
             libp2p_key: Box::new([0; 32]),
@@ -69,6 +81,8 @@ fn send_request_works_if_unknown_request() {
             tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
             log_callback: Arc::new(move |_, _| {}),
             jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
         })
         .await
         .unwrap();
@@ -86,3 +100,211 @@ fn send_request_works_if_unknown_request() {
         }
     });
 }
+
+#[test]
+fn batch_requests() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
+                keystore_path: None,
+                json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new([0; 32]),
+            listen_addresses: Vec::new(),
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
+        })
+        .await
+        .unwrap();
+
+        // Batch containing a valid request, a notification, an invalid item, and a request to
+        // an unknown method.
+        client.send_json_rpc_request(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"system_name","params":[]}, {"jsonrpc":"2.0","method":"system_name","params":[]}, 5, {"jsonrpc":"2.0","id":2,"method":"thisjsonrpcmethoddoesntexist","params":[]}]"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let responses = serde_json::from_str::<Vec<&serde_json::value::RawValue>>(&response_raw)
+            .unwrap()
+            .into_iter()
+            .map(|r| json_rpc::parse::parse_response(r.get()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(responses.len(), 3);
+        assert!(responses
+            .iter()
+            .any(|r| matches!(r, json_rpc::parse::Response::Success { id_json: "1", .. })));
+        assert!(responses
+            .iter()
+            .any(|r| matches!(r, json_rpc::parse::Response::Error { id_json: "2", .. })));
+        assert!(responses.iter().any(|r| matches!(
+            r,
+            json_rpc::parse::Response::ParseError {
+                error_code: -32600,
+                ..
+            }
+        )));
+
+        // Batch only containing notifications, for which no response must be sent back.
+        client.send_json_rpc_request(
+            r#"[{"jsonrpc":"2.0","method":"system_name","params":[]}]"#.to_owned(),
+        );
+
+        // Empty batch.
+        client.send_json_rpc_request(r#"[]"#.to_owned());
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(matches!(
+            json_rpc::parse::parse_response(&response_raw).unwrap(),
+            json_rpc::parse::Response::ParseError {
+                error_code: -32600,
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn http_requests() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
+                keystore_path: None,
+                json_rpc_listen: Some(smoldot_full_node::JsonRpcListenConfig {
+                    address: "127.0.0.1:0".parse().unwrap(),
+                    max_json_rpc_clients: 8,
+                    cors_allowed_origins: None,
+                    max_request_size: 1024 * 1024,
+                    allow_unsafe_methods: false,
+                }),
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new([0; 32]),
+            listen_addresses: Vec::new(),
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
+        })
+        .await
+        .unwrap();
+
+        let mut socket = smol::net::TcpStream::connect(client.json_rpc_server_addr().unwrap())
+            .await
+            .unwrap();
+
+        let response = http_post(
+            &mut socket,
+            r#"{"jsonrpc":"2.0","id":1,"method":"system_name","params":[]}"#,
+        )
+        .await;
+        match json_rpc::parse::parse_response(&response).unwrap() {
+            json_rpc::parse::Response::Success { id_json, .. } => assert_eq!(id_json, "1"),
+            _ => panic!(),
+        }
+
+        // Subscriptions can't be used through HTTP. The connection is kept alive between
+        // requests.
+        let response = http_post(
+            &mut socket,
+            r#"{"jsonrpc":"2.0","id":2,"method":"chain_subscribeNewHeads","params":[]}"#,
+        )
+        .await;
+        match json_rpc::parse::parse_response(&response).unwrap() {
+            json_rpc::parse::Response::Error {
+                id_json,
+                error_code,
+                error_message,
+                ..
+            } => {
+                assert_eq!(id_json, "2");
+                assert_eq!(error_code, -32000);
+                assert_eq!(
+                    error_message,
+                    "Subscriptions aren't available over HTTP. Use a WebSocket connection instead."
+                );
+            }
+            _ => panic!(),
+        }
+
+        // Methods that modify the keystore are refused unless explicitly allowed.
+        let response = http_post(
+            &mut socket,
+            r#"{"jsonrpc":"2.0","id":3,"method":"author_insertKey","params":["aura","//Alice","0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"]}"#,
+        )
+        .await;
+        match json_rpc::parse::parse_response(&response).unwrap() {
+            json_rpc::parse::Response::Error {
+                id_json,
+                error_code,
+                ..
+            } => {
+                assert_eq!(id_json, "3");
+                assert_eq!(error_code, -32000);
+            }
+            _ => panic!(),
+        }
+    });
+}
+
+/// Sends a JSON-RPC request through HTTP and returns the body of the response.
+async fn http_post(socket: &mut smol::net::TcpStream, body: &str) -> String {
+    use smol::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    socket
+        .write_all(
+            format!(
+                "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+
+    let mut response = Vec::new();
+    loop {
+        let mut byte = [0];
+        socket.read_exact(&mut byte).await.unwrap();
+        response.push(byte[0]);
+        if response.ends_with(b"\r\n\r\n") {
+            break;
+        }
+    }
+
+    let head = String::from_utf8(response).unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 "));
+    let content_length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+
+    let mut body = vec![0; content_length];
+    socket.read_exact(&mut body).await.unwrap();
+    String::from_utf8(body).unwrap()
+}
@@ -26,8 +26,13 @@ async fn start_client() -> smoldot_full_node::Client {
             keystore_memory: vec![],
             sqlite_database_path: None,
             sqlite_cache_size: 256 * 1024 * 1024,
+            sqlite_state_pruning: None,
+            sqlite_blocks_pruning: None,
             keystore_path: None,
             json_rpc_listen: None,
+            warp_sync: false,
+            telemetry_endpoints: None,
+            allow_inbound_storage_and_call_proof_requests: true,
         },
         relay_chain: None,
         libp2p_key: Box::new([0; 32]),
@@ -35,11 +40,94 @@ async fn start_client() -> smoldot_full_node::Client {
         tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
         log_callback: Arc::new(move |_, _| {}),
         jaeger_agent: None,
+        prometheus_address: None,
+        node_name: "smoldot".into(),
     })
     .await
     .unwrap()
 }
 
+#[test]
+fn author_has_key_after_insert() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"author_hasKey","params":["0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d","aura"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "false");
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":2,"method":"author_insertKey","params":["aura","//Alice","0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":3,"method":"author_hasKey","params":["0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d","aura"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "true");
+    });
+}
+
+#[test]
+fn author_insert_key_wrong_public_key() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"author_insertKey","params":["aura","//Bob","0xd43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .is_none());
+    });
+}
+
+#[test]
+fn author_rotate_keys() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"author_rotateKeys","params":[]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let session_keys = serde_json::from_str::<String>(result_json).unwrap();
+        // The node template uses an sr25519 Aura key and an ed25519 Grandpa key.
+        assert_eq!(session_keys.len(), 2 + 2 * 64);
+
+        client.send_json_rpc_request(format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"author_hasSessionKeys","params":["{session_keys}"]}}"#
+        ));
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "true");
+    });
+}
+
 #[test]
 fn chain_spec_v1_chain_name() {
     smol::block_on(async move {
@@ -207,6 +295,185 @@ fn chain_get_header() {
     });
 }
 
+#[test]
+fn chain_get_finalized_head() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chain_getFinalizedHead","params":[]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<String>(result_json).unwrap(),
+            "0x6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f"
+        );
+    });
+}
+
+#[test]
+fn chain_head_storage_descendants_values() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chainHead_unstable_follow","params":[false]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let subscription_id = serde_json::from_str::<String>(result_json).unwrap();
+
+        let finalized_block_hash =
+            match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await)
+                .unwrap()
+            {
+                json_rpc::methods::ServerToClient::chainHead_unstable_followEvent {
+                    result:
+                        json_rpc::methods::FollowEvent::Initialized {
+                            finalized_block_hash,
+                            ..
+                        },
+                    ..
+                } => finalized_block_hash,
+                _ => panic!(),
+            };
+
+        client.send_json_rpc_request(format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"chainHead_unstable_storage","params":["{}","0x{}",[{{"key":"0x26aa394eea5630e07c48ae0c9558cef7","type":"descendantsValues"}}]]}}"#,
+            subscription_id,
+            hex::encode(finalized_block_hash.0)
+        ));
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let operation_id =
+            match serde_json::from_str::<json_rpc::methods::ChainHeadStorageReturn>(result_json)
+                .unwrap()
+            {
+                json_rpc::methods::ChainHeadStorageReturn::Started {
+                    operation_id,
+                    discarded_items: 0,
+                } => operation_id.into_owned(),
+                _ => panic!(),
+            };
+
+        let mut num_items = 0;
+        loop {
+            match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await)
+                .unwrap()
+            {
+                json_rpc::methods::ServerToClient::chainHead_unstable_followEvent {
+                    result:
+                        json_rpc::methods::FollowEvent::OperationStorageItems {
+                            operation_id: op_id,
+                            items,
+                        },
+                    ..
+                } => {
+                    assert_eq!(op_id, operation_id);
+                    for item in items {
+                        assert!(item.key.0.starts_with(&[
+                            38, 170, 57, 78, 234, 86, 48, 224, 124, 72, 174, 12, 149, 88, 206, 247
+                        ]));
+                        assert!(item.value.is_some());
+                        num_items += 1;
+                    }
+                }
+                json_rpc::methods::ServerToClient::chainHead_unstable_followEvent {
+                    result:
+                        json_rpc::methods::FollowEvent::OperationStorageDone {
+                            operation_id: op_id,
+                        },
+                    ..
+                } => {
+                    assert_eq!(op_id, operation_id);
+                    break;
+                }
+                _ => panic!(),
+            }
+        }
+
+        assert_eq!(num_items, 18);
+    });
+}
+
+#[test]
+fn chain_head_call() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"chainHead_unstable_follow","params":[true]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let subscription_id = serde_json::from_str::<String>(result_json).unwrap();
+
+        let finalized_block_hash =
+            match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await)
+                .unwrap()
+            {
+                json_rpc::methods::ServerToClient::chainHead_unstable_followEvent {
+                    result:
+                        json_rpc::methods::FollowEvent::Initialized {
+                            finalized_block_hash,
+                            ..
+                        },
+                    ..
+                } => finalized_block_hash,
+                _ => panic!(),
+            };
+
+        client.send_json_rpc_request(format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"chainHead_unstable_call","params":["{}","0x{}","Metadata_metadata","0x"]}}"#,
+            subscription_id,
+            hex::encode(finalized_block_hash.0)
+        ));
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert!(matches!(
+            serde_json::from_str::<json_rpc::methods::ChainHeadBodyCallReturn>(result_json)
+                .unwrap(),
+            json_rpc::methods::ChainHeadBodyCallReturn::Started { .. }
+        ));
+
+        match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await).unwrap()
+        {
+            json_rpc::methods::ServerToClient::chainHead_unstable_followEvent {
+                result: json_rpc::methods::FollowEvent::OperationCallDone { output, .. },
+                ..
+            } => {
+                assert_eq!(
+                    hex::encode(
+                        json_rpc::methods::remove_metadata_length_prefix(&output.0).unwrap()
+                    ),
+                    include_str!("./substrate-node-template-metadata.hex")
+                        .trim()
+                        .trim_start_matches("0x")
+                );
+            }
+            _ => panic!(),
+        }
+    });
+}
+
 #[test]
 fn state_get_metadata() {
     smol::block_on(async move {
@@ -254,6 +521,97 @@ fn state_get_runtime_version() {
     });
 }
 
+#[test]
+fn state_get_keys() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        // The `:code` key is always present.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"state_getKeys","params":["0x3a"]}"#.to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert!(serde_json::from_str::<Vec<String>>(result_json)
+            .unwrap()
+            .contains(&"0x3a636f6465".to_owned()));
+    });
+}
+
+#[test]
+fn state_get_storage() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        // The `:code` key is always present.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"state_getStorage","params":["0x3a636f6465"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert!(serde_json::from_str::<String>(result_json)
+            .unwrap()
+            .starts_with("0x"));
+
+        // Unknown key.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":2,"method":"state_getStorage","params":["0xdeadbeef"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        assert_eq!(result_json, "null");
+
+        // Unknown block.
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":3,"method":"state_getStorage","params":["0x3a636f6465", "0xdeaddeaddeaddeaddeaddeaddeaddeaddeaddeaddeaddeaddeaddeaddeaddead"]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        assert!(matches!(
+            json_rpc::parse::parse_response(&response_raw).unwrap(),
+            json_rpc::parse::Response::Error {
+                error_code: -32602, // Invalid parameter error code.
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn state_get_read_proof() {
+    smol::block_on(async move {
+        let client = start_client().await;
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"state_getReadProof","params":[["0x3a636f6465", "0xdeadbeef"]]}"#
+                .to_owned(),
+        );
+        let response_raw = client.next_json_rpc_response().await;
+        let (_, result_json) = json_rpc::parse::parse_response(&response_raw)
+            .unwrap()
+            .into_success()
+            .unwrap();
+        let decoded = serde_json::from_str::<json_rpc::methods::ReadProof>(result_json).unwrap();
+        assert_eq!(
+            decoded.at.0,
+            hex::decode("6bf30d04495c16ef053de4ac74eac35dfd6473e4907810f450bea1b976ac518f")
+                .unwrap()[..]
+        );
+        assert!(!decoded.proof.is_empty());
+    });
+}
+
 #[test]
 fn state_get_keys_paged_basic() {
     smol::block_on(async move {
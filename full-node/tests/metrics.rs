@@ -0,0 +1,92 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use smol::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use std::sync::Arc;
+
+#[test]
+fn prometheus_metrics() {
+    smol::block_on(async move {
+        let client = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
+                keystore_path: None,
+                json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new([0; 32]),
+            listen_addresses: Vec::new(),
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new(move |_, _| {}),
+            jaeger_agent: None,
+            prometheus_address: Some("127.0.0.1:0".parse().unwrap()),
+            node_name: "smoldot".into(),
+        })
+        .await
+        .unwrap();
+
+        client.send_json_rpc_request(
+            r#"{"jsonrpc":"2.0","id":1,"method":"system_name","params":[]}"#.to_owned(),
+        );
+        let _ = client.next_json_rpc_response().await;
+
+        let mut socket = smol::net::TcpStream::connect(client.prometheus_server_addr().unwrap())
+            .await
+            .unwrap();
+        socket
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+
+        let mut response = Vec::new();
+        loop {
+            let mut byte = [0];
+            socket.read_exact(&mut byte).await.unwrap();
+            response.push(byte[0]);
+            if response.ends_with(b"\r\n\r\n") {
+                break;
+            }
+        }
+        let head = String::from_utf8(response).unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 "));
+        let content_length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        let mut body = vec![0; content_length];
+        socket.read_exact(&mut body).await.unwrap();
+        let body = String::from_utf8(body).unwrap();
+
+        assert!(body
+            .lines()
+            .any(|l| l == r#"smoldot_best_block_height{chain="local_testnet"} 0"#));
+        assert!(body.lines().any(|l| l
+            == r#"smoldot_json_rpc_requests_total{chain="local_testnet",method="system_name"} 1"#));
+        assert!(body.ends_with("# EOF\n"));
+    });
+}
@@ -0,0 +1,176 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use core::{iter, num::NonZeroU32, time::Duration};
+use smoldot::libp2p::{
+    connection::NoiseKey,
+    peer_id::{PeerId, PublicKey},
+};
+use std::sync::{Arc, Mutex};
+
+// The light client doesn't store anything and fetches storage values and runtime call results
+// from full nodes, which makes it a convenient way to send storage and call proof requests.
+
+#[test]
+fn storage_and_call_proofs_answered() {
+    smol::block_on(async move {
+        let libp2p_key = [1; 32];
+        let peer_id = PeerId::from_public_key(&PublicKey::Ed25519(
+            *NoiseKey::new(&libp2p_key, &[0; 32]).libp2p_public_ed25519_key(),
+        ));
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let _full_node = smoldot_full_node::start(smoldot_full_node::Config {
+            chain: smoldot_full_node::ChainConfig {
+                chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+                additional_bootnodes: Vec::new(),
+                keystore_memory: vec![],
+                sqlite_database_path: None,
+                sqlite_cache_size: 256 * 1024 * 1024,
+                sqlite_state_pruning: None,
+                sqlite_blocks_pruning: None,
+                keystore_path: None,
+                json_rpc_listen: None,
+                warp_sync: false,
+                telemetry_endpoints: None,
+                allow_inbound_storage_and_call_proof_requests: true,
+            },
+            relay_chain: None,
+            libp2p_key: Box::new(libp2p_key),
+            listen_addresses: vec![format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()],
+            tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+            log_callback: Arc::new({
+                let logs = logs.clone();
+                move |_, message| logs.lock().unwrap().push(message)
+            }),
+            jaeger_agent: None,
+            prometheus_address: None,
+            node_name: "smoldot".into(),
+        })
+        .await
+        .unwrap();
+
+        let chain_spec = {
+            let mut chain_spec = serde_json::from_slice::<serde_json::Value>(include_bytes!(
+                "./substrate-node-template.json"
+            ))
+            .unwrap();
+            chain_spec["bootNodes"] =
+                serde_json::json!([format!("/ip4/127.0.0.1/tcp/{port}/p2p/{peer_id}")]);
+            chain_spec.to_string()
+        };
+
+        let mut light_client = smoldot_light::Client::new(
+            smoldot_light::platform::default::DefaultPlatform::new("smoldot".into(), "0".into()),
+        );
+        let smoldot_light::AddChainSuccess {
+            chain_id,
+            json_rpc_responses,
+        } = light_client
+            .add_chain(smoldot_light::AddChainConfig {
+                specification: &chain_spec,
+                json_rpc: smoldot_light::AddChainConfigJsonRpc::Enabled {
+                    max_pending_requests: NonZeroU32::new(16).unwrap(),
+                    max_subscriptions: 16,
+                },
+                potential_relay_chains: iter::empty(),
+                database_content: "",
+                user_data: (),
+                enable_telemetry: false,
+            })
+            .unwrap();
+        let mut json_rpc_responses = json_rpc_responses.unwrap();
+
+        // Wait for the light client to be connected to the full node.
+        loop {
+            light_client
+                .json_rpc_request(
+                    r#"{"jsonrpc":"2.0","id":0,"method":"system_health","params":[]}"#,
+                    chain_id,
+                )
+                .unwrap();
+            if result(&json_rpc_responses.next().await.unwrap())["peers"] != 0 {
+                break;
+            }
+            smol::Timer::after(Duration::from_millis(100)).await;
+        }
+
+        light_client
+            .json_rpc_request(
+                r#"{"jsonrpc":"2.0","id":1,"method":"chain_getBlockHash","params":[0]}"#,
+                chain_id,
+            )
+            .unwrap();
+        let genesis_hash = result(&json_rpc_responses.next().await.unwrap());
+
+        // Storage proof request.
+        // The light client might not be ready to send requests to the full node right after
+        // having connected to it, in which case the request fails and is tried again.
+        let mut attempts = 0;
+        let code = loop {
+            light_client
+                .json_rpc_request(
+                    format!(
+                        r#"{{"jsonrpc":"2.0","id":2,"method":"state_getStorage","params":["0x{}",{}]}}"#,
+                        hex::encode(b":code"),
+                        genesis_hash
+                    ),
+                    chain_id,
+                )
+                .unwrap();
+            let code = result(&json_rpc_responses.next().await.unwrap());
+            if !code.is_null() {
+                break code;
+            }
+            attempts += 1;
+            assert!(attempts < 50);
+            smol::Timer::after(Duration::from_millis(100)).await;
+        };
+        assert!(code.as_str().unwrap().len() > 2);
+
+        // Call proof request.
+        light_client
+            .json_rpc_request(
+                format!(
+                    r#"{{"jsonrpc":"2.0","id":3,"method":"state_call","params":["Core_version","0x",{}]}}"#,
+                    genesis_hash
+                ),
+                chain_id,
+            )
+            .unwrap();
+        let version = result(&json_rpc_responses.next().await.unwrap());
+        assert!(version.as_str().unwrap().len() > 2);
+
+        let logs = logs.lock().unwrap();
+        assert!(logs
+            .iter()
+            .any(|l| l.starts_with("incoming-storage-proof-request;")));
+        assert!(logs
+            .iter()
+            .any(|l| l.starts_with("incoming-call-proof-request;")));
+    });
+}
+
+/// Extracts the `result` field of the given JSON-RPC response.
+fn result(response: &str) -> serde_json::Value {
+    serde_json::from_str::<serde_json::Value>(response).unwrap()["result"].clone()
+}
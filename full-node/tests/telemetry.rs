@@ -0,0 +1,158 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use smol::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use std::sync::Arc;
+
+async fn start_client(
+    telemetry_endpoint: String,
+    keystore_memory: Vec<Box<[u8; 64]>>,
+) -> smoldot_full_node::Client {
+    smoldot_full_node::start(smoldot_full_node::Config {
+        chain: smoldot_full_node::ChainConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            additional_bootnodes: Vec::new(),
+            keystore_memory,
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            sqlite_state_pruning: None,
+            sqlite_blocks_pruning: None,
+            keystore_path: None,
+            json_rpc_listen: None,
+            warp_sync: false,
+            telemetry_endpoints: Some(vec![(telemetry_endpoint, 0)]),
+            allow_inbound_storage_and_call_proof_requests: true,
+        },
+        relay_chain: None,
+        libp2p_key: Box::new([0; 32]),
+        listen_addresses: Vec::new(),
+        tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+        log_callback: Arc::new(move |_, _| {}),
+        jaeger_agent: None,
+        prometheus_address: None,
+        node_name: "telemetry-test-node".into(),
+    })
+    .await
+    .unwrap()
+}
+
+#[test]
+fn system_connected_sent() {
+    smol::block_on(async move {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let _client = start_client(format!("ws://{server_addr}/submit"), Vec::new()).await;
+
+        let mut receiver = accept_connection(&listener).await;
+
+        // The first message must be `system.connected`.
+        let mut payload = Vec::new();
+        receiver.receive_data(&mut payload).await.unwrap();
+
+        let message = serde_json::from_slice::<serde_json::Value>(&payload).unwrap();
+        assert_eq!(message["payload"]["msg"], "system.connected");
+        assert_eq!(message["payload"]["name"], "telemetry-test-node");
+        assert_eq!(message["payload"]["chain"], "Local Testnet");
+    });
+}
+
+#[test]
+fn invalid_handshake_response_refused() {
+    smol::block_on(async move {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let _client = start_client(format!("ws://{server_addr}/submit"), Vec::new()).await;
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        // Read the WebSocket handshake request and answer it without a `Sec-WebSocket-Accept`
+        // header.
+        let mut request = Vec::new();
+        loop {
+            let mut byte = [0];
+            socket.read_exact(&mut byte).await.unwrap();
+            request.push(byte[0]);
+            if request.ends_with(b"\r\n\r\n") {
+                break;
+            }
+        }
+        socket
+            .write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                Connection: Upgrade\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        // The client must close the connection without sending anything.
+        let mut remaining = Vec::new();
+        let _ = socket.read_to_end(&mut remaining).await;
+        assert!(remaining.is_empty());
+    });
+}
+
+#[test]
+fn locally_authored_block_import_reported_as_own() {
+    smol::block_on(async move {
+        let listener = smol::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let _client = start_client(
+            format!("ws://{server_addr}/submit"),
+            vec![smoldot::identity::seed_phrase::decode_sr25519_private_key("//Alice").unwrap()],
+        )
+        .await;
+
+        let mut receiver = accept_connection(&listener).await;
+
+        // Alice is the only authority of the chain, and thus all the new blocks are authored
+        // locally.
+        loop {
+            let mut payload = Vec::new();
+            receiver.receive_data(&mut payload).await.unwrap();
+
+            let message = serde_json::from_slice::<serde_json::Value>(&payload).unwrap();
+            if message["payload"]["msg"] == "block.import" {
+                assert_eq!(message["payload"]["origin"], "Own");
+                break;
+            }
+        }
+    });
+}
+
+/// Accepts a connection on the given listener and performs the WebSocket handshake.
+async fn accept_connection(
+    listener: &smol::net::TcpListener,
+) -> soketto::Receiver<smol::net::TcpStream> {
+    let (socket, _) = listener.accept().await.unwrap();
+
+    let mut server = soketto::handshake::Server::new(socket);
+    let request = server.receive_request().await.unwrap();
+    assert_eq!(request.path(), "/submit");
+    let key = request.key();
+    server
+        .send_response(&soketto::handshake::server::Response::Accept {
+            key,
+            protocol: None,
+        })
+        .await
+        .unwrap();
+    let (_, receiver) = server.into_builder().finish();
+    receiver
+}
@@ -0,0 +1,158 @@
+// Smoldot
+// Copyright (C) 2023  Pierre Krieger
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use smoldot::json_rpc;
+use std::sync::Arc;
+
+mod common;
+use common::{alice_remark, request};
+
+async fn start_client(authoring: bool) -> smoldot_full_node::Client {
+    smoldot_full_node::start(smoldot_full_node::Config {
+        chain: smoldot_full_node::ChainConfig {
+            chain_spec: (&include_bytes!("./substrate-node-template.json")[..]).into(),
+            additional_bootnodes: Vec::new(),
+            keystore_memory: if authoring {
+                vec![smoldot::identity::seed_phrase::decode_sr25519_private_key("//Alice").unwrap()]
+            } else {
+                vec![]
+            },
+            sqlite_database_path: None,
+            sqlite_cache_size: 256 * 1024 * 1024,
+            sqlite_state_pruning: None,
+            sqlite_blocks_pruning: None,
+            keystore_path: None,
+            json_rpc_listen: None,
+            warp_sync: false,
+            telemetry_endpoints: None,
+            allow_inbound_storage_and_call_proof_requests: true,
+        },
+        relay_chain: None,
+        libp2p_key: Box::new([0; 32]),
+        listen_addresses: Vec::new(),
+        tasks_executor: Arc::new(|task| smol::spawn(task).detach()),
+        log_callback: Arc::new(move |_, _| {}),
+        jaeger_agent: None,
+        prometheus_address: None,
+        node_name: "smoldot".into(),
+    })
+    .await
+    .unwrap()
+}
+
+#[test]
+fn invalid_transaction_rejected() {
+    smol::block_on(async move {
+        let client = start_client(false).await;
+
+        let subscription = serde_json::from_str::<String>(
+            &request(
+                &client,
+                r#"{"jsonrpc":"2.0","id":1,"method":"transaction_unstable_submitAndWatch","params":["0x0102"]}"#
+                    .to_owned(),
+            )
+            .await,
+        )
+        .unwrap();
+
+        match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await).unwrap()
+        {
+            json_rpc::methods::ServerToClient::transaction_unstable_watchEvent {
+                subscription: s,
+                result:
+                    json_rpc::methods::TransactionWatchEvent::Invalid { .. }
+                    | json_rpc::methods::TransactionWatchEvent::Error { .. },
+            } => assert_eq!(s, subscription),
+            other => panic!("{other:?}"),
+        }
+    });
+}
+
+#[test]
+fn transaction_validated() {
+    smol::block_on(async move {
+        let client = start_client(false).await;
+        let transaction = alice_remark(&client, 0, b"hello").await;
+
+        let subscription = serde_json::from_str::<String>(
+            &request(
+                &client,
+                format!(
+                    r#"{{"jsonrpc":"2.0","id":1,"method":"transaction_unstable_submitAndWatch","params":["0x{}"]}}"#,
+                    hex::encode(&transaction)
+                ),
+            )
+            .await,
+        )
+        .unwrap();
+
+        match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await).unwrap()
+        {
+            json_rpc::methods::ServerToClient::transaction_unstable_watchEvent {
+                subscription: s,
+                result: json_rpc::methods::TransactionWatchEvent::Validated {},
+            } => assert_eq!(s, subscription),
+            other => panic!("{other:?}"),
+        }
+    });
+}
+
+#[test]
+fn transaction_included_in_authored_block() {
+    smol::block_on(async move {
+        let client = start_client(true).await;
+        let transaction = alice_remark(&client, 0, b"hello").await;
+
+        let _ = request(
+            &client,
+            format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"transaction_unstable_submitAndWatch","params":["0x{}"]}}"#,
+                hex::encode(&transaction)
+            ),
+        )
+        .await;
+
+        let mut validated = false;
+        loop {
+            match json_rpc::methods::parse_notification(&client.next_json_rpc_response().await)
+                .unwrap()
+            {
+                json_rpc::methods::ServerToClient::transaction_unstable_watchEvent {
+                    result: json_rpc::methods::TransactionWatchEvent::Validated {},
+                    ..
+                } => validated = true,
+                json_rpc::methods::ServerToClient::transaction_unstable_watchEvent {
+                    result: json_rpc::methods::TransactionWatchEvent::Broadcasted { .. },
+                    ..
+                } => {}
+                json_rpc::methods::ServerToClient::transaction_unstable_watchEvent {
+                    result:
+                        json_rpc::methods::TransactionWatchEvent::BestChainBlockIncluded {
+                            block: Some(block),
+                        },
+                    ..
+                } => {
+                    assert!(validated);
+                    // The first transaction of the block is the timestamp inherent.
+                    assert_ne!(block.index, 0);
+                    break;
+                }
+                other => panic!("{other:?}"),
+            }
+        }
+    });
+}
@@ -16,7 +16,7 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::header;
-use core::{num::NonZeroU64, time::Duration};
+use core::{cmp, num::NonZeroU64, time::Duration};
 
 /// Configuration for [`next_slot_claim`].
 pub struct Config<'a, TLocAuth> {
@@ -27,6 +27,10 @@ pub struct Config<'a, TLocAuth> {
     /// Duration, in milliseconds, of an Aura slot.
     pub slot_duration: NonZeroU64,
 
+    /// Slot number of the parent of the block to author. `None` if the parent is the genesis
+    /// block.
+    pub parent_slot_number: Option<u64>,
+
     /// List of the Aura authorities allowed to produce a block. This is either the same as the
     /// ones of the current best block, or a new list if the current best block contains an
     /// authorities list change digest item.
@@ -62,6 +66,12 @@ pub fn next_slot_claim<'a>(
     )
     .unwrap();
 
+    // A block can only be authored in a slot strictly superior to the one of its parent.
+    let current_slot = match config.parent_slot_number {
+        Some(parent_slot_number) => cmp::max(current_slot, parent_slot_number.checked_add(1)?),
+        None => current_slot,
+    };
+
     let current_slot_index =
         usize::try_from(current_slot.checked_rem(u64::try_from(num_current_authorities).unwrap())?)
             .unwrap();
@@ -97,7 +107,7 @@ pub fn next_slot_claim<'a>(
         let slot_start_from_unix_epoch =
             Duration::from_millis(slot_number.checked_mul(config.slot_duration.get()).unwrap());
         let slot_end_from_unix_epoch =
-            slot_start_from_unix_epoch + Duration::from_secs(config.slot_duration.get());
+            slot_start_from_unix_epoch + Duration::from_millis(config.slot_duration.get());
         debug_assert!(slot_end_from_unix_epoch > config.now_from_unix_epoch);
 
         Some(SlotClaim {
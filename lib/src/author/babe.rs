@@ -0,0 +1,492 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{chain::chain_information, header, verify::babe as verify_babe};
+
+use alloc::vec::Vec;
+use core::{cmp, iter, num::NonZeroU64, time::Duration};
+
+/// Label of the VRF transcript that must be signed in order to claim a slot.
+///
+/// See also [`SlotClaim::vrf_transcript_items`].
+pub const VRF_TRANSCRIPT_LABEL: &[u8] = b"BABE";
+
+/// Configuration for [`next_slot_claim`].
+pub struct Config<'a, TLocAuth> {
+    /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
+    /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
+    pub now_from_unix_epoch: Duration,
+
+    /// Duration, in milliseconds, of a Babe slot.
+    pub slot_duration: NonZeroU64,
+
+    /// Number of slots per epoch in the Babe configuration.
+    pub slots_per_epoch: NonZeroU64,
+
+    /// Slot number of the parent of the block to author. `None` if the parent is the genesis
+    /// block.
+    pub parent_slot_number: Option<u64>,
+
+    /// Epoch the parent of the block to author belongs to. Must be `None` if and only if the
+    /// parent is the genesis block.
+    pub parent_block_epoch: Option<chain_information::BabeEpochInformationRef<'a>>,
+
+    /// Epoch that follows the epoch the parent of the block to author belongs to.
+    pub parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+
+    /// Iterator to the list of Sr25519 public keys available locally.
+    ///
+    /// Must implement `Iterator<Item = &[u8; 32]>`.
+    pub local_authorities: TLocAuth,
+}
+
+/// Calculates the earliest slot that one of the authorities in [`Config::local_authorities`]
+/// might be able to claim.
+///
+/// Returns `None` if none of the local authorities are allowed to produce blocks.
+///
+/// Contrary to Aura, the fact that a slot can be claimed isn't known ahead of time. Primary
+/// slot claims require generating a VRF output and comparing it with a threshold. The value
+/// returned by this function only indicates the slots during which the local authorities have a
+/// chance to produce a block. See [`SlotClaim::primary_candidates`] and
+/// [`SlotClaim::secondary_candidate`].
+///
+/// Keep in mind that, as the best block changes, the list of authorities might change, in which
+/// case this function should be called again.
+pub fn next_slot_claim<'a>(
+    config: Config<'a, impl Iterator<Item = &'a [u8; 32]>>,
+) -> Option<SlotClaim> {
+    let local_authorities = config.local_authorities.collect::<Vec<_>>();
+
+    // Note that this calculation (and some other calculations down below) can overflow in the
+    // very distant future. This is considered acceptable.
+    let current_slot = u64::try_from(
+        config.now_from_unix_epoch.as_millis() / u128::from(config.slot_duration.get()),
+    )
+    .unwrap();
+
+    let mut slot_number = match config.parent_slot_number {
+        Some(parent_slot_number) => cmp::max(current_slot, parent_slot_number.checked_add(1)?),
+        None => current_slot,
+    };
+
+    loop {
+        // Determine the epoch the slot belongs to. This mirrors what is done when verifying
+        // a block.
+        let (epoch_info, is_next_epoch) = match &config.parent_block_epoch {
+            Some(parent_epoch)
+                if matches!(
+                    config.parent_block_next_epoch.start_slot_number,
+                    Some(start) if start > slot_number
+                ) =>
+            {
+                (parent_epoch, false)
+            }
+            _ => (&config.parent_block_next_epoch, true),
+        };
+
+        // Check if the current slot number indicates that entire epochs have been skipped.
+        let skipped_epochs = match epoch_info.start_slot_number {
+            Some(start) => slot_number.saturating_sub(start) / config.slots_per_epoch.get(),
+            None => 0,
+        };
+        let epoch_index = epoch_info.epoch_index.checked_add(skipped_epochs)?;
+
+        let num_authorities = epoch_info.authorities.len();
+        if num_authorities == 0 {
+            return None;
+        }
+
+        // Index of the authority that can claim the slot as a secondary slot.
+        let secondary_authority_index = if !matches!(
+            epoch_info.allowed_slots,
+            header::BabeAllowedSlots::PrimarySlots
+        ) {
+            let hash = {
+                let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+                hash.update(epoch_info.randomness);
+                hash.update(&slot_number.to_le_bytes());
+                hash.finalize()
+            };
+
+            let hash = num_bigint::BigUint::from_bytes_be(hash.as_bytes());
+            let authorities_len = num_bigint::BigUint::from(num_authorities);
+            num_traits::cast::ToPrimitive::to_u32(&(hash % authorities_len))
+        } else {
+            None
+        };
+
+        let mut primary_candidates = Vec::new();
+        let mut secondary_candidate = None;
+        let mut any_local_authority = false;
+
+        for (local_authorities_index, local_pub_key) in local_authorities.iter().enumerate() {
+            // TODO: O(n) complexity
+            let (authority_index, authority) = match epoch_info
+                .authorities
+                .clone()
+                .enumerate()
+                .find(|(_, a)| a.public_key == *local_pub_key)
+            {
+                Some((idx, a)) => (u32::try_from(idx).unwrap(), a),
+                None => continue,
+            };
+
+            any_local_authority = true;
+
+            if authority.weight != 0 {
+                primary_candidates.push(PrimaryCandidate {
+                    local_authorities_index,
+                    authority_index,
+                    threshold: verify_babe::calculate_primary_threshold(
+                        epoch_info.c,
+                        epoch_info.authorities.clone().map(|a| a.weight),
+                        authority.weight,
+                    ),
+                });
+            }
+
+            if secondary_authority_index == Some(authority_index) && secondary_candidate.is_none() {
+                secondary_candidate = Some(SecondaryCandidate {
+                    local_authorities_index,
+                    authority_index,
+                    with_vrf: matches!(
+                        epoch_info.allowed_slots,
+                        header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots
+                    ),
+                });
+            }
+        }
+
+        if !primary_candidates.is_empty() || secondary_candidate.is_some() {
+            let slot_start_from_unix_epoch =
+                Duration::from_millis(slot_number.checked_mul(config.slot_duration.get())?);
+            let slot_end_from_unix_epoch =
+                slot_start_from_unix_epoch + Duration::from_millis(config.slot_duration.get());
+            debug_assert!(slot_end_from_unix_epoch > config.now_from_unix_epoch);
+
+            return Some(SlotClaim {
+                slot_start_from_unix_epoch,
+                slot_end_from_unix_epoch,
+                slot_number,
+                epoch_index,
+                randomness: *epoch_info.randomness,
+                primary_candidates,
+                secondary_candidate,
+            });
+        }
+
+        if any_local_authority && secondary_authority_index.is_some() {
+            // Some local authorities can claim secondary slots but not this one. Try the next
+            // slot.
+            slot_number = slot_number.checked_add(1)?;
+        } else if !is_next_epoch {
+            // None of the local authorities can claim slots in the current epoch. Jump to the
+            // start of the next epoch, whose authorities might be different.
+            slot_number = config.parent_block_next_epoch.start_slot_number?;
+        } else {
+            return None;
+        }
+    }
+}
+
+/// Slot happening now or in the future and that the authorities in
+/// [`Config::local_authorities`] might be able to claim.
+///
+/// See also [`next_slot_claim`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotClaim {
+    /// UNIX time when the slot starts. Can be inferior to the value passed to
+    /// [`Config::now_from_unix_epoch`] if the slot has already started.
+    pub slot_start_from_unix_epoch: Duration,
+    /// UNIX time when the slot ends. Always superior to the value passed to
+    /// [`Config::now_from_unix_epoch`].
+    pub slot_end_from_unix_epoch: Duration,
+    /// Slot number of the claim. Used when building the block.
+    pub slot_number: u64,
+    /// Index of the epoch the slot belongs to, taking skipped epochs into account.
+    pub epoch_index: u64,
+    /// Randomness of the epoch the slot belongs to.
+    pub randomness: [u8; 32],
+    /// List of the local authorities that can attempt a primary slot claim, ordered by
+    /// [`PrimaryCandidate::local_authorities_index`].
+    pub primary_candidates: Vec<PrimaryCandidate>,
+    /// Local authority that can claim the slot as a secondary slot, if any.
+    pub secondary_candidate: Option<SecondaryCandidate>,
+}
+
+impl SlotClaim {
+    /// Returns the items of the VRF transcript that the authorities must sign in order to claim
+    /// the slot. The label of the transcript is [`VRF_TRANSCRIPT_LABEL`].
+    ///
+    /// The same transcript is used for both primary and secondary VRF slot claims.
+    pub fn vrf_transcript_items(
+        &'_ self,
+    ) -> impl Iterator<Item = (&'static [u8], either::Either<&'_ [u8], u64>)> + '_ {
+        iter::once((&b"slot number"[..], either::Right(self.slot_number)))
+            .chain(iter::once((
+                &b"current epoch"[..],
+                either::Right(self.epoch_index),
+            )))
+            .chain(iter::once((
+                &b"chain randomness"[..],
+                either::Left(&self.randomness[..]),
+            )))
+    }
+
+    /// Returns `true` if the given VRF output, generated by the authority of the given
+    /// candidate, is below the primary slot claim threshold.
+    ///
+    /// The VRF output must have been generated using the transcript whose items are returned
+    /// by [`SlotClaim::vrf_transcript_items`]. The VRF proof is not verified.
+    ///
+    /// Returns `false` if the public key or the VRF output are invalid.
+    pub fn is_primary_claim(
+        &self,
+        candidate: &PrimaryCandidate,
+        public_key: &[u8; 32],
+        vrf_output: &[u8; 32],
+    ) -> bool {
+        let Ok(public_key) = schnorrkel::PublicKey::from_bytes(public_key) else {
+            return false;
+        };
+        let Ok(vrf_output) = schnorrkel::vrf::VRFPreOut::from_bytes(&vrf_output[..]) else {
+            return false;
+        };
+
+        let transcript = {
+            let mut transcript = merlin::Transcript::new(VRF_TRANSCRIPT_LABEL);
+            transcript.append_u64(b"slot number", self.slot_number);
+            transcript.append_u64(b"current epoch", self.epoch_index);
+            transcript.append_message(b"chain randomness", &self.randomness[..]);
+            transcript
+        };
+
+        let Ok(vrf_in_out) = vrf_output.attach_input_hash(&public_key, transcript) else {
+            return false;
+        };
+
+        u128::from_le_bytes(vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
+            < candidate.threshold
+    }
+}
+
+/// Local authority that can attempt to claim a slot as a primary slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimaryCandidate {
+    /// Index within [`Config::local_authorities`] of the authority.
+    pub local_authorities_index: usize,
+    /// Index of the authority within the list of authorities of the epoch.
+    pub authority_index: u32,
+    /// Value the VRF output must be below in order for the claim to be valid.
+    pub threshold: u128,
+}
+
+/// Local authority that can claim a slot as a secondary slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecondaryCandidate {
+    /// Index within [`Config::local_authorities`] of the authority.
+    pub local_authorities_index: usize,
+    /// Index of the authority within the list of authorities of the epoch.
+    pub authority_index: u32,
+    /// If `true`, the claim must contain a VRF output and proof.
+    pub with_vrf: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_slot_claim, Config, SlotClaim, VRF_TRANSCRIPT_LABEL};
+    use crate::{chain::chain_information, header, verify::babe as verify_babe};
+    use core::{iter, num::NonZeroU64, time::Duration};
+
+    fn claim(
+        authorities: &[header::BabeAuthority],
+        allowed_slots: header::BabeAllowedSlots,
+        c: (u64, u64),
+        local_authority: &[u8; 32],
+    ) -> Option<SlotClaim> {
+        let randomness = [7; 32];
+        next_slot_claim(Config {
+            // Slot 1000.
+            now_from_unix_epoch: Duration::from_millis(6_000_500),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+            slots_per_epoch: NonZeroU64::new(200).unwrap(),
+            parent_slot_number: Some(999),
+            parent_block_epoch: Some(chain_information::BabeEpochInformationRef {
+                epoch_index: 5,
+                start_slot_number: Some(1000 - 10),
+                authorities: header::BabeAuthoritiesIter::from_slice(authorities),
+                randomness: &randomness,
+                c,
+                allowed_slots,
+            }),
+            parent_block_next_epoch: chain_information::BabeEpochInformationRef {
+                epoch_index: 6,
+                start_slot_number: Some(1190),
+                authorities: header::BabeAuthoritiesIter::from_slice(authorities),
+                randomness: &randomness,
+                c,
+                allowed_slots,
+            },
+            local_authorities: iter::once(local_authority),
+        })
+    }
+
+    fn keypair(seed: u8) -> schnorrkel::Keypair {
+        schnorrkel::MiniSecretKey::from_bytes(&[seed; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+    }
+
+    #[test]
+    fn primary_claim() {
+        let local = keypair(1);
+        let authorities = [
+            header::BabeAuthority {
+                public_key: keypair(2).public.to_bytes(),
+                weight: 1,
+            },
+            header::BabeAuthority {
+                public_key: local.public.to_bytes(),
+                weight: 1,
+            },
+        ];
+
+        let claim = claim(
+            &authorities,
+            header::BabeAllowedSlots::PrimarySlots,
+            (1, 4),
+            &local.public.to_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(claim.slot_number, 1000);
+        assert_eq!(claim.epoch_index, 5);
+        assert_eq!(claim.slot_start_from_unix_epoch, Duration::from_secs(6000));
+        assert_eq!(claim.slot_end_from_unix_epoch, Duration::from_secs(6006));
+        assert!(claim.secondary_candidate.is_none());
+        assert_eq!(claim.primary_candidates.len(), 1);
+        assert_eq!(claim.primary_candidates[0].local_authorities_index, 0);
+        assert_eq!(claim.primary_candidates[0].authority_index, 1);
+        assert_eq!(
+            claim.primary_candidates[0].threshold,
+            verify_babe::calculate_primary_threshold((1, 4), [1, 1].into_iter(), 1)
+        );
+
+        let mut transcript = merlin::Transcript::new(VRF_TRANSCRIPT_LABEL);
+        for (label, value) in claim.vrf_transcript_items() {
+            match value {
+                either::Left(bytes) => transcript.append_message(label, bytes),
+                either::Right(num) => transcript.append_u64(label, num),
+            }
+        }
+        let (vrf_in_out, _, _) = local.vrf_sign(transcript);
+        assert_eq!(
+            claim.is_primary_claim(
+                &claim.primary_candidates[0],
+                &local.public.to_bytes(),
+                &vrf_in_out.to_preout().to_bytes()
+            ),
+            u128::from_le_bytes(vrf_in_out.make_bytes::<[u8; 16]>(b"substrate-babe-vrf"))
+                < claim.primary_candidates[0].threshold
+        );
+    }
+
+    #[test]
+    fn not_an_authority() {
+        let authorities = [header::BabeAuthority {
+            public_key: keypair(2).public.to_bytes(),
+            weight: 1,
+        }];
+
+        assert!(claim(
+            &authorities,
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+            (1, 4),
+            &keypair(1).public.to_bytes(),
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn secondary_plain_claim() {
+        let local = keypair(1);
+        // A weight of 0 prevents primary claims.
+        let authorities = [header::BabeAuthority {
+            public_key: local.public.to_bytes(),
+            weight: 0,
+        }];
+
+        let claim = claim(
+            &authorities,
+            header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+            (1, 4),
+            &local.public.to_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(claim.slot_number, 1000);
+        assert!(claim.primary_candidates.is_empty());
+        let secondary = claim.secondary_candidate.unwrap();
+        assert_eq!(secondary.local_authorities_index, 0);
+        assert_eq!(secondary.authority_index, 0);
+        assert!(!secondary.with_vrf);
+    }
+
+    #[test]
+    fn secondary_vrf_claim() {
+        let local = keypair(1);
+        let authorities = (0..8)
+            .map(|n| header::BabeAuthority {
+                public_key: if n == 5 {
+                    local.public.to_bytes()
+                } else {
+                    keypair(n + 10).public.to_bytes()
+                },
+                weight: 0,
+            })
+            .collect::<alloc::vec::Vec<_>>();
+
+        let claim = claim(
+            &authorities,
+            header::BabeAllowedSlots::PrimaryAndSecondaryVrfSlots,
+            (1, 4),
+            &local.public.to_bytes(),
+        )
+        .unwrap();
+
+        // The slot must be the first one whose secondary author is the local authority.
+        let secondary_author = |slot_number: u64| {
+            let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
+            hash.update(&[7; 32]);
+            hash.update(&slot_number.to_le_bytes());
+            num_traits::cast::ToPrimitive::to_u32(
+                &(num_bigint::BigUint::from_bytes_be(hash.finalize().as_bytes())
+                    % num_bigint::BigUint::from(8u32)),
+            )
+            .unwrap()
+        };
+        assert!(claim.slot_number >= 1000);
+        assert!((1000..claim.slot_number).all(|slot| secondary_author(slot) != 5));
+        assert_eq!(secondary_author(claim.slot_number), 5);
+
+        assert!(claim.primary_candidates.is_empty());
+        let secondary = claim.secondary_candidate.unwrap();
+        assert_eq!(secondary.authority_index, 5);
+        assert!(secondary.with_vrf);
+    }
+}
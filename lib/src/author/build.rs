@@ -18,13 +18,14 @@
 // TODO: docs
 
 use crate::{
-    author::{aura, runtime},
+    author::{aura, babe, runtime},
+    chain::chain_information,
     executor::host,
     header,
     verify::inherents,
 };
 
-use alloc::vec::Vec;
+use alloc::{boxed::Box, vec::Vec};
 use core::{num::NonZeroU64, time::Duration};
 
 pub use runtime::{Nibble, StorageChanges, TrieEntryVersion};
@@ -46,6 +47,10 @@ pub enum ConfigConsensus<'a, TLocAuth> {
         /// Duration, in milliseconds, of an Aura slot.
         slot_duration: NonZeroU64,
 
+        /// Slot number of the current best block. `None` if the current best block is the
+        /// genesis block.
+        parent_slot_number: Option<u64>,
+
         /// List of the Aura authorities allowed to produce a block. This is either the same as
         /// the ones of the current best block, or a new list if the current best block contains
         /// an authorities list change digest item.
@@ -56,7 +61,35 @@ pub enum ConfigConsensus<'a, TLocAuth> {
         /// Must implement `Iterator<Item = &[u8; 32]>`.
         local_authorities: TLocAuth,
     },
-    // TODO: Babe isn't supported yet
+
+    /// Chain is using the Babe consensus algorithm.
+    Babe {
+        /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
+        /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
+        now_from_unix_epoch: Duration,
+
+        /// Duration, in milliseconds, of a Babe slot.
+        slot_duration: NonZeroU64,
+
+        /// Number of slots per epoch in the Babe configuration.
+        slots_per_epoch: NonZeroU64,
+
+        /// Slot number of the current best block. `None` if the current best block is the
+        /// genesis block.
+        parent_slot_number: Option<u64>,
+
+        /// Epoch the current best block belongs to. Must be `None` if and only if the current
+        /// best block is the genesis block.
+        parent_block_epoch: Option<chain_information::BabeEpochInformationRef<'a>>,
+
+        /// Epoch that follows the epoch the current best block belongs to.
+        parent_block_next_epoch: chain_information::BabeEpochInformationRef<'a>,
+
+        /// Iterator to the list of Sr25519 public keys available locally.
+        ///
+        /// Must implement `Iterator<Item = &[u8; 32]>`.
+        local_authorities: TLocAuth,
+    },
 }
 
 /// Current state of the block building process.
@@ -68,6 +101,10 @@ pub enum Builder {
     /// Block production is idle, waiting for a slot.
     WaitSlot(WaitSlot),
 
+    /// The slot has started, and the local authorities must generate VRF outputs in order to
+    /// find out whether they are allowed to claim it.
+    ClaimSlot(ClaimSlot),
+
     /// Block production is ready to start.
     Ready(AuthoringStart),
 }
@@ -79,16 +116,18 @@ impl Builder {
     ///
     /// Keep in mind that the builder should be reconstructed every time the best block changes.
     pub fn new<'a>(config: Config<'a, impl Iterator<Item = &'a [u8; 32]>>) -> Self {
-        let (slot, ready): (WaitSlotConsensus, bool) = match config.consensus {
+        match config.consensus {
             ConfigConsensus::Aura {
                 current_authorities,
                 local_authorities,
                 now_from_unix_epoch,
                 slot_duration,
+                parent_slot_number,
             } => {
                 let consensus = match aura::next_slot_claim(aura::Config {
                     now_from_unix_epoch,
                     slot_duration,
+                    parent_slot_number,
                     current_authorities,
                     local_authorities,
                 }) {
@@ -97,16 +136,97 @@ impl Builder {
                 };
 
                 debug_assert!(now_from_unix_epoch < consensus.slot_end_from_unix_epoch);
-                let ready = now_from_unix_epoch >= consensus.slot_start_from_unix_epoch;
+                if now_from_unix_epoch >= consensus.slot_start_from_unix_epoch {
+                    Builder::Ready(AuthoringStart {
+                        consensus: SlotClaim::Aura(consensus),
+                    })
+                } else {
+                    Builder::WaitSlot(WaitSlot {
+                        consensus: WaitSlotConsensus::Aura(consensus),
+                    })
+                }
+            }
+            ConfigConsensus::Babe {
+                now_from_unix_epoch,
+                slot_duration,
+                slots_per_epoch,
+                parent_slot_number,
+                parent_block_epoch,
+                parent_block_next_epoch,
+                local_authorities,
+            } => {
+                let parameters = BabeParameters {
+                    slot_duration,
+                    slots_per_epoch,
+                    parent_slot_number,
+                    parent_block_epoch: parent_block_epoch.map(Into::into),
+                    parent_block_next_epoch: parent_block_next_epoch.into(),
+                    local_authorities: local_authorities.copied().collect(),
+                };
 
-                (WaitSlotConsensus::Aura(consensus), ready)
+                Builder::from_babe_parameters(parameters, now_from_unix_epoch)
             }
+        }
+    }
+
+    /// Builds the [`Builder`] corresponding to the next Babe slot that the local authorities
+    /// might be able to claim.
+    fn from_babe_parameters(parameters: BabeParameters, now_from_unix_epoch: Duration) -> Self {
+        let claim = match parameters.next_slot_claim(now_from_unix_epoch) {
+            Some(c) => c,
+            None => return Builder::Idle,
         };
 
-        if ready {
-            Builder::Ready(AuthoringStart { consensus: slot })
+        let slot = Box::new(BabeSlot { claim, parameters });
+        if now_from_unix_epoch >= slot.claim.slot_start_from_unix_epoch {
+            Builder::from_started_babe_slot(slot)
         } else {
-            Builder::WaitSlot(WaitSlot { consensus: slot })
+            Builder::WaitSlot(WaitSlot {
+                consensus: WaitSlotConsensus::Babe(slot),
+            })
+        }
+    }
+
+    /// Builds the [`Builder`] corresponding to a Babe slot that has started.
+    fn from_started_babe_slot(slot: Box<BabeSlot>) -> Self {
+        // Primary slot claims are always attempted first, as they have priority over secondary
+        // slot claims.
+        if !slot.claim.primary_candidates.is_empty() {
+            return Builder::ClaimSlot(ClaimSlot {
+                slot,
+                step: ClaimSlotStep::Primary(0),
+            });
+        }
+
+        match slot.claim.secondary_candidate {
+            Some(babe::SecondaryCandidate { with_vrf: true, .. }) => {
+                Builder::ClaimSlot(ClaimSlot {
+                    slot,
+                    step: ClaimSlotStep::SecondaryVrf,
+                })
+            }
+            Some(babe::SecondaryCandidate {
+                local_authorities_index,
+                authority_index,
+                with_vrf: false,
+            }) => Builder::Ready(AuthoringStart {
+                consensus: SlotClaim::Babe(BabeSlotClaim {
+                    slot_start_from_unix_epoch: slot.claim.slot_start_from_unix_epoch,
+                    slot_end_from_unix_epoch: slot.claim.slot_end_from_unix_epoch,
+                    local_authorities_index,
+                    pre_digest: header::BabePreDigest::SecondaryPlain(
+                        header::BabeSecondaryPlainPreDigest {
+                            authority_index,
+                            slot_number: slot.claim.slot_number,
+                        },
+                    ),
+                }),
+            }),
+            None => {
+                // Can't claim this slot. Try the next ones.
+                let now_from_unix_epoch = slot.claim.slot_end_from_unix_epoch;
+                Builder::from_babe_parameters(slot.parameters, now_from_unix_epoch)
+            }
         }
     }
 }
@@ -169,6 +289,7 @@ pub struct WaitSlot {
 #[derive(Debug)]
 enum WaitSlotConsensus {
     Aura(aura::SlotClaim),
+    Babe(Box<BabeSlot>),
 }
 
 impl WaitSlot {
@@ -176,8 +297,9 @@ impl WaitSlot {
     /// the UNIX epoch, ignoring leap seconds).
     pub fn when(&self) -> Duration {
         // TODO: we can actually start building the block before our slot in some situations?
-        match self.consensus {
+        match &self.consensus {
             WaitSlotConsensus::Aura(claim) => claim.slot_start_from_unix_epoch,
+            WaitSlotConsensus::Babe(slot) => slot.claim.slot_start_from_unix_epoch,
         }
     }
 
@@ -186,24 +308,165 @@ impl WaitSlot {
     /// Shouldn't be called before the timestamp returned by [`WaitSlot::when`]. Blocks that are
     /// authored and sent to other nodes before the proper timestamp will be considered as
     /// invalid.
-    pub fn start(self) -> AuthoringStart {
-        AuthoringStart {
-            consensus: self.consensus,
+    ///
+    /// Returns either [`Builder::Ready`], or, in the case of Babe, [`Builder::ClaimSlot`] if the
+    /// slot must first be claimed. Can also return [`Builder::WaitSlot`] or [`Builder::Idle`] if
+    /// the slot can't be claimed.
+    pub fn start(self) -> Builder {
+        match self.consensus {
+            WaitSlotConsensus::Aura(claim) => Builder::Ready(AuthoringStart {
+                consensus: SlotClaim::Aura(claim),
+            }),
+            WaitSlotConsensus::Babe(slot) => Builder::from_started_babe_slot(slot),
         }
     }
 }
 
+/// The slot has started, and the local authorities must generate VRF outputs in order to find
+/// out whether they are allowed to claim it.
+///
+/// The VRF output and proof of the authority designated by [`ClaimSlot::authority_index`] must
+/// be generated using a transcript whose label is [`babe::VRF_TRANSCRIPT_LABEL`] and whose
+/// items are returned by [`ClaimSlot::vrf_transcript_items`].
+#[must_use]
+#[derive(Debug)]
+pub struct ClaimSlot {
+    slot: Box<BabeSlot>,
+    step: ClaimSlotStep,
+}
+
+#[derive(Debug)]
+enum ClaimSlotStep {
+    /// Attempting a primary slot claim with the given index within
+    /// [`babe::SlotClaim::primary_candidates`].
+    Primary(usize),
+    /// Attempting a secondary slot claim with a VRF output.
+    SecondaryVrf,
+}
+
+impl ClaimSlot {
+    /// Returns the index within the list of local authorities of the authority that must
+    /// generate a VRF output.
+    ///
+    /// See [`ConfigConsensus::Babe::local_authorities`].
+    pub fn authority_index(&self) -> usize {
+        match self.step {
+            ClaimSlotStep::Primary(index) => {
+                self.slot.claim.primary_candidates[index].local_authorities_index
+            }
+            ClaimSlotStep::SecondaryVrf => {
+                self.slot
+                    .claim
+                    .secondary_candidate
+                    .as_ref()
+                    .unwrap()
+                    .local_authorities_index
+            }
+        }
+    }
+
+    /// Returns when the slot ends, as a UNIX timestamp (i.e. number of seconds since the UNIX
+    /// epoch, ignoring leap seconds).
+    pub fn slot_end_from_unix_epoch(&self) -> Duration {
+        self.slot.claim.slot_end_from_unix_epoch
+    }
+
+    /// Returns the items of the VRF transcript to sign.
+    pub fn vrf_transcript_items(
+        &'_ self,
+    ) -> impl Iterator<Item = (&'static [u8], either::Either<&'_ [u8], u64>)> + '_ {
+        self.slot.claim.vrf_transcript_items()
+    }
+
+    /// Injects the VRF output and proof generated by the authority designated by
+    /// [`ClaimSlot::authority_index`].
+    pub fn inject_vrf_signature(self, vrf_output: [u8; 32], vrf_proof: [u8; 64]) -> Builder {
+        match self.step {
+            ClaimSlotStep::Primary(index) => {
+                let candidate = &self.slot.claim.primary_candidates[index];
+                let public_key =
+                    &self.slot.parameters.local_authorities[candidate.local_authorities_index];
+
+                if self
+                    .slot
+                    .claim
+                    .is_primary_claim(candidate, public_key, &vrf_output)
+                {
+                    return Builder::Ready(AuthoringStart {
+                        consensus: SlotClaim::Babe(BabeSlotClaim {
+                            slot_start_from_unix_epoch: self.slot.claim.slot_start_from_unix_epoch,
+                            slot_end_from_unix_epoch: self.slot.claim.slot_end_from_unix_epoch,
+                            local_authorities_index: candidate.local_authorities_index,
+                            pre_digest: header::BabePreDigest::Primary(
+                                header::BabePrimaryPreDigest {
+                                    authority_index: candidate.authority_index,
+                                    slot_number: self.slot.claim.slot_number,
+                                    vrf_output,
+                                    vrf_proof,
+                                },
+                            ),
+                        }),
+                    });
+                }
+
+                self.skip()
+            }
+            ClaimSlotStep::SecondaryVrf => {
+                let candidate = self.slot.claim.secondary_candidate.as_ref().unwrap();
+                Builder::Ready(AuthoringStart {
+                    consensus: SlotClaim::Babe(BabeSlotClaim {
+                        slot_start_from_unix_epoch: self.slot.claim.slot_start_from_unix_epoch,
+                        slot_end_from_unix_epoch: self.slot.claim.slot_end_from_unix_epoch,
+                        local_authorities_index: candidate.local_authorities_index,
+                        pre_digest: header::BabePreDigest::SecondaryVRF(
+                            header::BabeSecondaryVRFPreDigest {
+                                authority_index: candidate.authority_index,
+                                slot_number: self.slot.claim.slot_number,
+                                vrf_output,
+                                vrf_proof,
+                            },
+                        ),
+                    }),
+                })
+            }
+        }
+    }
+
+    /// Gives up on the authority designated by [`ClaimSlot::authority_index`], for example
+    /// because it isn't possible to generate a VRF output with it.
+    ///
+    /// The next authority that might be able to claim the slot is tried, or, if there isn't any,
+    /// the next slot.
+    pub fn skip(mut self) -> Builder {
+        if let ClaimSlotStep::Primary(index) = self.step {
+            if index + 1 < self.slot.claim.primary_candidates.len() {
+                self.step = ClaimSlotStep::Primary(index + 1);
+                return Builder::ClaimSlot(self);
+            }
+
+            // All primary candidates have failed. Try a secondary claim instead.
+            self.slot.claim.primary_candidates.clear();
+            return Builder::from_started_babe_slot(self.slot);
+        }
+
+        // Can't claim this slot. Try the next ones.
+        let now_from_unix_epoch = self.slot.claim.slot_end_from_unix_epoch;
+        Builder::from_babe_parameters(self.slot.parameters, now_from_unix_epoch)
+    }
+}
+
 /// Ready to start producing blocks.
 pub struct AuthoringStart {
-    consensus: WaitSlotConsensus,
+    consensus: SlotClaim,
 }
 
 impl AuthoringStart {
     /// Returns when the authoring slot start, as a UNIX timestamp (i.e. number of seconds since
     /// the UNIX epoch, ignoring leap seconds).
     pub fn slot_start_from_unix_epoch(&self) -> Duration {
-        match self.consensus {
-            WaitSlotConsensus::Aura(claim) => claim.slot_start_from_unix_epoch,
+        match &self.consensus {
+            SlotClaim::Aura(claim) => claim.slot_start_from_unix_epoch,
+            SlotClaim::Babe(claim) => claim.slot_start_from_unix_epoch,
         }
     }
 
@@ -215,8 +478,9 @@ impl AuthoringStart {
     /// authored **and** propagated throughout the entire peer-to-peer network before the slot
     /// ends.
     pub fn slot_end_from_unix_epoch(&self) -> Duration {
-        match self.consensus {
-            WaitSlotConsensus::Aura(claim) => claim.slot_end_from_unix_epoch,
+        match &self.consensus {
+            SlotClaim::Aura(claim) => claim.slot_end_from_unix_epoch,
+            SlotClaim::Babe(claim) => claim.slot_end_from_unix_epoch,
         }
     }
 
@@ -228,12 +492,11 @@ impl AuthoringStart {
             parent_number: config.parent_number,
             parent_runtime: config.parent_runtime,
             block_body_capacity: config.block_body_capacity,
-            consensus_digest_log_item: match self.consensus {
-                WaitSlotConsensus::Aura(slot) => {
-                    runtime::ConfigPreRuntime::Aura(header::AuraPreDigest {
-                        slot_number: slot.slot_number,
-                    })
-                }
+            consensus_digest_log_item: match &self.consensus {
+                SlotClaim::Aura(slot) => runtime::ConfigPreRuntime::Aura(header::AuraPreDigest {
+                    slot_number: slot.slot_number,
+                }),
+                SlotClaim::Babe(slot) => runtime::ConfigPreRuntime::Babe((&slot.pre_digest).into()),
             },
             max_log_level: config.max_log_level,
             calculate_trie_changes: config.calculate_trie_changes,
@@ -463,10 +726,12 @@ impl Seal {
     /// Returns the index within the list of authorities of the authority that must sign the
     /// block.
     ///
-    /// See [`ConfigConsensus::Aura::local_authorities`].
+    /// See [`ConfigConsensus::Aura::local_authorities`] and
+    /// [`ConfigConsensus::Babe::local_authorities`].
     pub fn authority_index(&self) -> usize {
-        match self.shared.slot_claim {
-            WaitSlotConsensus::Aura(slot) => slot.local_authorities_index,
+        match &self.shared.slot_claim {
+            SlotClaim::Aura(slot) => slot.local_authorities_index,
+            SlotClaim::Babe(slot) => slot.local_authorities_index,
         }
     }
 
@@ -484,7 +749,10 @@ impl Seal {
         self.block.scale_encoded_header = header
             .scale_encoding_with_extra_digest_item(
                 self.shared.block_number_bytes,
-                header::DigestItemRef::AuraSeal(&signature),
+                match self.shared.slot_claim {
+                    SlotClaim::Aura(_) => header::DigestItemRef::AuraSeal(&signature),
+                    SlotClaim::Babe(_) => header::DigestItemRef::BabeSeal(&signature),
+                },
             )
             .fold(Vec::with_capacity(8192), |mut a, b| {
                 a.extend_from_slice(b.as_ref());
@@ -517,7 +785,56 @@ struct Shared {
     block_number_bytes: usize,
 
     /// Slot that has been claimed.
-    slot_claim: WaitSlotConsensus,
+    slot_claim: SlotClaim,
+}
+
+/// Slot that has been claimed by one of the local authorities.
+#[derive(Debug)]
+enum SlotClaim {
+    Aura(aura::SlotClaim),
+    Babe(BabeSlotClaim),
+}
+
+/// Babe slot that has been claimed by one of the local authorities.
+#[derive(Debug)]
+struct BabeSlotClaim {
+    slot_start_from_unix_epoch: Duration,
+    slot_end_from_unix_epoch: Duration,
+    local_authorities_index: usize,
+    pre_digest: header::BabePreDigest,
+}
+
+/// Babe slot that the local authorities might be able to claim.
+#[derive(Debug)]
+struct BabeSlot {
+    claim: babe::SlotClaim,
+    /// Parameters used to find the next slot in case this one can't be claimed.
+    parameters: BabeParameters,
+}
+
+/// Owned version of the fields of [`ConfigConsensus::Babe`].
+#[derive(Debug)]
+struct BabeParameters {
+    slot_duration: NonZeroU64,
+    slots_per_epoch: NonZeroU64,
+    parent_slot_number: Option<u64>,
+    parent_block_epoch: Option<chain_information::BabeEpochInformation>,
+    parent_block_next_epoch: chain_information::BabeEpochInformation,
+    local_authorities: Vec<[u8; 32]>,
+}
+
+impl BabeParameters {
+    fn next_slot_claim(&self, now_from_unix_epoch: Duration) -> Option<babe::SlotClaim> {
+        babe::next_slot_claim(babe::Config {
+            now_from_unix_epoch,
+            slot_duration: self.slot_duration,
+            slots_per_epoch: self.slots_per_epoch,
+            parent_slot_number: self.parent_slot_number,
+            parent_block_epoch: self.parent_block_epoch.as_ref().map(Into::into),
+            parent_block_next_epoch: (&self.parent_block_next_epoch).into(),
+            local_authorities: self.local_authorities.iter(),
+        })
+    }
 }
 
 impl Shared {
@@ -567,7 +884,10 @@ impl Shared {
                     inner = a.inject_inherents(self.inherent_data.take().unwrap());
                 }
                 runtime::BlockBuild::ApplyExtrinsic(a) => {
-                    inner = a.finish();
+                    break BuilderAuthoring::ApplyExtrinsic(ApplyExtrinsic {
+                        inner: a,
+                        shared: self,
+                    })
                 }
                 runtime::BlockBuild::ApplyExtrinsicResult { result, resume } => {
                     break BuilderAuthoring::ApplyExtrinsicResult {
@@ -144,6 +144,8 @@ pub enum Error {
     BadInherentExtrinsicsOutput,
     /// Error while parsing output of `BlockBuilder_apply_extrinsic`.
     BadApplyExtrinsicOutput,
+    /// Runtime called a forbidden host function.
+    ForbiddenHostCall,
     /// Applying an inherent extrinsic has returned a [`DispatchError`].
     #[display(fmt = "Error while applying inherent extrinsic: {error}\nExtrinsic: {extrinsic:?}")]
     InherentExtrinsicDispatchError {
@@ -294,6 +296,25 @@ impl BlockBuild {
                 (Inner::Runtime(runtime_host::RuntimeHostVm::OffchainStorageSet(inner)), _) => {
                     return BlockBuild::OffchainStorageSet(OffchainStorageSet(inner, shared))
                 }
+                (Inner::Runtime(runtime_host::RuntimeHostVm::SignatureVerification(sig)), _) => {
+                    inner = Inner::Runtime(sig.verify_and_resume());
+                }
+                (Inner::Runtime(runtime_host::RuntimeHostVm::LogEmit(req)), _) => {
+                    // Generated logs are ignored.
+                    inner = Inner::Runtime(req.resume());
+                }
+                (Inner::Runtime(runtime_host::RuntimeHostVm::Offchain(ctx)), _) => {
+                    return BlockBuild::Finished(Err((
+                        Error::ForbiddenHostCall,
+                        ctx.into_prototype(),
+                    )));
+                }
+                (Inner::Runtime(runtime_host::RuntimeHostVm::GenerateKey(req)), _) => {
+                    return BlockBuild::Finished(Err((
+                        Error::ForbiddenHostCall,
+                        runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype(),
+                    )));
+                }
 
                 (
                     Inner::Runtime(runtime_host::RuntimeHostVm::Finished(Ok(success))),
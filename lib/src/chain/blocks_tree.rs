@@ -108,6 +108,16 @@ pub struct Config {
     /// However, since a recognized consensus engine must always be present, both `true` and
     /// `false` guarantee that the number of authorable blocks over the network is bounded.
     pub allow_unknown_consensus_engines: bool,
+
+    /// List of block numbers and hashes that the chain must contain. Any block found at one of
+    /// these heights but whose hash is different fails to verify.
+    ///
+    /// This is used in order to follow one specific side of a contentious fork.
+    pub fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// List of hashes of blocks that are known to be bad. These blocks, and thus all their
+    /// descendants, fail to verify.
+    pub bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 /// Holds state about the current state of the chain for the purpose of verifying headers.
@@ -151,6 +161,10 @@ pub struct NonFinalizedTree<T> {
     block_number_bytes: usize,
     /// See [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// See [`Config::fork_blocks`].
+    fork_blocks: BTreeMap<u64, [u8; 32]>,
+    /// See [`Config::bad_blocks`].
+    bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 impl<T> NonFinalizedTree<T> {
@@ -223,6 +237,8 @@ impl<T> NonFinalizedTree<T> {
             blocks_trigger_gp_change: BTreeSet::new(),
             block_number_bytes: config.block_number_bytes,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            fork_blocks: config.fork_blocks,
+            bad_blocks: config.bad_blocks,
         }
     }
 
@@ -279,6 +295,25 @@ impl<T> NonFinalizedTree<T> {
         self.block_number_bytes
     }
 
+    /// Returns `true` if the given block height is one of the heights passed in
+    /// [`Config::fork_blocks`] and the hash passed as parameter doesn't match the one in the
+    /// configuration.
+    ///
+    /// A block for which this function returns `true` always fails to verify.
+    pub fn is_fork_block_mismatch(&self, height: u64, hash: &[u8; 32]) -> bool {
+        self.fork_blocks
+            .get(&height)
+            .map_or(false, |expected| expected != hash)
+    }
+
+    /// Returns `true` if the given block hash is one of the hashes passed in
+    /// [`Config::bad_blocks`].
+    ///
+    /// A block for which this function returns `true` always fails to verify.
+    pub fn is_bad_block(&self, hash: &[u8; 32]) -> bool {
+        self.bad_blocks.contains(hash)
+    }
+
     /// Builds a [`chain_information::ChainInformationRef`] struct that might later be used to
     /// build a new [`NonFinalizedTree`].
     pub fn as_chain_information(&self) -> chain_information::ValidChainInformationRef {
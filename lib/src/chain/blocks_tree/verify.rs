@@ -54,6 +54,21 @@ impl<T> NonFinalizedTree<T> {
             return Ok(HeaderVerifySuccess::Duplicate);
         }
 
+        // Blocks known to be bad are rejected. Because bad blocks are never inserted in the tree,
+        // their descendants are rejected as well, as their parent can't be found.
+        if self.bad_blocks.contains(&hash) {
+            return Err(HeaderVerifyError::BadBlock);
+        }
+
+        // The chain must contain the fork blocks passed in the configuration.
+        if let Some(expected_hash) = self.fork_blocks.get(&decoded_header.number) {
+            if *expected_hash != hash {
+                return Err(HeaderVerifyError::ForkBlockMismatch {
+                    expected_hash: *expected_hash,
+                });
+            }
+        }
+
         // Try to find the parent block in the tree of known blocks.
         // `Some` with an index of the parent within the tree of unfinalized blocks.
         // `None` means that the parent is the finalized block.
@@ -620,4 +635,17 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{_0}")]
     VerificationFailed(verify::header_only::Error),
+    /// The block is at the height of one of the fork blocks of the chain, but its hash doesn't
+    /// match. See [`super::Config::fork_blocks`].
+    #[display(
+        fmt = "Block doesn't match fork block {}",
+        "hex::encode(expected_hash)"
+    )]
+    ForkBlockMismatch {
+        /// Hash that the block at this height must have.
+        expected_hash: [u8; 32],
+    },
+    /// The block is in the list of bad blocks of the chain. See [`super::Config::bad_blocks`].
+    #[display(fmt = "Block is known to be bad")]
+    BadBlock,
 }
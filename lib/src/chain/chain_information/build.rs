@@ -108,6 +108,8 @@ pub enum Error {
     },
     /// Runtime has called an offchain worker host function.
     OffchainWorkerHostFunction,
+    /// Runtime has called a host function that requires access to a keystore.
+    KeystoreHostFunction,
     /// Failed to decode the output of the `AuraApi_slot_duration` runtime call.
     AuraSlotDurationOutputDecode,
     /// Failed to decode the output of the `AuraApi_authorities` runtime call.
@@ -845,6 +847,14 @@ impl ChainInformationBuild {
                         virtual_machine,
                     };
                 }
+                runtime_host::RuntimeHostVm::GenerateKey(req) => {
+                    let virtual_machine =
+                        runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype();
+                    break ChainInformationBuild::Finished {
+                        result: Err(Error::KeystoreHostFunction),
+                        virtual_machine,
+                    };
+                }
                 runtime_host::RuntimeHostVm::LogEmit(req) => {
                     // Generated logs are ignored.
                     call = req.resume();
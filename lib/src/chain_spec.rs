@@ -37,19 +37,22 @@
 use crate::{
     chain::chain_information::{
         build, BabeEpochInformation, ChainInformation, ChainInformationConsensus,
-        ChainInformationFinality, ValidChainInformation, ValidityError,
+        ChainInformationFinality, ValidChainInformation, ValidChainInformationRef, ValidityError,
     },
     executor, libp2p, trie,
 };
 
 use alloc::{
+    borrow::ToOwned as _,
     boxed::Box,
+    collections::BTreeMap,
     string::{String, ToString as _},
     vec::Vec,
 };
 use core::{iter, num::NonZeroU64, ops::Bound};
 
 mod light_sync_state;
+mod runtime_genesis;
 mod structs;
 mod tests;
 
@@ -62,14 +65,37 @@ pub struct ChainSpec {
 impl ChainSpec {
     /// Parse JSON content into a [`ChainSpec`].
     pub fn from_json_bytes(json: impl AsRef<[u8]>) -> Result<Self, ParseError> {
-        let client_spec: structs::ClientSpec = serde_json::from_slice(json.as_ref())
+        let mut client_spec: structs::ClientSpec = serde_json::from_slice(json.as_ref())
             .map_err(ParseErrorInner::Serde)
             .map_err(ParseError)?;
 
+        // If the chain spec contains the runtime and its configuration rather than the raw
+        // storage, build the raw storage by executing the runtime.
+        if let structs::Genesis::RuntimeGenesis(genesis) = &client_spec.genesis {
+            let config = match (&genesis.config, &genesis.patch) {
+                (Some(config), None) => runtime_genesis::GenesisConfig::Full(config),
+                (None, Some(patch)) => runtime_genesis::GenesisConfig::Patch(patch),
+                _ => return Err(ParseError(ParseErrorInner::Other)),
+            };
+
+            let storage = runtime_genesis::build_genesis_storage(&genesis.code.0, config)
+                .map_err(ParseErrorInner::RuntimeGenesis)
+                .map_err(ParseError)?;
+
+            client_spec.genesis = structs::Genesis::Raw(structs::RawGenesis {
+                top: storage
+                    .into_iter()
+                    .map(|(key, value)| (structs::HexString(key), structs::HexString(value)))
+                    .collect(),
+                children_default: Default::default(),
+            });
+        }
+
         // TODO: we don't support child tries in the genesis block
         assert!(match &client_spec.genesis {
             structs::Genesis::Raw(genesis) => genesis.children_default.is_empty(),
             structs::Genesis::StateRootHash(_) => true,
+            structs::Genesis::RuntimeGenesis(_) => unreachable!(),
         });
 
         if client_spec.relay_chain.is_some() != client_spec.para_id.is_some() {
@@ -85,6 +111,107 @@ impl ChainSpec {
         Ok(ChainSpec { client_spec })
     }
 
+    /// Builds a new chain specification from the given configuration.
+    ///
+    /// Contrary to [`ChainSpec::from_json_bytes`], which accepts for example boot nodes that
+    /// can't be parsed, the chain specification is fully validated. The genesis storage must
+    /// contain the runtime code under the `:code` key.
+    pub fn build<'a>(
+        config: BuildConfig<
+            'a,
+            impl Iterator<Item = &'a str>,
+            impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
+        >,
+    ) -> Result<Self, BuildError> {
+        if config.name.is_empty() || config.id.is_empty() {
+            return Err(BuildError::EmptyNameOrId);
+        }
+
+        if !(1..=8).contains(&config.block_number_bytes) {
+            return Err(BuildError::InvalidBlockNumberBytes);
+        }
+
+        let properties = match config.properties {
+            Some(properties) => {
+                let properties =
+                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(properties)
+                        .map_err(BuildError::InvalidProperties)?;
+                // Serializing a JSON map can't fail.
+                Some(serde_json::value::to_raw_value(&properties).unwrap())
+            }
+            None => None,
+        };
+
+        let genesis_storage = match config.genesis {
+            BuildGenesis::Raw(storage) => storage.collect::<BTreeMap<_, _>>(),
+            BuildGenesis::RuntimeGenesis { code, config } => {
+                let (RuntimeGenesisConfig::Full(json) | RuntimeGenesisConfig::Patch(json)) = config;
+                let json = serde_json::from_str::<serde_json::Value>(json)
+                    .map_err(BuildError::InvalidGenesisConfig)?;
+                runtime_genesis::build_genesis_storage(
+                    code,
+                    match config {
+                        RuntimeGenesisConfig::Full(_) => {
+                            runtime_genesis::GenesisConfig::Full(&json)
+                        }
+                        RuntimeGenesisConfig::Patch(_) => {
+                            runtime_genesis::GenesisConfig::Patch(&json)
+                        }
+                    },
+                )
+                .map_err(BuildError::RuntimeGenesis)?
+            }
+        };
+
+        if !genesis_storage.contains_key(&b":code"[..]) {
+            return Err(BuildError::RuntimeNotFound);
+        }
+
+        let chain_spec = ChainSpec {
+            client_spec: structs::ClientSpec {
+                name: config.name.to_owned(),
+                id: config.id.to_owned(),
+                chain_type: match config.chain_type {
+                    ChainType::Development => structs::ChainType::Development,
+                    ChainType::Local => structs::ChainType::Local,
+                    ChainType::Live => structs::ChainType::Live,
+                    ChainType::Custom(ty) => structs::ChainType::Custom(ty.to_owned()),
+                },
+                code_substitutes: Default::default(),
+                boot_nodes: config.boot_nodes.map(|addr| addr.to_owned()).collect(),
+                telemetry_endpoints: None,
+                protocol_id: config.protocol_id.map(|id| id.to_owned()),
+                fork_id: config.fork_id.map(|id| id.to_owned()),
+                block_number_bytes: Some(config.block_number_bytes),
+                properties,
+                fork_blocks: None,
+                bad_blocks: None,
+                consensus_engine: (),
+                genesis: structs::Genesis::Raw(structs::RawGenesis {
+                    top: genesis_storage
+                        .into_iter()
+                        .map(|(key, value)| (structs::HexString(key), structs::HexString(value)))
+                        .collect(),
+                    children_default: Default::default(),
+                }),
+                light_sync_state: None,
+                relay_chain: config
+                    .relay_chain
+                    .map(|(relay_chain, _)| relay_chain.to_owned()),
+                para_id: config.relay_chain.map(|(_, para_id)| para_id),
+            },
+        };
+
+        if let Some(Bootnode::UnrecognizedFormat(addr)) = chain_spec
+            .boot_nodes()
+            .find(|bootnode| matches!(bootnode, Bootnode::UnrecognizedFormat(_)))
+        {
+            return Err(BuildError::InvalidBootNode(addr.to_owned()));
+        }
+
+        Ok(chain_spec)
+    }
+
     /// Turns this chain specification into a JSON document representing it.
     pub fn serialize(&self) -> String {
         // Can only panic in case of a bug in this module.
@@ -235,6 +362,39 @@ impl ChainSpec {
         }
     }
 
+    /// Returns the list of runtime codes that must be used instead of the on-chain runtime
+    /// code, and the number of the block starting from which they must be used.
+    ///
+    /// A code substitute applies to the block whose number is given and its descendants, until
+    /// the `spec_version` ([`crate::executor::host::CoreVersionRef::spec_version`]) of the
+    /// on-chain runtime becomes different from the `spec_version` of the substitute. Code
+    /// substitutes are used in order to fix runtime bugs in historical blocks.
+    ///
+    /// The list is ordered by increasing block number.
+    pub fn code_substitutes(&'_ self) -> impl ExactSizeIterator<Item = (u64, &'_ [u8])> + '_ {
+        let mut list = self
+            .client_spec
+            .code_substitutes
+            .iter()
+            .map(|(block_number, code)| (*block_number, &code.0[..]))
+            .collect::<Vec<_>>();
+        list.sort_unstable_by_key(|(block_number, _)| *block_number);
+        list.into_iter()
+    }
+
+    /// Returns a list of block numbers and hashes. The chain must contain the block with the
+    /// given hash at the given height, and any chain that doesn't should be rejected.
+    ///
+    /// This is used in order to follow one specific side of a contentious fork.
+    pub fn fork_blocks(&'_ self) -> impl Iterator<Item = (u64, &'_ [u8; 32])> + '_ {
+        self.client_spec
+            .fork_blocks
+            .as_ref()
+            .into_iter()
+            .flat_map(|l| l.iter())
+            .map(|(n, h)| (*n, &h.0))
+    }
+
     /// Returns a list of hashes of block headers that should always be considered as invalid.
     pub fn bad_blocks_hashes(&'_ self) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
         self.client_spec
@@ -271,14 +431,16 @@ impl ChainSpec {
         })
     }
 
-    /// Returns the list of libp2p multiaddresses of the default telemetry servers of the chain.
-    // TODO: more strongly typed?
-    pub fn telemetry_endpoints(&'_ self) -> impl Iterator<Item = impl AsRef<str> + '_> + '_ {
+    /// Returns the list of addresses of the default telemetry servers of the chain, alongside
+    /// with the verbosity level of each of them.
+    ///
+    /// The addresses can be parsed with [`crate::telemetry::parse_endpoint`].
+    pub fn telemetry_endpoints(&'_ self) -> impl Iterator<Item = (&'_ str, u8)> + '_ {
         self.client_spec
             .telemetry_endpoints
             .as_ref()
             .into_iter()
-            .flat_map(|ep| ep.iter().map(|e| &e.0))
+            .flat_map(|ep| ep.iter().map(|(addr, verbosity)| (&addr[..], *verbosity)))
     }
 
     /// Returns the network protocol id that uniquely identifies a chain. Used to prevent nodes
@@ -315,6 +477,8 @@ impl ChainSpec {
         match &self.client_spec.genesis {
             structs::Genesis::Raw(raw) => GenesisStorage::Items(GenesisStorageItems { raw }),
             structs::Genesis::StateRootHash(hash) => GenesisStorage::TrieRootHash(&hash.0),
+            // Converted to `Raw` when parsing the chain spec.
+            structs::Genesis::RuntimeGenesis(_) => unreachable!(),
         }
     }
 
@@ -341,6 +505,116 @@ impl ChainSpec {
                 inner: state.decode(self.block_number_bytes().into()).unwrap(),
             })
     }
+
+    /// Replaces the storage of the genesis block of the chain with the given list of keys and
+    /// values.
+    ///
+    /// The checkpoint of the chain, if any, is removed, as it doesn't match the new genesis
+    /// block.
+    pub fn set_genesis_storage(&mut self, storage: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) {
+        self.client_spec.genesis = structs::Genesis::Raw(structs::RawGenesis {
+            top: storage
+                .map(|(key, value)| (structs::HexString(key), structs::HexString(value)))
+                .collect(),
+            children_default: Default::default(),
+        });
+        self.client_spec.light_sync_state = None;
+    }
+
+    /// Replaces the checkpoint of the chain with the finalized block of the given chain
+    /// information.
+    ///
+    /// Only chains using Babe and GrandPa are supported, as this is the only combination that
+    /// the checkpoint format supports.
+    pub fn set_light_sync_state<'a>(
+        &mut self,
+        chain_information: impl Into<ValidChainInformationRef<'a>>,
+    ) -> Result<(), ChainInformationToCheckpointError> {
+        let light_sync_state = light_sync_state::LightSyncState::from_chain_information(
+            chain_information.into(),
+            self.block_number_bytes().into(),
+        )?;
+
+        // Make sure that the checkpoint can be decoded, as `light_sync_state()` relies on this.
+        if light_sync_state
+            .decode(self.block_number_bytes().into())
+            .is_err()
+        {
+            return Err(ChainInformationToCheckpointError::InvalidData);
+        }
+
+        self.client_spec.light_sync_state = Some(light_sync_state);
+        Ok(())
+    }
+}
+
+/// Configuration for [`ChainSpec::build`].
+#[derive(Debug, Clone)]
+pub struct BuildConfig<'a, TBootNodes, TGenesisStorage> {
+    /// Human-readable name of the chain. Must not be empty.
+    pub name: &'a str,
+
+    /// Identifier of the chain, for example used in file system paths. Must not be empty.
+    pub id: &'a str,
+
+    /// Type of the chain.
+    pub chain_type: ChainType<'a>,
+
+    /// Multiaddresses of the bootnodes of the chain. Each multiaddress must end with
+    /// `/p2p/<peer_id>`.
+    pub boot_nodes: TBootNodes,
+
+    /// JSON-encoded object containing arbitrary properties, such as the name of the token or
+    /// the number of decimals. See [`ChainSpec::properties`].
+    pub properties: Option<&'a str>,
+
+    /// See [`ChainSpec::protocol_id`].
+    pub protocol_id: Option<&'a str>,
+
+    /// See [`ChainSpec::fork_id`].
+    pub fork_id: Option<&'a str>,
+
+    /// Number of bytes of the "block number" field of various data structures. Must be between
+    /// 1 and 8.
+    pub block_number_bytes: u8,
+
+    /// If the chain is a parachain, the identifier of its relay chain and its parachain id.
+    pub relay_chain: Option<(&'a str, u32)>,
+
+    /// Storage of the genesis block.
+    pub genesis: BuildGenesis<'a, TGenesisStorage>,
+}
+
+/// See [`BuildConfig::chain_type`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChainType<'a> {
+    Development,
+    Local,
+    Live,
+    Custom(&'a str),
+}
+
+/// See [`BuildConfig::genesis`].
+#[derive(Debug, Clone)]
+pub enum BuildGenesis<'a, TGenesisStorage> {
+    /// List of keys and values of the genesis storage.
+    Raw(TGenesisStorage),
+    /// Genesis storage built by executing the `GenesisBuilder` runtime API of the given runtime.
+    RuntimeGenesis {
+        /// Wasm runtime code. Stored in the genesis storage under the `:code` key.
+        code: &'a [u8],
+        /// Genesis configuration to pass to the runtime.
+        config: RuntimeGenesisConfig<'a>,
+    },
+}
+
+/// See [`BuildGenesis::RuntimeGenesis`].
+#[derive(Debug, Copy, Clone)]
+pub enum RuntimeGenesisConfig<'a> {
+    /// JSON-encoded genesis configuration, passed as is to the runtime.
+    Full(&'a str),
+    /// JSON-encoded patch applied on top of the default genesis configuration of the runtime.
+    Patch(&'a str),
 }
 
 /// See [`ChainSpec::boot_nodes`].
@@ -535,15 +809,72 @@ impl LightSyncState {
 
 /// Error that can happen when parsing a chain spec JSON.
 #[derive(Debug, derive_more::Display)]
-#[display(fmt = "Failed to parse chain spec")]
+#[display(fmt = "{_0}")]
 pub struct ParseError(ParseErrorInner);
 
 #[derive(Debug, derive_more::Display)]
 enum ParseErrorInner {
+    #[display(fmt = "Failed to parse chain spec")]
     Serde(serde_json::Error),
+    #[display(fmt = "Failed to build the genesis of the chain spec: {_0}")]
+    RuntimeGenesis(RuntimeGenesisError),
+    #[display(fmt = "Failed to parse chain spec")]
     Other,
 }
 
+/// Error potentially returned by [`ChainSpec::build`].
+#[derive(Debug, derive_more::Display)]
+pub enum BuildError {
+    /// The name or the id of the chain is empty.
+    EmptyNameOrId,
+    /// The number of bytes of the block number is out of range.
+    InvalidBlockNumberBytes,
+    /// The properties aren't a valid JSON object.
+    #[display(fmt = "Invalid properties: {_0}")]
+    InvalidProperties(serde_json::Error),
+    /// One of the bootnodes couldn't be parsed.
+    #[display(fmt = "Invalid bootnode: {_0}")]
+    InvalidBootNode(String),
+    /// The genesis configuration isn't valid JSON.
+    #[display(fmt = "Invalid genesis configuration: {_0}")]
+    InvalidGenesisConfig(serde_json::Error),
+    /// Error while building the genesis storage from the runtime.
+    #[display(fmt = "{_0}")]
+    RuntimeGenesis(RuntimeGenesisError),
+    /// The genesis storage doesn't contain the runtime code.
+    RuntimeNotFound,
+}
+
+/// Error when building the storage of the genesis block from the runtime code and its genesis
+/// configuration.
+#[derive(Debug, derive_more::Display)]
+pub enum RuntimeGenesisError {
+    /// Error when initializing the virtual machine.
+    #[display(fmt = "Error when initializing the virtual machine: {_0}")]
+    VmInitialization(executor::host::NewErr),
+    /// Error when starting the runtime call. This happens in particular if the runtime doesn't
+    /// support the `GenesisBuilder` runtime API.
+    #[display(fmt = "Error when starting the runtime call: {_0}")]
+    VmStart(executor::host::StartErr),
+    /// Error while executing the runtime.
+    #[display(fmt = "Error while executing the runtime: {_0}")]
+    Execution(executor::runtime_host::ErrorDetail),
+    /// The runtime has called a host function that isn't available when building the genesis.
+    ForbiddenHostFunction,
+    /// Failed to decode the output of the runtime call.
+    OutputDecode,
+    /// The runtime doesn't provide a default genesis configuration to apply the patch onto.
+    NoDefaultPreset,
+    /// The default genesis configuration returned by the runtime isn't valid JSON.
+    #[display(fmt = "Invalid default genesis configuration: {_0}")]
+    InvalidPreset(serde_json::Error),
+    /// The runtime has failed to build the storage from the genesis configuration.
+    #[display(fmt = "Failed to build the genesis storage: {_0}")]
+    BuildState(String),
+    /// The runtime has written to child tries, which isn't supported.
+    ChildTriesNotSupported,
+}
+
 /// Error when building the chain information from the genesis storage.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
@@ -562,6 +893,21 @@ pub enum FromGenesisStorageError {
     UnknownStorageItems,
 }
 
+/// Error when building a checkpoint from a chain information.
+#[derive(Debug, derive_more::Display)]
+pub enum ChainInformationToCheckpointError {
+    /// The chain doesn't use Babe, or the finalized block is the genesis block.
+    UnsupportedConsensus,
+    /// The chain doesn't use GrandPa.
+    UnsupportedFinality,
+    /// The slot number at which a Babe epoch starts isn't known.
+    UnknownEpochStartSlot,
+    /// The number of the finalized block doesn't fit in 32 bits.
+    BlockNumberOverflow,
+    /// The chain information can't be represented as a checkpoint.
+    InvalidData,
+}
+
 /// Error when building the chain information corresponding to a checkpoint.
 #[derive(Debug, derive_more::Display)]
 pub enum CheckpointToChainInformationError {
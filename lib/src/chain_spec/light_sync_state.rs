@@ -15,8 +15,15 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use super::{ParseError, ParseErrorInner};
-use crate::header::BabeNextConfig;
+use super::{ChainInformationToCheckpointError, ParseError, ParseErrorInner};
+use crate::{
+    chain::chain_information::{
+        BabeEpochInformationRef, ChainInformationConsensusRef, ChainInformationFinalityRef,
+        ValidChainInformationRef,
+    },
+    header::BabeNextConfig,
+    util,
+};
 
 use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
 use serde::{Deserialize, Serialize};
@@ -32,6 +39,92 @@ pub(super) struct LightSyncState {
 }
 
 impl LightSyncState {
+    /// Builds the checkpoint corresponding to the finalized block of the given chain
+    /// information.
+    ///
+    /// Only the fields that [`LightSyncState::decode`] reads are filled with meaningful values.
+    /// In particular, the Babe epoch changes only contain the current and next epochs, and the
+    /// GrandPa authority set doesn't contain any pending change.
+    pub(super) fn from_chain_information(
+        information: ValidChainInformationRef,
+        block_number_bytes: usize,
+    ) -> Result<Self, ChainInformationToCheckpointError> {
+        let information = information.as_ref();
+
+        let finalized_block_number = u32::try_from(information.finalized_block_header.number)
+            .map_err(|_| ChainInformationToCheckpointError::BlockNumberOverflow)?;
+        let finalized_block_hash = information.finalized_block_header.hash(block_number_bytes);
+
+        let ChainInformationConsensusRef::Babe {
+            slots_per_epoch,
+            finalized_block_epoch_information: Some(current_epoch),
+            finalized_next_epoch_transition: next_epoch,
+        } = information.consensus
+        else {
+            return Err(ChainInformationToCheckpointError::UnsupportedConsensus);
+        };
+
+        let ChainInformationFinalityRef::Grandpa {
+            after_finalized_block_authorities_set_id,
+            finalized_triggered_authorities,
+            ..
+        } = information.finality
+        else {
+            return Err(ChainInformationToCheckpointError::UnsupportedFinality);
+        };
+
+        // The epochs are indexed by the hash and number of the block that has announced them,
+        // which isn't known here. Since only the order of these keys matters when decoding, the
+        // current epoch is indexed by the parent of the finalized block and the next epoch by the
+        // finalized block.
+        let mut babe_epoch_changes = Vec::new();
+        // Empty fork tree, followed with the list of epochs.
+        babe_epoch_changes.extend_from_slice(util::encode_scale_compact_usize(0).as_ref());
+        babe_epoch_changes.push(0);
+        babe_epoch_changes.extend_from_slice(util::encode_scale_compact_usize(2).as_ref());
+        for (block_hash, block_number, epoch) in [
+            (
+                *information.finalized_block_header.parent_hash,
+                finalized_block_number.saturating_sub(1),
+                current_epoch,
+            ),
+            (finalized_block_hash, finalized_block_number, next_epoch),
+        ] {
+            babe_epoch_changes.extend_from_slice(&block_hash);
+            babe_epoch_changes.extend_from_slice(&block_number.to_le_bytes());
+            babe_epoch_changes.push(1); // `PersistedEpoch::Regular`
+            encode_babe_epoch(&mut babe_epoch_changes, epoch, slots_per_epoch.get())?;
+        }
+
+        let mut grandpa_authority_set = Vec::new();
+        grandpa_authority_set.extend_from_slice(
+            util::encode_scale_compact_usize(finalized_triggered_authorities.len()).as_ref(),
+        );
+        for authority in finalized_triggered_authorities {
+            grandpa_authority_set.extend_from_slice(&authority.public_key);
+            grandpa_authority_set.extend_from_slice(&authority.weight.get().to_le_bytes());
+        }
+        grandpa_authority_set
+            .extend_from_slice(&after_finalized_block_authorities_set_id.to_le_bytes());
+        // Empty fork tree of pending standard changes, empty list of pending forced changes, and
+        // empty list of authority set changes.
+        grandpa_authority_set.extend_from_slice(util::encode_scale_compact_usize(0).as_ref());
+        grandpa_authority_set.push(0);
+        grandpa_authority_set.extend_from_slice(util::encode_scale_compact_usize(0).as_ref());
+        grandpa_authority_set.extend_from_slice(util::encode_scale_compact_usize(0).as_ref());
+
+        Ok(LightSyncState {
+            babe_epoch_changes: HexString(babe_epoch_changes),
+            babe_finalized_block_weight: 0,
+            finalized_block_header: HexString(
+                information
+                    .finalized_block_header
+                    .scale_encoding_vec(block_number_bytes),
+            ),
+            grandpa_authority_set: HexString(grandpa_authority_set),
+        })
+    }
+
     pub(super) fn decode(
         &self,
         block_number_bytes: usize,
@@ -215,6 +308,36 @@ fn persisted_epoch<'a, E: nom::error::ParseError<&'a [u8]>>(
     ))(bytes)
 }
 
+/// Appends to `out` the encoding of the given epoch, in the format decoded by [`babe_epoch`].
+fn encode_babe_epoch(
+    out: &mut Vec<u8>,
+    epoch: BabeEpochInformationRef,
+    slots_per_epoch: u64,
+) -> Result<(), ChainInformationToCheckpointError> {
+    let start_slot_number = epoch
+        .start_slot_number
+        .ok_or(ChainInformationToCheckpointError::UnknownEpochStartSlot)?;
+
+    out.extend_from_slice(&epoch.epoch_index.to_le_bytes());
+    out.extend_from_slice(&start_slot_number.to_le_bytes());
+    out.extend_from_slice(&slots_per_epoch.to_le_bytes());
+    out.extend_from_slice(util::encode_scale_compact_usize(epoch.authorities.len()).as_ref());
+    for authority in epoch.authorities {
+        out.extend_from_slice(authority.public_key);
+        out.extend_from_slice(&authority.weight.to_le_bytes());
+    }
+    out.extend_from_slice(epoch.randomness);
+    for buffer in (BabeNextConfig {
+        c: epoch.c,
+        allowed_slots: epoch.allowed_slots,
+    })
+    .scale_encoding()
+    {
+        out.extend_from_slice(buffer.as_ref());
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub(super) struct BabeEpoch {
     pub(super) epoch_index: u64,
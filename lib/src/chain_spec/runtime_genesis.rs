@@ -0,0 +1,247 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Building the storage of the genesis block by executing the runtime.
+//!
+//! Instead of the raw storage of the genesis block, chain specifications can contain the
+//! runtime code and a JSON configuration of the genesis block. The storage of the genesis block
+//! is then obtained by calling the `GenesisBuilder_build_state` runtime function, which turns
+//! this JSON configuration into storage items.
+//!
+//! The JSON configuration can either be a full configuration, or a patch that is applied on top
+//! of the default configuration of the runtime, obtained by calling `GenesisBuilder_get_preset`.
+
+use super::RuntimeGenesisError;
+use crate::{
+    executor::{self, runtime_host},
+    util,
+};
+
+use alloc::{borrow::ToOwned as _, collections::BTreeMap, vec::Vec};
+use core::iter;
+
+/// JSON configuration of the genesis block to pass to the runtime.
+#[derive(Debug, Copy, Clone)]
+pub(super) enum GenesisConfig<'a> {
+    /// Configuration passed as is to `GenesisBuilder_build_state`.
+    Full(&'a serde_json::Value),
+    /// Configuration to apply on top of the default configuration of the runtime.
+    Patch(&'a serde_json::Value),
+}
+
+/// Executes the given runtime code in order to build the storage of the genesis block.
+///
+/// The returned storage contains the `:code` key, whose value is `code`.
+pub(super) fn build_genesis_storage(
+    code: &[u8],
+    config: GenesisConfig,
+) -> Result<BTreeMap<Vec<u8>, Vec<u8>>, RuntimeGenesisError> {
+    let virtual_machine = executor::host::HostVmPrototype::new(executor::host::Config {
+        module: code,
+        heap_pages: executor::DEFAULT_HEAP_PAGES,
+        exec_hint: executor::vm::ExecHint::Oneshot,
+        allow_unresolved_imports: true,
+    })
+    .map_err(RuntimeGenesisError::VmInitialization)?;
+
+    let (config_json, virtual_machine) = match config {
+        GenesisConfig::Full(config) => (serde_json::to_vec(config).unwrap(), virtual_machine),
+        GenesisConfig::Patch(patch) => {
+            // Passing `None` as parameter returns the default configuration.
+            let success = runtime_call(
+                virtual_machine,
+                "GenesisBuilder_get_preset",
+                iter::once([0u8]),
+            )?;
+
+            // The output is a SCALE-encoded `Option<Vec<u8>>` containing the JSON configuration.
+            let output = success.virtual_machine.value();
+            let default_config =
+                nom::combinator::all_consuming(util::nom_option_decode::<
+                    _,
+                    nom::error::Error<&[u8]>,
+                >(util::nom_bytes_decode))(output.as_ref())
+                .map_err(|_| RuntimeGenesisError::OutputDecode)?
+                .1
+                .ok_or(RuntimeGenesisError::NoDefaultPreset)?;
+
+            let mut config = serde_json::from_slice::<serde_json::Value>(default_config)
+                .map_err(RuntimeGenesisError::InvalidPreset)?;
+            drop(output);
+            json_merge_patch(&mut config, patch);
+
+            (
+                serde_json::to_vec(&config).unwrap(),
+                success.virtual_machine.into_prototype(),
+            )
+        }
+    };
+
+    let success = runtime_call(
+        virtual_machine,
+        "GenesisBuilder_build_state",
+        [
+            either::Left(util::encode_scale_compact_usize(config_json.len())),
+            either::Right(&config_json[..]),
+        ]
+        .into_iter(),
+    )?;
+
+    // The output is a SCALE-encoded `Result<(), String>`.
+    let output = success.virtual_machine.value();
+    let result: Result<_, nom::Err<nom::error::Error<&[u8]>>> =
+        nom::combinator::all_consuming(nom::branch::alt((
+            nom::combinator::map(nom::bytes::complete::tag(&[0]), |_| Ok(())),
+            nom::combinator::map(
+                nom::sequence::preceded(nom::bytes::complete::tag(&[1]), util::nom_string_decode),
+                Err,
+            ),
+        )))(output.as_ref());
+    match result {
+        Ok((_, Ok(()))) => {}
+        Ok((_, Err(error))) => return Err(RuntimeGenesisError::BuildState(error.to_owned())),
+        Err(_) => return Err(RuntimeGenesisError::OutputDecode),
+    }
+
+    // TODO: child tries not supported
+    if success
+        .storage_changes
+        .tries_with_storage_changes_unordered()
+        .next()
+        .is_some()
+    {
+        return Err(RuntimeGenesisError::ChildTriesNotSupported);
+    }
+
+    let mut storage = success
+        .storage_changes
+        .main_trie_storage_changes_iter_unordered()
+        .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+        .collect::<BTreeMap<_, _>>();
+    storage.insert(b":code".to_vec(), code.to_vec());
+    Ok(storage)
+}
+
+/// Performs a runtime call on top of an empty storage.
+fn runtime_call(
+    virtual_machine: executor::host::HostVmPrototype,
+    function_to_call: &str,
+    parameter: impl Iterator<Item = impl AsRef<[u8]>> + Clone,
+) -> Result<runtime_host::Success, RuntimeGenesisError> {
+    let mut call = runtime_host::run(runtime_host::Config {
+        virtual_machine,
+        function_to_call,
+        parameter,
+        storage_main_trie_changes: Default::default(),
+        max_log_level: 0,
+        calculate_trie_changes: false,
+    })
+    .map_err(|(error, _)| RuntimeGenesisError::VmStart(error))?;
+
+    loop {
+        match call {
+            runtime_host::RuntimeHostVm::Finished(Ok(success)) => return Ok(success),
+            runtime_host::RuntimeHostVm::Finished(Err(error)) => {
+                return Err(RuntimeGenesisError::Execution(error.detail))
+            }
+            runtime_host::RuntimeHostVm::StorageGet(req) => {
+                call = req.inject_value(None::<(iter::Empty<&[u8]>, _)>);
+            }
+            runtime_host::RuntimeHostVm::ClosestDescendantMerkleValue(req) => {
+                call = req.inject_merkle_value(None);
+            }
+            runtime_host::RuntimeHostVm::NextKey(req) => {
+                call = req.inject_key(None::<iter::Empty<_>>);
+            }
+            runtime_host::RuntimeHostVm::SignatureVerification(sig) => {
+                call = sig.verify_and_resume();
+            }
+            runtime_host::RuntimeHostVm::OffchainStorageSet(req) => {
+                // Do nothing.
+                call = req.resume();
+            }
+            runtime_host::RuntimeHostVm::LogEmit(req) => {
+                // Generated logs are ignored.
+                call = req.resume();
+            }
+            runtime_host::RuntimeHostVm::Offchain(_)
+            | runtime_host::RuntimeHostVm::GenerateKey(_) => {
+                return Err(RuntimeGenesisError::ForbiddenHostFunction)
+            }
+        }
+    }
+}
+
+/// Applies a JSON merge patch, as defined by RFC 7396, to the given value.
+///
+/// Objects are merged recursively. Fields of the patch whose value is `null` are removed from
+/// the target. Any other value of the patch replaces the value of the target.
+fn json_merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let serde_json::Value::Object(target) = target else {
+        unreachable!()
+    };
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            json_merge_patch(
+                target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn json_merge_patch() {
+        let mut target = serde_json::json!({
+            "balances": { "balances": [["alice", 1]] },
+            "sudo": { "key": "alice" },
+            "aura": { "authorities": [] },
+        });
+
+        super::json_merge_patch(
+            &mut target,
+            &serde_json::json!({
+                "balances": { "balances": [["bob", 2]] },
+                "sudo": null,
+                "aura": { "authorities": ["charlie"] },
+                "grandpa": { "authorities": [] },
+            }),
+        );
+
+        assert_eq!(
+            target,
+            serde_json::json!({
+                "balances": { "balances": [["bob", 2]] },
+                "aura": { "authorities": ["charlie"] },
+                "grandpa": { "authorities": [] },
+            })
+        );
+    }
+}
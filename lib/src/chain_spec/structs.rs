@@ -43,7 +43,6 @@ pub(super) struct ClientSpec {
     /// the given block number until the `spec_version`
     /// ([`crate::executor::host::CoreVersionRef::spec_version`]) on chain changes.
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    // TODO: make use of this
     pub(super) code_substitutes: HashMap<u64, HexString, fnv::FnvBuildHasher>,
     pub(super) boot_nodes: Vec<String>,
     #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
@@ -61,7 +60,6 @@ pub(super) struct ClientSpec {
     pub(super) block_number_bytes: Option<u8>,
     pub(super) properties: Option<Box<serde_json::value::RawValue>>,
     #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
-    // TODO: make use of this
     pub(super) fork_blocks: Option<Vec<(u64, HashHexString)>>,
     #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
     pub(super) bad_blocks: Option<HashSet<HashHexString, FnvBuildHasher>>,
@@ -103,6 +101,24 @@ impl Default for ChainType {
 pub(super) enum Genesis {
     Raw(RawGenesis),
     StateRootHash(HashHexString),
+    /// Runtime code and JSON configuration from which the raw storage is built. Always turned
+    /// into [`Genesis::Raw`] when the chain spec is parsed.
+    RuntimeGenesis(RuntimeGenesis),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub(super) struct RuntimeGenesis {
+    pub(super) code: HexString,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        alias = "runtimeGenesisConfig"
+    )]
+    pub(super) config: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) patch: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
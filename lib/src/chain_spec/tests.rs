@@ -17,7 +17,15 @@
 
 #![cfg(test)]
 
-use super::{Bootnode, ChainSpec, CheckpointToChainInformationError};
+use super::{
+    Bootnode, BuildConfig, BuildError, BuildGenesis, ChainSpec, ChainType,
+    CheckpointToChainInformationError, ParseError, ParseErrorInner, RuntimeGenesisConfig,
+    RuntimeGenesisError,
+};
+use crate::{chain::chain_information, header};
+
+use alloc::vec;
+use core::num::NonZeroU64;
 
 #[test]
 fn can_decode_polkadot_genesis() {
@@ -143,6 +151,34 @@ fn relay_chain_para_id_either_both_present_or_absent() {
     .is_err());
 }
 
+#[test]
+fn code_substitutes() {
+    let chain_spec = ChainSpec::from_json_bytes(
+        r#"{
+            "name": "Test",
+            "id": "test",
+            "bootNodes": [],
+            "codeSubstitutes": {
+              "1500": "0x0102",
+              "20": "0x03"
+            },
+            "genesis": {
+              "raw": {
+                "top": {},
+                "childrenDefault": {}
+              }
+            }
+          }
+          "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        chain_spec.code_substitutes().collect::<Vec<_>>(),
+        vec![(20, &[0x03][..]), (1500, &[0x01, 0x02][..])]
+    );
+}
+
 #[test]
 fn issue_598() {
     // Regression test for a panic.
@@ -155,3 +191,345 @@ fn issue_598() {
         Err(CheckpointToChainInformationError::GenesisBlockCheckpoint)
     ));
 }
+
+#[test]
+fn set_genesis_storage_round_trip() {
+    let mut chain_spec =
+        ChainSpec::from_json_bytes(include_bytes!("./tests/issue-598.json")).unwrap();
+    assert!(chain_spec.light_sync_state().is_some());
+
+    chain_spec.set_genesis_storage(
+        [(b"foo".to_vec(), b"bar".to_vec()), (vec![], vec![1, 2, 3])].into_iter(),
+    );
+    assert!(chain_spec.light_sync_state().is_none());
+
+    let chain_spec = ChainSpec::from_json_bytes(chain_spec.serialize()).unwrap();
+    let genesis_storage = chain_spec.genesis_storage().into_genesis_items().unwrap();
+    assert_eq!(genesis_storage.iter().len(), 2);
+    assert_eq!(genesis_storage.value(b"foo"), Some(&b"bar"[..]));
+    assert_eq!(genesis_storage.value(&[]), Some(&[1, 2, 3][..]));
+}
+
+#[test]
+fn set_light_sync_state_round_trip() {
+    let digest_items = [
+        header::DigestItem::BabePreDigest(header::BabePreDigest::SecondaryPlain(
+            header::BabeSecondaryPlainPreDigest {
+                authority_index: 0,
+                slot_number: 105,
+            },
+        )),
+        header::DigestItem::BabeSeal([0; 64]),
+    ];
+    let epoch = |epoch_index, start_slot_number| chain_information::BabeEpochInformation {
+        epoch_index,
+        start_slot_number: Some(start_slot_number),
+        authorities: vec![header::BabeAuthority {
+            public_key: [1; 32],
+            weight: 1,
+        }],
+        randomness: [2; 32],
+        c: (1, 4),
+        allowed_slots: header::BabeAllowedSlots::PrimaryAndSecondaryPlainSlots,
+    };
+    let information =
+        chain_information::ValidChainInformation::try_from(chain_information::ChainInformation {
+            finalized_block_header: Box::new(header::Header {
+                parent_hash: [3; 32],
+                number: 5,
+                state_root: [4; 32],
+                extrinsics_root: [5; 32],
+                digest: header::DigestRef::from_slice(&digest_items).unwrap().into(),
+            }),
+            consensus: chain_information::ChainInformationConsensus::Babe {
+                slots_per_epoch: NonZeroU64::new(10).unwrap(),
+                finalized_block_epoch_information: Some(Box::new(epoch(10, 100))),
+                finalized_next_epoch_transition: Box::new(epoch(11, 110)),
+            },
+            finality: chain_information::ChainInformationFinality::Grandpa {
+                after_finalized_block_authorities_set_id: 3,
+                finalized_triggered_authorities: vec![header::GrandpaAuthority {
+                    public_key: [6; 32],
+                    weight: NonZeroU64::new(1).unwrap(),
+                }],
+                finalized_scheduled_change: None,
+            },
+        })
+        .unwrap();
+
+    let mut chain_spec =
+        ChainSpec::from_json_bytes(include_bytes!("./tests/issue-598.json")).unwrap();
+    chain_spec.set_light_sync_state(&information).unwrap();
+
+    let chain_spec = ChainSpec::from_json_bytes(chain_spec.serialize()).unwrap();
+    let decoded = chain_spec
+        .light_sync_state()
+        .unwrap()
+        .to_chain_information()
+        .unwrap();
+    let decoded = decoded.as_ref();
+
+    assert_eq!(
+        decoded.finalized_block_header.hash(4),
+        information.as_ref().finalized_block_header.hash(4)
+    );
+    match decoded.consensus {
+        chain_information::ChainInformationConsensusRef::Babe {
+            slots_per_epoch,
+            finalized_block_epoch_information: Some(current_epoch),
+            finalized_next_epoch_transition: next_epoch,
+        } => {
+            assert_eq!(slots_per_epoch.get(), 10);
+            assert_eq!(current_epoch.epoch_index, 10);
+            assert_eq!(current_epoch.start_slot_number, Some(100));
+            assert_eq!(next_epoch.epoch_index, 11);
+            assert_eq!(next_epoch.start_slot_number, Some(110));
+            assert_eq!(next_epoch.randomness, &[2; 32]);
+        }
+        _ => panic!(),
+    }
+    match decoded.finality {
+        chain_information::ChainInformationFinalityRef::Grandpa {
+            after_finalized_block_authorities_set_id,
+            finalized_triggered_authorities,
+            ..
+        } => {
+            assert_eq!(after_finalized_block_authorities_set_id, 3);
+            assert_eq!(finalized_triggered_authorities.len(), 1);
+            assert_eq!(finalized_triggered_authorities[0].public_key, [6; 32]);
+        }
+        _ => panic!(),
+    }
+}
+
+/// Returns a minimal runtime whose `GenesisBuilder_build_state` function writes `value` at
+/// `key` in the storage, and whose `GenesisBuilder_get_preset` function returns `None`.
+fn genesis_builder_runtime() -> Vec<u8> {
+    wat::parse_str(
+        r#"
+    (module
+        (@custom "runtime_version" "\0cfoo\0cbar\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00\00")
+        (@custom "runtime_apis" "")
+        (import "env" "memory" (memory 1))
+        (import "env" "ext_storage_set_version_1" (func $storage_set (param i64 i64)))
+        (global (export "__heap_base") i32 (i32.const 1024))
+        (data (i32.const 0) "\00")
+        (data (i32.const 8) "key")
+        (data (i32.const 16) "value")
+        (func (export "GenesisBuilder_build_state") (param i32 i32) (result i64)
+            (call $storage_set (i64.const 0x300000008) (i64.const 0x500000010))
+            (i64.const 0x100000000))
+        (func (export "GenesisBuilder_get_preset") (param i32 i32) (result i64)
+            (i64.const 0x100000000))
+    )
+    "#,
+    )
+    .unwrap()
+}
+
+fn runtime_genesis_chain_spec(code: &[u8], genesis_config: &str) -> String {
+    format!(
+        r#"{{
+            "name": "Test",
+            "id": "test",
+            "bootNodes": [],
+            "genesis": {{
+              "runtimeGenesis": {{
+                "code": "0x{}",
+                {genesis_config}
+              }}
+            }}
+          }}
+          "#,
+        hex::encode(code)
+    )
+}
+
+#[test]
+fn runtime_genesis_build_state() {
+    let code = genesis_builder_runtime();
+    let chain_spec =
+        ChainSpec::from_json_bytes(runtime_genesis_chain_spec(&code, r#""config": {}"#)).unwrap();
+
+    let genesis_storage = chain_spec.genesis_storage().into_genesis_items().unwrap();
+    assert_eq!(genesis_storage.iter().len(), 2);
+    assert_eq!(genesis_storage.value(b"key"), Some(&b"value"[..]));
+    assert_eq!(genesis_storage.value(b":code"), Some(&code[..]));
+
+    // The chain spec is serialized with its raw storage.
+    let serialized = ChainSpec::from_json_bytes(chain_spec.serialize()).unwrap();
+    assert_eq!(
+        serialized
+            .genesis_storage()
+            .into_genesis_items()
+            .unwrap()
+            .value(b"key"),
+        Some(&b"value"[..])
+    );
+}
+
+#[test]
+fn runtime_genesis_patch_without_default_preset() {
+    let code = genesis_builder_runtime();
+    assert!(matches!(
+        ChainSpec::from_json_bytes(runtime_genesis_chain_spec(&code, r#""patch": {}"#)),
+        Err(ParseError(ParseErrorInner::RuntimeGenesis(
+            RuntimeGenesisError::NoDefaultPreset
+        )))
+    ));
+}
+
+#[test]
+fn runtime_genesis_config_and_patch_exclusive() {
+    let code = genesis_builder_runtime();
+    assert!(ChainSpec::from_json_bytes(runtime_genesis_chain_spec(
+        &code,
+        r#""config": {}, "patch": {}"#
+    ))
+    .is_err());
+    assert!(
+        ChainSpec::from_json_bytes(runtime_genesis_chain_spec(&code, r#""config": null"#)).is_err()
+    );
+}
+
+#[test]
+fn runtime_genesis_invalid_code() {
+    assert!(matches!(
+        ChainSpec::from_json_bytes(runtime_genesis_chain_spec(&[1, 2, 3], r#""config": {}"#)),
+        Err(ParseError(ParseErrorInner::RuntimeGenesis(
+            RuntimeGenesisError::VmInitialization(_)
+        )))
+    ));
+}
+
+#[test]
+fn runtime_genesis_error_displayed() {
+    let Err(error) = ChainSpec::from_json_bytes(runtime_genesis_chain_spec(
+        &genesis_builder_runtime(),
+        r#""patch": {}"#,
+    )) else {
+        panic!()
+    };
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "Failed to build the genesis of the chain spec: {}",
+            RuntimeGenesisError::NoDefaultPreset
+        )
+    );
+}
+
+type RawGenesisStorage = vec::IntoIter<(Vec<u8>, Vec<u8>)>;
+
+fn build_config<'a>(
+    genesis: BuildGenesis<'a, RawGenesisStorage>,
+) -> BuildConfig<'a, vec::IntoIter<&'a str>, RawGenesisStorage> {
+    BuildConfig {
+        name: "Test",
+        id: "test",
+        chain_type: ChainType::Custom("Ephemeral"),
+        boot_nodes: vec![
+            "/dns4/example.com/tcp/30333/p2p/12D3KooWEyoppNCUx8Yx66oV9fJnriXwCcXwDDUA2kj6vnc6iDEp",
+        ]
+        .into_iter(),
+        properties: Some(r#"{"tokenSymbol":"TST"}"#),
+        protocol_id: Some("tst"),
+        fork_id: Some("fork"),
+        block_number_bytes: 8,
+        relay_chain: Some(("relay", 2000)),
+        genesis,
+    }
+}
+
+#[test]
+fn build_raw_genesis_round_trip() {
+    let chain_spec = ChainSpec::build(build_config(BuildGenesis::Raw(
+        vec![
+            (b":code".to_vec(), vec![1, 2, 3]),
+            (b"foo".to_vec(), b"bar".to_vec()),
+        ]
+        .into_iter(),
+    )))
+    .unwrap();
+
+    let chain_spec = ChainSpec::from_json_bytes(chain_spec.serialize()).unwrap();
+    assert_eq!(chain_spec.name(), "Test");
+    assert_eq!(chain_spec.id(), "test");
+    assert_eq!(chain_spec.chain_type(), "Ephemeral");
+    assert!(matches!(
+        chain_spec.boot_nodes().collect::<Vec<_>>()[..],
+        [Bootnode::Parsed { ref multiaddr, .. }] if multiaddr == "/dns4/example.com/tcp/30333"
+    ));
+    assert_eq!(chain_spec.properties(), r#"{"tokenSymbol":"TST"}"#);
+    assert_eq!(chain_spec.protocol_id(), Some("tst"));
+    assert_eq!(chain_spec.fork_id(), Some("fork"));
+    assert_eq!(chain_spec.block_number_bytes(), 8);
+    assert_eq!(chain_spec.relay_chain(), Some(("relay", 2000)));
+
+    let genesis_storage = chain_spec.genesis_storage().into_genesis_items().unwrap();
+    assert_eq!(genesis_storage.iter().len(), 2);
+    assert_eq!(genesis_storage.value(b":code"), Some(&[1, 2, 3][..]));
+    assert_eq!(genesis_storage.value(b"foo"), Some(&b"bar"[..]));
+}
+
+#[test]
+fn build_runtime_genesis() {
+    let code = genesis_builder_runtime();
+    let chain_spec = ChainSpec::build(build_config(BuildGenesis::RuntimeGenesis {
+        code: &code,
+        config: RuntimeGenesisConfig::Full("{}"),
+    }))
+    .unwrap();
+
+    let genesis_storage = chain_spec.genesis_storage().into_genesis_items().unwrap();
+    assert_eq!(genesis_storage.value(b"key"), Some(&b"value"[..]));
+    assert_eq!(genesis_storage.value(b":code"), Some(&code[..]));
+
+    assert!(matches!(
+        ChainSpec::build(build_config(BuildGenesis::RuntimeGenesis {
+            code: &code,
+            config: RuntimeGenesisConfig::Full("not json"),
+        })),
+        Err(BuildError::InvalidGenesisConfig(_))
+    ));
+}
+
+#[test]
+fn build_validation() {
+    let genesis = || BuildGenesis::Raw(vec![(b":code".to_vec(), vec![1, 2, 3])].into_iter());
+
+    assert!(matches!(
+        ChainSpec::build(BuildConfig {
+            id: "",
+            ..build_config(genesis())
+        }),
+        Err(BuildError::EmptyNameOrId)
+    ));
+    assert!(matches!(
+        ChainSpec::build(BuildConfig {
+            block_number_bytes: 0,
+            ..build_config(genesis())
+        }),
+        Err(BuildError::InvalidBlockNumberBytes)
+    ));
+    assert!(matches!(
+        ChainSpec::build(BuildConfig {
+            properties: Some("[1, 2]"),
+            ..build_config(genesis())
+        }),
+        Err(BuildError::InvalidProperties(_))
+    ));
+    assert!(matches!(
+        ChainSpec::build(BuildConfig {
+            boot_nodes: vec!["/dns4/example.com/tcp/30333"].into_iter(),
+            ..build_config(genesis())
+        }),
+        Err(BuildError::InvalidBootNode(addr)) if addr == "/dns4/example.com/tcp/30333"
+    ));
+    assert!(matches!(
+        ChainSpec::build(build_config(BuildGenesis::Raw(
+            vec![(b"foo".to_vec(), b"bar".to_vec())].into_iter()
+        ))),
+        Err(BuildError::RuntimeNotFound)
+    ));
+}
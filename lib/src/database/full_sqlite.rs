@@ -35,10 +35,15 @@
 //! Any block that isn't an ancestor or descendant will be removed. Reverting finalization is
 //! not supported.
 //!
-//! In order to minimize disk usage, it is not possible to efficiently retrieve the storage items
-//! of blocks that are ancestors of the finalized block. When a block is finalized, the storage of
-//! its ancestors is lost, and the only way to reconstruct it is to execute all blocks starting
-//! from the genesis to the desired one.
+//! In order to minimize disk usage, the storage of old finalized blocks can be removed from the
+//! database depending on the [`StatePruning`] passed in the [`Config`]. Use
+//! [`SqliteFullDatabase::prune_state`] in order to remove the storage that is no longer needed.
+//! Once the storage of a block has been removed, the only way to reconstruct it is to execute all
+//! blocks starting from the genesis to the desired one.
+//!
+//! Similarly, the bodies and justifications of old finalized blocks can be removed depending on
+//! the [`BlocksPruning`] passed in the [`Config`]. Use [`SqliteFullDatabase::prune_blocks`] in
+//! order to remove them. The headers of all the blocks are always kept.
 //!
 //! # About errors handling
 //!
@@ -71,14 +76,14 @@
 #![cfg(feature = "database-sqlite")]
 #![cfg_attr(docsrs, doc(cfg(feature = "database-sqlite")))]
 
-use crate::{chain::chain_information, header, util};
+use crate::{chain::chain_information, finality::grandpa, header, util};
 
 use alloc::borrow::Cow;
 use core::{fmt, iter, num::NonZeroU64};
 use parking_lot::Mutex;
 use rusqlite::OptionalExtension as _;
 
-pub use open::{open, Config, ConfigTy, DatabaseEmpty, DatabaseOpen};
+pub use open::{open, BlocksPruning, Config, ConfigTy, DatabaseEmpty, DatabaseOpen, StatePruning};
 
 mod open;
 mod tests;
@@ -101,6 +106,12 @@ pub struct SqliteFullDatabase {
 
     /// Number of bytes used to encode the block number.
     block_number_bytes: usize,
+
+    /// See [`Config::state_pruning`].
+    state_pruning: StatePruning,
+
+    /// See [`Config::blocks_pruning`].
+    blocks_pruning: BlocksPruning,
 }
 
 impl SqliteFullDatabase {
@@ -124,6 +135,44 @@ impl SqliteFullDatabase {
         finalized_hash(&database)
     }
 
+    /// Returns the number of times, since the database has been opened, that a page has been
+    /// found in the SQLite cache (first value) and that it hasn't (second value).
+    ///
+    /// A value is 0 if the SQLite library fails to report it.
+    pub fn cache_statistics(&self) -> (u64, u64) {
+        let database = self.database.lock();
+
+        let get = |op| {
+            let mut current = 0;
+            let mut highwater = 0;
+            // `rusqlite` doesn't provide any safe wrapper around `sqlite3_db_status`, which is
+            // the only way to obtain these statistics. The raw FFI function must be called
+            // instead.
+            // SAFETY: the handle is valid as long as `database` is alive, and the pointers
+            // point to valid integers.
+            let result = unsafe {
+                rusqlite::ffi::sqlite3_db_status(
+                    database.handle(),
+                    op,
+                    &mut current,
+                    &mut highwater,
+                    0,
+                )
+            };
+            // An error can only happen if `op` isn't supported by the version of SQLite, in
+            // which case the statistic is reported as 0.
+            if result != rusqlite::ffi::SQLITE_OK {
+                return 0;
+            }
+            u64::try_from(current).unwrap_or(0)
+        };
+
+        (
+            get(rusqlite::ffi::SQLITE_DBSTATUS_CACHE_HIT),
+            get(rusqlite::ffi::SQLITE_DBSTATUS_CACHE_MISS),
+        )
+    }
+
     /// Returns the SCALE-encoded header of the given block, or `None` if the block is unknown.
     ///
     /// > **Note**: If this method is called twice times in a row with the same block hash, it
@@ -163,20 +212,34 @@ impl SqliteFullDatabase {
         Ok(out)
     }
 
-    /// Returns the list of extrinsics of the given block, or `None` if the block is unknown.
+    /// Returns the list of extrinsics of the given block.
+    ///
+    /// Returns [`BlockBodyAccessError::UnknownBlock`] if the block is unknown, and
+    /// [`BlockBodyAccessError::BodyPruned`] if the body of the block has been removed by
+    /// [`SqliteFullDatabase::prune_blocks`].
     ///
     /// > **Note**: The list of extrinsics of a block is also known as its *body*.
     ///
     /// > **Note**: If this method is called twice times in a row with the same block hash, it
-    /// >           is possible for the first time to return `Some` and the second time to return
-    /// >           `None`, in case the block has since been removed from the database.
+    /// >           is possible for the first time to return `Ok` and the second time to return
+    /// >           an error, in case the block has since been removed from the database.
     pub fn block_extrinsics(
         &self,
         block_hash: &[u8; 32],
-    ) -> Result<Option<impl ExactSizeIterator<Item = Vec<u8>>>, CorruptedError> {
+    ) -> Result<impl ExactSizeIterator<Item = Vec<u8>>, BlockBodyAccessError> {
         let connection = self.database.lock();
 
-        // TODO: doesn't detect if block is absent
+        let body_pruned = connection
+            .prepare_cached(r#"SELECT body_pruned FROM blocks WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&block_hash[..],), |row| row.get::<_, bool>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        match body_pruned {
+            None => return Err(BlockBodyAccessError::UnknownBlock),
+            Some(true) => return Err(BlockBodyAccessError::BodyPruned),
+            Some(false) => {}
+        }
 
         let result = connection
             .prepare_cached(r#"SELECT extrinsic FROM blocks_body WHERE hash = ? ORDER BY idx ASC"#)
@@ -186,7 +249,334 @@ impl SqliteFullDatabase {
             .collect::<Result<Vec<_>, _>>()
             .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
-        Ok(Some(result.into_iter()))
+        Ok(result.into_iter())
+    }
+
+    /// Returns the SCALE-encoded GrandPa justification of the given block, or `None` if the
+    /// block is unknown or doesn't have any justification stored.
+    pub fn block_justification(
+        &self,
+        block_hash: &[u8; 32],
+    ) -> Result<Option<Vec<u8>>, CorruptedError> {
+        let connection = self.database.lock();
+
+        let out = connection
+            .prepare_cached(r#"SELECT justification FROM blocks WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&block_hash[..],), |row| row.get::<_, Option<Vec<u8>>>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(out.flatten())
+    }
+
+    /// Stores the SCALE-encoded GrandPa justification of the given block, overwriting the
+    /// previous one if any.
+    ///
+    /// The justification isn't verified by this function.
+    pub fn set_block_justification(
+        &self,
+        block_hash: &[u8; 32],
+        scale_encoded_justification: &[u8],
+    ) -> Result<(), SetJustificationError> {
+        let connection = self.database.lock();
+
+        let num_updated = connection
+            .prepare_cached(r#"UPDATE blocks SET justification = ? WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((scale_encoded_justification, &block_hash[..]))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        if num_updated == 0 {
+            return Err(SetJustificationError::UnknownBlock);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the latest round reached by the local GrandPa voter in the given authorities set,
+    /// and the votes it has cast in this set. Returns `None` if nothing has been stored for this
+    /// set.
+    pub fn grandpa_voter_state(
+        &self,
+        set_id: u64,
+    ) -> Result<Option<(u64, Vec<grandpa::voter::LocalVote>)>, CorruptedError> {
+        let connection = self.database.lock();
+
+        let set_id = i64::from_ne_bytes(set_id.to_ne_bytes());
+
+        let Some(round_number) = connection
+            .prepare_cached(r#"SELECT round_number FROM grandpa_voter_round WHERE set_id = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((set_id,), |row| row.get::<_, i64>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        else {
+            return Ok(None);
+        };
+
+        let mut statement = connection
+            .prepare_cached(
+                r#"SELECT round_number, kind, target_hash, target_number, authority_public_key, signature
+                FROM grandpa_voter_local_votes WHERE set_id = ?"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        let local_votes = statement
+            .query_map((set_id,), |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, Vec<u8>>(4)?,
+                    row.get::<_, Vec<u8>>(5)?,
+                ))
+            })
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .map(|row| {
+                let (
+                    round_number,
+                    kind,
+                    target_hash,
+                    target_number,
+                    authority_public_key,
+                    signature,
+                ) = row.map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+                Ok(grandpa::voter::LocalVote {
+                    round_number: u64::from_ne_bytes(round_number.to_ne_bytes()),
+                    kind: match kind {
+                        0 => grandpa::voter::VoteKind::Prevote,
+                        1 => grandpa::voter::VoteKind::Precommit,
+                        2 => grandpa::voter::VoteKind::PrimaryPropose,
+                        _ => return Err(CorruptedError::InvalidGrandpaVote),
+                    },
+                    target_hash: <[u8; 32]>::try_from(&target_hash[..])
+                        .map_err(|_| CorruptedError::InvalidBlockHashLen)?,
+                    target_number: u64::try_from(target_number)
+                        .map_err(|_| CorruptedError::InvalidNumber)?,
+                    authority_public_key: <[u8; 32]>::try_from(&authority_public_key[..])
+                        .map_err(|_| CorruptedError::InvalidGrandpaVote)?,
+                    signature: <[u8; 64]>::try_from(&signature[..])
+                        .map_err(|_| CorruptedError::InvalidGrandpaVote)?,
+                })
+            })
+            .collect::<Result<Vec<_>, CorruptedError>>()?;
+
+        Ok(Some((
+            u64::from_ne_bytes(round_number.to_ne_bytes()),
+            local_votes,
+        )))
+    }
+
+    /// Stores the latest round reached by the local GrandPa voter in the given authorities set.
+    ///
+    /// The state of all the other authorities sets, including the votes, is removed from the
+    /// database.
+    pub fn set_grandpa_voter_round(
+        &self,
+        set_id: u64,
+        round_number: u64,
+    ) -> Result<(), CorruptedError> {
+        let mut connection = self.database.lock();
+        let transaction = connection
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let set_id = i64::from_ne_bytes(set_id.to_ne_bytes());
+        transaction
+            .prepare_cached(r#"DELETE FROM grandpa_voter_round WHERE set_id != ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((set_id,))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        transaction
+            .prepare_cached(r#"DELETE FROM grandpa_voter_local_votes WHERE set_id != ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((set_id,))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        transaction
+            .prepare_cached(
+                r#"INSERT OR REPLACE INTO grandpa_voter_round(set_id, round_number) VALUES (?, ?)"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((set_id, i64::from_ne_bytes(round_number.to_ne_bytes())))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        Ok(())
+    }
+
+    /// Stores a vote cast by the local GrandPa voter. Must be called before the vote is sent to
+    /// the network.
+    ///
+    /// Returns an error if the local voter has already cast a vote of the same kind in the same
+    /// round, in which case the vote must not be sent, as it would be an equivocation.
+    pub fn insert_grandpa_voter_local_vote(
+        &self,
+        set_id: u64,
+        vote: &grandpa::voter::LocalVote,
+    ) -> Result<(), InsertGrandpaVoteError> {
+        let mut connection = self.database.lock();
+        let transaction = connection
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let set_id = i64::from_ne_bytes(set_id.to_ne_bytes());
+        let round_number = i64::from_ne_bytes(vote.round_number.to_ne_bytes());
+        let kind = match vote.kind {
+            grandpa::voter::VoteKind::Prevote => 0,
+            grandpa::voter::VoteKind::Precommit => 1,
+            grandpa::voter::VoteKind::PrimaryPropose => 2,
+        };
+
+        let already_voted = transaction
+            .prepare_cached(
+                r#"SELECT COUNT(*) FROM grandpa_voter_local_votes WHERE set_id = ? AND round_number = ? AND kind = ?"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((set_id, round_number, kind), |row| row.get::<_, i64>(0))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            != 0;
+        if already_voted {
+            return Err(InsertGrandpaVoteError::AlreadyVoted);
+        }
+
+        transaction
+            .prepare_cached(
+                r#"INSERT INTO grandpa_voter_local_votes(set_id, round_number, kind, target_hash, target_number, authority_public_key, signature) VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((
+                set_id,
+                round_number,
+                kind,
+                &vote.target_hash[..],
+                i64::try_from(vote.target_number).map_err(|_| CorruptedError::InvalidNumber)?,
+                &vote.authority_public_key[..],
+                &vote.signature[..],
+            ))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        transaction
+            .prepare_cached(
+                r#"INSERT INTO grandpa_voter_round(set_id, round_number) VALUES (?, ?)
+                ON CONFLICT(set_id) DO UPDATE SET round_number = MAX(round_number, excluded.round_number)"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .execute((set_id, round_number))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        Ok(())
+    }
+
+    /// Builds the fragments of a GrandPa warp sync proof starting at the given finalized block.
+    ///
+    /// The fragments consist in the SCALE-encoded headers and GrandPa justifications of the
+    /// finalized blocks that are strictly above `begin_block_hash` and whose header contains a
+    /// change in the list of GrandPa authorities, followed with the highest finalized block
+    /// that has a justification. Only blocks whose justification is stored in the database are
+    /// considered.
+    ///
+    /// Because a forced change of the list of GrandPa authorities breaks the chain of trust
+    /// between authorities sets, the fragments stop before the first block containing a forced
+    /// change, similar to what Substrate does.
+    ///
+    /// No more fragments are added once their total size exceeds `max_size`, in which case
+    /// [`GrandpaWarpSyncFragments::is_finished`] is `false`.
+    ///
+    /// Returns `None` if `begin_block_hash` isn't a finalized block of the database.
+    pub fn grandpa_warp_sync_fragments(
+        &self,
+        begin_block_hash: &[u8; 32],
+        max_size: usize,
+    ) -> Result<Option<GrandpaWarpSyncFragments>, CorruptedError> {
+        let connection = self.database.lock();
+
+        let finalized_num = finalized_num(&connection)?;
+
+        let begin_number = connection
+            .prepare_cached(r#"SELECT number FROM blocks WHERE hash = ? AND is_best_chain = TRUE"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&begin_block_hash[..],), |row| row.get::<_, i64>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        let begin_number = match begin_number {
+            Some(n) => u64::try_from(n).map_err(|_| CorruptedError::InvalidNumber)?,
+            None => return Ok(None),
+        };
+        if begin_number > finalized_num {
+            return Ok(None);
+        }
+
+        let mut statement = connection
+            .prepare_cached(
+                r#"SELECT header, justification, grandpa_forced_change FROM blocks
+                WHERE number > ? AND number <= ? AND is_best_chain = TRUE
+                    AND (justification IS NOT NULL OR grandpa_forced_change = TRUE)
+                ORDER BY number ASC"#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        let mut rows = statement
+            .query((
+                i64::try_from(begin_number).unwrap(),
+                i64::try_from(finalized_num).unwrap(),
+            ))
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let mut fragments = Vec::new();
+        let mut fragments_size = 0;
+        let mut latest_justified = None;
+
+        while let Some(row) = rows
+            .next()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        {
+            let scale_encoded_header = row
+                .get::<_, Vec<u8>>(0)
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            let scale_encoded_justification = row
+                .get::<_, Option<Vec<u8>>>(1)
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            let grandpa_forced_change = row
+                .get::<_, bool>(2)
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            // Justifications that follow a forced change can't be verified against the
+            // authorities found in the previous fragments.
+            if grandpa_forced_change {
+                break;
+            }
+
+            let Some(scale_encoded_justification) = scale_encoded_justification else {
+                continue;
+            };
+
+            if !changes_grandpa_authorities(&scale_encoded_header, self.block_number_bytes)? {
+                latest_justified = Some((scale_encoded_header, scale_encoded_justification));
+                continue;
+            }
+
+            if !fragments.is_empty() && fragments_size >= max_size {
+                return Ok(Some(GrandpaWarpSyncFragments {
+                    fragments,
+                    is_finished: false,
+                }));
+            }
+
+            fragments_size += scale_encoded_header.len() + scale_encoded_justification.len();
+            fragments.push((scale_encoded_header, scale_encoded_justification));
+            latest_justified = None;
+        }
+
+        // The proof ends with the highest justified block, unless it is already in the list.
+        fragments.extend(latest_justified);
+        Ok(Some(GrandpaWarpSyncFragments {
+            fragments,
+            is_finished: true,
+        }))
     }
 
     /// Returns the hashes of the blocks given a block number.
@@ -382,7 +772,7 @@ impl SqliteFullDatabase {
 
         transaction
             .prepare_cached(
-                "INSERT INTO blocks(number, hash, parent_hash, state_trie_root_hash, header, is_best_chain, justification) VALUES (?, ?, ?, ?, ?, FALSE, NULL)",
+                "INSERT INTO blocks(number, hash, parent_hash, state_trie_root_hash, header, is_best_chain, justification, grandpa_forced_change) VALUES (?, ?, ?, ?, ?, FALSE, NULL, ?)",
             )
             .unwrap()
             .execute((
@@ -390,7 +780,8 @@ impl SqliteFullDatabase {
                 &block_hash[..],
                 &header.parent_hash[..],
                 &header.state_root[..],
-                scale_encoded_header
+                scale_encoded_header,
+                has_grandpa_forced_change(&header),
             ))
             .unwrap();
 
@@ -653,6 +1044,340 @@ impl SqliteFullDatabase {
         Ok(())
     }
 
+    /// Removes all the blocks and storage from the database, then inserts the given
+    /// [`chain_information::ChainInformationRef`] as the new finalized block.
+    ///
+    /// This is similar to [`DatabaseEmpty::initialize`], except that it is performed on a
+    /// database that already contains blocks. It is typically used after having warp synced to a
+    /// recent finalized block and downloaded its storage.
+    ///
+    /// The storage of the new finalized block consists in the trie nodes passed as
+    /// `finalized_block_storage_entries` and the trie nodes previously inserted with
+    /// [`SqliteFullDatabase::insert_trie_nodes`]. The trie nodes that were already in the
+    /// database and that aren't part of this storage are later removed by
+    /// [`SqliteFullDatabase::prune_state`].
+    ///
+    /// > **Note**: After this function returns, the database no longer contains the genesis
+    /// >           block, unless the new finalized block is the genesis block.
+    pub fn reset<'a>(
+        &self,
+        chain_information: impl Into<chain_information::ChainInformationRef<'a>>,
+        finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+        finalized_block_justification: Option<Vec<u8>>,
+        finalized_block_storage_entries: impl Iterator<Item = InsertTrieNode<'a>>,
+        finalized_block_state_version: u8,
+    ) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
+
+        // Start a transaction in order to atomically replace the content of the database.
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // Temporarily disable foreign key checks, as the order in which tables are cleared and
+        // trie nodes inserted doesn't respect the references between them.
+        // Note that this is immediately disabled again when we `COMMIT` later down below.
+        transaction
+            .execute("PRAGMA defer_foreign_keys = ON", ())
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // The trie nodes aren't removed, as they might be part of the storage of the new finalized
+        // block. Instead, the nodes that aren't referenced by any other node are marked as
+        // candidates for removal, which includes the roots of the storage of the removed blocks.
+        transaction
+            .execute_batch(
+                r#"
+INSERT OR IGNORE INTO trie_node_pruning_candidates(hash)
+    SELECT hash FROM trie_node
+    WHERE NOT EXISTS(SELECT 1 FROM trie_node_child WHERE child_hash = trie_node.hash)
+        AND NOT EXISTS(SELECT 1 FROM trie_node_storage WHERE trie_root_ref = trie_node.hash);
+DELETE FROM blocks_body;
+DELETE FROM blocks;
+DELETE FROM grandpa_triggered_authorities;
+DELETE FROM grandpa_scheduled_authorities;
+DELETE FROM aura_finalized_authorities;
+DELETE FROM meta;
+            "#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        open::insert_finalized_block(
+            &transaction,
+            self.block_number_bytes,
+            chain_information.into(),
+            finalized_block_body,
+            finalized_block_justification,
+            finalized_block_storage_entries,
+            finalized_block_state_version,
+        )?;
+
+        // If everything went well up to this point, commit the transaction.
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
+    /// Inserts trie nodes in the database, without associating them to any block.
+    ///
+    /// The children of the nodes and the child tries that they reference must be either already
+    /// in the database or part of `new_trie_nodes`. This is typically used in order to write the
+    /// storage of a block downloaded from the network in multiple steps, before calling
+    /// [`SqliteFullDatabase::reset`].
+    ///
+    /// The trie nodes that aren't referenced by any block when [`SqliteFullDatabase::reset`] is
+    /// called are later removed by [`SqliteFullDatabase::prune_state`].
+    pub fn insert_trie_nodes<'a>(
+        &self,
+        new_trie_nodes: impl Iterator<Item = InsertTrieNode<'a>>,
+        trie_entries_version: u8,
+    ) -> Result<(), CorruptedError> {
+        let mut database = self.database.lock();
+
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // Temporarily disable foreign key checks, as the nodes aren't necessarily ordered such
+        // that children are inserted before their parent.
+        // Note that this is immediately disabled again when we `COMMIT` later down below.
+        transaction
+            .execute("PRAGMA defer_foreign_keys = ON", ())
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        insert_storage(&transaction, None, new_trie_nodes, trie_entries_version)?;
+
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(())
+    }
+
+    /// Removes from the database the storage of the finalized blocks that is no longer needed
+    /// according to the [`StatePruning`] passed at initialization, then removes the trie nodes
+    /// that are no longer referenced by any block.
+    ///
+    /// In order to not block the database for too long, at most `max_trie_nodes` trie nodes are
+    /// examined per call. This function should be called repeatedly, for example after a block
+    /// has been finalized, until [`PruneStateOutcome::finished`] is `true`.
+    ///
+    /// Trie nodes that are no longer referenced because of [`SqliteFullDatabase::reset`] or
+    /// [`SqliteFullDatabase::purge_finality_orphans`] are also removed by this function, even
+    /// in [`StatePruning::Archive`] mode.
+    pub fn prune_state(&self, max_trie_nodes: usize) -> Result<PruneStateOutcome, CorruptedError> {
+        let mut database = self.database.lock();
+
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        // Remove the reference to the state of the blocks that are too old, and mark the root of
+        // their state as candidate for removal.
+        if let StatePruning::KeepFinalized(num_blocks) = self.state_pruning {
+            let threshold = finalized_num(&transaction)?
+                .saturating_add(1)
+                .saturating_sub(num_blocks.get());
+            let threshold = i64::try_from(threshold).map_err(|_| CorruptedError::InvalidNumber)?;
+
+            transaction
+                .prepare_cached(
+                    r#"
+                INSERT OR IGNORE INTO trie_node_pruning_candidates(hash)
+                SELECT state_trie_root_hash FROM blocks
+                WHERE number < :threshold AND state_trie_root_hash IS NOT NULL
+            "#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute(rusqlite::named_params! { ":threshold": threshold })
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            transaction
+                .prepare_cached(
+                    r#"
+                UPDATE blocks SET state_trie_root_hash = NULL
+                WHERE number < :threshold AND state_trie_root_hash IS NOT NULL
+            "#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute(rusqlite::named_params! { ":threshold": threshold })
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        }
+
+        // Garbage collection of the trie nodes.
+        // Each candidate is removed if nothing references it anymore, in which case its children
+        // become candidates as well. Because trie nodes are shared between blocks, it isn't
+        // possible to simply remove all the nodes found by walking down the trie of a block.
+        let mut outcome = PruneStateOutcome {
+            num_removed_trie_nodes: 0,
+            finished: false,
+        };
+
+        for _ in 0..max_trie_nodes {
+            let Some(candidate) = transaction
+                .prepare_cached("SELECT hash FROM trie_node_pruning_candidates LIMIT 1")
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .query_row((), |row| row.get::<_, Vec<u8>>(0))
+                .optional()
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            else {
+                outcome.finished = true;
+                break;
+            };
+
+            transaction
+                .prepare_cached("DELETE FROM trie_node_pruning_candidates WHERE hash = ?")
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute((&candidate,))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            let is_removable = transaction
+                .prepare_cached(
+                    r#"
+                SELECT
+                    EXISTS(SELECT 1 FROM trie_node WHERE hash = :hash)
+                    AND NOT EXISTS(SELECT 1 FROM blocks WHERE state_trie_root_hash = :hash)
+                    AND NOT EXISTS(SELECT 1 FROM trie_node_child WHERE child_hash = :hash)
+                    AND NOT EXISTS(SELECT 1 FROM trie_node_storage WHERE trie_root_ref = :hash)
+            "#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .query_row(rusqlite::named_params! { ":hash": &candidate }, |row| {
+                    row.get::<_, bool>(0)
+                })
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            if !is_removable {
+                continue;
+            }
+
+            // The children of the node and the child trie it references, if any, might no longer
+            // be referenced after the node has been removed.
+            transaction
+                .prepare_cached(
+                    r#"
+                INSERT OR IGNORE INTO trie_node_pruning_candidates(hash)
+                SELECT child_hash FROM trie_node_child WHERE hash = :hash
+                UNION ALL
+                SELECT trie_root_ref FROM trie_node_storage
+                    WHERE node_hash = :hash AND trie_root_ref IS NOT NULL
+            "#,
+                )
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute(rusqlite::named_params! { ":hash": &candidate })
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            // Entries in `trie_node_child` and `trie_node_storage` are removed through
+            // `ON DELETE CASCADE`.
+            transaction
+                .prepare_cached("DELETE FROM trie_node WHERE hash = ?")
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute((&candidate,))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            outcome.num_removed_trie_nodes += 1;
+        }
+
+        // If everything went well up to this point, commit the transaction.
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(outcome)
+    }
+
+    /// Removes from the database the bodies and justifications of the finalized blocks that are
+    /// no longer needed according to the [`BlocksPruning`] passed at initialization.
+    ///
+    /// In order to not block the database for too long, at most `max_blocks` blocks are pruned
+    /// per call. This function should be called repeatedly, for example after a block has been
+    /// finalized, until [`PruneBlocksOutcome::finished`] is `true`.
+    ///
+    /// Once the body of a block has been pruned, [`SqliteFullDatabase::block_extrinsics`]
+    /// returns [`BlockBodyAccessError::BodyPruned`] for this block. The header of the block is
+    /// kept.
+    pub fn prune_blocks(&self, max_blocks: usize) -> Result<PruneBlocksOutcome, CorruptedError> {
+        let BlocksPruning::KeepFinalized(num_blocks) = self.blocks_pruning else {
+            return Ok(PruneBlocksOutcome {
+                num_pruned_blocks: 0,
+                finished: true,
+            });
+        };
+
+        let mut database = self.database.lock();
+
+        let transaction = database
+            .transaction()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let threshold = finalized_num(&transaction)?
+            .saturating_add(1)
+            .saturating_sub(num_blocks.get());
+
+        let blocks = transaction
+            .prepare_cached(
+                r#"
+            SELECT hash, header, justification IS NOT NULL FROM blocks
+            WHERE body_pruned = FALSE AND number < ?
+            ORDER BY number ASC
+            LIMIT ?
+        "#,
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_map(
+                (
+                    i64::try_from(threshold).map_err(|_| CorruptedError::InvalidNumber)?,
+                    i64::try_from(max_blocks).unwrap_or(i64::MAX),
+                ),
+                |row| {
+                    Ok((
+                        row.get::<_, Vec<u8>>(0)?,
+                        row.get::<_, Vec<u8>>(1)?,
+                        row.get::<_, bool>(2)?,
+                    ))
+                },
+            )
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let outcome = PruneBlocksOutcome {
+            num_pruned_blocks: blocks.len(),
+            finished: blocks.len() < max_blocks,
+        };
+
+        for (hash, scale_encoded_header, has_justification) in blocks {
+            transaction
+                .prepare_cached("DELETE FROM blocks_body WHERE hash = ?")
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute((&hash,))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            transaction
+                .prepare_cached("UPDATE blocks SET body_pruned = TRUE WHERE hash = ?")
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                .execute((&hash,))
+                .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+            // Justifications of blocks that change the list of GrandPa authorities are kept in
+            // order to be able to build GrandPa warp sync proofs.
+            if has_justification
+                && !changes_grandpa_authorities(&scale_encoded_header, self.block_number_bytes)?
+            {
+                transaction
+                    .prepare_cached("UPDATE blocks SET justification = NULL WHERE hash = ?")
+                    .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+                    .execute((&hash,))
+                    .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            }
+        }
+
+        // If everything went well up to this point, commit the transaction.
+        transaction
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(outcome)
+    }
+
     /// Returns the value associated with a node of the trie of the given block.
     ///
     /// `parent_tries_paths_nibbles` is a list of keys to follow in order to find the root of the
@@ -1012,6 +1737,68 @@ impl SqliteFullDatabase {
 
         Ok(merkle_value)
     }
+
+    /// Returns the list of trie nodes that are traversed when searching for the given key in the
+    /// storage of the given block, starting from the root node of the trie.
+    ///
+    /// The last node of the list is either the node whose key is `key_nibbles`, the node with
+    /// the shortest key that starts with `key_nibbles`, or the node that proves that no such
+    /// node exists. In other words, this list contains all the nodes that are necessary in order
+    /// to build a Merkle proof of the storage value of `key_nibbles`, or of its closest
+    /// descendant Merkle value.
+    ///
+    /// `parent_tries_paths_nibbles` is a list of keys to follow in order to find the root of the
+    /// trie into which `key_nibbles` should be searched. Only the nodes of the trie into which
+    /// `key_nibbles` is searched are returned.
+    ///
+    /// Returns an empty list if `parent_tries_paths_nibbles` didn't lead to any trie, or if the
+    /// trie is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the values yielded by `parent_tries_paths_nibbles` or `key_nibbles` is
+    /// superior or equal to 16.
+    ///
+    pub fn block_storage_trie_nodes_path(
+        &self,
+        block_hash: &[u8; 32],
+        parent_tries_paths_nibbles: impl Iterator<Item = impl Iterator<Item = u8>>,
+        key_nibbles: impl Iterator<Item = u8>,
+    ) -> Result<Vec<StorageTrieNode>, StorageAccessError> {
+        let connection = self.database.lock();
+
+        let state_trie_root_hash = connection
+            .prepare_cached(r#"SELECT state_trie_root_hash FROM blocks WHERE hash = ?"#)
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+            .query_row((&block_hash[..],), |row| row.get::<_, Option<Vec<u8>>>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+        let mut trie_root = match state_trie_root_hash {
+            Some(Some(root)) => root,
+            Some(None) => return Err(StorageAccessError::StoragePruned),
+            None => return Err(StorageAccessError::UnknownBlock),
+        };
+
+        for parent_trie_path in parent_tries_paths_nibbles {
+            let parent_trie_path = parent_trie_path
+                .inspect(|n| assert!(*n < 16))
+                .collect::<Vec<_>>();
+            let path = trie_nodes_path(&connection, &trie_root, &parent_trie_path)?;
+            match path.last() {
+                Some(TrieNodesPathEntry {
+                    node,
+                    trie_root_ref: Some(trie_root_ref),
+                }) if node.key_nibbles == parent_trie_path => trie_root = trie_root_ref.clone(),
+                _ => return Ok(Vec::new()),
+            }
+        }
+
+        let key_nibbles = key_nibbles
+            .inspect(|n| assert!(*n < 16))
+            .collect::<Vec<_>>();
+        let path = trie_nodes_path(&connection, &trie_root, &key_nibbles)?;
+        Ok(path.into_iter().map(|entry| entry.node).collect())
+    }
 }
 
 impl fmt::Debug for SqliteFullDatabase {
@@ -1048,6 +1835,19 @@ pub enum InsertTrieNodeStorageValue<'a> {
     SameAsParent,
 }
 
+/// Trie node returned by [`SqliteFullDatabase::block_storage_trie_nodes_path`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageTrieNode {
+    /// Key of the node within its trie. Each byte is a nibble, in other words all bytes are
+    /// inferior to 16.
+    pub key_nibbles: Vec<u8>,
+    /// Node value of the node, as found in Merkle proofs.
+    pub node_value: Vec<u8>,
+    /// If the storage value of the node is hashed within [`StorageTrieNode::node_value`],
+    /// contains the unhashed storage value.
+    pub unhashed_storage_value: Option<Vec<u8>>,
+}
+
 /// Error while calling [`SqliteFullDatabase::insert`].
 #[derive(Debug, derive_more::Display, derive_more::From)]
 pub enum InsertError {
@@ -1076,6 +1876,65 @@ pub enum SetFinalizedError {
     RevertForbidden,
 }
 
+/// Outcome of [`SqliteFullDatabase::grandpa_warp_sync_fragments`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrandpaWarpSyncFragments {
+    /// List of SCALE-encoded headers and their SCALE-encoded GrandPa justification, ordered by
+    /// increasing block number.
+    pub fragments: Vec<(Vec<u8>, Vec<u8>)>,
+    /// `false` if the list of fragments has been truncated because of its size.
+    pub is_finished: bool,
+}
+
+/// Error while calling [`SqliteFullDatabase::set_block_justification`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum SetJustificationError {
+    /// Error accessing the database.
+    Corrupted(CorruptedError),
+    /// Block isn't in the database.
+    UnknownBlock,
+}
+
+/// Error while calling [`SqliteFullDatabase::insert_grandpa_voter_local_vote`].
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum InsertGrandpaVoteError {
+    /// Error accessing the database.
+    Corrupted(CorruptedError),
+    /// The local voter has already cast a vote of the same kind in the same round.
+    AlreadyVoted,
+}
+
+/// Outcome of [`SqliteFullDatabase::prune_blocks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PruneBlocksOutcome {
+    /// Number of blocks whose body has been removed from the database.
+    pub num_pruned_blocks: usize,
+    /// `true` if there isn't anything left to prune. `false` if
+    /// [`SqliteFullDatabase::prune_blocks`] should be called again.
+    pub finished: bool,
+}
+
+/// Outcome of [`SqliteFullDatabase::prune_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PruneStateOutcome {
+    /// Number of trie nodes that have been removed from the database.
+    pub num_removed_trie_nodes: usize,
+    /// `true` if there isn't anything left to prune. `false` if
+    /// [`SqliteFullDatabase::prune_state`] should be called again.
+    pub finished: bool,
+}
+
+/// Error while accessing the body of a block.
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum BlockBodyAccessError {
+    /// Error accessing the database.
+    Corrupted(CorruptedError),
+    /// Body of the block hash passed as parameter is no longer in the database.
+    BodyPruned,
+    /// Requested block couldn't be found in the database.
+    UnknownBlock,
+}
+
 /// Error while accessing the storage of the finalized block.
 #[derive(Debug, derive_more::Display, derive_more::From)]
 pub enum StorageAccessError {
@@ -1117,6 +1976,10 @@ pub enum CorruptedError {
     InvalidBabeEpochInformation,
     /// The version information about a storage entry has failed to decode.
     InvalidTrieEntryVersion,
+    /// A trie node is invalid or refers to a trie node that couldn't be found in the database.
+    BrokenTrie,
+    /// A vote of the local GrandPa voter stored in the database is invalid.
+    InvalidGrandpaVote,
     #[display(fmt = "Internal error: {_0}")]
     Internal(InternalError),
 }
@@ -1446,6 +2309,171 @@ fn insert_storage<'a>(
     Ok(())
 }
 
+/// Entry in the list returned by [`trie_nodes_path`].
+struct TrieNodesPathEntry {
+    /// Node that has been traversed.
+    node: StorageTrieNode,
+    /// Merkle value of the root of the trie the storage value of the node refers to, if any.
+    trie_root_ref: Option<Vec<u8>>,
+}
+
+/// Walks down the trie whose root node has the given Merkle value following the given key, and
+/// returns the list of nodes that have been traversed.
+///
+/// See [`SqliteFullDatabase::block_storage_trie_nodes_path`].
+fn trie_nodes_path(
+    database: &rusqlite::Connection,
+    trie_root_merkle_value: &[u8],
+    key_nibbles: &[u8],
+) -> Result<Vec<TrieNodesPathEntry>, CorruptedError> {
+    let mut partial_key_statement = database
+        .prepare_cached(r#"SELECT partial_key FROM trie_node WHERE hash = ?"#)
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    let mut storage_statement = database
+        .prepare_cached(r#"SELECT value, trie_root_ref, trie_entry_version FROM trie_node_storage WHERE node_hash = ?"#)
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+    let mut children_statement = database
+        .prepare_cached(r#"SELECT child_num, child_hash FROM trie_node_child WHERE hash = ?"#)
+        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+    let mut out = Vec::new();
+    let mut node_merkle_value = trie_root_merkle_value.to_vec();
+    let mut node_key = Vec::new();
+
+    loop {
+        let Some(partial_key) = partial_key_statement
+            .query_row((&node_merkle_value,), |row| row.get::<_, Vec<u8>>(0))
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        else {
+            // The Merkle value of the trie root might refer to an empty trie.
+            if out.is_empty() {
+                return Ok(out);
+            }
+            return Err(CorruptedError::BrokenTrie);
+        };
+
+        let storage = storage_statement
+            .query_row((&node_merkle_value,), |row| {
+                Ok((
+                    row.get::<_, Option<Vec<u8>>>(0)?,
+                    row.get::<_, Option<Vec<u8>>>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })
+            .optional()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        let mut children: [Option<Vec<u8>>; 16] = Default::default();
+        for child in children_statement
+            .query_map((&node_merkle_value,), |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?
+        {
+            let (child_num, child_merkle_value) =
+                child.map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+            let Some(slot) = child_num
+                .first()
+                .and_then(|n| children.get_mut(usize::from(*n)))
+            else {
+                return Err(CorruptedError::BrokenTrie);
+            };
+            *slot = Some(child_merkle_value);
+        }
+
+        node_key.extend_from_slice(&partial_key);
+
+        let (storage_value, trie_root_ref) = match storage {
+            Some((Some(value), _, version)) => (Some((value, version)), None),
+            Some((None, Some(trie_root_ref), version)) => {
+                (Some((trie_root_ref.clone(), version)), Some(trie_root_ref))
+            }
+            Some((None, None, _)) | None => (None, None),
+        };
+
+        // In version 1 of the trie, storage values strictly longer than 32 bytes are hashed.
+        let storage_value_hash = match &storage_value {
+            Some((value, 1)) if value.len() >= 33 => Some(
+                <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], value).as_bytes())
+                    .unwrap(),
+            ),
+            _ => None,
+        };
+
+        let node_value = crate::trie::trie_node::encode_to_vec(crate::trie::trie_node::Decoded {
+            children: children.clone(),
+            partial_key: partial_key
+                .iter()
+                .map(|n| crate::trie::Nibble::try_from(*n))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| CorruptedError::BrokenTrie)?
+                .into_iter(),
+            storage_value: match (&storage_value, &storage_value_hash) {
+                (_, Some(hash)) => crate::trie::trie_node::StorageValue::Hashed(hash),
+                (Some((value, _)), None) => crate::trie::trie_node::StorageValue::Unhashed(value),
+                (None, None) => crate::trie::trie_node::StorageValue::None,
+            },
+        })
+        .map_err(|_| CorruptedError::BrokenTrie)?;
+
+        out.push(TrieNodesPathEntry {
+            node: StorageTrieNode {
+                key_nibbles: node_key.clone(),
+                node_value,
+                unhashed_storage_value: storage_value_hash
+                    .and(storage_value)
+                    .map(|(value, _)| value),
+            },
+            trie_root_ref,
+        });
+
+        // Stop if the key has been reached or if the node diverges from the key.
+        if node_key.len() >= key_nibbles.len() || !key_nibbles.starts_with(&node_key) {
+            return Ok(out);
+        }
+
+        let child_num = key_nibbles[node_key.len()];
+        let Some(child_merkle_value) = children[usize::from(child_num)].take() else {
+            return Ok(out);
+        };
+
+        node_key.push(child_num);
+        node_merkle_value = child_merkle_value;
+    }
+}
+
+/// Returns `true` if the given header contains a change in the list of GrandPa authorities.
+fn changes_grandpa_authorities(
+    scale_encoded_header: &[u8],
+    block_number_bytes: usize,
+) -> Result<bool, CorruptedError> {
+    Ok(header::decode(scale_encoded_header, block_number_bytes)
+        .map_err(CorruptedError::BlockHeaderCorrupted)?
+        .digest
+        .logs()
+        .any(|item| {
+            matches!(
+                item,
+                header::DigestItemRef::GrandpaConsensus(
+                    header::GrandpaConsensusLogRef::ScheduledChange(_)
+                        | header::GrandpaConsensusLogRef::ForcedChange { .. }
+                )
+            )
+        }))
+}
+
+fn has_grandpa_forced_change(decoded_header: &header::HeaderRef) -> bool {
+    decoded_header.digest.logs().any(|item| {
+        matches!(
+            item,
+            header::DigestItemRef::GrandpaConsensus(
+                header::GrandpaConsensusLogRef::ForcedChange { .. }
+            )
+        )
+    })
+}
+
 fn purge_block(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), CorruptedError> {
     purge_block_storage(database, hash)?;
     database
@@ -1462,19 +2490,15 @@ fn purge_block(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), Corru
 }
 
 fn purge_block_storage(database: &rusqlite::Connection, hash: &[u8]) -> Result<(), CorruptedError> {
-    // TODO: untested
-
-    let state_trie_root_hash = database
-        .prepare_cached(r#"SELECT state_trie_root_hash FROM blocks WHERE hash = ?"#)
-        .map_err(|err| CorruptedError::Internal(InternalError(err)))?
-        .query_row((hash,), |row| row.get::<_, Vec<u8>>(0))
-        .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-
+    // The trie nodes of the block aren't removed immediately, as they might be shared with other
+    // blocks. Instead, the root node is marked as candidate for removal, and is later removed
+    // by `SqliteFullDatabase::prune_state` if nothing references it anymore.
     database
         .prepare_cached(
             r#"
-            UPDATE blocks SET state_trie_root_hash = NULL
-            WHERE hash = :block_hash
+            INSERT OR IGNORE INTO trie_node_pruning_candidates(hash)
+            SELECT state_trie_root_hash FROM blocks
+            WHERE hash = :block_hash AND state_trie_root_hash IS NOT NULL
         "#,
         )
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?
@@ -1483,34 +2507,19 @@ fn purge_block_storage(database: &rusqlite::Connection, hash: &[u8]) -> Result<(
         })
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
-    // TODO: doesn't delete everything in the situation where a single node with a merkle value is referenced multiple times from the same trie
-    // TODO: currently doesn't follow `trie_root_ref`
     database
-        .prepare_cached(r#"
-            WITH RECURSIVE
-                to_delete(node_hash) AS (
-                    SELECT trie_node.hash
-                        FROM trie_node
-                        LEFT JOIN blocks ON blocks.hash != :block_hash AND blocks.state_trie_root_hash = trie_node.hash
-                        LEFT JOIN trie_node_storage ON trie_node_storage.trie_root_ref = trie_node.hash
-                        WHERE trie_node.hash = :state_trie_root_hash AND blocks.hash IS NULL AND trie_node_storage.node_hash IS NULL
-                    UNION ALL
-                    SELECT trie_node_child.child_hash
-                        FROM to_delete
-                        JOIN trie_node_child ON trie_node_child.hash = to_delete.node_hash
-                        LEFT JOIN blocks ON blocks.state_trie_root_hash = trie_node_child.child_hash
-                        LEFT JOIN trie_node_storage ON trie_node_storage.trie_root_ref = to_delete.node_hash
-                        WHERE blocks.hash IS NULL AND trie_node_storage.node_hash IS NULL
-                )
-            DELETE FROM trie_node
-            WHERE hash IN (SELECT node_hash FROM to_delete)
-        "#)
+        .prepare_cached(
+            r#"
+            UPDATE blocks SET state_trie_root_hash = NULL
+            WHERE hash = :block_hash
+        "#,
+        )
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?
         .execute(rusqlite::named_params! {
-            ":state_trie_root_hash": &state_trie_root_hash,
             ":block_hash": hash,
         })
         .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
     Ok(())
 }
 
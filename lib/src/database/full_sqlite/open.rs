@@ -22,11 +22,12 @@
 // TODO:remove all the unwraps in this module that shouldn't be there
 
 use super::{
-    encode_babe_epoch_information, insert_storage, CorruptedError, InsertTrieNode, InternalError,
-    SqliteFullDatabase,
+    encode_babe_epoch_information, has_grandpa_forced_change, insert_storage, CorruptedError,
+    InsertTrieNode, InternalError, SqliteFullDatabase,
 };
-use crate::chain::chain_information;
+use crate::{chain::chain_information, header};
 
+use core::num::NonZeroU64;
 use std::path::Path;
 
 /// Opens the database using the given [`Config`].
@@ -197,7 +198,7 @@ List of all known blocks, indexed by their hash or number.
 */
 CREATE TABLE blocks(
     hash BLOB NOT NULL PRIMARY KEY,
-    parent_hash BLOB,  -- NULL only for the genesis block
+    parent_hash BLOB,  -- NULL only for the genesis block or for the block the database has been initialized with
     state_trie_root_hash BLOB,  -- NULL if and only if the trie is empty or if the trie storage has been pruned from the database
     number INTEGER NOT NULL,
     header BLOB NOT NULL,
@@ -266,6 +267,137 @@ PRAGMA user_version = 1;
             .map_err(InternalError)?
     }
 
+    if user_version <= 1 {
+        database
+            .execute_batch(
+                r#"
+/*
+List of trie nodes that might no longer be referenced by anything, and that should be checked and
+removed from the database if that is the case. Filled when the state of a block is pruned or when
+a block is removed, and emptied in an incremental way by the garbage collection of trie nodes.
+Entries in this table don't necessarily exist in `trie_node`.
+*/
+CREATE TABLE trie_node_pruning_candidates(
+    hash BLOB NOT NULL PRIMARY KEY
+);
+
+PRAGMA user_version = 2;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
+    if user_version <= 2 {
+        database
+            .execute_batch(
+                r#"
+/*
+`TRUE` if the body of the block has been removed from `blocks_body` because of the blocks pruning.
+Blocks whose body has been pruned have no entry in `blocks_body`, which is different from blocks
+whose body is empty.
+*/
+ALTER TABLE blocks ADD COLUMN body_pruned BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX blocks_by_body_pruned ON blocks(body_pruned, number);
+
+PRAGMA user_version = 3;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
+    if user_version <= 3 {
+        database
+            .execute_batch(
+                r#"
+/*
+Latest round of each GrandPa authorities set the local GrandPa voter has reached. Only the set
+that is currently being voted on is kept.
+Contrary to the other tables, this table isn't cleared when the database is reset, as voting
+again in a round that has already been voted in would be an equivocation.
+*/
+CREATE TABLE grandpa_voter_round(
+    set_id INTEGER NOT NULL PRIMARY KEY,
+    round_number INTEGER NOT NULL
+);
+
+/*
+Votes cast by the local GrandPa voter in the set found in `grandpa_voter_round`.
+`kind` is 0 for pre-votes, 1 for pre-commits, and 2 for primary proposals.
+Same as `grandpa_voter_round`, this table isn't cleared when the database is reset.
+*/
+CREATE TABLE grandpa_voter_local_votes(
+    set_id INTEGER NOT NULL,
+    round_number INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    target_hash BLOB NOT NULL,
+    target_number INTEGER NOT NULL,
+    authority_public_key BLOB NOT NULL,
+    signature BLOB NOT NULL,
+    PRIMARY KEY (set_id, round_number, kind),
+    CHECK(kind >= 0 AND kind <= 2),
+    CHECK(length(target_hash) == 32),
+    CHECK(length(authority_public_key) == 32),
+    CHECK(length(signature) == 64)
+);
+
+PRAGMA user_version = 4;
+
+        "#,
+            )
+            .map_err(InternalError)?
+    }
+
+    if user_version <= 4 {
+        database
+            .execute_batch(
+                r#"
+BEGIN TRANSACTION;
+ALTER TABLE blocks ADD COLUMN grandpa_forced_change BOOLEAN NOT NULL DEFAULT FALSE;
+                "#,
+            )
+            .map_err(InternalError)?;
+
+        // Blocks that are already in the database are flagged by decoding their header. Headers
+        // that fail to decode are left untouched, as the error will be reported when they are
+        // accessed.
+        let forced_changes = database
+            .prepare("SELECT hash, header FROM blocks")
+            .map_err(InternalError)?
+            .query_map((), |row| {
+                Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(InternalError)?
+            .filter_map(|row| match row {
+                Ok((hash, scale_encoded_header)) => {
+                    match header::decode(&scale_encoded_header, config.block_number_bytes) {
+                        Ok(decoded) if has_grandpa_forced_change(&decoded) => Some(Ok(hash)),
+                        _ => None,
+                    }
+                }
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(InternalError)?;
+        for hash in forced_changes {
+            database
+                .prepare_cached("UPDATE blocks SET grandpa_forced_change = TRUE WHERE hash = ?")
+                .map_err(InternalError)?
+                .execute((&hash,))
+                .map_err(InternalError)?;
+        }
+
+        database
+            .execute_batch(
+                r#"
+PRAGMA user_version = 5;
+COMMIT;
+                "#,
+            )
+            .map_err(InternalError)?
+    }
+
     let is_empty = database
         .prepare_cached("SELECT COUNT(*) FROM meta WHERE key = ?")
         .map_err(InternalError)?
@@ -277,11 +409,15 @@ PRAGMA user_version = 1;
         DatabaseOpen::Open(SqliteFullDatabase {
             database: parking_lot::Mutex::new(database),
             block_number_bytes: config.block_number_bytes, // TODO: consider storing this value in the DB and check it when opening
+            state_pruning: config.state_pruning,
+            blocks_pruning: config.blocks_pruning,
         })
     } else {
         DatabaseOpen::Empty(DatabaseEmpty {
             database,
             block_number_bytes: config.block_number_bytes,
+            state_pruning: config.state_pruning,
+            blocks_pruning: config.blocks_pruning,
         })
     })
 }
@@ -297,6 +433,37 @@ pub struct Config<'a> {
 
     /// Maximum allowed size, in bytes, of the SQLite cache.
     pub cache_size: usize,
+
+    /// Which block storages to keep in the database. See
+    /// [`SqliteFullDatabase::prune_state`].
+    pub state_pruning: StatePruning,
+
+    /// Which block bodies and justifications to keep in the database. See
+    /// [`SqliteFullDatabase::prune_blocks`].
+    pub blocks_pruning: BlocksPruning,
+}
+
+/// Policy regarding the blocks whose storage is kept in the database.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StatePruning {
+    /// The storage of all the blocks is kept forever.
+    Archive,
+    /// Only the storage of the non-finalized blocks and of the given number of most recent
+    /// finalized blocks, including the finalized block itself, is kept.
+    KeepFinalized(NonZeroU64),
+}
+
+/// Policy regarding the blocks whose body and justification are kept in the database.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlocksPruning {
+    /// The body and justification of all the blocks are kept forever.
+    Archive,
+    /// Only the body and justification of the non-finalized blocks and of the given number of
+    /// most recent finalized blocks, including the finalized block itself, are kept.
+    ///
+    /// The justifications of the blocks that change the list of GrandPa authorities are always
+    /// kept, as they are necessary in order to build GrandPa warp sync proofs.
+    KeepFinalized(NonZeroU64),
 }
 
 /// Type of database.
@@ -334,6 +501,12 @@ pub struct DatabaseEmpty {
 
     /// See the similar field in [`SqliteFullDatabase`].
     block_number_bytes: usize,
+
+    /// See the similar field in [`SqliteFullDatabase`].
+    state_pruning: StatePruning,
+
+    /// See the similar field in [`SqliteFullDatabase`].
+    blocks_pruning: BlocksPruning,
 }
 
 impl DatabaseEmpty {
@@ -363,83 +536,133 @@ impl DatabaseEmpty {
             .execute("PRAGMA defer_foreign_keys = ON", ())
             .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
 
-        let chain_information = chain_information.into();
-
-        let finalized_block_hash = chain_information
-            .finalized_block_header
-            .hash(self.block_number_bytes);
-
-        let scale_encoded_finalized_block_header = chain_information
-            .finalized_block_header
-            .scale_encoding(self.block_number_bytes)
-            .fold(Vec::new(), |mut a, b| {
-                a.extend_from_slice(b.as_ref());
-                a
-            });
-
-        insert_storage(
+        insert_finalized_block(
             &transaction,
-            None,
+            self.block_number_bytes,
+            chain_information.into(),
+            finalized_block_body,
+            finalized_block_justification,
             finalized_block_storage_entries,
             finalized_block_state_version,
         )?;
 
         transaction
-            .prepare_cached(
-                "INSERT INTO blocks(hash, parent_hash, state_trie_root_hash, number, header, is_best_chain, justification) VALUES(?, ?, ?, ?, ?, TRUE, ?)",
-            )
-            .unwrap()
-            .execute((
-                &finalized_block_hash[..],
-                if chain_information.finalized_block_header.number != 0 {
-                    Some(&chain_information.finalized_block_header.parent_hash[..])
-                } else { None },
-                &chain_information.finalized_block_header.state_root[..],
-                i64::try_from(chain_information.finalized_block_header.number).unwrap(),
-                &scale_encoded_finalized_block_header[..],
-                finalized_block_justification.as_deref(),
-            ))
+            .commit()
+            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
+
+        Ok(SqliteFullDatabase {
+            database: parking_lot::Mutex::new(self.database),
+            block_number_bytes: self.block_number_bytes,
+            state_pruning: self.state_pruning,
+            blocks_pruning: self.blocks_pruning,
+        })
+    }
+}
+
+/// Inserts the given finalized block, its storage, and the chain information in the database.
+///
+/// The database is assumed to be empty. Foreign key checks must have been deferred by the caller.
+pub(super) fn insert_finalized_block<'a>(
+    transaction: &rusqlite::Transaction,
+    block_number_bytes: usize,
+    chain_information: chain_information::ChainInformationRef<'a>,
+    finalized_block_body: impl ExactSizeIterator<Item = &'a [u8]>,
+    finalized_block_justification: Option<Vec<u8>>,
+    finalized_block_storage_entries: impl Iterator<Item = InsertTrieNode<'a>>,
+    finalized_block_state_version: u8,
+) -> Result<(), CorruptedError> {
+    let finalized_block_hash = chain_information
+        .finalized_block_header
+        .hash(block_number_bytes);
+
+    let scale_encoded_finalized_block_header = chain_information
+        .finalized_block_header
+        .scale_encoding(block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+    insert_storage(
+        transaction,
+        None,
+        finalized_block_storage_entries,
+        finalized_block_state_version,
+    )?;
+
+    transaction
+        .prepare_cached(
+            "INSERT INTO blocks(hash, parent_hash, state_trie_root_hash, number, header, is_best_chain, justification, grandpa_forced_change) VALUES(?, ?, ?, ?, ?, TRUE, ?, ?)",
+        )
+        .unwrap()
+        .execute((
+            &finalized_block_hash[..],
+            // The parent of the finalized block is never in the database, and inserting its
+            // hash would violate the foreign key constraint.
+            None::<&[u8]>,
+            &chain_information.finalized_block_header.state_root[..],
+            i64::try_from(chain_information.finalized_block_header.number).unwrap(),
+            &scale_encoded_finalized_block_header[..],
+            finalized_block_justification.as_deref(),
+            has_grandpa_forced_change(&chain_information.finalized_block_header),
+        ))
+        .unwrap();
+
+    {
+        let mut statement = transaction
+            .prepare_cached("INSERT INTO blocks_body(hash, idx, extrinsic) VALUES(?, ?, ?)")
             .unwrap();
+        for (index, item) in finalized_block_body.enumerate() {
+            statement
+                .execute((
+                    &finalized_block_hash[..],
+                    i64::try_from(index).unwrap(),
+                    item,
+                ))
+                .unwrap();
+        }
+    }
+
+    super::meta_set_blob(transaction, "best", &finalized_block_hash[..]).unwrap();
+    super::meta_set_number(
+        transaction,
+        "finalized",
+        chain_information.finalized_block_header.number,
+    )?;
+
+    match &chain_information.finality {
+        chain_information::ChainInformationFinalityRef::Outsourced => {}
+        chain_information::ChainInformationFinalityRef::Grandpa {
+            finalized_triggered_authorities,
+            after_finalized_block_authorities_set_id,
+            finalized_scheduled_change,
+        } => {
+            super::meta_set_number(
+                transaction,
+                "grandpa_authorities_set_id",
+                *after_finalized_block_authorities_set_id,
+            )?;
 
-        {
             let mut statement = transaction
-                .prepare_cached("INSERT INTO blocks_body(hash, idx, extrinsic) VALUES(?, ?, ?)")
+                .prepare_cached("INSERT INTO grandpa_triggered_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
                 .unwrap();
-            for (index, item) in finalized_block_body.enumerate() {
+            for (index, item) in finalized_triggered_authorities.iter().enumerate() {
                 statement
                     .execute((
-                        &finalized_block_hash[..],
                         i64::try_from(index).unwrap(),
-                        item,
+                        &item.public_key[..],
+                        i64::from_ne_bytes(item.weight.get().to_ne_bytes()),
                     ))
                     .unwrap();
             }
-        }
-
-        super::meta_set_blob(&transaction, "best", &finalized_block_hash[..]).unwrap();
-        super::meta_set_number(
-            &transaction,
-            "finalized",
-            chain_information.finalized_block_header.number,
-        )?;
 
-        match &chain_information.finality {
-            chain_information::ChainInformationFinalityRef::Outsourced => {}
-            chain_information::ChainInformationFinalityRef::Grandpa {
-                finalized_triggered_authorities,
-                after_finalized_block_authorities_set_id,
-                finalized_scheduled_change,
-            } => {
-                super::meta_set_number(
-                    &transaction,
-                    "grandpa_authorities_set_id",
-                    *after_finalized_block_authorities_set_id,
-                )?;
+            if let Some((height, list)) = finalized_scheduled_change {
+                super::meta_set_number(transaction, "grandpa_scheduled_target", *height)?;
 
                 let mut statement = transaction
-                    .prepare_cached("INSERT INTO grandpa_triggered_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
+                    .prepare_cached("INSERT INTO grandpa_scheduled_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
                     .unwrap();
-                for (index, item) in finalized_triggered_authorities.iter().enumerate() {
+                for (index, item) in list.iter().enumerate() {
                     statement
                         .execute((
                             i64::try_from(index).unwrap(),
@@ -448,75 +671,53 @@ impl DatabaseEmpty {
                         ))
                         .unwrap();
                 }
-
-                if let Some((height, list)) = finalized_scheduled_change {
-                    super::meta_set_number(&transaction, "grandpa_scheduled_target", *height)?;
-
-                    let mut statement = transaction
-                        .prepare_cached("INSERT INTO grandpa_scheduled_authorities(idx, public_key, weight) VALUES(?, ?, ?)")
-                        .unwrap();
-                    for (index, item) in list.iter().enumerate() {
-                        statement
-                            .execute((
-                                i64::try_from(index).unwrap(),
-                                &item.public_key[..],
-                                i64::from_ne_bytes(item.weight.get().to_ne_bytes()),
-                            ))
-                            .unwrap();
-                    }
-                }
             }
         }
+    }
 
-        match &chain_information.consensus {
-            chain_information::ChainInformationConsensusRef::Unknown => {}
-            chain_information::ChainInformationConsensusRef::Aura {
-                finalized_authorities_list,
-                slot_duration,
-            } => {
-                super::meta_set_number(&transaction, "aura_slot_duration", slot_duration.get())
-                    .unwrap();
+    match &chain_information.consensus {
+        chain_information::ChainInformationConsensusRef::Unknown => {}
+        chain_information::ChainInformationConsensusRef::Aura {
+            finalized_authorities_list,
+            slot_duration,
+        } => {
+            super::meta_set_number(transaction, "aura_slot_duration", slot_duration.get()).unwrap();
 
-                let mut statement = transaction
-                    .prepare_cached(
-                        "INSERT INTO aura_finalized_authorities(idx, public_key) VALUES(?, ?)",
-                    )
+            let mut statement = transaction
+                .prepare_cached(
+                    "INSERT INTO aura_finalized_authorities(idx, public_key) VALUES(?, ?)",
+                )
+                .unwrap();
+            for (index, item) in finalized_authorities_list.clone().enumerate() {
+                statement
+                    .execute((i64::try_from(index).unwrap(), &item.public_key[..]))
                     .unwrap();
-                for (index, item) in finalized_authorities_list.clone().enumerate() {
-                    statement
-                        .execute((i64::try_from(index).unwrap(), &item.public_key[..]))
-                        .unwrap();
-                }
             }
-            chain_information::ChainInformationConsensusRef::Babe {
-                slots_per_epoch,
-                finalized_next_epoch_transition,
-                finalized_block_epoch_information,
-            } => {
-                super::meta_set_number(&transaction, "babe_slots_per_epoch", slots_per_epoch.get())
-                    .unwrap();
+        }
+        chain_information::ChainInformationConsensusRef::Babe {
+            slots_per_epoch,
+            finalized_next_epoch_transition,
+            finalized_block_epoch_information,
+        } => {
+            super::meta_set_number(transaction, "babe_slots_per_epoch", slots_per_epoch.get())
+                .unwrap();
+            super::meta_set_blob(
+                transaction,
+                "babe_finalized_next_epoch",
+                &encode_babe_epoch_information(finalized_next_epoch_transition.clone())[..],
+            )
+            .unwrap();
+
+            if let Some(finalized_block_epoch_information) = finalized_block_epoch_information {
                 super::meta_set_blob(
-                    &transaction,
-                    "babe_finalized_next_epoch",
-                    &encode_babe_epoch_information(finalized_next_epoch_transition.clone())[..],
+                    transaction,
+                    "babe_finalized_epoch",
+                    &encode_babe_epoch_information(finalized_block_epoch_information.clone())[..],
                 )
                 .unwrap();
-
-                if let Some(finalized_block_epoch_information) = finalized_block_epoch_information {
-                    super::meta_set_blob(&transaction, "babe_finalized_epoch", &encode_babe_epoch_information(
-            finalized_block_epoch_information.clone(),
-        )[..]).unwrap();
-                }
             }
         }
-
-        transaction
-            .commit()
-            .map_err(|err| CorruptedError::Internal(InternalError(err)))?;
-
-        Ok(SqliteFullDatabase {
-            database: parking_lot::Mutex::new(self.database),
-            block_number_bytes: self.block_number_bytes,
-        })
     }
+
+    Ok(())
 }
@@ -17,11 +17,15 @@
 
 #![cfg(test)]
 
-use super::{open, Config, ConfigTy, DatabaseOpen, InsertTrieNode, InsertTrieNodeStorageValue};
-use crate::{chain::chain_information, header, trie};
+use super::{
+    open, BlockBodyAccessError, BlocksPruning, Config, ConfigTy, DatabaseOpen,
+    GrandpaWarpSyncFragments, InsertGrandpaVoteError, InsertTrieNode, InsertTrieNodeStorageValue,
+    PruneBlocksOutcome, PruneStateOutcome, StatePruning, StorageAccessError,
+};
+use crate::{chain::chain_information, finality::grandpa, header, trie};
 
 use alloc::borrow::Cow;
-use core::{array, iter};
+use core::{array, iter, num::NonZeroU64};
 use rand::distributions::{Distribution as _, Uniform};
 
 #[test]
@@ -32,6 +36,8 @@ fn empty_database_fill_then_query() {
             block_number_bytes: 4,
             cache_size: 2 * 1024 * 1024,
             ty: ConfigTy::Memory,
+            state_pruning: StatePruning::Archive,
+            blocks_pruning: BlocksPruning::Archive,
         })
         .unwrap() else {
             panic!()
@@ -205,6 +211,54 @@ fn empty_database_fill_then_query() {
             );
         }
 
+        // Build Merkle proofs of random keys and verify them.
+        for _ in 0..32 {
+            let key = (0..uniform_sample(0, 4))
+                .map(|_| uniform_sample(0, 255))
+                .collect::<Vec<_>>();
+            let nodes = open_db
+                .block_storage_trie_nodes_path(
+                    &block0_hash,
+                    iter::empty::<iter::Empty<_>>(),
+                    trie::bytes_to_nibbles(key.iter().copied()).map(u8::from),
+                )
+                .unwrap();
+
+            let mut proof_builder = trie::proof_encode::ProofBuilder::new();
+            for node in &nodes {
+                proof_builder.set_node_value(
+                    &node
+                        .key_nibbles
+                        .iter()
+                        .map(|n| trie::Nibble::try_from(*n).unwrap())
+                        .collect::<Vec<_>>(),
+                    &node.node_value,
+                    node.unhashed_storage_value.as_deref(),
+                );
+            }
+            let state_root = proof_builder.trie_root_hash().unwrap();
+            let proof = trie::proof_decode::decode_and_verify_proof(trie::proof_decode::Config {
+                proof: proof_builder.build_to_vec(),
+            })
+            .unwrap();
+
+            let expected = trie
+                .node_by_full_key(trie::bytes_to_nibbles(key.iter().copied()))
+                .and_then(|n| trie[n].0.clone());
+            assert_eq!(
+                proof
+                    .storage_value(&state_root, &key)
+                    .unwrap()
+                    .map(|(v, _)| v.to_vec()),
+                expected
+            );
+            assert_eq!(
+                Some(state_root),
+                trie.root_user_data()
+                    .map(|n| *<&[u8; 32]>::try_from(n.1.as_ref().unwrap().as_ref()).unwrap())
+            );
+        }
+
         // Ask random next keys.
         for _ in 0..1024 {
             let key = (0..uniform_sample(0, 8))
@@ -272,3 +326,774 @@ fn empty_database_fill_then_query() {
         }
     }
 }
+
+#[test]
+fn justifications_and_grandpa_warp_sync_fragments() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::Archive,
+        blocks_pruning: BlocksPruning::Archive,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    // The genesis storage consists in a single entry at the root of the trie.
+    let state_root = trie::trie_node::calculate_merkle_value(
+        trie::trie_node::Decoded {
+            children: [None::<&[u8]>; 16],
+            partial_key: iter::empty(),
+            storage_value: trie::trie_node::StorageValue::Unhashed(b"foo"),
+        },
+        trie::HashFunction::Blake2,
+        true,
+    )
+    .unwrap();
+    let state_root = <[u8; 32]>::try_from(state_root.as_ref()).unwrap();
+
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(InsertTrieNode {
+                storage_value: InsertTrieNodeStorageValue::Value {
+                    value: Cow::Borrowed(b"foo"),
+                    references_merkle_value: false,
+                },
+                merkle_value: Cow::Borrowed(&state_root[..]),
+                children_merkle_values: array::from_fn(|_| None),
+                partial_key_nibbles: Cow::Borrowed(&[]),
+            }),
+            0,
+        )
+        .unwrap();
+
+    let block0_hash = open_db.finalized_block_hash().unwrap();
+
+    // Build a chain of 4 blocks, where blocks 1 and 3 change the list of GrandPa authorities.
+    let authorities_change = [header::DigestItem::GrandpaConsensus(
+        header::GrandpaConsensusLog::ScheduledChange(header::GrandpaScheduledChange {
+            next_authorities: Vec::new(),
+            delay: 0,
+        }),
+    )];
+    let mut headers = Vec::new();
+    let mut parent_hash = block0_hash;
+    for number in 1..=4 {
+        let digest = if number == 1 || number == 3 {
+            header::DigestRef::from_slice(&authorities_change).unwrap()
+        } else {
+            header::DigestRef::empty()
+        };
+        let scale_encoded_header = header::HeaderRef {
+            parent_hash: &parent_hash,
+            number,
+            state_root: &state_root,
+            extrinsics_root: &[0; 32],
+            digest,
+        }
+        .scale_encoding_vec(4);
+        parent_hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+        open_db
+            .insert(
+                &scale_encoded_header,
+                true,
+                iter::empty::<Vec<u8>>(),
+                iter::empty(),
+                0,
+            )
+            .unwrap();
+        headers.push((parent_hash, scale_encoded_header));
+    }
+
+    assert!(open_db
+        .block_justification(&headers[0].0)
+        .unwrap()
+        .is_none());
+    for (hash, _) in [&headers[0], &headers[2], &headers[3]] {
+        open_db.set_block_justification(hash, &hash[..]).unwrap();
+    }
+    assert_eq!(
+        open_db.block_justification(&headers[0].0).unwrap(),
+        Some(headers[0].0.to_vec())
+    );
+    assert!(open_db.set_block_justification(&[0xff; 32], &[]).is_err());
+
+    // Fragments can only start from a finalized block.
+    assert!(open_db
+        .grandpa_warp_sync_fragments(&headers[0].0, usize::MAX)
+        .unwrap()
+        .is_none());
+
+    open_db.set_finalized(&headers[3].0).unwrap();
+
+    let GrandpaWarpSyncFragments {
+        fragments,
+        is_finished,
+    } = open_db
+        .grandpa_warp_sync_fragments(&block0_hash, usize::MAX)
+        .unwrap()
+        .unwrap();
+    assert!(is_finished);
+    assert_eq!(
+        fragments,
+        [0, 2, 3]
+            .into_iter()
+            .map(|n| (headers[n].1.clone(), headers[n].0.to_vec()))
+            .collect::<Vec<_>>()
+    );
+
+    let GrandpaWarpSyncFragments {
+        fragments,
+        is_finished,
+    } = open_db
+        .grandpa_warp_sync_fragments(&block0_hash, 0)
+        .unwrap()
+        .unwrap();
+    assert!(!is_finished);
+    assert_eq!(fragments, [(headers[0].1.clone(), headers[0].0.to_vec())]);
+
+    let GrandpaWarpSyncFragments {
+        fragments,
+        is_finished,
+    } = open_db
+        .grandpa_warp_sync_fragments(&headers[2].0, usize::MAX)
+        .unwrap()
+        .unwrap();
+    assert!(is_finished);
+    assert_eq!(fragments, [(headers[3].1.clone(), headers[3].0.to_vec())]);
+}
+
+#[test]
+fn grandpa_warp_sync_fragments_stop_at_forced_change() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::Archive,
+        blocks_pruning: BlocksPruning::Archive,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let (state_root, genesis_node) = single_entry_storage(b"foo");
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(genesis_node),
+            0,
+        )
+        .unwrap();
+
+    let block0_hash = open_db.finalized_block_hash().unwrap();
+
+    // Build a chain of 5 blocks, where blocks 1 and 4 schedule a change of the list of GrandPa
+    // authorities and block 3 forces a change. Block 3 has no justification.
+    let scheduled_change = [header::DigestItem::GrandpaConsensus(
+        header::GrandpaConsensusLog::ScheduledChange(header::GrandpaScheduledChange {
+            next_authorities: Vec::new(),
+            delay: 0,
+        }),
+    )];
+    let forced_change = [header::DigestItem::GrandpaConsensus(
+        header::GrandpaConsensusLog::ForcedChange {
+            reset_block_height: 2,
+            change: header::GrandpaScheduledChange {
+                next_authorities: Vec::new(),
+                delay: 0,
+            },
+        },
+    )];
+    let mut headers = Vec::new();
+    let mut parent_hash = block0_hash;
+    for number in 1..=5 {
+        let digest = match number {
+            1 | 4 => header::DigestRef::from_slice(&scheduled_change).unwrap(),
+            3 => header::DigestRef::from_slice(&forced_change).unwrap(),
+            _ => header::DigestRef::empty(),
+        };
+        let scale_encoded_header = header::HeaderRef {
+            parent_hash: &parent_hash,
+            number,
+            state_root: &state_root,
+            extrinsics_root: &[0; 32],
+            digest,
+        }
+        .scale_encoding_vec(4);
+        parent_hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+        open_db
+            .insert(
+                &scale_encoded_header,
+                true,
+                iter::empty::<Vec<u8>>(),
+                iter::empty(),
+                0,
+            )
+            .unwrap();
+        headers.push((parent_hash, scale_encoded_header));
+    }
+
+    for (hash, _) in [&headers[0], &headers[1], &headers[3], &headers[4]] {
+        open_db.set_block_justification(hash, &hash[..]).unwrap();
+    }
+    open_db.set_finalized(&headers[4].0).unwrap();
+
+    // The fragments end with the highest justified block before the forced change.
+    let GrandpaWarpSyncFragments {
+        fragments,
+        is_finished,
+    } = open_db
+        .grandpa_warp_sync_fragments(&block0_hash, usize::MAX)
+        .unwrap()
+        .unwrap();
+    assert!(is_finished);
+    assert_eq!(
+        fragments,
+        [0, 1]
+            .into_iter()
+            .map(|n| (headers[n].1.clone(), headers[n].0.to_vec()))
+            .collect::<Vec<_>>()
+    );
+
+    // Starting after the forced change works normally.
+    let GrandpaWarpSyncFragments {
+        fragments,
+        is_finished,
+    } = open_db
+        .grandpa_warp_sync_fragments(&headers[2].0, usize::MAX)
+        .unwrap()
+        .unwrap();
+    assert!(is_finished);
+    assert_eq!(
+        fragments,
+        [3, 4]
+            .into_iter()
+            .map(|n| (headers[n].1.clone(), headers[n].0.to_vec()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn reset_replaces_content() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::Archive,
+        blocks_pruning: BlocksPruning::Archive,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let (genesis_state_root, genesis_node) = single_entry_storage(b"foo");
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &genesis_state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(genesis_node),
+            0,
+        )
+        .unwrap();
+    let genesis_hash = open_db.finalized_block_hash().unwrap();
+
+    // Replace the content of the database with a block whose parent isn't in the database.
+    let (state_root, node) = single_entry_storage(b"bar");
+    let new_header = header::HeaderRef {
+        number: 1000,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0xaa; 32],
+        state_root: &state_root,
+        digest: header::DigestRef::empty(),
+    };
+    let new_hash = new_header.hash(4);
+    open_db
+        .reset(
+            chain_information::ChainInformationRef {
+                finalized_block_header: new_header,
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::once(&b"extrinsic"[..]),
+            Some(b"justification".to_vec()),
+            iter::once(node),
+            0,
+        )
+        .unwrap();
+
+    assert_eq!(open_db.finalized_block_hash().unwrap(), new_hash);
+    assert_eq!(open_db.best_block_hash().unwrap(), new_hash);
+    assert_eq!(open_db.block_hash_by_number(0).unwrap().len(), 0);
+    assert!(open_db
+        .block_scale_encoded_header(&genesis_hash)
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        open_db.block_justification(&new_hash).unwrap(),
+        Some(b"justification".to_vec())
+    );
+    assert_eq!(
+        open_db
+            .block_storage_get(&new_hash, iter::empty::<iter::Empty<u8>>(), iter::empty())
+            .unwrap(),
+        Some((b"bar".to_vec(), 0))
+    );
+}
+
+#[test]
+fn reset_with_previously_inserted_trie_nodes() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::Archive,
+        blocks_pruning: BlocksPruning::Archive,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let (genesis_state_root, genesis_node) = single_entry_storage(b"foo");
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &genesis_state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(genesis_node),
+            0,
+        )
+        .unwrap();
+
+    // Insert the storage of the new finalized block ahead of time, alongside with a node that
+    // ends up not being referenced by any block.
+    let (state_root, node) = single_entry_storage(b"bar");
+    let (_, unreferenced_node) = single_entry_storage(b"baz");
+    open_db
+        .insert_trie_nodes([node, unreferenced_node].into_iter(), 0)
+        .unwrap();
+
+    let new_header = header::HeaderRef {
+        number: 1000,
+        extrinsics_root: &[0; 32],
+        parent_hash: &[0xaa; 32],
+        state_root: &state_root,
+        digest: header::DigestRef::empty(),
+    };
+    let new_hash = new_header.hash(4);
+    open_db
+        .reset(
+            chain_information::ChainInformationRef {
+                finalized_block_header: new_header,
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::empty(),
+            0,
+        )
+        .unwrap();
+
+    // The storage of the genesis block and the unreferenced node are removed.
+    assert_eq!(
+        open_db.prune_state(usize::MAX).unwrap(),
+        PruneStateOutcome {
+            num_removed_trie_nodes: 2,
+            finished: true
+        }
+    );
+    assert_eq!(
+        open_db
+            .block_storage_get(&new_hash, iter::empty::<iter::Empty<u8>>(), iter::empty())
+            .unwrap(),
+        Some((b"bar".to_vec(), 0))
+    );
+}
+
+#[test]
+fn grandpa_voter_state() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::Archive,
+        blocks_pruning: BlocksPruning::Archive,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let (genesis_state_root, genesis_node) = single_entry_storage(b"foo");
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &genesis_state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(genesis_node),
+            0,
+        )
+        .unwrap();
+
+    assert!(open_db.grandpa_voter_state(0).unwrap().is_none());
+
+    let vote = grandpa::voter::LocalVote {
+        round_number: 3,
+        kind: grandpa::voter::VoteKind::Prevote,
+        target_hash: [1; 32],
+        target_number: 1,
+        authority_public_key: [2; 32],
+        signature: [3; 64],
+    };
+    open_db.set_grandpa_voter_round(0, 2).unwrap();
+    open_db.insert_grandpa_voter_local_vote(0, &vote).unwrap();
+    assert_eq!(
+        open_db.grandpa_voter_state(0).unwrap(),
+        Some((3, vec![vote.clone()]))
+    );
+
+    // Voting twice in the same round is refused.
+    assert!(matches!(
+        open_db.insert_grandpa_voter_local_vote(
+            0,
+            &grandpa::voter::LocalVote {
+                target_hash: [4; 32],
+                ..vote.clone()
+            }
+        ),
+        Err(InsertGrandpaVoteError::AlreadyVoted)
+    ));
+
+    // The state survives a reset of the database.
+    let (state_root, node) = single_entry_storage(b"bar");
+    open_db
+        .reset(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 1000,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0xaa; 32],
+                    state_root: &state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(node),
+            0,
+        )
+        .unwrap();
+    assert_eq!(
+        open_db.grandpa_voter_state(0).unwrap(),
+        Some((3, vec![vote]))
+    );
+
+    // Moving to a new set removes the state of the previous one.
+    open_db.set_grandpa_voter_round(1, 1).unwrap();
+    assert!(open_db.grandpa_voter_state(0).unwrap().is_none());
+    assert_eq!(
+        open_db.grandpa_voter_state(1).unwrap(),
+        Some((1, Vec::new()))
+    );
+}
+
+#[test]
+fn prune_state_keeps_recent_finalized() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::KeepFinalized(NonZeroU64::new(2).unwrap()),
+        blocks_pruning: BlocksPruning::Archive,
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let (genesis_state_root, genesis_node) = single_entry_storage(b"foo");
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &genesis_state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(genesis_node),
+            0,
+        )
+        .unwrap();
+
+    // Build a chain of 3 blocks, each with a different storage. Block 2 has the same storage as
+    // the genesis block, meaning that their trie nodes are shared.
+    let mut hashes = vec![open_db.finalized_block_hash().unwrap()];
+    for (number, value) in [(1, &b"bar"[..]), (2, &b"foo"[..]), (3, &b"baz"[..])] {
+        let (state_root, node) = single_entry_storage(value);
+        let scale_encoded_header = header::HeaderRef {
+            parent_hash: hashes.last().unwrap(),
+            number,
+            state_root: &state_root,
+            extrinsics_root: &[0; 32],
+            digest: header::DigestRef::empty(),
+        }
+        .scale_encoding_vec(4);
+        open_db
+            .insert(
+                &scale_encoded_header,
+                true,
+                iter::empty::<Vec<u8>>(),
+                iter::once(node),
+                0,
+            )
+            .unwrap();
+        hashes.push(header::hash_from_scale_encoded_header(
+            &scale_encoded_header,
+        ));
+    }
+
+    // Nothing is pruned as long as the finalized block is the genesis block.
+    assert_eq!(
+        open_db.prune_state(usize::MAX).unwrap(),
+        PruneStateOutcome {
+            num_removed_trie_nodes: 0,
+            finished: true
+        }
+    );
+
+    // Only the storage of blocks 2 and 3 is kept. The storage of the genesis block is shared
+    // with block 2 and must not be removed from the database.
+    open_db.set_finalized(&hashes[3]).unwrap();
+    assert_eq!(
+        open_db.prune_state(0).unwrap(),
+        PruneStateOutcome {
+            num_removed_trie_nodes: 0,
+            finished: false
+        }
+    );
+    assert_eq!(
+        open_db.prune_state(usize::MAX).unwrap(),
+        PruneStateOutcome {
+            num_removed_trie_nodes: 1,
+            finished: true
+        }
+    );
+
+    for hash in &hashes[..2] {
+        assert!(matches!(
+            open_db.block_storage_get(hash, iter::empty::<iter::Empty<u8>>(), iter::empty()),
+            Err(StorageAccessError::StoragePruned)
+        ));
+    }
+    for (hash, value) in [(&hashes[2], &b"foo"[..]), (&hashes[3], &b"baz"[..])] {
+        assert_eq!(
+            open_db
+                .block_storage_get(hash, iter::empty::<iter::Empty<u8>>(), iter::empty())
+                .unwrap(),
+            Some((value.to_vec(), 0))
+        );
+    }
+}
+
+#[test]
+fn prune_blocks_keeps_recent_finalized() {
+    let DatabaseOpen::Empty(empty_db) = open(Config {
+        block_number_bytes: 4,
+        cache_size: 2 * 1024 * 1024,
+        ty: ConfigTy::Memory,
+        state_pruning: StatePruning::Archive,
+        blocks_pruning: BlocksPruning::KeepFinalized(NonZeroU64::new(2).unwrap()),
+    })
+    .unwrap() else {
+        panic!()
+    };
+
+    let (state_root, genesis_node) = single_entry_storage(b"foo");
+    let open_db = empty_db
+        .initialize(
+            chain_information::ChainInformationRef {
+                finalized_block_header: header::HeaderRef {
+                    number: 0,
+                    extrinsics_root: &[0; 32],
+                    parent_hash: &[0; 32],
+                    state_root: &state_root,
+                    digest: header::DigestRef::empty(),
+                },
+                consensus: chain_information::ChainInformationConsensusRef::Unknown,
+                finality: chain_information::ChainInformationFinalityRef::Outsourced,
+            },
+            iter::empty(),
+            None,
+            iter::once(genesis_node),
+            0,
+        )
+        .unwrap();
+
+    // Build a chain of 4 blocks with a justification, where block 1 changes the list of GrandPa
+    // authorities.
+    let authorities_change = [header::DigestItem::GrandpaConsensus(
+        header::GrandpaConsensusLog::ScheduledChange(header::GrandpaScheduledChange {
+            next_authorities: Vec::new(),
+            delay: 0,
+        }),
+    )];
+    let mut hashes = vec![open_db.finalized_block_hash().unwrap()];
+    for number in 1..=4 {
+        let scale_encoded_header = header::HeaderRef {
+            parent_hash: hashes.last().unwrap(),
+            number,
+            state_root: &state_root,
+            extrinsics_root: &[0; 32],
+            digest: if number == 1 {
+                header::DigestRef::from_slice(&authorities_change).unwrap()
+            } else {
+                header::DigestRef::empty()
+            },
+        }
+        .scale_encoding_vec(4);
+        open_db
+            .insert(
+                &scale_encoded_header,
+                true,
+                iter::once(vec![u8::try_from(number).unwrap()]),
+                iter::empty(),
+                0,
+            )
+            .unwrap();
+        let hash = header::hash_from_scale_encoded_header(&scale_encoded_header);
+        open_db.set_block_justification(&hash, &hash[..]).unwrap();
+        hashes.push(hash);
+    }
+
+    // Only the bodies of blocks 3 and 4 are kept.
+    open_db.set_finalized(&hashes[4]).unwrap();
+    assert_eq!(
+        open_db.prune_blocks(1).unwrap(),
+        PruneBlocksOutcome {
+            num_pruned_blocks: 1,
+            finished: false
+        }
+    );
+    assert_eq!(
+        open_db.prune_blocks(usize::MAX).unwrap(),
+        PruneBlocksOutcome {
+            num_pruned_blocks: 2,
+            finished: true
+        }
+    );
+
+    for hash in &hashes[..3] {
+        assert!(matches!(
+            open_db.block_extrinsics(hash),
+            Err(BlockBodyAccessError::BodyPruned)
+        ));
+    }
+    for (number, hash) in hashes.iter().enumerate().skip(3) {
+        assert_eq!(
+            open_db.block_extrinsics(hash).unwrap().collect::<Vec<_>>(),
+            vec![vec![u8::try_from(number).unwrap()]]
+        );
+    }
+    assert!(matches!(
+        open_db.block_extrinsics(&[0xff; 32]),
+        Err(BlockBodyAccessError::UnknownBlock)
+    ));
+
+    // The justification of the block that changes the list of authorities is kept.
+    assert!(open_db.block_justification(&hashes[1]).unwrap().is_some());
+    assert!(open_db.block_justification(&hashes[2]).unwrap().is_none());
+    assert!(open_db.block_justification(&hashes[3]).unwrap().is_some());
+    assert!(open_db
+        .block_scale_encoded_header(&hashes[2])
+        .unwrap()
+        .is_some());
+}
+
+/// Builds a storage consisting in a single entry at the root of the trie.
+fn single_entry_storage(value: &[u8]) -> ([u8; 32], InsertTrieNode) {
+    let state_root = trie::trie_node::calculate_merkle_value(
+        trie::trie_node::Decoded {
+            children: [None::<&[u8]>; 16],
+            partial_key: iter::empty(),
+            storage_value: trie::trie_node::StorageValue::Unhashed(value),
+        },
+        trie::HashFunction::Blake2,
+        true,
+    )
+    .unwrap();
+    let state_root = <[u8; 32]>::try_from(state_root.as_ref()).unwrap();
+    let node = InsertTrieNode {
+        storage_value: InsertTrieNodeStorageValue::Value {
+            value: Cow::Borrowed(value),
+            references_merkle_value: false,
+        },
+        merkle_value: Cow::Owned(state_root.to_vec()),
+        children_merkle_values: array::from_fn(|_| None),
+        partial_key_nibbles: Cow::Borrowed(&[]),
+    };
+    (state_root, node)
+}
@@ -607,6 +607,9 @@ pub enum HostVm {
     /// Need to verify whether a signature is valid.
     #[from]
     SignatureVerification(SignatureVerification),
+    /// Need to generate a new key and store it in the keystore.
+    #[from]
+    GenerateKey(GenerateKey),
     /// Need to call `Core_version` on the given Wasm code and return the raw output (i.e.
     /// still SCALE-encoded), or an error if the call has failed.
     #[from]
@@ -655,6 +658,7 @@ impl HostVm {
             HostVm::OffchainRandomSeed(inner) => inner.inner.into_prototype(),
             HostVm::OffchainSubmitTransaction(inner) => inner.inner.into_prototype(),
             HostVm::SignatureVerification(inner) => inner.inner.into_prototype(),
+            HostVm::GenerateKey(inner) => inner.inner.into_prototype(),
             HostVm::CallRuntimeVersion(inner) => inner.inner.into_prototype(),
             HostVm::StartStorageTransaction(inner) => inner.inner.into_prototype(),
             HostVm::EndStorageTransaction { resume, .. } => resume.inner.into_prototype(),
@@ -1395,7 +1399,48 @@ impl ReadyToRun {
                 })
             }
             HostFunction::ext_crypto_ed25519_public_keys_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_crypto_ed25519_generate_version_1 => host_fn_not_implemented!(),
+            HostFunction::ext_crypto_ed25519_generate_version_1
+            | HostFunction::ext_crypto_sr25519_generate_version_1 => {
+                let key_type_id = expect_pointer_constant_size!(0, 4);
+
+                // The seed is a SCALE-encoded `Option<Vec<u8>>`.
+                let seed = {
+                    let input = expect_pointer_size!(1);
+                    let parsing_result: Result<_, nom::Err<(&[u8], nom::error::ErrorKind)>> =
+                        nom::combinator::all_consuming(util::nom_option_decode(
+                            util::nom_bytes_decode,
+                        ))(input.as_ref())
+                        .map(|(_, parse_result)| parse_result.map(|seed| seed.to_vec()));
+
+                    match parsing_result {
+                        Ok(val) => Ok(val),
+                        Err(_) => Err(()),
+                    }
+                };
+
+                let seed = match seed {
+                    Ok(s) => s,
+                    Err(()) => {
+                        return HostVm::Error {
+                            error: Error::ParamDecodeError,
+                            prototype: self.inner.into_prototype(),
+                        };
+                    }
+                };
+
+                HostVm::GenerateKey(GenerateKey {
+                    inner: self.inner,
+                    calling: id,
+                    algorithm: match host_fn {
+                        HostFunction::ext_crypto_ed25519_generate_version_1 => {
+                            GenerateKeyAlgorithm::Ed25519
+                        }
+                        _ => GenerateKeyAlgorithm::Sr25519,
+                    },
+                    key_type_id,
+                    seed,
+                })
+            }
             HostFunction::ext_crypto_ed25519_sign_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_ed25519_verify_version_1
             | HostFunction::ext_crypto_ed25519_batch_verify_version_1 => {
@@ -1423,7 +1468,6 @@ impl ReadyToRun {
                 })
             }
             HostFunction::ext_crypto_sr25519_public_keys_version_1 => host_fn_not_implemented!(),
-            HostFunction::ext_crypto_sr25519_generate_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_sr25519_sign_version_1 => host_fn_not_implemented!(),
             HostFunction::ext_crypto_sr25519_verify_version_1
             | HostFunction::ext_crypto_sr25519_batch_verify_version_1 => {
@@ -3313,6 +3357,72 @@ impl fmt::Debug for ExternalOffchainStorageGet {
     }
 }
 
+/// Must generate a new key and store it in the keystore.
+pub struct GenerateKey {
+    inner: Box<Inner>,
+
+    /// Function currently being called by the Wasm code. Refers to an index within
+    /// [`VmCommon::registered_functions`]. Guaranteed to be [`FunctionImport::Resolved`̀].
+    calling: usize,
+
+    /// Which cryptographic algorithm.
+    algorithm: GenerateKeyAlgorithm,
+
+    /// Identifier of the type of key, such as `b"aura"`.
+    key_type_id: [u8; 4],
+
+    /// Seed passed by the runtime, if any.
+    seed: Option<Vec<u8>>,
+}
+
+impl GenerateKey {
+    /// Returns the cryptographic algorithm of the key to generate.
+    pub fn algorithm(&self) -> GenerateKeyAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the identifier of the type of key, such as `b"aura"` or `b"gran"`. This is
+    /// known as the "key type" in Substrate.
+    pub fn key_type_id(&self) -> &[u8; 4] {
+        &self.key_type_id
+    }
+
+    /// Returns the seed from which the key must be generated, if any. If `None`, the key must
+    /// be generated randomly.
+    ///
+    /// The seed is supposed to be a UTF-8-encoded seed phrase, optionally followed with a
+    /// derivation path, such as `//Alice`.
+    ///
+    /// > **Note**: The seed is untrusted input and might not be valid UTF-8.
+    pub fn seed(&self) -> Option<&[u8]> {
+        self.seed.as_deref()
+    }
+
+    /// Resumes execution after having generated the key. Must be passed the public key of the
+    /// newly-generated key.
+    pub fn resume(self, public_key: [u8; 32]) -> HostVm {
+        let host_fn = match self.inner.common.registered_functions[self.calling] {
+            FunctionImport::Resolved(f) => f,
+            FunctionImport::Unresolved { .. } => unreachable!(),
+        };
+        self.inner
+            .alloc_write_and_return_pointer(host_fn.name(), iter::once(public_key))
+    }
+}
+
+impl fmt::Debug for GenerateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("GenerateKey").finish()
+    }
+}
+
+/// Cryptographic algorithm of a key to generate. See [`GenerateKey::algorithm`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenerateKeyAlgorithm {
+    Ed25519,
+    Sr25519,
+}
+
 /// Must return the current UNIX timestamp.
 pub struct OffchainTimestamp {
     inner: Box<Inner>,
@@ -52,7 +52,9 @@ use alloc::{
 };
 use core::{fmt, iter, ops};
 
-pub use host::{Error as ErrorDetail, LogEmitInfo, LogEmitInfoHex, LogEmitInfoStr};
+pub use host::{
+    Error as ErrorDetail, GenerateKeyAlgorithm, LogEmitInfo, LogEmitInfoHex, LogEmitInfoStr,
+};
 pub use trie::{Nibble, TrieEntryVersion};
 
 mod tests;
@@ -487,6 +489,11 @@ pub enum RuntimeHostVm {
     NextKey(NextKey),
     /// Verifying whether a signature is correct is required in order to continue.
     SignatureVerification(SignatureVerification),
+    /// Generating a new key and storing it in the keystore is required in order to continue.
+    ///
+    /// This typically only happens when calling `SessionKeys_generate_session_keys`. Runtime
+    /// calls that don't have access to a keystore should treat this as an error.
+    GenerateKey(GenerateKey),
     /// Runtime would like to emit some log.
     LogEmit(LogEmit),
     /// Setting an offchain storage value is required in order to continue.
@@ -508,6 +515,7 @@ impl RuntimeHostVm {
             RuntimeHostVm::ClosestDescendantMerkleValue(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::NextKey(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::SignatureVerification(inner) => inner.inner.vm.into_prototype(),
+            RuntimeHostVm::GenerateKey(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::LogEmit(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::OffchainStorageSet(inner) => inner.inner.vm.into_prototype(),
             RuntimeHostVm::Offchain(inner) => inner.into_prototype(),
@@ -1007,6 +1015,51 @@ impl SignatureVerification {
     }
 }
 
+/// Generating a new key and storing it in the keystore is required in order to continue.
+#[must_use]
+pub struct GenerateKey {
+    inner: Inner,
+}
+
+impl GenerateKey {
+    /// Returns the cryptographic algorithm of the key to generate.
+    pub fn algorithm(&self) -> GenerateKeyAlgorithm {
+        match self.inner.vm {
+            host::HostVm::GenerateKey(ref req) => req.algorithm(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the identifier of the type of key, such as `b"aura"` or `b"gran"`.
+    pub fn key_type_id(&self) -> &[u8; 4] {
+        match self.inner.vm {
+            host::HostVm::GenerateKey(ref req) => req.key_type_id(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the seed from which the key must be generated, if any. If `None`, the key must
+    /// be generated randomly.
+    ///
+    /// See [`host::GenerateKey::seed`].
+    pub fn seed(&self) -> Option<&[u8]> {
+        match self.inner.vm {
+            host::HostVm::GenerateKey(ref req) => req.seed(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resume execution by providing the public key of the newly-generated key.
+    pub fn inject_public_key(mut self, public_key: [u8; 32]) -> RuntimeHostVm {
+        match self.inner.vm {
+            host::HostVm::GenerateKey(req) => self.inner.vm = req.resume(public_key),
+            _ => unreachable!(),
+        }
+
+        self.inner.run()
+    }
+}
+
 /// Loading an offchain storage value is required in order to continue.
 #[must_use]
 pub struct OffchainStorageGet {
@@ -1763,6 +1816,11 @@ impl Inner {
                     });
                 }
 
+                host::HostVm::GenerateKey(req) => {
+                    self.vm = req.into();
+                    return RuntimeHostVm::GenerateKey(GenerateKey { inner: self });
+                }
+
                 host::HostVm::CallRuntimeVersion(req) => {
                     // TODO: make the user execute this ; see https://github.com/paritytech/smoldot/issues/144
                     // The code below compiles the provided WebAssembly runtime code, which is a
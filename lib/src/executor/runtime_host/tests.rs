@@ -180,7 +180,9 @@ fn execute_blocks() {
                     execution = req.inject_key(next_key.map(|nk| nk.into_iter()));
                 }
                 RuntimeHostVm::LogEmit(log) => execution = log.resume(),
-                RuntimeHostVm::OffchainStorageSet(_) | RuntimeHostVm::Offchain(_) => {
+                RuntimeHostVm::OffchainStorageSet(_)
+                | RuntimeHostVm::Offchain(_)
+                | RuntimeHostVm::GenerateKey(_) => {
                     unimplemented!()
                 }
             }
@@ -0,0 +1,1687 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! GrandPa voter state machine.
+//!
+//! The GrandPa algorithm is divided in rounds. During each round, each authority emits a
+//! pre-vote, then a pre-commit. Once more than two thirds of the authorities (in terms of
+//! weight) have pre-committed a block or one of its descendants, this block is finalized.
+//! Additionally, at the beginning of each round, the so-called primary authority of the round
+//! proposes a block that the other authorities use as a base for their pre-votes.
+//!
+//! The [`Voter`] keeps track of the votes emitted by the authorities of a specific authorities
+//! set, and determines when the local authority (if any) must vote and which blocks are
+//! finalized. It doesn't perform any I/O and doesn't have access to any private key. Votes that
+//! the local authority must emit are returned by [`Voter::next_action`] and must be signed by
+//! the API user then passed back to [`Voter::inject_local_vote`].
+//!
+//! A [`Voter`] can only cover one authorities set. When the authorities set changes, a new
+//! [`Voter`] must be created.
+//!
+//! # Usage
+//!
+//! - Call [`Voter::block_imported`] for each new block, [`Voter::set_best_block`] whenever the
+//!   best block changes, and [`Voter::block_finalized`] whenever a block is finalized through a
+//!   different mechanism (for example through a justification received from the network).
+//! - Call [`Voter::inject_vote`] for each vote received from the network.
+//! - Call [`Voter::next_action`] in a loop after each of the calls above, and again at the time
+//!   indicated by [`Voter::next_wake_up`].
+//!
+
+// TODO: the vote ancestry isn't limited in any way, meaning that a malicious authority could vote for a block very far in the future
+
+use crate::{finality::justification, header, network::codec, util};
+
+use alloc::vec::Vec;
+use core::{cmp, iter, mem, ops::Add, time::Duration};
+
+/// Configuration for a [`Voter`].
+#[derive(Debug)]
+pub struct Config<TNow> {
+    /// Time at which the first round starts.
+    pub now: TNow,
+
+    /// Number of bytes used to encode the block number in the headers of the chain.
+    pub block_number_bytes: usize,
+
+    /// Identifier of the authorities set the voter covers.
+    pub authorities_set_id: u64,
+
+    /// List of the authorities of the set. Must not be empty.
+    pub authorities: Vec<header::GrandpaAuthority>,
+
+    /// Ed25519 public key of the local authority, if any. If `None`, or if the key isn't part of
+    /// [`Config::authorities`], the voter only observes the votes of the other authorities.
+    pub local_authority: Option<[u8; 32]>,
+
+    /// Hash of the latest finalized block. All the votes are expected to concern this block or
+    /// one of its descendants.
+    pub finalized_block_hash: [u8; 32],
+
+    /// Height of the latest finalized block.
+    pub finalized_block_number: u64,
+
+    /// Round number to start at. Generally `1` when the authorities set has just been enacted.
+    /// The voter catches up with the other authorities if necessary.
+    pub round_number: u64,
+
+    /// Votes that the local authority has already cast within this authorities set, for example
+    /// before the node has been restarted.
+    ///
+    /// The voter starts at the highest round found in this list if it is above
+    /// [`Config::round_number`], and never casts a vote of a kind that the local authority has
+    /// already cast in the same round. Voting twice in the same round would be an equivocation.
+    pub local_votes: Vec<LocalVote>,
+
+    /// Estimated time it takes for a vote to propagate through the network. The local authority
+    /// emits its pre-vote after twice this duration since the start of the round, and its
+    /// pre-commit at the latest after four times this duration.
+    pub gossip_duration: Duration,
+
+    /// Seed used for the hash maps of the voter.
+    pub randomness_seed: [u8; 16],
+}
+
+/// GrandPa voter. See the module-level documentation.
+pub struct Voter<TNow> {
+    /// See [`Config::block_number_bytes`].
+    block_number_bytes: usize,
+
+    /// See [`Config::authorities_set_id`].
+    set_id: u64,
+
+    /// See [`Config::authorities`].
+    authorities: Vec<header::GrandpaAuthority>,
+
+    /// Index within [`Voter::authorities`] of each authority.
+    authorities_indices: hashbrown::HashMap<[u8; 32], usize, util::SipHasherBuild>,
+
+    /// Sum of the weights of all the authorities.
+    total_weight: u64,
+
+    /// Weight that votes must reach in order to be considered as a supermajority.
+    threshold: u64,
+
+    /// See [`Config::local_authority`]. `None` if the key isn't part of the authorities.
+    local_authority: Option<[u8; 32]>,
+
+    /// See [`Config::gossip_duration`].
+    gossip_duration: Duration,
+
+    /// See [`Config::randomness_seed`].
+    randomness_seed: [u8; 16],
+
+    /// Blocks known by the voter, indexed by their hash.
+    blocks: hashbrown::HashMap<[u8; 32], Block, util::SipHasherBuild>,
+
+    /// Hash of the current best block.
+    best_block_hash: [u8; 32],
+
+    /// Hash and height of the latest finalized block.
+    finalized_block: ([u8; 32], u64),
+
+    /// Round that is currently in progress.
+    current_round: Round<TNow>,
+
+    /// Latest completed round. Votes concerning this round are still accepted, as they might
+    /// lead to finalizing more blocks.
+    previous_round: Option<Round<TNow>>,
+
+    /// Votes concerning the round following [`Voter::current_round`]. The signatures of these
+    /// votes have already been verified. They are injected when the next round starts.
+    next_round_votes: Vec<(VoteKind, [u8; 32], Vote)>,
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    number: u64,
+    parent_hash: [u8; 32],
+}
+
+struct Round<TNow> {
+    number: u64,
+    start: TNow,
+    /// Estimate of the round that precedes this one. The votes of the round are expected to
+    /// concern this block or one of its descendants.
+    base: ([u8; 32], u64),
+    /// Pre-vote GHOST of the round that precedes this one, if known.
+    previous_prevote_ghost: Option<([u8; 32], u64)>,
+    /// Block proposed by the primary authority of the round.
+    primary_proposal: Option<([u8; 32], u64)>,
+    prevotes: VoteSet,
+    precommits: VoteSet,
+    /// Progress of the local authority.
+    local_primary_proposed: bool,
+    local_prevoted: bool,
+    local_precommitted: bool,
+}
+
+struct VoteSet {
+    votes: hashbrown::HashMap<[u8; 32], AuthorityVotes, util::SipHasherBuild>,
+}
+
+enum AuthorityVotes {
+    Single(Vote),
+    /// The authority has voted twice for different blocks.
+    Equivocation(Vote, Vote),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Vote {
+    target_hash: [u8; 32],
+    target_number: u64,
+    signature: [u8; 64],
+}
+
+/// Result of tallying the votes of a [`VoteSet`].
+struct Tally {
+    /// For each block, the sum of the weights of the non-equivocating authorities that have
+    /// voted for that block or one of its descendants.
+    weights: hashbrown::HashMap<[u8; 32], (u64, u64), util::SipHasherBuild>,
+    /// Sum of the weights of the authorities that have equivocated. This weight counts towards
+    /// every block.
+    equivocations_weight: u64,
+    /// Sum of the weights of all the authorities whose votes have been taken into account.
+    voters_weight: u64,
+}
+
+impl<TNow> Voter<TNow>
+where
+    TNow: Clone + Add<Duration, Output = TNow> + Ord,
+{
+    /// Initializes a new [`Voter`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`Config::authorities`] is empty.
+    ///
+    pub fn new(config: Config<TNow>) -> Self {
+        assert!(!config.authorities.is_empty());
+
+        let mut authorities_indices = hashbrown::HashMap::with_capacity_and_hasher(
+            config.authorities.len(),
+            util::SipHasherBuild::new(config.randomness_seed),
+        );
+        for (index, authority) in config.authorities.iter().enumerate() {
+            authorities_indices.insert(authority.public_key, index);
+        }
+
+        let total_weight = config
+            .authorities
+            .iter()
+            .fold(0u64, |sum, a| sum.saturating_add(a.weight.get()));
+        let threshold = total_weight - (total_weight - 1) / 3;
+
+        let local_authority = config
+            .local_authority
+            .filter(|key| authorities_indices.contains_key(key));
+
+        let mut blocks = hashbrown::HashMap::with_capacity_and_hasher(
+            32,
+            util::SipHasherBuild::new(config.randomness_seed),
+        );
+        blocks.insert(
+            config.finalized_block_hash,
+            Block {
+                number: config.finalized_block_number,
+                // The parent of the finalized block is never accessed.
+                parent_hash: [0; 32],
+            },
+        );
+
+        let finalized_block = (config.finalized_block_hash, config.finalized_block_number);
+
+        // Resume at the latest round the local authority has voted in, in order to never go
+        // back to a round that has already been voted in.
+        let round_number = config
+            .local_votes
+            .iter()
+            .map(|v| v.round_number)
+            .fold(config.round_number, cmp::max);
+
+        let mut current_round = Round::new(
+            round_number,
+            config.now,
+            finalized_block,
+            None,
+            config.randomness_seed,
+        );
+
+        for local_vote in config
+            .local_votes
+            .into_iter()
+            .filter(|v| v.round_number == round_number)
+        {
+            // Primary proposals are always cast before pre-votes, which are always cast before
+            // pre-commits.
+            match local_vote.kind {
+                VoteKind::PrimaryPropose => {
+                    current_round.local_primary_proposed = true;
+                }
+                VoteKind::Prevote => {
+                    current_round.local_primary_proposed = true;
+                    current_round.local_prevoted = true;
+                }
+                VoteKind::Precommit => {
+                    current_round.local_primary_proposed = true;
+                    current_round.local_prevoted = true;
+                    current_round.local_precommitted = true;
+                }
+            }
+
+            if authorities_indices.contains_key(&local_vote.authority_public_key) {
+                let is_primary = {
+                    let index = round_number % u64::try_from(config.authorities.len()).unwrap();
+                    config.authorities[usize::try_from(index).unwrap()].public_key
+                        == local_vote.authority_public_key
+                };
+                let _ = current_round.insert_vote(
+                    local_vote.kind,
+                    local_vote.authority_public_key,
+                    Vote {
+                        target_hash: local_vote.target_hash,
+                        target_number: local_vote.target_number,
+                        signature: local_vote.signature,
+                    },
+                    is_primary,
+                );
+            }
+        }
+
+        Voter {
+            block_number_bytes: config.block_number_bytes,
+            set_id: config.authorities_set_id,
+            current_round,
+            previous_round: None,
+            next_round_votes: Vec::new(),
+            authorities: config.authorities,
+            authorities_indices,
+            total_weight,
+            threshold,
+            local_authority,
+            gossip_duration: config.gossip_duration,
+            randomness_seed: config.randomness_seed,
+            blocks,
+            best_block_hash: config.finalized_block_hash,
+            finalized_block,
+        }
+    }
+
+    /// Returns the identifier of the authorities set covered by the voter.
+    pub fn authorities_set_id(&self) -> u64 {
+        self.set_id
+    }
+
+    /// Returns the number of the round currently in progress.
+    pub fn round_number(&self) -> u64 {
+        self.current_round.number
+    }
+
+    /// Returns the hash and height of the latest block that the voter considers as finalized.
+    pub fn finalized_block(&self) -> ([u8; 32], u64) {
+        self.finalized_block
+    }
+
+    /// Returns the public key of the local authority, if the voter votes.
+    pub fn local_authority(&self) -> Option<&[u8; 32]> {
+        self.local_authority.as_ref()
+    }
+
+    /// Adds a block to the list of blocks known by the voter.
+    ///
+    /// Blocks must be added in order, meaning that the parent of a block must have been added
+    /// before the block itself.
+    pub fn block_imported(&mut self, hash: [u8; 32], number: u64, parent_hash: [u8; 32]) {
+        if number <= self.finalized_block.1 {
+            return;
+        }
+
+        self.blocks.insert(
+            hash,
+            Block {
+                number,
+                parent_hash,
+            },
+        );
+    }
+
+    /// Sets the block that the local authority considers as the best block. The local
+    /// authority pre-votes for this block if possible.
+    ///
+    /// The block must have been passed to [`Voter::block_imported`] beforehand.
+    pub fn set_best_block(&mut self, hash: [u8; 32]) {
+        self.best_block_hash = hash;
+    }
+
+    /// Notifies the voter that a block has been finalized through a different mechanism, for
+    /// example a justification received from the network.
+    ///
+    /// Has no effect if the block is older than the current finalized block.
+    pub fn block_finalized(&mut self, hash: [u8; 32], number: u64) {
+        if number <= self.finalized_block.1 {
+            return;
+        }
+
+        self.finalized_block = (hash, number);
+        self.blocks.entry(hash).or_insert(Block {
+            number,
+            parent_hash: [0; 32],
+        });
+    }
+
+    /// Returns the next moment in time when [`Voter::next_action`] should be called, or `None`
+    /// if it is only necessary to call [`Voter::next_action`] after the state of the voter has
+    /// been modified.
+    pub fn next_wake_up(&self) -> Option<TNow> {
+        self.local_authority?;
+
+        if !self.current_round.local_prevoted {
+            Some(self.current_round.start.clone() + self.gossip_duration * 2)
+        } else if !self.current_round.local_precommitted {
+            Some(self.current_round.start.clone() + self.gossip_duration * 4)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the next action that the API user must perform, or `None` if there is nothing
+    /// to do at the moment.
+    ///
+    /// This function should be called repeatedly until it returns `None`.
+    pub fn next_action(&mut self, now: &TNow) -> Option<Action> {
+        if let Some(local_authority) = self.local_authority {
+            // Primary proposal.
+            if !self.current_round.local_primary_proposed {
+                self.current_round.local_primary_proposed = true;
+
+                if *self.primary(self.current_round.number) == local_authority
+                    && self.current_round.base.1 > self.finalized_block.1
+                {
+                    let (target_hash, target_number) = self.current_round.base;
+                    return Some(Action::Vote(VoteToSign {
+                        block_number_bytes: self.block_number_bytes,
+                        round_number: self.current_round.number,
+                        set_id: self.set_id,
+                        kind: VoteKind::PrimaryPropose,
+                        target_hash,
+                        target_number,
+                        authority_public_key: local_authority,
+                    }));
+                }
+            }
+
+            // Pre-vote.
+            if !self.current_round.local_prevoted
+                && *now >= self.current_round.start.clone() + self.gossip_duration * 2
+            {
+                self.current_round.local_prevoted = true;
+                let (target_hash, target_number) = self.prevote_target();
+                return Some(Action::Vote(VoteToSign {
+                    block_number_bytes: self.block_number_bytes,
+                    round_number: self.current_round.number,
+                    set_id: self.set_id,
+                    kind: VoteKind::Prevote,
+                    target_hash,
+                    target_number,
+                    authority_public_key: local_authority,
+                }));
+            }
+
+            // Pre-commit.
+            if self.current_round.local_prevoted && !self.current_round.local_precommitted {
+                let prevotes = self.tally(&self.current_round.prevotes);
+                if let Some(prevote_ghost) = prevotes.ghost(self.threshold) {
+                    let timer_elapsed =
+                        *now >= self.current_round.start.clone() + self.gossip_duration * 4;
+                    if self.is_descendant_or_equal(&self.current_round.base.0, &prevote_ghost.0)
+                        && (timer_elapsed || self.is_completable(&self.current_round))
+                    {
+                        self.current_round.local_precommitted = true;
+                        return Some(Action::Vote(VoteToSign {
+                            block_number_bytes: self.block_number_bytes,
+                            round_number: self.current_round.number,
+                            set_id: self.set_id,
+                            kind: VoteKind::Precommit,
+                            target_hash: prevote_ghost.0,
+                            target_number: prevote_ghost.1,
+                            authority_public_key: local_authority,
+                        }));
+                    }
+                }
+            }
+        }
+
+        // Finality.
+        for round in iter::once(&self.current_round).chain(self.previous_round.iter()) {
+            let precommits = self.tally(&round.precommits);
+            let Some((target_hash, target_number)) = precommits.ghost(self.threshold) else {
+                continue;
+            };
+            if target_number <= self.finalized_block.1 {
+                continue;
+            }
+
+            let commit = self.build_commit(round, target_hash, target_number);
+            self.finalized_block = (target_hash, target_number);
+            return Some(Action::Finalized(commit));
+        }
+
+        // Round completion.
+        if (self.local_authority.is_none() || self.current_round.local_precommitted)
+            && self.is_completable(&self.current_round)
+        {
+            let prevotes = self.tally(&self.current_round.prevotes);
+            let prevote_ghost = prevotes.ghost(self.threshold);
+            let estimate = self.estimate(&self.current_round).unwrap();
+            self.start_next_round(now.clone(), estimate, prevote_ghost);
+            return Some(Action::RoundStarted {
+                round_number: self.current_round.number,
+            });
+        }
+
+        None
+    }
+
+    /// Injects in the state machine a vote received from the network.
+    pub fn inject_vote(
+        &mut self,
+        vote: &codec::VoteMessageRef,
+    ) -> Result<InjectVoteOutcome, InjectVoteError> {
+        if vote.set_id != self.set_id {
+            return Err(InjectVoteError::BadSetId);
+        }
+
+        let (kind, target_hash, target_number) = match &vote.message {
+            codec::MessageRef::Prevote(m) => (VoteKind::Prevote, m.target_hash, m.target_number),
+            codec::MessageRef::Precommit(m) => {
+                (VoteKind::Precommit, m.target_hash, m.target_number)
+            }
+            codec::MessageRef::PrimaryPropose(m) => {
+                (VoteKind::PrimaryPropose, m.target_hash, m.target_number)
+            }
+        };
+
+        if !self
+            .authorities_indices
+            .contains_key(vote.authority_public_key)
+        {
+            return Err(InjectVoteError::NotAuthority);
+        }
+
+        let is_current_round = vote.round_number == self.current_round.number;
+        let is_previous_round =
+            matches!(&self.previous_round, Some(r) if r.number == vote.round_number);
+        let is_next_round = vote.round_number == self.current_round.number + 1;
+        if !is_current_round && !is_previous_round && !is_next_round {
+            return Err(InjectVoteError::UnknownRound);
+        }
+
+        verify_signature(
+            kind,
+            target_hash,
+            target_number,
+            vote.round_number,
+            self.set_id,
+            self.block_number_bytes,
+            vote.signature,
+            vote.authority_public_key,
+        )?;
+
+        let new_vote = Vote {
+            target_hash: *target_hash,
+            target_number,
+            signature: *vote.signature,
+        };
+
+        if is_next_round {
+            // TODO: DoS attack possible here, as the list of votes isn't bounded by anything else than the number of authorities
+            if self.next_round_votes.len() < self.authorities.len() * 3 {
+                self.next_round_votes
+                    .push((kind, *vote.authority_public_key, new_vote));
+            }
+            return Ok(InjectVoteOutcome::Accepted);
+        }
+
+        let is_primary = *self.primary(vote.round_number) == *vote.authority_public_key;
+        let round = if is_current_round {
+            &mut self.current_round
+        } else {
+            self.previous_round.as_mut().unwrap()
+        };
+
+        Ok(round.insert_vote(kind, *vote.authority_public_key, new_vote, is_primary))
+    }
+
+    /// Injects the signature of a vote previously returned by [`Voter::next_action`].
+    ///
+    /// Returns the SCALE-encoded GrandPa notification containing the vote, that must be sent to
+    /// all the peers.
+    pub fn inject_local_vote(&mut self, vote: VoteToSign, signature: [u8; 64]) -> Vec<u8> {
+        let encoded = vote.scale_encoded_notification(&signature);
+
+        if vote.set_id == self.set_id {
+            let round = if vote.round_number == self.current_round.number {
+                Some(&mut self.current_round)
+            } else {
+                self.previous_round
+                    .as_mut()
+                    .filter(|r| r.number == vote.round_number)
+            };
+
+            if let Some(round) = round {
+                let _ = round.insert_vote(
+                    vote.kind,
+                    vote.authority_public_key,
+                    Vote {
+                        target_hash: vote.target_hash,
+                        target_number: vote.target_number,
+                        signature,
+                    },
+                    true,
+                );
+            }
+        }
+
+        encoded
+    }
+
+    /// Returns the catch-up request to send to a peer that has sent the given neighbor packet,
+    /// if the peer is far enough ahead of the local voter.
+    ///
+    /// The value returned can be sent to the peer with
+    /// [`codec::GrandpaNotificationRef::CatchUpRequest`].
+    pub fn catch_up_request(
+        &self,
+        neighbor_packet: &codec::NeighborPacket,
+    ) -> Option<codec::CatchUpRequest> {
+        if neighbor_packet.set_id != self.set_id {
+            return None;
+        }
+
+        if neighbor_packet.round_number <= self.current_round.number.saturating_add(1) {
+            return None;
+        }
+
+        Some(codec::CatchUpRequest {
+            round_number: self.current_round.number,
+            set_id: self.set_id,
+        })
+    }
+
+    /// Builds the answer to a catch-up request received from a peer.
+    ///
+    /// Returns the SCALE-encoded GrandPa notification to send back to the peer, or `None` if
+    /// the local voter isn't capable of answering the request.
+    pub fn catch_up_response(&self, request: &codec::CatchUpRequest) -> Option<Vec<u8>> {
+        if request.set_id != self.set_id {
+            return None;
+        }
+
+        let round = self.previous_round.as_ref()?;
+        if round.number < request.round_number {
+            return None;
+        }
+
+        let prevotes = round
+            .prevotes
+            .votes
+            .iter()
+            .map(|(authority, votes)| {
+                let vote = votes.first();
+                codec::PrevoteRef {
+                    target_hash: &vote.target_hash,
+                    target_number: vote.target_number,
+                    signature: &vote.signature,
+                    authority_public_key: authority,
+                }
+            })
+            .collect::<Vec<_>>();
+        let precommits = round
+            .precommits
+            .votes
+            .iter()
+            .map(|(authority, votes)| {
+                let vote = votes.first();
+                justification::decode::PrecommitRef {
+                    target_hash: &vote.target_hash,
+                    target_number: vote.target_number,
+                    signature: &vote.signature,
+                    authority_public_key: authority,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let catch_up = codec::GrandpaNotificationRef::CatchUp(codec::CatchUpRef {
+            set_id: self.set_id,
+            round_number: round.number,
+            prevotes,
+            precommits,
+            base_hash: &round.base.0,
+            base_number: round.base.1,
+        });
+
+        Some(
+            catch_up
+                .scale_encoding(self.block_number_bytes)
+                .fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                }),
+        )
+    }
+
+    /// Injects in the state machine a catch-up message received from a peer.
+    ///
+    /// On success, the voter jumps to the round that follows the one in the catch-up message.
+    pub fn inject_catch_up(
+        &mut self,
+        now: TNow,
+        catch_up: &codec::CatchUpRef,
+    ) -> Result<(), InjectCatchUpError> {
+        if catch_up.set_id != self.set_id {
+            return Err(InjectCatchUpError::BadSetId);
+        }
+
+        if catch_up.round_number < self.current_round.number {
+            return Err(InjectCatchUpError::Obsolete);
+        }
+
+        let mut round = Round::new(
+            catch_up.round_number,
+            now.clone(),
+            (*catch_up.base_hash, catch_up.base_number),
+            None,
+            self.randomness_seed,
+        );
+        round.local_primary_proposed = true;
+        round.local_prevoted = true;
+        round.local_precommitted = true;
+
+        let votes = catch_up
+            .prevotes
+            .iter()
+            .map(|v| {
+                (
+                    VoteKind::Prevote,
+                    v.target_hash,
+                    v.target_number,
+                    v.signature,
+                    v.authority_public_key,
+                )
+            })
+            .chain(catch_up.precommits.iter().map(|v| {
+                (
+                    VoteKind::Precommit,
+                    v.target_hash,
+                    v.target_number,
+                    v.signature,
+                    v.authority_public_key,
+                )
+            }));
+
+        for (kind, target_hash, target_number, signature, authority_public_key) in votes {
+            if !self.authorities_indices.contains_key(authority_public_key) {
+                return Err(InjectCatchUpError::NotAuthority);
+            }
+
+            verify_signature(
+                kind,
+                target_hash,
+                target_number,
+                catch_up.round_number,
+                self.set_id,
+                self.block_number_bytes,
+                signature,
+                authority_public_key,
+            )
+            .map_err(|_| InjectCatchUpError::BadSignature)?;
+
+            let _ = round.insert_vote(
+                kind,
+                *authority_public_key,
+                Vote {
+                    target_hash: *target_hash,
+                    target_number,
+                    signature: *signature,
+                },
+                false,
+            );
+        }
+
+        if !self.is_completable(&round) {
+            return Err(InjectCatchUpError::NotCompletable);
+        }
+
+        let prevote_ghost = self.tally(&round.prevotes).ghost(self.threshold);
+        let estimate = self.estimate(&round).unwrap();
+        self.current_round = round;
+        self.start_next_round(now, estimate, prevote_ghost);
+        Ok(())
+    }
+
+    /// Returns the public key of the primary authority of the given round.
+    fn primary(&self, round_number: u64) -> &[u8; 32] {
+        let index = round_number % u64::try_from(self.authorities.len()).unwrap();
+        &self.authorities[usize::try_from(index).unwrap()].public_key
+    }
+
+    /// Replaces [`Voter::current_round`] with the round that follows.
+    fn start_next_round(
+        &mut self,
+        now: TNow,
+        estimate: ([u8; 32], u64),
+        prevote_ghost: Option<([u8; 32], u64)>,
+    ) {
+        let next_round = Round::new(
+            self.current_round.number + 1,
+            now,
+            estimate,
+            prevote_ghost,
+            self.randomness_seed,
+        );
+
+        let previous = mem::replace(&mut self.current_round, next_round);
+        self.previous_round = Some(previous);
+
+        // Inject the votes that have been received in advance.
+        for (kind, authority_public_key, vote) in mem::take(&mut self.next_round_votes) {
+            let _ = self
+                .current_round
+                .insert_vote(kind, authority_public_key, vote, true);
+        }
+
+        // Remove the blocks that can no longer be voted upon.
+        let min_number = cmp::min(
+            self.previous_round.as_ref().unwrap().base.1,
+            self.finalized_block.1,
+        );
+        self.blocks.retain(|_, b| b.number >= min_number);
+    }
+
+    /// Returns the block the local authority should pre-vote for in the current round.
+    fn prevote_target(&self) -> ([u8; 32], u64) {
+        let mut base = self.current_round.base;
+
+        if let (Some(proposal), Some(previous_ghost)) = (
+            self.current_round.primary_proposal,
+            self.current_round.previous_prevote_ghost,
+        ) {
+            if self.is_descendant_or_equal(&base.0, &proposal.0)
+                && self.is_descendant_or_equal(&proposal.0, &previous_ghost.0)
+            {
+                base = proposal;
+            }
+        }
+
+        match self.blocks.get(&self.best_block_hash) {
+            Some(best) if self.is_descendant_or_equal(&base.0, &self.best_block_hash) => {
+                (self.best_block_hash, best.number)
+            }
+            _ => base,
+        }
+    }
+
+    /// Returns `true` if `descendant` is equal to `ancestor` or one of its descendants.
+    fn is_descendant_or_equal(&self, ancestor: &[u8; 32], descendant: &[u8; 32]) -> bool {
+        let Some(ancestor_number) = self.blocks.get(ancestor).map(|b| b.number) else {
+            return false;
+        };
+
+        let mut iter = *descendant;
+        loop {
+            if iter == *ancestor {
+                return true;
+            }
+
+            match self.blocks.get(&iter) {
+                Some(b) if b.number > ancestor_number => iter = b.parent_hash,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Tallies the given votes.
+    fn tally(&self, votes: &VoteSet) -> Tally {
+        let mut tally = Tally {
+            weights: hashbrown::HashMap::with_capacity_and_hasher(
+                votes.votes.len() * 4,
+                util::SipHasherBuild::new(self.randomness_seed),
+            ),
+            equivocations_weight: 0,
+            voters_weight: 0,
+        };
+
+        for (authority, authority_votes) in &votes.votes {
+            let weight = self.authorities[*self.authorities_indices.get(authority).unwrap()]
+                .weight
+                .get();
+
+            let vote = match authority_votes {
+                AuthorityVotes::Single(vote) => vote,
+                AuthorityVotes::Equivocation(..) => {
+                    tally.equivocations_weight += weight;
+                    tally.voters_weight += weight;
+                    continue;
+                }
+            };
+
+            // Votes targeting unknown blocks are ignored. They will be taken into account
+            // once the block is known.
+            if !self.blocks.contains_key(&vote.target_hash) {
+                continue;
+            }
+
+            tally.voters_weight += weight;
+
+            let mut iter = vote.target_hash;
+            while let Some(block) = self.blocks.get(&iter) {
+                tally.weights.entry(iter).or_insert((block.number, 0)).1 += weight;
+                if block.number <= self.finalized_block.1 {
+                    break;
+                }
+                iter = block.parent_hash;
+            }
+        }
+
+        tally
+    }
+
+    /// Returns the estimate of the given round, in other words the highest block that is an
+    /// ancestor (or equal) to the pre-vote GHOST and that can still be pre-committed by a
+    /// supermajority.
+    fn estimate(&self, round: &Round<TNow>) -> Option<([u8; 32], u64)> {
+        let prevote_ghost = self.tally(&round.prevotes).ghost(self.threshold)?;
+        let precommits = self.tally(&round.precommits);
+        let not_voted = self.total_weight - precommits.voters_weight;
+
+        let mut iter = prevote_ghost.0;
+        loop {
+            let block = self.blocks.get(&iter)?;
+            if precommits.weight(&iter) + not_voted >= self.threshold {
+                return Some((iter, block.number));
+            }
+            if block.number <= self.finalized_block.1 {
+                return None;
+            }
+            iter = block.parent_hash;
+        }
+    }
+
+    /// Returns `true` if the given round is completable, in other words if its estimate can no
+    /// longer change.
+    fn is_completable(&self, round: &Round<TNow>) -> bool {
+        let Some(prevote_ghost) = self.tally(&round.prevotes).ghost(self.threshold) else {
+            return false;
+        };
+
+        let precommits = self.tally(&round.precommits);
+        if precommits.voters_weight < self.threshold {
+            return false;
+        }
+
+        let Some(estimate) = self.estimate(round) else {
+            return false;
+        };
+
+        if estimate != prevote_ghost {
+            return true;
+        }
+
+        // The estimate is equal to the pre-vote GHOST. The round is completable only if none of
+        // the children of the GHOST can reach a supermajority.
+        let not_voted = self.total_weight - precommits.voters_weight;
+        let best_child_weight = precommits
+            .weights
+            .iter()
+            .filter(|(hash, _)| {
+                matches!(self.blocks.get(*hash), Some(b) if b.parent_hash == prevote_ghost.0)
+            })
+            .map(|(_, (_, weight))| *weight)
+            .max()
+            .unwrap_or(0);
+        best_child_weight + precommits.equivocations_weight + not_voted < self.threshold
+    }
+
+    /// Builds the commit that proves the finality of the given block.
+    fn build_commit(
+        &self,
+        round: &Round<TNow>,
+        target_hash: [u8; 32],
+        target_number: u64,
+    ) -> Commit {
+        let mut precommits = Vec::with_capacity(round.precommits.votes.len());
+        let mut votes_ancestries = Vec::new();
+
+        for (authority, votes) in &round.precommits.votes {
+            // Only one vote per authority can be included. In case of an equivocation, pick
+            // the vote that concerns the target.
+            let vote = match votes {
+                AuthorityVotes::Single(vote) => vote,
+                AuthorityVotes::Equivocation(a, b) => {
+                    if self.is_descendant_or_equal(&target_hash, &a.target_hash) {
+                        a
+                    } else {
+                        b
+                    }
+                }
+            };
+
+            if !self.is_descendant_or_equal(&target_hash, &vote.target_hash) {
+                continue;
+            }
+
+            let mut iter = vote.target_hash;
+            while iter != target_hash {
+                if !votes_ancestries.contains(&iter) {
+                    votes_ancestries.push(iter);
+                }
+                iter = self.blocks.get(&iter).unwrap().parent_hash;
+            }
+
+            precommits.push(SignedPrecommit {
+                target_hash: vote.target_hash,
+                target_number: vote.target_number,
+                signature: vote.signature,
+                authority_public_key: *authority,
+            });
+        }
+
+        Commit {
+            round_number: round.number,
+            set_id: self.set_id,
+            target_hash,
+            target_number,
+            precommits,
+            votes_ancestries,
+        }
+    }
+}
+
+impl<TNow> Round<TNow> {
+    fn new(
+        number: u64,
+        start: TNow,
+        base: ([u8; 32], u64),
+        previous_prevote_ghost: Option<([u8; 32], u64)>,
+        randomness_seed: [u8; 16],
+    ) -> Self {
+        Round {
+            number,
+            start,
+            base,
+            previous_prevote_ghost,
+            primary_proposal: None,
+            prevotes: VoteSet {
+                votes: hashbrown::HashMap::with_hasher(util::SipHasherBuild::new(randomness_seed)),
+            },
+            precommits: VoteSet {
+                votes: hashbrown::HashMap::with_hasher(util::SipHasherBuild::new(randomness_seed)),
+            },
+            local_primary_proposed: false,
+            local_prevoted: false,
+            local_precommitted: false,
+        }
+    }
+
+    /// Inserts a vote whose signature has already been verified.
+    fn insert_vote(
+        &mut self,
+        kind: VoteKind,
+        authority_public_key: [u8; 32],
+        vote: Vote,
+        is_primary: bool,
+    ) -> InjectVoteOutcome {
+        let set = match kind {
+            VoteKind::Prevote => &mut self.prevotes,
+            VoteKind::Precommit => &mut self.precommits,
+            VoteKind::PrimaryPropose => {
+                if is_primary && self.primary_proposal.is_none() {
+                    self.primary_proposal = Some((vote.target_hash, vote.target_number));
+                    return InjectVoteOutcome::Accepted;
+                }
+                return InjectVoteOutcome::Duplicate;
+            }
+        };
+
+        match set.votes.entry(authority_public_key) {
+            hashbrown::hash_map::Entry::Vacant(entry) => {
+                entry.insert(AuthorityVotes::Single(vote));
+                InjectVoteOutcome::Accepted
+            }
+            hashbrown::hash_map::Entry::Occupied(mut entry) => match entry.get_mut() {
+                AuthorityVotes::Single(existing) if *existing == vote => {
+                    InjectVoteOutcome::Duplicate
+                }
+                AuthorityVotes::Single(existing)
+                    if existing.target_hash == vote.target_hash
+                        && existing.target_number == vote.target_number =>
+                {
+                    // Same vote but different signature. This is not an equivocation.
+                    InjectVoteOutcome::Duplicate
+                }
+                AuthorityVotes::Single(existing) => {
+                    let first = existing.clone();
+                    let equivocation = Equivocation {
+                        round_number: self.number,
+                        kind,
+                        authority_public_key,
+                        first: (first.target_hash, first.target_number),
+                        second: (vote.target_hash, vote.target_number),
+                    };
+                    *entry.get_mut() = AuthorityVotes::Equivocation(first, vote);
+                    InjectVoteOutcome::Equivocation(equivocation)
+                }
+                AuthorityVotes::Equivocation(..) => InjectVoteOutcome::Duplicate,
+            },
+        }
+    }
+}
+
+impl AuthorityVotes {
+    fn first(&self) -> &Vote {
+        match self {
+            AuthorityVotes::Single(v) => v,
+            AuthorityVotes::Equivocation(v, _) => v,
+        }
+    }
+}
+
+impl Tally {
+    /// Returns the weight of the votes for the given block or its descendants.
+    fn weight(&self, hash: &[u8; 32]) -> u64 {
+        self.weights.get(hash).map_or(0, |(_, w)| *w) + self.equivocations_weight
+    }
+
+    /// Returns the highest block whose weight is superior or equal to the threshold.
+    fn ghost(&self, threshold: u64) -> Option<([u8; 32], u64)> {
+        self.weights
+            .iter()
+            .filter(|(_, (_, weight))| *weight + self.equivocations_weight >= threshold)
+            .map(|(hash, (number, _))| (*hash, *number))
+            .max_by_key(|(hash, number)| (*number, *hash))
+    }
+}
+
+/// Action that the API user must perform. See [`Voter::next_action`].
+#[derive(Debug)]
+pub enum Action {
+    /// The local authority must sign a vote. The signature must then be passed to
+    /// [`Voter::inject_local_vote`].
+    Vote(VoteToSign),
+
+    /// A block has been finalized by the GrandPa authorities.
+    Finalized(Commit),
+
+    /// A new round has started. The API user should notify its peers by sending them a
+    /// neighbor packet.
+    RoundStarted {
+        /// Number of the round that has just started.
+        round_number: u64,
+    },
+}
+
+/// Kind of GrandPa vote.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+    PrimaryPropose,
+}
+
+/// Vote previously cast by the local authority. See [`Config::local_votes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalVote {
+    /// Round the vote belongs to.
+    pub round_number: u64,
+    /// Kind of vote.
+    pub kind: VoteKind,
+    /// Hash of the block the vote is targeting.
+    pub target_hash: [u8; 32],
+    /// Height of the block the vote is targeting.
+    pub target_number: u64,
+    /// Ed25519 public key that has signed the vote.
+    pub authority_public_key: [u8; 32],
+    /// Ed25519 signature of the vote.
+    pub signature: [u8; 64],
+}
+
+/// Vote that the local authority must sign.
+#[derive(Debug, Clone)]
+pub struct VoteToSign {
+    block_number_bytes: usize,
+    /// Round the vote belongs to.
+    pub round_number: u64,
+    /// Authorities set the vote belongs to.
+    pub set_id: u64,
+    /// Kind of vote.
+    pub kind: VoteKind,
+    /// Hash of the block the vote is targeting.
+    pub target_hash: [u8; 32],
+    /// Height of the block the vote is targeting.
+    pub target_number: u64,
+    /// Ed25519 public key that must sign the vote.
+    pub authority_public_key: [u8; 32],
+}
+
+impl VoteToSign {
+    /// Returns the payload that must be signed using Ed25519 by
+    /// [`VoteToSign::authority_public_key`].
+    pub fn payload_to_sign(&self) -> Vec<u8> {
+        signed_payload(
+            self.kind,
+            &self.target_hash,
+            self.target_number,
+            self.round_number,
+            self.set_id,
+            self.block_number_bytes,
+        )
+    }
+
+    fn scale_encoded_notification(&self, signature: &[u8; 64]) -> Vec<u8> {
+        let message = match self.kind {
+            VoteKind::Prevote => codec::MessageRef::Prevote(codec::UnsignedPrevoteRef {
+                target_hash: &self.target_hash,
+                target_number: self.target_number,
+            }),
+            VoteKind::Precommit => codec::MessageRef::Precommit(codec::UnsignedPrecommitRef {
+                target_hash: &self.target_hash,
+                target_number: self.target_number,
+            }),
+            VoteKind::PrimaryPropose => {
+                codec::MessageRef::PrimaryPropose(codec::PrimaryProposeRef {
+                    target_hash: &self.target_hash,
+                    target_number: self.target_number,
+                })
+            }
+        };
+
+        codec::GrandpaNotificationRef::Vote(codec::VoteMessageRef {
+            round_number: self.round_number,
+            set_id: self.set_id,
+            message,
+            signature,
+            authority_public_key: &self.authority_public_key,
+        })
+        .scale_encoding(self.block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+    }
+}
+
+/// Proof that a block has been finalized by the GrandPa authorities.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    /// Round during which the block has been finalized.
+    pub round_number: u64,
+    /// Authorities set that has finalized the block.
+    pub set_id: u64,
+    /// Hash of the finalized block.
+    pub target_hash: [u8; 32],
+    /// Height of the finalized block.
+    pub target_number: u64,
+    /// List of the pre-commits that target [`Commit::target_hash`] or one of its descendants.
+    pub precommits: Vec<SignedPrecommit>,
+    /// Hashes of the blocks between [`Commit::target_hash`] (exclusive) and the targets of the
+    /// pre-commits (inclusive). The headers of these blocks must be included in justifications.
+    pub votes_ancestries: Vec<[u8; 32]>,
+}
+
+impl Commit {
+    /// Returns the SCALE-encoded GrandPa commit notification to send to peers.
+    pub fn scale_encoded_notification(&self, block_number_bytes: usize) -> Vec<u8> {
+        codec::GrandpaNotificationRef::Commit(codec::CommitMessageRef {
+            round_number: self.round_number,
+            set_id: self.set_id,
+            message: crate::finality::grandpa::commit::decode::CompactCommitRef {
+                target_hash: &self.target_hash,
+                target_number: self.target_number,
+                precommits: self
+                    .precommits
+                    .iter()
+                    .map(|p| codec::UnsignedPrecommitRef {
+                        target_hash: &p.target_hash,
+                        target_number: p.target_number,
+                    })
+                    .collect(),
+                auth_data: self
+                    .precommits
+                    .iter()
+                    .map(|p| (&p.signature, &p.authority_public_key))
+                    .collect(),
+            },
+        })
+        .scale_encoding(block_number_bytes)
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        })
+    }
+
+    /// Returns the SCALE-encoded justification of the finalized block.
+    ///
+    /// Must be passed the SCALE-encoded headers of the blocks whose hashes are found in
+    /// [`Commit::votes_ancestries`].
+    pub fn scale_encoded_justification<'a>(
+        &self,
+        block_number_bytes: usize,
+        votes_ancestries_headers: impl ExactSizeIterator<Item = &'a [u8]>,
+    ) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            8 + 32
+                + block_number_bytes
+                + 10
+                + self.precommits.len() * (32 + block_number_bytes + 64 + 32)
+                + 10,
+        );
+        out.extend_from_slice(&self.round_number.to_le_bytes());
+        out.extend_from_slice(&self.target_hash);
+        out.extend_from_slice(&encode_block_number(self.target_number, block_number_bytes));
+        out.extend_from_slice(util::encode_scale_compact_usize(self.precommits.len()).as_ref());
+        for precommit in &self.precommits {
+            out.extend_from_slice(&precommit.target_hash);
+            out.extend_from_slice(&encode_block_number(
+                precommit.target_number,
+                block_number_bytes,
+            ));
+            out.extend_from_slice(&precommit.signature);
+            out.extend_from_slice(&precommit.authority_public_key);
+        }
+        out.extend_from_slice(
+            util::encode_scale_compact_usize(votes_ancestries_headers.len()).as_ref(),
+        );
+        for header in votes_ancestries_headers {
+            out.extend_from_slice(header);
+        }
+        out
+    }
+}
+
+/// Pre-commit found in a [`Commit`].
+#[derive(Debug, Clone)]
+pub struct SignedPrecommit {
+    /// Hash of the block concerned by the pre-commit.
+    pub target_hash: [u8; 32],
+    /// Height of the block concerned by the pre-commit.
+    pub target_number: u64,
+    /// Ed25519 signature made with [`SignedPrecommit::authority_public_key`].
+    pub signature: [u8; 64],
+    /// Authority that signed the pre-commit.
+    pub authority_public_key: [u8; 32],
+}
+
+/// Outcome of a successful call to [`Voter::inject_vote`].
+#[derive(Debug)]
+pub enum InjectVoteOutcome {
+    /// Vote has been taken into account.
+    Accepted,
+    /// Vote has already been received in the past and has been ignored.
+    Duplicate,
+    /// The authority has already voted for a different block during the same round. Both votes
+    /// are kept, and the weight of the authority now counts towards every block.
+    Equivocation(Equivocation),
+}
+
+/// Two different votes emitted by the same authority during the same round.
+#[derive(Debug, Clone)]
+pub struct Equivocation {
+    /// Round the votes belong to.
+    pub round_number: u64,
+    /// Kind of the two votes.
+    pub kind: VoteKind,
+    /// Authority that has emitted the votes.
+    pub authority_public_key: [u8; 32],
+    /// Hash and height of the block targeted by the first vote.
+    pub first: ([u8; 32], u64),
+    /// Hash and height of the block targeted by the second vote.
+    pub second: ([u8; 32], u64),
+}
+
+/// Error potentially returned by [`Voter::inject_vote`].
+#[derive(Debug, derive_more::Display)]
+pub enum InjectVoteError {
+    /// Vote concerns a different authorities set.
+    BadSetId,
+    /// Vote concerns a round that is neither the current one, the previous one, or the next one.
+    UnknownRound,
+    /// Vote has been emitted by a public key that isn't an authority.
+    NotAuthority,
+    /// Public key of the authority is invalid.
+    BadPublicKey,
+    /// Signature of the vote is invalid.
+    BadSignature,
+}
+
+/// Error potentially returned by [`Voter::inject_catch_up`].
+#[derive(Debug, derive_more::Display)]
+pub enum InjectCatchUpError {
+    /// Catch-up concerns a different authorities set.
+    BadSetId,
+    /// Catch-up concerns a round older than the current round.
+    Obsolete,
+    /// One of the votes has been emitted by a public key that isn't an authority.
+    NotAuthority,
+    /// One of the signatures is invalid.
+    BadSignature,
+    /// The votes in the catch-up don't make the round completable. This can also happen if some
+    /// of the blocks voted on aren't known yet.
+    NotCompletable,
+}
+
+/// Verifies the signature of a vote.
+#[allow(clippy::too_many_arguments)]
+fn verify_signature(
+    kind: VoteKind,
+    target_hash: &[u8; 32],
+    target_number: u64,
+    round_number: u64,
+    set_id: u64,
+    block_number_bytes: usize,
+    signature: &[u8; 64],
+    authority_public_key: &[u8; 32],
+) -> Result<(), InjectVoteError> {
+    let public_key = ed25519_zebra::VerificationKey::try_from(*authority_public_key)
+        .map_err(|_| InjectVoteError::BadPublicKey)?;
+    let payload = signed_payload(
+        kind,
+        target_hash,
+        target_number,
+        round_number,
+        set_id,
+        block_number_bytes,
+    );
+    public_key
+        .verify(&ed25519_zebra::Signature::from(*signature), &payload)
+        .map_err(|_| InjectVoteError::BadSignature)
+}
+
+/// Builds the payload that authorities sign when voting.
+fn signed_payload(
+    kind: VoteKind,
+    target_hash: &[u8; 32],
+    target_number: u64,
+    round_number: u64,
+    set_id: u64,
+    block_number_bytes: usize,
+) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(1 + 32 + block_number_bytes + 8 + 8);
+    msg.push(match kind {
+        VoteKind::Prevote => 0u8,
+        VoteKind::Precommit => 1u8,
+        VoteKind::PrimaryPropose => 2u8,
+    });
+    msg.extend_from_slice(target_hash);
+    msg.extend_from_slice(&encode_block_number(target_number, block_number_bytes));
+    msg.extend_from_slice(&round_number.to_le_bytes());
+    msg.extend_from_slice(&set_id.to_le_bytes());
+    msg
+}
+
+/// Encodes a block number using `block_number_bytes` bytes.
+fn encode_block_number(number: u64, block_number_bytes: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(cmp::max(block_number_bytes, mem::size_of_val(&number)));
+    out.extend_from_slice(&number.to_le_bytes());
+    // TODO: unclear what to do if the block number doesn't fit in `block_number_bytes`
+    debug_assert!(!out.iter().skip(block_number_bytes).any(|b| *b != 0));
+    out.resize(block_number_bytes, 0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{finality::justification, header};
+    use core::{num::NonZeroU64, time::Duration};
+
+    fn signing_key(n: u8) -> ed25519_zebra::SigningKey {
+        ed25519_zebra::SigningKey::from([n; 32])
+    }
+
+    fn public_key(n: u8) -> [u8; 32] {
+        ed25519_zebra::VerificationKey::from(&signing_key(n)).into()
+    }
+
+    fn vote(
+        n: u8,
+        round_number: u64,
+        kind: super::VoteKind,
+        target_hash: [u8; 32],
+        target_number: u64,
+    ) -> ([u8; 32], [u8; 64]) {
+        let payload = super::signed_payload(kind, &target_hash, target_number, round_number, 0, 4);
+        (public_key(n), signing_key(n).sign(&payload).into())
+    }
+
+    fn new_voter() -> super::Voter<Duration> {
+        new_voter_with_local_votes(Vec::new())
+    }
+
+    fn new_voter_with_local_votes(local_votes: Vec<super::LocalVote>) -> super::Voter<Duration> {
+        let mut voter = super::Voter::new(super::Config {
+            now: Duration::from_secs(0),
+            block_number_bytes: 4,
+            authorities_set_id: 0,
+            authorities: (0..4)
+                .map(|n| header::GrandpaAuthority {
+                    public_key: public_key(n),
+                    weight: NonZeroU64::new(1).unwrap(),
+                })
+                .collect(),
+            local_authority: Some(public_key(0)),
+            finalized_block_hash: [0; 32],
+            finalized_block_number: 0,
+            round_number: 1,
+            local_votes,
+            gossip_duration: Duration::from_secs(1),
+            randomness_seed: [0; 16],
+        });
+
+        voter.block_imported([1; 32], 1, [0; 32]);
+        voter.block_imported([2; 32], 2, [1; 32]);
+        voter.block_imported([3; 32], 1, [0; 32]);
+        voter.set_best_block([2; 32]);
+        voter
+    }
+
+    fn inject_remote_vote(
+        voter: &mut super::Voter<Duration>,
+        n: u8,
+        kind: super::VoteKind,
+        target_hash: [u8; 32],
+        target_number: u64,
+    ) -> super::InjectVoteOutcome {
+        let (public_key, signature) = vote(n, 1, kind, target_hash, target_number);
+        let message = match kind {
+            super::VoteKind::Prevote => {
+                super::codec::MessageRef::Prevote(super::codec::UnsignedPrevoteRef {
+                    target_hash: &target_hash,
+                    target_number,
+                })
+            }
+            super::VoteKind::Precommit => {
+                super::codec::MessageRef::Precommit(super::codec::UnsignedPrecommitRef {
+                    target_hash: &target_hash,
+                    target_number,
+                })
+            }
+            super::VoteKind::PrimaryPropose => unreachable!(),
+        };
+
+        voter
+            .inject_vote(&super::codec::VoteMessageRef {
+                round_number: 1,
+                set_id: 0,
+                message,
+                signature: &signature,
+                authority_public_key: &public_key,
+            })
+            .unwrap()
+    }
+
+    fn sign_local_vote(voter: &mut super::Voter<Duration>, action: Option<super::Action>) {
+        let Some(super::Action::Vote(vote)) = action else {
+            panic!()
+        };
+        let signature = signing_key(0).sign(&vote.payload_to_sign()).into();
+        voter.inject_local_vote(vote, signature);
+    }
+
+    #[test]
+    fn basic_round() {
+        let mut voter = new_voter();
+
+        // Not the primary of round 1, and pre-vote timer not elapsed yet.
+        assert!(voter.next_action(&Duration::from_secs(0)).is_none());
+        assert_eq!(voter.next_wake_up(), Some(Duration::from_secs(2)));
+
+        let action = voter.next_action(&Duration::from_secs(2));
+        assert!(matches!(
+            &action,
+            Some(super::Action::Vote(v))
+                if v.kind == super::VoteKind::Prevote && v.target_hash == [2; 32]
+        ));
+        sign_local_vote(&mut voter, action);
+
+        for n in 1..3 {
+            inject_remote_vote(&mut voter, n, super::VoteKind::Prevote, [2; 32], 2);
+        }
+
+        // Round isn't completable, so the local authority waits for the pre-commit timer.
+        assert!(voter.next_action(&Duration::from_secs(3)).is_none());
+        let action = voter.next_action(&Duration::from_secs(4));
+        assert!(matches!(
+            &action,
+            Some(super::Action::Vote(v))
+                if v.kind == super::VoteKind::Precommit && v.target_hash == [2; 32]
+        ));
+        sign_local_vote(&mut voter, action);
+
+        inject_remote_vote(&mut voter, 1, super::VoteKind::Precommit, [2; 32], 2);
+        assert!(voter.next_action(&Duration::from_secs(4)).is_none());
+        inject_remote_vote(&mut voter, 2, super::VoteKind::Precommit, [1; 32], 1);
+
+        let Some(super::Action::Finalized(commit)) = voter.next_action(&Duration::from_secs(4))
+        else {
+            panic!()
+        };
+        assert_eq!(commit.target_hash, [1; 32]);
+        assert_eq!(commit.precommits.len(), 3);
+        assert_eq!(commit.votes_ancestries, vec![[2; 32]]);
+        assert_eq!(voter.finalized_block(), ([1; 32], 1));
+
+        let justification = commit.scale_encoded_justification(4, core::iter::empty());
+        justification::verify::verify(justification::verify::Config {
+            justification: justification::decode::decode_grandpa(&justification, 4).unwrap(),
+            block_number_bytes: 4,
+            authorities_set_id: 0,
+            authorities_list: (0..4)
+                .map(public_key)
+                .collect::<Vec<_>>()
+                .iter()
+                .map(|k| &k[..]),
+            randomness_seed: [0; 32],
+        })
+        .unwrap();
+
+        assert!(matches!(
+            voter.next_action(&Duration::from_secs(5)),
+            Some(super::Action::RoundStarted { round_number: 2 })
+        ));
+        assert_eq!(voter.round_number(), 2);
+    }
+
+    #[test]
+    fn equivocation_detected() {
+        let mut voter = new_voter();
+
+        assert!(matches!(
+            inject_remote_vote(&mut voter, 3, super::VoteKind::Prevote, [2; 32], 2),
+            super::InjectVoteOutcome::Accepted
+        ));
+        assert!(matches!(
+            inject_remote_vote(&mut voter, 3, super::VoteKind::Prevote, [2; 32], 2),
+            super::InjectVoteOutcome::Duplicate
+        ));
+        assert!(matches!(
+            inject_remote_vote(&mut voter, 3, super::VoteKind::Prevote, [3; 32], 1),
+            super::InjectVoteOutcome::Equivocation(super::Equivocation { first, second, .. })
+                if first == ([2; 32], 2) && second == ([3; 32], 1)
+        ));
+    }
+
+    #[test]
+    fn bad_signature_rejected() {
+        let mut voter = new_voter();
+        let (public_key, signature) = vote(1, 1, super::VoteKind::Prevote, [2; 32], 2);
+
+        let result = voter.inject_vote(&super::codec::VoteMessageRef {
+            round_number: 1,
+            set_id: 0,
+            message: super::codec::MessageRef::Prevote(super::codec::UnsignedPrevoteRef {
+                target_hash: &[1; 32],
+                target_number: 1,
+            }),
+            signature: &signature,
+            authority_public_key: &public_key,
+        });
+        assert!(matches!(result, Err(super::InjectVoteError::BadSignature)));
+    }
+
+    #[test]
+    fn local_votes_not_cast_again() {
+        let (authority_public_key, signature) = vote(0, 1, super::VoteKind::Prevote, [3; 32], 1);
+        let mut voter = new_voter_with_local_votes(vec![super::LocalVote {
+            round_number: 1,
+            kind: super::VoteKind::Prevote,
+            target_hash: [3; 32],
+            target_number: 1,
+            authority_public_key,
+            signature,
+        }]);
+
+        // The pre-vote has already been cast before the restart.
+        assert!(voter.next_action(&Duration::from_secs(2)).is_none());
+
+        // The previous pre-vote counts towards the round.
+        for n in 1..3 {
+            inject_remote_vote(&mut voter, n, super::VoteKind::Prevote, [3; 32], 1);
+        }
+        let action = voter.next_action(&Duration::from_secs(4));
+        assert!(matches!(
+            &action,
+            Some(super::Action::Vote(v))
+                if v.kind == super::VoteKind::Precommit && v.target_hash == [3; 32]
+        ));
+    }
+
+    #[test]
+    fn resumes_at_latest_local_vote_round() {
+        let (authority_public_key, signature) = vote(0, 5, super::VoteKind::Precommit, [2; 32], 2);
+        let mut voter = new_voter_with_local_votes(vec![super::LocalVote {
+            round_number: 5,
+            kind: super::VoteKind::Precommit,
+            target_hash: [2; 32],
+            target_number: 2,
+            authority_public_key,
+            signature,
+        }]);
+
+        assert_eq!(voter.round_number(), 5);
+        assert!(voter.next_action(&Duration::from_secs(10)).is_none());
+        assert_eq!(voter.next_wake_up(), None);
+    }
+}
@@ -74,7 +74,10 @@ impl KeyNamespace {
         .into_iter()
     }
 
-    fn from_string(str: &str) -> Option<Self> {
+    /// Parses the four-characters identifier of the namespace, such as `aura` or `gran`.
+    ///
+    /// This is the opposite of [`KeyNamespace::as_string`].
+    pub fn from_string(str: &str) -> Option<Self> {
         match str {
             "aura" => Some(KeyNamespace::Aura),
             "audi" => Some(KeyNamespace::AuthorityDiscovery),
@@ -85,7 +88,9 @@ impl KeyNamespace {
         }
     }
 
-    fn as_string(&self) -> &'static str {
+    /// Returns the four-characters identifier of the namespace, such as `aura` or `gran`. This
+    /// identifier is also known as the "key type" in Substrate.
+    pub fn as_string(&self) -> &'static str {
         match self {
             KeyNamespace::Aura => "aura",
             KeyNamespace::AuthorityDiscovery => "audi",
@@ -304,6 +309,104 @@ impl Keystore {
         Ok(public_key)
     }
 
+    /// Inserts in the keystore the Ed25519 private key described by the given seed phrase.
+    ///
+    /// If `save` is `true`, the seed phrase is saved in the file system. The value of `save` is
+    /// silently ignored if no path was provided to [`Keystore::new`].
+    ///
+    /// Returns the corresponding public key.
+    pub async fn insert_ed25519_phrase(
+        &self,
+        namespace: KeyNamespace,
+        phrase: &str,
+        save: bool,
+    ) -> Result<[u8; 32], InsertPhraseError> {
+        let mut private_key =
+            seed_phrase::decode_ed25519_private_key(phrase).map_err(InsertPhraseError::Phrase)?;
+        let private_key_zebra =
+            zeroize::Zeroizing::new(ed25519_zebra::SigningKey::from(*private_key));
+        zeroize::Zeroize::zeroize(&mut *private_key);
+        let public_key: [u8; 32] = ed25519_zebra::VerificationKey::from(&*private_key_zebra).into();
+
+        let mut guarded = self.guarded.lock().await;
+
+        let save_path = if save {
+            self.path_of_key_ed25519(namespace, &public_key)
+        } else {
+            None
+        };
+
+        if let Some(save_path) = save_path {
+            // If the file already exists, it necessarily contains the same key.
+            if !save_path.try_exists().map_err(InsertPhraseError::Io)? {
+                Self::write_to_file(&save_path, phrase.as_bytes())
+                    .await
+                    .map_err(InsertPhraseError::Io)?;
+            }
+            guarded
+                .keys
+                .insert((namespace, public_key), PrivateKey::FileEd25519);
+        } else {
+            guarded.keys.insert(
+                (namespace, public_key),
+                PrivateKey::MemoryEd25519(private_key_zebra),
+            );
+        }
+
+        Ok(public_key)
+    }
+
+    /// Inserts in the keystore the Sr25519 private key described by the given seed phrase.
+    ///
+    /// If `save` is `true`, the seed phrase is saved in the file system. The value of `save` is
+    /// silently ignored if no path was provided to [`Keystore::new`].
+    ///
+    /// Returns the corresponding public key.
+    pub async fn insert_sr25519_phrase(
+        &self,
+        namespace: KeyNamespace,
+        phrase: &str,
+        save: bool,
+    ) -> Result<[u8; 32], InsertPhraseError> {
+        let mut private_key =
+            seed_phrase::decode_sr25519_private_key(phrase).map_err(InsertPhraseError::Phrase)?;
+        // `from_bytes` only panics if the key is of the wrong length, which we know can't
+        // happen here.
+        let keypair: zeroize::Zeroizing<schnorrkel::Keypair> = zeroize::Zeroizing::new(
+            schnorrkel::SecretKey::from_bytes(&*private_key)
+                .unwrap()
+                .into(),
+        );
+        zeroize::Zeroize::zeroize(&mut *private_key);
+        let public_key = keypair.public.to_bytes();
+
+        let mut guarded = self.guarded.lock().await;
+
+        let save_path = if save {
+            self.path_of_key_sr25519(namespace, &public_key)
+        } else {
+            None
+        };
+
+        if let Some(save_path) = save_path {
+            // If the file already exists, it necessarily contains the same key.
+            if !save_path.try_exists().map_err(InsertPhraseError::Io)? {
+                Self::write_to_file(&save_path, phrase.as_bytes())
+                    .await
+                    .map_err(InsertPhraseError::Io)?;
+            }
+            guarded
+                .keys
+                .insert((namespace, public_key), PrivateKey::FileSr25519);
+        } else {
+            guarded
+                .keys
+                .insert((namespace, public_key), PrivateKey::MemorySr25519(keypair));
+        }
+
+        Ok(public_key)
+    }
+
     /// Returns the list of all keys known to this keystore.
     ///
     /// > **Note**: Keep in mind that this function is racy, as keys can be added and removed
@@ -475,9 +578,9 @@ impl Keystore {
                         }
                     }
 
-                    let (_in_out, proof, _) = key.vrf_sign(transcript);
+                    let (in_out, proof, _) = key.vrf_sign(transcript);
                     Ok(VrfSignature {
-                        // TODO: should probably output the `_in_out` as well
+                        output: in_out.to_preout().to_bytes(),
                         proof: proof.to_bytes(),
                     })
                 }
@@ -523,8 +626,9 @@ impl Keystore {
         path: impl AsRef<path::Path>,
         key: &ed25519_zebra::SigningKey,
     ) -> Result<(), io::Error> {
-        let mut phrase = zeroize::Zeroizing::new(vec![0; key.as_ref().len() * 2]);
-        hex::encode_to_slice(key.as_ref(), &mut phrase).unwrap();
+        let mut phrase = zeroize::Zeroizing::new(vec![0; 2 + key.as_ref().len() * 2]);
+        phrase[..2].copy_from_slice(b"0x");
+        hex::encode_to_slice(key.as_ref(), &mut phrase[2..]).unwrap();
         Self::write_to_file(path, &phrase).await
     }
 
@@ -534,8 +638,9 @@ impl Keystore {
     ) -> Result<(), io::Error> {
         // TODO: `to_bytes` isn't zeroize-friendly
         let bytes = key.to_bytes();
-        let mut phrase = zeroize::Zeroizing::new(vec![0; bytes.len() * 2]);
-        hex::encode_to_slice(bytes, &mut phrase).unwrap();
+        let mut phrase = zeroize::Zeroizing::new(vec![0; 2 + bytes.len() * 2]);
+        phrase[..2].copy_from_slice(b"0x");
+        hex::encode_to_slice(bytes, &mut phrase[2..]).unwrap();
         Self::write_to_file(path, &phrase).await
     }
 
@@ -547,7 +652,6 @@ impl Keystore {
         // TODO: proper security flags on Windows?
         #[cfg(target_family = "unix")]
         file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o400))?;
-        io::Write::write_all(&mut file, b"0x")?;
         io::Write::write_all(&mut file, key_phrase)?;
         io::Write::flush(&mut file)?; // This call is generally useless, but doesn't hurt.
         file.sync_all()?;
@@ -609,6 +713,10 @@ struct Guarded {
 }
 
 pub struct VrfSignature {
+    /// Output of the VRF. Can be verified against the public key and the transcript using
+    /// [`VrfSignature::proof`].
+    pub output: [u8; 32],
+    /// Proof that [`VrfSignature::output`] has been generated by the owner of the secret key.
     pub proof: [u8; 64],
 }
 
@@ -624,6 +732,18 @@ pub enum SignError {
     KeyLoad(KeyLoadError),
 }
 
+/// Error potentially returned by [`Keystore::insert_ed25519_phrase`] and
+/// [`Keystore::insert_sr25519_phrase`].
+#[derive(Debug, derive_more::Display)]
+pub enum InsertPhraseError {
+    /// Failed to decode the seed phrase.
+    #[display(fmt = "Invalid seed phrase: {_0}")]
+    Phrase(seed_phrase::ParsePrivateKeyError),
+    /// Error while writing the seed phrase to the file system.
+    #[display(fmt = "{_0}")]
+    Io(io::Error),
+}
+
 #[derive(Debug, derive_more::Display)]
 pub enum KeyLoadError {
     /// Error reported by the operating system.
@@ -737,4 +857,41 @@ mod tests {
                 .is_ok());
         });
     }
+
+    #[test]
+    fn insert_phrase_saved_on_disk() {
+        futures_executor::block_on(async move {
+            let path = tempfile::tempdir().unwrap();
+
+            let keystore1 = Keystore::new(Some(path.path().to_owned()), rand::random())
+                .await
+                .unwrap();
+            let public_key = keystore1
+                .insert_sr25519_phrase(KeyNamespace::Aura, "//Alice", true)
+                .await
+                .unwrap();
+            assert_eq!(
+                public_key,
+                crate::identity::seed_phrase::decode_sr25519_public_key("//Alice").unwrap()
+            );
+            // Inserting the same key a second time is a no-op.
+            keystore1
+                .insert_sr25519_phrase(KeyNamespace::Aura, "//Alice", true)
+                .await
+                .unwrap();
+            drop(keystore1);
+
+            let keystore2 = Keystore::new(Some(path.path().to_owned()), rand::random())
+                .await
+                .unwrap();
+            assert_eq!(
+                keystore2.keys().await.collect::<Vec<_>>(),
+                vec![(KeyNamespace::Aura, public_key)]
+            );
+            assert!(keystore2
+                .sign(KeyNamespace::Aura, &public_key, b"hello world")
+                .await
+                .is_ok());
+        });
+    }
 }
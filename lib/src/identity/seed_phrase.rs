@@ -15,7 +15,11 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString as _},
+    vec::Vec,
+};
 use zeroize::Zeroize as _;
 
 // TODO: unclear what purpose soft derivations serve
@@ -46,7 +50,7 @@ pub fn decode_sr25519_private_key(phrase: &str) -> Result<Box<[u8; 64]>, ParsePr
 
     for junction in parsed.path {
         secret_key = match junction {
-            DeriveJunction::Soft(_) => todo!(), // TODO: return error
+            DeriveJunction::Soft(_) => return Err(ParsePrivateKeyError::SoftDerivation),
             DeriveJunction::Hard(cc) => secret_key
                 .hard_derive_mini_secret_key(Some(schnorrkel::derive::ChainCode(cc)), b"")
                 .0
@@ -71,7 +75,7 @@ pub fn decode_ed25519_private_key(phrase: &str) -> Result<Box<[u8; 32]>, ParsePr
     let mut secret_key = parsed.seed;
     for junction in parsed.path {
         secret_key = match junction {
-            DeriveJunction::Soft(_) => todo!(), // TODO: return error
+            DeriveJunction::Soft(_) => return Err(ParsePrivateKeyError::SoftDerivation),
             DeriveJunction::Hard(cc) => {
                 let mut hash = blake2_rfc::blake2b::Blake2b::new(32);
                 hash.update(crate::util::encode_scale_compact_usize(11).as_ref()); // Length of `"Ed25519HDKD"`
@@ -90,6 +94,27 @@ pub fn decode_ed25519_private_key(phrase: &str) -> Result<Box<[u8; 32]>, ParsePr
     Ok(secret_key)
 }
 
+/// Decodes a human-readable private key (a.k.a. a seed phrase) using the Sr25519 curve and
+/// returns the corresponding public key.
+pub fn decode_sr25519_public_key(phrase: &str) -> Result<[u8; 32], ParsePrivateKeyError> {
+    let mut private_key = decode_sr25519_private_key(phrase)?;
+    // Note: `from_bytes` can only panic if the slice is of the wrong length, which we know can
+    // never happen.
+    let secret_key =
+        zeroize::Zeroizing::new(schnorrkel::SecretKey::from_bytes(&private_key[..]).unwrap());
+    private_key.zeroize();
+    Ok(secret_key.to_public().to_bytes())
+}
+
+/// Decodes a human-readable private key (a.k.a. a seed phrase) using the Ed25519 curve and
+/// returns the corresponding public key.
+pub fn decode_ed25519_public_key(phrase: &str) -> Result<[u8; 32], ParsePrivateKeyError> {
+    let mut private_key = decode_ed25519_private_key(phrase)?;
+    let signing_key = zeroize::Zeroizing::new(ed25519_zebra::SigningKey::from(*private_key));
+    private_key.zeroize();
+    Ok(ed25519_zebra::VerificationKey::from(&*signing_key).into())
+}
+
 /// Turns a human-readable private key (a.k.a. a seed phrase) into a seed and a derivation path.
 pub fn parse_private_key(phrase: &str) -> Result<ParsedPrivateKey, ParsePrivateKeyError> {
     let parse_result: Result<_, nom::Err<nom::error::Error<&str>>> =
@@ -185,9 +210,14 @@ pub struct ParsedPrivateKey {
 #[derive(Debug, derive_more::Display)]
 pub enum ParsePrivateKeyError {
     /// Couldn't parse the string in any meaningful way.
+    #[display(fmt = "Invalid format")]
     InvalidFormat,
     /// Failed to decode the provided BIP39 seed phrase.
+    #[display(fmt = "{_0}")]
     Bip39Decode(Bip39ToSeedError),
+    /// The derivation path contains a soft derivation, which isn't supported.
+    #[display(fmt = "Soft derivations aren't supported")]
+    SoftDerivation,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -271,6 +301,16 @@ pub fn bip39_to_seed(phrase: &str, password: &str) -> Result<Box<[u8; 32]>, Bip3
     Ok(seed)
 }
 
+/// Turns entropy into a BIP39 seed phrase.
+///
+/// The entropy must be between 16 and 32 bytes long, and its length must be a multiple of 4.
+/// The resulting phrase contains 12 words for 16 bytes of entropy, and 24 words for 32 bytes.
+pub fn entropy_to_bip39(entropy: &[u8]) -> Result<String, Bip39DecodeError> {
+    let mnemonic = bip39::Mnemonic::from_entropy_in(bip39::Language::English, entropy)
+        .map_err(Bip39DecodeError)?;
+    Ok(mnemonic.to_string())
+}
+
 /// Failed to decode BIP39 mnemonic phrase.
 #[derive(Debug, derive_more::Display)]
 pub enum Bip39ToSeedError {
@@ -417,4 +457,49 @@ mod tests {
             [95, 205, 122, 218, 56, 195, 127, 158, 30, 205, 82, 84, 159, 120, 105, 63, 210, 155, 217, 74, 40, 142, 70, 179, 11, 75, 82, 143, 219, 208, 86, 245]
         );
     }
+
+    #[test]
+    fn alice_public_keys() {
+        assert_eq!(
+            super::decode_sr25519_public_key("//Alice").unwrap(),
+            [
+                212, 53, 147, 199, 21, 253, 211, 28, 97, 20, 26, 189, 4, 169, 159, 214, 130, 44,
+                133, 88, 133, 76, 205, 227, 154, 86, 132, 231, 165, 109, 162, 125
+            ]
+        );
+        assert_eq!(
+            super::decode_ed25519_public_key("//Alice").unwrap(),
+            [
+                136, 220, 52, 23, 213, 5, 142, 196, 180, 80, 62, 12, 18, 234, 26, 10, 137, 190, 32,
+                15, 233, 137, 34, 66, 61, 67, 52, 1, 79, 166, 176, 238
+            ]
+        );
+    }
+
+    #[test]
+    fn soft_derivation_is_error() {
+        assert!(matches!(
+            super::decode_sr25519_private_key("//Alice/soft"),
+            Err(super::ParsePrivateKeyError::SoftDerivation)
+        ));
+        assert!(matches!(
+            super::decode_ed25519_private_key("//Alice/soft"),
+            Err(super::ParsePrivateKeyError::SoftDerivation)
+        ));
+    }
+
+    #[test]
+    fn entropy_to_bip39_round_trip() {
+        assert_eq!(
+            super::entropy_to_bip39(&[0; 16]).unwrap(),
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+            abandon about"
+        );
+
+        let phrase = super::entropy_to_bip39(&[0x5a; 32]).unwrap();
+        assert_eq!(phrase.split(' ').count(), 24);
+        assert!(super::bip39_to_seed(&phrase, "").is_ok());
+
+        assert!(super::entropy_to_bip39(&[0; 15]).is_err());
+    }
 }
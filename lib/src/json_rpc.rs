@@ -65,3 +65,4 @@ pub mod methods;
 pub mod parse;
 pub mod payment_info;
 pub mod service;
+pub mod session_keys;
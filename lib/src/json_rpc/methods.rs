@@ -386,9 +386,9 @@ define_methods! {
     MethodCall,
     Response<'a>,
     account_nextIndex() -> (), // TODO:
-    author_hasKey() -> (), // TODO:
-    author_hasSessionKeys() -> (), // TODO:
-    author_insertKey() -> (), // TODO:
+    author_hasKey(#[rename = "publicKey"] public_key: HexString, #[rename = "keyType"] key_type: Cow<'a, str>) -> bool,
+    author_hasSessionKeys(#[rename = "sessionKeys"] session_keys: HexString) -> bool,
+    author_insertKey(#[rename = "keyType"] key_type: Cow<'a, str>, suri: Cow<'a, str>, #[rename = "publicKey"] public_key: HexString) -> (),
     author_pendingExtrinsics() -> Vec<HexString>,  // TODO: what does the returned value mean?
     author_removeExtrinsic() -> (), // TODO:
     author_rotateKeys() -> HexString,
@@ -421,7 +421,7 @@ define_methods! {
     state_getKeysPaged(prefix: Option<HexString>, count: u32, start_key: Option<HexString>, hash: Option<HashHexString>) -> Vec<HexString> [state_getKeysPagedAt],
     state_getMetadata(hash: Option<HashHexString>) -> HexString,
     state_getPairs() -> (), // TODO:
-    state_getReadProof() -> (), // TODO:
+    state_getReadProof(keys: Vec<HexString>, at: Option<HashHexString>) -> ReadProof,
     state_getRuntimeVersion(at: Option<HashHexString>) -> RuntimeVersion<'a> [chain_getRuntimeVersion],
     state_getStorage(key: HexString, hash: Option<HashHexString>) -> HexString [state_getStorageAt],
     state_getStorageHash() -> () [state_getStorageHashAt], // TODO:
@@ -736,7 +736,10 @@ pub enum FollowEvent<'a> {
         operation_id: Cow<'a, str>,
     },
     #[serde(rename = "operationWaitingForContinue")]
-    OperationWaitingForContinue,
+    OperationWaitingForContinue {
+        #[serde(rename = "operationId")]
+        operation_id: Cow<'a, str>,
+    },
     #[serde(rename = "operationError")]
     OperationError {
         #[serde(rename = "operationId")]
@@ -1061,6 +1064,12 @@ pub enum DispatchClass {
     Mandatory,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReadProof {
+    pub at: HashHexString,
+    pub proof: Vec<HexString>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct StorageChangeSet {
     pub block: HashHexString,
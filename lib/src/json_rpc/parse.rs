@@ -17,7 +17,7 @@
 
 //! Parse JSON-RPC method calls and notifications, and build responses messages.
 
-use alloc::{borrow::Cow, string::String};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 
 /// Parses a JSON-encoded RPC method call or notification.
 pub fn parse_request(request_json: &str) -> Result<Request, ParseError> {
@@ -45,6 +45,32 @@ pub fn parse_request(request_json: &str) -> Result<Request, ParseError> {
     })
 }
 
+/// Parses a JSON-encoded batch of RPC method calls and notifications.
+///
+/// On success, returns the JSON-formatted list of the individual elements of the batch, in the
+/// same order as in the batch. Each element must then be parsed individually with
+/// [`parse_request`].
+///
+/// Returns an error if `batch_json` isn't a JSON array, in which case it might be a single
+/// request instead.
+///
+/// # Example
+///
+/// ```
+/// # use smoldot::json_rpc::parse;
+/// let items = parse::parse_batch(
+///     r#"[{"jsonrpc":"2.0","id":1,"method":"foo"}, {"jsonrpc":"2.0","method":"bar"}]"#
+/// ).unwrap();
+/// assert_eq!(items.len(), 2);
+/// assert_eq!(parse::parse_request(items[1]).unwrap().method, "bar");
+/// ```
+///
+pub fn parse_batch(batch_json: &str) -> Result<Vec<&str>, ParseError> {
+    let items: Vec<&serde_json::value::RawValue> =
+        serde_json::from_str(batch_json).map_err(ParseError)?;
+    Ok(items.into_iter().map(|item| item.get()).collect())
+}
+
 /// Parses a JSON-encoded RPC response.
 pub fn parse_response(response_json: &str) -> Result<Response, ParseError> {
     let error = match serde_json::from_str::<SerdeSuccess>(response_json) {
@@ -306,6 +332,44 @@ pub fn build_error_response(
     .unwrap()
 }
 
+/// Builds a JSON response to a batch of requests.
+///
+/// Each item of `responses_json` must be a JSON-formatted response, as returned by for example
+/// [`build_success_response`] or [`build_error_response`].
+///
+/// # Example
+///
+/// ```
+/// # use smoldot::json_rpc::parse;
+/// let response = parse::build_batch_response([
+///     parse::build_success_response("1", "true"),
+///     parse::build_success_response("2", "false"),
+/// ].iter());
+///
+/// assert_eq!(
+///     response,
+///     r#"[{"jsonrpc":"2.0","id":1,"result":true},{"jsonrpc":"2.0","id":2,"result":false}]"#
+/// );
+/// ```
+///
+/// > **Note**: According to the JSON-RPC specification, no response must be sent back if none
+/// >           of the elements of a batch expect a response. This function doesn't enforce this
+/// >           rule, and it is the responsibility of the caller to not call this function with
+/// >           an empty list of responses.
+///
+pub fn build_batch_response(responses_json: impl Iterator<Item = impl AsRef<str>>) -> String {
+    let mut out = String::with_capacity(256);
+    out.push('[');
+    for (index, response) in responses_json.enumerate() {
+        if index != 0 {
+            out.push(',');
+        }
+        out.push_str(response.as_ref());
+    }
+    out.push(']');
+    out
+}
+
 /// Error that can be reported to the JSON-RPC client.
 #[derive(Debug)]
 pub enum ErrorResponse<'a> {
@@ -669,6 +733,30 @@ mod tests {
         });
     }
 
+    #[test]
+    fn parse_batch_basic_works() {
+        let items = super::parse_batch(
+            r#"[{"jsonrpc":"2.0","id":5,"method":"foo","params":[]}, 1, {"jsonrpc":"2.0","method":"bar"}]"#,
+        )
+        .unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(super::parse_request(items[0]).unwrap().method, "foo");
+        assert!(super::parse_request(items[1]).is_err());
+        assert!(super::parse_request(items[2]).unwrap().id_json.is_none());
+    }
+
+    #[test]
+    fn parse_batch_empty() {
+        assert!(super::parse_batch("[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_batch_not_an_array() {
+        assert!(
+            super::parse_batch(r#"{"jsonrpc":"2.0","id":5,"method":"foo","params":[]}"#).is_err()
+        );
+    }
+
     #[test]
     fn build_parse_error() {
         let response = super::build_parse_error_response();
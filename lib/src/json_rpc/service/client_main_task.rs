@@ -24,6 +24,7 @@ use alloc::{
     collections::VecDeque,
     string::{String, ToString as _},
     sync::{Arc, Weak},
+    vec::Vec,
 };
 use async_lock::Mutex;
 use core::{
@@ -61,6 +62,17 @@ struct Inner {
     /// Maximum size that [`Inner::active_subscriptions`] is allowed to reach. Beyond this,
     /// subscription start requests are automatically denied.
     max_active_subscriptions: u32,
+    /// See [`Config::subscriptions_supported`].
+    subscriptions_supported: bool,
+
+    /// List of batches of requests whose individual responses are being collected. Once all the
+    /// responses of a batch have been collected, they are merged into one and sent back to the
+    /// JSON-RPC client.
+    batches: Slab<InnerBatch>,
+
+    /// Requests that have been extracted from a batch and that haven't been processed yet,
+    /// alongside with the index of their batch within [`Inner::batches`].
+    pending_batch_requests: VecDeque<(String, usize)>,
 
     /// Structure shared with the [`SerializedRequestsIo`].
     serialized_io: Arc<SerializedIo>,
@@ -75,8 +87,17 @@ struct Inner {
 struct InnerSubscription {
     /// Shared with the subscription. Used to notify the subscription that it should be killed.
     kill_channel: Arc<SubscriptionKillChannel>,
-    /// Response to an unsubscribe request that must be sent out once the subscription is killed.
-    unsubscribe_response: Option<String>,
+    /// Response to an unsubscribe request that must be sent out once the subscription is killed,
+    /// and index within [`Inner::batches`] of the batch the unsubscribe request belongs to, if
+    /// any.
+    unsubscribe_response: Option<(String, Option<usize>)>,
+}
+
+struct InnerBatch {
+    /// Responses to the requests of the batch that have been collected so far.
+    responses: Vec<String>,
+    /// Number of requests of the batch whose response hasn't been collected yet.
+    num_pending_responses: usize,
 }
 
 struct SerializedIo {
@@ -138,9 +159,15 @@ struct ResponsesNotificationsQueue {
 
 // TODO: weird enum
 enum ToMainTask {
-    RequestResponse(String),
+    RequestResponse {
+        response: String,
+        /// Index within [`Inner::batches`] of the batch the request belongs to, if any.
+        batch: Option<usize>,
+    },
     Notification(String),
-    SubscriptionDestroyed { subscription_id: String },
+    SubscriptionDestroyed {
+        subscription_id: String,
+    },
 }
 
 /// Configuration for [`client_main_task`].
@@ -155,6 +182,11 @@ pub struct Config {
     /// Maximum number of simultaneous subscriptions allowed. Trying to create a subscription will
     /// be automatically rejected if this limit is reached.
     pub max_active_subscriptions: u32,
+
+    /// If `false`, the transport can't deliver notifications, as is the case for plain HTTP.
+    /// Trying to create a subscription is then automatically rejected with an error indicating
+    /// that subscriptions aren't available over HTTP.
+    pub subscriptions_supported: bool,
 }
 
 /// Creates a new [`ClientMainTask`] and a [`SerializedRequestsIo`] connected to it.
@@ -178,6 +210,9 @@ pub fn client_main_task(config: Config) -> (ClientMainTask, SerializedRequestsIo
                 Default::default(),
             ),
             max_active_subscriptions: config.max_active_subscriptions,
+            subscriptions_supported: config.subscriptions_supported,
+            batches: Slab::new(),
+            pending_batch_requests: VecDeque::new(),
             serialized_io: Arc::new(SerializedIo {
                 requests_queue: crossbeam_queue::SegQueue::new(),
                 on_request_pushed: event_listener::Event::new(),
@@ -219,11 +254,16 @@ impl ClientMainTask {
     pub async fn run_until_event(mut self) -> Event {
         loop {
             enum WakeUpReason {
-                NewRequest(String),
+                NewRequest(String, Option<usize>),
                 Message(ToMainTask),
             }
 
-            let wake_up_reason = {
+            let wake_up_reason = if let Some((request, batch_index)) =
+                self.inner.pending_batch_requests.pop_front()
+            {
+                // Requests extracted from a batch are processed before anything else.
+                WakeUpReason::NewRequest(request, Some(batch_index))
+            } else {
                 let serialized_requests_io_destroyed = async {
                     (&mut self.inner.on_serialized_requests_io_destroyed).await;
                     Err(())
@@ -237,7 +277,7 @@ impl ClientMainTask {
                                 .serialized_io
                                 .on_request_pulled_or_task_destroyed
                                 .notify(usize::max_value());
-                            break Ok(WakeUpReason::NewRequest(elem));
+                            break Ok(WakeUpReason::NewRequest(elem, None));
                         }
                         if let Some(wait) = wait.take() {
                             wait.await
@@ -273,8 +313,8 @@ impl ClientMainTask {
             };
 
             // Immediately handle every event apart from `NewRequest`.
-            let new_request = match wake_up_reason {
-                WakeUpReason::NewRequest(request) => request,
+            let (new_request, batch) = match wake_up_reason {
+                WakeUpReason::NewRequest(request, batch) => (request, batch),
                 WakeUpReason::Message(ToMainTask::SubscriptionDestroyed { subscription_id }) => {
                     let InnerSubscription {
                         unsubscribe_response,
@@ -285,19 +325,8 @@ impl ClientMainTask {
                         .remove(&subscription_id)
                         .unwrap();
                     // TODO: post a `stop`/`error` event for chainhead subscriptions
-                    if let Some(unsubscribe_response) = unsubscribe_response {
-                        let mut responses_queue =
-                            self.inner.serialized_io.responses_queue.lock().await;
-                        let pos = responses_queue
-                            .pending_serialized_responses
-                            .insert((unsubscribe_response, true));
-                        responses_queue
-                            .pending_serialized_responses_queue
-                            .push_back(pos);
-                        self.inner
-                            .serialized_io
-                            .on_response_pushed_or_task_destroyed
-                            .notify(usize::max_value());
+                    if let Some((unsubscribe_response, batch)) = unsubscribe_response {
+                        self.inner.push_response(unsubscribe_response, batch).await;
                     }
 
                     // Shrink the list of active subscriptions if necessary.
@@ -312,18 +341,8 @@ impl ClientMainTask {
                         subscription_id,
                     };
                 }
-                WakeUpReason::Message(ToMainTask::RequestResponse(response)) => {
-                    let mut responses_queue = self.inner.serialized_io.responses_queue.lock().await;
-                    let pos = responses_queue
-                        .pending_serialized_responses
-                        .insert((response, true));
-                    responses_queue
-                        .pending_serialized_responses_queue
-                        .push_back(pos);
-                    self.inner
-                        .serialized_io
-                        .on_response_pushed_or_task_destroyed
-                        .notify(usize::max_value());
+                WakeUpReason::Message(ToMainTask::RequestResponse { response, batch }) => {
+                    self.inner.push_response(response, batch).await;
                     continue;
                 }
                 WakeUpReason::Message(ToMainTask::Notification(notification)) => {
@@ -343,40 +362,28 @@ impl ClientMainTask {
                 }
             };
 
+            // Batches are split into individual requests, which are then processed one by one.
+            // Note that batches within batches are invalid, and would have been detected as
+            // such when splitting the parent batch.
+            if batch.is_none() {
+                if let Ok(items) = parse::parse_batch(&new_request) {
+                    self.inner.start_batch(items).await;
+                    continue;
+                }
+            }
+
             let (request_id, parsed_request) =
                 match methods::parse_jsonrpc_client_to_server(&new_request) {
                     Ok((request_id, method)) => (request_id, method),
                     Err(methods::ParseClientToServerError::Method { request_id, error }) => {
                         let response = error.to_json_error(request_id);
-                        let mut responses_queue =
-                            self.inner.serialized_io.responses_queue.lock().await;
-                        let pos = responses_queue
-                            .pending_serialized_responses
-                            .insert((response, true));
-                        responses_queue
-                            .pending_serialized_responses_queue
-                            .push_back(pos);
-                        self.inner
-                            .serialized_io
-                            .on_response_pushed_or_task_destroyed
-                            .notify(usize::max_value());
+                        self.inner.push_response(response, batch).await;
                         continue;
                     }
                     Err(methods::ParseClientToServerError::UnknownNotification(_)) => continue,
                     Err(methods::ParseClientToServerError::JsonRpcParse(_)) => {
                         let response = parse::build_parse_error_response();
-                        let mut responses_queue =
-                            self.inner.serialized_io.responses_queue.lock().await;
-                        let pos = responses_queue
-                            .pending_serialized_responses
-                            .insert((response, true));
-                        responses_queue
-                            .pending_serialized_responses_queue
-                            .push_back(pos);
-                        self.inner
-                            .serialized_io
-                            .on_response_pushed_or_task_destroyed
-                            .notify(usize::max_value());
+                        self.inner.push_response(response, batch).await;
                         continue;
                     }
                 };
@@ -458,6 +465,7 @@ impl ClientMainTask {
                                 .responses_notifications_queue
                                 .clone(),
                             request: new_request,
+                            batch,
                             has_sent_response: false,
                         },
                         task: self,
@@ -475,6 +483,19 @@ impl ClientMainTask {
                 | methods::MethodCall::chainHead_unstable_follow { .. } => {
                     // Subscription starting requests.
 
+                    if !self.inner.subscriptions_supported {
+                        let response = parse::build_error_response(
+                            request_id,
+                            ErrorResponse::ServerError(
+                                -32000,
+                                "Subscriptions aren't available over HTTP. Use a WebSocket connection instead.",
+                            ),
+                            None,
+                        );
+                        self.inner.push_response(response, batch).await;
+                        continue;
+                    }
+
                     // We must check the maximum number of subscriptions.
                     let max_subscriptions = usize::try_from(self.inner.max_active_subscriptions)
                         .unwrap_or(usize::max_value());
@@ -485,18 +506,7 @@ impl ClientMainTask {
                             ErrorResponse::ServerError(-32000, "Too many active subscriptions"),
                             None,
                         );
-                        let mut responses_queue =
-                            self.inner.serialized_io.responses_queue.lock().await;
-                        let pos = responses_queue
-                            .pending_serialized_responses
-                            .insert((response, true));
-                        responses_queue
-                            .pending_serialized_responses_queue
-                            .push_back(pos);
-                        self.inner
-                            .serialized_io
-                            .on_response_pushed_or_task_destroyed
-                            .notify(usize::max_value());
+                        self.inner.push_response(response, batch).await;
                         continue;
                     }
 
@@ -529,6 +539,7 @@ impl ClientMainTask {
                                 .responses_notifications_queue
                                 .clone(),
                             request: new_request,
+                            batch,
                             kill_channel,
                             subscription_id,
                             has_sent_response: false,
@@ -554,7 +565,7 @@ impl ClientMainTask {
                             kill_channel,
                             unsubscribe_response,
                         }) if unsubscribe_response.is_none() => {
-                            *unsubscribe_response = Some(
+                            *unsubscribe_response = Some((
                                 match parsed_request {
                                     methods::MethodCall::author_unwatchExtrinsic { .. } => {
                                         methods::Response::author_unwatchExtrinsic(true)
@@ -577,7 +588,8 @@ impl ClientMainTask {
                                     _ => unreachable!(),
                                 }
                                 .to_json_response(request_id),
-                            );
+                                batch,
+                            ));
 
                             kill_channel.dead.store(true, Ordering::Release);
                             kill_channel.on_dead_changed.notify(usize::max_value());
@@ -603,18 +615,7 @@ impl ClientMainTask {
                                 ),
                             };
 
-                            let mut responses_queue =
-                                self.inner.serialized_io.responses_queue.lock().await;
-                            let pos = responses_queue
-                                .pending_serialized_responses
-                                .insert((response, true));
-                            responses_queue
-                                .pending_serialized_responses_queue
-                                .push_back(pos);
-                            self.inner
-                                .serialized_io
-                                .on_response_pushed_or_task_destroyed
-                                .notify(usize::max_value());
+                            self.inner.push_response(response, batch).await;
                         }
                     }
                 }
@@ -628,21 +629,24 @@ impl ClientMainTask {
                             unsubscribe_response,
                             kill_channel,
                         }) if unsubscribe_response.is_none() => {
-                            *unsubscribe_response = Some(match parsed_request {
-                                methods::MethodCall::chain_unsubscribeAllHeads { .. } => {
-                                    methods::Response::chain_unsubscribeAllHeads(true)
-                                        .to_json_response(request_id)
-                                }
-                                methods::MethodCall::chain_unsubscribeFinalizedHeads { .. } => {
-                                    methods::Response::chain_unsubscribeFinalizedHeads(true)
-                                        .to_json_response(request_id)
-                                }
-                                methods::MethodCall::chain_unsubscribeNewHeads { .. } => {
-                                    methods::Response::chain_unsubscribeNewHeads(true)
-                                        .to_json_response(request_id)
-                                }
-                                _ => unreachable!(),
-                            });
+                            *unsubscribe_response = Some((
+                                match parsed_request {
+                                    methods::MethodCall::chain_unsubscribeAllHeads { .. } => {
+                                        methods::Response::chain_unsubscribeAllHeads(true)
+                                            .to_json_response(request_id)
+                                    }
+                                    methods::MethodCall::chain_unsubscribeFinalizedHeads {
+                                        ..
+                                    } => methods::Response::chain_unsubscribeFinalizedHeads(true)
+                                        .to_json_response(request_id),
+                                    methods::MethodCall::chain_unsubscribeNewHeads { .. } => {
+                                        methods::Response::chain_unsubscribeNewHeads(true)
+                                            .to_json_response(request_id)
+                                    }
+                                    _ => unreachable!(),
+                                },
+                                batch,
+                            ));
 
                             kill_channel.dead.store(true, Ordering::Release);
                             kill_channel.on_dead_changed.notify(usize::max_value());
@@ -664,18 +668,7 @@ impl ClientMainTask {
                                 _ => unreachable!(),
                             };
 
-                            let mut responses_queue =
-                                self.inner.serialized_io.responses_queue.lock().await;
-                            let pos = responses_queue
-                                .pending_serialized_responses
-                                .insert((response, true));
-                            responses_queue
-                                .pending_serialized_responses_queue
-                                .push_back(pos);
-                            self.inner
-                                .serialized_io
-                                .on_response_pushed_or_task_destroyed
-                                .notify(usize::max_value());
+                            self.inner.push_response(response, batch).await;
                         }
                     }
                 }
@@ -690,6 +683,118 @@ impl ClientMainTask {
     }
 }
 
+impl Inner {
+    /// Splits a batch of requests into individual requests that are queued for processing.
+    ///
+    /// `items` must be the JSON-formatted elements of the batch, as returned by
+    /// [`parse::parse_batch`].
+    async fn start_batch(&mut self, items: Vec<&str>) {
+        // According to the JSON-RPC specification, an empty batch must lead to a single error
+        // response.
+        if items.is_empty() {
+            let response = parse::build_error_response("null", ErrorResponse::InvalidRequest, None);
+            self.push_serialized_response(response).await;
+            return;
+        }
+
+        let batch_index = self.batches.vacant_key();
+        let mut batch = InnerBatch {
+            responses: Vec::with_capacity(items.len()),
+            num_pending_responses: 0,
+        };
+
+        for item in items {
+            match parse::parse_request(item) {
+                Ok(parse::Request {
+                    id_json: Some(_), ..
+                }) => {
+                    batch.num_pending_responses += 1;
+                    self.pending_batch_requests
+                        .push_back((String::from(item), batch_index));
+                }
+                Ok(parse::Request { id_json: None, .. }) => {
+                    // No notification is supported by this server. They are silently ignored,
+                    // like when they aren't part of a batch.
+                }
+                Err(_) => {
+                    batch.responses.push(parse::build_error_response(
+                        "null",
+                        ErrorResponse::InvalidRequest,
+                        None,
+                    ));
+                }
+            }
+        }
+
+        if batch.num_pending_responses == 0 {
+            self.finish_batch(batch).await;
+        } else {
+            let _inserted_index = self.batches.insert(batch);
+            debug_assert_eq!(_inserted_index, batch_index);
+        }
+    }
+
+    /// Sends back the response to a request to the JSON-RPC client, or, if the request belongs
+    /// to a batch, adds it to the responses of this batch.
+    async fn push_response(&mut self, response: String, batch: Option<usize>) {
+        let Some(batch_index) = batch else {
+            self.push_serialized_response(response).await;
+            return;
+        };
+
+        let batch = &mut self.batches[batch_index];
+        batch.responses.push(response);
+        batch.num_pending_responses -= 1;
+
+        if batch.num_pending_responses == 0 {
+            let batch = self.batches.remove(batch_index);
+            self.finish_batch(batch).await;
+
+            // Shrink the list of batches if necessary.
+            if self.batches.capacity() >= 2 * self.batches.len() + 16 {
+                self.batches.shrink_to_fit();
+            }
+        }
+    }
+
+    /// Sends back the responses of a batch whose requests have all been answered.
+    async fn finish_batch(&self, batch: InnerBatch) {
+        debug_assert_eq!(batch.num_pending_responses, 0);
+
+        if batch.responses.is_empty() {
+            // The batch only contains notifications, in which case no response must be sent
+            // back. Since no response will ever be pulled, the number of requests in fly must be
+            // updated here.
+            let _prev_val = self
+                .serialized_io
+                .num_requests_in_fly
+                .fetch_sub(1, Ordering::Release);
+            debug_assert_ne!(_prev_val, 0); // Check underflows.
+            self.serialized_io
+                .on_request_pulled_or_task_destroyed
+                .notify(usize::MAX);
+            return;
+        }
+
+        let response = parse::build_batch_response(batch.responses.iter());
+        self.push_serialized_response(response).await;
+    }
+
+    /// Pushes a response to the queue of responses to send back to the JSON-RPC client.
+    async fn push_serialized_response(&self, response: String) {
+        let mut responses_queue = self.serialized_io.responses_queue.lock().await;
+        let pos = responses_queue
+            .pending_serialized_responses
+            .insert((response, true));
+        responses_queue
+            .pending_serialized_responses_queue
+            .push_back(pos);
+        self.serialized_io
+            .on_response_pushed_or_task_destroyed
+            .notify(usize::MAX);
+    }
+}
+
 impl fmt::Debug for ClientMainTask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("ClientMainTask").finish()
@@ -755,6 +860,10 @@ pub enum Event {
 
 /// Object connected to the [`ClientMainTask`] that allows sending requests to the task and
 /// receiving responses.
+///
+/// Requests can also be JSON-RPC batches, in which case the elements of the batch are processed
+/// individually and their responses are merged into one. Note that notifications of a
+/// subscription started within a batch might be sent back before the response to the batch.
 pub struct SerializedRequestsIo {
     serialized_io: Weak<SerializedIo>,
 
@@ -983,6 +1092,8 @@ pub struct RequestProcess {
     responses_notifications_queue: Arc<ResponsesNotificationsQueue>,
     /// Request in JSON form. Guaranteed to decode successfully.
     request: String,
+    /// Index within [`Inner::batches`] of the batch the request belongs to, if any.
+    batch: Option<usize>,
     /// `true` if a response has already been sent.
     has_sent_response: bool,
 }
@@ -1008,7 +1119,10 @@ impl RequestProcess {
         let serialized = response.to_json_response(request_id);
         self.responses_notifications_queue
             .queue
-            .push(ToMainTask::RequestResponse(serialized));
+            .push(ToMainTask::RequestResponse {
+                response: serialized,
+                batch: self.batch,
+            });
         self.responses_notifications_queue
             .on_pushed
             .notify(usize::max_value());
@@ -1026,7 +1140,10 @@ impl RequestProcess {
         let serialized = parse::build_success_response(request_id, "null");
         self.responses_notifications_queue
             .queue
-            .push(ToMainTask::RequestResponse(serialized));
+            .push(ToMainTask::RequestResponse {
+                response: serialized,
+                batch: self.batch,
+            });
         self.responses_notifications_queue
             .on_pushed
             .notify(usize::max_value());
@@ -1043,7 +1160,10 @@ impl RequestProcess {
         let serialized = parse::build_error_response(request_id, error, None);
         self.responses_notifications_queue
             .queue
-            .push(ToMainTask::RequestResponse(serialized));
+            .push(ToMainTask::RequestResponse {
+                response: serialized,
+                batch: self.batch,
+            });
         self.responses_notifications_queue
             .on_pushed
             .notify(usize::max_value());
@@ -1063,7 +1183,10 @@ impl RequestProcess {
         let serialized = parse::build_error_response(request_id, error, Some(json));
         self.responses_notifications_queue
             .queue
-            .push(ToMainTask::RequestResponse(serialized));
+            .push(ToMainTask::RequestResponse {
+                response: serialized,
+                batch: self.batch,
+            });
         self.responses_notifications_queue
             .on_pushed
             .notify(usize::max_value());
@@ -1087,7 +1210,10 @@ impl Drop for RequestProcess {
                 parse::build_error_response(request_id, ErrorResponse::InternalError, None);
             self.responses_notifications_queue
                 .queue
-                .push(ToMainTask::RequestResponse(serialized));
+                .push(ToMainTask::RequestResponse {
+                    response: serialized,
+                    batch: self.batch,
+                });
             self.responses_notifications_queue
                 .on_pushed
                 .notify(usize::max_value());
@@ -1108,6 +1234,8 @@ pub struct SubscriptionStartProcess {
     kill_channel: Arc<SubscriptionKillChannel>,
     /// Request in JSON form. Guaranteed to decode successfully.
     request: String,
+    /// Index within [`Inner::batches`] of the batch the request belongs to, if any.
+    batch: Option<usize>,
     /// Identifier of the subscription. Assigned by the client task.
     subscription_id: String,
     /// `true` if a response has already been sent.
@@ -1178,7 +1306,10 @@ impl SubscriptionStartProcess {
 
         self.responses_notifications_queue
             .queue
-            .push(ToMainTask::RequestResponse(serialized_response));
+            .push(ToMainTask::RequestResponse {
+                response: serialized_response,
+                batch: self.batch,
+            });
         self.responses_notifications_queue
             .on_pushed
             .notify(usize::max_value());
@@ -1202,7 +1333,10 @@ impl SubscriptionStartProcess {
         let serialized = parse::build_error_response(request_id, error, None);
         self.responses_notifications_queue
             .queue
-            .push(ToMainTask::RequestResponse(serialized));
+            .push(ToMainTask::RequestResponse {
+                response: serialized,
+                batch: self.batch,
+            });
         self.responses_notifications_queue
             .queue
             .push(ToMainTask::SubscriptionDestroyed {
@@ -1231,7 +1365,10 @@ impl Drop for SubscriptionStartProcess {
                 parse::build_error_response(request_id, ErrorResponse::InternalError, None);
             self.responses_notifications_queue
                 .queue
-                .push(ToMainTask::RequestResponse(serialized));
+                .push(ToMainTask::RequestResponse {
+                    response: serialized,
+                    batch: self.batch,
+                });
             self.responses_notifications_queue
                 .queue
                 .push(ToMainTask::SubscriptionDestroyed {
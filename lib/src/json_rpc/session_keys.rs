@@ -0,0 +1,147 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Encoding and decoding of the runtime calls of the `SessionKeys` API, used by the
+//! `author_rotateKeys` and `author_hasSessionKeys` JSON-RPC functions.
+//!
+//! The "session keys" of a validator are the concatenation of all the public keys that the
+//! runtime requires, such as the Babe key and the Grandpa key. Their exact format is defined
+//! by the runtime and is opaque from the point of view of the client.
+
+use crate::util;
+
+use alloc::vec::Vec;
+use core::iter;
+
+/// Name of the runtime function to call in order to generate a new set of session keys.
+///
+/// The runtime generates the keys through host functions that store them in the keystore.
+pub const GENERATE_SESSION_KEYS_FUNCTION_NAME: &str = "SessionKeys_generate_session_keys";
+
+/// Produces the input to pass to the `SessionKeys_generate_session_keys` runtime call.
+///
+/// If `seed` is `Some`, the runtime passes it to the host functions that generate the keys,
+/// which must derive the keys from it. If `None`, the keys are generated randomly.
+pub fn generate_session_keys_parameters(
+    seed: Option<&'_ [u8]>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + Clone + '_ {
+    // The parameter is a SCALE-encoded `Option<Vec<u8>>`.
+    let (tag, seed) = match seed {
+        Some(seed) => ([1u8], Some(seed)),
+        None => ([0u8], None),
+    };
+
+    iter::once(either::Left(tag)).chain(seed.into_iter().flat_map(|seed| {
+        [
+            either::Right(either::Left(util::encode_scale_compact_usize(seed.len()))),
+            either::Right(either::Right(seed)),
+        ]
+    }))
+}
+
+/// Attempt to decode the output of the `SessionKeys_generate_session_keys` runtime call.
+///
+/// Returns the session keys, in the format expected by `author_hasSessionKeys`.
+pub fn decode_generate_session_keys_output(
+    scale_encoded: &'_ [u8],
+) -> Result<&'_ [u8], DecodeError> {
+    match nom::combinator::all_consuming(util::nom_bytes_decode::<nom::error::Error<&'_ [u8]>>)(
+        scale_encoded,
+    ) {
+        Ok((_, keys)) => Ok(keys),
+        Err(_) => Err(DecodeError()),
+    }
+}
+
+/// Name of the runtime function to call in order to split session keys into individual keys.
+pub const DECODE_SESSION_KEYS_FUNCTION_NAME: &str = "SessionKeys_decode_session_keys";
+
+/// Produces the input to pass to the `SessionKeys_decode_session_keys` runtime call.
+pub fn decode_session_keys_parameters(
+    session_keys: &'_ [u8],
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + Clone + '_ {
+    [
+        either::Left(util::encode_scale_compact_usize(session_keys.len())),
+        either::Right(session_keys),
+    ]
+    .into_iter()
+}
+
+/// Attempt to decode the output of the `SessionKeys_decode_session_keys` runtime call.
+///
+/// Returns `None` if the runtime has indicated that the session keys are invalid. Otherwise,
+/// returns the list of public keys and their key type, such as `b"gran"`.
+pub fn decode_decode_session_keys_output(
+    scale_encoded: &'_ [u8],
+) -> Result<Option<Vec<(&'_ [u8], [u8; 4])>>, DecodeError> {
+    let result: Result<_, nom::Err<nom::error::Error<&'_ [u8]>>> =
+        nom::combinator::all_consuming(util::nom_option_decode(nom::combinator::flat_map(
+            util::nom_scale_compact_usize,
+            |num_keys| {
+                nom::multi::many_m_n(
+                    num_keys,
+                    num_keys,
+                    nom::sequence::tuple((
+                        util::nom_bytes_decode,
+                        nom::combinator::map(nom::bytes::streaming::take(4u32), |key_type| {
+                            <[u8; 4]>::try_from(key_type).unwrap()
+                        }),
+                    )),
+                )
+            },
+        )))(scale_encoded);
+
+    match result {
+        Ok((_, keys)) => Ok(keys),
+        Err(_) => Err(DecodeError()),
+    }
+}
+
+/// Failed to decode the output of a runtime call of the `SessionKeys` API.
+#[derive(Debug, derive_more::Display)]
+#[display(fmt = "Failed to decode the output of the runtime call")]
+pub struct DecodeError();
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn generate_session_keys_parameters() {
+        let encode = |seed| {
+            super::generate_session_keys_parameters(seed).fold(Vec::new(), |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            })
+        };
+
+        assert_eq!(encode(None), vec![0]);
+        assert_eq!(encode(Some(b"//Alice")), b"\x01\x1c//Alice".to_vec());
+    }
+
+    #[test]
+    fn decode_session_keys_output() {
+        assert_eq!(
+            super::decode_decode_session_keys_output(b"\x01\x08\x08\xaa\xbbaura\x04\xccgran")
+                .unwrap(),
+            Some(vec![(&[0xaa, 0xbb][..], *b"aura"), (&[0xcc][..], *b"gran")])
+        );
+        assert_eq!(
+            super::decode_decode_session_keys_output(&[0]).unwrap(),
+            None
+        );
+        assert!(super::decode_decode_session_keys_output(b"\x01\x08\x04\xccgran").is_err());
+    }
+}
@@ -38,6 +38,7 @@
 use crate::{finality, header};
 
 use alloc::vec::Vec;
+use core::iter;
 
 // TODO: all the constraints explained here should be checked when decoding the message
 
@@ -74,6 +75,26 @@ pub struct GrandpaWarpSyncResponseFragment<'a> {
 #[display(fmt = "Failed to decode response")]
 pub struct DecodeGrandpaWarpSyncResponseError;
 
+/// Builds the bytes corresponding to a GrandPa warp sync response.
+pub fn build_grandpa_warp_sync_response(
+    response: GrandpaWarpSyncResponse<'_>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
+    let num_fragments = crate::util::encode_scale_compact_usize(response.fragments.len());
+
+    iter::once(either::Left(num_fragments))
+        .chain(response.fragments.into_iter().flat_map(|fragment| {
+            [
+                either::Right(fragment.scale_encoded_header),
+                either::Right(fragment.scale_encoded_justification),
+            ]
+        }))
+        .chain(iter::once(either::Right(if response.is_finished {
+            &[1][..]
+        } else {
+            &[0][..]
+        })))
+}
+
 /// Decodes a SCALE-encoded GrandPa warp sync response.
 pub fn decode_grandpa_warp_sync_response(
     encoded: &[u8],
@@ -50,6 +50,8 @@
 
 use crate::util::protobuf;
 
+use alloc::vec::Vec;
+
 /// Description of a state request that can be sent to a peer.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct StateRequest<'a> {
@@ -107,6 +109,136 @@ pub fn build_state_request(
         .chain(protobuf::bool_tag_encode(3, false).map(either::Left))
 }
 
+/// Decodes a state request.
+pub fn decode_state_request(
+    request_bytes: &[u8],
+) -> Result<DecodedStateRequest<'_>, DecodeStateRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[required] block = 1 => protobuf::bytes_tag_decode,
+            #[repeated(max = 2)] start = 2 => protobuf::bytes_tag_decode,
+            #[optional] no_proof = 3 => protobuf::bool_tag_decode,
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, rq)) => rq,
+        Err(_) => return Err(DecodeStateRequestError::ProtobufDecode),
+    };
+
+    let block_hash = <&[u8; 32]>::try_from(decoded.block)
+        .map_err(|_| DecodeStateRequestError::InvalidBlockHashLength)?;
+
+    let start_key = match &decoded.start[..] {
+        [] => StateRequestStart::MainTrie(&[]),
+        [key] => StateRequestStart::MainTrie(key),
+        [child_trie, key] => match child_trie.strip_prefix(b":child_storage:default:") {
+            Some(child_trie) => StateRequestStart::ChildTrieDefault { child_trie, key },
+            None => return Err(DecodeStateRequestError::UnsupportedChildTrie),
+        },
+        _ => unreachable!(),
+    };
+
+    Ok(DecodedStateRequest {
+        block_hash,
+        start_key,
+        no_proof: decoded.no_proof.unwrap_or(false),
+    })
+}
+
+/// Decoded state request. See [`decode_state_request`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedStateRequest<'a> {
+    /// Hash of the block to make the request against.
+    pub block_hash: &'a [u8; 32],
+
+    /// Response shouldn't contain any key lexicographically inferior or equal to this key.
+    pub start_key: StateRequestStart<'a>,
+
+    /// If `true`, the response should contain the list of storage entries rather than a Merkle
+    /// proof.
+    pub no_proof: bool,
+}
+
+/// Error potentially returned by [`decode_state_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStateRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Hash of the block isn't 32 bytes.
+    InvalidBlockHashLength,
+    /// Start key refers to a child trie that isn't a default child trie.
+    UnsupportedChildTrie,
+}
+
+/// Response to a state request. See [`build_state_response`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateResponse<'a> {
+    /// Response to a request where [`DecodedStateRequest::no_proof`] is `false`.
+    ///
+    /// Contains a compact Merkle proof, as built by
+    /// [`crate::trie::proof_encode::build_compact_multiple`].
+    Proof(&'a [u8]),
+
+    /// Response to a request where [`DecodedStateRequest::no_proof`] is `true`.
+    ///
+    /// The first item concerns the main trie, and the following items each concern a child trie.
+    KeyValues(Vec<StateResponseKeyValues<'a>>),
+}
+
+/// List of storage entries of a trie. See [`StateResponse::KeyValues`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateResponseKeyValues<'a> {
+    /// Merkle value of the root of the child trie the entries belong to, or an empty slice for
+    /// the main trie.
+    pub state_root: &'a [u8],
+
+    /// List of keys and storage values, in lexicographic order of the keys.
+    pub entries: Vec<(&'a [u8], &'a [u8])>,
+
+    /// `true` if there isn't any entry in the trie after the last entry of
+    /// [`StateResponseKeyValues::entries`].
+    pub complete: bool,
+}
+
+/// Builds the bytes corresponding to a response to a state request.
+pub fn build_state_response(
+    response: StateResponse<'_>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
+    match response {
+        StateResponse::Proof(proof) => {
+            either::Left(protobuf::bytes_tag_encode(2, proof).map(either::Left))
+        }
+        StateResponse::KeyValues(tries) => either::Right(
+            tries
+                .into_iter()
+                .flat_map(|trie| {
+                    protobuf::message_tag_encode(
+                        1,
+                        protobuf::bytes_tag_encode(1, trie.state_root)
+                            .map(either::Left)
+                            .map(either::Left)
+                            .chain(
+                                trie.entries
+                                    .into_iter()
+                                    .flat_map(|(key, value)| {
+                                        protobuf::message_tag_encode(
+                                            2,
+                                            protobuf::bytes_tag_encode(1, key)
+                                                .chain(protobuf::bytes_tag_encode(2, value)),
+                                        )
+                                    })
+                                    .map(either::Right)
+                                    .map(either::Left),
+                            )
+                            .chain(protobuf::bool_tag_encode(3, trie.complete).map(either::Right)),
+                    )
+                })
+                .map(either::Right),
+        ),
+    }
+}
+
 /// Decodes a response to a state request.
 ///
 /// On success, contains a Merkle proof.
@@ -132,3 +264,50 @@ pub enum DecodeStateResponseError {
     /// Error while decoding the Protobuf encoding.
     ProtobufDecode,
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn request_encode_decode() {
+        let encoded = super::build_state_request(super::StateRequest {
+            block_hash: &[0xaa; 32],
+            start_key: super::StateRequestStart::ChildTrieDefault {
+                child_trie: &[1, 2, 3],
+                key: &[4, 5],
+            },
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        let decoded = super::decode_state_request(&encoded).unwrap();
+        assert_eq!(
+            decoded,
+            super::DecodedStateRequest {
+                block_hash: &[0xaa; 32],
+                start_key: super::StateRequestStart::ChildTrieDefault {
+                    child_trie: &[1, 2, 3],
+                    key: &[4, 5],
+                },
+                no_proof: false,
+            }
+        );
+    }
+
+    #[test]
+    fn proof_response_encode_decode() {
+        let encoded = super::build_state_response(super::StateResponse::Proof(&[1, 2, 3, 4])).fold(
+            Vec::new(),
+            |mut a, b| {
+                a.extend_from_slice(b.as_ref());
+                a
+            },
+        );
+
+        assert_eq!(
+            super::decode_state_response(&encoded).unwrap(),
+            &[1, 2, 3, 4]
+        );
+    }
+}
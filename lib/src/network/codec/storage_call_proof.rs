@@ -17,7 +17,8 @@
 
 use crate::util::protobuf;
 
-use alloc::{borrow::Cow, vec::Vec};
+use alloc::{borrow::Cow, vec, vec::Vec};
+use core::iter;
 
 /// Description of a storage proof request that can be sent to a peer.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -92,6 +93,91 @@ pub fn build_call_proof_request<'a>(
     )
 }
 
+/// Storage proof request or call proof request received from a peer.
+#[derive(Debug, Clone)]
+pub enum StorageOrCallProofRequest<'a> {
+    /// Request for a storage proof.
+    StorageProof(StorageProofRequestConfig<vec::IntoIter<&'a [u8]>>),
+    /// Request for a call proof.
+    CallProof(CallProofRequestConfig<'a, iter::Once<&'a [u8]>>),
+}
+
+/// Decodes a storage proof request or a call proof request.
+pub fn decode_storage_or_call_proof_request(
+    request_bytes: &[u8],
+) -> Result<StorageOrCallProofRequest<'_>, DecodeStorageCallProofRequestError> {
+    let mut parser = nom::combinator::all_consuming::<_, _, nom::error::Error<&[u8]>, _>(
+        nom::combinator::complete(protobuf::message_decode! {
+            #[optional] call = 1 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[required] method = 3 => protobuf::string_tag_decode,
+                #[optional] data = 4 => protobuf::bytes_tag_decode,
+            }),
+            #[optional] read = 2 => protobuf::message_tag_decode(protobuf::message_decode!{
+                #[required] block = 2 => protobuf::bytes_tag_decode,
+                #[repeated(max = 1024)] keys = 3 => protobuf::bytes_tag_decode,
+            }),
+        }),
+    );
+
+    let decoded = match nom::Finish::finish(parser(request_bytes)) {
+        Ok((_, rq)) => rq,
+        Err(_) => return Err(DecodeStorageCallProofRequestError::ProtobufDecode),
+    };
+
+    match (decoded.call, decoded.read) {
+        (Some(call), None) => Ok(StorageOrCallProofRequest::CallProof(
+            CallProofRequestConfig {
+                block_hash: <[u8; 32]>::try_from(call.block)
+                    .map_err(|_| DecodeStorageCallProofRequestError::InvalidBlockHashLength)?,
+                method: Cow::Borrowed(call.method),
+                parameter_vectored: iter::once(call.data.unwrap_or(&[])),
+            },
+        )),
+        (None, Some(read)) => Ok(StorageOrCallProofRequest::StorageProof(
+            StorageProofRequestConfig {
+                block_hash: <[u8; 32]>::try_from(read.block)
+                    .map_err(|_| DecodeStorageCallProofRequestError::InvalidBlockHashLength)?,
+                keys: read.keys.into_iter(),
+            },
+        )),
+        _ => Err(DecodeStorageCallProofRequestError::UnsupportedRequest),
+    }
+}
+
+/// Error potentially returned by [`decode_storage_or_call_proof_request`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum DecodeStorageCallProofRequestError {
+    /// Error while decoding the Protobuf encoding.
+    ProtobufDecode,
+    /// Request is neither a storage proof request nor a call proof request.
+    UnsupportedRequest,
+    /// Hash of the block isn't 32 bytes.
+    InvalidBlockHashLength,
+}
+
+/// Builds the bytes corresponding to a response to a storage proof request or a call proof
+/// request.
+///
+/// Pass `None` for the proof if the request can't be answered, for example because the storage
+/// of the requested block isn't available.
+pub fn build_storage_or_call_proof_response(
+    ty: StorageOrCallProof,
+    scale_encoded_proof: Option<&'_ [u8]>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + '_> + '_ {
+    let field_num = match ty {
+        StorageOrCallProof::CallProof => 1,
+        StorageOrCallProof::StorageProof => 2,
+    };
+
+    protobuf::message_tag_encode(
+        field_num,
+        scale_encoded_proof
+            .into_iter()
+            .flat_map(|proof| protobuf::bytes_tag_encode(2, proof)),
+    )
+}
+
 /// Decodes a response to a storage proof request or a call proof request.
 ///
 /// On success, returns a SCALE-encoded Merkle proof, or `None` if the remote couldn't answer
@@ -141,3 +227,76 @@ pub enum StorageOrCallProof {
     StorageProof,
     CallProof,
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::borrow::Cow;
+
+    #[test]
+    fn storage_proof_request_encode_decode() {
+        let keys = [&b"foo"[..], &b"barbaz"[..]];
+        let encoded = super::build_storage_proof_request(super::StorageProofRequestConfig {
+            block_hash: [0xaa; 32],
+            keys: keys.iter(),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        let super::StorageOrCallProofRequest::StorageProof(decoded) =
+            super::decode_storage_or_call_proof_request(&encoded).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(decoded.block_hash, [0xaa; 32]);
+        assert_eq!(decoded.keys.collect::<Vec<_>>(), keys);
+    }
+
+    #[test]
+    fn call_proof_request_encode_decode() {
+        let encoded = super::build_call_proof_request(super::CallProofRequestConfig {
+            block_hash: [0x55; 32],
+            method: Cow::Borrowed("Core_version"),
+            parameter_vectored: [&b"hello"[..], &b"world"[..]].into_iter(),
+        })
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        let super::StorageOrCallProofRequest::CallProof(decoded) =
+            super::decode_storage_or_call_proof_request(&encoded).unwrap()
+        else {
+            panic!()
+        };
+        assert_eq!(decoded.block_hash, [0x55; 32]);
+        assert_eq!(decoded.method, "Core_version");
+        assert_eq!(
+            decoded.parameter_vectored.collect::<Vec<_>>(),
+            [&b"helloworld"[..]]
+        );
+    }
+
+    #[test]
+    fn response_encode_decode() {
+        for ty in [
+            super::StorageOrCallProof::StorageProof,
+            super::StorageOrCallProof::CallProof,
+        ] {
+            for proof in [None, Some(&[1, 2, 3, 4][..])] {
+                let encoded = super::build_storage_or_call_proof_response(ty, proof).fold(
+                    Vec::new(),
+                    |mut a, b| {
+                        a.extend_from_slice(b.as_ref());
+                        a
+                    },
+                );
+                assert_eq!(
+                    super::decode_storage_or_call_proof_response(ty, &encoded).unwrap(),
+                    proof
+                );
+            }
+        }
+    }
+}
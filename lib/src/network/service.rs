@@ -151,6 +151,15 @@ pub struct ChainConfig<TChain> {
     /// `true` if incoming block requests are allowed.
     pub allow_inbound_block_requests: bool,
 
+    /// `true` if incoming GrandPa warp sync requests are allowed.
+    pub allow_inbound_grandpa_warp_sync_requests: bool,
+
+    /// `true` if incoming storage proof and call proof requests are allowed.
+    pub allow_inbound_storage_and_call_proof_requests: bool,
+
+    /// `true` if incoming state requests are allowed.
+    pub allow_inbound_state_requests: bool,
+
     /// Hash of the best block according to the local node.
     pub best_hash: [u8; 32],
     /// Height of the best block according to the local node.
@@ -261,6 +270,15 @@ struct Chain<TChain> {
     /// See [`ChainConfig::allow_inbound_block_requests`].
     allow_inbound_block_requests: bool,
 
+    /// See [`ChainConfig::allow_inbound_grandpa_warp_sync_requests`].
+    allow_inbound_grandpa_warp_sync_requests: bool,
+
+    /// See [`ChainConfig::allow_inbound_storage_and_call_proof_requests`].
+    allow_inbound_storage_and_call_proof_requests: bool,
+
+    /// See [`ChainConfig::allow_inbound_state_requests`].
+    allow_inbound_state_requests: bool,
+
     /// See [`ChainConfig::user_data`].
     user_data: TChain,
 }
@@ -450,6 +468,11 @@ where
             best_hash: config.best_hash,
             best_number: config.best_number,
             allow_inbound_block_requests: config.allow_inbound_block_requests,
+            allow_inbound_grandpa_warp_sync_requests: config
+                .allow_inbound_grandpa_warp_sync_requests,
+            allow_inbound_storage_and_call_proof_requests: config
+                .allow_inbound_storage_and_call_proof_requests,
+            allow_inbound_state_requests: config.allow_inbound_state_requests,
             grandpa_protocol_config: config.grandpa_protocol_config,
             user_data: config.user_data,
         });
@@ -1138,8 +1161,11 @@ where
                     match &mut connection_info.peer_index {
                         Some(expected_peer_index) if *expected_peer_index == actual_peer_index => {}
                         peer_index_refmut @ None => {
-                            self.unconnected_desired.remove(&actual_peer_index);
                             *peer_index_refmut = Some(actual_peer_index);
+                            let _was_inserted =
+                                self.connections_by_peer_id.insert((actual_peer_index, id));
+                            debug_assert!(_was_inserted);
+                            self.unconnected_desired.remove(&actual_peer_index);
                         }
                         Some(peer_index_refmut) => {
                             // The actual PeerId doesn't match the expected PeerId.
@@ -1419,12 +1445,45 @@ where
                                     self.inner.reject_inbound(substream_id);
                                     continue;
                                 }
+                                Protocol::SyncWarp { chain_index }
+                                    if self.chains[chain_index]
+                                        .allow_inbound_grandpa_warp_sync_requests =>
+                                {
+                                    collection::InboundTy::Request {
+                                        request_max_size: Some(32),
+                                    }
+                                }
+                                Protocol::SyncWarp { .. } => {
+                                    self.inner.reject_inbound(substream_id);
+                                    continue;
+                                }
+                                Protocol::LightUnknown { chain_index }
+                                    if self.chains[chain_index]
+                                        .allow_inbound_storage_and_call_proof_requests =>
+                                {
+                                    collection::InboundTy::Request {
+                                        request_max_size: Some(1024 * 1024),
+                                    }
+                                }
+                                Protocol::LightUnknown { .. } => {
+                                    self.inner.reject_inbound(substream_id);
+                                    continue;
+                                }
+
+                                Protocol::State { chain_index }
+                                    if self.chains[chain_index].allow_inbound_state_requests =>
+                                {
+                                    collection::InboundTy::Request {
+                                        request_max_size: Some(16 * 1024),
+                                    }
+                                }
+                                Protocol::State { .. } => {
+                                    self.inner.reject_inbound(substream_id);
+                                    continue;
+                                }
 
                                 // TODO: protocols that are not supported
-                                Protocol::LightUnknown { .. }
-                                | Protocol::Kad { .. }
-                                | Protocol::SyncWarp { .. }
-                                | Protocol::State { .. } => {
+                                Protocol::Kad { .. } => {
                                     self.inner.reject_inbound(substream_id);
                                     continue;
                                 }
@@ -1643,6 +1702,104 @@ where
                                 }
                             }
                         }
+                        Some(Protocol::SyncWarp { chain_index }) => {
+                            // The request consists in the hash of the block to start from.
+                            match <[u8; 32]>::try_from(&request_payload[..]) {
+                                Ok(begin_hash) => {
+                                    return Some(Event::GrandpaWarpSyncRequestIn {
+                                        peer_id,
+                                        chain_id: ChainId(chain_index),
+                                        begin_hash,
+                                        substream_id,
+                                    })
+                                }
+                                Err(_) => {
+                                    let _ = self.substreams.remove(&substream_id);
+                                    self.inner.respond_in_request(substream_id, Err(()));
+                                    return Some(Event::ProtocolError {
+                                        peer_id,
+                                        error: ProtocolError::BadGrandpaWarpSyncRequest,
+                                    });
+                                }
+                            }
+                        }
+                        Some(Protocol::State { chain_index }) => {
+                            match codec::decode_state_request(&request_payload) {
+                                Ok(request) => {
+                                    let (child_trie, start_key) = match request.start_key {
+                                        codec::StateRequestStart::MainTrie(key) => {
+                                            (None, key.to_vec())
+                                        }
+                                        codec::StateRequestStart::ChildTrieDefault {
+                                            child_trie,
+                                            key,
+                                        } => (Some(child_trie.to_vec()), key.to_vec()),
+                                    };
+
+                                    return Some(Event::StateRequestIn {
+                                        peer_id,
+                                        chain_id: ChainId(chain_index),
+                                        block_hash: *request.block_hash,
+                                        child_trie,
+                                        start_key,
+                                        with_proof: !request.no_proof,
+                                        substream_id,
+                                    });
+                                }
+                                Err(error) => {
+                                    let _ = self.substreams.remove(&substream_id);
+                                    self.inner.respond_in_request(substream_id, Err(()));
+                                    return Some(Event::ProtocolError {
+                                        peer_id,
+                                        error: ProtocolError::BadStateRequest(error),
+                                    });
+                                }
+                            }
+                        }
+                        Some(Protocol::LightUnknown { chain_index }) => {
+                            // The same protocol is used for both storage proof requests and call
+                            // proof requests. Once the request has been decoded, the protocol of
+                            // the substream is updated in order to know what to respond.
+                            match codec::decode_storage_or_call_proof_request(&request_payload) {
+                                Ok(codec::StorageOrCallProofRequest::StorageProof(config)) => {
+                                    self.substreams.get_mut(&substream_id).unwrap().protocol =
+                                        Some(Protocol::LightStorage { chain_index });
+                                    return Some(Event::StorageProofRequestIn {
+                                        peer_id,
+                                        chain_id: ChainId(chain_index),
+                                        block_hash: config.block_hash,
+                                        keys: config.keys.map(|k| k.to_vec()).collect(),
+                                        substream_id,
+                                    });
+                                }
+                                Ok(codec::StorageOrCallProofRequest::CallProof(config)) => {
+                                    self.substreams.get_mut(&substream_id).unwrap().protocol =
+                                        Some(Protocol::LightCall { chain_index });
+                                    return Some(Event::CallProofRequestIn {
+                                        peer_id,
+                                        chain_id: ChainId(chain_index),
+                                        block_hash: config.block_hash,
+                                        function_name: config.method.into_owned(),
+                                        parameter: config.parameter_vectored.fold(
+                                            Vec::new(),
+                                            |mut a, b| {
+                                                a.extend_from_slice(b);
+                                                a
+                                            },
+                                        ),
+                                        substream_id,
+                                    });
+                                }
+                                Err(error) => {
+                                    let _ = self.substreams.remove(&substream_id);
+                                    self.inner.respond_in_request(substream_id, Err(()));
+                                    return Some(Event::ProtocolError {
+                                        peer_id,
+                                        error: ProtocolError::BadStorageOrCallProofRequest(error),
+                                    });
+                                }
+                            }
+                        }
                         // Any other protocol is declined when the protocol is negotiated.
                         _ => unreachable!(),
                     }
@@ -2640,9 +2797,34 @@ where
                                         },
                                     })
                                 }
-                                _ => {
-                                    // Any other type of message is currently ignored. Support
-                                    // for them could be added in the future.
+                                codec::GrandpaNotificationRef::Vote(_) => {
+                                    return Some(Event::GrandpaVoteMessage {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id: self.peers[peer_index.0].clone(),
+                                        message: EncodedGrandpaVoteMessage {
+                                            message: notification,
+                                            block_number_bytes: self.chains[chain_index]
+                                                .block_number_bytes,
+                                        },
+                                    })
+                                }
+                                codec::GrandpaNotificationRef::CatchUpRequest(request) => {
+                                    return Some(Event::GrandpaCatchUpRequest {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id: self.peers[peer_index.0].clone(),
+                                        request,
+                                    })
+                                }
+                                codec::GrandpaNotificationRef::CatchUp(_) => {
+                                    return Some(Event::GrandpaCatchUpMessage {
+                                        chain_id: ChainId(chain_index),
+                                        peer_id: self.peers[peer_index.0].clone(),
+                                        message: EncodedGrandpaCatchUpMessage {
+                                            message: notification,
+                                            block_number_bytes: self.chains[chain_index]
+                                                .block_number_bytes,
+                                        },
+                                    })
                                 }
                             }
                         }
@@ -3086,6 +3268,33 @@ where
                             })
                             .into_iter(),
                     )
+                    .chain(
+                        chain
+                            .allow_inbound_grandpa_warp_sync_requests
+                            .then_some(codec::ProtocolName::SyncWarp {
+                                genesis_hash: chain.genesis_hash,
+                                fork_id: chain.fork_id.as_deref(),
+                            })
+                            .into_iter(),
+                    )
+                    .chain(
+                        chain
+                            .allow_inbound_storage_and_call_proof_requests
+                            .then_some(codec::ProtocolName::Light {
+                                genesis_hash: chain.genesis_hash,
+                                fork_id: chain.fork_id.as_deref(),
+                            })
+                            .into_iter(),
+                    )
+                    .chain(
+                        chain
+                            .allow_inbound_state_requests
+                            .then_some(codec::ProtocolName::State {
+                                genesis_hash: chain.genesis_hash,
+                                fork_id: chain.fork_id.as_deref(),
+                            })
+                            .into_iter(),
+                    )
                 }));
 
             let supported_protocols_names = supported_protocols
@@ -3148,6 +3357,160 @@ where
         self.inner.respond_in_request(substream_id, response);
     }
 
+    /// Responds to a GrandPa warp sync request. Call this function in response to
+    /// a [`Event::GrandpaWarpSyncRequestIn`].
+    ///
+    /// Pass `None` in order to deny the request. Do this if the starting block isn't available
+    /// locally.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a GrandPa warp sync
+    /// request or if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_grandpa_warp_sync(
+        &mut self,
+        substream_id: SubstreamId,
+        response: Option<codec::GrandpaWarpSyncResponse>,
+    ) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Some(Protocol::SyncWarp { .. })
+        ));
+
+        let response = if let Some(response) = response {
+            Ok(
+                codec::build_grandpa_warp_sync_response(response).fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                }),
+            )
+        } else {
+            Err(())
+        };
+
+        self.inner.respond_in_request(substream_id, response);
+    }
+
+    /// Responds to a state request. Call this function in response to
+    /// a [`Event::StateRequestIn`].
+    ///
+    /// Pass `None` in order to deny the request. Do this if the storage of the requested block
+    /// isn't available locally.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a state request or if
+    /// the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_state(
+        &mut self,
+        substream_id: SubstreamId,
+        response: Option<codec::StateResponse>,
+    ) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Some(Protocol::State { .. })
+        ));
+
+        let response = if let Some(response) = response {
+            Ok(
+                codec::build_state_response(response).fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                }),
+            )
+        } else {
+            Err(())
+        };
+
+        self.inner.respond_in_request(substream_id, response);
+    }
+
+    /// Responds to a storage proof request. Call this function in response to
+    /// a [`Event::StorageProofRequestIn`].
+    ///
+    /// Pass `None` in order to indicate that the proof can't be generated, for example because
+    /// the storage of the requested block isn't available locally. Otherwise, pass the
+    /// SCALE-encoded Merkle proof.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a storage proof request
+    /// or if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_storage_proof(
+        &mut self,
+        substream_id: SubstreamId,
+        scale_encoded_proof: Option<&[u8]>,
+    ) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Some(Protocol::LightStorage { .. })
+        ));
+
+        let response = codec::build_storage_or_call_proof_response(
+            codec::StorageOrCallProof::StorageProof,
+            scale_encoded_proof,
+        )
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        self.inner.respond_in_request(substream_id, Ok(response));
+    }
+
+    /// Responds to a call proof request. Call this function in response to
+    /// a [`Event::CallProofRequestIn`].
+    ///
+    /// Pass `None` in order to indicate that the proof can't be generated, for example because
+    /// the storage of the requested block isn't available locally. Otherwise, pass the
+    /// SCALE-encoded Merkle proof.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SubstreamId`] is invalid or doesn't correspond to a call proof request
+    /// or if the request has been cancelled with a [`Event::RequestInCancel`].
+    ///
+    pub fn respond_call_proof(
+        &mut self,
+        substream_id: SubstreamId,
+        scale_encoded_proof: Option<&[u8]>,
+    ) {
+        let substream_info = self.substreams.remove(&substream_id).unwrap();
+        assert!(matches!(
+            substream_info.protocol,
+            Some(Protocol::LightCall { .. })
+        ));
+
+        let response = codec::build_storage_or_call_proof_response(
+            codec::StorageOrCallProof::CallProof,
+            scale_encoded_proof,
+        )
+        .fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        self.inner.respond_in_request(substream_id, Ok(response));
+    }
+
     /// Returns the list of all peers for a [`Event::GossipConnected`] event of the given kind has
     /// been emitted.
     /// It is possible to send gossip notifications to these peers.
@@ -3660,6 +4023,71 @@ where
         )
     }
 
+    /// Sends a SCALE-encoded GrandPa notification to the given peer.
+    ///
+    /// If no [`Event::GossipConnected`] event of kind [`GossipKind::ConsensusTransactions`] has
+    /// been emitted for the given peer, then a [`QueueNotificationError::NoConnection`] will be
+    /// returned.
+    ///
+    /// This function might generate a message destined a connection. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process messages after it has returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_send_grandpa_message(
+        &mut self,
+        target: &PeerId,
+        chain_id: ChainId,
+        scale_encoded_message: &[u8],
+    ) -> Result<(), QueueNotificationError> {
+        self.queue_notification(
+            target,
+            NotificationsProtocol::Grandpa {
+                chain_index: chain_id.0,
+            },
+            scale_encoded_message.to_vec(),
+        )
+    }
+
+    /// Sends a SCALE-encoded GrandPa notification to all the peers we are connected to through
+    /// the GrandPa protocol of the given chain.
+    ///
+    /// This function might generate a message destined to connections. Use
+    /// [`ChainNetwork::pull_message_to_connection`] to process these messages after it has
+    /// returned.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`ChainId`] is invalid.
+    ///
+    pub fn gossip_broadcast_grandpa_message(
+        &mut self,
+        chain_id: ChainId,
+        scale_encoded_message: &[u8],
+    ) {
+        // TODO: O(n)
+        for (_, _, _, _, substream_id) in
+            self.notification_substreams_by_peer_id
+                .iter()
+                .filter(|(p, _, d, s, _)| {
+                    *p == NotificationsProtocol::Grandpa {
+                        chain_index: chain_id.0,
+                    } && *d == SubstreamDirection::Out
+                        && *s == NotificationsSubstreamState::Open
+                })
+        {
+            match self
+                .inner
+                .queue_notification(*substream_id, scale_encoded_message.to_vec())
+            {
+                Ok(()) => {}
+                Err(collection::QueueNotificationError::QueueFull) => {}
+            }
+        }
+    }
+
     /// Inner implementation for all the notifications sends.
     fn queue_notification(
         &mut self,
@@ -4085,6 +4513,44 @@ pub enum Event<TConn> {
         message: EncodedGrandpaCommitMessage,
     },
 
+    /// Received a GrandPa vote (pre-vote, pre-commit, or primary proposal) from the network.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaVoteMessage {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the vote relates to.
+        chain_id: ChainId,
+        message: EncodedGrandpaVoteMessage,
+    },
+
+    /// Received a GrandPa catch-up request from the network. The remote would like to receive
+    /// the votes of a round that has already been completed.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaCatchUpRequest {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the request relates to.
+        chain_id: ChainId,
+        request: codec::CatchUpRequest,
+    },
+
+    /// Received a GrandPa catch-up message from the network, normally as an answer to a
+    /// catch-up request.
+    ///
+    /// Can only happen after a [`Event::GossipConnected`] with the given [`PeerId`] and [`ChainId`]
+    /// combination has happened.
+    GrandpaCatchUpMessage {
+        /// Identity of the sender of the message.
+        peer_id: PeerId,
+        /// Index of the chain the catch-up relates to.
+        chain_id: ChainId,
+        message: EncodedGrandpaCatchUpMessage,
+    },
+
     /// Error in the protocol in a connection, such as failure to decode a message. This event
     /// doesn't have any consequence on the health of the connection, and is purely for diagnostic
     /// purposes.
@@ -4122,6 +4588,88 @@ pub enum Event<TConn> {
         substream_id: SubstreamId,
     },
 
+    /// A remote has sent a GrandPa warp sync request.
+    ///
+    /// Can only happen for chains where
+    /// [`ChainConfig::allow_inbound_grandpa_warp_sync_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_grandpa_warp_sync`].
+    GrandpaWarpSyncRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the finalized block the proof must start from.
+        begin_hash: [u8; 32],
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has sent a storage proof request.
+    ///
+    /// Can only happen for chains where
+    /// [`ChainConfig::allow_inbound_storage_and_call_proof_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_storage_proof`].
+    StorageProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the block whose storage must be proven.
+        block_hash: [u8; 32],
+        /// List of keys whose storage value must be proven.
+        keys: Vec<Vec<u8>>,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has sent a call proof request.
+    ///
+    /// Can only happen for chains where
+    /// [`ChainConfig::allow_inbound_storage_and_call_proof_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_call_proof`].
+    CallProofRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the block on top of which the call must be performed.
+        block_hash: [u8; 32],
+        /// Name of the runtime function to call.
+        function_name: String,
+        /// Parameter to pass to the runtime function.
+        parameter: Vec<u8>,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
+    /// A remote has sent a state request.
+    ///
+    /// Can only happen for chains where [`ChainConfig::allow_inbound_state_requests`] is `true`.
+    ///
+    /// You are strongly encouraged to call [`ChainNetwork::respond_state`].
+    StateRequestIn {
+        /// Remote that has sent the request.
+        peer_id: PeerId,
+        /// Index of the chain concerned by the request.
+        chain_id: ChainId,
+        /// Hash of the block whose storage is requested.
+        block_hash: [u8; 32],
+        /// If `Some`, the iteration must start within the given default child trie, then
+        /// continue in the main trie after the entry corresponding to this child trie.
+        child_trie: Option<Vec<u8>>,
+        /// The response must only contain keys strictly superior to this key, in the trie
+        /// indicated by `child_trie`.
+        start_key: Vec<u8>,
+        /// If `true`, the response must contain a Merkle proof. If `false`, the response must
+        /// contain the list of storage entries.
+        with_proof: bool,
+        /// Identifier of the request. Necessary to send back the answer.
+        substream_id: SubstreamId,
+    },
+
     /// A remote is no longer interested in the response to a request.
     ///
     /// Calling [`ChainNetwork::respond_identify`], [`ChainNetwork::respond_blocks`], or similar
@@ -4159,6 +4707,14 @@ pub enum ProtocolError {
     /// Error while decoding a received blocks request.
     #[display(fmt = "Error while decoding a received blocks request: {_0}")]
     BadBlocksRequest(codec::DecodeBlockRequestError),
+    /// Received an invalid GrandPa warp sync request.
+    BadGrandpaWarpSyncRequest,
+    /// Error while decoding a received storage proof or call proof request.
+    #[display(fmt = "Error while decoding a received storage or call proof request: {_0}")]
+    BadStorageOrCallProofRequest(codec::DecodeStorageCallProofRequestError),
+    /// Error while decoding a received state request.
+    #[display(fmt = "Error while decoding a received state request: {_0}")]
+    BadStateRequest(codec::DecodeStateRequestError),
 }
 
 /// Error potentially returned when starting a request.
@@ -4354,7 +4910,9 @@ impl fmt::Debug for EncodedGrandpaWarpSyncResponse {
 pub struct EncodedStateResponse(Vec<u8>);
 
 impl EncodedStateResponse {
-    /// Returns the Merkle proof of the state response.
+    /// Returns the compact Merkle proof of the state response.
+    ///
+    /// See [`crate::trie::proof_decode::decode_compact_proof`].
     pub fn decode(&self) -> &[u8] {
         match codec::decode_state_response(&self.0) {
             Ok(r) => r,
@@ -4452,3 +5010,49 @@ impl fmt::Debug for EncodedGrandpaCommitMessage {
         fmt::Debug::fmt(&self.decode(), f)
     }
 }
+
+/// Undecoded but valid GrandPa vote message.
+#[derive(Clone)]
+pub struct EncodedGrandpaVoteMessage {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaVoteMessage {
+    /// Returns the decoded version of the vote message.
+    pub fn decode(&self) -> codec::VoteMessageRef {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::Vote(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaVoteMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
+
+/// Undecoded but valid GrandPa catch-up message.
+#[derive(Clone)]
+pub struct EncodedGrandpaCatchUpMessage {
+    message: Vec<u8>,
+    block_number_bytes: usize,
+}
+
+impl EncodedGrandpaCatchUpMessage {
+    /// Returns the decoded version of the catch-up message.
+    pub fn decode(&self) -> codec::CatchUpRef {
+        match codec::decode_grandpa_notification(&self.message, self.block_number_bytes) {
+            Ok(codec::GrandpaNotificationRef::CatchUp(msg)) => msg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl fmt::Debug for EncodedGrandpaCatchUpMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.decode(), f)
+    }
+}
@@ -40,7 +40,11 @@ use crate::{
     verify,
 };
 
-use alloc::{borrow::Cow, vec::Vec};
+use alloc::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
 use core::{
     cmp, iter, marker, mem,
     num::{NonZeroU32, NonZeroU64},
@@ -124,6 +128,18 @@ pub struct Config {
     /// but if the hint matches it saves a big download.
     // TODO: provide only in non-full mode?
     pub code_trie_node_hint: Option<ConfigCodeTrieNodeHint>,
+
+    /// List of block numbers and hashes that the chain must contain. Any chain that doesn't
+    /// contain the given hash at the given height is rejected, and sources that announce such a
+    /// chain aren't used to download blocks of this chain.
+    ///
+    /// This is used in order to follow one specific side of a contentious fork.
+    pub fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// List of hashes of blocks that are known to be bad. These blocks and their descendants
+    /// are rejected, and sources that announce them aren't used to download blocks of this
+    /// chain.
+    pub bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 /// Identifier for a source in the [`AllSync`].
@@ -190,6 +206,8 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                         blocks_capacity: config.blocks_capacity,
                         download_ahead_blocks: config.download_ahead_blocks,
                         download_bodies: config.full_mode,
+                        fork_blocks: config.fork_blocks.clone(),
+                        bad_blocks: config.bad_blocks.clone(),
                     }),
                 }
             } else {
@@ -223,6 +241,8 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 blocks_capacity: config.blocks_capacity,
                                 download_ahead_blocks: config.download_ahead_blocks,
                                 download_bodies: false,
+                                fork_blocks: config.fork_blocks.clone(),
+                                bad_blocks: config.bad_blocks.clone(),
                             }),
                         }
                     }
@@ -238,6 +258,8 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 max_requests_per_block: config.max_requests_per_block,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                fork_blocks: config.fork_blocks,
+                bad_blocks: config.bad_blocks,
             },
         }
     }
@@ -1255,6 +1277,29 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     ) -> BlockAnnounceOutcome {
         let source_id = self.shared.sources.get(source_id.0).unwrap();
 
+        // Blocks that don't match the fork blocks of the chain, and bad blocks and their
+        // children, are ignored, no matter the syncing strategy. Since the source isn't
+        // considered as knowing this block, no request concerning this block or its descendants
+        // will be sent to it.
+        if let Ok(header) = header::decode(
+            &announced_scale_encoded_header,
+            self.shared.block_number_bytes,
+        ) {
+            let hash = header::hash_from_scale_encoded_header(&announced_scale_encoded_header);
+
+            if self.shared.bad_blocks.contains(&hash)
+                || self.shared.bad_blocks.contains(header.parent_hash)
+            {
+                return BlockAnnounceOutcome::BadBlock;
+            }
+
+            if let Some(expected_hash) = self.shared.fork_blocks.get(&header.number) {
+                if *expected_hash != hash {
+                    return BlockAnnounceOutcome::ForkBlockMismatch;
+                }
+            }
+        }
+
         match (&mut self.inner, source_id) {
             (AllSyncInner::AllForks(sync), &SourceMapping::AllForks(source_id)) => {
                 match sync.block_announce(source_id, announced_scale_encoded_header, is_best) {
@@ -1400,6 +1445,29 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
         }
     }
 
+    /// Update the state machine with a justification that doesn't originate from a source, for
+    /// example a justification generated by a GrandPa voter running locally.
+    ///
+    /// This function only inserts the justification into the state machine, and does not
+    /// immediately verify it. Justifications are currently only supported when syncing in full
+    /// mode.
+    pub fn inject_justification(
+        &mut self,
+        consensus_engine_id: [u8; 4],
+        scale_encoded_justification: Vec<u8>,
+    ) -> InjectJustificationOutcome {
+        match &mut self.inner {
+            AllSyncInner::Optimistic { inner } => {
+                inner.inject_justification(consensus_engine_id, scale_encoded_justification);
+                InjectJustificationOutcome::Queued
+            }
+            AllSyncInner::AllForks(_) | AllSyncInner::WarpSync { .. } => {
+                InjectJustificationOutcome::Discarded
+            }
+            AllSyncInner::Poisoned => unreachable!(),
+        }
+    }
+
     /// Inject a response to a previously-emitted blocks request.
     ///
     /// # Panic
@@ -2049,6 +2117,16 @@ pub enum BlockAnnounceOutcome {
     /// Failed to decode announce header.
     InvalidHeader(header::Error),
 
+    /// Announced block is at the height of one of the fork blocks of the chain, but its hash
+    /// doesn't match. The block and its descendants will never be downloaded, and the source
+    /// isn't considered as knowing the chain. See [`Config::fork_blocks`].
+    ForkBlockMismatch,
+
+    /// Announced block, or its parent, is in the list of bad blocks of the chain. The block and
+    /// its descendants will never be downloaded, and the source isn't considered as knowing the
+    /// chain. See [`Config::bad_blocks`].
+    BadBlock,
+
     /// Header cannot be verified now and has been silently discarded.
     Discarded,
 }
@@ -2144,6 +2222,15 @@ pub enum GrandpaCommitMessageOutcome {
     Queued,
 }
 
+/// See [`AllSync::inject_justification`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InjectJustificationOutcome {
+    /// Justification has been silently discarded.
+    Discarded,
+    /// Justification has been queued for later verification.
+    Queued,
+}
+
 // TODO: doc
 #[derive(Debug, Clone)]
 pub struct Block<TBl> {
@@ -2247,6 +2334,12 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                                 all_forks::HeaderVerifyError::ConsensusMismatch => {
                                     HeaderVerifyError::ConsensusMismatch
                                 }
+                                all_forks::HeaderVerifyError::ForkBlockMismatch {
+                                    expected_hash,
+                                } => HeaderVerifyError::ForkBlockMismatch { expected_hash },
+                                all_forks::HeaderVerifyError::BadBlock => {
+                                    HeaderVerifyError::BadBlock
+                                }
                             },
                         }
                     }
@@ -2266,13 +2359,23 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                             },
                         }
                     }
-                    optimistic::BlockVerification::Reset { sync, .. } => {
+                    optimistic::BlockVerification::Reset { sync, reason, .. } => {
                         HeaderVerifyOutcome::Error {
                             sync: AllSync {
                                 inner: AllSyncInner::Optimistic { inner: sync },
                                 shared: self.shared,
                             },
-                            error: HeaderVerifyError::ConsensusMismatch, // TODO: dummy error cause /!\
+                            error: match reason {
+                                optimistic::ResetCause::HeaderError(
+                                    blocks_tree::HeaderVerifyError::ForkBlockMismatch {
+                                        expected_hash,
+                                    },
+                                ) => HeaderVerifyError::ForkBlockMismatch { expected_hash },
+                                optimistic::ResetCause::HeaderError(
+                                    blocks_tree::HeaderVerifyError::BadBlock,
+                                ) => HeaderVerifyError::BadBlock,
+                                _ => HeaderVerifyError::ConsensusMismatch, // TODO: dummy error cause /!\
+                            },
                         }
                     }
                 }
@@ -2309,6 +2412,19 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{_0}")]
     VerificationFailed(verify::header_only::Error),
+    /// The block is at the height of one of the fork blocks of the chain, but its hash doesn't
+    /// match. See [`Config::fork_blocks`].
+    #[display(
+        fmt = "Block doesn't match fork block {}",
+        "hex::encode(expected_hash)"
+    )]
+    ForkBlockMismatch {
+        /// Hash that the block at this height must have.
+        expected_hash: [u8; 32],
+    },
+    /// The block is in the list of bad blocks of the chain. See [`Config::bad_blocks`].
+    #[display(fmt = "Block is known to be bad")]
+    BadBlock,
 }
 
 pub struct HeaderVerifySuccess<TRq, TSrc, TBl> {
@@ -2548,7 +2664,11 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                         updates_best_block: false,
                     },
                 ),
-                (inner, optimistic::JustificationVerification::Reset { error, .. }) => (
+                (
+                    inner,
+                    optimistic::JustificationVerification::Reset { error, .. }
+                    | optimistic::JustificationVerification::Discarded { error },
+                ) => (
                     AllSync {
                         inner: AllSyncInner::Optimistic { inner },
                         shared: self.shared,
@@ -2776,6 +2896,10 @@ struct Shared<TRq> {
     block_number_bytes: usize,
     /// Value passed through [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Value passed through [`Config::fork_blocks`].
+    fork_blocks: BTreeMap<u64, [u8; 32]>,
+    /// Value passed through [`Config::bad_blocks`].
+    bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 impl<TRq> Shared<TRq> {
@@ -2804,6 +2928,8 @@ impl<TRq> Shared<TRq> {
             max_requests_per_block: self.max_requests_per_block,
             allow_unknown_consensus_engines: self.allow_unknown_consensus_engines,
             full: false,
+            fork_blocks: self.fork_blocks.clone(),
+            bad_blocks: self.bad_blocks.clone(),
         });
 
         debug_assert!(self
@@ -88,7 +88,12 @@ use crate::{
     header, verify,
 };
 
-use alloc::{borrow::ToOwned as _, boxed::Box, vec::Vec};
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
 use core::{
     cmp, mem,
     num::{NonZeroU32, NonZeroU64},
@@ -172,6 +177,22 @@ pub struct Config {
 
     /// If true, the block bodies and storage are also synchronized.
     pub full: bool,
+
+    /// List of block numbers and hashes that the chain must contain.
+    ///
+    /// Announced blocks whose height is found in this list but whose hash is different are
+    /// immediately considered as bad, and are never downloaded from sources.
+    ///
+    /// See also [`blocks_tree::Config::fork_blocks`].
+    pub fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// List of hashes of blocks that are known to be bad.
+    ///
+    /// Announced blocks whose hash is found in this list, and all their descendants, are
+    /// immediately considered as bad, and are never downloaded from sources.
+    ///
+    /// See also [`blocks_tree::Config::bad_blocks`].
+    pub bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 pub struct AllForksSync<TBl, TRq, TSrc> {
@@ -430,6 +451,8 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             block_number_bytes: config.block_number_bytes,
             blocks_capacity: config.blocks_capacity,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            fork_blocks: config.fork_blocks,
+            bad_blocks: config.bad_blocks,
         });
 
         Self {
@@ -1105,6 +1128,18 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             }
         }*/
     }*/
+
+    /// Marks the given unverified block as bad if its hash is in [`Config::bad_blocks`], or if
+    /// its height is one of the heights of [`Config::fork_blocks`] but its hash doesn't match.
+    ///
+    /// Because bad blocks and their descendants are never downloaded, this guarantees that
+    /// requests are only sent for blocks of chains that contain the fork blocks and that don't
+    /// contain any of the bad blocks.
+    fn mark_bad_if_forbidden(&mut self, height: u64, hash: &[u8; 32]) {
+        if self.chain.is_bad_block(hash) || self.chain.is_fork_block_mismatch(height, hash) {
+            self.inner.blocks.mark_unverified_block_as_bad(height, hash);
+        }
+    }
 }
 
 impl<TBl, TRq, TSrc> ops::Index<SourceId> for AllForksSync<TBl, TRq, TSrc> {
@@ -1477,6 +1512,9 @@ impl<TBl, TRq, TSrc> AddBlockVacant<TBl, TRq, TSrc> {
                 user_data,
             },
         );
+        self.inner
+            .inner
+            .mark_bad_if_forbidden(self.decoded_header.number, &self.inner.expected_next_hash);
 
         if !self.justifications.is_empty() {
             self.inner.inner.inner.blocks[self.inner.source_id]
@@ -1715,6 +1753,8 @@ impl<'a, TBl, TRq, TSrc> AnnouncedBlockUnknown<'a, TBl, TRq, TSrc> {
                 user_data,
             },
         );
+        self.inner
+            .mark_bad_if_forbidden(self.announced_header_number, &self.announced_header_hash);
 
         // If there are too many blocks stored in the blocks list, remove unnecessary ones.
         // Not doing this could lead to an explosion of the size of the collections.
@@ -1919,6 +1959,8 @@ impl<'a, TBl, TRq, TSrc> AddSourceUnknown<'a, TBl, TRq, TSrc> {
                 user_data: best_block_user_data,
             },
         );
+        self.inner
+            .mark_bad_if_forbidden(self.best_block_number, &self.best_block_hash);
 
         source_id
     }
@@ -2000,6 +2042,24 @@ impl<TBl, TRq, TSrc> BlockVerify<TBl, TRq, TSrc> {
 
                 Err(HeaderVerifyError::UnknownConsensusEngine)
             }
+            Err(blocks_tree::HeaderVerifyError::ForkBlockMismatch { expected_hash }) => {
+                // Remove the block from `pending_blocks`.
+                self.parent.inner.blocks.mark_unverified_block_as_bad(
+                    self.block_to_verify.block_number,
+                    &self.block_to_verify.block_hash,
+                );
+
+                Err(HeaderVerifyError::ForkBlockMismatch { expected_hash })
+            }
+            Err(blocks_tree::HeaderVerifyError::BadBlock) => {
+                // Remove the block from `pending_blocks`.
+                self.parent.inner.blocks.mark_unverified_block_as_bad(
+                    self.block_to_verify.block_number,
+                    &self.block_to_verify.block_hash,
+                );
+
+                Err(HeaderVerifyError::BadBlock)
+            }
             Ok(blocks_tree::HeaderVerifySuccess::Duplicate)
             | Err(
                 blocks_tree::HeaderVerifyError::BadParent { .. }
@@ -2310,6 +2370,19 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{_0}")]
     VerificationFailed(verify::header_only::Error),
+    /// The block is at the height of one of the fork blocks of the chain, but its hash doesn't
+    /// match. See [`Config::fork_blocks`].
+    #[display(
+        fmt = "Block doesn't match fork block {}",
+        "hex::encode(expected_hash)"
+    )]
+    ForkBlockMismatch {
+        /// Hash that the block at this height must have.
+        expected_hash: [u8; 32],
+    },
+    /// The block is in the list of bad blocks of the chain. See [`Config::bad_blocks`].
+    #[display(fmt = "Block is known to be bad")]
+    BadBlock,
 }
 
 /// Information about the outcome of verifying a finality proof.
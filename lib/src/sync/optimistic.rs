@@ -52,7 +52,7 @@ use crate::{
 
 use alloc::{
     boxed::Box,
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     vec::{self, Vec},
 };
 use core::{
@@ -97,6 +97,12 @@ pub struct Config {
 
     /// If `true`, the downloaded block bodies are stored in the state machine.
     pub download_bodies: bool,
+
+    /// See [`blocks_tree::Config::fork_blocks`].
+    pub fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// See [`blocks_tree::Config::bad_blocks`].
+    pub bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 /// Identifier for an ongoing request in the [`OptimisticSync`].
@@ -158,8 +164,10 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     verification_queue:
         verification_queue::VerificationQueue<(RequestId, TRq), RequestSuccessBlock<TBl>>,
 
-    /// Justifications, if any, of the block that has just been verified.
-    pending_encoded_justifications: vec::IntoIter<([u8; 4], Vec<u8>, SourceId)>,
+    /// Justifications, if any, of the block that has just been verified, and justifications
+    /// injected through [`OptimisticSync::inject_justification`]. The source is `None` for the
+    /// latter.
+    pending_encoded_justifications: vec::IntoIter<([u8; 4], Vec<u8>, Option<SourceId>)>,
 
     /// Identifier to assign to the next request.
     next_request_id: RequestId,
@@ -253,6 +261,8 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             // a malicious node could send non-finalized blocks. Accepting blocks with an
             // unrecognized consensus engine doesn't add any additional risk.
             allow_unknown_consensus_engines: true,
+            fork_blocks: config.fork_blocks,
+            bad_blocks: config.bad_blocks,
         };
 
         let chain = blocks_tree::NonFinalizedTree::new(blocks_tree_config.clone());
@@ -669,6 +679,23 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         user_data
     }
 
+    /// Queues a justification that doesn't come from any source, for example a justification
+    /// generated locally by a GrandPa voter.
+    ///
+    /// The justification is verified during the next call to [`OptimisticSync::process_one`].
+    /// Contrary to justifications coming from sources, failing to verify this justification
+    /// doesn't reset the chain.
+    pub fn inject_justification(
+        &mut self,
+        consensus_engine_id: [u8; 4],
+        scale_encoded_justification: Vec<u8>,
+    ) {
+        let mut pending =
+            mem::take(&mut self.inner.pending_encoded_justifications).collect::<Vec<_>>();
+        pending.push((consensus_engine_id, scale_encoded_justification, None));
+        self.inner.pending_encoded_justifications = pending.into_iter();
+    }
+
     /// Process the next block in the queue of verification.
     ///
     /// This method takes ownership of the [`OptimisticSync`]. The [`OptimisticSync`] is yielded
@@ -814,7 +841,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
             .scale_encoded_justifications
             .clone()
             .into_iter()
-            .map(|(e, j)| (e, j, source_id))
+            .map(|(e, j)| (e, j, Some(source_id)))
             .collect::<Vec<_>>()
             .into_iter();
 
@@ -1061,8 +1088,17 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
             randomness_seed,
         ) {
             Ok(a) => a,
+            Err(error) if source_id.is_none() => {
+                return (
+                    OptimisticSync {
+                        chain: self.chain,
+                        inner: self.inner,
+                    },
+                    JustificationVerification::Discarded { error },
+                );
+            }
             Err(error) => {
-                if let Some(source) = self.inner.sources.get_mut(&source_id) {
+                if let Some(source) = source_id.and_then(|id| self.inner.sources.get_mut(&id)) {
                     source.banned = true;
                 }
 
@@ -1089,8 +1125,6 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
             }
         };
 
-        assert!(apply.is_current_best_block()); // TODO: can legitimately fail in case of malicious node
-
         // As part of the finalization, put the justification in the chain that's
         // going to be reported to the user.
         apply
@@ -1105,10 +1139,6 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
             .map(|b| b.user_data)
             .collect();
 
-        // Since the best block is now the finalized block, reset the storage
-        // diff.
-        debug_assert!(self.chain.is_empty());
-
         self.inner.finalized_chain_information.chain_information =
             self.chain.as_chain_information().into();
 
@@ -1136,7 +1166,16 @@ pub enum JustificationVerification<TBl> {
         error: blocks_tree::JustificationVerifyError,
     },
 
-    /// Processing of the justification is over. The best block has now been finalized.
+    /// An issue happened when verifying a justification injected with
+    /// [`OptimisticSync::inject_justification`]. The justification has been discarded and the
+    /// chain is left untouched.
+    Discarded {
+        /// Problem that happened.
+        error: blocks_tree::JustificationVerifyError,
+    },
+
+    /// Processing of the justification is over. The target of the justification and its
+    /// ancestors have now been finalized.
     ///
     /// There might be more blocks remaining. Call [`OptimisticSync::process_one`] again.
     Finalized {
@@ -0,0 +1,411 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate telemetry.
+//!
+//! A telemetry server is a WebSocket server to which nodes report information about themselves
+//! and about the state of the chain they are connected to, such as their best and finalized
+//! blocks. Chain specifications can contain a list of telemetry servers that nodes should
+//! connect to, alongside with a verbosity level for each of them. See
+//! [`crate::chain_spec::ChainSpec::telemetry_endpoints`].
+//!
+//! This module contains:
+//!
+//! - [`parse_endpoint`], which parses the address of a telemetry server found in a chain
+//! specification.
+//! - [`Payload`] and [`encode_message`], which build the JSON messages sent to telemetry servers.
+//!
+//! This code intentionally doesn't perform any networking or access any clock, and leaves this
+//! to the API user. Each message returned by [`encode_message`] must be sent to the telemetry
+//! server as a separate WebSocket message.
+
+use crate::libp2p::multiaddr;
+
+use alloc::{
+    string::{String, ToString as _},
+    vec::Vec,
+};
+use core::{fmt, str::FromStr as _, time::Duration};
+
+#[cfg(test)]
+mod tests;
+
+/// Address of a telemetry server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    /// Host to connect to.
+    pub host: EndpointHost,
+    /// TCP port to connect to.
+    pub port: u16,
+    /// `true` if the connection must be encrypted with TLS (`wss://`).
+    pub secure: bool,
+    /// URL path to request during the WebSocket handshake. Always starts with `/`.
+    pub path: String,
+}
+
+/// See [`Endpoint::host`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointHost {
+    /// Domain name that must be resolved.
+    Dns(String),
+    /// IPv4 address.
+    Ipv4([u8; 4]),
+    /// IPv6 address.
+    Ipv6([u8; 16]),
+}
+
+impl fmt::Display for EndpointHost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EndpointHost::Dns(name) => fmt::Display::fmt(name, f),
+            EndpointHost::Ipv4(ip) => fmt::Display::fmt(&no_std_net::Ipv4Addr::from(*ip), f),
+            EndpointHost::Ipv6(ip) => write!(f, "[{}]", no_std_net::Ipv6Addr::from(*ip)),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}:{}{}",
+            if self.secure { "wss" } else { "ws" },
+            self.host,
+            self.port,
+            self.path
+        )
+    }
+}
+
+/// Parses the address of a telemetry server.
+///
+/// Two formats are supported:
+///
+/// - Multiaddresses, such as `/dns/telemetry.polkadot.io/tcp/443/x-parity-wss/%2Fsubmit%2F`,
+/// which is the format used by Substrate in chain specifications.
+/// - URLs, such as `wss://telemetry.polkadot.io/submit/`.
+///
+pub fn parse_endpoint(address: &str) -> Result<Endpoint, ParseEndpointError> {
+    if address.starts_with('/') {
+        parse_multiaddr_endpoint(address)
+    } else if let Some(rest) = address.strip_prefix("ws://") {
+        parse_url_endpoint(rest, false)
+    } else if let Some(rest) = address.strip_prefix("wss://") {
+        parse_url_endpoint(rest, true)
+    } else {
+        Err(ParseEndpointError::UnrecognizedFormat)
+    }
+}
+
+fn parse_multiaddr_endpoint(address: &str) -> Result<Endpoint, ParseEndpointError> {
+    let mut parts = address.split('/').skip(1);
+
+    let host = match multiaddr::Protocol::from_str_parts(&mut parts)
+        .map_err(ParseEndpointError::InvalidMultiaddr)?
+    {
+        multiaddr::Protocol::Dns(name)
+        | multiaddr::Protocol::Dns4(name)
+        | multiaddr::Protocol::Dns6(name) => EndpointHost::Dns(name.to_string()),
+        multiaddr::Protocol::Ip4(ip) => EndpointHost::Ipv4(ip),
+        multiaddr::Protocol::Ip6(ip) => EndpointHost::Ipv6(ip),
+        _ => return Err(ParseEndpointError::NotWebSocket),
+    };
+
+    let port = match multiaddr::Protocol::from_str_parts(&mut parts)
+        .map_err(ParseEndpointError::InvalidMultiaddr)?
+    {
+        multiaddr::Protocol::Tcp(port) => port,
+        _ => return Err(ParseEndpointError::NotWebSocket),
+    };
+
+    let (secure, path) = match parts.next() {
+        Some("ws") => (false, String::from("/")),
+        Some("wss") => (true, String::from("/")),
+        Some("x-parity-ws") => (false, percent_decode(parts.next().unwrap_or(""))?),
+        Some("x-parity-wss") => (true, percent_decode(parts.next().unwrap_or(""))?),
+        _ => return Err(ParseEndpointError::NotWebSocket),
+    };
+
+    if parts.next().is_some() {
+        return Err(ParseEndpointError::NotWebSocket);
+    }
+
+    Ok(Endpoint {
+        host,
+        port,
+        secure,
+        path: if path.starts_with('/') {
+            path
+        } else {
+            String::from("/") + &path
+        },
+    })
+}
+
+fn parse_url_endpoint(url: &str, secure: bool) -> Result<Endpoint, ParseEndpointError> {
+    let (authority, path) = match url.find('/') {
+        Some(pos) => (&url[..pos], &url[pos..]),
+        None => (url, "/"),
+    };
+
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (ip, rest) = rest.split_once(']').ok_or(ParseEndpointError::InvalidUrl)?;
+        let ip = no_std_net::Ipv6Addr::from_str(ip).map_err(|_| ParseEndpointError::InvalidUrl)?;
+        let port = match rest {
+            "" => None,
+            rest => Some(
+                rest.strip_prefix(':')
+                    .ok_or(ParseEndpointError::InvalidUrl)?,
+            ),
+        };
+        (EndpointHost::Ipv6(ip.octets()), port)
+    } else {
+        let (host, port) = match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+        if host.is_empty() {
+            return Err(ParseEndpointError::InvalidUrl);
+        }
+        match no_std_net::Ipv4Addr::from_str(host) {
+            Ok(ip) => (EndpointHost::Ipv4(ip.octets()), port),
+            Err(_) => (EndpointHost::Dns(host.to_string()), port),
+        }
+    };
+
+    let port = match port {
+        Some(port) => port.parse().map_err(|_| ParseEndpointError::InvalidUrl)?,
+        None if secure => 443,
+        None => 80,
+    };
+
+    Ok(Endpoint {
+        host,
+        port,
+        secure,
+        path: path.to_string(),
+    })
+}
+
+fn percent_decode(input: &str) -> Result<String, ParseEndpointError> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut bytes = input.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            output.push(byte);
+            continue;
+        }
+
+        let hex = [
+            bytes.next().ok_or(ParseEndpointError::InvalidPath)?,
+            bytes.next().ok_or(ParseEndpointError::InvalidPath)?,
+        ];
+        let mut decoded = [0u8];
+        hex::decode_to_slice(hex, &mut decoded).map_err(|_| ParseEndpointError::InvalidPath)?;
+        output.push(decoded[0]);
+    }
+
+    String::from_utf8(output).map_err(|_| ParseEndpointError::InvalidPath)
+}
+
+/// Error potentially returned by [`parse_endpoint`].
+#[derive(Debug, Clone, derive_more::Display)]
+pub enum ParseEndpointError {
+    /// Address is neither a multiaddress nor a `ws://` or `wss://` URL.
+    UnrecognizedFormat,
+    /// Failed to parse the multiaddress.
+    #[display(fmt = "{_0}")]
+    InvalidMultiaddr(multiaddr::ParseError),
+    /// Multiaddress doesn't designate a WebSocket server.
+    NotWebSocket,
+    /// Failed to parse the URL.
+    InvalidUrl,
+    /// URL path of the endpoint is invalid.
+    InvalidPath,
+}
+
+/// Content of a message sent to a telemetry server.
+///
+/// Use [`encode_message`] in order to turn this into the message that is actually sent.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "msg")]
+pub enum Payload<'a> {
+    /// Sent when connecting to a telemetry server, in order to describe the node.
+    #[serde(rename = "system.connected")]
+    SystemConnected {
+        /// Name of the chain, as found in the chain specification.
+        chain: &'a str,
+        /// Hash of the genesis block of the chain.
+        #[serde(serialize_with = "serialize_hash")]
+        genesis_hash: &'a [u8; 32],
+        /// Human-readable name of the node, displayed by the telemetry server.
+        name: &'a str,
+        /// Name of the client implementation, for example `smoldot-full-node`.
+        implementation: &'a str,
+        /// Version of the client implementation.
+        version: &'a str,
+        /// String representation of the network identity (i.e. the `PeerId`) of the node.
+        network_id: &'a str,
+        /// `true` if the node might author blocks.
+        authority: bool,
+        /// Time when the node was started, in milliseconds since the UNIX epoch.
+        #[serde(serialize_with = "serialize_as_string")]
+        startup_time: u64,
+        /// Operating system the node is running on, for example `linux`.
+        target_os: &'a str,
+        /// CPU architecture the node is running on, for example `x86_64`.
+        target_arch: &'a str,
+        /// Environment the node has been compiled for, for example `gnu`.
+        target_env: &'a str,
+    },
+
+    /// Sent at a regular interval.
+    #[serde(rename = "system.interval")]
+    SystemInterval {
+        /// Number of peers the node is connected to.
+        peers: u64,
+        /// Height of the current best block.
+        height: u64,
+        /// Hash of the current best block.
+        #[serde(serialize_with = "serialize_hash")]
+        best: &'a [u8; 32],
+        /// Height of the current finalized block.
+        finalized_height: u64,
+        /// Hash of the current finalized block.
+        #[serde(serialize_with = "serialize_hash")]
+        finalized_hash: &'a [u8; 32],
+        /// Number of transactions in the transactions pool, if known.
+        #[serde(rename = "txcount", skip_serializing_if = "Option::is_none")]
+        transactions_count: Option<u64>,
+        /// Average number of bytes per second received from the network, if known.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bandwidth_download: Option<f64>,
+        /// Average number of bytes per second sent to the network, if known.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        bandwidth_upload: Option<f64>,
+    },
+
+    /// Sent when a new best block has been imported.
+    #[serde(rename = "block.import")]
+    BlockImport {
+        /// Height of the new best block.
+        height: u64,
+        /// Hash of the new best block.
+        #[serde(serialize_with = "serialize_hash")]
+        best: &'a [u8; 32],
+        /// Where the block comes from. Substrate uses for example `Own` or `NetworkBroadcast`.
+        origin: &'a str,
+    },
+
+    /// Sent when a new block has been finalized.
+    #[serde(rename = "notify.finalized")]
+    NotifyFinalized {
+        /// Height of the new finalized block.
+        #[serde(serialize_with = "serialize_as_string")]
+        height: u64,
+        /// Hash of the new finalized block.
+        #[serde(serialize_with = "serialize_hash")]
+        best: &'a [u8; 32],
+    },
+}
+
+impl<'a> Payload<'a> {
+    /// Returns the verbosity level of the message. The message should only be sent to telemetry
+    /// servers whose verbosity level is superior or equal to this value.
+    pub fn verbosity(&self) -> u8 {
+        // All the messages currently supported are sent by Substrate at the lowest verbosity
+        // level.
+        match self {
+            Payload::SystemConnected { .. }
+            | Payload::SystemInterval { .. }
+            | Payload::BlockImport { .. }
+            | Payload::NotifyFinalized { .. } => 0,
+        }
+    }
+}
+
+fn serialize_hash<S: serde::Serializer>(
+    hash: &&[u8; 32],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&(String::from("0x") + &hex::encode(hash)))
+}
+
+fn serialize_as_string<S: serde::Serializer>(
+    value: &u64,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+/// Builds the JSON message to send to a telemetry server.
+///
+/// The `id` is a number that identifies the node among the nodes that share the same connection
+/// to a telemetry server. It is typically constant.
+///
+/// Must be passed the current time as a duration since the UNIX epoch.
+pub fn encode_message(id: u64, now_from_unix_epoch: Duration, payload: &Payload) -> String {
+    #[derive(serde::Serialize)]
+    struct Message<'a> {
+        id: u64,
+        ts: String,
+        payload: &'a Payload<'a>,
+    }
+
+    serde_json::to_string(&Message {
+        id,
+        ts: rfc3339_timestamp(now_from_unix_epoch),
+        payload,
+    })
+    .unwrap()
+}
+
+/// Formats the given duration since the UNIX epoch as a RFC 3339 timestamp in the UTC timezone,
+/// with a millisecond precision.
+fn rfc3339_timestamp(now_from_unix_epoch: Duration) -> String {
+    let secs = now_from_unix_epoch.as_secs();
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+
+    // Conversion from a number of days since the epoch to a civil date.
+    // See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    alloc::format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60,
+        now_from_unix_epoch.subsec_millis()
+    )
+}
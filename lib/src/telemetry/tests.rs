@@ -0,0 +1,95 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg(test)]
+
+use super::{encode_message, parse_endpoint, Endpoint, EndpointHost, ParseEndpointError, Payload};
+use core::time::Duration;
+
+#[test]
+fn parse_substrate_multiaddr() {
+    assert_eq!(
+        parse_endpoint("/dns/telemetry.polkadot.io/tcp/443/x-parity-wss/%2Fsubmit%2F").unwrap(),
+        Endpoint {
+            host: EndpointHost::Dns("telemetry.polkadot.io".into()),
+            port: 443,
+            secure: true,
+            path: "/submit/".into(),
+        }
+    );
+
+    assert_eq!(
+        parse_endpoint("/ip4/127.0.0.1/tcp/8001/ws").unwrap(),
+        Endpoint {
+            host: EndpointHost::Ipv4([127, 0, 0, 1]),
+            port: 8001,
+            secure: false,
+            path: "/".into(),
+        }
+    );
+
+    assert!(matches!(
+        parse_endpoint("/ip4/127.0.0.1/tcp/8001"),
+        Err(ParseEndpointError::NotWebSocket)
+    ));
+}
+
+#[test]
+fn parse_url() {
+    assert_eq!(
+        parse_endpoint("wss://telemetry.example.com/submit/").unwrap(),
+        Endpoint {
+            host: EndpointHost::Dns("telemetry.example.com".into()),
+            port: 443,
+            secure: true,
+            path: "/submit/".into(),
+        }
+    );
+
+    assert_eq!(
+        parse_endpoint("ws://[::1]:8001").unwrap(),
+        Endpoint {
+            host: EndpointHost::Ipv6([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+            port: 8001,
+            secure: false,
+            path: "/".into(),
+        }
+    );
+
+    assert!(matches!(
+        parse_endpoint("http://example.com"),
+        Err(ParseEndpointError::UnrecognizedFormat)
+    ));
+}
+
+#[test]
+fn encode_notify_finalized() {
+    let message = encode_message(
+        1,
+        Duration::from_millis(1_690_000_000_123),
+        &Payload::NotifyFinalized {
+            height: 12,
+            best: &[0xab; 32],
+        },
+    );
+
+    assert_eq!(
+        message,
+        "{\"id\":1,\"ts\":\"2023-07-22T04:26:40.123Z\",\"payload\":{\"msg\":\"notify.finalized\",\
+        \"height\":\"12\",\"best\":\"0xabababababababababababababababababababababababababababababababab\"}}"
+    );
+}
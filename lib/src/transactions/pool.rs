@@ -679,6 +679,8 @@ impl<TTx> Pool<TTx> {
         // validated then mark it again as validated.
         self.unvalidate_transaction(id);
         debug_assert!(self.transactions[id.0].validation.is_none());
+        let _was_in = self.not_validated.remove(&id);
+        debug_assert!(_was_in);
 
         // Whether the transaction can be included at the head of the chain. Set to `false` below
         // if there is a reason why not.
@@ -56,4 +56,36 @@ fn basic_includable() {
     assert!(pool.best_block_includable_transactions().next().is_none());
 }
 
+#[test]
+fn validation_removes_from_unvalidated() {
+    let mut pool = Pool::new(Config {
+        capacity: 16,
+        finalized_block_height: 0,
+        randomness_seed: [0; 16],
+    });
+
+    let tx_id = pool.add_unvalidated(vec![], ());
+    assert_eq!(pool.unvalidated_transactions().len(), 1);
+
+    pool.set_validation_result(
+        tx_id,
+        0,
+        ValidTransaction {
+            longevity: NonZeroU64::new(2).unwrap(),
+            priority: 0,
+            propagate: true,
+            provides: Vec::new(),
+            requires: Vec::new(),
+        },
+    );
+    assert_eq!(pool.unvalidated_transactions().len(), 0);
+
+    pool.append_empty_block();
+    assert_eq!(pool.unvalidated_transactions().len(), 0);
+
+    // The validation expires.
+    pool.append_empty_block();
+    assert_eq!(pool.unvalidated_transactions().len(), 1);
+}
+
 // TODO: more tests
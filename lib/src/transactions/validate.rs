@@ -499,6 +499,10 @@ impl Query {
                     result: Err(Error::ForbiddenHostCall),
                     virtual_machine: ctx.into_prototype(),
                 },
+                runtime_host::RuntimeHostVm::GenerateKey(req) => Query::Finished {
+                    result: Err(Error::ForbiddenHostCall),
+                    virtual_machine: runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype(),
+                },
                 runtime_host::RuntimeHostVm::LogEmit(req) => {
                     // Generated logs are ignored.
                     inner = req.resume();
@@ -573,6 +577,10 @@ impl Query {
                     result: Err(Error::ForbiddenHostCall),
                     virtual_machine: ctx.into_prototype(),
                 },
+                runtime_host::RuntimeHostVm::GenerateKey(req) => Query::Finished {
+                    result: Err(Error::ForbiddenHostCall),
+                    virtual_machine: runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype(),
+                },
                 runtime_host::RuntimeHostVm::LogEmit(req) => {
                     // Generated logs are ignored.
                     inner = req.resume();
@@ -37,6 +37,9 @@
 //!
 //! Once decoded, one can examine the content of the proof, in other words the list of storage
 //! items and values.
+//!
+//! Compact proofs, where the Merkle values of the children that are part of the proof are
+//! omitted, must first be converted to regular proofs using [`decode_compact_proof`].
 
 use super::{nibble, trie_node, TrieEntryVersion};
 
@@ -326,6 +329,143 @@ where
     })
 }
 
+/// Converts a compact Merkle proof into a regular Merkle proof, which can then be passed to
+/// [`decode_and_verify_proof`].
+///
+/// In a compact proof, the node values are ordered from the trie root node and in increasing
+/// order of keys, and the references to the children that are part of the proof are empty.
+/// See [`super::proof_encode::ProofBuilder::build_compact`]. A compact proof can contain the
+/// nodes of multiple tries, one after the other.
+///
+/// The returned proof is SCALE-encoded and doesn't contain any duplicate entry.
+///
+/// > **Note**: This function doesn't verify the validity of the proof against a trie root hash.
+/// >           This is done by [`decode_and_verify_proof`].
+pub fn decode_compact_proof(compact_proof: &[u8]) -> Result<Vec<u8>, Error> {
+    // A compact Merkle proof is a SCALE-encoded `Vec<Vec<u8>>`, like a regular proof.
+    let (_, compact_entries) = nom::combinator::all_consuming(nom::combinator::flat_map(
+        crate::util::nom_scale_compact_usize,
+        |num_elems| nom::multi::many_m_n(num_elems, num_elems, crate::util::nom_bytes_decode),
+    ))(compact_proof)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::InvalidFormat)?;
+
+    let mut output = Vec::with_capacity(compact_entries.len());
+    let mut compact_entries = compact_entries.into_iter();
+
+    // A node whose children are being decoded.
+    struct CompactNode<'a> {
+        /// Node value found in the compact proof, without the escape header.
+        node_value: &'a [u8],
+        /// Hash of the storage value, if the storage value was found in the next entry.
+        storage_value_hash: Option<[u8; 32]>,
+        /// Merkle values of the children that have been omitted from the node value.
+        children: [Option<Vec<u8>>; 16],
+        /// Index of the next child to examine.
+        next_child: usize,
+    }
+
+    // Each iteration of this loop decodes the nodes of one trie, starting from its root node.
+    while let Some(root_entry) = compact_entries.next() {
+        // List of nodes from the root of the trie to the node currently being decoded.
+        let mut stack = Vec::<CompactNode>::new();
+        let mut next_entry = Some(root_entry);
+
+        loop {
+            if let Some(entry) = next_entry.take() {
+                let (node_value, storage_value_hash) = match entry.split_first() {
+                    Some((&super::proof_encode::COMPACT_PROOF_ESCAPE_HEADER, node_value)) => {
+                        let storage_value = compact_entries
+                            .next()
+                            .ok_or(Error::IncompleteCompactProof)?;
+                        output.push(storage_value.to_vec());
+                        let hash = *<&[u8; 32]>::try_from(
+                            blake2_rfc::blake2b::blake2b(32, &[], storage_value).as_bytes(),
+                        )
+                        .unwrap();
+                        (node_value, Some(hash))
+                    }
+                    _ => (entry, None),
+                };
+
+                let decoded = trie_node::decode(node_value).map_err(Error::InvalidNodeValue)?;
+                if storage_value_hash.is_some()
+                    && !matches!(decoded.storage_value, trie_node::StorageValue::Unhashed(v) if v.is_empty())
+                {
+                    return Err(Error::InvalidFormat);
+                }
+
+                stack.push(CompactNode {
+                    node_value,
+                    storage_value_hash,
+                    children: Default::default(),
+                    next_child: 0,
+                });
+            }
+
+            // Node values have already been successfully decoded above.
+            let node = stack.last_mut().unwrap();
+            let mut decoded = trie_node::decode(node.node_value).unwrap();
+
+            // If a child has been omitted, its node value is found in the next entry.
+            if let Some(child) = (node.next_child..16)
+                .find(|n| matches!(decoded.children[*n], Some(child) if child.is_empty()))
+            {
+                node.next_child = child + 1;
+                next_entry = Some(
+                    compact_entries
+                        .next()
+                        .ok_or(Error::IncompleteCompactProof)?,
+                );
+                continue;
+            }
+
+            // All the children of the node are known. Rebuild its actual node value.
+            let node = stack.pop().unwrap();
+            for (nibble, child) in node.children.iter().enumerate() {
+                if let Some(child) = child {
+                    decoded.children[nibble] = Some(child);
+                }
+            }
+            if let Some(storage_value_hash) = &node.storage_value_hash {
+                decoded.storage_value = trie_node::StorageValue::Hashed(storage_value_hash);
+            }
+            // `encode_to_vec` can only fail if the node has neither children nor storage value,
+            // which isn't possible as it was successfully decoded.
+            let node_value = trie_node::encode_to_vec(decoded).unwrap();
+
+            let Some(parent) = stack.last_mut() else {
+                // Root node of the trie.
+                output.push(node_value);
+                break;
+            };
+
+            // Nodes of length < 32 are inlined in their parent.
+            parent.children[parent.next_child - 1] = Some(if node_value.len() < 32 {
+                node_value
+            } else {
+                let hash = blake2_rfc::blake2b::blake2b(32, &[], &node_value)
+                    .as_bytes()
+                    .to_vec();
+                output.push(node_value);
+                hash
+            });
+        }
+    }
+
+    // Identical nodes or storage values might be found in multiple places of the tries.
+    output.sort_unstable();
+    output.dedup();
+
+    let mut proof = crate::util::encode_scale_compact_usize(output.len())
+        .as_ref()
+        .to_vec();
+    for entry in output {
+        proof.extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+        proof.extend_from_slice(&entry);
+    }
+    Ok(proof)
+}
+
 /// Equivalent to [`StorageValue`] but contains offsets indexing [`DecodedTrieProof::proof`].
 #[derive(Debug, Copy, Clone)]
 enum StorageValueInner {
@@ -1075,7 +1215,7 @@ impl<'a> fmt::Debug for StorageValue<'a> {
     }
 }
 
-/// Possible error returned by [`decode_and_verify_proof`].
+/// Possible error returned by [`decode_and_verify_proof`] or [`decode_compact_proof`].
 #[derive(Debug, Clone, derive_more::Display)]
 pub enum Error {
     /// Proof is in an invalid format.
@@ -1090,6 +1230,8 @@ pub enum Error {
     /// A node has been passed separately and referred to by its hash, while its length is inferior
     /// to 32 bytes.
     UnexpectedHashedNode,
+    /// A node of a compact proof refers to more entries than the proof contains.
+    IncompleteCompactProof,
 }
 
 /// Information about an entry in the proof.
@@ -1464,4 +1606,139 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn compact_proof_roundtrip() {
+        use super::super::{nibble, proof_encode, trie_node, trie_structure};
+        use core::array;
+        use rand::distributions::{Distribution as _, Uniform};
+
+        // Key, node value, and unhashed storage value of a trie node, as parameters to pass to
+        // `ProofBuilder::set_node_value`.
+        type TrieNode = (Vec<nibble::Nibble>, Vec<u8>, Option<Vec<u8>>);
+
+        // Generates the list of nodes of a random trie.
+        fn random_trie_nodes() -> Vec<TrieNode> {
+            let mut trie = trie_structure::TrieStructure::new();
+            for _ in 0..Uniform::new_inclusive(1, 32).sample(&mut rand::thread_rng()) {
+                let key = (0..Uniform::new_inclusive(0, 12).sample(&mut rand::thread_rng()))
+                    .map(|_| {
+                        nibble::Nibble::try_from(
+                            Uniform::new_inclusive(0, 15).sample(&mut rand::thread_rng()),
+                        )
+                        .unwrap()
+                    })
+                    .collect::<Vec<_>>();
+
+                match trie.node(key.into_iter()) {
+                    trie_structure::Entry::Vacant(e) => {
+                        e.insert_storage_value().insert((), ());
+                    }
+                    trie_structure::Entry::Occupied(trie_structure::NodeAccess::Branch(e)) => {
+                        e.insert_storage_value();
+                    }
+                    trie_structure::Entry::Occupied(trie_structure::NodeAccess::Storage(_)) => {}
+                }
+            }
+
+            let mut nodes = Vec::new();
+            for node_index in trie.iter_unordered().collect::<Vec<_>>() {
+                let node = trie.node_by_index(node_index).unwrap();
+                let key = node.full_key().collect::<Vec<_>>();
+                let partial_key = node.partial_key().collect::<Vec<_>>();
+                let children: [bool; 16] = array::from_fn(|nibble| {
+                    node.child_user_data(
+                        nibble::Nibble::try_from(u8::try_from(nibble).unwrap()).unwrap(),
+                    )
+                    .is_some()
+                });
+
+                let storage_value = (0..Uniform::new_inclusive(0, 64)
+                    .sample(&mut rand::thread_rng()))
+                    .map(|_| Uniform::new_inclusive(0, 255).sample(&mut rand::thread_rng()))
+                    .collect::<Vec<u8>>();
+                // Storage values are randomly put as hashes in the node value. The hash is
+                // later fixed by `make_coherent`.
+                let is_hashed = rand::random::<bool>();
+
+                let node_value = trie_node::encode_to_vec(trie_node::Decoded {
+                    children: array::from_fn(|n| if children[n] { Some(&[][..]) } else { None }),
+                    partial_key: partial_key.into_iter(),
+                    storage_value: match (node.has_storage_value(), is_hashed) {
+                        (false, _) => trie_node::StorageValue::None,
+                        (true, false) => trie_node::StorageValue::Unhashed(&storage_value),
+                        (true, true) => trie_node::StorageValue::Hashed(&[0; 32]),
+                    },
+                })
+                .unwrap();
+
+                let unhashed_storage_value = if node.has_storage_value() && is_hashed {
+                    Some(storage_value)
+                } else {
+                    None
+                };
+
+                nodes.push((key, node_value, unhashed_storage_value));
+            }
+            nodes
+        }
+
+        // We repeat the test many times due to its random factor.
+        for _ in 0..500 {
+            let tries = (0..Uniform::new_inclusive(1, 3).sample(&mut rand::thread_rng()))
+                .map(|_| random_trie_nodes())
+                .collect::<Vec<_>>();
+            let builders = || {
+                tries.iter().map(|nodes| {
+                    let mut builder = proof_encode::ProofBuilder::new();
+                    for (key, node_value, unhashed_storage_value) in nodes {
+                        builder.set_node_value(key, node_value, unhashed_storage_value.as_deref());
+                    }
+                    builder.make_coherent();
+                    builder
+                })
+            };
+
+            let concat = |a: Vec<u8>, b: &dyn AsRef<[u8]>| {
+                let mut a = a;
+                a.extend_from_slice(b.as_ref());
+                a
+            };
+            let proof =
+                proof_encode::build_multiple(builders()).fold(Vec::new(), |a, b| concat(a, &b));
+            let compact_proof = proof_encode::build_compact_multiple(builders())
+                .fold(Vec::new(), |a, b| concat(a, &b));
+
+            let decoded = super::decode_and_verify_proof(super::Config { proof }).unwrap();
+            let decoded_compact = super::decode_and_verify_proof(super::Config {
+                proof: super::decode_compact_proof(&compact_proof).unwrap(),
+            })
+            .unwrap();
+
+            let entries = |decoded: &super::DecodedTrieProof<Vec<u8>>| {
+                decoded
+                    .iter_ordered()
+                    .map(|(key, entry)| {
+                        (
+                            *key.trie_root_hash,
+                            key.key.to_vec(),
+                            entry.node_value.to_vec(),
+                            entry.unhashed_storage_value.map(|v| v.to_vec()),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(entries(&decoded), entries(&decoded_compact));
+        }
+    }
+
+    #[test]
+    fn compact_proof_missing_entry() {
+        // Root node indicating an omitted child, but the child is missing from the proof.
+        let compact_proof = [4, 16, 128, 1, 0, 0];
+        assert!(matches!(
+            super::decode_compact_proof(&compact_proof),
+            Err(super::Error::IncompleteCompactProof)
+        ));
+    }
 }
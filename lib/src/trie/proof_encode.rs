@@ -372,14 +372,17 @@ impl ProofBuilder {
     ///
     /// This function will succeed even if [`ProofBuilder::missing_node_values`] returns a
     /// non-zero number of elements. However, the proof produced will then be invalid.
-    pub fn build(mut self) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> {
+    pub fn build(self) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> {
+        build_multiple(iter::once(self))
+    }
+
+    /// Returns the list of entries to put in the proof, including duplicates.
+    fn into_entries(mut self) -> impl Iterator<Item = Vec<u8>> {
         // Index of the root node in the trie, if any.
         let root_node_index = self.trie_structure.root_node().map(|n| n.node_index());
 
-        // Collect the entries in the proof into a `HashSet` in order to de-duplicate them.
         // TODO: we need to collect the indices into a Vec due to the API of trie_structure not allowing non-mutable access to nodes
-        let entries = self
-            .trie_structure
+        self.trie_structure
             .iter_unordered()
             .collect::<Vec<_>>()
             .into_iter()
@@ -410,18 +413,114 @@ impl ProofBuilder {
                         .chain(trie_structure_value.storage_value_node),
                 )
             })
-            .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>();
+    }
 
-        // The first bytes of the proof contain the number of entries in the proof.
-        let num_entries_encoded = crate::util::encode_scale_compact_usize(entries.len());
+    /// Builds the Merkle proof in the compact format.
+    ///
+    /// In a compact proof, the node values are ordered from the trie root node and in increasing
+    /// order of keys, and the references to the children that are themselves part of the proof
+    /// are left empty, as they can be calculated by the verifier. Storage values that are stored
+    /// as hashes in their node are put right after this node, and the node value is then prefixed
+    /// with an escape header. This is the format of the proofs found in responses to state
+    /// requests.
+    ///
+    /// This function returns an iterator of buffers. The actual Merkle proof consists in the
+    /// concatenation of all the buffers.
+    ///
+    /// This function will succeed even if [`ProofBuilder::missing_node_values`] returns a
+    /// non-zero number of elements. However, the proof produced will then be invalid.
+    pub fn build_compact(self) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> {
+        build_compact_multiple(iter::once(self))
+    }
 
-        // Add the size of each entry before each entry.
-        let entries = entries.into_iter().flat_map(|entry| {
-            let len = crate::util::encode_scale_compact_usize(entry.len());
-            [either::Left(len), either::Right(entry)].into_iter()
-        });
+    /// Returns the list of entries to put in the compact proof, in order.
+    fn into_compact_entries(mut self) -> Vec<Vec<u8>> {
+        let mut output = Vec::new();
+
+        // The nodes are iterated in lexicographic order, in other words each node is traversed
+        // before its children.
+        let mut iter = match self.trie_structure.root_node() {
+            Some(n) => n,
+            None => return output,
+        };
+        let root_node_index = iter.node_index();
+
+        loop {
+            // Due to borrowing issues, we calculate ahead of time which children are part of the
+            // proof and not inlined in their parent.
+            let omitted_children: [bool; 16] = array::from_fn(|nibble| {
+                let nibble = nibble::Nibble::try_from(u8::try_from(nibble).unwrap()).unwrap();
+                matches!(iter.child_user_data(nibble), Some(Some(child)) if child.node_value.len() >= 32)
+            });
+
+            let is_root = iter.node_index() == root_node_index;
+
+            // Nodes whose value is missing are not part of the proof, and nodes of length < 32
+            // are inlined within their parent. In both cases, their descendants are skipped.
+            let traverse_children = match iter.user_data().as_ref() {
+                Some(node_info) if is_root || node_info.node_value.len() >= 32 => {
+                    // We already make sure that node values are valid when inserting them. As
+                    // such, it is ok to `unwrap()` here.
+                    let mut decoded_node_value = trie_node::decode(&node_info.node_value).unwrap();
+
+                    for (nibble, omitted) in omitted_children.iter().enumerate() {
+                        if *omitted {
+                            decoded_node_value.children[nibble] = Some(&[]);
+                        }
+                    }
+
+                    // If the unhashed storage value is known, it is put right after the node
+                    // value rather than its hash.
+                    let storage_value = match (
+                        &decoded_node_value.storage_value,
+                        &node_info.storage_value_node,
+                    ) {
+                        (trie_node::StorageValue::Hashed(_), Some(storage_value)) => {
+                            decoded_node_value.storage_value =
+                                trie_node::StorageValue::Unhashed(&[]);
+                            Some(storage_value.clone())
+                        }
+                        _ => None,
+                    };
 
-        iter::once(either::Left(num_entries_encoded)).chain(entries.into_iter().map(either::Right))
+                    let mut entry = Vec::with_capacity(node_info.node_value.len() + 1);
+                    if storage_value.is_some() {
+                        entry.push(COMPACT_PROOF_ESCAPE_HEADER);
+                    }
+                    // `encode` can return an error only if there's no children and no storage
+                    // value, which can't happen as the node value was valid when decoded.
+                    for buffer in trie_node::encode(decoded_node_value).unwrap() {
+                        entry.extend_from_slice(buffer.as_ref());
+                    }
+
+                    output.push(entry);
+                    output.extend(storage_value);
+                    true
+                }
+                _ => false,
+            };
+
+            // Jump to the next node in lexicographic order.
+            if traverse_children {
+                match iter.into_first_child() {
+                    Ok(child) => {
+                        iter = child;
+                        continue;
+                    }
+                    Err(node) => iter = node,
+                }
+            }
+
+            iter = loop {
+                match iter.into_next_sibling() {
+                    Ok(sibling) => break sibling,
+                    Err(node) => match node.into_parent() {
+                        Some(parent) => iter = parent,
+                        None => return output, // Finished.
+                    },
+                }
+            };
+        }
     }
 
     /// Similar to [`ProofBuilder::build`], but returns a `Vec`.
@@ -441,6 +540,84 @@ impl Default for ProofBuilder {
     }
 }
 
+/// Builds a single Merkle proof containing the entries of all the given [`ProofBuilder`]s.
+///
+/// A [`ProofBuilder`] can only contain the nodes of a single trie. This function is useful in
+/// order to build a proof that concerns multiple tries, such as the main trie and one or more
+/// child tries.
+///
+/// This function returns an iterator of buffers. The actual Merkle proof consists in the
+/// concatenation of all the buffers.
+///
+/// See also [`ProofBuilder::build`].
+pub fn build_multiple(
+    builders: impl Iterator<Item = ProofBuilder>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> {
+    let entries = build_multiple_entries(builders);
+
+    // The first bytes of the proof contain the number of entries in the proof.
+    let num_entries_encoded = crate::util::encode_scale_compact_usize(entries.len());
+
+    // Add the size of each entry before each entry.
+    let entries = entries.into_iter().flat_map(|entry| {
+        let len = crate::util::encode_scale_compact_usize(entry.len());
+        [either::Left(len), either::Right(entry)].into_iter()
+    });
+
+    iter::once(either::Left(num_entries_encoded)).chain(entries.into_iter().map(either::Right))
+}
+
+/// Builds a single compact Merkle proof containing the entries of all the given
+/// [`ProofBuilder`]s.
+///
+/// The entries of each trie are put one after the other, in the order in which the builders are
+/// provided. When a proof concerns both the main trie and child tries, the main trie must come
+/// first, followed with the child tries in increasing order of child trie keys.
+///
+/// This function returns an iterator of buffers. The actual Merkle proof consists in the
+/// concatenation of all the buffers.
+///
+/// See also [`ProofBuilder::build_compact`].
+pub fn build_compact_multiple(
+    builders: impl Iterator<Item = ProofBuilder>,
+) -> impl Iterator<Item = impl AsRef<[u8]> + Clone> {
+    // Contrary to regular proofs, the entries must not be de-duplicated, as their order is
+    // meaningful.
+    let entries = builders
+        .flat_map(|builder| builder.into_compact_entries())
+        .collect::<Vec<_>>();
+
+    // The first bytes of the proof contain the number of entries in the proof.
+    let num_entries_encoded = crate::util::encode_scale_compact_usize(entries.len());
+
+    // Add the size of each entry before each entry.
+    let entries = entries.into_iter().flat_map(|entry| {
+        let len = crate::util::encode_scale_compact_usize(entry.len());
+        [either::Left(len), either::Right(entry)].into_iter()
+    });
+
+    iter::once(either::Left(num_entries_encoded)).chain(entries.into_iter().map(either::Right))
+}
+
+/// Returns the list of entries of the Merkle proof that [`build_multiple`] builds, in other
+/// words the list of trie node values and storage values, without the length prefixes.
+///
+/// The entries are de-duplicated and returned in an unspecified order. This is the format that
+/// the `state_getReadProof` JSON-RPC function uses.
+pub fn build_multiple_entries(
+    builders: impl Iterator<Item = ProofBuilder>,
+) -> impl ExactSizeIterator<Item = Vec<u8>> {
+    // Collect the entries in the proof into a `HashSet` in order to de-duplicate them.
+    builders
+        .flat_map(|builder| builder.into_entries())
+        .collect::<hashbrown::HashSet<_, fnv::FnvBuildHasher>>()
+        .into_iter()
+}
+
+/// Byte put in front of the node values of a compact proof whose storage value is found in the
+/// next entry of the proof. This value can't be the first byte of a valid node value.
+pub(super) const COMPACT_PROOF_ESCAPE_HEADER: u8 = 0x01;
+
 fn blake2_hash(data: &[u8]) -> [u8; 32] {
     <[u8; 32]>::try_from(blake2_rfc::blake2b::blake2b(32, &[], data).as_bytes()).unwrap()
 }
@@ -448,7 +625,7 @@ fn blake2_hash(data: &[u8]) -> [u8; 32] {
 #[cfg(test)]
 mod tests {
     use super::super::{nibble, proof_decode, trie_node, trie_structure};
-    use core::array;
+    use core::{array, iter};
     use rand::distributions::{Distribution as _, Uniform};
 
     #[test]
@@ -663,4 +840,115 @@ mod tests {
         })
         .unwrap();
     }
+
+    #[test]
+    fn build_multiple_merges_tries() {
+        let mut proof_builder1 = super::ProofBuilder::new();
+        proof_builder1.set_node_value(
+            &nibble::bytes_to_nibbles([1, 2, 3, 4].into_iter()).collect::<Vec<_>>(),
+            &[72, 1, 2, 3, 4, 20, 104, 101, 108, 108, 111],
+            None,
+        );
+
+        // Identical to the first builder. Its entries must be de-duplicated.
+        let mut proof_builder2 = super::ProofBuilder::new();
+        proof_builder2.set_node_value(
+            &nibble::bytes_to_nibbles([1, 2, 3, 4].into_iter()).collect::<Vec<_>>(),
+            &[72, 1, 2, 3, 4, 20, 104, 101, 108, 108, 111],
+            None,
+        );
+
+        let mut proof_builder3 = super::ProofBuilder::new();
+        proof_builder3.set_node_value(
+            &nibble::bytes_to_nibbles([5, 6].into_iter()).collect::<Vec<_>>(),
+            &[68, 5, 6, 12, 102, 111, 111],
+            None,
+        );
+
+        let proof =
+            super::build_multiple([proof_builder1, proof_builder2, proof_builder3].into_iter())
+                .fold(Vec::new(), |mut a, b| {
+                    a.extend_from_slice(b.as_ref());
+                    a
+                });
+
+        let decoded =
+            proof_decode::decode_and_verify_proof(proof_decode::Config { proof }).unwrap();
+        assert_eq!(decoded.iter_ordered().count(), 2);
+    }
+
+    #[test]
+    fn build_compact_omits_children_and_detaches_values() {
+        let nibbles = |n: &[u8]| {
+            n.iter()
+                .map(|n| nibble::Nibble::try_from(*n).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Root node with two leaf children. The first leaf contains its storage value, while the
+        // second one contains the hash of its storage value.
+        let root_node_value = |children: [Option<&[u8]>; 16]| {
+            trie_node::encode_to_vec(trie_node::Decoded {
+                children,
+                partial_key: iter::empty(),
+                storage_value: trie_node::StorageValue::None,
+            })
+            .unwrap()
+        };
+        let leaf1_node_value = trie_node::encode_to_vec(trie_node::Decoded {
+            children: [None::<&[u8]>; 16],
+            partial_key: nibbles(&[5]).into_iter(),
+            storage_value: trie_node::StorageValue::Unhashed(&[0xaa; 40]),
+        })
+        .unwrap();
+        let leaf2_storage_value = [0xbb; 40];
+        let leaf2_node_value = |storage_value: trie_node::StorageValue| {
+            trie_node::encode_to_vec(trie_node::Decoded {
+                children: [None::<&[u8]>; 16],
+                partial_key: nibbles(&[6]).into_iter(),
+                storage_value,
+            })
+            .unwrap()
+        };
+
+        let mut children = [None; 16];
+        children[0] = Some(&[][..]);
+        children[1] = Some(&[][..]);
+
+        let mut proof_builder = super::ProofBuilder::new();
+        proof_builder.set_node_value(&[], &root_node_value(children), None);
+        proof_builder.set_node_value(&nibbles(&[0, 5]), &leaf1_node_value, None);
+        proof_builder.set_node_value(
+            &nibbles(&[1, 6]),
+            &leaf2_node_value(trie_node::StorageValue::Hashed(&super::blake2_hash(
+                &leaf2_storage_value,
+            ))),
+            Some(&leaf2_storage_value),
+        );
+        proof_builder.make_coherent();
+
+        let proof = proof_builder.build_compact().fold(Vec::new(), |mut a, b| {
+            a.extend_from_slice(b.as_ref());
+            a
+        });
+
+        let expected_entries = [
+            root_node_value(children),
+            leaf1_node_value,
+            iter::once(super::COMPACT_PROOF_ESCAPE_HEADER)
+                .chain(leaf2_node_value(trie_node::StorageValue::Unhashed(&[])))
+                .collect(),
+            leaf2_storage_value.to_vec(),
+        ];
+        let mut expected = crate::util::encode_scale_compact_usize(expected_entries.len())
+            .as_ref()
+            .to_vec();
+        for entry in expected_entries {
+            expected
+                .extend_from_slice(crate::util::encode_scale_compact_usize(entry.len()).as_ref());
+            expected.extend_from_slice(&entry);
+        }
+
+        assert_eq!(proof, expected);
+    }
 }
@@ -555,7 +555,7 @@ macro_rules! gen_calculate_primary_threshold {
         /// Panics if `authorities_weights` is empty.
         /// Panics if `authority_weight` is 0.
         ///
-        fn $name(
+        pub(crate) fn $name(
             c: (u64, u64),
             authorities_weights: impl Iterator<Item = u64>,
             authority_weight: u64, // TODO: use a NonZeroU64 once crate::header also has weights that use NonZeroU64
@@ -508,6 +508,12 @@ impl VerifyInner {
                 (runtime_host::RuntimeHostVm::Offchain(ctx), _phase) => {
                     return Verify::Finished(Err((Error::ForbiddenHostCall, ctx.into_prototype())))
                 }
+                (runtime_host::RuntimeHostVm::GenerateKey(req), _phase) => {
+                    return Verify::Finished(Err((
+                        Error::ForbiddenHostCall,
+                        runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype(),
+                    )))
+                }
             }
         }
     }
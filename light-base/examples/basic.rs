@@ -67,6 +67,10 @@ fn main() {
             // This field is necessary only if adding a parachain.
             potential_relay_chains: iter::empty(),
 
+            // If `true`, the client reports information about itself to the telemetry servers
+            // found in the chain specification. Telemetry is opt-in for privacy reasons.
+            enable_telemetry: false,
+
             // After a chain has been added, it is possible to extract a "database" (in the form of a
             // simple string). This database can later be passed back the next time the same chain is
             // added again.
@@ -102,6 +106,7 @@ fn main() {
             },
             database_content: "",
             user_data: (),
+            enable_telemetry: false,
 
             // The chain specification of the asset hub parachain mentions that the identifier
             // of its relay chain is `polkadot`. Because the `Client` might contain multiple different
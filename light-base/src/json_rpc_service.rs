@@ -92,6 +92,7 @@ pub fn service(config: Config) -> (Frontend, ServicePrototype) {
     let (requests_processing_task, requests_responses_io) =
         service::client_main_task(service::Config {
             max_active_subscriptions: config.max_subscriptions,
+            subscriptions_supported: true,
             max_pending_requests: config.max_pending_requests,
         });
 
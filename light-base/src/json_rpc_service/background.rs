@@ -909,6 +909,7 @@ impl<TPlat: PlatformRef> Background<TPlat> {
         let pinned_runtime_id = self
             .runtime_service
             .compile_and_pin_runtime(
+                block_number,
                 storage_code,
                 storage_heap_pages,
                 code_merkle_value,
@@ -1139,6 +1140,11 @@ impl<TPlat: PlatformRef> Background<TPlat> {
                         .unlock(runtime_host::RuntimeHostVm::Offchain(ctx).into_prototype());
                     break Err(RuntimeCallError::ForbiddenHostCall);
                 }
+                runtime_host::RuntimeHostVm::GenerateKey(req) => {
+                    runtime_call_lock
+                        .unlock(runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype());
+                    break Err(RuntimeCallError::ForbiddenHostCall);
+                }
                 runtime_host::RuntimeHostVm::LogEmit(log) => {
                     // Logs are ignored.
                     runtime_call = log.resume();
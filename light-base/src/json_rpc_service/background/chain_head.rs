@@ -1510,6 +1510,18 @@ impl<TPlat: PlatformRef> ChainHeadFollowTask<TPlat> {
                                             }).await;
                                             break;
                                         }
+                                        runtime_host::RuntimeHostVm::GenerateKey(req) => {
+                                            runtime_call_lock.unlock(runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype());
+                                            let _ = to_main_task.send(OperationEvent {
+                                                operation_id: operation_id.clone(),
+                                                is_done: true,
+                                                notification: methods::FollowEvent::OperationError {
+                                                    operation_id: operation_id.clone().into(),
+                                                    error: "Runtime has called a keystore host function".to_string().into(),
+                                                }
+                                            }).await;
+                                            break;
+                                        }
                                         runtime_host::RuntimeHostVm::LogEmit(log) => {
                                             // Logs are ignored. 
                                             runtime_call = log.resume();
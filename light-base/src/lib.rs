@@ -85,7 +85,16 @@
 
 extern crate alloc;
 
-use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
 use core::{num::NonZeroU32, ops, pin, time::Duration};
 use futures_util::FutureExt as _;
 use hashbrown::{hash_map::Entry, HashMap};
@@ -101,6 +110,7 @@ mod json_rpc_service;
 mod network_service;
 mod runtime_service;
 mod sync_service;
+mod telemetry_service;
 mod transactions_service;
 mod util;
 
@@ -142,6 +152,14 @@ pub struct AddChainConfig<'a, TChain, TRelays> {
 
     /// Configuration for the JSON-RPC endpoint.
     pub json_rpc: AddChainConfigJsonRpc,
+
+    /// If `true`, the client reports information about itself and the chain, such as its name,
+    /// version, and best and finalized blocks, to the telemetry servers listed in the chain
+    /// specification.
+    ///
+    /// Ignored if the chain was already running because of a previous call to
+    /// [`Client::add_chain`], in which case the value passed during that previous call applies.
+    pub enable_telemetry: bool,
 }
 
 /// See [`AddChainConfig::json_rpc`].
@@ -254,13 +272,18 @@ struct ChainKey {
     genesis_block_hash: [u8; 32],
 
     // TODO: what about light checkpoints?
-    // TODO: must also contain forkBlocks, and badBlocks fields
     /// If the chain is a parachain, contains the relay chain and the "para ID" on this relay
     /// chain.
     relay_chain: Option<(Box<ChainKey>, u32)>,
 
     /// Networking fork id, found in the chain specification.
     fork_id: Option<String>,
+
+    /// Fork blocks, found in the chain specification.
+    fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// Bad blocks, found in the chain specification.
+    bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 struct RunningChain<TPlat: platform::PlatformRef> {
@@ -610,6 +633,11 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                 )
             }),
             fork_id: chain_spec.fork_id().map(|f| f.to_owned()),
+            fork_blocks: chain_spec
+                .fork_blocks()
+                .map(|(block_number, hash)| (block_number, *hash))
+                .collect(),
+            bad_blocks: chain_spec.bad_blocks_hashes().copied().collect(),
         };
 
         // If the chain we are adding is a parachain, grab the services of the relay chain.
@@ -682,6 +710,7 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                 }
 
                 // Start the services of the new chain.
+                let enable_telemetry = config.enable_telemetry;
                 let services = {
                     // Version of the client when requested through the networking.
                     let network_identify_agent_version = format!(
@@ -710,25 +739,52 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                                 para_id: *para_id,
                             }
                         }
-                        (None, Some(chain_information)) => {
-                            StartServicesChainTy::RelayChain { chain_information }
-                        }
+                        (None, Some(chain_information)) => StartServicesChainTy::RelayChain {
+                            chain_information,
+                            fork_blocks: new_chain_key.fork_blocks.clone(),
+                            bad_blocks: new_chain_key.bad_blocks.clone(),
+                        },
                         (None, None) => {
                             // Checked above.
                             unreachable!()
                         }
                     };
 
-                    start_services(
+                    let genesis_block_hash =
+                        header::hash_from_scale_encoded_header(&genesis_block_header);
+
+                    let services = start_services(
                         log_name.clone(),
                         &self.platform,
                         runtime_code_hint,
                         genesis_block_header,
                         usize::from(chain_spec.block_number_bytes()),
                         chain_spec.fork_id().map(|f| f.to_owned()),
+                        chain_spec
+                            .code_substitutes()
+                            .map(|(block_number, code)| (block_number, code.to_vec()))
+                            .collect(),
                         config,
                         network_identify_agent_version,
-                    )
+                    );
+
+                    // Report to the telemetry servers of the chain, if any and if the API user
+                    // has opted in.
+                    if enable_telemetry {
+                        telemetry_service::start(telemetry_service::Config {
+                            log_name: log_name.clone(),
+                            platform: self.platform.clone(),
+                            endpoints: chain_spec
+                                .telemetry_endpoints()
+                                .map(|(address, verbosity)| (address.to_owned(), verbosity))
+                                .collect(),
+                            chain_name: chain_spec.name().to_owned(),
+                            genesis_block_hash,
+                            sync_service: services.sync_service.clone(),
+                        });
+                    }
+
+                    services
                 };
 
                 // Note that the chain name is printed through the `Debug` trait (rather
@@ -784,28 +840,16 @@ impl<TPlat: platform::PlatformRef, TChain> Client<TPlat, TChain> {
                     );
                 }
 
-                if chain_spec.telemetry_endpoints().count() != 0 {
-                    log::warn!(
-                        target: "smoldot",
-                        "Chain specification of {} contains a non-empty \
-                        `telemetryEndpoints` field. Smoldot doesn't support telemetry \
-                        endpoints and as such this field is unused.", log_name
-                    );
-                }
-
                 // TODO: remove after https://github.com/paritytech/smoldot/issues/2584
-                if chain_spec.bad_blocks_hashes().count() != 0 {
+                if relay_chain.is_some() && chain_spec.bad_blocks_hashes().count() != 0 {
                     log::warn!(
                         target: "smoldot",
                         "Chain specification of {} contains a list of bad blocks. Bad \
-                        blocks are not implemented in the light client. An appropriate \
-                        way to silence this warning is to remove the bad blocks from the \
-                        chain specification, which can safely be done:\n\
-                        - For relay chains: if the chain specification contains a \
-                        checkpoint and that the bad blocks have a block number inferior \
-                        to this checkpoint.\n\
-                        - For parachains: if the bad blocks have a block number inferior \
-                        to the current parachain finalized block.", log_name
+                        blocks are not implemented in the light client for parachains. An \
+                        appropriate way to silence this warning is to remove the bad blocks \
+                        from the chain specification, which can safely be done if the bad \
+                        blocks have a block number inferior to the current parachain \
+                        finalized block.", log_name
                     );
                 }
 
@@ -1070,6 +1114,8 @@ pub enum AddChainError {
 enum StartServicesChainTy<'a, TPlat: platform::PlatformRef> {
     RelayChain {
         chain_information: &'a chain::chain_information::ValidChainInformation,
+        fork_blocks: BTreeMap<u64, [u8; 32]>,
+        bad_blocks: BTreeSet<[u8; 32]>,
     },
     Parachain {
         relay_chain: &'a ChainServices<TPlat>,
@@ -1089,6 +1135,7 @@ fn start_services<TPlat: platform::PlatformRef>(
     genesis_block_scale_encoded_header: Vec<u8>,
     block_number_bytes: usize,
     fork_id: Option<String>,
+    code_substitutes: BTreeMap<u64, Vec<u8>>,
     config: StartServicesChainTy<'_, TPlat>,
     network_identify_agent_version: String,
 ) -> ChainServices<TPlat> {
@@ -1104,6 +1151,7 @@ fn start_services<TPlat: platform::PlatformRef>(
                 num_out_slots: 4,
                 grandpa_protocol_finalized_block_height: if let StartServicesChainTy::RelayChain {
                     chain_information,
+                    ..
                 } = &config
                 {
                     if matches!(
@@ -1122,7 +1170,9 @@ fn start_services<TPlat: platform::PlatformRef>(
                     &genesis_block_scale_encoded_header,
                 ),
                 best_block: match &config {
-                    StartServicesChainTy::RelayChain { chain_information } => (
+                    StartServicesChainTy::RelayChain {
+                        chain_information, ..
+                    } => (
                         chain_information.as_ref().finalized_block_header.number,
                         chain_information
                             .as_ref()
@@ -1194,12 +1244,17 @@ fn start_services<TPlat: platform::PlatformRef>(
                     platform: platform.clone(),
                     sync_service: sync_service.clone(),
                     genesis_block_scale_encoded_header,
+                    code_substitutes,
                 },
             ));
 
             (sync_service, runtime_service)
         }
-        StartServicesChainTy::RelayChain { chain_information } => {
+        StartServicesChainTy::RelayChain {
+            chain_information,
+            fork_blocks,
+            bad_blocks,
+        } => {
             // Chain is a relay chain.
 
             // The sync service is leveraging the network service, downloads block headers,
@@ -1213,6 +1268,8 @@ fn start_services<TPlat: platform::PlatformRef>(
                 chain_type: sync_service::ConfigChainType::RelayChain(
                     sync_service::ConfigRelayChain {
                         chain_information: chain_information.clone(),
+                        fork_blocks,
+                        bad_blocks,
                         runtime_code_hint: runtime_code_hint.map(|hint| {
                             sync_service::ConfigRelayChainRuntimeCodeHint {
                                 storage_value: hint.code,
@@ -1232,6 +1289,7 @@ fn start_services<TPlat: platform::PlatformRef>(
                     platform: platform.clone(),
                     sync_service: sync_service.clone(),
                     genesis_block_scale_encoded_header,
+                    code_substitutes,
                 },
             ));
 
@@ -41,7 +41,7 @@ use crate::platform::{self, address_parse, PlatformRef};
 use alloc::{
     borrow::ToOwned as _,
     boxed::Box,
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     format,
     string::{String, ToString as _},
     sync::Arc,
@@ -181,6 +181,9 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
                     genesis_hash: chain.genesis_block_hash,
                     role: Role::Light,
                     allow_inbound_block_requests: false,
+                    allow_inbound_grandpa_warp_sync_requests: false,
+                    allow_inbound_storage_and_call_proof_requests: false,
+                    allow_inbound_state_requests: false,
                     user_data: Chain {
                         log_name: chain.log_name.clone(),
                         block_number_bytes: chain.block_number_bytes,
@@ -225,7 +228,7 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
             next_recent_connection_restore: None,
             platform: config.platform.clone(),
             open_gossip_links: BTreeMap::new(),
-            event_pending_send: None,
+            event_pending_send: VecDeque::new(),
             event_senders: either::Left(Vec::new()),
             pending_new_subscriptions: Vec::new(),
             important_nodes: HashSet::with_capacity_and_hasher(16, Default::default()),
@@ -610,6 +613,30 @@ impl<TPlat: PlatformRef> NetworkService<TPlat> {
         rx.await.unwrap()
     }
 
+    /// Bans the given peer from the given chain for the given duration, and closes the gossip
+    /// link with this peer, if any.
+    ///
+    /// If a gossip link with this peer was open, an [`Event::Disconnected`] is generated.
+    ///
+    /// The `reason` is used only for logging purposes.
+    pub async fn ban_and_disconnect(
+        &self,
+        peer_id: PeerId,
+        chain_id: ChainId,
+        ban_duration: Duration,
+        reason: &'static str,
+    ) {
+        self.messages_tx
+            .send(ToBackground::BanAndDisconnect {
+                peer_id,
+                chain_id,
+                ban_duration,
+                reason,
+            })
+            .await
+            .unwrap();
+    }
+
     /// Marks the given peers as belonging to the given chain, and adds some addresses to these
     /// peers to the address book.
     ///
@@ -821,6 +848,12 @@ enum ToBackground {
         is_best: bool,
         result: oneshot::Sender<Result<(), QueueNotificationError>>,
     },
+    BanAndDisconnect {
+        peer_id: PeerId,
+        chain_id: ChainId,
+        ban_duration: Duration,
+        reason: &'static str,
+    },
     Discover {
         chain_id: ChainId,
         list: vec::IntoIter<(PeerId, vec::IntoIter<Multiaddr>)>,
@@ -887,8 +920,10 @@ struct BackgroundTask<TPlat: PlatformRef> {
     // TODO: should also detect whenever we fail to open a block announces substream with any of these peers
     important_nodes: HashSet<PeerId, fnv::FnvBuildHasher>,
 
-    /// Event about to be sent on the senders of [`BackgroundTask::event_senders`].
-    event_pending_send: Option<(ChainId, Event)>,
+    /// Events about to be sent on the senders of [`BackgroundTask::event_senders`].
+    ///
+    /// No new event is pulled from [`BackgroundTask::network`] while this list isn't empty.
+    event_pending_send: VecDeque<(ChainId, Event)>,
 
     /// Sending events through the public API.
     ///
@@ -994,7 +1029,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 }
             };
             let service_event = async {
-                if let Some(event) = (task.event_pending_send.is_none()
+                if let Some(event) = (task.event_pending_send.is_empty()
                     && task.pending_new_subscriptions.is_empty())
                 .then(|| task.network.next_event())
                 .flatten()
@@ -1087,7 +1122,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     let event_senders = event_sending_future.await;
                     task.event_senders = either::Left(event_senders);
                     WakeUpReason::EventSendersReady
-                } else if task.event_pending_send.is_some()
+                } else if !task.event_pending_send.is_empty()
                     || !task.pending_new_subscriptions.is_empty()
                 {
                     WakeUpReason::EventSendersReady
@@ -1126,7 +1161,7 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 };
 
                 if let Some((event_to_dispatch_chain_id, event_to_dispatch)) =
-                    task.event_pending_send.take()
+                    task.event_pending_send.pop_front()
                 {
                     let mut event_senders = mem::take(event_senders);
                     task.event_senders = either::Right(Box::pin(async move {
@@ -1404,6 +1439,58 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     is_best,
                 ));
             }
+            WakeUpReason::Message(ToBackground::BanAndDisconnect {
+                peer_id,
+                chain_id,
+                ban_duration,
+                reason,
+            }) => {
+                if matches!(
+                    task.peering_strategy.unassign_slot_and_ban(
+                        &chain_id,
+                        &peer_id,
+                        task.platform.now() + ban_duration,
+                    ),
+                    basic_peering_strategy::UnassignSlotAndBan::Banned { had_slot: true }
+                ) {
+                    log::debug!(
+                        target: "network",
+                        "Slots({}) ∌ {} (reason={}, ban-duration={:?})",
+                        &task.network[chain_id].log_name,
+                        peer_id,
+                        reason,
+                        ban_duration
+                    );
+                    task.network.gossip_remove_desired(
+                        chain_id,
+                        &peer_id,
+                        service::GossipKind::ConsensusTransactions,
+                    );
+                }
+
+                // Closing the gossip link doesn't generate any event, and we thus need to
+                // generate the `Disconnected` event ourselves.
+                let _ = task.network.gossip_close(
+                    chain_id,
+                    &peer_id,
+                    service::GossipKind::ConsensusTransactions,
+                );
+                if task
+                    .open_gossip_links
+                    .remove(&(chain_id, peer_id.clone()))
+                    .is_some()
+                {
+                    log::debug!(
+                        target: "network",
+                        "Gossip({}, {}) <= Close(reason={})",
+                        &task.network[chain_id].log_name,
+                        peer_id,
+                        reason
+                    );
+                    task.event_pending_send
+                        .push_back((chain_id, Event::Disconnected { peer_id }));
+                }
+            }
             WakeUpReason::Message(ToBackground::Discover {
                 chain_id,
                 list,
@@ -1624,9 +1711,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     }
                 }
 
-                debug_assert!(task.event_pending_send.is_none());
-                task.event_pending_send =
-                    Some((chain_id, Event::BlockAnnounce { peer_id, announce }));
+                debug_assert!(task.event_pending_send.is_empty());
+                task.event_pending_send
+                    .push_back((chain_id, Event::BlockAnnounce { peer_id, announce }));
             }
             WakeUpReason::NetworkEvent(service::Event::GossipConnected {
                 peer_id,
@@ -1656,8 +1743,8 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 );
                 debug_assert!(_prev_value.is_none());
 
-                debug_assert!(task.event_pending_send.is_none());
-                task.event_pending_send = Some((
+                debug_assert!(task.event_pending_send.is_empty());
+                task.event_pending_send.push_back((
                     chain_id,
                     Event::Connected {
                         peer_id,
@@ -1755,8 +1842,9 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     );
                 }
 
-                debug_assert!(task.event_pending_send.is_none());
-                task.event_pending_send = Some((chain_id, Event::Disconnected { peer_id }));
+                debug_assert!(task.event_pending_send.is_empty());
+                task.event_pending_send
+                    .push_back((chain_id, Event::Disconnected { peer_id }));
             }
             WakeUpReason::NetworkEvent(service::Event::RequestResult {
                 substream_id,
@@ -1998,7 +2086,13 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                 task.network
                     .respond_identify(substream_id, &task.identify_agent_version);
             }
-            WakeUpReason::NetworkEvent(service::Event::BlocksRequestIn { .. }) => unreachable!(),
+            WakeUpReason::NetworkEvent(service::Event::BlocksRequestIn { .. })
+            | WakeUpReason::NetworkEvent(service::Event::GrandpaWarpSyncRequestIn { .. })
+            | WakeUpReason::NetworkEvent(service::Event::StorageProofRequestIn { .. })
+            | WakeUpReason::NetworkEvent(service::Event::CallProofRequestIn { .. })
+            | WakeUpReason::NetworkEvent(service::Event::StateRequestIn { .. }) => {
+                unreachable!()
+            }
             WakeUpReason::NetworkEvent(service::Event::RequestInCancel { .. }) => {
                 // All incoming requests are immediately answered.
                 unreachable!()
@@ -2023,8 +2117,8 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     .unwrap()
                     .finalized_block_height = Some(state.commit_finalized_height);
 
-                debug_assert!(task.event_pending_send.is_none());
-                task.event_pending_send = Some((
+                debug_assert!(task.event_pending_send.is_empty());
+                task.event_pending_send.push_back((
                     chain_id,
                     Event::GrandpaNeighborPacket {
                         peer_id,
@@ -2045,9 +2139,16 @@ async fn background_task<TPlat: PlatformRef>(mut task: BackgroundTask<TPlat>) {
                     HashDisplay(message.decode().message.target_hash),
                 );
 
-                debug_assert!(task.event_pending_send.is_none());
-                task.event_pending_send =
-                    Some((chain_id, Event::GrandpaCommitMessage { peer_id, message }));
+                debug_assert!(task.event_pending_send.is_empty());
+                task.event_pending_send
+                    .push_back((chain_id, Event::GrandpaCommitMessage { peer_id, message }));
+            }
+            WakeUpReason::NetworkEvent(
+                service::Event::GrandpaVoteMessage { .. }
+                | service::Event::GrandpaCatchUpRequest { .. }
+                | service::Event::GrandpaCatchUpMessage { .. },
+            ) => {
+                // Light clients don't participate in GrandPa voting.
             }
             WakeUpReason::NetworkEvent(service::Event::ProtocolError { peer_id, error }) => {
                 // TODO: handle properly?
@@ -384,6 +384,8 @@ pub enum Address<'a> {
         ip: IpAddr,
         /// TCP port to connect to.
         port: u16,
+        /// Path of the URL to request during the WebSocket handshake. Always starts with `/`.
+        path: &'a str,
     },
 
     /// WebSocket connection with a domain name.
@@ -399,6 +401,8 @@ pub enum Address<'a> {
         hostname: &'a str,
         /// TCP port to connect to.
         port: u16,
+        /// Path of the URL to request during the WebSocket handshake. Always starts with `/`.
+        path: &'a str,
         /// `true` for WebSocket secure connections.
         secure: bool,
     },
@@ -73,12 +73,14 @@ pub fn multiaddr_to_address(multiaddr: &Multiaddr) -> Result<AddressOrMultiStrea
             AddressOrMultiStreamAddress::Address(Address::WebSocketIp {
                 ip: IpAddr::V4(ip),
                 port,
+                path: "/",
             })
         }
         (Protocol::Ip6(ip), Protocol::Tcp(port), Some(Protocol::Ws), None) => {
             AddressOrMultiStreamAddress::Address(Address::WebSocketIp {
                 ip: IpAddr::V6(ip),
                 port,
+                path: "/",
             })
         }
         (
@@ -89,6 +91,7 @@ pub fn multiaddr_to_address(multiaddr: &Multiaddr) -> Result<AddressOrMultiStrea
         ) => AddressOrMultiStreamAddress::Address(Address::WebSocketDns {
             hostname: str::from_utf8(addr.into_bytes()).map_err(Error::NonUtf8DomainName)?,
             port,
+            path: "/",
             secure: false,
         }),
         (
@@ -105,6 +108,7 @@ pub fn multiaddr_to_address(multiaddr: &Multiaddr) -> Result<AddressOrMultiStrea
         ) => AddressOrMultiStreamAddress::Address(Address::WebSocketDns {
             hostname: str::from_utf8(addr.into_bytes()).map_err(Error::NonUtf8DomainName)?,
             port,
+            path: "/",
             secure: true,
         }),
 
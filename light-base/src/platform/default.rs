@@ -163,9 +163,9 @@ impl PlatformRef for Arc<DefaultPlatform> {
     }
 
     fn connect_stream(&self, multiaddr: Address) -> Self::StreamConnectFuture {
-        let (tcp_socket_addr, host_if_websocket): (
+        let (tcp_socket_addr, host_and_path_if_websocket): (
             either::Either<SocketAddr, (String, u16)>,
-            Option<String>,
+            Option<(String, String)>,
         ) = match multiaddr {
             Address::TcpDns { hostname, port } => {
                 (either::Right((hostname.to_string(), port)), None)
@@ -181,24 +181,33 @@ impl PlatformRef for Arc<DefaultPlatform> {
             Address::WebSocketDns {
                 hostname,
                 port,
+                path,
                 secure: false,
             } => (
                 either::Right((hostname.to_string(), port)),
-                Some(format!("{}:{}", hostname, port)),
+                Some((format!("{}:{}", hostname, port), path.to_owned())),
             ),
             Address::WebSocketIp {
                 ip: IpAddr::V4(ip),
                 port,
+                path,
             } => {
                 let addr = SocketAddr::from((ip, port));
-                (either::Left(addr), Some(addr.to_string()))
+                (
+                    either::Left(addr),
+                    Some((addr.to_string(), path.to_owned())),
+                )
             }
             Address::WebSocketIp {
                 ip: IpAddr::V6(ip),
                 port,
+                path,
             } => {
                 let addr = SocketAddr::from((ip, port));
-                (either::Left(addr), Some(addr.to_string()))
+                (
+                    either::Left(addr),
+                    Some((addr.to_string(), path.to_owned())),
+                )
             }
 
             // The API user of the `PlatformRef` trait is never supposed to open connections of
@@ -216,12 +225,12 @@ impl PlatformRef for Arc<DefaultPlatform> {
                 let _ = tcp_socket.set_nodelay(true);
             }
 
-            match (tcp_socket, host_if_websocket) {
-                (Ok(tcp_socket), Some(host)) => {
+            match (tcp_socket, host_and_path_if_websocket) {
+                (Ok(tcp_socket), Some((host, path))) => {
                     websocket::websocket_client_handshake(websocket::Config {
                         tcp_socket,
                         host: &host,
-                        url: "/",
+                        url: &path,
                     })
                     .await
                     .map(TcpOrWs::Right)
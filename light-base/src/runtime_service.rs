@@ -100,6 +100,12 @@ pub struct Config<TPlat: PlatformRef> {
 
     /// Header of the genesis block of the chain, in SCALE encoding.
     pub genesis_block_scale_encoded_header: Vec<u8>,
+
+    /// Runtime codes that must be used instead of the on-chain runtime code, indexed by the
+    /// number of the block starting from which they apply.
+    ///
+    /// See [`smoldot::chain_spec::ChainSpec::code_substitutes`].
+    pub code_substitutes: BTreeMap<u64, Vec<u8>>,
 }
 
 /// Identifies a runtime currently pinned within a [`RuntimeService`].
@@ -135,6 +141,7 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
                     platform,
                     sync_service,
                     config.genesis_block_scale_encoded_header,
+                    config.code_substitutes,
                     rx,
                     tx_weak,
                 )
@@ -293,8 +300,12 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
     /// heap pages. If none is found, compiles the runtime and stores it within the
     /// [`RuntimeService`]. In both cases, it is kept pinned until it is unpinned with
     /// [`RuntimeService::unpin_runtime`].
+    ///
+    /// The number of the block the storage code and heap pages belong to is used in order to
+    /// determine whether a code substitute of the chain specification applies.
     pub async fn compile_and_pin_runtime(
         &self,
+        block_number: u64,
         storage_code: Option<Vec<u8>>,
         storage_heap_pages: Option<Vec<u8>>,
         code_merkle_value: Option<Vec<u8>>,
@@ -306,6 +317,7 @@ impl<TPlat: PlatformRef> RuntimeService<TPlat> {
             .to_background
             .send(ToBackground::CompileAndPinRuntime {
                 result_tx,
+                block_number,
                 storage_code,
                 storage_heap_pages,
                 code_merkle_value,
@@ -799,6 +811,7 @@ enum ToBackground<TPlat: PlatformRef> {
     SubscribeAll(ToBackgroundSubscribeAll<TPlat>),
     CompileAndPinRuntime {
         result_tx: oneshot::Sender<Arc<Runtime>>,
+        block_number: u64,
         storage_code: Option<Vec<u8>>,
         storage_heap_pages: Option<Vec<u8>>,
         code_merkle_value: Option<Vec<u8>>,
@@ -865,6 +878,7 @@ async fn run_background<TPlat: PlatformRef>(
     platform: TPlat,
     sync_service: Arc<sync_service::SyncService<TPlat>>,
     genesis_block_scale_encoded_header: Vec<u8>,
+    code_substitutes: BTreeMap<u64, Vec<u8>>,
     to_background: async_channel::Receiver<ToBackground<TPlat>>,
     to_background_tx: async_channel::WeakSender<ToBackground<TPlat>>,
 ) {
@@ -896,6 +910,7 @@ async fn run_background<TPlat: PlatformRef>(
             log_target: log_target.clone(),
             platform: platform.clone(),
             sync_service: sync_service.clone(),
+            code_substitutes,
             to_background: Box::pin(to_background.clone()),
             to_background_tx: to_background_tx.clone(),
             next_subscription_id: 0,
@@ -925,6 +940,7 @@ async fn run_background<TPlat: PlatformRef>(
                 async_tree::AsyncOpId,
                 Result<
                     (
+                        u64,
                         Option<Vec<u8>>,
                         Option<Vec<u8>>,
                         Option<Vec<u8>>,
@@ -1111,7 +1127,7 @@ async fn run_background<TPlat: PlatformRef>(
                                         } else {
                                             (None, None)
                                         };
-                                        Ok((code, heap_pages, code_merkle_value, code_closest_ancestor))
+                                        Ok((block_number, code, heap_pages, code_merkle_value, code_closest_ancestor))
                                     }
                                     Err(error) => Err(RuntimeDownloadError::StorageQuery(error)),
                                 };
@@ -1456,21 +1472,29 @@ async fn run_background<TPlat: PlatformRef>(
                         )
                         .unwrap();
 
+                        let code_substitute = header::decode(
+                            &subscription.finalized_block_scale_encoded_header,
+                            sync_service.block_number_bytes(),
+                        )
+                        .ok()
+                        .and_then(|header| {
+                            background
+                                .code_substitutes
+                                .range(..=header.number)
+                                .next_back()
+                        });
+
                         let runtime = Arc::new(Runtime {
                             runtime_code: finalized_block_runtime.storage_code,
                             heap_pages: finalized_block_runtime.storage_heap_pages,
                             code_merkle_value: finalized_block_runtime.code_merkle_value,
                             closest_ancestor_excluding: finalized_block_runtime
                                 .closest_ancestor_excluding,
-                            runtime: Ok(SuccessfulRuntime {
-                                runtime_spec: finalized_block_runtime
-                                    .virtual_machine
-                                    .runtime_version()
-                                    .clone(),
-                                virtual_machine: Mutex::new(Some(
-                                    finalized_block_runtime.virtual_machine,
-                                )),
-                            }),
+                            code_substitute: code_substitute.map(|(block_number, _)| *block_number),
+                            runtime: Ok(SuccessfulRuntime::from_virtual_machine(
+                                finalized_block_runtime.virtual_machine,
+                                code_substitute.map(|(_, code)| &code[..]),
+                            )),
                         });
 
                         match &runtime.runtime {
@@ -1534,6 +1558,7 @@ async fn run_background<TPlat: PlatformRef>(
                                     let same_runtime_as_parent = same_runtime_as_parent(
                                         &block.scale_encoded_header,
                                         sync_service.block_number_bytes(),
+                                        &background.code_substitutes,
                                     );
                                     let _ = tree.input_insert_block(
                                         Block {
@@ -1583,6 +1608,7 @@ async fn run_background<TPlat: PlatformRef>(
                                     let same_runtime_as_parent = same_runtime_as_parent(
                                         &block.scale_encoded_header,
                                         sync_service.block_number_bytes(),
+                                        &background.code_substitutes,
                                     );
                                     let _ = tree.input_insert_block(
                                         Block {
@@ -1777,6 +1803,7 @@ async fn run_background<TPlat: PlatformRef>(
 
             WakeUpReason::ToBackground(ToBackground::CompileAndPinRuntime {
                 result_tx,
+                block_number,
                 storage_code,
                 storage_heap_pages,
                 code_merkle_value,
@@ -1784,26 +1811,38 @@ async fn run_background<TPlat: PlatformRef>(
             }) => {
                 // Foreground wants to compile the given runtime.
 
+                let code_substitute = background
+                    .code_substitutes
+                    .range(..=block_number)
+                    .next_back();
+
                 // Try to find an existing identical runtime.
                 let existing_runtime = background
                     .runtimes
                     .iter()
                     .filter_map(|(_, rt)| rt.upgrade())
                     .find(|rt| {
-                        rt.runtime_code == storage_code && rt.heap_pages == storage_heap_pages
+                        rt.runtime_code == storage_code
+                            && rt.heap_pages == storage_heap_pages
+                            && rt.code_substitute == code_substitute.map(|(n, _)| *n)
                     });
 
                 let runtime = if let Some(existing_runtime) = existing_runtime {
                     existing_runtime
                 } else {
                     // No identical runtime was found. Try compiling the new runtime.
-                    let runtime =
-                        SuccessfulRuntime::from_storage(&storage_code, &storage_heap_pages).await;
+                    let runtime = SuccessfulRuntime::from_storage(
+                        &storage_code,
+                        &storage_heap_pages,
+                        code_substitute.map(|(_, code)| &code[..]),
+                    )
+                    .await;
                     let runtime = Arc::new(Runtime {
                         heap_pages: storage_heap_pages,
                         runtime_code: storage_code,
                         code_merkle_value,
                         closest_ancestor_excluding,
+                        code_substitute: code_substitute.map(|(n, _)| *n),
                         runtime,
                     });
                     background.runtimes.insert(Arc::downgrade(&runtime));
@@ -1964,6 +2003,7 @@ async fn run_background<TPlat: PlatformRef>(
                 let same_runtime_as_parent = same_runtime_as_parent(
                     &new_block.scale_encoded_header,
                     sync_service.block_number_bytes(),
+                    &background.code_substitutes,
                 );
 
                 match &mut background.tree {
@@ -2123,6 +2163,7 @@ async fn run_background<TPlat: PlatformRef>(
             WakeUpReason::RuntimeDownloadFinished(
                 async_op_id,
                 Ok((
+                    block_number,
                     storage_code,
                     storage_heap_pages,
                     code_merkle_value,
@@ -2134,6 +2175,11 @@ async fn run_background<TPlat: PlatformRef>(
                 // TODO: the line below is a complete hack; the code that updates this value is never reached for parachains, and as such the line below is here to update this field
                 background.best_near_head_of_chain = true;
 
+                let code_substitute = background
+                    .code_substitutes
+                    .range(..=block_number)
+                    .next_back();
+
                 // Try to find an existing runtime identical to the one that has just been
                 // downloaded. This loop is `O(n)`, but given that we expect this list to very
                 // small (at most 1 or 2 elements), this is not a problem.
@@ -2142,15 +2188,21 @@ async fn run_background<TPlat: PlatformRef>(
                     .iter()
                     .filter_map(|(_, rt)| rt.upgrade())
                     .find(|rt| {
-                        rt.runtime_code == storage_code && rt.heap_pages == storage_heap_pages
+                        rt.runtime_code == storage_code
+                            && rt.heap_pages == storage_heap_pages
+                            && rt.code_substitute == code_substitute.map(|(n, _)| *n)
                     });
 
                 // If no identical runtime was found, try compiling the runtime.
                 let runtime = if let Some(existing_runtime) = existing_runtime {
                     existing_runtime
                 } else {
-                    let runtime =
-                        SuccessfulRuntime::from_storage(&storage_code, &storage_heap_pages).await;
+                    let runtime = SuccessfulRuntime::from_storage(
+                        &storage_code,
+                        &storage_heap_pages,
+                        code_substitute.map(|(_, code)| &code[..]),
+                    )
+                    .await;
                     match &runtime {
                         Ok(runtime) => {
                             log::info!(
@@ -2177,6 +2229,7 @@ async fn run_background<TPlat: PlatformRef>(
                         runtime,
                         code_merkle_value,
                         closest_ancestor_excluding,
+                        code_substitute: code_substitute.map(|(n, _)| *n),
                     });
 
                     background.runtimes.insert(Arc::downgrade(&runtime));
@@ -2272,6 +2325,9 @@ struct Background<TPlat: PlatformRef> {
 
     sync_service: Arc<sync_service::SyncService<TPlat>>,
 
+    /// See [`Config::code_substitutes`].
+    code_substitutes: BTreeMap<u64, Vec<u8>>,
+
     /// Receiver for messages to the background task.
     to_background: Pin<Box<async_channel::Receiver<ToBackground<TPlat>>>>,
 
@@ -2318,8 +2374,9 @@ struct Background<TPlat: PlatformRef> {
     blocks_stream: Option<Pin<Box<dyn Stream<Item = sync_service::Notification> + Send>>>,
 
     /// List of runtimes currently being downloaded from the network.
-    /// For each item, the download id, storage value of `:code`, storage value of `:heappages`,
-    /// and Merkle value and closest ancestor of `:code`.
+    /// For each item, the download id, number of the block whose runtime is downloaded, storage
+    /// value of `:code`, storage value of `:heappages`, and Merkle value and closest ancestor
+    /// of `:code`.
     runtime_downloads: stream::FuturesUnordered<
         future::BoxFuture<
             'static,
@@ -2327,6 +2384,7 @@ struct Background<TPlat: PlatformRef> {
                 async_tree::AsyncOpId,
                 Result<
                     (
+                        u64,
                         Option<Vec<u8>>,
                         Option<Vec<u8>>,
                         Option<Vec<u8>>,
@@ -2421,6 +2479,13 @@ struct Runtime {
     /// build.
     // TODO: consider storing hash instead
     heap_pages: Option<Vec<u8>>,
+
+    /// Block number of the entry in [`Config::code_substitutes`] that was considered when
+    /// building [`Runtime::runtime`], if any.
+    ///
+    /// Note that the code substitute is only used if its `spec_version` matches the one of the
+    /// on-chain runtime. This field is `Some` even if that is not the case.
+    code_substitute: Option<u64>,
 }
 
 struct SuccessfulRuntime {
@@ -2437,6 +2502,7 @@ impl SuccessfulRuntime {
     async fn from_storage(
         code: &Option<Vec<u8>>,
         heap_pages: &Option<Vec<u8>>,
+        code_substitute: Option<&[u8]>,
     ) -> Result<Self, RuntimeError> {
         // Since compiling the runtime is a CPU-intensive operation, we yield once before.
         futures_lite::future::yield_now().await;
@@ -2445,70 +2511,125 @@ impl SuccessfulRuntime {
         let module = code.as_ref().ok_or(RuntimeError::CodeNotFound)?;
         let heap_pages = executor::storage_heap_pages_to_value(heap_pages.as_deref())
             .map_err(RuntimeError::InvalidHeapPages)?;
-        let exec_hint = executor::vm::ExecHint::CompileAheadOfTime;
-
-        // We try once with `allow_unresolved_imports: false`. If this fails due to unresolved
-        // import, we try again but with `allowed_unresolved_imports: true`.
-        // Having unresolved imports might cause errors later on, for example when validating
-        // transactions or getting the parachain heads, but for now we continue the execution
-        // and print a warning.
-        match executor::host::HostVmPrototype::new(executor::host::Config {
-            module,
-            heap_pages,
-            exec_hint,
-            allow_unresolved_imports: false,
-        }) {
-            Ok(vm) => {
-                return Ok(SuccessfulRuntime {
-                    runtime_spec: vm.runtime_version().clone(),
-                    virtual_machine: Mutex::new(Some(vm)),
-                })
+
+        let virtual_machine = build_virtual_machine(module, heap_pages)?;
+        Ok(SuccessfulRuntime::from_virtual_machine(
+            virtual_machine,
+            code_substitute,
+        ))
+    }
+
+    /// Builds a [`SuccessfulRuntime`] from the runtime found in the storage of a block.
+    ///
+    /// If `code_substitute` is `Some` and the `spec_version` of the code substitute is the same
+    /// as the one of the on-chain runtime, the code substitute is used instead.
+    fn from_virtual_machine(
+        virtual_machine: executor::host::HostVmPrototype,
+        code_substitute: Option<&[u8]>,
+    ) -> Self {
+        if let Some(code_substitute) = code_substitute {
+            match build_virtual_machine(code_substitute, virtual_machine.heap_pages()) {
+                Ok(substitute)
+                    if substitute.runtime_version().decode().spec_version
+                        == virtual_machine.runtime_version().decode().spec_version =>
+                {
+                    log::info!(
+                        "Using code substitute of the chain specification for runtime with \
+                        spec version {}",
+                        substitute.runtime_version().decode().spec_version
+                    );
+
+                    return SuccessfulRuntime {
+                        runtime_spec: substitute.runtime_version().clone(),
+                        virtual_machine: Mutex::new(Some(substitute)),
+                    };
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    log::warn!(
+                        "Failed to compile code substitute of the chain specification: {}",
+                        error
+                    );
+                }
             }
-            Err(executor::host::NewErr::VirtualMachine(
-                executor::vm::NewErr::UnresolvedFunctionImport {
-                    function,
-                    module_name,
-                },
-            )) => {
-                match executor::host::HostVmPrototype::new(executor::host::Config {
-                    module,
-                    heap_pages,
-                    exec_hint,
-                    allow_unresolved_imports: true,
-                }) {
-                    Ok(vm) => {
-                        log::warn!(
-                            "Unresolved host function in runtime: `{}`:`{}`. Smoldot might \
-                            encounter errors later on. Please report this issue in \
-                            https://github.com/smol-dot/smoldot",
-                            module_name,
-                            function
-                        );
+        }
 
-                        Ok(SuccessfulRuntime {
-                            runtime_spec: vm.runtime_version().clone(),
-                            virtual_machine: Mutex::new(Some(vm)),
-                        })
-                    }
-                    Err(executor::host::NewErr::VirtualMachine(
-                        executor::vm::NewErr::UnresolvedFunctionImport { .. },
-                    )) => unreachable!(),
-                    Err(error) => {
-                        // It's still possible that errors other than an unresolved host
-                        // function happen.
-                        Err(RuntimeError::Build(error))
-                    }
+        SuccessfulRuntime {
+            runtime_spec: virtual_machine.runtime_version().clone(),
+            virtual_machine: Mutex::new(Some(virtual_machine)),
+        }
+    }
+}
+
+/// Compiles the given runtime code.
+fn build_virtual_machine(
+    module: &[u8],
+    heap_pages: executor::host::HeapPages,
+) -> Result<executor::host::HostVmPrototype, RuntimeError> {
+    let exec_hint = executor::vm::ExecHint::CompileAheadOfTime;
+
+    // We try once with `allow_unresolved_imports: false`. If this fails due to unresolved
+    // import, we try again but with `allowed_unresolved_imports: true`.
+    // Having unresolved imports might cause errors later on, for example when validating
+    // transactions or getting the parachain heads, but for now we continue the execution
+    // and print a warning.
+    match executor::host::HostVmPrototype::new(executor::host::Config {
+        module,
+        heap_pages,
+        exec_hint,
+        allow_unresolved_imports: false,
+    }) {
+        Ok(vm) => Ok(vm),
+        Err(executor::host::NewErr::VirtualMachine(
+            executor::vm::NewErr::UnresolvedFunctionImport {
+                function,
+                module_name,
+            },
+        )) => {
+            match executor::host::HostVmPrototype::new(executor::host::Config {
+                module,
+                heap_pages,
+                exec_hint,
+                allow_unresolved_imports: true,
+            }) {
+                Ok(vm) => {
+                    log::warn!(
+                        "Unresolved host function in runtime: `{}`:`{}`. Smoldot might \
+                        encounter errors later on. Please report this issue in \
+                        https://github.com/smol-dot/smoldot",
+                        module_name,
+                        function
+                    );
+
+                    Ok(vm)
+                }
+                Err(executor::host::NewErr::VirtualMachine(
+                    executor::vm::NewErr::UnresolvedFunctionImport { .. },
+                )) => unreachable!(),
+                Err(error) => {
+                    // It's still possible that errors other than an unresolved host
+                    // function happen.
+                    Err(RuntimeError::Build(error))
                 }
             }
-            Err(error) => Err(RuntimeError::Build(error)),
         }
+        Err(error) => Err(RuntimeError::Build(error)),
     }
 }
 
 /// Returns `true` if the block can be assumed to have the same runtime as its parent.
-fn same_runtime_as_parent(header: &[u8], block_number_bytes: usize) -> bool {
+///
+/// Blocks starting from which a code substitute applies never have the same runtime as their
+/// parent, in order for their runtime to be downloaded and the substitute to be considered.
+fn same_runtime_as_parent(
+    header: &[u8],
+    block_number_bytes: usize,
+    code_substitutes: &BTreeMap<u64, Vec<u8>>,
+) -> bool {
     match header::decode(header, block_number_bytes) {
-        Ok(h) => !h.digest.has_runtime_environment_updated(),
+        Ok(h) => {
+            !h.digest.has_runtime_environment_updated() && !code_substitutes.contains_key(&h.number)
+        }
         Err(_) => false,
     }
 }
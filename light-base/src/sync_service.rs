@@ -28,7 +28,15 @@
 
 use crate::{network_service, platform::PlatformRef, runtime_service};
 
-use alloc::{borrow::ToOwned as _, boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use alloc::{
+    borrow::ToOwned as _,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
 use core::{cmp, fmt, future::Future, mem, num::NonZeroU32, pin::Pin, time::Duration};
 use futures_channel::oneshot;
 use rand::seq::IteratorRandom as _;
@@ -89,6 +97,14 @@ pub struct ConfigRelayChain {
     /// instead of downloading it. If the hint doesn't match, an extra round-trip will be needed,
     /// but if the hint matches it saves a big download.
     pub runtime_code_hint: Option<ConfigRelayChainRuntimeCodeHint>,
+
+    /// List of block numbers and hashes that the chain must contain. Blocks that don't match
+    /// are rejected, and peers announcing them aren't used to download blocks.
+    pub fork_blocks: BTreeMap<u64, [u8; 32]>,
+
+    /// List of hashes of blocks that are known to be bad. These blocks and their descendants are
+    /// rejected, and peers announcing them are banned.
+    pub bad_blocks: BTreeSet<[u8; 32]>,
 }
 
 /// See [`ConfigRelayChain::runtime_code_hint`].
@@ -171,6 +187,8 @@ impl<TPlat: PlatformRef> SyncService<TPlat> {
                     config_relay_chain.chain_information,
                     config.block_number_bytes,
                     config_relay_chain.runtime_code_hint,
+                    config_relay_chain.fork_blocks,
+                    config_relay_chain.bad_blocks,
                     from_foreground,
                     config.network_service.0.clone(),
                     config.network_service.1,
@@ -1434,6 +1434,11 @@ async fn fetch_parahead<TPlat: PlatformRef>(
                     .unlock(runtime_host::RuntimeHostVm::Offchain(req).into_prototype());
                 return Err(ParaheadError::OffchainWorkerHostFunction);
             }
+            runtime_host::RuntimeHostVm::GenerateKey(req) => {
+                runtime_call_lock
+                    .unlock(runtime_host::RuntimeHostVm::GenerateKey(req).into_prototype());
+                return Err(ParaheadError::KeystoreHostFunction);
+            }
             runtime_host::RuntimeHostVm::LogEmit(log) => {
                 // Logs are ignored.
                 runtime_call = log.resume();
@@ -1474,6 +1479,8 @@ enum ParaheadError {
     InvalidRuntimeOutput(para::Error),
     /// Runtime has called an offchain worker host function.
     OffchainWorkerHostFunction,
+    /// Runtime has called a host function that requires access to a keystore.
+    KeystoreHostFunction,
     /// Runtime service subscription is no longer valid.
     ObsoleteSubscription,
 }
@@ -1489,6 +1496,7 @@ impl ParaheadError {
             ParaheadError::NoCore => false,
             ParaheadError::InvalidRuntimeOutput(_) => false,
             ParaheadError::OffchainWorkerHostFunction => false,
+            ParaheadError::KeystoreHostFunction => false,
             ParaheadError::ObsoleteSubscription => false,
         }
     }
@@ -24,6 +24,7 @@ use crate::{network_service, platform::PlatformRef, util};
 use alloc::{
     borrow::{Cow, ToOwned as _},
     boxed::Box,
+    collections::{BTreeMap, BTreeSet},
     string::{String, ToString as _},
     sync::Arc,
     vec::Vec,
@@ -52,6 +53,8 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
     chain_information: chain::chain_information::ValidChainInformation,
     block_number_bytes: usize,
     runtime_code_hint: Option<ConfigRelayChainRuntimeCodeHint>,
+    fork_blocks: BTreeMap<u64, [u8; 32]>,
+    bad_blocks: BTreeSet<[u8; 32]>,
     mut from_foreground: Pin<Box<async_channel::Receiver<ToBackground>>>,
     network_service: Arc<network_service::NetworkService<TPlat>>,
     network_chain_id: network_service::ChainId,
@@ -97,6 +100,8 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                 storage_value: hint.storage_value,
                 closest_ancestor_excluding: hint.closest_ancestor_excluding,
             }),
+            fork_blocks,
+            bad_blocks,
         }),
         network_up_to_date_best: true,
         network_up_to_date_finalized: true,
@@ -374,6 +379,42 @@ pub(super) async fn start_standalone_chain<TPlat: PlatformRef>(
                             peer_id
                         );
                     }
+                    all::BlockAnnounceOutcome::ForkBlockMismatch => {
+                        log::debug!(
+                            target: &task.log_target,
+                            "Sync => ForkBlockMismatch"
+                        );
+
+                        log::warn!(
+                            target: &task.log_target,
+                            "Block announce from {} doesn't match the fork blocks of the chain",
+                            peer_id
+                        );
+                    }
+                    all::BlockAnnounceOutcome::BadBlock => {
+                        log::debug!(
+                            target: &task.log_target,
+                            "Sync => BadBlock"
+                        );
+
+                        log::warn!(
+                            target: &task.log_target,
+                            "Block announce from {} is a known bad block or a child of a known \
+                            bad block. Banning peer.",
+                            peer_id
+                        );
+
+                        // The peer is following a chain that will never be accepted, and is
+                        // thus useless to us.
+                        task.network_service
+                            .ban_and_disconnect(
+                                peer_id,
+                                network_chain_id,
+                                Duration::from_secs(60),
+                                "bad-block-announce",
+                            )
+                            .await;
+                    }
                     all::BlockAnnounceOutcome::InvalidHeader(_) => {
                         // Log messages are already printed above.
                     }
@@ -0,0 +1,429 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Background tasks that report information about the client to telemetry servers.
+//!
+//! One background task follows the blocks of the sync service and generates the telemetry
+//! messages, and one background task per telemetry server sends these messages.
+//!
+//! Connections to telemetry servers are opened through [`PlatformRef::connect_stream`] as
+//! WebSocket connections. Telemetry servers whose type of connection isn't supported by the
+//! platform, for example secure WebSocket servers on platforms that don't support TLS, are
+//! ignored.
+//!
+//! The background tasks automatically stop when the sync service is destroyed.
+
+use crate::{
+    platform::{Address, ConnectionType, IpAddr, PlatformRef},
+    sync_service,
+};
+
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::{String, ToString as _},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{pin, time::Duration};
+use futures_lite::FutureExt as _;
+use futures_util::StreamExt as _;
+use smoldot::{header, libp2p::peer_id, telemetry};
+
+/// Configuration for the telemetry background tasks.
+pub struct Config<TPlat: PlatformRef> {
+    /// Name of the chain, for logging purposes.
+    ///
+    /// > **Note**: This name will be directly printed out. Any special character should already
+    /// >           have been filtered out from this name.
+    pub log_name: String,
+
+    /// Access to the platform's capabilities.
+    pub platform: TPlat,
+
+    /// List of addresses of telemetry servers to connect to, alongside with their verbosity
+    /// level, as found in the chain specification.
+    pub endpoints: Vec<(String, u8)>,
+
+    /// Name of the chain, as found in the chain specification.
+    pub chain_name: String,
+
+    /// Hash of the genesis block of the chain.
+    pub genesis_block_hash: [u8; 32],
+
+    /// Service responsible for synchronizing the chain. Its blocks are reported to the telemetry
+    /// servers.
+    pub sync_service: Arc<sync_service::SyncService<TPlat>>,
+}
+
+/// Interval between two `system.interval` messages.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Delay before trying to reconnect to a telemetry server after a disconnection or a failed
+/// connection attempt.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Maximum number of messages waiting to be sent to a telemetry server.
+const MAX_QUEUED_MESSAGES: usize = 64;
+
+/// Spawns the telemetry background tasks. Does nothing if no telemetry server can be reached.
+pub fn start<TPlat: PlatformRef>(config: Config<TPlat>) {
+    let log_target = format!("telemetry-{}", config.log_name);
+
+    // The light client doesn't have a stable network identity, as a new network key is
+    // generated for each connection. A random identity is reported instead.
+    let network_id = {
+        let mut key = [0; 32];
+        config.platform.fill_random_bytes(&mut key);
+        peer_id::PublicKey::Ed25519(key).into_peer_id().to_string()
+    };
+
+    let now_from_unix_epoch = config.platform.now_from_unix_epoch();
+    let system_connected = telemetry::encode_message(
+        1,
+        now_from_unix_epoch,
+        &telemetry::Payload::SystemConnected {
+            chain: &config.chain_name,
+            genesis_hash: &config.genesis_block_hash,
+            name: &config.platform.client_name(),
+            implementation: &config.platform.client_name(),
+            version: &config.platform.client_version(),
+            network_id: &network_id,
+            authority: false,
+            startup_time: u64::try_from(now_from_unix_epoch.as_millis()).unwrap_or(u64::MAX),
+            target_os: "",
+            target_arch: "",
+            target_env: "",
+        },
+    );
+
+    let mut endpoints_senders = Vec::with_capacity(config.endpoints.len());
+    for (address, verbosity) in config.endpoints {
+        let endpoint = match telemetry::parse_endpoint(&address) {
+            Ok(ep) => ep,
+            Err(error) => {
+                log::warn!(
+                    target: &log_target,
+                    "Failed to parse telemetry endpoint {}: {}", address, error
+                );
+                continue;
+            }
+        };
+
+        let supported = match (&endpoint.host, endpoint.secure) {
+            // The `PlatformRef` trait doesn't support secure WebSocket connections towards an
+            // IP address.
+            (telemetry::EndpointHost::Ipv4(_) | telemetry::EndpointHost::Ipv6(_), true) => false,
+            _ => config
+                .platform
+                .supports_connection_type(ConnectionType::from(&endpoint_address(&endpoint))),
+        };
+        if !supported {
+            log::warn!(
+                target: &log_target,
+                "Telemetry endpoint {} isn't supported on this platform", address
+            );
+            continue;
+        }
+
+        let (tx, rx) = async_channel::bounded(64);
+        endpoints_senders.push((tx, verbosity));
+
+        config.platform.spawn_task(
+            format!("{log_target}-{address}").into(),
+            endpoint_task(
+                config.platform.clone(),
+                log_target.clone(),
+                endpoint,
+                system_connected.clone(),
+                rx,
+            ),
+        );
+    }
+
+    if endpoints_senders.is_empty() {
+        return;
+    }
+
+    config.platform.spawn_task(
+        log_target.clone().into(),
+        blocks_task(
+            config.platform.clone(),
+            Arc::downgrade(&config.sync_service),
+            endpoints_senders,
+        ),
+    );
+}
+
+/// Background task that follows the blocks of the sync service and generates the telemetry
+/// messages.
+async fn blocks_task<TPlat: PlatformRef>(
+    platform: TPlat,
+    sync_service: Weak<sync_service::SyncService<TPlat>>,
+    endpoints: Vec<(async_channel::Sender<String>, u8)>,
+) {
+    let send = |payload: telemetry::Payload| {
+        let message = telemetry::encode_message(1, platform.now_from_unix_epoch(), &payload);
+        for (sender, verbosity) in &endpoints {
+            if payload.verbosity() <= *verbosity {
+                // Telemetry is best effort. Messages are discarded if the server is too slow.
+                let _ = sender.try_send(message.clone());
+            }
+        }
+    };
+
+    let mut next_interval = Box::pin(platform.sleep(INTERVAL));
+
+    loop {
+        let Some(sync_service_strong) = sync_service.upgrade() else {
+            return;
+        };
+        let block_number_bytes = sync_service_strong.block_number_bytes();
+        let subscribe_all = sync_service_strong.subscribe_all(32, false).await;
+        drop(sync_service_strong);
+
+        // Numbers of all the non-finalized blocks.
+        let mut blocks_numbers = hashbrown::HashMap::<_, _, fnv::FnvBuildHasher>::default();
+        let mut finalized = (
+            header::decode(
+                &subscribe_all.finalized_block_scale_encoded_header,
+                block_number_bytes,
+            )
+            .map_or(0, |h| h.number),
+            header::hash_from_scale_encoded_header(
+                &subscribe_all.finalized_block_scale_encoded_header,
+            ),
+        );
+        let mut best = finalized;
+        for block in subscribe_all.non_finalized_blocks_ancestry_order {
+            let number = header::decode(&block.scale_encoded_header, block_number_bytes)
+                .map_or(0, |h| h.number);
+            let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+            blocks_numbers.insert(hash, number);
+            if block.is_new_best {
+                best = (number, hash);
+            }
+        }
+
+        let mut new_blocks = pin::pin!(subscribe_all.new_blocks);
+
+        loop {
+            enum WakeUpReason {
+                Notification(sync_service::Notification),
+                SubscriptionDead,
+                Interval,
+            }
+
+            let wake_up_reason = async {
+                match new_blocks.next().await {
+                    Some(notification) => WakeUpReason::Notification(notification),
+                    None => WakeUpReason::SubscriptionDead,
+                }
+            }
+            .or(async {
+                next_interval.as_mut().await;
+                WakeUpReason::Interval
+            })
+            .await;
+
+            match wake_up_reason {
+                WakeUpReason::SubscriptionDead => break,
+                WakeUpReason::Interval => {
+                    next_interval = Box::pin(platform.sleep(INTERVAL));
+                    let Some(sync_service) = sync_service.upgrade() else {
+                        return;
+                    };
+                    let peers = sync_service.syncing_peers().await.len();
+                    send(telemetry::Payload::SystemInterval {
+                        peers: u64::try_from(peers).unwrap_or(u64::MAX),
+                        height: best.0,
+                        best: &best.1,
+                        finalized_height: finalized.0,
+                        finalized_hash: &finalized.1,
+                        transactions_count: None,
+                        bandwidth_download: None,
+                        bandwidth_upload: None,
+                    });
+                }
+                WakeUpReason::Notification(sync_service::Notification::Block(block)) => {
+                    let number = header::decode(&block.scale_encoded_header, block_number_bytes)
+                        .map_or(0, |h| h.number);
+                    let hash = header::hash_from_scale_encoded_header(&block.scale_encoded_header);
+                    blocks_numbers.insert(hash, number);
+                    if block.is_new_best {
+                        best = (number, hash);
+                        send(telemetry::Payload::BlockImport {
+                            height: number,
+                            best: &hash,
+                            origin: "NetworkBroadcast",
+                        });
+                    }
+                }
+                WakeUpReason::Notification(sync_service::Notification::BestBlockChanged {
+                    hash,
+                }) => {
+                    best = (
+                        blocks_numbers.get(&hash).copied().unwrap_or(finalized.0),
+                        hash,
+                    );
+                    send(telemetry::Payload::BlockImport {
+                        height: best.0,
+                        best: &best.1,
+                        origin: "NetworkBroadcast",
+                    });
+                }
+                WakeUpReason::Notification(sync_service::Notification::Finalized {
+                    hash,
+                    best_block_hash,
+                }) => {
+                    finalized = (blocks_numbers.get(&hash).copied().unwrap_or(0), hash);
+                    best = (
+                        blocks_numbers
+                            .get(&best_block_hash)
+                            .copied()
+                            .unwrap_or(finalized.0),
+                        best_block_hash,
+                    );
+                    blocks_numbers.retain(|_, number| *number > finalized.0);
+
+                    send(telemetry::Payload::NotifyFinalized {
+                        height: finalized.0,
+                        best: &finalized.1,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Background task that maintains a connection with a telemetry server and sends the messages
+/// received on `messages` to it. Stops when `messages` is closed.
+async fn endpoint_task<TPlat: PlatformRef>(
+    platform: TPlat,
+    log_target: String,
+    endpoint: telemetry::Endpoint,
+    system_connected: String,
+    messages: async_channel::Receiver<String>,
+) {
+    let mut messages = pin::pin!(messages);
+
+    loop {
+        // As documented in the `PlatformRef` trait, `connect_stream` must return as soon as
+        // possible.
+        let socket = platform.connect_stream(endpoint_address(&endpoint)).await;
+        let mut socket = pin::pin!(socket);
+
+        // Messages waiting to be sent to the server.
+        let mut queue = VecDeque::with_capacity(MAX_QUEUED_MESSAGES);
+        queue.push_back(system_connected.clone());
+
+        loop {
+            let result = match platform.read_write_access(socket.as_mut()) {
+                Ok(mut read_write) => {
+                    // The content of the messages sent by the server, if any, is ignored.
+                    read_write.discard_all_incoming();
+
+                    if read_write.expected_incoming_bytes.is_none()
+                        || read_write.write_bytes_queueable.is_none()
+                    {
+                        Err(String::from("connection closed by the remote"))
+                    } else {
+                        // Each telemetry message must be sent as a separate WebSocket message.
+                        // As such, a message is only written out once the previous one has
+                        // been entirely sent.
+                        if read_write.write_bytes_queued == 0 {
+                            if let Some(message) = queue.pop_front() {
+                                read_write.write_out(message.into_bytes());
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+                Err(err) => Err(err.to_string()),
+            };
+
+            if let Err(error) = result {
+                log::debug!(
+                    target: &log_target,
+                    "Disconnected from telemetry server {}: {}", endpoint, error
+                );
+                break;
+            }
+
+            enum WakeUpReason {
+                Message(String),
+                MessagesClosed,
+                SocketEvent,
+            }
+
+            let wake_up_reason = async {
+                match messages.next().await {
+                    Some(message) => WakeUpReason::Message(message),
+                    None => WakeUpReason::MessagesClosed,
+                }
+            }
+            .or(async {
+                platform.wait_read_write_again(socket.as_mut()).await;
+                WakeUpReason::SocketEvent
+            })
+            .await;
+
+            match wake_up_reason {
+                WakeUpReason::Message(message) => {
+                    // Telemetry is best effort. Messages are discarded if the queue is full.
+                    if queue.len() < MAX_QUEUED_MESSAGES {
+                        queue.push_back(message);
+                    }
+                }
+                WakeUpReason::MessagesClosed => return,
+                WakeUpReason::SocketEvent => {}
+            }
+        }
+
+        platform.sleep(RECONNECT_DELAY).await;
+
+        // Messages generated while disconnected are discarded.
+        while messages.try_recv().is_ok() {}
+        if messages.is_closed() && messages.is_empty() {
+            return;
+        }
+    }
+}
+
+/// Returns the address to pass to [`PlatformRef::connect_stream`] in order to connect to the
+/// given telemetry server.
+fn endpoint_address(endpoint: &telemetry::Endpoint) -> Address {
+    match &endpoint.host {
+        telemetry::EndpointHost::Dns(hostname) => Address::WebSocketDns {
+            hostname,
+            port: endpoint.port,
+            path: &endpoint.path,
+            secure: endpoint.secure,
+        },
+        telemetry::EndpointHost::Ipv4(ip) => Address::WebSocketIp {
+            ip: IpAddr::V4(*ip),
+            port: endpoint.port,
+            path: &endpoint.path,
+        },
+        telemetry::EndpointHost::Ipv6(ip) => Address::WebSocketIp {
+            ip: IpAddr::V6(*ip),
+            port: endpoint.port,
+            path: &endpoint.path,
+        },
+    }
+}
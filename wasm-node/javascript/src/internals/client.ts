@@ -507,7 +507,8 @@ export function start(options: ClientOptions, wasmModule: SmoldotBytecode | Prom
                 potentialRelayChainsIds,
                 !!options.disableJsonRpc,
                 jsonRpcMaxPendingRequests,
-                jsonRpcMaxSubscriptions
+                jsonRpcMaxSubscriptions,
+                !!options.enableTelemetry
             );
 
             const outcome = await promise;
@@ -80,7 +80,7 @@ export type ParsedMultiaddr =
 export interface Instance {
     request: (request: string, chainId: number) => number,
     peekJsonRpcResponse: (chainId: number) => string | null,
-    addChain: (chainSpec: string, databaseContent: string, potentialRelayChains: number[], disableJsonRpc: boolean, jsonRpcMaxPendingRequests: number, jsonRpcMaxSubscriptions: number) => void,
+    addChain: (chainSpec: string, databaseContent: string, potentialRelayChains: number[], disableJsonRpc: boolean, jsonRpcMaxPendingRequests: number, jsonRpcMaxSubscriptions: number, enableTelemetry: boolean) => void,
     removeChain: (chainId: number) => void,
     /**
      * Notifies the background executor that it should stop. Once it has effectively stopped,
@@ -298,20 +298,29 @@ export async function startLocalInstance(config: Config, wasmModule: WebAssembly
                 case 4:
                 case 6: {
                     const port = buffer.readUInt16BE(mem, addrPtr + 1);
-                    const hostname = buffer.utf8BytesToString(mem, addrPtr + 3, addrLen - 3);
-                    address = { ty: "websocket", url: "ws://" + hostname + ":" + port }
+                    const hostnameAndPath = buffer.utf8BytesToString(mem, addrPtr + 3, addrLen - 3);
+                    const pathStart = hostnameAndPath.indexOf("/");
+                    const hostname = hostnameAndPath.slice(0, pathStart);
+                    const path = hostnameAndPath.slice(pathStart);
+                    address = { ty: "websocket", url: "ws://" + hostname + ":" + port + path }
                     break;
                 }
                 case 5: {
                     const port = buffer.readUInt16BE(mem, addrPtr + 1);
-                    const hostname = buffer.utf8BytesToString(mem, addrPtr + 3, addrLen - 3);
-                    address = { ty: "websocket", url: "ws://[" + hostname + "]:" + port }
+                    const hostnameAndPath = buffer.utf8BytesToString(mem, addrPtr + 3, addrLen - 3);
+                    const pathStart = hostnameAndPath.indexOf("/");
+                    const hostname = hostnameAndPath.slice(0, pathStart);
+                    const path = hostnameAndPath.slice(pathStart);
+                    address = { ty: "websocket", url: "ws://[" + hostname + "]:" + port + path }
                     break;
                 }
                 case 14: {
                     const port = buffer.readUInt16BE(mem, addrPtr + 1);
-                    const hostname = buffer.utf8BytesToString(mem, addrPtr + 3, addrLen - 3);
-                    address = { ty: "websocket", url: "wss://" + hostname + ":" + port }
+                    const hostnameAndPath = buffer.utf8BytesToString(mem, addrPtr + 3, addrLen - 3);
+                    const pathStart = hostnameAndPath.indexOf("/");
+                    const hostname = hostnameAndPath.slice(0, pathStart);
+                    const path = hostnameAndPath.slice(pathStart);
+                    address = { ty: "websocket", url: "wss://" + hostname + ":" + port + path }
                     break;
                 }
                 case 16: {
@@ -500,7 +509,7 @@ export async function startLocalInstance(config: Config, wasmModule: WebAssembly
             }
         },
 
-        addChain: (chainSpec: string, databaseContent: string, potentialRelayChains: number[], disableJsonRpc: boolean, jsonRpcMaxPendingRequests: number, jsonRpcMaxSubscriptions: number) => {
+        addChain: (chainSpec: string, databaseContent: string, potentialRelayChains: number[], disableJsonRpc: boolean, jsonRpcMaxPendingRequests: number, jsonRpcMaxSubscriptions: number, enableTelemetry: boolean) => {
             if (!state.instance) {
                 eventCallback({ ty: "add-chain-result", success: false, error: "Smoldot has crashed" });
                 return;
@@ -522,7 +531,7 @@ export async function startLocalInstance(config: Config, wasmModule: WebAssembly
                 buffer.writeUInt32LE(potentialRelayChainsEncoded, idx * 4, potentialRelayChains[idx]!);
             }
             state.bufferIndices[2] = potentialRelayChainsEncoded
-            const chainId = state.instance.exports.add_chain(0, 1, disableJsonRpc ? 0 : jsonRpcMaxPendingRequests, jsonRpcMaxSubscriptions, 2);
+            const chainId = state.instance.exports.add_chain(0, 1, disableJsonRpc ? 0 : jsonRpcMaxPendingRequests, jsonRpcMaxSubscriptions, 2, enableTelemetry ? 1 : 0);
 
             delete state.bufferIndices[0]
             delete state.bufferIndices[1]
@@ -621,7 +630,7 @@ interface SmoldotWasmExports extends WebAssembly.Exports {
     memory: WebAssembly.Memory,
     init: (maxLogLevel: number) => void,
     advance_execution: () => void,
-    add_chain: (chainSpecBufferIndex: number, databaseContentBufferIndex: number, jsonRpcMaxPendingRequests: number, jsonRpcMaxSubscriptions: number, potentialRelayChainsBufferIndex: number) => number;
+    add_chain: (chainSpecBufferIndex: number, databaseContentBufferIndex: number, jsonRpcMaxPendingRequests: number, jsonRpcMaxSubscriptions: number, potentialRelayChainsBufferIndex: number, enableTelemetry: number) => number;
     remove_chain: (chainId: number) => void,
     chain_is_ok: (chainId: number) => number,
     chain_error_len: (chainId: number) => number,
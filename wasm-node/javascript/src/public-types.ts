@@ -441,5 +441,16 @@ export interface AddChainOptions {
      *
      * If this value is not set, it means that there is no maximum.
      */
-    jsonRpcMaxSubscriptions?: number
+    jsonRpcMaxSubscriptions?: number,
+
+    /**
+     * If `true`, smoldot reports information about itself and the chain, such as its name, version,
+     * and best and finalized blocks, to the telemetry servers listed in the chain specification.
+     *
+     * This field is ignored if the chain was already added to the client by a previous call to
+     * {@link Client.addChain}, in which case the value passed during that previous call applies.
+     *
+     * If this value is not set, no telemetry is sent.
+     */
+    enableTelemetry?: boolean
 }
@@ -178,6 +178,8 @@ extern "C" {
     /// - An UTF-8-encoded IP address or domain name. Use the `addr_len` parameter to determine
     /// its length. When using an IPv4, it is encoded as `a.b.c.d`. When using an IPv6, it is
     /// encoded according to RFC5952.
+    /// - (WebSocket only) The UTF-8-encoded path of the URL to connect to, which always starts
+    /// with `/`. The IP address or domain name ends at the first `/` character.
     ///
     /// The `type` byte defines the type of connection and whether the optional field is present:
     ///
@@ -365,6 +367,9 @@ pub extern "C" fn advance_execution() {
 /// If `json_rpc_max_pending_requests` is 0, then the value of `json_rpc_max_subscriptions` is
 /// ignored.
 ///
+/// If `enable_telemetry` is non-zero, the client reports information about itself and the chain
+/// to the telemetry servers found in the chain specification. If it is 0, no telemetry is sent.
+///
 /// If an error happens during the creation of the chain, a chain id will be allocated
 /// nonetheless, and must later be de-allocated by calling [`remove_chain`]. This allocated chain,
 /// however, will be in an erroneous state. Use [`chain_is_ok`] to determine whether this function
@@ -377,6 +382,7 @@ pub extern "C" fn add_chain(
     json_rpc_max_pending_requests: u32,
     json_rpc_max_subscriptions: u32,
     potential_relay_chains_buffer_index: u32,
+    enable_telemetry: u32,
 ) -> u32 {
     super::add_chain(
         get_buffer(chain_spec_buffer_index),
@@ -384,6 +390,7 @@ pub extern "C" fn add_chain(
         json_rpc_max_pending_requests,
         json_rpc_max_subscriptions,
         get_buffer(potential_relay_chains_buffer_index),
+        enable_telemetry != 0,
     )
 }
 
@@ -56,6 +56,7 @@ fn add_chain(
     json_rpc_max_pending_requests: u32,
     json_rpc_max_subscriptions: u32,
     potential_relay_chains: Vec<u8>,
+    enable_telemetry: bool,
 ) -> u32 {
     let mut client_lock = CLIENT.try_lock().unwrap();
 
@@ -116,6 +117,7 @@ fn add_chain(
                 smoldot_light::AddChainConfigJsonRpc::Disabled
             },
             potential_relay_chains: potential_relay_chains.into_iter(),
+            enable_telemetry,
         }) {
         Ok(c) => c,
         Err(error) => {
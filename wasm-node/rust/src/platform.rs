@@ -220,32 +220,40 @@ impl smoldot_light::platform::PlatformRef for PlatformRef {
             smoldot_light::platform::Address::WebSocketIp {
                 ip: smoldot_light::platform::IpAddr::V4(ip),
                 port,
+                path,
             } => iter::once(4u8)
                 .chain(port.to_be_bytes())
                 .chain(no_std_net::Ipv4Addr::from(ip).to_string().bytes())
+                .chain(path.as_bytes().iter().copied())
                 .collect(),
             smoldot_light::platform::Address::WebSocketIp {
                 ip: smoldot_light::platform::IpAddr::V6(ip),
                 port,
+                path,
             } => iter::once(5u8)
                 .chain(port.to_be_bytes())
                 .chain(no_std_net::Ipv6Addr::from(ip).to_string().bytes())
+                .chain(path.as_bytes().iter().copied())
                 .collect(),
             smoldot_light::platform::Address::WebSocketDns {
                 hostname,
                 port,
+                path,
                 secure: false,
             } => iter::once(6u8)
                 .chain(port.to_be_bytes())
                 .chain(hostname.as_bytes().iter().copied())
+                .chain(path.as_bytes().iter().copied())
                 .collect(),
             smoldot_light::platform::Address::WebSocketDns {
                 hostname,
                 port,
+                path,
                 secure: true,
             } => iter::once(14u8)
                 .chain(port.to_be_bytes())
                 .chain(hostname.as_bytes().iter().copied())
+                .chain(path.as_bytes().iter().copied())
                 .collect(),
         };
 